@@ -0,0 +1,139 @@
+/*
+
+Benchmarks for the packer and the Rust reader, run with `cargo bench`.
+
+The input is a made up postcode file, in the ONS postcode database format, with about the same
+spread of postcodes per district as the real thing. It is written to a temporary file once, and
+the same file is used by every benchmark, so that results can be compared between format changes.
+
+    read_csv:      reading and parsing the input file, in input bytes per second
+    pack:          encoding the postcodes as a pack file, in postcodes per second
+    open:          opening a pack file that is already in memory, in file bytes per second
+    map_lookup:    memory mapping a pack file and looking up one postcode, in files per second
+    lookup:        looking up postcodes by name, in lookups per second
+    nearest:       finding the nearest postcode to a location with the spatial index, in queries per second
+    shared_lookup: looking up postcodes from several threads sharing one reader, in lookups per second
+
+*/
+use std::hint::black_box;
+use std::path::PathBuf;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nearmypostcode_packer::{Packer, Reader};
+
+/// Postcode areas to generate postcodes in
+const AREAS: [&str; 10] = ["AB", "B", "CB", "E", "G", "LS", "M", "SW", "YO", "BT"];
+
+/// Districts in each area
+const DISTRICTS: u32 = 20;
+
+/// Postcodes in each district
+const PER_DISTRICT: u32 = 500;
+
+/// Threads sharing one reader in `shared_lookup`
+const SHARED_THREADS: usize = 4;
+
+/// A small linear congruential generator, so the input is the same every time
+struct Lcg(u64);
+
+impl Lcg{
+    /// A number from 0 to 1
+    fn next(&mut self) -> f64{
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Write the made up input file, returns its path
+fn input_csv() -> PathBuf{
+    let mut rng = Lcg(0x4e4d50);
+    let mut csv = String::from("pcds,dointr,doterm,ctry,lat,long\n");
+    for (a, area) in AREAS.iter().enumerate(){
+        for district in 1..=DISTRICTS{
+            let lat = 50.5 + (a as f64 * 0.8) + (rng.next() * 0.5);
+            let long = -5.0 + (rng.next() * 5.0);
+            for i in 0..PER_DISTRICT{
+                let inward = format!("{}{}{}", i % 10, (b'A' + ((i / 10) % 26) as u8) as char, (b'A' + ((i / 260) * 7 % 26) as u8) as char);
+                let plat = lat + (rng.next() - 0.5) * 0.05;
+                let plong = long + (rng.next() - 0.5) * 0.05;
+                csv.push_str(&format!("{area}{district} {inward},201001,,E92000001,{plat:.6},{plong:.6}\n"));
+            }
+        }
+    }
+    let path = std::env::temp_dir().join(format!("nmp-bench-{}.csv", std::process::id()));
+    std::fs::write(&path, csv).expect("Can't write the benchmark input");
+    path
+}
+
+fn benchmarks(c: &mut Criterion){
+    let path = input_csv();
+    let path_str = path.to_string_lossy().to_string();
+    let input_len = std::fs::metadata(&path).unwrap().len();
+
+    let mut group = c.benchmark_group("packer");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(input_len));
+    group.bench_function("read_csv", |b| b.iter(|| Packer::from_csv(&path_str, &[]).unwrap()));
+
+    let mut packer = Packer::from_csv(&path_str, &[]).unwrap();
+    packer.set_format_version(5).unwrap();
+    packer.set_spatial_index(true);
+    group.throughput(Throughput::Elements(packer.postcodes().len() as u64));
+    group.bench_function("pack", |b| b.iter(|| {
+        let mut data = Vec::new();
+        packer.write_to(&mut data).unwrap();
+        data
+    }));
+    group.finish();
+
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    let mut group = c.benchmark_group("reader");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("open", |b| b.iter_batched(|| data.clone(), |d| Reader::from_bytes(d).unwrap(), BatchSize::LargeInput));
+
+    let pack_path = path.with_extension("pack");
+    std::fs::write(&pack_path, &data).unwrap();
+    let pack_path_str = pack_path.to_string_lossy().to_string();
+    let reader = Reader::from_bytes(data).unwrap();
+    let queries: Vec<String> = reader.iter().filter(|p| !p.is_partial).step_by(97).map(|p| p.postcode).collect();
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("map_lookup", |b| b.iter(|| Reader::map(&pack_path_str).unwrap().lookup(&queries[0])));
+
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_function("lookup", |b| b.iter(|| {
+        for q in &queries{
+            black_box(reader.lookup(q));
+        }
+    }));
+
+    // Near to postcodes, like a user's location would be
+    let mut rng = Lcg(1);
+    let locations: Vec<(f64, f64)> = reader.iter().step_by(100)
+        .map(|p| (p.location.y + (rng.next() - 0.5) * 0.01, p.location.x + (rng.next() - 0.5) * 0.01))
+        .collect();
+    group.throughput(Throughput::Elements(locations.len() as u64));
+    group.bench_function("nearest", |b| b.iter(|| {
+        for &(lat, long) in &locations{
+            black_box(reader.nearest(lat, long));
+        }
+    }));
+
+    // Each thread looks up all of the queries, so this should scale with the number of threads
+    group.throughput(Throughput::Elements((queries.len() * SHARED_THREADS) as u64));
+    group.bench_function("shared_lookup", |b| b.iter(|| std::thread::scope(|s| {
+        for _ in 0..SHARED_THREADS{
+            s.spawn(|| {
+                for q in &queries{
+                    black_box(reader.lookup(q));
+                }
+            });
+        }
+    })));
+    group.finish();
+
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&pack_path);
+}
+
+criterion_group!(benches, benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+// Write the C header, ukpp.h, from the functions and types in src/lib.rs
+fn main(){
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(format!("{dir}/cbindgen.toml")).expect("can't read cbindgen.toml");
+    // Only this crate's own source, so that cbindgen doesn't need to run cargo metadata
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{dir}/src/lib.rs"))
+        .generate()
+        .expect("can't generate the C header")
+        .write_to_file(format!("{dir}/ukpp.h"));
+}
@@ -0,0 +1,140 @@
+/*
+
+C interface to the Rust pack file reader, for Python, Go, PHP and anything else that can call C.
+
+Like the WebAssembly build, this is the same `Reader` that the packer uses, so the file format is
+only decoded in one place. `cargo build -p nearmypostcode_capi --release` builds a shared and a
+static library in target/release, and cbindgen writes the header, ukpp.h, next to this crate's
+Cargo.toml.
+
+    UkppReader *reader = ukpp_open("postcodes.pack");
+    UkppPostcode found;
+    if (reader && ukpp_lookup(reader, "sw1a 2aa", &found) == UKPP_STATUS_OK){
+        printf("%s %f %f\n", found.postcode, found.latitude, found.longitude);
+    }
+    ukpp_close(reader);
+
+The pack file is memory mapped, and a reader can be used from several threads at once.
+
+*/
+use std::ffi::{CStr, c_char};
+use nearmypostcode_packer::{Reader, Point};
+
+/// A loaded pack file, from `ukpp_open`
+pub struct UkppReader{
+    reader: Reader,
+}
+
+/// Result of a lookup
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UkppStatus{
+    /// Found, and the result was written
+    Ok = 0,
+    /// A well-formed code that isn't in the file, or no postcodes in the file at all
+    NotFound = 1,
+    /// Not a postcode or outward code of the file's scheme
+    InvalidPostcode = 2,
+    /// A null pointer, or a string that isn't UTF-8
+    InvalidArgument = 3,
+}
+
+/// A postcode and its location
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UkppPostcode{
+    /// Canonical form, 7 chars with the inward code aligned right, or the 4 char outward code,
+    /// and a terminating null
+    pub postcode: [c_char; 8],
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl UkppPostcode{
+    fn new(postcode: &str, location: Point) -> Self{
+        let mut out = [0; 8];
+        for (o, &b) in out.iter_mut().zip(postcode.as_bytes().iter().take(7)){
+            *o = b as c_char;
+        }
+        Self{postcode: out, latitude: location.y, longitude: location.x}
+    }
+}
+
+/// Open a pack file of any version, returns null if it can't be read or isn't a pack file.
+/// Close it with `ukpp_close`.
+///
+/// # Safety
+///
+/// `path` must be null or a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ukpp_open(path: *const c_char) -> *mut UkppReader{
+    if path.is_null(){
+        return std::ptr::null_mut();
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else{
+        return std::ptr::null_mut();
+    };
+    match Reader::map(path){
+        Ok(reader) => Box::into_raw(Box::new(UkppReader{reader})),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Look up a postcode, or an outward code by itself, in any case and spacing
+///
+/// # Safety
+///
+/// `reader` must be null or from `ukpp_open`, `postcode` must be null or a null terminated
+/// string, and `result` must be null or point to a `UkppPostcode`.
+#[no_mangle]
+pub unsafe extern "C" fn ukpp_lookup(reader: *const UkppReader, postcode: *const c_char, result: *mut UkppPostcode) -> UkppStatus{
+    if reader.is_null() || postcode.is_null() || result.is_null(){
+        return UkppStatus::InvalidArgument;
+    }
+    let reader = &(*reader).reader;
+    let Ok(postcode) = CStr::from_ptr(postcode).to_str() else{
+        return UkppStatus::InvalidArgument;
+    };
+    let Ok(canonical) = reader.scheme().codec().format(postcode) else{
+        return UkppStatus::InvalidPostcode;
+    };
+    match reader.lookup(&canonical){
+        Some(location) => {
+            *result = UkppPostcode::new(canonical.trim_end(), location);
+            UkppStatus::Ok
+        }
+        None => UkppStatus::NotFound,
+    }
+}
+
+/// The postcode closest to a location, faster if the pack file has a spatial index
+///
+/// # Safety
+///
+/// `reader` must be null or from `ukpp_open`, and `result` must be null or point to a
+/// `UkppPostcode`.
+#[no_mangle]
+pub unsafe extern "C" fn ukpp_nearest(reader: *const UkppReader, latitude: f64, longitude: f64, result: *mut UkppPostcode) -> UkppStatus{
+    if reader.is_null() || result.is_null(){
+        return UkppStatus::InvalidArgument;
+    }
+    match (*reader).reader.nearest(latitude, longitude){
+        Some(p) => {
+            *result = UkppPostcode::new(p.postcode.trim_end(), p.location);
+            UkppStatus::Ok
+        }
+        None => UkppStatus::NotFound,
+    }
+}
+
+/// Close a pack file, null is ignored
+///
+/// # Safety
+///
+/// `reader` must be null or from `ukpp_open`, and not used again.
+#[no_mangle]
+pub unsafe extern "C" fn ukpp_close(reader: *mut UkppReader){
+    if !reader.is_null(){
+        drop(Box::from_raw(reader));
+    }
+}
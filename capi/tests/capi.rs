@@ -0,0 +1,61 @@
+/*
+
+Tests of the C interface, called the way a C program would call it.
+
+*/
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use nearmypostcode_capi::*;
+
+fn golden(version: u32) -> CString{
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("testdata").join("golden").join(format!("v{version}.pack"));
+    CString::new(path.to_string_lossy().as_bytes()).unwrap()
+}
+
+fn empty() -> UkppPostcode{
+    UkppPostcode{postcode: [0; 8], latitude: 0.0, longitude: 0.0}
+}
+
+fn text(p: &UkppPostcode) -> &str{
+    unsafe { CStr::from_ptr(p.postcode.as_ptr()) }.to_str().unwrap()
+}
+
+#[test]
+fn lookup_and_nearest(){
+    unsafe{
+        let reader = ukpp_open(golden(26).as_ptr());
+        assert!(!reader.is_null());
+        let mut found = empty();
+        let postcode = CString::new("sw1a 2aa").unwrap();
+        assert_eq!(ukpp_lookup(reader, postcode.as_ptr(), &mut found), UkppStatus::Ok);
+        assert_eq!(text(&found), "SW1A2AA");
+        assert!((found.latitude - 51.5035).abs() < 0.001 && (found.longitude + 0.1277).abs() < 0.001);
+
+        let outward = CString::new("ab10").unwrap();
+        assert_eq!(ukpp_lookup(reader, outward.as_ptr(), &mut found), UkppStatus::Ok);
+        assert_eq!(text(&found), "AB10");
+
+        let mut nearest = empty();
+        assert_eq!(ukpp_nearest(reader, 51.5035, -0.1277, &mut nearest), UkppStatus::Ok);
+        assert_eq!(text(&nearest), "SW1A2AA");
+
+        let missing = CString::new("ZZ9 9ZZ").unwrap();
+        assert_eq!(ukpp_lookup(reader, missing.as_ptr(), &mut found), UkppStatus::NotFound);
+        let invalid = CString::new("not a postcode").unwrap();
+        assert_eq!(ukpp_lookup(reader, invalid.as_ptr(), &mut found), UkppStatus::InvalidPostcode);
+        assert_eq!(ukpp_lookup(reader, std::ptr::null(), &mut found), UkppStatus::InvalidArgument);
+        ukpp_close(reader);
+    }
+}
+
+#[test]
+fn bad_files_are_null(){
+    unsafe{
+        let missing = CString::new("no such file.pack").unwrap();
+        assert!(ukpp_open(missing.as_ptr()).is_null());
+        assert!(ukpp_open(std::ptr::null()).is_null());
+        let mut found = empty();
+        assert_eq!(ukpp_nearest(std::ptr::null(), 0.0, 0.0, &mut found), UkppStatus::InvalidArgument);
+        ukpp_close(std::ptr::null_mut());
+    }
+}
@@ -0,0 +1,68 @@
+/* C interface to nearmypostcode pack files, generated by cbindgen from src/lib.rs. Don't edit it by hand. */
+
+#ifndef UKPP_H
+#define UKPP_H
+
+#include <stdint.h>
+
+// Result of a lookup
+typedef enum UkppStatus {
+  // Found, and the result was written
+  UKPP_STATUS_OK = 0,
+  // A well-formed code that isn't in the file, or no postcodes in the file at all
+  UKPP_STATUS_NOT_FOUND = 1,
+  // Not a postcode or outward code of the file's scheme
+  UKPP_STATUS_INVALID_POSTCODE = 2,
+  // A null pointer, or a string that isn't UTF-8
+  UKPP_STATUS_INVALID_ARGUMENT = 3,
+} UkppStatus;
+
+// A loaded pack file, from `ukpp_open`
+typedef struct UkppReader UkppReader;
+
+// A postcode and its location
+typedef struct UkppPostcode {
+  // Canonical form, 7 chars with the inward code aligned right, or the 4 char outward code,
+  // and a terminating null
+  char postcode[8];
+  double latitude;
+  double longitude;
+} UkppPostcode;
+
+// Open a pack file of any version, returns null if it can't be read or isn't a pack file.
+// Close it with `ukpp_close`.
+//
+// # Safety
+//
+// `path` must be null or a null terminated string.
+struct UkppReader *ukpp_open(const char *path);
+
+// Look up a postcode, or an outward code by itself, in any case and spacing
+//
+// # Safety
+//
+// `reader` must be null or from `ukpp_open`, `postcode` must be null or a null terminated
+// string, and `result` must be null or point to a `UkppPostcode`.
+enum UkppStatus ukpp_lookup(const struct UkppReader *reader,
+                            const char *postcode,
+                            struct UkppPostcode *result);
+
+// The postcode closest to a location, faster if the pack file has a spatial index
+//
+// # Safety
+//
+// `reader` must be null or from `ukpp_open`, and `result` must be null or point to a
+// `UkppPostcode`.
+enum UkppStatus ukpp_nearest(const struct UkppReader *reader,
+                             double latitude,
+                             double longitude,
+                             struct UkppPostcode *result);
+
+// Close a pack file, null is ignored
+//
+// # Safety
+//
+// `reader` must be null or from `ukpp_open`, and not used again.
+void ukpp_close(struct UkppReader *reader);
+
+#endif  /* UKPP_H */
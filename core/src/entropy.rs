@@ -0,0 +1,80 @@
+/*
+
+Decoding of entropy coded prefix blocks, from format version 14 onwards.
+
+The code is stored in the header of the file as the length in bits of the code for each byte
+value, and the codes are canonical (see RFC 1951). The packer's side, which trains and writes
+the code, is in entropy.rs of the packer.
+
+*/
+use alloc::vec::Vec;
+use crate::record::read_varint;
+
+/// Longest code, in bits
+pub const MAX_CODE_LEN: u8 = 15;
+
+/// Decoding tables for a canonical code
+#[derive(Debug, Clone)]
+pub struct Decoder{
+    /// Number of codes of each length
+    counts: [u16; MAX_CODE_LEN as usize + 1],
+    /// Byte values, in code order
+    symbols: Vec<u8>,
+}
+
+impl Decoder{
+    /// Returns `None` if every length is 0, or the lengths don't make a valid code
+    pub fn new(lengths: &[u8]) -> Option<Self>{
+        if lengths.len() != 256 || lengths.iter().any(|&l| l > MAX_CODE_LEN){
+            return None;
+        }
+        let mut counts = [0u16; MAX_CODE_LEN as usize + 1];
+        let mut symbols = Vec::new();
+        for len in 1..=MAX_CODE_LEN{
+            for (b, &l) in lengths.iter().enumerate(){
+                if l == len{
+                    counts[len as usize] += 1;
+                    symbols.push(b as u8);
+                }
+            }
+        }
+        // Kraft's inequality, there can't be more codes than there is room for
+        let room: u32 = counts.iter().enumerate().map(|(len, &n)| (n as u32) << (MAX_CODE_LEN as usize - len)).sum();
+        if symbols.is_empty() || room > 1 << MAX_CODE_LEN{
+            return None;
+        }
+        Some(Self{counts, symbols})
+    }
+
+    /// Decode one prefix block, returns `None` if it is damaged
+    pub fn decode(&self, data: &[u8]) -> Option<Vec<u8>>{
+        let (len, start) = read_varint(data)?;
+        let len = len as usize;
+        // Every code is at least one bit
+        if len > (data.len() - start) * 8{
+            return None;
+        }
+        let mut out = Vec::with_capacity(len);
+        let mut bits = data[start..].iter().flat_map(|b| (0..8).rev().map(move |i| (b >> i) & 1));
+        while out.len() < len{
+            // Codes of each length follow on from the shorter ones, see `canonical_codes`
+            let mut code: u32 = 0;
+            let mut first: u32 = 0;
+            let mut index: u32 = 0;
+            let mut found = None;
+            for len in 1..=MAX_CODE_LEN as usize{
+                code |= bits.next()? as u32;
+                let count = self.counts[len] as u32;
+                if code < first + count{
+                    found = Some(self.symbols[(index + code - first) as usize]);
+                    break;
+                }
+                index += count;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+            out.push(found?);
+        }
+        Some(out)
+    }
+}
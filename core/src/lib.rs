@@ -0,0 +1,27 @@
+#![no_std]
+/*
+
+Decoding core of the pack file reader, for devices without an operating system.
+
+This is the part of `Reader` that turns a prefix block of the file in to its records, and undoes
+the entropy coding of version 14 files. It needs only integer maths and slices, and the entropy
+decoder needs an allocator, so it builds with `no_std` and `alloc`. A handheld device can keep a
+pack file in flash, find a block with the quick lookup table, and decode it in place:
+
+    for record in Records::new(block, version, resolution){
+        // record.code is the packed postcode, record.lat and record.long the quantized location
+    }
+
+Everything else, reading the header and the other sections, packing postcodes to look them up
+and turning quantized locations in to latitudes and longitudes, is in `Reader`, which uses this
+crate, so the records are only decoded in one place. See `Packer::write_to` for the file
+structure.
+
+*/
+extern crate alloc;
+
+pub mod record;
+pub mod entropy;
+
+pub use record::{Records, Record, DecodeState, read_varint};
+pub use entropy::Decoder;
@@ -0,0 +1,207 @@
+/*
+
+Records of a prefix block.
+
+Each record is a postcode and its quantized location, stored either in full or as a delta from
+the record before it, and the decoder state is reset at the start of each block. The encodings
+are described in `Packer::write_to`.
+
+*/
+
+/// Format byte of a run record, version 12 onwards
+pub const RUN_FORMAT: u8 = 0x41;
+
+/// Format byte of a varint record, version 13 onwards. The terminated flag can be added to it.
+pub const VARINT_FORMAT: u8 = 0x42;
+
+/// Length in bytes of each axis of an absolute location at a resolution
+pub fn coord_len(resolution: u32) -> usize{
+    resolution.div_ceil(8) as usize
+}
+
+/// Largest quantized location at a resolution
+pub fn max_quantized(resolution: u32) -> u32{
+    1u32.checked_shl(resolution).map_or(u32::MAX, |n| n - 1)
+}
+
+/// Decoder state, this is reset at the start of each prefix block
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeState{
+    pub code: u32,
+    pub lat: u32,
+    pub long: u32,
+}
+
+/// A single decoded record, with the quantized location
+#[derive(Debug, Clone, Copy)]
+pub struct Record{
+    pub code: u32,
+    pub lat: u32,
+    pub long: u32,
+    pub is_partial: bool,
+    /// Encoding of the record, indexed like `reader::RECORD_KINDS`
+    pub kind: usize,
+    /// Year of termination, as stored in the file
+    pub terminated: Option<u8>,
+    /// Length of the record in bytes
+    pub len: usize,
+}
+
+/// Decode the record at the start of `data`, returns the record and its length in bytes.
+///
+/// `has_flags` is set for version 6 onwards, where the format byte has a terminated flag.
+/// `coord_len` is the length of each axis of an absolute location, see `coord_len`.
+fn decode_record(data: &[u8], state: &DecodeState, has_flags: bool, coord_len: usize) -> Option<(Record, usize)>{
+    let format = *data.first()?;
+    let pc_is_delta = (format & 0x80) > 0;
+    let ll_is_delta = (format & 0x40) > 0;
+    let is_terminated = has_flags && (format & 0x20) > 0;
+    let extra = if has_flags { format & 0x1f } else { format & 0x3f };
+    let mut pos = 1;
+    let mut is_partial = false;
+    let code = if pc_is_delta{
+        // Postcode delta encoding is part of the format byte
+        state.code.checked_add(extra as u32)?.checked_add(1)?
+    }
+    else{
+        is_partial = extra == if has_flags { 0x10 } else { 0x20 };
+        let c = data.get(pos..pos+3)?;
+        pos += 3;
+        u32::from_le_bytes([c[0], c[1], c[2], 0])
+    };
+    let (lat, long) = if ll_is_delta{
+        let d = data.get(pos..pos+2)?;
+        pos += 2;
+        let dlat = d[0] as i8;
+        let dlong = d[1] as i8;
+        (state.lat.wrapping_add_signed(dlat as i32), state.long.wrapping_add_signed(dlong as i32))
+    }
+    else{
+        let d = data.get(pos..pos+(coord_len*2))?;
+        pos += coord_len*2;
+        let coord = |b: &[u8]| b.iter().rev().fold(0, |x, &b| (x << 8) | b as u32);
+        (coord(&d[..coord_len]), coord(&d[coord_len..]))
+    };
+    let terminated = if is_terminated{
+        let year = *data.get(pos)?;
+        pos += 1;
+        Some(year)
+    }
+    else{
+        None
+    };
+    let kind = (pc_is_delta as usize) + ((ll_is_delta as usize) * 2);
+    Some((Record{code, lat, long, is_partial, kind, terminated, len: pos}, pos))
+}
+
+/// Decode one postcode of a run (version 12 onwards). The postcode delta is after `header`
+/// bytes, which is the length of the run record's header for the first postcode, or 0.
+fn decode_run_entry(data: &[u8], state: &DecodeState, header: usize) -> Option<(Record, usize)>{
+    let delta = *data.get(header)?;
+    let len = header + 1;
+    let code = state.code.checked_add(delta as u32)?.checked_add(1)?;
+    Some((Record{code, lat: state.lat, long: state.long, is_partial: false, kind: 4, terminated: None, len}, len))
+}
+
+/// Read an unsigned LEB128 varint, returns the value and its length in bytes
+pub fn read_varint(data: &[u8]) -> Option<(u32, usize)>{
+    let mut x: u32 = 0;
+    for (i, &b) in data.iter().enumerate().take(5){
+        x |= ((b & 0x7f) as u32).checked_shl(7 * i as u32)?;
+        if b & 0x80 == 0{
+            return Some((x, i + 1));
+        }
+    }
+    None
+}
+
+/// Reverse of the zigzag encoding of signed deltas
+fn unzigzag(z: u32) -> i32{
+    ((z >> 1) as i32) ^ -((z & 1) as i32)
+}
+
+/// Decode a varint record (version 13 onwards)
+fn decode_varint_record(data: &[u8], state: &DecodeState) -> Option<(Record, usize)>{
+    let is_terminated = (*data.first()? & 0x20) > 0;
+    let mut pos = 1;
+    let (delta, len) = read_varint(data.get(pos..)?)?;
+    pos += len;
+    let (dlat, len) = read_varint(data.get(pos..)?)?;
+    pos += len;
+    let (dlong, len) = read_varint(data.get(pos..)?)?;
+    pos += len;
+    let terminated = if is_terminated{
+        let year = *data.get(pos)?;
+        pos += 1;
+        Some(year)
+    }
+    else{
+        None
+    };
+    let code = state.code.checked_add(delta)?.checked_add(1)?;
+    let lat = state.lat.wrapping_add_signed(unzigzag(dlat));
+    let long = state.long.wrapping_add_signed(unzigzag(dlong));
+    Some((Record{code, lat, long, is_partial: false, kind: 5, terminated, len: pos}, pos))
+}
+
+/// Iterator over the records of a prefix block
+#[derive(Debug, Clone)]
+pub struct Records<'a>{
+    data: &'a [u8],
+    state: DecodeState,
+    has_flags: bool,
+    has_runs: bool,
+    has_varints: bool,
+    /// Length of each axis of an absolute location
+    coord_len: usize,
+    /// Largest quantized location, anything bigger is damage
+    max: u32,
+    /// Number of postcodes left in the current run
+    run: usize,
+}
+
+impl<'a> Records<'a>{
+    /// Records of a block of a file with this format version and resolution. Entropy coded
+    /// blocks must be decoded first, see `Decoder`. A block that is damaged or cut short ends
+    /// at the last record that can be read, and every location is within the resolution.
+    pub fn new(data: &'a [u8], version: u32, resolution: u32) -> Self{
+        Self{
+            data,
+            state: DecodeState::default(),
+            has_flags: version >= 6,
+            has_runs: version >= 12,
+            has_varints: version >= 13,
+            coord_len: coord_len(resolution),
+            max: max_quantized(resolution),
+            run: 0,
+        }
+    }
+}
+
+impl Iterator for Records<'_>{
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record>{
+        let (record, len) = if self.run > 0{
+            self.run -= 1;
+            decode_run_entry(self.data, &self.state, 0)?
+        }
+        else if self.has_runs && self.data.first() == Some(&RUN_FORMAT){
+            self.run = *self.data.get(1)? as usize;
+            decode_run_entry(self.data, &self.state, 2)?
+        }
+        else if self.has_varints && self.data.first().is_some_and(|f| f & !0x20 == VARINT_FORMAT){
+            decode_varint_record(self.data, &self.state)?
+        }
+        else{
+            decode_record(self.data, &self.state, self.has_flags, self.coord_len)?
+        };
+        // Deltas wrap around, so a damaged block can go outside the resolution
+        if record.lat > self.max || record.long > self.max{
+            return None;
+        }
+        self.data = &self.data[len..];
+        self.state = DecodeState{code: record.code, lat: record.lat, long: record.long};
+        Some(record)
+    }
+}
@@ -0,0 +1,21 @@
+/*
+
+Feeds 7 byte strings to pack_code. Anything that packs must unpack to the same string.
+
+*/
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nearmypostcode_packer::{pack_code, unpack_code};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(code) = std::str::from_utf8(data) else {
+        return;
+    };
+    if code.len() != 7{
+        return;
+    }
+    if let Ok(packed) = pack_code(code){
+        let packed = u32::from_le_bytes([packed[0], packed[1], packed[2], 0]);
+        assert_eq!(unpack_code(&code[0..2], packed), code);
+    }
+});
@@ -0,0 +1,39 @@
+/*
+
+Feeds arbitrary bytes to the pack file reader. The reader runs on other people's servers, and in
+browsers through wasm, so a truncated or hostile file must give an error or wrong answers, never
+a panic. Anything that opens is read all the way through.
+
+Seed the corpus with real pack files, and the damaged files in seeds/reader that used to panic,
+so that they stay fixed, e.g. `cargo fuzz run reader corpus/reader seeds/reader ../testdata/vectors-v17`.
+
+*/
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nearmypostcode_packer::{Reader, CentroidLevel};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = Reader::from_bytes(data.to_vec()) else {
+        return;
+    };
+    let _ = reader.file_stats();
+    let _ = reader.block_ranges();
+    // Re-encodes every record, as inspect does
+    let _ = reader.order_lens();
+    let mut entries = 0;
+    for p in reader.iter(){
+        // Look up some of the entries that were found, which takes a different path through the blocks
+        if entries < 16{
+            let _ = reader.lookup(&p.postcode);
+            let _ = reader.country(&p.postcode);
+        }
+        entries += 1;
+    }
+    let _ = reader.lookup("AB1 0AA");
+    let _ = reader.lookup("AB1 ");
+    let _ = reader.suggest("AB1 0AA", 2);
+    let _ = reader.complete_postcodes("AB1", 20);
+    let _ = reader.nearest(54.0, -2.0);
+    let _ = reader.within_radius(54.0, -2.0, 1000.0);
+    let _ = reader.centroids(CentroidLevel::District);
+});
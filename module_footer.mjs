@@ -1 +1 @@
-export {NearMyPostcode};
+export {NearMyPostcode, NearMyPostcodeLayered};
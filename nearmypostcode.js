@@ -20,7 +20,7 @@ async function NearMyPostcode(datafile_url, quiet=false){
     // Header, 16 bytes
     //
     //     magic:   4 bytes "UKPP" - magic number for "UK Postcode Pack"
-    //     version: 4 bytes (u32)  - version number of the file format (this code generates version 1)
+    //     version: 4 bytes (u32)  - version number of the file format
     //     date:    8 bytes (u64)  - seconds since unix epoch when the most recently updated postcode was updated
 
        
@@ -29,11 +29,47 @@ async function NearMyPostcode(datafile_url, quiet=false){
         throw new Error("Postcode data file is not using a known format");
     }
     const version = new Uint32Array(deltapack.slice(4,8))[0];
-    const max_version = 2; // This version of the library only supports version 1
+    const max_version = 26; // This version of the library supports versions 1 to 26
     if (version > max_version){
         throw new Error(`Postcode data file uses format version ${version}. This NMP version only supports data formats up to ${max_version}. NMP needs to be updated.`);
     }
 
+    // Version 10 onwards ends with checksums, the last of which is a CRC32 of the rest of the file
+    //
+    //     data_crc, centroids_crc, countries_crc, index_crc: 4 bytes each (u32), checksums of each section
+    //     file_crc:                                          4 bytes (u32)
+    //
+    // Version 19 onwards also has attributes_crc, after data_crc, version 20 onwards also has
+    // prefix_extents_crc, after that, and version 21 onwards also has sector_bitmap_crc, after that.
+    // Version 26 onwards only has file_crc, the checksum of each section is in the section table
+    //
+    // Only the whole file is checked here, then the checksums are removed
+    if (version >= 10){
+        function crc32(bytes){
+            const table = new Int32Array(256).map((_, n) => {
+                for (let k = 0; k < 8; k++){
+                    n = (n >>> 1) ^ (0xedb88320 & -(n & 1));
+                }
+                return n;
+            });
+            let crc = -1;
+            for (const b of bytes){
+                crc = table[(crc ^ b) & 0xff] ^ (crc >>> 8);
+            }
+            return (crc ^ -1) >>> 0;
+        }
+        const checksums_len = ((version >= 26)? 1 : (version >= 21)? 8 : (version >= 20)? 7 : (version >= 19)? 6 : 5)*4;
+        if (deltapack.byteLength < 16 + checksums_len){
+            throw new Error("Postcode data file is corrupt or truncated");
+        }
+        const body = new Uint8Array(deltapack, 0, deltapack.byteLength - 4);
+        const file_crc = new DataView(deltapack).getUint32(deltapack.byteLength - 4, true);
+        if (crc32(body) != file_crc){
+            throw new Error("Postcode data file is corrupt or truncated");
+        }
+        deltapack = deltapack.slice(0, deltapack.byteLength - checksums_len);
+    }
+
     const timestamp = new Uint32Array(deltapack.slice(8,16));
     const unixtime = BigInt(timestamp[0]) + (BigInt(timestamp[1]) * (2n**32n));
     const date = new Date(Number(unixtime*1000n));
@@ -45,8 +81,441 @@ async function NearMyPostcode(datafile_url, quiet=false){
     var nmp = Object();
     nmp.deltapack = deltapack.slice(16); // Discard the header, no longer needed
 
+    // Version 4 onwards has a compression codec after the header
+    //
+    //     codec:   4 bytes (u32)  - compression applied to the postcode data (0 = none, 1 = gzip, 2 = zstd, 3 = brotli)
+    //
+    // Only the postcode data is compressed, so the codec, extents and lookup table can always be read directly
+    //
+    // Version 5 onwards also has the length of the spatial index section (which NMP does not use)
+    //
+    //     index_len: 4 bytes (u32) - length of the spatial index section at the end of the file, 0 if there is no index
+    //
+    // Version 7 onwards also has the length of the countries section, which is just before the spatial index
+    //
+    //     countries_len: 4 bytes (u32) - length of the countries section, 0 if there are no countries
+    //
+    // Version 8 onwards also has the length of the centroids section, which is just before the countries
+    //
+    //     centroids_len: 4 bytes (u32) - length of the centroids section, 0 if there are no centroids
+    //
+    // Version 9 onwards also has the chunk size that the prefix blocks are aligned to (which NMP does not use)
+    //
+    //     chunk_size: 4 bytes (u32)
+    //
+    // Version 11 onwards also has the fingerprint of the key that signed the file (which NMP does not use)
+    //
+    //     key_id: 8 bytes
+    //
+    // Version 14 onwards also has the entropy code, which is all zeros if the postcode data is not entropy coded
+    //
+    //     code_lengths: 256 bytes (u8 each) - length in bits of the code for each byte value
+    //
+    // Version 15 onwards also has the number of prefix blocks with their own bounding box (the boxes are after the extents)
+    //
+    //     local_boxes: 4 bytes (u32)
+    //
+    // Version 16 onwards also has the number of bits per axis of each location (8, 12, 16 or 24)
+    //
+    //     resolution: 4 bytes (u32)
+    //
+    // Version 17 onwards also says which country's codes the file has (0 for UK postcodes, 1 for Irish Eircodes)
+    //
+    //     scheme: 4 bytes (u32)
+    //
+    // Version 18 onwards also says which kind of spatial index the file has (0 for a grid, 1 for a k-d tree, 2 for geohash buckets).
+    // The index isn't used here, so this is skipped
+    //
+    //     index_kind: 4 bytes (u32)
+    //
+    // Version 19 onwards also has the length of the attributes section, which is just before the centroids
+    //
+    //     attributes_len: 4 bytes (u32) - length of the attributes section, 0 if there are no attributes
+    //
+    // Version 20 onwards also has the length of the prefix extents section, which is just before the attributes
+    //
+    //     prefix_extents_len: 4 bytes (u32) - length of the prefix extents section, 0 if there are none
+    //
+    // Version 21 onwards also has the length of the sector bitmap section, which is just before the prefix extents
+    //
+    //     sector_bitmap_len: 4 bytes (u32) - length of the sector bitmap section, 0 if there is none
+    //
+    // Version 22 onwards also has the release date of the dataset
+    //
+    //     release_date: 8 bytes (u64) - seconds since unix epoch of the day the dataset was released, 0 if it isn't known
+    //
+    // Version 23 onwards also has text fields that say how the file was made
+    //
+    //     metadata_len: 4 bytes (u32) - length of the fields
+    //     fields, one after the other:
+    //         key_len: 1 byte (u8), key: key_len bytes (ASCII)
+    //         value_len: 2 bytes (u16), value: value_len bytes (UTF-8)
+    //
+    // Version 24 onwards also has the exact locations of the entries outside the bounding box, whose records are on its edge
+    //
+    //     count: 4 bytes (u32)
+    //     outliers, sorted by postcode:
+    //         postcode: 7 bytes (canonical, outward codes padded to 7 chars)
+    //         long: 4 bytes (u32), lat: 4 bytes (u32) - fractions of the whole range, like the bounding box
+    //
+    // Version 25 onwards also has coordinate groups, each with its own box that the locations of its postcodes are quantized in
+    //
+    //     count: 4 bytes (u32) - number of groups, 0 if there are none
+    //     groups, the main group first:
+    //         minlong, maxlong, minlat, maxlat: 4 bytes each (u32, fractions of the whole range, like the bounding box)
+    //     members: 4 bytes (u32) - number of outward codes that aren't in the main group
+    //     outward codes, sorted:
+    //         outward: 4 bytes (padded, e.g. "ZE1 "), group: 1 byte (u8)
+    //
+    // The lookup table in version 9 has absolute byte ranges, so keep track of how much was removed from the front
+    nmp.header_len = 16;
+    nmp.countries = new Uint8Array(0);
+    nmp.centroids = new Uint8Array(0);
+    nmp.attributes = new Uint8Array(0);
+    nmp.prefix_extents = new Uint8Array(0);
+    nmp.sector_bitmap = new Uint8Array(0);
+    nmp.date_released = null;
+    nmp.metadata = {};
+    nmp.outliers = new Map();
+    nmp.groups = [];
+    nmp.group_members = new Map();
+    nmp.local_boxes_count = 0;
+    nmp.resolution = 16;
+    nmp.scheme = 0;
+    // EPSG code of the coordinate reference system that the locations are in
+    nmp.crs_epsg = 4326;
+
+    // Parts of the header that are read the same way from version 26's sections
+    const text = new TextDecoder();
+    const dequantize = (x, range) => ((x / 4294967295) * (2 * range)) - range;
+    function read_entropy_code(buffer){
+        // The codes are canonical (as in deflate), so the lengths are enough to decode them. Keep
+        // the number of codes of each length, and the byte values in code order.
+        const lengths = new Uint8Array(buffer);
+        if (lengths.some((l) => l > 0)){
+            const counts = new Array(16).fill(0);
+            const symbols = [];
+            for (let len = 1; len <= 15; len++){
+                for (let b = 0; b < 256; b++){
+                    if (lengths[b] == len){
+                        counts[len] += 1;
+                        symbols.push(b);
+                    }
+                }
+            }
+            nmp.entropy_code = {counts, symbols};
+        }
+    }
+    function read_metadata(buffer){
+        const view = new DataView(buffer);
+        let pos = 0;
+        while (pos < buffer.byteLength){
+            const key_len = view.getUint8(pos);
+            const key = text.decode(new Uint8Array(buffer, pos + 1, key_len));
+            const value_len = view.getUint16(pos + 1 + key_len, true);
+            nmp.metadata[key] = text.decode(new Uint8Array(buffer, pos + 3 + key_len, value_len));
+            pos += 3 + key_len + value_len;
+        }
+    }
+    // Returns the length of the outliers
+    function read_outliers(buffer){
+        const view = new DataView(buffer);
+        const count = view.getUint32(0, true);
+        for (let pos = 4; pos < 4 + (count * 15); pos += 15){
+            const postcode = text.decode(new Uint8Array(buffer, pos, 7));
+            nmp.outliers.set(postcode, [dequantize(view.getUint32(pos + 7, true), 180), dequantize(view.getUint32(pos + 11, true), 90)]);
+        }
+        return 4 + (count * 15);
+    }
+    // Returns the length of the groups
+    function read_groups(buffer){
+        const view = new DataView(buffer);
+        const count = view.getUint32(0, true);
+        for (let pos = 4; pos < 4 + (count * 16); pos += 16){
+            const q = (i) => view.getUint32(pos + i, true);
+            nmp.groups.push([dequantize(q(0), 180), dequantize(q(4), 180), dequantize(q(8), 90), dequantize(q(12), 90)]);
+        }
+        const members_start = 4 + (count * 16);
+        const members = view.getUint32(members_start, true);
+        for (let pos = members_start + 4; pos < members_start + 4 + (members * 5); pos += 5){
+            nmp.group_members.set(text.decode(new Uint8Array(buffer, pos, 4)), view.getUint8(pos + 4));
+        }
+        return members_start + 4 + (members * 5);
+    }
+    function check_scheme(){
+        if (nmp.scheme > 1){
+            throw new Error(`Postcode data file uses postcode scheme ${nmp.scheme}, which this NMP version does not support. NMP needs to be updated.`);
+        }
+    }
+
+    // Version 26 onwards has a table of sections after the header instead of all of the above
+    //
+    //     count: 4 bytes (u32)
+    //     sections, count of these:
+    //         kind:   2 bytes (u16)
+    //         flags:  2 bytes (u16) - bit 0 is set if the file can't be read without the section
+    //         offset: 4 bytes (u32) - absolute position in the file
+    //         length: 4 bytes (u32)
+    //         crc:    4 bytes (u32) - not checked here, the whole file has been
+    //
+    // Each section is laid out as the field or section of the same name in earlier versions. Sections
+    // that would be empty are left out. Kinds that NMP doesn't use (the signing key, chunk size,
+    // spatial index and record order) are skipped, as are kinds it doesn't know, unless they are
+    // required. Records are found by reading a block from its start, so their order doesn't matter.
+    //
+    //     1 bounds, 2 lookup table, 3 postcode data, 4 resolution, 5 scheme, 6 entropy code,
+    //     7 local boxes, 8 outliers, 9 groups, 10 crs (0 WGS84, 1 ETRS89, 2 OSGB36),
+    //     16 release date, 17 metadata, 18 key id, 19 chunk size, 20 sector bitmap,
+    //     21 prefix extents, 22 attributes, 23 centroids, 24 countries, 25 to 27 spatial index,
+    //     28 record order
+    let bounds_section, local_boxes_section;
+    if (version >= 26){
+        const view = new DataView(deltapack);
+        const count = view.getUint32(16, true);
+        const sections = new Map();
+        for (let entry = 20; entry < 20 + (count * 16); entry += 16){
+            const kind = view.getUint16(entry, true);
+            const required = (view.getUint16(entry + 2, true) & 1) != 0;
+            const offset = view.getUint32(entry + 4, true);
+            if (required && !((kind >= 1 && kind <= 10) || (kind >= 16 && kind <= 27))){
+                throw new Error(`Postcode data file has a section of kind ${kind}, which this NMP version does not support. NMP needs to be updated.`);
+            }
+            sections.set(kind, {offset, bytes: deltapack.slice(offset, offset + view.getUint32(entry + 8, true))});
+        }
+        const section = (kind) => sections.get(kind)?.bytes;
+        const u32 = (kind) => new Uint32Array(section(kind))[0];
+        if (sections.has(4)){
+            nmp.resolution = u32(4);
+        }
+        if (sections.has(5)){
+            nmp.scheme = u32(5);
+            check_scheme();
+        }
+        if (sections.has(6)){
+            read_entropy_code(section(6));
+        }
+        if (sections.has(8)){
+            read_outliers(section(8));
+        }
+        if (sections.has(9)){
+            read_groups(section(9));
+        }
+        if (sections.has(10)){
+            nmp.crs_epsg = [4326, 4258, 4277][u32(10)];
+        }
+        if (sections.has(16)){
+            nmp.date_released = new Date(Number(new DataView(section(16)).getBigUint64(0, true)*1000n));
+        }
+        if (sections.has(17)){
+            read_metadata(section(17));
+        }
+        nmp.sector_bitmap = new Uint8Array(section(20) ?? new ArrayBuffer(0));
+        nmp.prefix_extents = new Uint8Array(section(21) ?? new ArrayBuffer(0));
+        nmp.attributes = new Uint8Array(section(22) ?? new ArrayBuffer(0));
+        nmp.centroids = new Uint8Array(section(23) ?? new ArrayBuffer(0));
+        nmp.countries = new Uint8Array(section(24) ?? new ArrayBuffer(0));
+        // The lookup table has absolute ranges, so keep the whole file
+        bounds_section = section(1);
+        local_boxes_section = section(7) ?? new ArrayBuffer(0);
+        nmp.local_boxes_count = local_boxes_section.byteLength / 10;
+        nmp.deltapack = deltapack;
+        nmp.header_len = 0;
+        nmp.lut_start = sections.get(2).offset;
+    }
+    else if (version >= 4){
+        const codec = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+        nmp.deltapack = nmp.deltapack.slice(4);
+        nmp.header_len += 4;
+        if (version >= 5){
+            const index_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            nmp.deltapack = nmp.deltapack.slice(4, nmp.deltapack.byteLength - index_len);
+            nmp.header_len += 4;
+        }
+        if (version >= 7){
+            const countries_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            const countries_start = nmp.deltapack.byteLength - countries_len;
+            nmp.countries = new Uint8Array(nmp.deltapack.slice(countries_start));
+            nmp.deltapack = nmp.deltapack.slice(4, countries_start);
+            nmp.header_len += 4;
+        }
+        if (version >= 8){
+            const centroids_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            const centroids_start = nmp.deltapack.byteLength - centroids_len;
+            nmp.centroids = new Uint8Array(nmp.deltapack.slice(centroids_start));
+            nmp.deltapack = nmp.deltapack.slice(4, centroids_start);
+            nmp.header_len += 4;
+        }
+        if (version >= 9){
+            nmp.deltapack = nmp.deltapack.slice(4);
+            nmp.header_len += 4;
+        }
+        if (version >= 11){
+            nmp.deltapack = nmp.deltapack.slice(8);
+            nmp.header_len += 8;
+        }
+        if (version >= 14){
+            read_entropy_code(nmp.deltapack.slice(0,256));
+            nmp.deltapack = nmp.deltapack.slice(256);
+            nmp.header_len += 256;
+        }
+        if (version >= 15){
+            nmp.local_boxes_count = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            nmp.deltapack = nmp.deltapack.slice(4);
+            nmp.header_len += 4;
+        }
+        if (version >= 16){
+            nmp.resolution = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            nmp.deltapack = nmp.deltapack.slice(4);
+            nmp.header_len += 4;
+        }
+        if (version >= 17){
+            nmp.scheme = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            check_scheme();
+            nmp.deltapack = nmp.deltapack.slice(4);
+            nmp.header_len += 4;
+        }
+        if (version >= 18){
+            nmp.deltapack = nmp.deltapack.slice(4);
+            nmp.header_len += 4;
+        }
+        // The centroids have already been removed from the end, so the attributes are at the end now
+        if (version >= 19){
+            const attributes_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            const attributes_start = nmp.deltapack.byteLength - attributes_len;
+            nmp.attributes = new Uint8Array(nmp.deltapack.slice(attributes_start));
+            nmp.deltapack = nmp.deltapack.slice(4, attributes_start);
+            nmp.header_len += 4;
+        }
+        if (version >= 20){
+            const prefix_extents_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            const prefix_extents_start = nmp.deltapack.byteLength - prefix_extents_len;
+            nmp.prefix_extents = new Uint8Array(nmp.deltapack.slice(prefix_extents_start));
+            nmp.deltapack = nmp.deltapack.slice(4, prefix_extents_start);
+            nmp.header_len += 4;
+        }
+        if (version >= 21){
+            const sector_bitmap_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            const sector_bitmap_start = nmp.deltapack.byteLength - sector_bitmap_len;
+            nmp.sector_bitmap = new Uint8Array(nmp.deltapack.slice(sector_bitmap_start));
+            nmp.deltapack = nmp.deltapack.slice(4, sector_bitmap_start);
+            nmp.header_len += 4;
+        }
+        if (version >= 22){
+            const release_date = new DataView(nmp.deltapack).getBigUint64(0, true);
+            if (release_date > 0n){
+                nmp.date_released = new Date(Number(release_date*1000n));
+            }
+            nmp.deltapack = nmp.deltapack.slice(8);
+            nmp.header_len += 8;
+        }
+        if (version >= 23){
+            const metadata_len = new Uint32Array(nmp.deltapack.slice(0,4))[0];
+            read_metadata(nmp.deltapack.slice(4, 4 + metadata_len));
+            nmp.deltapack = nmp.deltapack.slice(4 + metadata_len);
+            nmp.header_len += 4 + metadata_len;
+        }
+        if (version >= 24){
+            const outliers_len = read_outliers(nmp.deltapack);
+            nmp.deltapack = nmp.deltapack.slice(outliers_len);
+            nmp.header_len += outliers_len;
+        }
+        if (version >= 25){
+            const groups_len = read_groups(nmp.deltapack);
+            nmp.deltapack = nmp.deltapack.slice(groups_len);
+            nmp.header_len += groups_len;
+        }
+        if (codec != 0){
+            const codec_name = ["none", "gzip", "zstd", "brotli"][codec];
+            let stream;
+            try{
+                stream = new DecompressionStream(codec_name);
+            }
+            catch{
+                throw new Error(`Postcode data file is compressed with ${codec_name ?? `unknown codec ${codec}`}, which can not be decompressed here`);
+            }
+            const datastart = (4*4) + (4*26*36) + 4;
+            const compressed = new Blob([nmp.deltapack.slice(datastart)]).stream().pipeThrough(stream);
+            const records = await new Response(compressed).arrayBuffer();
+            const unpacked = new Uint8Array(datastart + records.byteLength);
+            unpacked.set(new Uint8Array(nmp.deltapack.slice(0, datastart)), 0);
+            unpacked.set(new Uint8Array(records), datastart);
+            nmp.deltapack = unpacked.buffer;
+        }
+    }
+
     nmp.date_last_updated = date;
 
+    // Version 3 onwards stores the bounding box as u32 fractions of the whole range of lat/long
+    if (version >= 3){
+        const q = new Uint32Array((version >= 26)? bounds_section : nmp.deltapack.slice(0,16));
+        nmp.extents = [dequantize(q[0], 180), dequantize(q[1], 180), dequantize(q[2], 90), dequantize(q[3], 90)];
+        nmp.extents_len = 4*4;
+    }
+    else{
+        nmp.extents = new Float64Array(nmp.deltapack.slice(0,32));
+        nmp.extents_len = 8*4;
+    }
+
+    // Version 15 onwards, prefix blocks can have their own bounding box, which the locations in the block
+    // are quantized in. Each box is 10 bytes, after the extents:
+    //
+    //     block:                           2 bytes (u16, index in the quick lookup table)
+    //     minlong, maxlong, minlat, maxlat: 2 bytes each (u16, fractions of the file's bounding box)
+    nmp.local_boxes = new Map();
+    {
+        const [minlong,maxlong,minlat,maxlat] = nmp.extents;
+        const view = (version >= 26)? new DataView(local_boxes_section) : new DataView(nmp.deltapack, nmp.extents_len, nmp.local_boxes_count * 10);
+        for (let i = 0; i < view.byteLength; i += 10){
+            const q = (j) => view.getUint16(i + j, true);
+            const x = (v) => minlong + ((maxlong-minlong)*(v/65535.0));
+            const y = (v) => minlat + ((maxlat-minlat)*(v/65535.0));
+            nmp.local_boxes.set(q(0), [x(q(2)), x(q(4)), y(q(6)), y(q(8))]);
+        }
+        if (version < 26){
+            nmp.lut_start = nmp.extents_len + view.byteLength;
+        }
+    }
+
+    // Decode one entropy coded prefix block (version 14 onwards), which starts with its decoded length
+    // as a varint, followed by the code of each byte, most significant bit first
+    nmp.decode_block = ((bytes) => {
+        const {counts, symbols} = nmp.entropy_code;
+        let pos = 0;
+        let len = 0;
+        let scale = 1;
+        let b;
+        do {
+            b = bytes[pos];
+            pos += 1;
+            len += (b & 0x7f) * scale;
+            scale *= 128;
+        } while (b & 0x80);
+        const out = new Uint8Array(len);
+        let bit = pos * 8;
+        for (let i = 0; i < len; i++){
+            // Codes of each length follow on from the shorter ones
+            let code = 0;
+            let first = 0;
+            let index = 0;
+            let found = false;
+            for (let l = 1; l <= 15 && !found; l++){
+                code |= (bytes[bit >> 3] >> (7 - (bit & 7))) & 1;
+                bit += 1;
+                if (code < first + counts[l]){
+                    out[i] = symbols[index + code - first];
+                    found = true;
+                }
+                index += counts[l];
+                first = (first + counts[l]) << 1;
+                code <<= 1;
+            }
+            if (!found || bit > bytes.length * 8){
+                throw new Error("Postcode data file is corrupt or truncated");
+            }
+        }
+        return out.buffer;
+    });
+
     nmp.E_FORMAT = "Postcode format not recognised";
     nmp.E_NOTFOUND = "Postcode not found";
     nmp.E_DATA_VERSION = "Data file format does not support this type of postcode";
@@ -106,6 +575,17 @@ async function NearMyPostcode(datafile_url, quiet=false){
             return encoded;
         }
 
+        if (postcode.length == 7 && nmp.scheme == 1){
+            // Eircodes: the last char of the routing key, then the four chars of the unique identifier
+            const routing_last = "0123456789W";
+            const unique = "0123456789ACDEFHKNPRTVWXY";
+            let encoded = routing_last.indexOf(postcode[2]);
+            for (const x of postcode.slice(3)){
+                encoded = (encoded * 25) + unique.indexOf(x);
+            }
+            return encoded;
+        }
+
         if (postcode.length == 7){
             const [a,b,c,d,e,f,g] = postcode;
 
@@ -124,7 +604,32 @@ async function NearMyPostcode(datafile_url, quiet=false){
         throw new Error(nmp.E_FORMAT);
     });
 
+    nmp.format_eircode = ((pc) => {
+        // An Eircode is a routing key (a letter and two digits, or D6W) followed by a four character
+        // unique identifier. The canonical format has no space, and a routing key by itself is padded
+        // to 4 characters like a UK outward code.
+        const VALID_CHARS = " abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        if (!Array.from(pc).every((c) => VALID_CHARS.includes(c))){
+            throw new Error(nmp.E_FORMAT);
+        }
+        const code = pc.replaceAll(" ", "").toUpperCase();
+        const key = code.slice(0, 3);
+        if (!(key == "D6W" || /^[ACDEFHKNPRTVWXY][0-9][0-9]$/.test(key))){
+            throw new Error(nmp.E_FORMAT);
+        }
+        if (code.length == 3){
+            return key + " ";
+        }
+        if (code.length == 7 && /^[0-9ACDEFHKNPRTVWXY]{4}$/.test(code.slice(3))){
+            return code;
+        }
+        throw new Error(nmp.E_FORMAT);
+    });
+
     nmp.format_postcode = ((pc) => {
+        if (nmp.scheme == 1){
+            return nmp.format_eircode(pc);
+        }
         // A UK postcode has two parts.
         // The first is the "outward code", and is 2, 3, or 4 characters long
         // The second is the "inward code", which is always 3 characters long
@@ -167,25 +672,40 @@ async function NearMyPostcode(datafile_url, quiet=false){
         }
     })
 
-    nmp.lookup_postcode = ((postcode)=>{
+    nmp.lookup_postcode = ((postcode, approximate=false)=>{
         // File structure:
         // (all numbers in little endian unless specified otherwise)
         // 
         // Header, 16 bytes (skipped by loader)
         //     (skipped)
         //
-        // Bounding box extents, 4*8=32 bytes
+        // Bounding box extents, version 1 and 2, 4*8=32 bytes
         //
         //     minlong: 8 bytes (f64)
         //     maxlong: 8 bytes (f64)
         //     minlat:  8 bytes (f64)
         //     maxlat:  8 bytes (f64)
+        //
+        // Bounding box extents, version 3, 4*4=16 bytes
+        //
+        //     minlong: 4 bytes (u32, fraction of the range -180 to 180)
+        //     maxlong: 4 bytes (u32, fraction of the range -180 to 180)
+        //     minlat:  4 bytes (u32, fraction of the range -90 to 90)
+        //     maxlat:  4 bytes (u32, fraction of the range -90 to 90)
+        //
+        // Local bounding boxes, version 15, 10 bytes each (see above)
         // 
         // Quick lookup table, 26*36*4 = 3744 bytes:
         // 
         //     list of 26*36 offset values
         //         offset: 4 bytes (u32, offset in to postcode data)
         //     last_offset: 4 bytes (u32, conveniently placed at the end of the lookup table)
+        //
+        // Quick lookup table, version 9, 26*36*8 = 7488 bytes:
+        //
+        //     list of 26*36 ranges
+        //         start: 4 bytes (u32, offset from the start of the file)
+        //         end:   4 bytes (u32, offset from the start of the file)
         //     
         // Postcode data, variable length (3 to 8 bytes per postcode):
         // 
@@ -205,8 +725,21 @@ async function NearMyPostcode(datafile_url, quiet=false){
         //                         (all other values) => reserved
         //         postcode: 0 or 3 bytes (custom encoding, present only if not postcode_is_delta)
         //         longlat:  2 or 4 bytes (2 x i8 if latlong_is_delta, or 2 x u16 otherwise)
+        //
+        //     version 6 onwards, the third bit of the format byte is a terminated flag, which leaves
+        //     5 bits of extra_data (so the outward code special mode is 10000), and terminated
+        //     postcodes have one more byte after longlat:
+        //         terminated: 1 byte (u8, year of termination minus 1900)
+        //
+        //     version 12 onwards, a format byte of 0x41 (latlong_is_delta with special mode 00001) starts
+        //     a run of full postcodes at exactly the same location as the entry before:
+        //         count:  1 byte (u8, number of postcodes in the run minus 1)
+        //         deltas: count+1 bytes (u8 each, number to add to the previous postcode minus 1)
+        //
+        //     version 14 onwards, if the file has an entropy code, each block is coded on its own and has
+        //     to be decoded first (see decode_block)
 
-        const pack = nmp.deltapack;
+        let pack = nmp.deltapack;
 
         // Calculate the encoded value of this postcode
         let cpostcode = nmp.format_postcode(postcode);
@@ -221,88 +754,517 @@ async function NearMyPostcode(datafile_url, quiet=false){
             (c_code >> 16) & 0xff,
         ];
 
-        // Get the extents of the postcode bounding box
-        const extents = new Float64Array(pack.slice(0,32));
-        const [minlong,maxlong,minlat,maxlat] = extents;
+        const lut_start = nmp.lut_start;
 
-        // Use the two character prefix to find the offsets in the offset lookup table
+        // Use the two character prefix to find the offsets in the offset lookup table. This is the
+        // area for two letter areas (SW), but the area and a digit for one letter areas (B1, which
+        // also has B10 to B19), and a letter and a digit for Eircodes (D6 for D6W)
         const c1 = cpostcode.charCodeAt(0);
         const c2 = cpostcode.charCodeAt(1);
         const ord = (x)=>x.charCodeAt(0);
         const c2_i = (c2 < ord('A')? (c2 - ord('0')) : (10 + c2 - ord('A')));
         const lut_index = ((c1 - ord('A'))*36)+c2_i;
-        const lpos = (8*4) + (lut_index * 4);
-        const range = new Uint32Array(pack.slice(lpos,lpos+8));
-        const [startpos, endpos] = range;
-
-        // Scan the rest of the file from startpos to endpos looking for the postcode
-        // (startpos is relative to the start of the postcode data, so calculate that offset first)
-        const datastart = (8*4) + (4*26*36) + 4;
-        var pos = startpos + datastart
+
+        // Get the extents of the postcode bounding box, or the block's own bounding box if it has one,
+        // or the box of the postcode's coordinate group if there are groups
+        const outward = cpostcode.slice(0, nmp.scheme == 0 ? 4 : 3).padEnd(4);
+        const group_box = nmp.groups[nmp.group_members.get(outward) ?? 0];
+        const [minlong,maxlong,minlat,maxlat] = group_box ?? nmp.local_boxes.get(lut_index) ?? nmp.extents;
+        var pos, endpos;
+        if (version >= 9){
+            // Absolute ranges, the header has already been removed from the front of the pack
+            const lpos = lut_start + (lut_index * 8);
+            const [start, end] = new Uint32Array(pack.slice(lpos,lpos+8));
+            pos = start - nmp.header_len;
+            endpos = end - nmp.header_len;
+        }
+        else{
+            // Offsets are relative to the start of the postcode data, so calculate that offset first
+            const lpos = lut_start + (lut_index * 4);
+            const [startpos, end] = new Uint32Array(pack.slice(lpos,lpos+8));
+            const datastart = lut_start + (4*26*36) + 4;
+            pos = startpos + datastart;
+            endpos = end + datastart;
+        }
+        if (nmp.entropy_code !== undefined && endpos > pos){
+            // Version 14 onwards, each block can be entropy coded on its own
+            pack = nmp.decode_block(new Uint8Array(pack, pos, endpos - pos));
+            pos = 0;
+            endpos = pack.byteLength;
+        }
+
+        // Scan the rest of the file from pos to endpos looking for the postcode
         var last_code = 0;
         var last_lat = 0;
         var last_long = 0;
         var is_outward_only = false;
-        while (pos < endpos + datastart){
+        const has_flags = version >= 6;
+        const extra_mask = has_flags? 0x1f : 0x3f;
+        const outward_flag = has_flags? 0x10 : 0x20;
+        const has_runs = version >= 12;
+        const has_varints = version >= 13;
+        const max_quantized = (2 ** nmp.resolution) - 1;
+        const coord_len = Math.ceil(nmp.resolution / 8);
+        // For approximate lookups, the first postcode in the same sector. Each sector is a range of codes:
+        // the last two letters of a UK postcode, or the last three chars of an Eircode
+        const sector_size = (nmp.scheme == 1)? 25*25*25 : 26*26;
+        const same_sector = (code) => approximate && !lookup_outward_only && Math.floor(code / sector_size) == Math.floor(c_code / sector_size);
+        let sector_location = null;
+        while (pos < endpos){
             is_outward_only = false;
             // Get the format of this postcode entry (each field delta encoded or not)
             const format = new Uint8Array(pack.slice(pos,pos+1))[0];
             pos += 1;
+            if (has_runs && format == 0x41){
+                // Version 12 onwards, a run of postcodes at the same location as the previous entry,
+                // stored as a count (minus one) and then a postcode delta (minus one) for each
+                const count = new Uint8Array(pack.slice(pos,pos+1))[0] + 1;
+                const deltas = new Uint8Array(pack.slice(pos+1,pos+1+count));
+                pos += 1 + count;
+                for (const delta of deltas){
+                    last_code = last_code + delta + 1;
+                    if (!lookup_outward_only && last_code == c_code){
+                        const lat2  = minlat +  ((maxlat -minlat )*(last_lat/max_quantized));
+                        const long2 = minlong + ((maxlong-minlong)*(last_long/max_quantized));
+                        return [cpostcode,nmp.outliers.get(cpostcode.padEnd(7)) ?? [long2,lat2]];
+                    }
+                    if (sector_location === null && same_sector(last_code)){
+                        sector_location = [minlong + ((maxlong-minlong)*(last_long/max_quantized)), minlat + ((maxlat-minlat)*(last_lat/max_quantized))];
+                    }
+                }
+                continue;
+            }
             const pc_is_delta = (format & 0x80) > 0;
             const ll_is_delta = (format & 0x40) > 0;
-            // Calculate the postcode and lat/long by addition of the delta value or from absolute values
-            // as specified in the format byte
+            const is_terminated = has_flags && ((format & 0x20) > 0);
             let this_code;
-            if (pc_is_delta){
-                // Postcode delta encoding is part of the format byte
-                const delta = format & 0x3f;
-                this_code = last_code + delta + 1;
+            let long;
+            let lat;
+            if (has_varints && (format & 0xdf) == 0x42){
+                // Version 13 onwards, the postcode delta (minus one) and the lat/long deltas are LEB128
+                // varints, with the lat/long deltas zigzag encoded (0, -1, 1, -2, 2, ... as 0, 1, 2, 3, 4, ...)
+                const varint = () => {
+                    let x = 0;
+                    let scale = 1;
+                    let b;
+                    do {
+                        b = new Uint8Array(pack.slice(pos,pos+1))[0];
+                        pos += 1;
+                        x += (b & 0x7f) * scale;
+                        scale *= 128;
+                    } while (b & 0x80);
+                    return x;
+                };
+                const unzigzag = (z) => (z % 2 == 1)? -(z+1)/2 : z/2;
+                this_code = last_code + varint() + 1;
+                lat = last_lat + unzigzag(varint());
+                long = last_long + unzigzag(varint());
             }
             else{
-                // Absolute postcode is three bytes long
-                const special = format & 0x3f;
-                if (special == 0x20) {
-                    is_outward_only = true;
-                    const [nc_a, nc_b, nc_c] = new Uint8Array(pack.slice(pos,pos+3));
-                    pos += 3;
-                    this_code = (nc_c << 16) + (nc_b << 8) + nc_a;
+                // Calculate the postcode and lat/long by addition of the delta value or from absolute values
+                // as specified in the format byte
+                if (pc_is_delta){
+                    // Postcode delta encoding is part of the format byte
+                    const delta = format & extra_mask;
+                    this_code = last_code + delta + 1;
                 }
                 else{
-                    const [nc_a, nc_b, nc_c] = new Uint8Array(pack.slice(pos,pos+3));
-                    pos += 3;
-                    this_code = (nc_c << 16) + (nc_b << 8) + nc_a;
+                    // Absolute postcode is three bytes long
+                    const special = format & extra_mask;
+                    if (special == outward_flag) {
+                        is_outward_only = true;
+                        const [nc_a, nc_b, nc_c] = new Uint8Array(pack.slice(pos,pos+3));
+                        pos += 3;
+                        this_code = (nc_c << 16) + (nc_b << 8) + nc_a;
+                    }
+                    else{
+                        const [nc_a, nc_b, nc_c] = new Uint8Array(pack.slice(pos,pos+3));
+                        pos += 3;
+                        this_code = (nc_c << 16) + (nc_b << 8) + nc_a;
+                    }
+                }
+                if (ll_is_delta){
+                    // lat/long is delta encoded as a pair of signed 8 bit numbers
+                    const [dlat, dlong] = new Int8Array(pack.slice(pos,pos+2));
+                    pos += 2;
+                    long = last_long + dlong;
+                    lat = last_lat + dlat;
+                }
+                else{
+                    // Absolute lat/long is a pair of unsigned little endian numbers, 16 bits each, or
+                    // in version 16 onwards, as many whole bytes as the resolution needs
+                    const coord = () => {
+                        const bytes = new Uint8Array(pack.slice(pos,pos+coord_len));
+                        pos += coord_len;
+                        return bytes.reduceRight((x, b) => (x * 256) + b, 0);
+                    };
+                    lat = coord();
+                    long = coord();
                 }
             }
-            let long;
-            let lat;
-            if (ll_is_delta){
-                // lat/long is delta encoded as a pair of signed 8 bit numbers
-                const [dlat, dlong] = new Int8Array(pack.slice(pos,pos+2));
-                pos += 2;
-                long = last_long + dlong;
-                lat = last_lat + dlat;
-            }
-            else{
-                // Absolute lat/long is a pair of 16 bit unsigned numbers
-                [lat, long] = new Uint16Array(pack.slice(pos,pos+4));
-                pos += 4;
+            let terminated_year = null;
+            if (is_terminated){
+                terminated_year = 1900 + new Uint8Array(pack.slice(pos,pos+1))[0];
+                pos += 1;
             }
             // Now ready to check if this code is a match
             if (is_outward_only == lookup_outward_only){
                 if (this_code == c_code){
                     // Calculate the real coordinates (the stored value is the fraction of the width or height of the bounding box)
-                    const lat2  = minlat +  ((maxlat -minlat )*(lat/65535.0));
-                    const long2 = minlong + ((maxlong-minlong)*(long/65535.0));
-                    return [cpostcode,[long2,lat2]];
+                    const lat2  = minlat +  ((maxlat -minlat )*(lat/max_quantized));
+                    const long2 = minlong + ((maxlong-minlong)*(long/max_quantized));
+                    if (terminated_year !== null){
+                        // Terminated postcodes (only in version 6 files) also give the year of termination
+                        return [cpostcode,nmp.outliers.get(cpostcode.padEnd(7)) ?? [long2,lat2],terminated_year];
+                    }
+                    return [cpostcode,nmp.outliers.get(cpostcode.padEnd(7)) ?? [long2,lat2]];
                 }
             }
+            if (sector_location === null && !is_outward_only && !is_terminated && same_sector(this_code)){
+                sector_location = [minlong + ((maxlong-minlong)*(long/max_quantized)), minlat + ((maxlat-minlat)*(lat/max_quantized))];
+            }
             last_code = this_code;
             last_lat = lat;
             last_long = long;
         }
+        if (approximate && !lookup_outward_only){
+            // A postcode that isn't in the file (such as a layer 0 file) is at the location of
+            // another postcode in its sector, or else of its outward code
+            if (sector_location !== null){
+                return [cpostcode,sector_location];
+            }
+            return [cpostcode,nmp.lookup_postcode(cpostcode.slice(0, nmp.scheme == 1 ? 3 : 4))[1]];
+        }
+        throw new Error(nmp.E_NOTFOUND);
+    });
+
+    nmp.COUNTRIES = {
+        E: "England",
+        W: "Wales",
+        S: "Scotland",
+        N: "Northern Ireland",
+        L: "Channel Islands",
+        M: "Isle of Man",
+    };
+
+    nmp.lookup_country = ((postcode)=>{
+        // Countries section (version 7 onwards, optional):
+        //
+        //     list of runs, sorted by postcode:
+        //         postcode: 7 bytes (first entry in the run, outward codes padded to 7 chars)
+        //         country:  1 byte (first letter of the ONS country code, or 0 if not known)
+        //
+        // Each run covers every entry up to the start of the next run
+        const [cpostcode] = nmp.lookup_postcode(postcode);
+        const key = cpostcode.padEnd(7, ' ');
+        const runs = nmp.countries;
+        // Find the last run that starts at or before this postcode
+        let lo = 0;
+        let hi = runs.length / 8;
+        while (lo < hi){
+            const mid = (lo + hi) >> 1;
+            const start = String.fromCharCode(...runs.subarray(mid*8, (mid*8)+7));
+            if (start <= key){
+                lo = mid + 1;
+            }
+            else{
+                hi = mid;
+            }
+        }
+        if (lo == 0){
+            return null;
+        }
+        return nmp.COUNTRIES[String.fromCharCode(runs[((lo-1)*8)+7])] ?? null;
+    });
+
+    // Attributes section (version 19 onwards, optional):
+    //
+    //     count: 1 byte (u8, number of attributes)
+    //     each attribute:
+    //         name_len:   1 byte (u8)
+    //         name:       name_len bytes (ASCII, e.g. laua)
+    //         values:     4 bytes (u32)
+    //         dictionary: values of these, sorted
+    //             len:    1 byte (u8)
+    //             value:  len bytes (UTF-8)
+    //         runs:       4 bytes (u32)
+    //         list of runs, sorted by postcode:
+    //             postcode: 7 bytes (first entry in the run, outward codes padded to 7 chars)
+    //             code:     1, 2 or 4 bytes (u8 if there are fewer than 256 values, u16 if fewer than 65536,
+    //                       otherwise u32). 0 for no value, otherwise the position in the dictionary from 1
+    //
+    // Each run covers every entry up to the start of the next run
+    const attribute_columns = (()=>{
+        const a = nmp.attributes;
+        if (a.length == 0){
+            return [];
+        }
+        const view = new DataView(a.buffer, a.byteOffset, a.byteLength);
+        const text = new TextDecoder();
+        const columns = [];
+        let pos = 1;
+        for (let i = 0; i < a[0]; i++){
+            const name = String.fromCharCode(...a.subarray(pos+1, pos+1+a[pos]));
+            pos += 1 + a[pos];
+            const n_values = view.getUint32(pos, true);
+            pos += 4;
+            const values = [];
+            for (let v = 0; v < n_values; v++){
+                values.push(text.decode(a.subarray(pos+1, pos+1+a[pos])));
+                pos += 1 + a[pos];
+            }
+            const code_len = (n_values < 256)? 1 : (n_values < 65536)? 2 : 4;
+            const n_runs = view.getUint32(pos, true);
+            columns.push({name, values, code_len, runs: a.subarray(pos+4, pos+4+(n_runs*(7+code_len)))});
+            pos += 4 + (n_runs*(7+code_len));
+        }
+        return columns;
+    })();
+
+    nmp.attribute_names = (()=>attribute_columns.map((c)=>c.name));
+
+    nmp.lookup_attribute = ((postcode, name)=>{
+        const column = attribute_columns.find((c)=>c.name.toLowerCase() == name.toLowerCase());
+        if (column === undefined){
+            throw new Error(`Unknown attribute ${name}`);
+        }
+        const [cpostcode] = nmp.lookup_postcode(postcode);
+        const key = cpostcode.padEnd(7, ' ');
+        const run_len = 7 + column.code_len;
+        // Find the last run that starts at or before this postcode
+        let lo = 0;
+        let hi = column.runs.length / run_len;
+        while (lo < hi){
+            const mid = (lo + hi) >> 1;
+            const start = String.fromCharCode(...column.runs.subarray(mid*run_len, (mid*run_len)+7));
+            if (start <= key){
+                lo = mid + 1;
+            }
+            else{
+                hi = mid;
+            }
+        }
+        if (lo == 0){
+            return null;
+        }
+        let code = 0;
+        for (let b = column.code_len - 1; b >= 0; b--){
+            code = (code * 256) + column.runs[((lo-1)*run_len)+7+b];
+        }
+        return (code == 0)? null : column.values[code-1];
+    });
+
+    nmp.lookup_extent = ((postcode)=>{
+        // Prefix extents section (version 20 onwards, optional):
+        //
+        //     count: 4 bytes (u32)
+        //     boxes: count of these, sorted by outward code
+        //         outward: 4 bytes (as in a canonical postcode, padded to 4 chars)
+        //         minlong, minlat, maxlong, maxlat: 2 bytes each (u16, quantized as the centroids are,
+        //                                           rounded outwards)
+        const cpostcode = nmp.format_postcode(postcode);
+        const outward = (cpostcode.length < 7)? cpostcode : (nmp.scheme == 1)? cpostcode.slice(0,3) : cpostcode.slice(0,4);
+        const key = outward.trimEnd().padEnd(4, ' ');
+        const e = nmp.prefix_extents;
+        if (e.length == 0){
+            return null;
+        }
+        const view = new DataView(e.buffer, e.byteOffset, e.byteLength);
+        let lo = 0;
+        let hi = view.getUint32(0, true);
+        while (lo < hi){
+            const mid = (lo + hi) >> 1;
+            const pos = 4 + (mid*12);
+            const name = String.fromCharCode(...e.subarray(pos, pos+4));
+            if (name < key){
+                lo = mid + 1;
+            }
+            else if (name > key){
+                hi = mid;
+            }
+            else{
+                const [minlong,maxlong,minlat,maxlat] = nmp.extents;
+                const x = (v) => minlong + ((maxlong-minlong)*(v/65535.0));
+                const y = (v) => minlat + ((maxlat-minlat)*(v/65535.0));
+                return [
+                    [x(view.getUint16(pos+4, true)), y(view.getUint16(pos+6, true))],
+                    [x(view.getUint16(pos+8, true)), y(view.getUint16(pos+10, true))],
+                ];
+            }
+        }
+        return null;
+    });
+
+    // Sector bitmap section (version 21 onwards, optional):
+    //
+    //     count: 4 bytes (u32)
+    //     outward codes: count of these, sorted
+    //         outward: 4 bytes (as in a canonical postcode, padded to 4 chars)
+    //         sectors: 4 bytes (u32, bit n is set if the sector ending with SECTOR_CHARS[n] exists)
+    const SECTOR_CHARS = "0123456789ACDEFHKNPRTVWXY";
+
+    // Outward codes and their sector bitmaps, read the first time they are needed
+    let sector_list = null;
+    const sector_entries = (()=>{
+        if (sector_list === null){
+            const b = nmp.sector_bitmap;
+            const view = new DataView(b.buffer, b.byteOffset, b.byteLength);
+            sector_list = [];
+            for (let i = 0; b.length > 0 && i < view.getUint32(0, true); i++){
+                const pos = 4 + (i*8);
+                sector_list.push([String.fromCharCode(...b.subarray(pos, pos+4)).trimEnd(), view.getUint32(pos+4, true)]);
+            }
+        }
+        return sector_list;
+    });
+
+    nmp.prefix_exists = ((text)=>{
+        if (nmp.sector_bitmap.length == 0){
+            return null;
+        }
+        text = text.trim();
+        const space = text.search(/\s/);
+        const outward = ((space < 0)? text : text.slice(0, space)).toUpperCase();
+        const sector = (space < 0)? null : text.slice(space).trimStart().toUpperCase();
+        const entry = sector_entries().find(([name, _]) => name == outward);
+        if (entry === undefined){
+            return false;
+        }
+        if (sector === null){
+            return true;
+        }
+        const bit = SECTOR_CHARS.indexOf(sector);
+        return sector.length == 1 && bit >= 0 && ((entry[1] >>> bit) & 1) == 1;
+    });
+
+    nmp.complete = ((text)=>{
+        // A space at the end still means that the outward code has been typed
+        text = text.trimStart().toUpperCase();
+        const space = text.search(/\s/);
+        const outward_only = (space < 0)? null : text.slice(0, space);
+        const typed = text.replace(/\s/g, '');
+        const found = [];
+        for (const [outward, sectors] of sector_entries()){
+            if (outward_only !== null && outward_only != outward){
+                continue;
+            }
+            if (outward_only === null && outward.startsWith(typed)){
+                found.push(outward);
+            }
+            // Sectors are only listed once the whole outward code has been typed
+            if (!typed.startsWith(outward)){
+                continue;
+            }
+            const rest = typed.slice(outward.length);
+            for (let bit = 0; bit < SECTOR_CHARS.length; bit++){
+                const c = SECTOR_CHARS[bit];
+                if (((sectors >>> bit) & 1) == 1 && (rest == '' || rest == c)){
+                    found.push(`${outward} ${c}`);
+                }
+            }
+        }
+        return found;
+    });
+
+    // Centroids section (version 8 onwards, optional):
+    //
+    //     tables: 3 * 8 bytes, for areas, districts and sectors in that order
+    //         offset: 4 bytes (u32, from the start of the centroids section)
+    //         count:  4 bytes (u32, number of entries in the table)
+    //     each table: count entries, sorted by name
+    //         name:  2, 4 or 5 bytes (area padded to 2 chars, district or sector as in a canonical postcode)
+    //         lat:   2 bytes (u16, quantized as in the postcode data)
+    //         long:  2 bytes (u16, quantized as in the postcode data)
+    //         count: 4 bytes (u32, number of postcodes)
+    nmp.CENTROID_LEVELS = ["area", "district", "sector"];
+
+    const centroid_table = ((level)=>{
+        const i = nmp.CENTROID_LEVELS.indexOf(level);
+        if (i < 0){
+            throw new Error(`Unknown centroid level ${level}`);
+        }
+        const key_len = [2, 4, 5][i];
+        if (nmp.centroids.length == 0){
+            return {key_len, count: 0, entry: null};
+        }
+        const view = new DataView(nmp.centroids.buffer, nmp.centroids.byteOffset, nmp.centroids.byteLength);
+        const offset = view.getUint32(i*8, true);
+        const count = view.getUint32((i*8)+4, true);
+        const entry = ((n)=>{
+            const pos = offset + (n*(key_len+8));
+            const key = String.fromCharCode(...nmp.centroids.subarray(pos, pos+key_len));
+            const lat = view.getUint16(pos+key_len, true);
+            const long = view.getUint16(pos+key_len+2, true);
+            const members = view.getUint32(pos+key_len+4, true);
+            const [minlong,maxlong,minlat,maxlat] = nmp.extents;
+            const lat2  = minlat +  ((maxlat -minlat )*(lat/65535.0));
+            const long2 = minlong + ((maxlong-minlong)*(long/65535.0));
+            const name = (i == 2)? `${key.slice(0,4).trimEnd()} ${key[4]}` : key.trimEnd();
+            return {key, name, coords: [long2, lat2], count: members};
+        });
+        return {key_len, count, entry};
+    });
+
+    const centroid_key = ((level, name)=>{
+        const n = name.replace(/\s/g, '').toUpperCase();
+        if (level == "area"){
+            if (!/^[A-Z]{1,2}$/.test(n)){
+                throw new Error(nmp.E_FORMAT);
+            }
+            return n.padEnd(2, ' ');
+        }
+        if (level == "district"){
+            const outward = nmp.format_postcode(n);
+            if (outward.length != 4){
+                throw new Error(nmp.E_FORMAT);
+            }
+            return outward;
+        }
+        const digit = n.slice(-1);
+        if (!/^[0-9]$/.test(digit)){
+            throw new Error(nmp.E_FORMAT);
+        }
+        const outward = nmp.format_postcode(n.slice(0,-1));
+        if (outward.length != 4){
+            throw new Error(nmp.E_FORMAT);
+        }
+        return outward + digit;
+    });
+
+    nmp.lookup_centroid = ((level, name)=>{
+        const key = centroid_key(level, name);
+        const table = centroid_table(level);
+        let lo = 0;
+        let hi = table.count;
+        while (lo < hi){
+            const mid = (lo + hi) >> 1;
+            const e = table.entry(mid);
+            if (e.key == key){
+                return [e.name, e.coords, e.count];
+            }
+            if (e.key < key){
+                lo = mid + 1;
+            }
+            else{
+                hi = mid;
+            }
+        }
         throw new Error(nmp.E_NOTFOUND);
     });
 
+    nmp.list_centroids = ((level, prefix="")=>{
+        const table = centroid_table(level);
+        const p = prefix.toUpperCase();
+        const out = [];
+        for (let n = 0; n < table.count; n++){
+            const e = table.entry(n);
+            if (e.name.startsWith(p)){
+                out.push([e.name, e.coords, e.count]);
+            }
+        }
+        return out;
+    });
+
     nmp.distance_between = ((point_a,point_b)=>{
         const toRad = (x)=> x * Math.PI / 180;
 
@@ -333,5 +1295,29 @@ async function NearMyPostcode(datafile_url, quiet=false){
     return nmp;
 }
 
+// Load a layered pack (see --layered in the packer) from the URL of its manifest: the small layer 0
+// file first, then the full file in the background. Resolves to an instance for layer 0, which can only
+// look up postcodes approximately (with lookup_postcode(postcode, true)), and calls on_full with an
+// instance for the full file once it has loaded, or on_error if it can't be loaded.
+async function NearMyPostcodeLayered(manifest_url, on_full, on_error=(err)=>console.error(err), quiet=false){
+    let manifest;
+    try{
+        const response = await fetch(manifest_url);
+        if (!response.ok){
+            throw new Error(`${response.status}`);
+        }
+        manifest = await response.json();
+    }
+    catch (err){
+        throw new Error(`Failed to fetch layered postcode data manifest (${manifest_url}): ${err.message}`);
+    }
+    // The files are named relative to the manifest
+    const base = new URL(manifest_url, globalThis.location?.href);
+    const layer_url = (layer) => new URL(layer.file, base).href;
+    const layer0 = await NearMyPostcode(layer_url(manifest.layers[0]), quiet);
+    NearMyPostcode(layer_url(manifest.layers[manifest.layers.length - 1]), quiet).then(on_full, on_error);
+    return layer0;
+}
+
 
 
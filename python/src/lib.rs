@@ -0,0 +1,166 @@
+/*
+
+Python module for reading pack files, built on the Rust pack file reader.
+
+Like the WebAssembly build and the C interface, this is the same `Reader` that the packer uses,
+so the file format is only decoded in one place. `./build_python` builds a wheel in to
+target/wheels with maturin, or `maturin develop -m python/Cargo.toml` installs it in to the
+current virtualenv.
+
+    import nearmypostcode
+    import pandas as pd
+    reader = nearmypostcode.Reader('postcodes.pack')
+    reader.lookup('sw1a 2aa').lat
+    df = pd.DataFrame((p.postcode, p.lat, p.long) for p in reader)
+
+*/
+use std::sync::Arc;
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyOSError, PyValueError};
+use nearmypostcode_packer::{PostcodeError, PostcodeInfo, Point};
+
+fn py_error(e: PostcodeError) -> PyErr{
+    match e{
+        PostcodeError::IOError(e) => PyOSError::new_err(e.to_string()),
+        e => PyValueError::new_err(e.to_string()),
+    }
+}
+
+/// A postcode and its location
+#[pyclass(frozen, get_all, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Postcode{
+    /// Canonical form, 7 chars with the inward code aligned right, or the 4 char outward code
+    pub postcode: String,
+    pub lat: f64,
+    pub long: f64,
+    /// ONS code of the country the postcode is in, if the pack file has countries
+    pub country: Option<String>,
+    /// Year the postcode was terminated, if the pack file has terminated postcodes
+    pub terminated: Option<u16>,
+}
+
+impl Postcode{
+    fn new(info: PostcodeInfo) -> Self{
+        let postcode = if info.is_partial { info.postcode[0..4].to_string() } else { info.postcode };
+        Self{
+            postcode,
+            lat: info.location.y,
+            long: info.location.x,
+            country: info.country.map(|c| c.code().to_string()),
+            terminated: info.terminated,
+        }
+    }
+
+    fn at(postcode: String, location: Point, country: Option<String>) -> Self{
+        Self{postcode, lat: location.y, long: location.x, country, terminated: None}
+    }
+}
+
+#[pymethods]
+impl Postcode{
+    fn __repr__(&self) -> String{
+        format!("Postcode('{}', lat={}, long={})", self.postcode, self.lat, self.long)
+    }
+}
+
+/// A pack file of any version
+#[pyclass(frozen)]
+pub struct Reader{
+    reader: Arc<nearmypostcode_packer::Reader>,
+}
+
+#[pymethods]
+impl Reader{
+    /// Open a pack file, which is memory mapped
+    #[new]
+    fn new(path: &str) -> PyResult<Self>{
+        Ok(Self{reader: Arc::new(nearmypostcode_packer::Reader::map(path).map_err(py_error)?)})
+    }
+
+    /// Load a pack file from its contents
+    #[staticmethod]
+    fn from_bytes(data: Vec<u8>) -> PyResult<Self>{
+        Ok(Self{reader: Arc::new(nearmypostcode_packer::Reader::from_bytes(data).map_err(py_error)?)})
+    }
+
+    /// File format version of the pack file
+    #[getter]
+    fn version(&self) -> u32{
+        self.reader.version()
+    }
+
+    /// Date of the most recently introduced postcode, as a unix timestamp in seconds
+    #[getter]
+    fn last_update(&self) -> u64{
+        self.reader.last_update()
+    }
+
+    /// Look up a postcode, or an outward code by itself, in any case and spacing. Returns None if
+    /// it isn't in the file, and raises ValueError if it isn't a postcode at all.
+    fn lookup(&self, postcode: &str) -> PyResult<Option<Postcode>>{
+        let canonical = self.reader.scheme().codec().format(postcode).map_err(py_error)?;
+        Ok(self.reader.lookup(&canonical).map(|location| {
+            let country = self.reader.country(&canonical).map(|c| c.code().to_string());
+            Postcode::at(canonical.trim_end().to_string(), location, country)
+        }))
+    }
+
+    /// The postcode closest to a location, faster if the pack file has a spatial index
+    fn nearest(&self, lat: f64, long: f64) -> Option<Postcode>{
+        self.reader.nearest(lat, long).map(Postcode::new)
+    }
+
+    /// All of the postcodes within a distance (in metres) of a location, nearest first
+    fn within_radius(&self, lat: f64, long: f64, metres: f64) -> Vec<Postcode>{
+        self.reader.within_radius(lat, long, metres).into_iter().map(Postcode::new).collect()
+    }
+
+    /// Every postcode in the file, in order, without the outward codes
+    fn __iter__(&self) -> Entries{
+        let prefixes = self.reader.block_ranges().into_iter().map(|(prefix, _, _)| prefix).collect();
+        Entries{reader: self.reader.clone(), prefixes, block: 0, entries: Vec::new().into_iter()}
+    }
+}
+
+/// Iterator over the postcodes in a pack file, from `iter(reader)`. It decodes one prefix block
+/// at a time.
+#[pyclass]
+pub struct Entries{
+    reader: Arc<nearmypostcode_packer::Reader>,
+    prefixes: Vec<String>,
+    /// The next block to decode
+    block: usize,
+    /// What's left of the last block decoded
+    entries: std::vec::IntoIter<Postcode>,
+}
+
+#[pymethods]
+impl Entries{
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self>{
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<Postcode>{
+        loop{
+            if let Some(p) = self.entries.next(){
+                return Some(p);
+            }
+            let prefix = self.prefixes.get(self.block)?;
+            self.entries = self.reader.iter_prefix(prefix)
+                .filter(|p| !p.is_partial)
+                .map(Postcode::new)
+                .collect::<Vec<_>>()
+                .into_iter();
+            self.block += 1;
+        }
+    }
+}
+
+#[pymodule]
+fn nearmypostcode(m: &Bound<'_, PyModule>) -> PyResult<()>{
+    m.add_class::<Reader>()?;
+    m.add_class::<Postcode>()?;
+    m.add_class::<Entries>()?;
+    Ok(())
+}
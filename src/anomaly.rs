@@ -0,0 +1,131 @@
+/*
+
+Postcodes whose locations are far from the rest of their postcode area.
+
+The ONS database occasionally has a location with the latitude and longitude swapped, or a digit
+typed wrong, which puts a postcode in the sea or at the other end of the country, and stretches
+the bounding box of the whole file. Each postcode area (AB, SW and so on) covers a region that
+its postcodes are nearly all in, so a postcode outside it is suspect.
+
+The region of an area is the box between the quartiles of its postcodes' latitudes and
+longitudes, which the stray postcodes themselves hardly move, widened on each side by `FENCE`
+times its size (and at least `MIN_MARGIN`), so that postcodes at the edges of large or oddly
+shaped areas, such as the islands off the coast of Scotland and Cornwall, aren't flagged. These
+are Tukey's fences for "far out" values. A convex hull would fit more closely, but a single
+stray postcode is one of its corners. Areas with fewer than `MIN_POSTCODES` postcodes are too
+small for quartiles to mean anything, and aren't checked.
+
+*/
+use std::collections::HashMap;
+use crate::{PostcodeInfo, Point, Scheme, distance_m};
+
+/// The region is widened on each side by this many times its size
+pub const FENCE: f64 = 3.0;
+
+/// Least that the region is widened by on each side, in degrees
+pub const MIN_MARGIN: f64 = 0.05;
+
+/// Areas with fewer postcodes than this aren't checked
+pub const MIN_POSTCODES: usize = 20;
+
+/// A postcode outside the usual region of its area
+#[derive(Debug, Clone)]
+pub struct Anomaly{
+    pub postcode: String,
+    /// The postcode area, e.g. AB
+    pub area: String,
+    pub location: Point,
+    /// Lower left and upper right corners of the area's region
+    pub region: (Point, Point),
+    /// Distance from the region, in metres
+    pub distance: f64,
+}
+
+/// The postcode area of a canonical full code, the letters at the start of its outward code
+fn area(postcode: &str, scheme: Scheme) -> &str{
+    let outward = scheme.codec().prefix(postcode);
+    let letters = outward.bytes().take_while(|c| c.is_ascii_alphabetic()).count();
+    &outward[..letters]
+}
+
+/// The latitudes and longitudes of every postcode in each area
+#[derive(Debug)]
+pub(crate) struct AreaLocations{
+    areas: HashMap<String, (Vec<f64>, Vec<f64>)>,
+    scheme: Scheme,
+}
+
+impl AreaLocations{
+    pub fn new(scheme: Scheme) -> Self{
+        Self{areas: HashMap::new(), scheme}
+    }
+
+    pub fn add(&mut self, p: &PostcodeInfo){
+        if p.is_partial{
+            return;
+        }
+        let (lats, longs) = self.areas.entry(area(&p.postcode, self.scheme).to_string()).or_default();
+        lats.push(p.location.y);
+        longs.push(p.location.x);
+    }
+
+    /// The region of each area that has enough postcodes
+    pub fn regions(self) -> AreaRegions{
+        let percentiles = |mut values: Vec<f64>| {
+            values.sort_by(f64::total_cmp);
+            let at = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+            let (low, high) = (at(0.25), at(0.75));
+            let margin = ((high - low) * FENCE).max(MIN_MARGIN);
+            (low - margin, high + margin)
+        };
+        let regions = self.areas.into_iter()
+            .filter(|(_, (lats, _))| lats.len() >= MIN_POSTCODES)
+            .map(|(area, (lats, longs))| {
+                let (min_y, max_y) = percentiles(lats);
+                let (min_x, max_x) = percentiles(longs);
+                (area, (Point{x: min_x, y: min_y}, Point{x: max_x, y: max_y}))
+            })
+            .collect();
+        AreaRegions{regions, scheme: self.scheme}
+    }
+}
+
+/// The usual region of each postcode area
+#[derive(Debug)]
+pub(crate) struct AreaRegions{
+    regions: HashMap<String, (Point, Point)>,
+    scheme: Scheme,
+}
+
+impl AreaRegions{
+    /// The anomaly if a postcode is outside the region of its area
+    pub fn check(&self, p: &PostcodeInfo) -> Option<Anomaly>{
+        if p.is_partial{
+            return None;
+        }
+        let area = area(&p.postcode, self.scheme);
+        let &(min, max) = self.regions.get(area)?;
+        let l = p.location;
+        if (min.x..=max.x).contains(&l.x) && (min.y..=max.y).contains(&l.y){
+            return None;
+        }
+        let nearest = Point{x: l.x.clamp(min.x, max.x), y: l.y.clamp(min.y, max.y)};
+        Some(Anomaly{
+            postcode: p.postcode.clone(),
+            area: area.to_string(),
+            location: l,
+            region: (min, max),
+            distance: distance_m(&l, &nearest),
+        })
+    }
+}
+
+/// Every postcode in a list that is outside the region of its area, in the order of the list
+pub(crate) fn find_anomalies(postcodes: &[PostcodeInfo], scheme: Scheme) -> Vec<Anomaly>{
+    let mut locations = AreaLocations::new(scheme);
+    for p in postcodes{
+        locations.add(p);
+    }
+    let regions = locations.regions();
+    postcodes.iter().filter_map(|p| regions.check(p)).collect()
+}
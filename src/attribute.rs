@@ -0,0 +1,210 @@
+/*
+
+Attributes of each postcode, such as the local authority (laua), ward or LSOA it is in.
+
+Version 19 files can have a section of attributes, chosen when the file is packed, each taken
+from a column of the input file. Each attribute is stored as its own column: a dictionary of
+every value it has, and a list of runs that give the value of every entry as a small number.
+Like the countries (see country.rs), neighbouring postcodes nearly always have the same values,
+so each run starts at an entry (in sorted order) whose value is different from the entry before
+it, and covers every entry up to the start of the next run.
+
+Attributes section structure:
+
+    count:      1 byte (u8, number of attributes)
+    attributes: count of these, one after the other
+        name_len:   1 byte (u8)
+        name:       name_len bytes (ASCII, the name of the column it came from, e.g. laua)
+        values:     4 bytes (u32, number of values in the dictionary)
+        dictionary: values of these, sorted
+            len:    1 byte (u8)
+            value:  len bytes (UTF-8, e.g. E09000033)
+        runs:       4 bytes (u32, number of runs)
+        list of runs, sorted by postcode:
+            postcode: 7 bytes (canonical postcode of the first entry in the run, outward codes are padded to 7 chars)
+            code:     1 byte (u8) if there are fewer than 256 values, 2 bytes (u16) if fewer than
+                      65536, otherwise 4 bytes (u32). 0 if the entries have no value, otherwise
+                      the position of their value in the dictionary, counting from 1
+
+Outward codes have no values. Entries before the first run have no values either.
+
+*/
+#[cfg(feature="packer")]
+use std::collections::BTreeMap;
+#[cfg(feature="packer")]
+use crate::PostcodeError;
+
+/// Other names of some of the columns, which vary between the ONSPD and NSPL, and between
+/// releases of each. The first name of each is the one to ask for.
+#[cfg(feature="packer")]
+const ALIASES: [&[&str];3] = [
+    &["laua", "lad"],
+    &["ward", "osward"],
+    &["rgn", "gor"],
+];
+
+/// Names of the input file columns that an attribute can come from
+#[cfg(feature="packer")]
+pub(crate) fn column_names(name: &str) -> Vec<&str>{
+    ALIASES.iter()
+        .find(|names| names[0].eq_ignore_ascii_case(name))
+        .map(|names| names.to_vec())
+        .unwrap_or_else(|| vec![name])
+}
+
+/// Check that a list of attribute names can be stored
+#[cfg(feature="packer")]
+pub(crate) fn check_names(names: &[String]) -> Result<(), PostcodeError>{
+    if names.len() > 255{
+        return Err(PostcodeError::IncompatibleOptions("a pack file can have at most 255 attributes"));
+    }
+    if names.iter().any(|n| n.is_empty() || n.len() > 255 || !n.is_ascii()){
+        return Err(PostcodeError::IncompatibleOptions("attribute names must be 1 to 255 ASCII characters"));
+    }
+    if names.iter().enumerate().any(|(i, n)| names[..i].iter().any(|m| m.eq_ignore_ascii_case(n))){
+        return Err(PostcodeError::IncompatibleOptions("the same attribute can't be stored twice"));
+    }
+    Ok(())
+}
+
+/// Length of a code in the runs of an attribute with this many values
+fn code_len(values: usize) -> usize{
+    if values < 256 { 1 } else if values < 65536 { 2 } else { 4 }
+}
+
+/// Encode the attributes of a sorted list of entries as an attributes section. Each entry has
+/// the values of the attributes in the order of `names`, an empty or missing value is no value.
+#[cfg(feature="packer")]
+pub(crate) fn encode<'a, I: IntoIterator<Item=(&'a [u8], &'a [String])>>(names: &[String], entries: I) -> Result<Vec<u8>, PostcodeError>{
+    check_names(names)?;
+    let entries: Vec<(&[u8], &[String])> = entries.into_iter().collect();
+    let mut out = vec![names.len() as u8];
+    for (i, name) in names.iter().enumerate(){
+        let value = |values: &'a [String]| values.get(i).map(|v| v.as_str()).filter(|v| !v.is_empty());
+        let mut dictionary: BTreeMap<&str, u32> = entries.iter().filter_map(|(_, values)| Some((value(values)?, 0))).collect();
+        if dictionary.keys().any(|v| v.len() > 255){
+            return Err(PostcodeError::IncompatibleOptions("attribute values can be at most 255 bytes long"));
+        }
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(dictionary.len() as u32).to_le_bytes());
+        for (n, (v, code)) in dictionary.iter_mut().enumerate(){
+            *code = n as u32 + 1;
+            out.push(v.len() as u8);
+            out.extend_from_slice(v.as_bytes());
+        }
+        let len = code_len(dictionary.len());
+        let mut runs = Vec::new();
+        let mut last = 0;
+        for (postcode, values) in &entries{
+            let code = value(values).map(|v| dictionary[v]).unwrap_or(0);
+            if code != last{
+                runs.extend_from_slice(&postcode[0..7]);
+                runs.extend_from_slice(&code.to_le_bytes()[..len]);
+                last = code;
+            }
+        }
+        out.extend_from_slice(&((runs.len() / (7 + len)) as u32).to_le_bytes());
+        out.extend_from_slice(&runs);
+    }
+    Ok(out)
+}
+
+/// One attribute of a decoded attributes section
+#[derive(Debug, Clone)]
+struct Column{
+    name: String,
+    values: Vec<String>,
+    /// First postcode of each run, and its code
+    runs: Vec<([u8;7], u32)>,
+}
+
+/// A decoded attributes section
+#[derive(Debug, Clone)]
+pub(crate) struct Attributes{
+    columns: Vec<Column>,
+    /// The section as it is in the file, for patches
+    #[cfg(feature="packer")]
+    bytes: Vec<u8>,
+}
+
+impl Attributes{
+    pub fn from_bytes(data: &[u8]) -> Option<Self>{
+        let mut pos: usize = 0;
+        let mut take = |n: usize| -> Option<&[u8]>{
+            let bytes = data.get(pos..pos.checked_add(n)?)?;
+            pos += n;
+            Some(bytes)
+        };
+        let count = take(1)?[0];
+        let mut columns = Vec::with_capacity(count as usize);
+        for _ in 0..count{
+            let name_len = take(1)?[0] as usize;
+            let name = std::str::from_utf8(take(name_len)?).ok()?.to_string();
+            let n_values = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let values = (0..n_values)
+                .map(|_| {
+                    let len = take(1)?[0] as usize;
+                    Some(std::str::from_utf8(take(len)?).ok()?.to_string())
+                })
+                .collect::<Option<Vec<String>>>()?;
+            let len = code_len(n_values);
+            let n_runs = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let runs: Vec<([u8;7], u32)> = take(n_runs.checked_mul(7 + len)?)?
+                .chunks_exact(7 + len)
+                .map(|run| {
+                    let mut code = [0;4];
+                    code[..len].copy_from_slice(&run[7..]);
+                    (run[0..7].try_into().unwrap(), u32::from_le_bytes(code))
+                })
+                .collect();
+            if !runs.is_sorted_by(|a, b| a.0 < b.0) || runs.iter().any(|&(_, code)| code as usize > values.len()){
+                return None;
+            }
+            columns.push(Column{name, values, runs});
+        }
+        if pos != data.len(){
+            return None;
+        }
+        Some(Self{
+            columns,
+            #[cfg(feature="packer")]
+            bytes: data.to_vec(),
+        })
+    }
+
+    #[cfg(feature="packer")]
+    pub fn as_bytes(&self) -> &[u8]{
+        &self.bytes
+    }
+
+    /// Names of the attributes, in the order they are stored
+    pub fn names(&self) -> Vec<&str>{
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// Every value that an attribute has, sorted
+    pub fn values(&self, name: &str) -> Option<&[String]>{
+        self.column(name).map(|c| c.values.as_slice())
+    }
+
+    /// Value of an attribute for an entry, by its 7 char postcode
+    pub fn get(&self, name: &str, postcode: &[u8]) -> Option<&str>{
+        value_of(self.column(name)?, postcode)
+    }
+
+    /// Value of every attribute for an entry, in the order they are stored, empty if it has none
+    pub fn get_all(&self, postcode: &[u8]) -> Vec<String>{
+        self.columns.iter().map(|c| value_of(c, postcode).unwrap_or_default().to_string()).collect()
+    }
+
+    fn column(&self, name: &str) -> Option<&Column>{
+        self.columns.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+fn value_of<'a>(column: &'a Column, postcode: &[u8]) -> Option<&'a str>{
+    let i = column.runs.partition_point(|(p, _)| p.as_slice() <= postcode);
+    let code = column.runs.get(i.checked_sub(1)?)?.1 as usize;
+    Some(column.values.get(code.checked_sub(1)?)?.as_str())
+}
@@ -0,0 +1,223 @@
+/*
+
+Centroid tables, from format version 8 onwards.
+
+For apps that zoom from coarse to fine, the packer can store the mean location of the postcodes
+in each postcode area (e.g. "YO"), district (the outward code, e.g. "YO1") and sector (e.g.
+"YO1 7"), along with the number of postcodes in each. Terminated postcodes are not counted.
+
+Centroids section, variable length (before the countries section):
+
+    tables: 3 * 8 bytes, for areas, districts and sectors in that order
+        offset: 4 bytes (u32, from the start of the centroids section)
+        count:  4 bytes (u32, number of entries in the table)
+    each table: count entries, sorted by name
+        name:  2, 4 or 5 bytes (area padded to 2 chars e.g. "B ", district as in a canonical postcode e.g. "B1  ",
+                                sector as in a canonical postcode e.g. "B1  2")
+        lat:   2 bytes (u16, quantized as in the postcode data)
+        long:  2 bytes (u16, quantized as in the postcode data)
+        count: 4 bytes (u32, number of postcodes)
+
+*/
+#[cfg(feature="packer")]
+use std::collections::BTreeMap;
+
+use crate::{Point, format_postcode};
+#[cfg(feature="packer")]
+use crate::{PostcodeInfo, LLTotal, calc_ll};
+
+/// How much of a postcode a centroid covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CentroidLevel{
+    /// The letters at the start of the postcode, e.g. "YO"
+    Area,
+    /// The outward code, e.g. "YO1"
+    District,
+    /// The outward code and the first digit of the inward code, e.g. "YO1 7"
+    Sector,
+}
+
+impl CentroidLevel{
+    pub const ALL: [CentroidLevel; 3] = [CentroidLevel::Area, CentroidLevel::District, CentroidLevel::Sector];
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            CentroidLevel::Area => "area",
+            CentroidLevel::District => "district",
+            CentroidLevel::Sector => "sector",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|l| l.name() == name)
+    }
+
+    /// Length of the names in the table
+    fn key_len(&self) -> usize{
+        match self{
+            CentroidLevel::Area => 2,
+            CentroidLevel::District => 4,
+            CentroidLevel::Sector => 5,
+        }
+    }
+
+    /// The table name for a canonical 7 char postcode
+    #[cfg(feature="packer")]
+    fn key_of(&self, postcode: &str) -> String{
+        match self{
+            CentroidLevel::Area => postcode[0..2].replace(|c: char| c.is_ascii_digit(), " "),
+            _ => postcode[0..self.key_len()].to_string(),
+        }
+    }
+
+    /// The table name for an area, district or sector in any case and spacing
+    pub(crate) fn key(&self, name: &str) -> Option<String>{
+        let name: String = name.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_uppercase();
+        match self{
+            CentroidLevel::Area => {
+                if name.is_empty() || name.len() > 2 || !name.chars().all(|c| c.is_ascii_uppercase()){
+                    return None;
+                }
+                Some(format!("{name:2}"))
+            },
+            CentroidLevel::District => format_postcode(&name).ok().filter(|k| k.len() == 4),
+            CentroidLevel::Sector => {
+                let digit = name.chars().last().filter(|c| c.is_ascii_digit())?;
+                let outward = format_postcode(&name[..name.len()-1]).ok().filter(|k| k.len() == 4)?;
+                Some(format!("{outward}{digit}"))
+            },
+        }
+    }
+
+    /// How a table name is shown, e.g. "B1 2" for the sector "B1  2"
+    fn display(&self, key: &str) -> String{
+        match self{
+            CentroidLevel::Sector => format!("{} {}", key[0..4].trim_end(), &key[4..5]),
+            _ => key.trim_end().to_string(),
+        }
+    }
+}
+
+/// The mean location of the postcodes in an area, district or sector
+#[derive(Debug, Clone)]
+pub struct Centroid{
+    pub name: String,
+    pub location: Point,
+    /// Number of postcodes
+    pub count: u32,
+}
+
+/// Running totals of the postcode locations at each level, in name order
+#[cfg(feature="packer")]
+#[derive(Default)]
+pub(crate) struct CentroidTotals([BTreeMap<String, LLTotal>; 3]);
+
+#[cfg(feature="packer")]
+impl CentroidTotals{
+    pub fn add(&mut self, p: &PostcodeInfo){
+        if p.is_partial || p.terminated.is_some(){
+            return;
+        }
+        for (level, totals) in CentroidLevel::ALL.iter().zip(self.0.iter_mut()){
+            totals.entry(level.key_of(&p.postcode)).or_insert_with(LLTotal::new).add(&p.location, None);
+        }
+    }
+
+    /// Encode the centroids section, locations are quantized relative to the bounding box
+    pub fn to_bytes(&self, minll: Point, maxll: Point) -> Vec<u8>{
+        let mut header = Vec::new();
+        let mut tables = Vec::new();
+        for totals in &self.0{
+            header.extend_from_slice(&((3*8 + tables.len()) as u32).to_le_bytes());
+            header.extend_from_slice(&(totals.len() as u32).to_le_bytes());
+            for (k, t) in totals{
+                let (long, lat) = calc_ll(minll, maxll, t.average());
+                tables.extend_from_slice(k.as_bytes());
+                tables.extend_from_slice(&lat.to_le_bytes());
+                tables.extend_from_slice(&long.to_le_bytes());
+                tables.extend_from_slice(&t.n.to_le_bytes());
+            }
+        }
+        header.extend_from_slice(&tables);
+        header
+    }
+}
+
+/// A centroids section read from a pack file
+#[derive(Debug, Clone)]
+pub(crate) struct CentroidTables{
+    data: Vec<u8>,
+    /// Offset and number of entries of each table
+    tables: [(usize, usize); 3],
+}
+
+impl CentroidTables{
+    pub fn from_bytes(data: &[u8]) -> Option<Self>{
+        let mut tables = [(0, 0); 3];
+        for (i, level) in CentroidLevel::ALL.iter().enumerate(){
+            let offset = u32::from_le_bytes(data.get(i*8..i*8+4)?.try_into().ok()?) as usize;
+            let count = u32::from_le_bytes(data.get(i*8+4..i*8+8)?.try_into().ok()?) as usize;
+            let entry_len = level.key_len() + 8;
+            let table = data.get(offset..offset.checked_add(count.checked_mul(entry_len)?)?)?;
+            if !table.chunks_exact(entry_len).all(|e| e[0..level.key_len()].is_ascii()){
+                return None;
+            }
+            tables[i] = (offset, count);
+        }
+        Some(Self{data: data.to_vec(), tables})
+    }
+
+    /// The whole section, as it is in the file
+    #[cfg(feature="packer")]
+    pub fn as_bytes(&self) -> &[u8]{
+        &self.data
+    }
+
+    /// Number of entries in each table
+    pub fn counts(&self) -> [usize; 3]{
+        self.tables.map(|(_, count)| count)
+    }
+
+    fn table_index(level: CentroidLevel) -> usize{
+        CentroidLevel::ALL.iter().position(|l| *l == level).unwrap()
+    }
+
+    /// Name, quantized lat and long, and count of an entry in a table
+    fn entry(&self, level: CentroidLevel, i: usize) -> (&str, u16, u16, u32){
+        let (offset, _) = self.tables[Self::table_index(level)];
+        let key_len = level.key_len();
+        let e = &self.data[offset + i*(key_len+8)..offset + (i+1)*(key_len+8)];
+        (
+            std::str::from_utf8(&e[0..key_len]).unwrap(),
+            u16::from_le_bytes([e[key_len], e[key_len+1]]),
+            u16::from_le_bytes([e[key_len+2], e[key_len+3]]),
+            u32::from_le_bytes(e[key_len+4..key_len+8].try_into().unwrap()),
+        )
+    }
+
+    /// Every entry in a table, in order, with the name as it is shown
+    pub fn iter(&self, level: CentroidLevel) -> impl Iterator<Item=(String, u16, u16, u32)> + '_{
+        let (_, count) = self.tables[Self::table_index(level)];
+        (0..count).map(move |i| {
+            let (key, lat, long, n) = self.entry(level, i);
+            (level.display(key), lat, long, n)
+        })
+    }
+
+    /// Find an entry by its name in any case and spacing, returns the name as it is shown
+    pub fn get(&self, level: CentroidLevel, name: &str) -> Option<(String, u16, u16, u32)>{
+        let key = level.key(name)?;
+        let (_, count) = self.tables[Self::table_index(level)];
+        let (mut lo, mut hi) = (0, count);
+        while lo < hi{
+            let mid = (lo + hi) / 2;
+            let (k, lat, long, n) = self.entry(level, mid);
+            match k.cmp(key.as_str()){
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some((level.display(&key), lat, long, n)),
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,120 @@
+/*
+
+Compression of the postcode data section, from format version 4 onwards.
+
+The header, bounding box and quick lookup table are always stored uncompressed, so that
+readers can tell which codec was used. Offsets in the quick lookup table are relative to
+the decompressed postcode data.
+
+*/
+#[cfg(feature="compression")]
+use std::io::{Read, Write};
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression{
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+    Brotli,
+}
+
+impl Compression{
+    pub const ALL: [Compression;4] = [Compression::None, Compression::Gzip, Compression::Zstd, Compression::Brotli];
+
+    /// Value stored in the file to identify the codec
+    pub fn id(&self) -> u32{
+        use Compression::*;
+        match self{
+            None => 0,
+            Gzip => 1,
+            Zstd => 2,
+            Brotli => 3,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self>{
+        Self::ALL.into_iter().find(|c| c.id() == id)
+    }
+
+    pub fn name(&self) -> &'static str{
+        use Compression::*;
+        match self{
+            None => "none",
+            Gzip => "gzip",
+            Zstd => "zstd",
+            Brotli => "brotli",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    pub fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>{
+        use Compression::*;
+        match self{
+            None => Ok(data.to_vec()),
+            #[cfg(feature="compression")]
+            Gzip => {
+                let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                e.write_all(data)?;
+                e.finish()
+            },
+            #[cfg(feature="zstd")]
+            Zstd => zstd::encode_all(data, 19),
+            #[cfg(not(feature="zstd"))]
+            Zstd => Err(no_zstd()),
+            #[cfg(feature="compression")]
+            Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut e = brotli::CompressorWriter::new(&mut out, 4096, 11, 22);
+                    e.write_all(data)?;
+                }
+                Ok(out)
+            },
+            #[cfg(not(feature="compression"))]
+            Gzip | Brotli => Err(no_compression()),
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>{
+        use Compression::*;
+        let mut out = Vec::new();
+        match self{
+            None => out.extend_from_slice(data),
+            #[cfg(feature="compression")]
+            Gzip => { flate2::read::GzDecoder::new(data).read_to_end(&mut out)?; },
+            #[cfg(feature="zstd")]
+            Zstd => { out = zstd::decode_all(data)?; },
+            #[cfg(not(feature="zstd"))]
+            Zstd => return Err(no_zstd()),
+            #[cfg(feature="compression")]
+            Brotli => { brotli::Decompressor::new(data, 4096).read_to_end(&mut out)?; },
+            #[cfg(not(feature="compression"))]
+            Gzip | Brotli => return Err(no_compression()),
+        }
+        Ok(out)
+    }
+}
+
+/// zstd is a C library, so it can be left out with `default-features = false` (e.g. for WebAssembly)
+#[cfg(not(feature="zstd"))]
+fn no_zstd() -> std::io::Error{
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "this build does not support zstd")
+}
+
+/// Readers that only need uncompressed files can leave out gzip and Brotli with `default-features = false`
+#[cfg(not(feature="compression"))]
+fn no_compression() -> std::io::Error{
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "this build does not support gzip or Brotli")
+}
+
+impl Display for Compression{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
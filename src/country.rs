@@ -0,0 +1,136 @@
+/*
+
+The country that each postcode is in.
+
+Version 7 files can have a section that gives the country of every entry. Neighbouring postcodes
+are nearly always in the same country, so the section is a list of runs. Each run starts at an
+entry (in sorted order) whose country is different from the entry before it, and covers every
+entry up to the start of the next run. Entries before the first run have no known country.
+
+Countries section structure:
+
+    list of runs, to the end of the section:
+        postcode: 7 bytes (canonical postcode of the first entry in the run, outward codes are padded to 7 chars)
+        country:  1 byte (first letter of the ONS country code: E, W, S, N, L or M, or 0 if not known)
+
+*/
+
+/// A country of the UK, or one of the Crown Dependencies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Country{
+    England,
+    Wales,
+    Scotland,
+    NorthernIreland,
+    ChannelIslands,
+    IsleOfMan,
+}
+
+impl Country{
+    pub const ALL: [Country; 6] = [
+        Country::England,
+        Country::Wales,
+        Country::Scotland,
+        Country::NorthernIreland,
+        Country::ChannelIslands,
+        Country::IsleOfMan,
+    ];
+
+    /// From an ONS country code, such as E92000001. Only the first letter is checked.
+    pub fn from_code(code: &str) -> Option<Self>{
+        Self::from_byte(*code.trim().as_bytes().first()?)
+    }
+
+    /// The Crown Dependencies, from the area of a postcode. The countries of the UK can't be
+    /// told from the postcode alone.
+    pub fn from_postcode(postcode: &str) -> Option<Self>{
+        match postcode.get(0..2)?{
+            "JE" | "GY" => Some(Country::ChannelIslands),
+            "IM" => Some(Country::IsleOfMan),
+            _ => None,
+        }
+    }
+
+    /// The ONS country code
+    pub fn code(&self) -> &'static str{
+        match self{
+            Country::England => "E92000001",
+            Country::Wales => "W92000004",
+            Country::Scotland => "S92000003",
+            Country::NorthernIreland => "N92000002",
+            Country::ChannelIslands => "L93000001",
+            Country::IsleOfMan => "M83000003",
+        }
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            Country::England => "England",
+            Country::Wales => "Wales",
+            Country::Scotland => "Scotland",
+            Country::NorthernIreland => "Northern Ireland",
+            Country::ChannelIslands => "Channel Islands",
+            Country::IsleOfMan => "Isle of Man",
+        }
+    }
+
+    /// The byte stored in the countries section
+    pub(crate) fn byte(&self) -> u8{
+        self.code().as_bytes()[0]
+    }
+
+    pub(crate) fn from_byte(b: u8) -> Option<Self>{
+        Self::ALL.into_iter().find(|c| c.byte() == b.to_ascii_uppercase())
+    }
+}
+
+/// Encode the countries of a sorted list of entries as a countries section
+#[cfg(feature="packer")]
+pub(crate) fn encode_runs<'a, I: IntoIterator<Item=(&'a [u8], Option<Country>)>>(entries: I) -> Vec<u8>{
+    let mut out = Vec::new();
+    let mut last = None;
+    for (postcode, country) in entries{
+        if country != last{
+            out.extend_from_slice(&postcode[0..7]);
+            out.push(country.map(|c| c.byte()).unwrap_or(0));
+            last = country;
+        }
+    }
+    out
+}
+
+/// A decoded countries section
+#[derive(Debug, Clone)]
+pub(crate) struct CountryRuns(Vec<([u8; 7], Option<Country>)>);
+
+impl CountryRuns{
+    pub fn from_bytes(data: &[u8]) -> Option<Self>{
+        if !data.len().is_multiple_of(8){
+            return None;
+        }
+        let runs = data.chunks_exact(8)
+            .map(|run|{
+                let country = match run[7]{
+                    0 => None,
+                    b => Some(Country::from_byte(b)?),
+                };
+                Some((run[0..7].try_into().unwrap(), country))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if !runs.is_sorted_by(|a, b| a.0 < b.0){
+            return None;
+        }
+        Some(Self(runs))
+    }
+
+    /// Number of runs
+    pub fn len(&self) -> usize{
+        self.0.len()
+    }
+
+    /// Country of an entry, by its 7 char postcode
+    pub fn get(&self, postcode: &[u8]) -> Option<Country>{
+        let i = self.0.partition_point(|(p, _)| p.as_slice() <= postcode);
+        self.0.get(i.checked_sub(1)?)?.1
+    }
+}
@@ -0,0 +1,83 @@
+/*
+
+The coordinate reference system (datum) of the locations in a pack file.
+
+Locations are read from the input as WGS84 (or converted to it from grid references, see
+osgb.rs), and are packed as WGS84 unless another system is chosen. Maps made from the National
+Grid or by European mapping agencies are on other datums, which are up to about 100m away from
+WGS84 in the UK, so a layer of postcodes only lines up with them once it's shifted to the same
+datum. ETRS89 is within a metre of WGS84, and OSGB36 is the datum of the National Grid, as a
+latitude and longitude rather than eastings and northings.
+
+Each location is shifted as it's read, so the bounding box, spatial indexes, centroids and every
+distance are in the chosen system. Only version 26 files can say which system they're in (in
+the CRS section, see section.rs), and files without one are WGS84.
+
+*/
+use std::fmt::{Display, Formatter};
+use crate::{Point, osgb};
+
+/// The coordinate reference system of the locations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Crs{
+    /// World Geodetic System 1984, as used by GPS
+    #[default]
+    Wgs84,
+    /// European Terrestrial Reference System 1989, at `osgb::ETRS89_EPOCH`
+    Etrs89,
+    /// Ordnance Survey Great Britain 1936, the datum of the National Grid
+    Osgb36,
+}
+
+impl Crs{
+    pub const ALL: [Crs;3] = [Crs::Wgs84, Crs::Etrs89, Crs::Osgb36];
+
+    /// Value stored in the file to identify the system
+    pub fn id(&self) -> u32{
+        match self{
+            Crs::Wgs84 => 0,
+            Crs::Etrs89 => 1,
+            Crs::Osgb36 => 2,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self>{
+        Self::ALL.into_iter().find(|c| c.id() == id)
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            Crs::Wgs84 => "wgs84",
+            Crs::Etrs89 => "etrs89",
+            Crs::Osgb36 => "osgb36",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// EPSG code of the system as a latitude and longitude
+    pub fn epsg(&self) -> u32{
+        match self{
+            Crs::Wgs84 => 4326,
+            Crs::Etrs89 => 4258,
+            Crs::Osgb36 => 4277,
+        }
+    }
+
+    /// Shift a WGS84 location into this system
+    pub fn from_wgs84(&self, p: Point) -> Point{
+        match self{
+            Crs::Wgs84 => p,
+            Crs::Etrs89 => osgb::wgs84_to_etrs89(p),
+            Crs::Osgb36 => osgb::wgs84_to_osgb36(p),
+        }
+    }
+}
+
+impl Display for Crs{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,154 @@
+/*
+
+Comparison of two pack files, for example consecutive releases of the ONS database.
+
+Locations are quantized relative to each file's own bounding box, so a postcode that has not
+moved can still read back at a slightly different location. Only moves larger than a
+threshold are reported.
+
+The postcodes can also come from input files, so that two releases can be compared before
+either is packed, and `release_notes` writes the differences as Markdown to go with a
+published pack file: the districts that are new or gone, the postcodes that are gone, and the
+postcodes that moved a long way.
+
+*/
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use crate::{Reader, PostcodeInfo, Point, Scheme, distance_m, spaced_postcode};
+
+/// Moves smaller than this (in metres) are assumed to be quantization noise by default
+pub const DEFAULT_MIN_MOVE: f64 = 50.0;
+
+/// Moves smaller than this (in metres) are left out of release notes by default
+pub const DEFAULT_NOTABLE_MOVE: f64 = 500.0;
+
+/// A postcode that is in both files, but in a different place
+#[derive(Debug, Clone)]
+pub struct Moved{
+    pub postcode: String,
+    pub from: Point,
+    pub to: Point,
+    /// Distance moved, in metres
+    pub distance: f64,
+}
+
+/// Differences between two pack files, each list is sorted by postcode.
+///
+/// Outward-only entries are not compared.
+#[derive(Debug, Clone, Default)]
+pub struct PackDiff{
+    /// Postcodes that are only in the new file
+    pub added: Vec<PostcodeInfo>,
+    /// Postcodes that are only in the old file
+    pub removed: Vec<PostcodeInfo>,
+    pub moved: Vec<Moved>,
+    /// Number of postcodes in each file
+    pub old_len: usize,
+    pub new_len: usize,
+    /// Outward codes that only the new file has postcodes in
+    pub added_districts: Vec<String>,
+    /// Outward codes that only the old file has postcodes in
+    pub removed_districts: Vec<String>,
+}
+
+/// Compare two pack files, reporting postcodes that moved by more than `min_move` metres
+pub fn diff(old: &Reader, new: &Reader, min_move: f64) -> PackDiff{
+    diff_postcodes(old.iter(), new.iter(), new.scheme(), min_move)
+}
+
+/// As `diff`, for postcodes from anywhere, such as input files
+pub fn diff_postcodes(old: impl IntoIterator<Item=PostcodeInfo>, new: impl IntoIterator<Item=PostcodeInfo>, scheme: Scheme, min_move: f64) -> PackDiff{
+    let mut old_postcodes: HashMap<String, PostcodeInfo> = old.into_iter()
+        .filter(|p| !p.is_partial)
+        .map(|p| (p.postcode.clone(), p))
+        .collect();
+    let district = |p: &PostcodeInfo| scheme.codec().prefix(&p.postcode).to_string();
+    let old_districts: BTreeSet<String> = old_postcodes.values().map(district).collect();
+    let mut new_districts = BTreeSet::new();
+    let mut result = PackDiff{old_len: old_postcodes.len(), ..Default::default()};
+    for p in new.into_iter().filter(|p| !p.is_partial){
+        result.new_len += 1;
+        new_districts.insert(district(&p));
+        match old_postcodes.remove(&p.postcode){
+            None => result.added.push(p),
+            Some(old) => {
+                let from = old.location;
+                let distance = distance_m(&from, &p.location);
+                if distance > min_move{
+                    result.moved.push(Moved{
+                        postcode: p.postcode,
+                        from,
+                        to: p.location,
+                        distance,
+                    });
+                }
+            },
+        }
+    }
+    result.removed = old_postcodes.into_values().collect();
+    result.added.sort_by(|a,b|a.postcode.cmp(&b.postcode));
+    result.removed.sort_by(|a,b|a.postcode.cmp(&b.postcode));
+    result.moved.sort_by(|a,b|a.postcode.cmp(&b.postcode));
+    result.added_districts = new_districts.difference(&old_districts).cloned().collect();
+    result.removed_districts = old_districts.difference(&new_districts).cloned().collect();
+    result
+}
+
+/// The differences between two releases as Markdown, for release notes. `min_move` is the
+/// shortest move to list, which can be longer than the one the differences were found with.
+pub fn release_notes(d: &PackDiff, old_name: &str, new_name: &str, scheme: Scheme, min_move: f64) -> String{
+    let district = |postcode: &str| scheme.codec().prefix(postcode).to_string();
+    let spaced = |postcode: &str| spaced_postcode(postcode, scheme);
+    let mut moved: Vec<&Moved> = d.moved.iter().filter(|m| m.distance > min_move).collect();
+    moved.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = writeln!(out, "# Changes from {old_name} to {new_name}\n");
+    let _ = writeln!(out, "| | Postcodes |\n|---|---:|");
+    let _ = writeln!(out, "| {old_name} | {} |\n| {new_name} | {} |", d.old_len, d.new_len);
+    let _ = writeln!(out, "| New | {} |\n| Retired | {} |\n| Moved more than {min_move} m | {} |", d.added.len(), d.removed.len(), moved.len());
+
+    let mut added_in: BTreeMap<String, usize> = BTreeMap::new();
+    for p in &d.added{
+        *added_in.entry(district(&p.postcode)).or_default() += 1;
+    }
+    let _ = writeln!(out, "\n## New districts\n");
+    if d.added_districts.is_empty(){
+        let _ = writeln!(out, "None.");
+    }
+    for name in &d.added_districts{
+        let n = added_in.get(name).copied().unwrap_or(0);
+        let _ = writeln!(out, "- {name} ({n} postcode{})", if n == 1 { "" } else { "s" });
+    }
+    let _ = writeln!(out, "\n## Retired districts\n");
+    if d.removed_districts.is_empty(){
+        let _ = writeln!(out, "None.");
+    }
+    for name in &d.removed_districts{
+        let _ = writeln!(out, "- {name}");
+    }
+
+    let _ = writeln!(out, "\n## Retired postcodes\n");
+    if d.removed.is_empty(){
+        let _ = writeln!(out, "None.");
+    }
+    let mut removed_in: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for p in &d.removed{
+        removed_in.entry(district(&p.postcode)).or_default().push(spaced(&p.postcode));
+    }
+    for (name, postcodes) in &removed_in{
+        let _ = writeln!(out, "- {name}: {}", postcodes.join(", "));
+    }
+
+    let _ = writeln!(out, "\n## Moved more than {min_move} m\n");
+    if moved.is_empty(){
+        let _ = writeln!(out, "None.");
+    }
+    else{
+        let _ = writeln!(out, "| Postcode | From | To | Distance |\n|---|---|---|---:|");
+    }
+    for m in moved{
+        let _ = writeln!(out, "| {} | {:.5}, {:.5} | {:.5}, {:.5} | {:.0} m |", spaced(&m.postcode), m.from.y, m.from.x, m.to.y, m.to.x, m.distance);
+    }
+    out
+}
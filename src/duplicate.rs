@@ -0,0 +1,106 @@
+/*
+
+Postcodes that are in more than one row of the input.
+
+A pack file can only hold one location for each postcode, and a reader stops at the first record
+it finds, so a postcode that is in two rows would be packed twice, with the second record never
+read. This happens when an extra CSV file has postcodes that are also in the main input, or when
+the input has been put together from several releases. By default a duplicate is an error, or
+one row of each duplicated postcode is kept: the first or last in the input (the main input
+first, then each extra CSV file in turn), or the one nearest the average location of its outward
+code, which is the most likely to be right when one of the rows has a stray location.
+
+Only `Packer` can choose a row to keep, since the low-memory packer has already counted every row
+in its totals and bounding boxes before it finds out that a postcode is in more than one.
+
+*/
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::{PostcodeInfo, PostcodeError, Point, Scheme, InputProblem, OutwardTotals, input_error, distance_m, spaced_postcode};
+
+/// What to do about a postcode that is in more than one row of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDuplicate{
+    /// Stop with an error
+    #[default]
+    Error,
+    /// Keep the first row
+    First,
+    /// Keep the last row
+    Last,
+    /// Keep the row nearest the average location of the postcode's outward code
+    NearestCentroid,
+}
+
+impl OnDuplicate{
+    pub const ALL: [OnDuplicate;4] = [OnDuplicate::Error, OnDuplicate::First, OnDuplicate::Last, OnDuplicate::NearestCentroid];
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            OnDuplicate::Error => "error",
+            OnDuplicate::First => "first",
+            OnDuplicate::Last => "last",
+            OnDuplicate::NearestCentroid => "nearest-centroid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|d| d.name() == name)
+    }
+}
+
+impl Display for OnDuplicate{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Error for a canonical postcode that is in more than one row
+pub(crate) fn duplicate_error(postcode: &str, scheme: Scheme) -> PostcodeError{
+    input_error(None, None, InputProblem::DuplicatePostcode(spaced_postcode(postcode, scheme)))
+}
+
+/// Keep one row of each postcode. The postcodes must be full codes, sorted by postcode with a
+/// stable sort, so that the rows of each postcode are still in input order. Returns the postcodes
+/// of the rows that were taken out.
+pub(crate) fn resolve(postcodes: &mut Vec<PostcodeInfo>, policy: OnDuplicate, scheme: Scheme) -> Result<Vec<String>, PostcodeError>{
+    if !postcodes.windows(2).any(|w| w[0].postcode == w[1].postcode){
+        return Ok(Vec::new());
+    }
+    let centroids: HashMap<String, Point> = match policy{
+        OnDuplicate::Error => {
+            let w = postcodes.windows(2).find(|w| w[0].postcode == w[1].postcode).unwrap();
+            return Err(duplicate_error(&w[0].postcode, scheme));
+        }
+        OnDuplicate::NearestCentroid => {
+            let mut totals = OutwardTotals::new(scheme);
+            for p in postcodes.iter(){
+                totals.add(p);
+            }
+            totals.averages().map(|p| (p.postcode.trim_end().to_string(), p.location)).collect()
+        }
+        _ => HashMap::new(),
+    };
+    let mut kept = vec![false; postcodes.len()];
+    let mut removed = Vec::new();
+    let mut start = 0;
+    for rows in postcodes.chunk_by(|a, b| a.postcode == b.postcode){
+        let keep = match policy{
+            OnDuplicate::Last => rows.len() - 1,
+            // An outward code of only terminated postcodes has no average, so the first is kept
+            OnDuplicate::NearestCentroid => match centroids.get(scheme.codec().prefix(&rows[0].postcode)){
+                Some(centroid) => (0..rows.len())
+                    .min_by(|&a, &b| distance_m(&rows[a].location, centroid).total_cmp(&distance_m(&rows[b].location, centroid)))
+                    .unwrap(),
+                None => 0,
+            },
+            _ => 0,
+        };
+        kept[start + keep] = true;
+        removed.extend(rows.iter().skip(1).map(|p| p.postcode.clone()));
+        start += rows.len();
+    }
+    let mut kept = kept.into_iter();
+    postcodes.retain(|_| kept.next().unwrap());
+    Ok(removed)
+}
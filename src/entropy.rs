@@ -0,0 +1,124 @@
+/*
+
+Entropy coding of the postcode data, from format version 14 onwards (optional).
+
+The records are mostly format bytes and small deltas, so some byte values are far more common
+than others. A static Huffman code, trained on the bytes of the whole record stream, stores the
+common ones in fewer bits. Unlike compressing the whole data section, each prefix block is coded
+on its own, so a block can still be fetched with a range request and decoded without the rest.
+The coded data is also still fairly compressible at the HTTP level.
+
+The code is stored in the header as the length in bits of the code for each byte value, and the
+codes themselves are canonical (see RFC 1951), so the lengths are enough to rebuild them. A byte
+value with length 0 does not appear in the data. If every length is 0 the data is not coded.
+
+Coded block structure:
+
+    decoded_len: varint (unsigned LEB128, number of bytes after decoding)
+    codes:       the code of each byte, most significant bit first, padded with zeros to a whole byte
+
+The decoder is in the `nearmypostcode_core` crate, with the rest of the record decoding.
+
+*/
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use crate::write_varint;
+use nearmypostcode_core::entropy::MAX_CODE_LEN;
+
+/// Length in bits of the code for each byte value, 0 for values that are not used
+pub(crate) type CodeLengths = [u8;256];
+
+/// Train a code on the bytes in `data`
+pub(crate) fn code_lengths(data: &[u8]) -> CodeLengths{
+    let mut counts = [0u64;256];
+    for &b in data{
+        counts[b as usize] += 1;
+    }
+    // Every code needs at least one bit, which needs at least two values
+    let mut used = counts.iter().filter(|&&n| n > 0).count();
+    for n in counts.iter_mut().take(2){
+        if used < 2 && *n == 0{
+            *n = 1;
+            used += 1;
+        }
+    }
+    loop{
+        let lengths = huffman_lengths(&counts);
+        if lengths.iter().all(|&l| l <= MAX_CODE_LEN){
+            return lengths;
+        }
+        // Flatten the distribution until the longest code is short enough
+        for n in counts.iter_mut().filter(|n| **n > 0){
+            *n = (*n / 2).max(1);
+        }
+    }
+}
+
+/// Code lengths from a standard Huffman tree, which may be longer than `MAX_CODE_LEN`
+fn huffman_lengths(counts: &[u64;256]) -> CodeLengths{
+    // Nodes 0 to 255 are the leaves, the rest are added as the tree is built
+    let mut parents: Vec<usize> = vec![usize::MAX; 256];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = counts.iter().enumerate()
+        .filter(|(_, &n)| n > 0)
+        .map(|(b, &n)| Reverse((n, b)))
+        .collect();
+    while heap.len() > 1{
+        let Reverse((n1, a)) = heap.pop().unwrap();
+        let Reverse((n2, b)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[a] = node;
+        parents[b] = node;
+        heap.push(Reverse((n1 + n2, node)));
+    }
+    let mut lengths = [0u8;256];
+    for (b, length) in lengths.iter_mut().enumerate(){
+        if counts[b] == 0{
+            continue;
+        }
+        let mut node = b;
+        while parents[node] != usize::MAX{
+            node = parents[node];
+            *length = length.saturating_add(1);
+        }
+    }
+    lengths
+}
+
+/// The canonical code for each byte value, with its length
+fn canonical_codes(lengths: &CodeLengths) -> [(u16, u8);256]{
+    let mut codes = [(0u16, 0u8);256];
+    let mut code: u16 = 0;
+    for len in 1..=MAX_CODE_LEN{
+        for (b, &l) in lengths.iter().enumerate(){
+            if l == len{
+                codes[b] = (code, len);
+                code += 1;
+            }
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+/// Code one prefix block
+pub(crate) fn encode(data: &[u8], lengths: &CodeLengths) -> Vec<u8>{
+    let codes = canonical_codes(lengths);
+    let mut out = Vec::new();
+    write_varint(&mut out, data.len() as u32);
+    let mut bits: u32 = 0;
+    let mut nbits: u32 = 0;
+    for &b in data{
+        let (code, len) = codes[b as usize];
+        bits = (bits << len) | code as u32;
+        nbits += len as u32;
+        while nbits >= 8{
+            nbits -= 8;
+            out.push((bits >> nbits) as u8);
+        }
+    }
+    if nbits > 0{
+        out.push((bits << (8 - nbits)) as u8);
+    }
+    out
+}
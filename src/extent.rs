@@ -0,0 +1,134 @@
+/*
+
+Bounding boxes of outward codes, from format version 20 onwards.
+
+So that a map can zoom to the right area as soon as the outward code has been typed, before the
+whole postcode is, the packer can store the bounding box of the postcodes in each outward code
+(the routing key, for Eircodes). Terminated postcodes are not counted. The corners are quantized
+to 16 bits in the file's bounding box, like the centroids, but rounded outwards, so the box always
+covers every postcode in it.
+
+Prefix extents section, variable length (before the attributes section):
+
+    count: 4 bytes (u32, number of outward codes)
+    boxes: count of these, sorted by outward code
+        outward: 4 bytes (as in a canonical postcode, e.g. "B1  ", or "D6W " for an Eircode)
+        minlong: 2 bytes (u16, rounded down)
+        minlat:  2 bytes (u16, rounded down)
+        maxlong: 2 bytes (u16, rounded up)
+        maxlat:  2 bytes (u16, rounded up)
+
+*/
+#[cfg(feature="packer")]
+use std::collections::BTreeMap;
+
+#[cfg(feature="packer")]
+use crate::{PostcodeInfo, Point, Scheme};
+#[cfg(feature="packer")]
+use crate::region::bounds;
+
+const ENTRY_LEN: usize = 12;
+
+/// Running bounding box of the postcodes in each outward code, in order
+#[cfg(feature="packer")]
+pub(crate) struct ExtentTotals{
+    boxes: BTreeMap<String, (Point, Point)>,
+    scheme: Scheme,
+}
+
+#[cfg(feature="packer")]
+impl ExtentTotals{
+    pub fn new(scheme: Scheme) -> Self{
+        Self{boxes: BTreeMap::new(), scheme}
+    }
+
+    pub fn add(&mut self, p: &PostcodeInfo){
+        if p.is_partial || p.terminated.is_some(){
+            return;
+        }
+        let outward = self.scheme.codec().prefix(&p.postcode);
+        let b = self.boxes.entry(format!("{outward:<4}")).or_insert((p.location, p.location));
+        *b = bounds([&b.0, &b.1, &p.location]).unwrap();
+    }
+
+    /// Encode the prefix extents section, corners are quantized relative to the bounding box
+    pub fn to_bytes(&self, minll: Point, maxll: Point) -> Vec<u8>{
+        let quantize = |v: f64, min: f64, max: f64, up: bool| {
+            let q = ((v - min) / (max - min)) * 65535.0;
+            (if up { q.ceil() } else { q.floor() }).clamp(0.0, 65535.0) as u16
+        };
+        let mut out = Vec::with_capacity(4 + (self.boxes.len() * ENTRY_LEN));
+        out.extend_from_slice(&(self.boxes.len() as u32).to_le_bytes());
+        for (outward, (min, max)) in &self.boxes{
+            out.extend_from_slice(outward.as_bytes());
+            out.extend_from_slice(&quantize(min.x, minll.x, maxll.x, false).to_le_bytes());
+            out.extend_from_slice(&quantize(min.y, minll.y, maxll.y, false).to_le_bytes());
+            out.extend_from_slice(&quantize(max.x, minll.x, maxll.x, true).to_le_bytes());
+            out.extend_from_slice(&quantize(max.y, minll.y, maxll.y, true).to_le_bytes());
+        }
+        out
+    }
+}
+
+/// A prefix extents section read from a pack file
+#[derive(Debug, Clone)]
+pub(crate) struct PrefixExtents{
+    data: Vec<u8>,
+}
+
+impl PrefixExtents{
+    pub fn from_bytes(data: &[u8]) -> Option<Self>{
+        let count = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+        if count.checked_mul(ENTRY_LEN)?.checked_add(4)? != data.len(){
+            return None;
+        }
+        let entries: Vec<&[u8]> = data[4..].chunks_exact(ENTRY_LEN).collect();
+        if !entries.iter().all(|e| e[0..4].is_ascii()) || !entries.windows(2).all(|w| w[0][0..4] < w[1][0..4]){
+            return None;
+        }
+        Some(Self{data: data.to_vec()})
+    }
+
+    /// The whole section, as it is in the file
+    #[cfg(feature="packer")]
+    pub fn as_bytes(&self) -> &[u8]{
+        &self.data
+    }
+
+    /// Number of outward codes
+    pub fn len(&self) -> usize{
+        (self.data.len() - 4) / ENTRY_LEN
+    }
+
+    fn entry(&self, i: usize) -> &[u8]{
+        &self.data[4 + (i * ENTRY_LEN)..4 + ((i + 1) * ENTRY_LEN)]
+    }
+
+    /// Outward code (without padding) and quantized corners of every entry, in order
+    pub fn iter(&self) -> impl Iterator<Item=(&str, [u16;4])> + '_{
+        (0..self.len()).map(|i| {
+            let e = self.entry(i);
+            (std::str::from_utf8(&e[0..4]).unwrap().trim_end(), corners(e))
+        })
+    }
+
+    /// Quantized minlong, minlat, maxlong and maxlat of an outward code, padded to 4 chars
+    pub fn get(&self, outward: &str) -> Option<[u16;4]>{
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi{
+            let mid = (lo + hi) / 2;
+            let e = self.entry(mid);
+            match e[0..4].cmp(outward.as_bytes()){
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(corners(e)),
+            }
+        }
+        None
+    }
+}
+
+/// Quantized minlong, minlat, maxlong and maxlat of an entry
+fn corners(e: &[u8]) -> [u16;4]{
+    [0, 1, 2, 3].map(|i| u16::from_le_bytes([e[4 + i*2], e[5 + i*2]]))
+}
@@ -0,0 +1,192 @@
+/*
+
+Downloading the ONS postcode database (ONSPD).
+
+ONSPD releases are published quarterly on the ONS Open Geography portal, which is an ArcGIS Hub
+site. Each release is an ArcGIS item called "ONS Postcode Directory (<month> <year>)", and the
+zip file can be downloaded from the item's data URL. The portal's search API is used to find
+the items, so there is no need to find the right page and link by hand.
+
+Downloads are cached, so running `fetch` again only downloads a release once. The SHA-256 of each
+file is kept next to it, and checked whenever the cached file is used. The portal doesn't publish
+checksums, but an expected SHA-256 can be given (e.g. one recorded from an earlier download) to
+make sure that the file is the same.
+
+Only built with the `fetch` feature, which is on by default.
+
+*/
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+
+use crate::{PostcodeError, sha256_file, hex};
+
+const SEARCH_URL: &str = "https://www.arcgis.com/sharing/rest/search";
+const ITEM_URL: &str = "https://www.arcgis.com/sharing/rest/content/items";
+const OWNER: &str = "ONSGeography_data";
+const TITLE: &str = "ONS Postcode Directory";
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// A release of the ONS postcode database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release{
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// Title of the item on the portal
+    pub title: String,
+    pub url: String,
+}
+
+impl Release{
+    /// Name of the release in the form used by `--release`, e.g. "2024-11"
+    pub fn name(&self) -> String{
+        format!("{}-{:02}", self.year, self.month)
+    }
+}
+
+/// Parse a release name such as "2024-11" in to a year and month
+pub fn parse_release(name: &str) -> Option<(u16, u8)>{
+    let (year, month) = name.split_once('-')?;
+    let month: u8 = month.parse().ok()?;
+    (year.len() == 4 && (1..=12).contains(&month)).then_some((year.parse().ok()?, month))
+}
+
+/// Year and month from an item title, such as "ONS Postcode Directory (November 2024) for the UK"
+fn title_release(title: &str) -> Option<(u16, u8)>{
+    let rest = title.strip_prefix(TITLE)?.trim_start().strip_prefix('(')?;
+    let (date, _) = rest.split_once(')')?;
+    let (month, year) = date.trim().split_once(' ')?;
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u8 + 1;
+    Some((year.trim().parse().ok()?, month))
+}
+
+fn http_error(e: ureq::Error) -> PostcodeError{
+    PostcodeError::DownloadFailed(e.to_string())
+}
+
+/// Every release that is on the portal, newest first
+pub fn find_releases() -> Result<Vec<Release>, PostcodeError>{
+    let query = format!("title:\"{TITLE}\" AND owner:{OWNER}");
+    let body = ureq::get(SEARCH_URL)
+        .query("q", &query)
+        .query("sortField", "created")
+        .query("sortOrder", "desc")
+        .query("num", "100")
+        .query("f", "json")
+        .call().map_err(http_error)?
+        .body_mut().read_to_string().map_err(http_error)?;
+    let json: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| PostcodeError::DownloadFailed(format!("unexpected search response: {e}")))?;
+    let results = json["results"].as_array()
+        .ok_or_else(|| PostcodeError::DownloadFailed("unexpected search response: no results".to_string()))?;
+    let mut releases: Vec<Release> = results.iter()
+        // Each release also has user guides and other documents with similar titles
+        .filter(|item| item["type"].as_str().is_some_and(|t| t.contains("CSV")))
+        .filter_map(|item|{
+            let title = item["title"].as_str()?;
+            let id = item["id"].as_str()?;
+            let (year, month) = title_release(title)?;
+            Some(Release{year, month, title: title.to_string(), url: format!("{ITEM_URL}/{id}/data")})
+        })
+        .collect();
+    releases.sort_by_key(|r| std::cmp::Reverse((r.year, r.month)));
+    releases.dedup_by_key(|r| (r.year, r.month));
+    Ok(releases)
+}
+
+/// Find a release by name, or the newest one
+pub fn find_release(name: Option<&str>) -> Result<Release, PostcodeError>{
+    let wanted = match name{
+        Some(n) => Some(parse_release(n).ok_or_else(|| PostcodeError::ReleaseNotFound(n.to_string()))?),
+        None => None,
+    };
+    find_releases()?.into_iter()
+        .find(|r| wanted.is_none_or(|w| w == (r.year, r.month)))
+        .ok_or_else(|| PostcodeError::ReleaseNotFound(name.unwrap_or("latest").to_string()))
+}
+
+/// Where downloads are cached by default: `$XDG_CACHE_HOME/nearmypostcode`, or
+/// `~/.cache/nearmypostcode`
+pub fn default_cache_dir() -> PathBuf{
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| Path::new(&h).join(".cache")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("nearmypostcode")
+}
+
+fn checksum_path(path: &Path) -> PathBuf{
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Check a file against its stored checksum and the expected one, returns the checksum
+fn check_cached(path: &Path, expected: Option<&str>) -> Result<Option<String>, PostcodeError>{
+    let Ok(stored) = fs::read_to_string(checksum_path(path)) else { return Ok(None) };
+    let found = sha256_file(path)?;
+    if found != stored.trim() || expected.is_some_and(|e| !e.eq_ignore_ascii_case(&found)){
+        return Ok(None);
+    }
+    Ok(Some(found))
+}
+
+/// A downloaded file
+#[derive(Debug, Clone)]
+pub struct Fetched{
+    pub path: PathBuf,
+    pub sha256: String,
+    /// Whether the file was already in the cache
+    pub cached: bool,
+}
+
+/// Download a file in to the cache directory, unless a good copy is already there.
+///
+/// `progress` is called with the number of bytes downloaded so far and the total size, if the
+/// server says what it is. If `expected_sha256` is given, a download that doesn't match it is
+/// deleted and an error is returned.
+pub fn fetch<F: FnMut(u64, Option<u64>)>(url: &str, cache_dir: &Path, file_name: &str, expected_sha256: Option<&str>, mut progress: F) -> Result<Fetched, PostcodeError>{
+    fs::create_dir_all(cache_dir)?;
+    let path = cache_dir.join(file_name);
+    if let Some(sha256) = check_cached(&path, expected_sha256)?{
+        return Ok(Fetched{path, sha256, cached: true});
+    }
+
+    // Download to a temporary name first, so an interrupted download is never used
+    let partial = cache_dir.join(format!("{file_name}.part"));
+    let mut response = ureq::get(url).call().map_err(http_error)?;
+    let total = response.body().content_length();
+    let mut reader = response.body_mut().with_config().limit(u64::MAX).reader();
+    let mut out = File::create(&partial)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    let mut done = 0;
+    loop{
+        let n = reader.read(&mut buf)?;
+        if n == 0{
+            break;
+        }
+        out.write_all(&buf[..n])?;
+        hasher.update(&buf[..n]);
+        done += n as u64;
+        progress(done, total);
+    }
+    out.flush()?;
+    drop(out);
+    let sha256 = hex(&hasher.finalize());
+    if let Some(expected) = expected_sha256{
+        if !expected.eq_ignore_ascii_case(&sha256){
+            fs::remove_file(&partial)?;
+            return Err(PostcodeError::ChecksumMismatch(expected.to_string(), sha256));
+        }
+    }
+    fs::rename(&partial, &path)?;
+    fs::write(checksum_path(&path), format!("{sha256}\n"))?;
+    Ok(Fetched{path, sha256, cached: false})
+}
@@ -0,0 +1,99 @@
+/*
+
+Adding locations to a CSV file, for example a list of addresses, by looking up the postcode in
+one of its columns.
+
+The output is the input with lat and long columns added to the end of each row, which are left
+empty if the postcode can't be found. Rows are read in batches, and the postcodes in each batch
+are looked up in parallel, so a file of any size can be geocoded without reading it all in.
+
+*/
+use std::io::{Read, Write};
+use rayon::prelude::*;
+use crate::{PostcodeError, Point, Reader, InputProblem, input_error};
+
+/// Rows to look up at once
+const BATCH: usize = 10000;
+
+/// Which column of the input has the postcodes in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column{
+    /// Counting from 1
+    Number(usize),
+    /// Name in the header row
+    Name(String),
+}
+
+impl Column{
+    /// A column number if `s` is a number, otherwise a column name
+    pub fn parse(s: &str) -> Column{
+        match s.parse::<usize>(){
+            Ok(n) => Column::Number(n),
+            Err(_) => Column::Name(s.to_string()),
+        }
+    }
+}
+
+/// What happened to the rows of the input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GeocodeStats{
+    /// Rows, not counting the header row
+    pub rows: u64,
+    /// Rows with a postcode that was found in the pack file
+    pub found: u64,
+}
+
+fn locate(reader: &Reader, postcode: Option<&str>) -> Option<Point>{
+    reader.lookup(postcode?.trim())
+}
+
+/// Copy a CSV file from `input` to `output`, adding the location of the postcode in `column` to
+/// each row. Without a header row, `column` must be a number.
+pub fn geocode<R: Read, W: Write>(reader: &Reader, input: R, output: W, column: &Column, has_header: bool) -> Result<GeocodeStats,PostcodeError>{
+    let mut csv_in = csv::ReaderBuilder::new().has_headers(has_header).flexible(true).from_reader(input);
+    let mut csv_out = csv::WriterBuilder::new().flexible(true).from_writer(output);
+    let index = match column{
+        Column::Number(0) => return Err(PostcodeError::IncompatibleOptions("column numbers start from 1")),
+        Column::Number(n) => n - 1,
+        Column::Name(name) => {
+            if !has_header{
+                return Err(PostcodeError::IncompatibleOptions("columns can only be given by name if there is a header row"));
+            }
+            csv_in.headers()?.iter().position(|h| h.trim() == name)
+                .ok_or_else(|| input_error(Some(1), None, InputProblem::MissingColumn(name.clone())))?
+        }
+    };
+    if has_header{
+        let mut header = csv_in.headers()?.clone();
+        header.push_field("lat");
+        header.push_field("long");
+        csv_out.write_record(&header)?;
+    }
+
+    let mut stats = GeocodeStats::default();
+    let mut records = csv_in.into_records();
+    loop{
+        let batch = records.by_ref().take(BATCH).collect::<Result<Vec<_>,_>>()?;
+        if batch.is_empty(){
+            break;
+        }
+        let locations: Vec<Option<Point>> = batch.par_iter().map(|r| locate(reader, r.get(index))).collect();
+        for (mut record, location) in batch.into_iter().zip(locations){
+            stats.rows += 1;
+            match location{
+                Some(l) => {
+                    stats.found += 1;
+                    record.push_field(&l.y.to_string());
+                    record.push_field(&l.x.to_string());
+                }
+                None => {
+                    record.push_field("");
+                    record.push_field("");
+                }
+            }
+            csv_out.write_record(&record)?;
+        }
+    }
+    csv_out.flush()?;
+    Ok(stats)
+}
@@ -0,0 +1,229 @@
+/*
+
+Geohash spatial index, from format version 18 onwards.
+
+Every postcode that is in use (not an outward code, and not terminated) is put in the bucket for
+its geohash, at a chosen precision. A geohash splits the world in half by longitude, then each
+half by latitude, and so on, one bit at a time, so the geohashes that start with the same bits
+are the same area. 5 bits is one character of a geohash string, so at precision 6 (30 bits, the
+most) the buckets are about 1.2km by 0.6km. The geohash is of the location as it is read back
+from the file, not the location in the input, so it is exact.
+
+The buckets are sorted by geohash, so the buckets in any area are next to each other, and can be
+found with a binary search. Each bucket lists the entries of the postcodes in it, which the
+reader decodes to get their postcodes and locations.
+
+Geohash spatial index section, variable length (at the end of the file):
+
+    precision: 1 byte (u8, characters of geohash, 1 to 6)
+    count:     4 bytes (u32, number of buckets)
+    buckets:   count*8 bytes, in order of geohash
+        hash:  4 bytes (u32, the geohash as a number, precision*5 bits)
+        start: 4 bytes (u32, position in the record list of the bucket's first record)
+    records:   6 bytes each, to the end of the section. A bucket's records are the ones from its
+               start up to the next bucket's start (or the end of the list)
+        block: 2 bytes (u16, position of the postcode's prefix in the quick lookup table)
+        entry: 4 bytes (u32, which entry of the block the postcode is, counting from 0, as in
+                        the k-d tree index, see kdtree.rs)
+
+Geohash bits, starting with the most significant: for each bit, the range (-180 to 180 for
+longitude, -90 to 90 for latitude) is split at its midpoint, the bit is 1 if the location is at
+or above the midpoint, and the range becomes the half that the location is in. Bits alternate
+between longitude and latitude, starting with longitude.
+
+*/
+use crate::Point;
+#[cfg(feature="packer")]
+use crate::LUT_SIZE;
+#[cfg(feature="packer")]
+use crate::spatial::IndexPoint;
+
+/// Longest geohash, in characters, so that a geohash fits in a u32
+pub const MAX_PRECISION: u8 = 6;
+/// Precision used if none is given
+pub const DEFAULT_PRECISION: u8 = 6;
+
+const BUCKET_LEN: usize = 8;
+const RECORD_LEN: usize = 6;
+
+/// Geohash of a location as a number, with `precision`*5 bits
+pub fn geohash(location: &Point, precision: u8) -> u32{
+    let mut long = (-180.0, 180.0);
+    let mut lat = (-90.0, 90.0);
+    let mut hash = 0;
+    for bit in 0..(precision as u32 * 5){
+        let (range, value) = if bit.is_multiple_of(2) { (&mut long, location.x) } else { (&mut lat, location.y) };
+        let mid = (range.0 + range.1) / 2.0;
+        hash <<= 1;
+        if value >= mid{
+            hash |= 1;
+            range.0 = mid;
+        }
+        else{
+            range.1 = mid;
+        }
+    }
+    hash
+}
+
+/// Lower left and upper right corners of the area of the geohashes that start with the `bits`
+/// bits of `prefix`
+pub fn cell_bounds(prefix: u32, bits: u32) -> (Point, Point){
+    let mut long = (-180.0, 180.0);
+    let mut lat = (-90.0, 90.0);
+    for bit in 0..bits{
+        let range = if bit.is_multiple_of(2) { &mut long } else { &mut lat };
+        let mid = (range.0 + range.1) / 2.0;
+        if (prefix >> (bits - bit - 1)) & 1 == 1{
+            range.0 = mid;
+        }
+        else{
+            range.1 = mid;
+        }
+    }
+    (Point{x: long.0, y: lat.0}, Point{x: long.1, y: lat.1})
+}
+
+/// The buckets whose geohashes start with the same bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Cell{
+    pub prefix: u32,
+    pub bits: u32,
+    /// Range of buckets
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeohashIndex{
+    precision: u8,
+    /// (geohash, position of the first record)
+    buckets: Vec<(u32, u32)>,
+    /// (block, entry)
+    records: Vec<(u16, u32)>,
+}
+
+impl GeohashIndex{
+    /// Build an index from every entry of the file, in file order
+    #[cfg(feature="packer")]
+    pub(crate) fn build<I: Iterator<Item=IndexPoint>>(precision: u8, entries: I) -> Self{
+        let mut next_entry = vec![0u32; LUT_SIZE];
+        let mut points: Vec<(u32, u16, u32)> = entries
+            .filter_map(|p| {
+                let entry = next_entry[p.block];
+                next_entry[p.block] += 1;
+                (!p.is_partial && !p.terminated).then(|| (geohash(&p.location, precision), p.block as u16, entry))
+            })
+            .collect();
+        points.sort();
+        let mut buckets: Vec<(u32, u32)> = Vec::new();
+        let mut records = Vec::with_capacity(points.len());
+        for (hash, block, entry) in points{
+            if buckets.last().is_none_or(|&(h, _)| h != hash){
+                buckets.push((hash, records.len() as u32));
+            }
+            records.push((block, entry));
+        }
+        Self{precision, buckets, records}
+    }
+
+    #[cfg(feature="packer")]
+    pub(crate) fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(5 + (self.buckets.len() * BUCKET_LEN) + (self.records.len() * RECORD_LEN));
+        out.push(self.precision);
+        out.extend_from_slice(&(self.buckets.len() as u32).to_le_bytes());
+        for (hash, start) in &self.buckets{
+            out.extend_from_slice(&hash.to_le_bytes());
+            out.extend_from_slice(&start.to_le_bytes());
+        }
+        for (block, entry) in &self.records{
+            out.extend_from_slice(&block.to_le_bytes());
+            out.extend_from_slice(&entry.to_le_bytes());
+        }
+        out
+    }
+
+    /// Parse the index section, returns `None` if it is damaged
+    pub(crate) fn from_bytes(data: &[u8]) -> Option<Self>{
+        let precision = *data.first()?;
+        if precision == 0 || precision > MAX_PRECISION{
+            return None;
+        }
+        let count = u32::from_le_bytes(data.get(1..5)?.try_into().ok()?) as usize;
+        let records_start = count.checked_mul(BUCKET_LEN)?.checked_add(5)?;
+        let records = data.get(records_start..)?;
+        if !records.len().is_multiple_of(RECORD_LEN){
+            return None;
+        }
+        let u32_at = |p: &[u8], i: usize| u32::from_le_bytes(p[i..i+4].try_into().unwrap());
+        let buckets: Vec<(u32, u32)> = data[5..records_start].chunks_exact(BUCKET_LEN)
+            .map(|b| (u32_at(b, 0), u32_at(b, 4)))
+            .collect();
+        let records: Vec<(u16, u32)> = records.chunks_exact(RECORD_LEN)
+            .map(|r| (u16::from_le_bytes([r[0], r[1]]), u32_at(r, 2)))
+            .collect();
+        // Geohashes must be in order and fit in the precision, and every bucket must have records
+        if buckets.windows(2).any(|w| w[0].0 >= w[1].0 || w[0].1 >= w[1].1){
+            return None;
+        }
+        if buckets.last().is_some_and(|&(hash, start)| hash >> (precision as u32 * 5) != 0 || start as usize >= records.len()){
+            return None;
+        }
+        if buckets.first().map(|&(_, start)| start as usize) != (!records.is_empty()).then_some(0){
+            return None;
+        }
+        Some(Self{precision, buckets, records})
+    }
+
+    /// Characters of geohash
+    pub fn precision(&self) -> u8{
+        self.precision
+    }
+
+    /// Number of buckets, which is the number of different geohashes
+    pub fn buckets(&self) -> usize{
+        self.buckets.len()
+    }
+
+    /// Number of postcodes in the index
+    pub fn len(&self) -> usize{
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.records.is_empty()
+    }
+
+    /// Highest block number of any record
+    pub(crate) fn max_block(&self) -> Option<usize>{
+        self.records.iter().map(|&(block, _)| block as usize).max()
+    }
+
+    /// Every bucket, `None` if there are none
+    pub(crate) fn root(&self) -> Option<Cell>{
+        (!self.buckets.is_empty()).then_some(Cell{prefix: 0, bits: 0, start: 0, end: self.buckets.len()})
+    }
+
+    /// Whether a cell is a single bucket
+    pub(crate) fn is_bucket(&self, cell: &Cell) -> bool{
+        cell.bits == self.precision as u32 * 5
+    }
+
+    /// The halves of a cell that is bigger than a bucket, either can be `None` if it has no
+    /// buckets
+    pub(crate) fn children(&self, cell: &Cell) -> (Option<Cell>, Option<Cell>){
+        let bits = cell.bits + 1;
+        let shift = (self.precision as u32 * 5) - bits;
+        let split = cell.start + self.buckets[cell.start..cell.end].partition_point(|&(hash, _)| (hash >> shift) & 1 == 0);
+        let left = Cell{prefix: cell.prefix << 1, bits, start: cell.start, end: split};
+        let right = Cell{prefix: (cell.prefix << 1) | 1, bits, start: split, end: cell.end};
+        ((left.start < left.end).then_some(left), (right.start < right.end).then_some(right))
+    }
+
+    /// The (block, entry) of each postcode in a bucket
+    pub(crate) fn records(&self, cell: &Cell) -> &[(u16, u32)]{
+        let start = self.buckets[cell.start].1 as usize;
+        let end = self.buckets.get(cell.start + 1).map(|&(_, s)| s as usize).unwrap_or(self.records.len());
+        &self.records[start..end]
+    }
+}
@@ -0,0 +1,257 @@
+/*
+
+Coordinate groups, from format version 25 onwards (optional).
+
+The UK's postcodes are in a few clusters a long way apart: Great Britain, Northern Ireland,
+Shetland, the Isles of Scilly, the Channel Islands and so on. Quantized across one bounding box
+around all of them, much of the grid is sea. With `--groups` the packer joins up the outward
+codes (routing keys for Eircodes) whose postcodes are less than 20 km from each other's, and
+quantizes the locations of each of the clusters it ends up with across its own bounding box.
+Only the outward codes in the other groups are listed, every other one is in the main group,
+which is the one with the most postcodes.
+
+The file's bounding box is still around everything, and the centroids, prefix extents and
+spatial index are still quantized in it. Groups take the place of local bounding boxes and of
+the outliers, so they can't be used together.
+
+Coordinate groups, variable length (in the header, after the outliers):
+
+    count: 4 bytes (u32, number of groups, 0 if there are none)
+    groups: count of these, the main group first
+        minlong: 4 bytes (u32, fraction of the range -180 to 180, rounded down, like the bounding box)
+        maxlong: 4 bytes (u32, rounded up)
+        minlat:  4 bytes (u32, fraction of the range -90 to 90, rounded down)
+        maxlat:  4 bytes (u32, rounded up)
+    members: 4 bytes (u32, number of outward codes that aren't in the main group)
+    outward codes: members of these, sorted
+        outward: 4 bytes (as in a canonical postcode, e.g. "ZE1 ", or "D6W " for an Eircode)
+        group:   1 byte (u8, index of the group, from 1)
+
+*/
+use std::collections::BTreeMap;
+
+use crate::{Point, Scheme, dequantize_extent};
+#[cfg(feature="packer")]
+use crate::{PostcodeInfo, quantize_extent, distance_m};
+
+/// Length of each group's box, and of each member
+const BOX_LEN: usize = 16;
+const MEMBER_LEN: usize = 5;
+
+/// Outward codes closer than this are in the same group
+#[cfg(feature="packer")]
+const MIN_GAP_M: f64 = 20_000.0;
+
+/// Most groups in a file, the nearest ones are joined up until there are no more than this
+#[cfg(feature="packer")]
+const MAX_GROUPS: usize = 16;
+
+/// The groups that the locations are quantized in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Groups{
+    /// Quantized like the file's bounding box: minlong, maxlong, minlat, maxlat
+    boxes: Vec<[u32; 4]>,
+    /// Group of each outward code (padded to 4 chars) that isn't in the main group
+    members: BTreeMap<String, u8>,
+    scheme: Scheme,
+}
+
+impl Groups{
+    /// Read the groups from the start of `data`, returns them and their length
+    pub fn from_bytes(data: &[u8], scheme: Scheme) -> Option<(Self, usize)>{
+        let u = |i: usize| data.get(i..i+4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let count = u(0)? as usize;
+        let boxes_end = count.checked_mul(BOX_LEN)?.checked_add(4)?;
+        let boxes: Vec<[u32; 4]> = data.get(4..boxes_end)?.chunks_exact(BOX_LEN)
+            .map(|b| std::array::from_fn(|i| u32::from_le_bytes(b[i*4..i*4+4].try_into().unwrap())))
+            .collect();
+        let members_len = u(boxes_end)? as usize;
+        let len = members_len.checked_mul(MEMBER_LEN)?.checked_add(boxes_end + 4)?;
+        let mut members = BTreeMap::new();
+        for m in data.get(boxes_end+4..len)?.chunks_exact(MEMBER_LEN){
+            let outward = std::str::from_utf8(&m[0..4]).ok()?;
+            if m[4] == 0 || m[4] as usize >= count || members.insert(outward.to_string(), m[4]).is_some(){
+                return None;
+            }
+        }
+        if boxes.iter().any(|b| b[0] > b[1] || b[2] > b[3]){
+            return None;
+        }
+        Some((Self{boxes, members, scheme}, len))
+    }
+
+    #[cfg(feature="packer")]
+    pub fn is_empty(&self) -> bool{
+        self.boxes.is_empty()
+    }
+
+    #[cfg(feature="packer")]
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(8 + (self.boxes.len() * BOX_LEN) + (self.members.len() * MEMBER_LEN));
+        out.extend_from_slice(&(self.boxes.len() as u32).to_le_bytes());
+        for b in &self.boxes{
+            for q in b{
+                out.extend_from_slice(&q.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&(self.members.len() as u32).to_le_bytes());
+        for (outward, &group) in &self.members{
+            out.extend_from_slice(outward.as_bytes());
+            out.push(group);
+        }
+        out
+    }
+
+    /// Lower left and upper right corners of a group's box
+    fn group_bounds(&self, group: usize) -> (Point, Point){
+        let [minlong, maxlong, minlat, maxlat] = self.boxes[group];
+        (
+            Point{x: dequantize_extent(minlong, 180.0), y: dequantize_extent(minlat, 90.0)},
+            Point{x: dequantize_extent(maxlong, 180.0), y: dequantize_extent(maxlat, 90.0)},
+        )
+    }
+
+    /// The box that the location of a canonical postcode or outward code is quantized in, `None`
+    /// if there are no groups
+    pub fn bounds(&self, postcode: &[u8]) -> Option<(Point, Point)>{
+        if self.boxes.is_empty(){
+            return None;
+        }
+        let outward = std::str::from_utf8(postcode).ok().map(|p| self.scheme.codec().prefix(p));
+        let group = outward.and_then(|o| self.members.get(&format!("{o:<4}"))).copied().unwrap_or(0);
+        Some(self.group_bounds(group as usize))
+    }
+
+    /// Box of each group and the outward codes in it, without padding. The main group's list is
+    /// empty, since it has every outward code that isn't in another.
+    pub fn list(&self) -> Vec<((Point, Point), Vec<String>)>{
+        (0..self.boxes.len())
+            .map(|g| (self.group_bounds(g), self.members.iter()
+                .filter(|(_, &m)| m as usize == g)
+                .map(|(o, _)| o.trim_end().to_string())
+                .collect()))
+            .collect()
+    }
+}
+
+/// The extent and number of postcodes of each outward code, to find the groups
+#[cfg(feature="packer")]
+#[derive(Debug, Clone)]
+pub(crate) struct OutwardExtents{
+    boxes: BTreeMap<String, (Point, Point, usize)>,
+    scheme: Scheme,
+}
+
+#[cfg(feature="packer")]
+impl OutwardExtents{
+    pub fn new(scheme: Scheme) -> Self{
+        Self{boxes: BTreeMap::new(), scheme}
+    }
+
+    /// Every entry is quantized in its group's box, so terminated postcodes and outward codes
+    /// count too
+    pub fn add(&mut self, p: &PostcodeInfo){
+        let outward = format!("{:<4}", self.scheme.codec().prefix(&p.postcode));
+        let l = p.location;
+        let (min, max, count) = self.boxes.entry(outward).or_insert((l, l, 0));
+        *min = Point{x: min.x.min(l.x), y: min.y.min(l.y)};
+        *max = Point{x: max.x.max(l.x), y: max.y.max(l.y)};
+        *count += 1;
+    }
+
+    /// Join up the outward codes that are near each other in to groups
+    pub fn groups(&self) -> Groups{
+        let boxes: Vec<(&String, &(Point, Point, usize))> = self.boxes.iter().collect();
+        if boxes.is_empty(){
+            return Groups::default();
+        }
+        // Each cluster starts as one outward code, and takes in the ones near it
+        let mut cluster: Vec<usize> = (0..boxes.len()).collect();
+        fn root(cluster: &mut [usize], mut i: usize) -> usize{
+            while cluster[i] != i{
+                cluster[i] = cluster[cluster[i]];
+                i = cluster[i];
+            }
+            i
+        }
+        // A degree of latitude is always about 111 km, so most pairs can be skipped quickly
+        let max_dlat = MIN_GAP_M / 110_000.0;
+        for i in 0..boxes.len(){
+            for j in i+1..boxes.len(){
+                let (a, b) = (boxes[i].1, boxes[j].1);
+                if gap_degrees(a.0.y, a.1.y, b.0.y, b.1.y) > max_dlat || gap_m((a.0, a.1), (b.0, b.1)) >= MIN_GAP_M{
+                    continue;
+                }
+                let (ri, rj) = (root(&mut cluster, i), root(&mut cluster, j));
+                cluster[ri.max(rj)] = ri.min(rj);
+            }
+        }
+        // The box and number of postcodes of each cluster, by its first outward code
+        let mut clusters: BTreeMap<usize, (Point, Point, usize, Vec<usize>)> = BTreeMap::new();
+        for (i, (_, &(min, max, count))) in boxes.iter().enumerate(){
+            let c = clusters.entry(root(&mut cluster, i)).or_insert((min, max, 0, Vec::new()));
+            c.0 = Point{x: c.0.x.min(min.x), y: c.0.y.min(min.y)};
+            c.1 = Point{x: c.1.x.max(max.x), y: c.1.y.max(max.y)};
+            c.2 += count;
+            c.3.push(i);
+        }
+        let mut clusters: Vec<(Point, Point, usize, Vec<usize>)> = clusters.into_values().collect();
+        // Too many, so join the two whose boxes are nearest each other
+        while clusters.len() > MAX_GROUPS{
+            let mut nearest = (f64::INFINITY, 0, 0);
+            for i in 0..clusters.len(){
+                for j in i+1..clusters.len(){
+                    let d = gap_m((clusters[i].0, clusters[i].1), (clusters[j].0, clusters[j].1));
+                    if d < nearest.0{
+                        nearest = (d, i, j);
+                    }
+                }
+            }
+            let (_, i, j) = nearest;
+            let (min, max, count, members) = clusters.remove(j);
+            let c = &mut clusters[i];
+            c.0 = Point{x: c.0.x.min(min.x), y: c.0.y.min(min.y)};
+            c.1 = Point{x: c.1.x.max(max.x), y: c.1.y.max(max.y)};
+            c.2 += count;
+            c.3.extend(members);
+        }
+        // The main group has the most postcodes, ties go to the first
+        let main = (0..clusters.len()).max_by_key(|&i| (clusters[i].2, std::cmp::Reverse(i))).unwrap();
+        let main = clusters.remove(main);
+        clusters.insert(0, main);
+
+        let mut groups = Groups{scheme: self.scheme, ..Default::default()};
+        for (g, (min, max, _, members)) in clusters.iter().enumerate(){
+            // A group with one location still needs a box with some size
+            let edges = |lo: f64, hi: f64, range: f64| {
+                let (lo, hi) = (quantize_extent(lo, range, false), quantize_extent(hi, range, true));
+                if hi > lo { (lo, hi) } else if hi < u32::MAX { (lo, hi + 1) } else { (lo - 1, hi) }
+            };
+            let (minlong, maxlong) = edges(min.x, max.x, 180.0);
+            let (minlat, maxlat) = edges(min.y, max.y, 90.0);
+            groups.boxes.push([minlong, maxlong, minlat, maxlat]);
+            if g > 0{
+                for &m in members{
+                    groups.members.insert(boxes[m].0.clone(), g as u8);
+                }
+            }
+        }
+        groups
+    }
+}
+
+/// Gap between two ranges, 0 if they overlap
+#[cfg(feature="packer")]
+fn gap_degrees(min_a: f64, max_a: f64, min_b: f64, max_b: f64) -> f64{
+    (min_b - max_a).max(min_a - max_b).max(0.0)
+}
+
+/// Roughly the shortest distance between two boxes in metres, 0 if they overlap
+#[cfg(feature="packer")]
+fn gap_m(a: (Point, Point), b: (Point, Point)) -> f64{
+    let dlong = gap_degrees(a.0.x, a.1.x, b.0.x, b.1.x);
+    let dlat = gap_degrees(a.0.y, a.1.y, b.0.y, b.1.y);
+    // The gap is measured where the boxes are nearest the equator, where it's widest
+    let lat = a.0.y.abs().min(b.0.y.abs()).min(a.1.y.abs()).min(b.1.y.abs());
+    distance_m(&Point{x: 0.0, y: lat}, &Point{x: dlong, y: lat + dlat})
+}
@@ -0,0 +1,154 @@
+/*
+
+Opening the input file.
+
+The ONS postcode database is distributed as a zip file, which contains the full CSV file along
+with the same data split in to one CSV file per area, and some lookup tables. The input can be
+the zip file, a gzip compressed CSV file, or a plain CSV file. The type is detected from the
+start of the file, not from its name.
+
+Code-Point Open is distributed as a zip file too, with one CSV file per postcode area in
+`Data/CSV`. The input can be the zip file, or the directory it was extracted to.
+
+The month a release of the ONS postcode database came out is only in the names of its files,
+such as `ONSPD_FEB_2025.zip` and `Data/ONSPD_FEB_2025_UK.csv`. The dates in the data are when
+each postcode was introduced, so the newest of them is usually a month or two before the release.
+
+*/
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use time::{Date, OffsetDateTime};
+use crate::{PostcodeError, InputProblem, input_error};
+
+/// Call `f` with a reader for the CSV data in the named file
+pub(crate) fn with_input<T, F: FnOnce(&mut dyn Read) -> Result<T, PostcodeError>>(path: &str, f: F) -> Result<T, PostcodeError>{
+    let mut file = File::open(path)?;
+    let mut magic = [0u8;4];
+    let n = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    match &magic[..n]{
+        [0x1f, 0x8b, ..] => f(&mut flate2::read::MultiGzDecoder::new(BufReader::new(file))),
+        [b'P', b'K', 3, 4] => {
+            let mut archive = zip::ZipArchive::new(BufReader::new(file)).map_err(zip_err)?;
+            let mut files = Vec::new();
+            for i in 0..archive.len(){
+                let entry = archive.by_index(i).map_err(zip_err)?;
+                if entry.is_file(){
+                    files.push((entry.name().map_err(zip_err)?.to_string(), entry.size()));
+                }
+            }
+            let i = choose_csv(&files).ok_or_else(|| input_error(None, None, InputProblem::NoCsvFile))?;
+            let mut csv = archive.by_index(i).map_err(zip_err)?;
+            f(&mut csv)
+        },
+        _ => f(&mut file),
+    }
+}
+
+/// Release date of an ONS postcode database file, from its name, or from the name of the full
+/// CSV file in it if it's a zip. `None` if neither names a month.
+pub(crate) fn release_date(path: &str) -> Option<Date>{
+    let name = Path::new(path).file_name()?.to_string_lossy().to_string();
+    if let Some(date) = name_release(&name){
+        return Some(date);
+    }
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path).ok()?)).ok()?;
+    let mut files = Vec::new();
+    for i in 0..archive.len(){
+        let entry = archive.by_index(i).ok()?;
+        if entry.is_file(){
+            files.push((entry.name().ok()?.to_string(), entry.size()));
+        }
+    }
+    name_release(&files[choose_csv(&files)?].0)
+}
+
+/// First day of the month in a file name, such as `ONSPD_FEB_2025_UK.csv` or `ONSPD_2025-02.zip`
+/// (the name that `fetch` gives the files it downloads)
+fn name_release(name: &str) -> Option<Date>{
+    let words: Vec<&str> = name.split(['_', '.', '/', ' ']).collect();
+    let year = |w: &str| w.parse::<i32>().ok().filter(|_| w.len() == 4);
+    for (i, word) in words.iter().enumerate(){
+        let found = match (word.split_once('-'), words.get(i + 1)){
+            (Some((y, m)), _) if m.len() == 2 => year(y).zip(m.parse::<u8>().ok()),
+            (None, Some(next)) => year(next).zip(month_number(word)),
+            _ => None,
+        };
+        if let Some((year, month)) = found{
+            return Date::from_calendar_date(year, month.try_into().ok()?, 1).ok();
+        }
+    }
+    None
+}
+
+/// 1 to 12, from a month's name, or its first three letters, in any case
+fn month_number(name: &str) -> Option<u8>{
+    const MONTHS: [&str;12] = ["january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november", "december"];
+    let name = name.to_ascii_lowercase();
+    let i = MONTHS.iter().position(|m| name.len() >= 3 && m.starts_with(&name))?;
+    Some(i as u8 + 1)
+}
+
+fn zip_err(e: zip::result::ZipError) -> PostcodeError{
+    PostcodeError::IOError(std::io::Error::other(e))
+}
+
+/// Pick the full postcode CSV file from the list of (name, size) of the files in a zip.
+///
+/// The full file is named like `Data/ONSPD_MAY_2025_UK.csv`. If there's no file with a name
+/// like that, the largest CSV file is used, ignoring the per-area files in `multi_csv`.
+fn choose_csv(files: &[(String, u64)]) -> Option<usize>{
+    let candidates: Vec<usize> = (0..files.len())
+        .filter(|i| {
+            let name = files[*i].0.to_ascii_lowercase();
+            name.ends_with(".csv") && !name.contains("multi_csv/")
+        })
+        .collect();
+    candidates.iter().copied()
+        .find(|i| files[*i].0.to_ascii_lowercase().ends_with("_uk.csv"))
+        .or_else(|| candidates.iter().copied().max_by_key(|i| files[*i].1))
+}
+
+/// Call `f` for each of the Code-Point Open CSV files in a directory or zip file, in name order,
+/// with the date that the file was last modified
+pub(crate) fn with_codepoint_inputs<F: FnMut(&mut dyn Read, Option<Date>) -> Result<(), PostcodeError>>(path: &str, mut f: F) -> Result<(), PostcodeError>{
+    if Path::new(path).is_dir(){
+        let csv_dir = Path::new(path).join("Data").join("CSV");
+        let dir = if csv_dir.is_dir() { csv_dir } else { PathBuf::from(path) };
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)?{
+            let p = entry?.path();
+            if p.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")){
+                files.push(p);
+            }
+        }
+        files.sort();
+        for p in files{
+            let modified = std::fs::metadata(&p)?.modified().ok().map(|m| OffsetDateTime::from(m).date());
+            f(&mut BufReader::new(File::open(&p)?), modified)?;
+        }
+        return Ok(());
+    }
+
+    let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?)).map_err(zip_err)?;
+    let mut files = Vec::new();
+    for i in 0..archive.len(){
+        let entry = archive.by_index(i).map_err(zip_err)?;
+        let name = entry.name().map_err(zip_err)?.to_ascii_lowercase();
+        if entry.is_file() && name.contains("data/csv/") && name.ends_with(".csv"){
+            files.push((name, i));
+        }
+    }
+    if files.is_empty(){
+        return Err(input_error(None, None, InputProblem::NoCsvFile));
+    }
+    files.sort();
+    for (_, i) in files{
+        let mut entry = archive.by_index(i).map_err(zip_err)?;
+        let modified = entry.last_modified()
+            .and_then(|m| Date::from_calendar_date(m.year() as i32, m.month().try_into().ok()?, m.day()).ok());
+        f(&mut entry, modified)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+/*
+
+K-d tree spatial index, from format version 18 onwards.
+
+Every postcode that is in use (not an outward code, and not terminated) is a point in the tree,
+with its location quantized to 16 bits in the file's bounding box (as for the grid index), and
+the entry in the postcode data that it came from. A reader can find the nearest points to a
+location without decoding any prefix blocks, and then only decodes the blocks of the points
+that it needs, to get their postcodes and exact locations.
+
+K-d tree spatial index section, variable length (at the end of the file):
+
+    count:  4 bytes (u32, number of points)
+    points: count*10 bytes, in tree order
+        long:  2 bytes (u16, quantized to 16 bits in the file's bounding box)
+        lat:   2 bytes (u16)
+        block: 2 bytes (u16, position of the postcode's prefix in the quick lookup table)
+        entry: 4 bytes (u32, which entry of the block the postcode is, counting from 0. Outward
+                        codes and terminated postcodes are entries, and so is each postcode in
+                        a run)
+
+The tree is implicit in the order of the points. The points from `start` up to (not including)
+`end` are a subtree, and its root is the point at `mid = (start + end) / 2`. The points from
+`start` to `mid` are its left subtree, and the points from `mid + 1` to `end` are its right
+subtree. The whole tree is the subtree from 0 to count. A subtree at an even depth (the whole
+tree is depth 0) is split by longitude: no point in the left subtree has a greater long than the
+root, and no point in the right subtree has a smaller one. Odd depths are split by latitude.
+
+The quantized location is within one step (1/65535 of the bounding box) of the postcode's
+location in each direction, so a search must allow for that before it decodes the postcode.
+
+*/
+#[cfg(feature="packer")]
+use crate::LUT_SIZE;
+#[cfg(feature="packer")]
+use crate::spatial::IndexPoint;
+
+/// Length of a point in the index section
+const POINT_LEN: usize = 10;
+
+/// A postcode in the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KdPoint{
+    pub long: u16,
+    pub lat: u16,
+    pub block: u16,
+    pub entry: u32,
+}
+
+impl KdPoint{
+    fn axis(&self, depth: u32) -> u16{
+        if depth.is_multiple_of(2) { self.long } else { self.lat }
+    }
+}
+
+/// A subtree, and the quantized box that all of its points are in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Node{
+    pub start: usize,
+    pub end: usize,
+    pub depth: u32,
+    /// Lower left corner, (long, lat)
+    pub min: (u16, u16),
+    /// Upper right corner, (long, lat)
+    pub max: (u16, u16),
+}
+
+impl Node{
+    pub fn mid(&self) -> usize{
+        (self.start + self.end) / 2
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KdTree{
+    points: Vec<KdPoint>,
+}
+
+/// Put the points in tree order, splitting at the median at each level
+#[cfg(feature="packer")]
+fn arrange(points: &mut [KdPoint], depth: u32){
+    if points.len() <= 1{
+        return;
+    }
+    let mid = points.len() / 2;
+    // Ties are broken by the other fields, so that the order is always the same
+    points.select_nth_unstable_by_key(mid, |p| (p.axis(depth), p.axis(depth + 1), p.block, p.entry));
+    let (left, right) = points.split_at_mut(mid);
+    arrange(left, depth + 1);
+    arrange(&mut right[1..], depth + 1);
+}
+
+impl KdTree{
+    /// Build a tree from every entry of the file, in file order
+    #[cfg(feature="packer")]
+    pub(crate) fn build<I: Iterator<Item=IndexPoint>>(entries: I) -> Self{
+        let mut next_entry = vec![0u32; LUT_SIZE];
+        let mut points: Vec<KdPoint> = entries
+            .filter_map(|p| {
+                let entry = next_entry[p.block];
+                next_entry[p.block] += 1;
+                (!p.is_partial && !p.terminated).then_some(KdPoint{long: p.long, lat: p.lat, block: p.block as u16, entry})
+            })
+            .collect();
+        arrange(&mut points, 0);
+        Self{points}
+    }
+
+    #[cfg(feature="packer")]
+    pub(crate) fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(4 + (self.points.len() * POINT_LEN));
+        out.extend_from_slice(&(self.points.len() as u32).to_le_bytes());
+        for p in &self.points{
+            out.extend_from_slice(&p.long.to_le_bytes());
+            out.extend_from_slice(&p.lat.to_le_bytes());
+            out.extend_from_slice(&p.block.to_le_bytes());
+            out.extend_from_slice(&p.entry.to_le_bytes());
+        }
+        out
+    }
+
+    /// Parse the index section, returns `None` if it is damaged
+    pub(crate) fn from_bytes(data: &[u8]) -> Option<Self>{
+        let count = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+        let points = data.get(4..)?;
+        if points.len() != count.checked_mul(POINT_LEN)?{
+            return None;
+        }
+        let u16_at = |p: &[u8], i: usize| u16::from_le_bytes([p[i], p[i+1]]);
+        let points = points.chunks_exact(POINT_LEN)
+            .map(|p| KdPoint{
+                long: u16_at(p, 0),
+                lat: u16_at(p, 2),
+                block: u16_at(p, 4),
+                entry: u32::from_le_bytes(p[6..10].try_into().unwrap()),
+            })
+            .collect();
+        Some(Self{points})
+    }
+
+    /// Number of postcodes in the tree
+    pub fn len(&self) -> usize{
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.points.is_empty()
+    }
+
+    pub(crate) fn points(&self) -> &[KdPoint]{
+        &self.points
+    }
+
+    /// The whole tree, `None` if it is empty
+    pub(crate) fn root(&self) -> Option<Node>{
+        (!self.points.is_empty()).then_some(Node{start: 0, end: self.points.len(), depth: 0, min: (0, 0), max: (u16::MAX, u16::MAX)})
+    }
+
+    /// The left and right subtrees of a node, either can be `None` if it has no points
+    pub(crate) fn children(&self, node: &Node) -> (Option<Node>, Option<Node>){
+        let mid = node.mid();
+        let split = self.points[mid].axis(node.depth);
+        let mut left = Node{start: node.start, end: mid, depth: node.depth + 1, ..*node};
+        let mut right = Node{start: mid + 1, end: node.end, depth: node.depth + 1, ..*node};
+        if node.depth.is_multiple_of(2){
+            left.max.0 = split;
+            right.min.0 = split;
+        }
+        else{
+            left.max.1 = split;
+            right.min.1 = split;
+        }
+        ((left.start < left.end).then_some(left), (right.start < right.end).then_some(right))
+    }
+}
@@ -0,0 +1,855 @@
+#![allow(non_upper_case_globals)]
+#![allow(non_snake_case)]
+/*
+
+Library that converts the postcode database .csv file from the Office for National Statistics (ONS)
+in to a packed binary format that is much more compact and quick to search. The packed format can
+be read using the javascript library provided.
+
+Typical use:
+
+    let packer = Packer::from_csv("ONSPD.csv", &["BT"])?;
+    packer.write("postcodes.pack")?;
+
+The packer is behind the `packer` feature (see packer.rs), which is on by default. Without it,
+this is just the reader.
+
+*/
+use std::io::Write;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+pub mod reader;
+pub mod compression;
+pub mod spatial;
+pub mod kdtree;
+pub mod geohash;
+pub mod osgb;
+#[cfg(feature="packer")]
+pub mod stream;
+pub mod diff;
+#[cfg(feature="packer")]
+pub mod patch;
+pub mod country;
+pub mod attribute;
+pub mod extent;
+pub mod sector;
+pub mod metadata;
+pub mod outlier;
+pub mod centroid;
+#[cfg(feature="packer")]
+pub mod vectors;
+#[cfg(feature="packer")]
+pub mod output;
+#[cfg(feature="packer")]
+pub mod repack;
+pub mod scheme;
+#[cfg(feature="packer")]
+pub mod geocode;
+pub mod region;
+#[cfg(feature="fetch")]
+pub mod fetch;
+#[cfg(feature="sign")]
+pub mod signature;
+#[cfg(feature="serve")]
+pub mod serve;
+#[cfg(feature="packer")]
+mod input;
+#[cfg(feature="packer")]
+mod packer;
+#[cfg(feature="packer")]
+mod entropy;
+mod local;
+mod group;
+mod section;
+pub mod suggest;
+pub mod remote;
+pub mod order;
+#[cfg(feature="packer")]
+pub mod duplicate;
+#[cfg(feature="packer")]
+pub mod summary;
+#[cfg(feature="packer")]
+pub mod anomaly;
+pub mod crs;
+pub mod words;
+pub use reader::{Reader, FileStats, Validity};
+pub use country::Country;
+pub use centroid::{Centroid, CentroidLevel};
+pub use compression::Compression;
+pub use scheme::{Scheme, CodeCodec};
+pub use metadata::Metadata;
+pub use outlier::Outlier;
+pub use order::RecordOrder;
+pub use crs::Crs;
+#[cfg(feature="packer")]
+pub use packer::*;
+#[cfg(feature="packer")]
+pub use output::OutputFormat;
+#[cfg(feature="packer")]
+pub use stream::StreamPacker;
+#[cfg(feature="packer")]
+pub use duplicate::OnDuplicate;
+#[cfg(feature="packer")]
+pub use summary::InputSummary;
+#[cfg(feature="packer")]
+pub use anomaly::Anomaly;
+
+#[derive(Debug)]
+pub enum PostcodeError{
+    IOError(std::io::Error),
+    InputMalformed(InputError),
+    InvalidFormat(),
+    NotFound(),
+    NotAPackFile(),
+    UnsupportedVersion(u32),
+    VerifyFailed(usize),
+    IncompatibleOptions(&'static str),
+    NotAPatchFile(),
+    PatchDoesNotApply(),
+    DownloadFailed(String),
+    ReleaseNotFound(String),
+    /// Expected and found SHA-256
+    ChecksumMismatch(String, String),
+    /// Which part of a pack file failed its checksum
+    Corrupt(&'static str),
+    BadKey(String),
+    /// Why the signature was rejected
+    BadSignature(&'static str),
+    /// Why a boundary file couldn't be read
+    BadBoundary(String),
+    /// Kind of a required section that this version doesn't know (version 26 onwards)
+    UnsupportedSection(u16),
+}
+
+/// What was wrong with the input file, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputError{
+    /// Line of the input file, counting the header row as line 1, if the problem is on one line
+    pub line: Option<u64>,
+    /// Name of the column, or its number (from 1) in files without a header row
+    pub field: Option<String>,
+    pub reason: InputProblem,
+}
+
+/// Why the input file could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputProblem{
+    /// A column that is needed is not in the header row, with the names that were looked for
+    MissingColumn(String),
+    /// A field that should be a number, with its value
+    BadNumber(String),
+    /// A date that is not in the form YYYYMM, with its value
+    BadDate(String),
+    /// A row that can't be read as CSV, and why
+    BadRow(String),
+    /// A zip file that doesn't have the postcode CSV file in it
+    NoCsvFile,
+    /// A postcode that is in more than one row, see `ReadOptions::on_duplicate`
+    DuplicatePostcode(String),
+    /// Postcodes outside the usual region of their area, see anomaly.rs, with how many there are
+    Anomalies(usize),
+}
+
+#[cfg(feature="packer")]
+pub(crate) fn input_error(line: Option<u64>, field: Option<String>, reason: InputProblem) -> PostcodeError{
+    PostcodeError::InputMalformed(InputError{line, field, reason})
+}
+
+impl Display for InputError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        use InputProblem::*;
+        let mut place = Vec::new();
+        if let Some(line) = self.line{
+            place.push(format!("line {line}"));
+        }
+        if let Some(field) = &self.field{
+            place.push(format!("column {field}"));
+        }
+        if !place.is_empty(){
+            write!(f, "{}: ", place.join(", "))?;
+        }
+        match &self.reason{
+            MissingColumn(names) => write!(f, "there is no {names} column"),
+            BadNumber(value) => write!(f, "{value:?} is not a number"),
+            BadDate(value) => write!(f, "{value:?} is not a date in the form YYYYMM"),
+            BadRow(why) => write!(f, "{why}"),
+            NoCsvFile => write!(f, "there is no postcode CSV file in the zip file"),
+            DuplicatePostcode(postcode) => write!(f, "{postcode} is in more than one row"),
+            Anomalies(1) => write!(f, "a postcode is outside the usual region of its area"),
+            Anomalies(n) => write!(f, "{n} postcodes are outside the usual region of their areas"),
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy)]
+pub struct Point{
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Mean radius of the earth, in metres
+pub const EARTH_RADIUS_M: f64 = 6371000.0;
+
+impl Point{
+    /// Great circle distance to another point, in metres, treating `x` as longitude and `y` as
+    /// latitude
+    pub fn haversine_to(&self, other: &Point) -> f64{
+        let dlat = (other.y - self.y).to_radians();
+        let dlong = (other.x - self.x).to_radians();
+        let h = (dlat / 2.0).sin().powi(2)
+            + (self.y.to_radians().cos() * other.y.to_radians().cos() * (dlong / 2.0).sin().powi(2));
+        2.0 * EARTH_RADIUS_M * h.sqrt().atan2((1.0 - h).sqrt())
+    }
+}
+
+/// Great circle distance between two locations, in metres
+pub fn distance_m(a: &Point, b: &Point) -> f64{
+    a.haversine_to(b)
+}
+
+#[derive(Debug, Clone)]
+pub struct PostcodeInfo{
+    pub postcode: String,
+    pub location: Point,
+    pub is_partial: bool,
+    /// Year that the postcode was terminated, `None` if it is still in use
+    pub terminated: Option<u16>,
+    pub country: Option<Country>,
+    /// Month that the postcode was introduced, as a unix timestamp of the 1st, if the input file
+    /// has it. This isn't stored in pack files.
+    pub introduced: Option<u64>,
+    /// Values of the attributes chosen with `ReadOptions::attributes` (or in the pack file it was
+    /// read from), in the same order. An empty string is no value, and outward codes have none.
+    pub attributes: Vec<String>,
+}
+
+impl Display for PostcodeError{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        use PostcodeError::*;
+        match self{
+            IOError(e) => write!(f,"Error reading or writing postcode file: {e}"),
+            InputMalformed(e) => write!(f, "Input file is not well formed: {e}"),
+            InvalidFormat() => write!(f, "Postcode format not recognised"),
+            NotFound() => write!(f, "Postcode is well-formed, but not known"),
+            NotAPackFile() => write!(f, "File is not a postcode pack file, or is damaged"),
+            UnsupportedVersion(v) => write!(f, "Postcode pack file format version {v} is not supported"),
+            VerifyFailed(n) => write!(f, "Pack file failed verification, {n} postcodes did not match the input"),
+            IncompatibleOptions(why) => write!(f, "Incompatible options: {why}"),
+            NotAPatchFile() => write!(f, "File is not a postcode patch file, or is damaged"),
+            PatchDoesNotApply() => write!(f, "Patch was made for a different pack file, or could not recreate the new pack file"),
+            DownloadFailed(why) => write!(f, "Download failed: {why}"),
+            ReleaseNotFound(name) => write!(f, "Could not find the {name} release of the ONS postcode database"),
+            ChecksumMismatch(expected, found) => write!(f, "Downloaded file has SHA-256 {found}, expected {expected}"),
+            Corrupt(part) => write!(f, "Pack file is corrupt or truncated, the checksum of the {part} does not match"),
+            BadKey(why) => write!(f, "Could not read key: {why}"),
+            BadSignature(why) => write!(f, "Signature is not valid: {why}"),
+            BadBoundary(why) => write!(f, "Could not read boundary: {why}"),
+            UnsupportedSection(kind) => write!(f, "Postcode pack file has a section of kind {kind}, which is needed to read it but not supported"),
+        }
+    }
+}
+
+impl std::error::Error for PostcodeError{}
+
+impl From<std::io::Error> for PostcodeError{
+    fn from(e: std::io::Error) -> Self { PostcodeError::IOError(e) }
+}
+
+fn encode_AZ(x:u8) -> Result<u32, PostcodeError> {
+    if x.is_ascii_uppercase(){
+        Ok((x-b'A') as u32)
+    }
+    else {
+        Err(PostcodeError::InvalidFormat())
+    }
+}
+
+fn encode_09(x:u8) -> Result<u32, PostcodeError> {
+    if x.is_ascii_digit(){
+        Ok((x-b'0') as u32)
+    }
+    else {
+        Err(PostcodeError::InvalidFormat())
+    }
+}
+
+fn encode_AZ09(x:u8) -> Result<u32, PostcodeError> {
+    encode_AZ(x).or_else(|_|Ok(encode_09(x)?+26))
+}
+
+fn encode_AZ09_space(x:u8) -> Result<u32, PostcodeError> {
+    if x == b' '{ Ok(36) } else{ encode_AZ(x).or_else(|_|Ok(encode_09(x)?+26)) }
+}
+
+
+pub fn pack_outward_code(code: &str) -> Result<[u8;3], PostcodeError>{
+    if code.len() < 7{
+        return Err(PostcodeError::InvalidFormat());
+    }
+
+    let mut chars = code.as_bytes().iter();
+
+    // Skip the first two chars
+    let _a = encode_AZ(*chars.next().unwrap())?;
+    let _b = encode_AZ09(*chars.next().unwrap())?;
+
+    // Encode the rest
+    let c = 37*encode_AZ09_space(*chars.next().unwrap())?;
+    let d = encode_AZ09_space(*chars.next().unwrap())?;
+    let encoded = c + d;
+    assert!(encoded < 2_u32.pow(16));
+    let encoded = encoded.to_le_bytes();
+    Ok([
+        encoded[0],
+        encoded[1],
+        encoded[2],
+    ])
+}
+
+pub fn pack_code(code: &str) -> Result<[u8;3], PostcodeError>{
+    if code.len() < 7{
+        return Err(PostcodeError::InvalidFormat());
+    }
+
+    let mut chars = code.as_bytes().iter();
+
+    // Skip the first two chars
+    let _a = encode_AZ(*chars.next().unwrap())?;
+    let _b = encode_AZ09(*chars.next().unwrap())?;
+
+    // Encode the rest
+    let c = 26*26*10*37*encode_AZ09_space(*chars.next().unwrap())?;
+    let d = 26*26*10*encode_AZ09_space(*chars.next().unwrap())?;
+
+    let e = 26*26*encode_09(*chars.next().unwrap())?;
+    let f = 26*encode_AZ(*chars.next().unwrap())?;
+    let g = encode_AZ(*chars.next().unwrap())?;
+    let encoded = c + d + e + f + g;
+    assert!(encoded < 2_u32.pow(24));
+    let encoded = encoded.to_le_bytes();
+    Ok([
+        encoded[0],
+        encoded[1],
+        encoded[2]
+    ])
+}
+
+fn decode_AZ09_space(x: u32) -> char{
+    match x{
+        0..=25 => (b'A' + x as u8) as char,
+        26..=35 => (b'0' + (x-26) as u8) as char,
+        _ => ' ',
+    }
+}
+
+/// Reverse of `pack_code`, needs the first two chars of the postcode since they are not packed
+pub fn unpack_code(prefix: &str, code: u32) -> String{
+    let g = code % 26;
+    let f = (code / 26) % 26;
+    let e = (code / (26*26)) % 10;
+    let d = (code / (26*26*10)) % 37;
+    let c = code / (26*26*10*37);
+    format!("{}{}{}{}{}{}",
+        prefix,
+        decode_AZ09_space(c),
+        decode_AZ09_space(d),
+        (b'0' + e as u8) as char,
+        (b'A' + f as u8) as char,
+        (b'A' + g as u8) as char,
+    )
+}
+
+/// Reverse of `pack_outward_code`, the result is padded to 7 chars to match the input format
+pub fn unpack_outward_code(prefix: &str, code: u32) -> String{
+    let d = code % 37;
+    let c = code / 37;
+    format!("{}{}{}   ", prefix, decode_AZ09_space(c), decode_AZ09_space(d))
+}
+
+/// Convert a postcode, or an outward code by itself, in to canonical form.
+///
+/// Case and spaces are ignored. Full postcodes become 7 chars long, with the outward code
+/// aligned left and the inward code aligned right. Outward codes are padded to 4 chars.
+/// This matches `format_postcode` in the javascript library.
+pub fn format_postcode(pc: &str) -> Result<String, PostcodeError>{
+    if !pc.chars().all(|c| c == ' ' || c.is_ascii_alphanumeric()){
+        return Err(PostcodeError::InvalidFormat());
+    }
+    let code: String = pc.chars().filter(|c| *c != ' ').map(|c| c.to_ascii_uppercase()).collect();
+    let numchars = code.len();
+    if !(2..=7).contains(&numchars){
+        return Err(PostcodeError::InvalidFormat());
+    }
+    if numchars <= 4{
+        // is only the outward code, just pad with spaces
+        return Ok(format!("{:<4}", code));
+    }
+    let (outward, inward) = code.split_at(numchars-3);
+    Ok(format!("{:<4}{}", outward, inward))
+}
+
+/// Convert a full postcode from an input file in to canonical form, whichever variant it's in:
+/// 7 chars (`pcd`), 8 chars (`pcd2`), variable length (`pcds`), or with any other spacing.
+///
+/// `None` if it isn't 5 to 7 letters and digits, not counting whitespace.
+pub fn normalize_postcode(pc: &str) -> Option<String>{
+    let pc: String = pc.chars().filter(|c| !c.is_whitespace()).collect();
+    if !(5..=7).contains(&pc.len()){
+        return None;
+    }
+    format_postcode(&pc).ok()
+}
+
+/// The file format version written by default
+pub const DEFAULT_VERSION: u32 = 2;
+
+/// All of the file format versions that can be written
+pub const WRITABLE_VERSIONS: [u32;25] = [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26];
+
+/// The newest file format version, which `repack::upgrade` writes
+pub const NEWEST_VERSION: u32 = WRITABLE_VERSIONS[WRITABLE_VERSIONS.len() - 1];
+
+/// Bits per axis of the quantized locations, before version 16 it is always 16
+pub const DEFAULT_RESOLUTION: u32 = 16;
+
+/// All of the resolutions that can be written, version 16 onwards
+pub const RESOLUTIONS: [u32;4] = [8, 12, 16, 24];
+
+/// Quantize a coordinate in the range `-range..=range` to a u32, rounding down
+/// (or up if `round_up`) so that the result covers the original value
+#[cfg(feature="packer")]
+pub(crate) fn quantize_extent(x: f64, range: f64, round_up: bool) -> u32{
+    let q = ((x + range) / (2.0 * range)) * (u32::MAX as f64);
+    let q = if round_up { q.ceil() } else { q.floor() };
+    q.clamp(0.0, u32::MAX as f64) as u32
+}
+
+/// Reverse of `quantize_extent`
+pub(crate) fn dequantize_extent(q: u32, range: f64) -> f64{
+    ((q as f64 / u32::MAX as f64) * (2.0 * range)) - range
+}
+
+/// Length of the prefix that postcodes are grouped in to blocks by, see `LUT_SIZE`
+pub(crate) const PREFIX_LEN: usize = 2;
+
+/// Number of entries in the quick lookup table, one for each possible two char prefix.
+///
+/// The prefix is always the first two chars of the canonical code, whatever shape its outward
+/// code is, so it isn't always the postcode area. For two letter areas it is the whole area
+/// ("SW" for SW1A 1AA), but for the one letter areas (B, E, G, L, M, N, S and W) it is the area
+/// and the first digit of the district ("B1" for B1 1AA, B10 0AA and B19 9ZZ), and a prefix of
+/// just "B" never happens. Eircode routing keys are a letter and a digit ("D6" for D6W). So the
+/// first char is always a letter, and the second a letter or a digit, which is 26*36 prefixes.
+pub(crate) const LUT_SIZE: usize = 26*36;
+
+/// Room for the fields of the header before version 26, which are all short except the metadata
+#[cfg(feature="packer")]
+const HEADER_CAPACITY: usize = 1024;
+
+/// Position of a two char prefix in the quick lookup table, only the first two chars of
+/// `prefix` are used. `None` if it isn't a letter followed by a letter or a digit.
+pub(crate) fn lut_index(prefix: &[u8]) -> Option<usize>{
+    let c1 = encode_AZ(*prefix.first()?).ok()? as usize;
+    let c2 = *prefix.get(1)?;
+    let c2 = match c2{
+        b'0'..=b'9' => c2 - b'0',
+        b'A'..=b'Z' => 10 + c2 - b'A',
+        _ => return None,
+    } as usize;
+    Some((c1*36)+c2)
+}
+
+/// The two char prefix at a position in the quick lookup table
+pub(crate) fn lut_prefix(index: usize) -> String{
+    let c1 = b'A' + (index / 36) as u8;
+    let c2 = (index % 36) as u8;
+    let c2 = if c2 > 9{ b'A'+c2-10 } else { b'0'+c2};
+    format!("{}{}", c1 as char, c2 as char)
+}
+
+/// A canonical full postcode with a single space between the outward and inward codes
+pub(crate) fn spaced_postcode(postcode: &str, scheme: Scheme) -> String{
+    // Canonical postcodes are padded, or have no space at all if the outward code is 4 chars
+    let outward = scheme.codec().prefix(postcode);
+    let inward = match scheme{
+        Scheme::Uk => &postcode[4..],
+        _ => &postcode[outward.len()..],
+    };
+    format!("{outward} {inward}")
+}
+
+/// Whether a canonical postcode starts with a prefix. A prefix of only letters is a whole
+/// postcode area, so "E" matches "E1  6AN" but not "EC1A1BB".
+#[cfg(feature="packer")]
+pub(crate) fn has_prefix(postcode: &str, prefix: &str) -> bool{
+    if prefix.chars().all(|c| c.is_ascii_alphabetic()){
+        let area_len = postcode.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(postcode.len());
+        postcode[..area_len] == *prefix
+    }
+    else{
+        postcode.starts_with(prefix)
+    }
+}
+
+#[cfg(feature="packer")]
+pub(crate) fn calc_ll(minll: Point, maxll: Point, ll: Point) -> (u16,u16){
+    let (long, lat) = quantize_ll(minll, maxll, ll, DEFAULT_RESOLUTION);
+    (long as u16, lat as u16)
+}
+
+/// Quantize a location to `resolution` bits per axis
+#[cfg(feature="packer")]
+pub(crate) fn quantize_ll(minll: Point, maxll: Point, ll: Point, resolution: u32) -> (u32,u32){
+    let max = max_quantized(resolution) as f64;
+    let latrange = maxll.y - minll.y;
+    let longrange = maxll.x - minll.x;
+    let lat = (((ll.y-minll.y)/latrange)*max).round().clamp(0.0, max) as u32;
+    let long = (((ll.x-minll.x)/longrange)*max).round().clamp(0.0, max) as u32;
+    (long,lat)
+}
+
+/// The location that a reader gets back from a quantized location
+pub(crate) fn dequantize_ll(minll: Point, maxll: Point, long: u32, lat: u32, resolution: u32) -> Point{
+    let max = max_quantized(resolution) as f64;
+    let lat = minll.y + ((maxll.y - minll.y) * (lat as f64 / max));
+    let long = minll.x + ((maxll.x - minll.x) * (long as f64 / max));
+    Point{x:long, y:lat}
+}
+
+/// A single packed postcode, as a record in one of the four record encodings or as part of a run
+pub enum DeltaPacked{
+    /// The location is 2 to 6 bytes, depending on the resolution (version 16 onwards), so these
+    /// have their length
+    Absolute([u8;10], u8),
+    DeltaP([u8;7], u8),
+    DeltaLL([u8;6]),
+    DeltaPLL([u8;3]),
+    /// A record in any of the encodings followed by the year of termination (version 6 onwards),
+    /// and the length of the record including the year
+    Terminated([u8;17], u8),
+    /// A record with the postcode and location deltas as varints (version 13 onwards), and its
+    /// length. The deltas are up to 5 bytes each, whatever the resolution.
+    Varint([u8;16], u8),
+    /// The first postcode of a run at the same location as the record before (version 12
+    /// onwards): the format byte, the length of the run minus one, and the postcode delta
+    Run([u8;3]),
+    /// Any other postcode in a run, just the postcode delta
+    RunDelta([u8;1]),
+}
+
+impl DeltaPacked{
+    pub fn write_to_file<W:Write>(&self, mut f:W) -> std::io::Result<()>{
+        f.write_all(self.bytes())
+    }
+
+    pub fn bytes(&self) -> &[u8]{
+        use DeltaPacked::*;
+        match self{
+            Absolute(a, len) => &a[..*len as usize],
+            DeltaP(a, len) => &a[..*len as usize],
+            DeltaLL(a) => a,
+            DeltaPLL(a) => a,
+            Terminated(a, len) => &a[..*len as usize],
+            Run(a) => a,
+            RunDelta(a) => a,
+            Varint(a, len) => &a[..*len as usize],
+        }
+    }
+}
+
+/// A postcode that is ready to pack, with its location quantized relative to the bounding box.
+///
+/// This is much smaller than a `PostcodeInfo`, for when all of the postcodes need to be
+/// kept in memory at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct QuantizedPostcode{
+    /// Canonical postcode, for sorting
+    pub postcode: [u8;7],
+    /// Packed code, from the scheme's `CodeCodec::encode` or `pack_outward_code`
+    pub code: [u8;3],
+    pub is_partial: bool,
+    pub long: u32,
+    pub lat: u32,
+    /// Year of termination, as stored in the file
+    pub terminated: Option<u8>,
+    pub country: Option<Country>,
+}
+
+impl QuantizedPostcode{
+    #[cfg(feature="packer")]
+    pub fn new(p: &PostcodeInfo, minll: Point, maxll: Point, resolution: u32, scheme: Scheme) -> Result<Self, PostcodeError>{
+        let code = if p.is_partial {
+            pack_outward_code(&p.postcode)?
+        } else {
+            scheme.codec().encode(&p.postcode)?
+        };
+        let (long, lat) = quantize_ll(minll, maxll, p.location, resolution);
+        Ok(Self{
+            // Encoding checks there are at least 7 chars
+            postcode: p.postcode.as_bytes()[0..7].try_into().unwrap(),
+            code,
+            is_partial: p.is_partial,
+            long,
+            lat,
+            terminated: p.terminated.map(|y| y.saturating_sub(TERMINATED_YEAR_BASE).min(255) as u8),
+            country: p.country,
+        })
+    }
+
+    pub fn prefix(&self) -> &str{
+        // Encoding only accepts ASCII here
+        std::str::from_utf8(&self.postcode[..PREFIX_LEN]).unwrap()
+    }
+}
+
+/// Termination years are stored as an offset from this year
+pub(crate) const TERMINATED_YEAR_BASE: u16 = 1900;
+
+pub(crate) use nearmypostcode_core::record::{RUN_FORMAT, VARINT_FORMAT, coord_len, max_quantized};
+
+/// Length of a record in the fixed size encodings, depending on which deltas fit
+pub(crate) fn fixed_record_len(pc_is_delta: bool, ll_is_delta: bool, coord_len: usize) -> usize{
+    match (pc_is_delta, ll_is_delta){
+        (false, false) => 4 + (2 * coord_len),
+        (true, false) => 1 + (2 * coord_len),
+        (false, true) => 6,
+        (true, true) => 3,
+    }
+}
+
+/// Append `x` as an unsigned LEB128 varint
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut x: u32){
+    while x >= 0x80{
+        out.push((x as u8 & 0x7f) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+/// Zigzag encoding, so that small negative numbers are small varints too (0, -1, 1, -2, 2, ...)
+fn zigzag(x: i32) -> u32{
+    ((x << 1) ^ (x >> 31)) as u32
+}
+
+/// A varint record for a full postcode, from the postcode delta (minus one) and the location deltas
+fn varint_record(delta: u32, dlat: i32, dlong: i32) -> DeltaPacked{
+    let mut bytes = vec![VARINT_FORMAT];
+    write_varint(&mut bytes, delta);
+    write_varint(&mut bytes, zigzag(dlat));
+    write_varint(&mut bytes, zigzag(dlong));
+    let mut packed = [0;16];
+    packed[..bytes.len()].copy_from_slice(&bytes);
+    DeltaPacked::Varint(packed, bytes.len() as u8)
+}
+
+/// Most postcodes in one run record
+const MAX_RUN: usize = 256;
+
+/// Postcode deltas (minus one) of the run at the start of `postcodes`: full, current postcodes at
+/// the same location as the record before, which had the code `last_code`
+fn location_run(postcodes: &[QuantizedPostcode], mut last_code: u32, lat: u32, long: u32) -> Vec<u8>{
+    let mut deltas = Vec::new();
+    for p in postcodes.iter().take(MAX_RUN){
+        let code = u32::from_le_bytes([p.code[0], p.code[1], p.code[2], 0]);
+        if p.is_partial || p.terminated.is_some() || (p.lat, p.long) != (lat, long)
+            || code <= last_code || code - last_code > 256{
+            break;
+        }
+        deltas.push((code - last_code - 1) as u8);
+        last_code = code;
+    }
+    deltas
+}
+
+/// Encode a single prefix block, starting from the initial state
+pub(crate) fn pack_block(postcodes: &[QuantizedPostcode], version: u32, resolution: u32) -> Result<Vec<DeltaPacked>, PostcodeError> {
+    // Version 6 uses a bit of the format byte to flag terminated postcodes, so there's one
+    // less bit for the postcode delta, and the outward code flag moves down
+    let has_flags = version >= 6;
+    let has_runs = version >= 12;
+    let has_varints = version >= 13;
+    let max_delta = if has_flags { 32 } else { 64 };
+    let partial_flag = if has_flags { 0x10 } else { 0x20 };
+    let coord_len = coord_len(resolution);
+    // Postcodes from a damaged file could be anywhere, the packer's own are always in range
+    let max = max_quantized(resolution);
+    if postcodes.iter().any(|p| p.lat > max || p.long > max){
+        return Err(PostcodeError::Corrupt("postcode data"));
+    }
+    let mut packed_codes = Vec::new();
+    let mut last_code:u32 = 0;
+    let mut last_lat:i32 = 0;
+    let mut last_long:i32 = 0;
+    let mut i = 0;
+    while i < postcodes.len(){
+        if has_runs && i > 0{
+            let run = location_run(&postcodes[i..], last_code, last_lat as u32, last_long as u32);
+            // Only use a run if it's smaller than the records it replaces
+            let separate: usize = run.iter().map(|&d| fixed_record_len((d as u32) < max_delta, true, coord_len)).sum();
+            if run.len() + 2 < separate{
+                packed_codes.push(DeltaPacked::Run([RUN_FORMAT, (run.len() - 1) as u8, run[0]]));
+                packed_codes.extend(run[1..].iter().map(|&d| DeltaPacked::RunDelta([d])));
+                i += run.len();
+                let c = postcodes[i-1].code;
+                last_code = u32::from_le_bytes([c[0], c[1], c[2], 0]);
+                continue;
+            }
+        }
+        let p = &postcodes[i];
+        i += 1;
+        let partial = p.is_partial;
+        let c = p.code;
+        let code_number = u32::from_le_bytes([c[0],c[1],c[2],0]);
+        let can_delta_encode_pc = (!p.is_partial) && {
+            if last_code >= code_number{
+                // List is probably not sorted, inefficient
+                // (or this is the very first code, which can't be stored as a delta of zero)
+                false
+            }
+            else{
+                (code_number - last_code) <= max_delta
+            }
+        };
+        let (long,lat) = (p.long, p.lat);
+        let dlong = (long as i32) - last_long;
+        let dlat = (lat as i32) - last_lat;
+        let can_delta_encode_ll: bool = (!p.is_partial) && {
+            let can_long = (-128..=127).contains(&dlong);
+            let can_lat = (-128..=127).contains(&dlat);
+            can_long && can_lat
+        };
+        // Absolute locations are as many bytes as the resolution needs, 2 bytes each before version 16
+        let mut ll = [0u8;6];
+        ll[..coord_len].copy_from_slice(&lat.to_le_bytes()[..coord_len]);
+        ll[coord_len..coord_len*2].copy_from_slice(&long.to_le_bytes()[..coord_len]);
+        let ll = &ll[..coord_len*2];
+
+        // A varint record is used if it's smaller than the fixed size record would be
+        let varint = (has_varints && !partial && code_number > last_code)
+            .then(|| varint_record(code_number - last_code - 1, dlat, dlong))
+            .filter(|v| v.bytes().len() < fixed_record_len(can_delta_encode_pc, can_delta_encode_ll, coord_len));
+        match (varint, can_delta_encode_pc, can_delta_encode_ll){
+            (Some(v), _, _) => packed_codes.push(v),
+            (None, false,false) => {
+                let mut packed: [u8;10] = [0;10];
+                packed[0] = if partial {partial_flag} else {0x00};
+                packed[1] = c[0];
+                packed[2] = c[1];
+                packed[3] = c[2];
+                packed[4..4+ll.len()].copy_from_slice(ll);
+                packed_codes.push(DeltaPacked::Absolute(packed, 4 + ll.len() as u8));
+            },
+            (None, true,false) => {
+                let mut packed: [u8;7] = [0;7];
+                packed[0] = 0x80 + ((code_number - last_code - 1) as u8).to_le_bytes()[0];
+                packed[1..1+ll.len()].copy_from_slice(ll);
+                packed_codes.push(DeltaPacked::DeltaP(packed, 1 + ll.len() as u8));
+            },
+            (None, false,true) => {
+                let mut packed: [u8;6] = [0;6];
+                packed[0] = 0x40;
+                packed[1] = c[0];
+                packed[2] = c[1];
+                packed[3] = c[2];
+                packed[4] = dlat.to_le_bytes()[0];
+                packed[5] = dlong.to_le_bytes()[0];
+                packed_codes.push(DeltaPacked::DeltaLL(packed));
+            },
+            (None, true,true) => {
+                let mut packed: [u8;3] = [0;3];
+                packed[0] = 0xc0 + ((code_number - last_code - 1) as u8).to_le_bytes()[0];
+                packed[1] = dlat.to_le_bytes()[0];
+                packed[2] = dlong.to_le_bytes()[0];
+                packed_codes.push(DeltaPacked::DeltaPLL(packed));
+            },
+        }
+        if let Some(year) = p.terminated{
+            if !has_flags{
+                return Err(PostcodeError::IncompatibleOptions("terminated postcodes need file format version 6 or newer"));
+            }
+            let record = packed_codes.pop().unwrap();
+            let bytes = record.bytes();
+            let mut packed: [u8;17] = [0;17];
+            packed[..bytes.len()].copy_from_slice(bytes);
+            packed[0] |= 0x20;
+            packed[bytes.len()] = year;
+            packed_codes.push(DeltaPacked::Terminated(packed, bytes.len() as u8 + 1));
+        }
+        last_code = code_number;
+        last_lat = lat as i32;
+        last_long = long as i32;
+    }
+    Ok(packed_codes)
+}
+
+/// A string as a JSON string literal, for the JSON files written by hand
+pub fn json_string(s: &str) -> String{
+    let mut out = String::from("\"");
+    for c in s.chars(){
+        match c{
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub(crate) fn crc32(data: &[u8]) -> u32{
+    crc32fast::hash(data)
+}
+
+/// Bytes as lower case hex
+pub fn hex(bytes: &[u8]) -> String{
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn human(n: u64) -> String{
+    let mut n: f64 = n as f64;
+    const names: [&str;4] = [
+        "Bytes",
+        "KiB",
+        "MiB",
+        "GiB",
+    ];
+    let mut ni = 0;
+    while ni < names.len()-1 && n > 1024.0{
+        ni += 1;
+        n /= 1024.0;
+    }
+    format!("{:.3} {}",n, names[ni])
+}
+
+#[cfg(feature="packer")]
+pub(crate) struct LLTotal{
+    lat: f64,
+    long: f64,
+    pub n: u32,
+    /// The country of the postcodes, if they are all in the same one
+    country: Option<Country>,
+}
+
+#[cfg(feature="packer")]
+impl LLTotal{
+    pub fn new() -> Self{
+        Self {lat:0.0, long:0.0, n:0, country:None}
+    }
+    pub fn add(&mut self, p: &Point, country: Option<Country>){
+        self.country = if self.n == 0 || self.country == country { country } else { None };
+        self.n += 1;
+        self.lat += p.y;
+        self.long += p.x;
+    }
+
+    pub fn average(&self) -> Point{
+        if self.n == 0 {
+            return Point{x:0.0,y:0.0};
+        }
+        let n = self.n as f64;
+        Point{x:self.long/n, y:self.lat/n}
+    }
+}
+
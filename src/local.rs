@@ -0,0 +1,195 @@
+/*
+
+Local bounding boxes, one for each prefix block, from format version 15 onwards (optional).
+
+Locations are quantized to 16 bits across the bounding box of the whole file, which is about 10 to
+20 metres for the whole UK. Most prefix blocks cover a much smaller area, so if a block has its own
+bounding box, and its locations are quantized across that instead, they are much more precise
+(under a metre in the dense city centre areas). The deltas between neighbouring postcodes are
+bigger in the finer grid, so the postcode data is a little larger.
+
+A block's box is stored as fractions of the file's bounding box, rounded outwards, so its edges
+are on the grid of the whole file. Blocks that don't have a box use the file's bounding box.
+
+Local bounding boxes structure, between the file's bounding box and the quick lookup table:
+
+    list of boxes (the number of them is in the header):
+        block:   2 bytes (u16, index of the prefix block in the quick lookup table)
+        minlong: 2 bytes (u16, fraction of the file's bounding box, like a quantized location)
+        maxlong: 2 bytes (u16)
+        minlat:  2 bytes (u16)
+        maxlat:  2 bytes (u16)
+
+From version 25 the locations can be quantized in coordinate groups instead (see group.rs),
+which are kept here too, since they're used in the same way.
+
+*/
+use std::collections::BTreeMap;
+use crate::{Point, LUT_SIZE};
+#[cfg(feature="packer")]
+use crate::{PostcodeInfo, lut_index, max_quantized, quantize_ll, dequantize_ll};
+use crate::group::Groups;
+
+/// Length of each box in the file
+const BOX_LEN: usize = 10;
+
+/// The bounding box of one prefix block, as quantized locations in the file's bounding box
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LocalBox{
+    pub long: (u16, u16),
+    pub lat: (u16, u16),
+}
+
+impl LocalBox{
+    /// The smallest box on the file's grid that contains the extents `min` to `max`
+    #[cfg(feature="packer")]
+    fn around(min: Point, max: Point, file_min: Point, file_max: Point) -> Self{
+        let edges = |lo: f64, hi: f64, file_lo: f64, file_hi: f64|{
+            let q = |x: f64| ((x - file_lo) / (file_hi - file_lo)) * 65535.0;
+            let lo = q(lo).floor().clamp(0.0, 65535.0) as u16;
+            let hi = q(hi).ceil().clamp(0.0, 65535.0) as u16;
+            // A block with one location still needs a box with some size
+            match hi > lo{
+                true => (lo, hi),
+                false if lo < 65535 => (lo, lo + 1),
+                false => (lo - 1, lo),
+            }
+        };
+        Self{
+            long: edges(min.x, max.x, file_min.x, file_max.x),
+            lat: edges(min.y, max.y, file_min.y, file_max.y),
+        }
+    }
+
+    /// Lower left and upper right corners of the box, from the file's bounding box
+    pub fn bounds(&self, file_min: Point, file_max: Point) -> (Point, Point){
+        let x = |q: u16| file_min.x + ((file_max.x - file_min.x) * (q as f64 / 65535.0));
+        let y = |q: u16| file_min.y + ((file_max.y - file_min.y) * (q as f64 / 65535.0));
+        (Point{x: x(self.long.0), y: y(self.lat.0)}, Point{x: x(self.long.1), y: y(self.lat.1)})
+    }
+
+    /// Convert a location quantized in this box to one quantized to 16 bits in the file's bounding box
+    #[cfg(feature="packer")]
+    fn to_file(self, long: u32, lat: u32, max: f64) -> (u16, u16){
+        let q = |x: u32, (lo, hi): (u16, u16)| lo + ((x as f64 * (hi - lo) as f64) / max).round() as u16;
+        (q(long, self.long), q(lat, self.lat))
+    }
+}
+
+/// The local bounding box of each prefix block that has one, or the coordinate groups
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LocalBoxes{
+    boxes: BTreeMap<usize, LocalBox>,
+    groups: Groups,
+}
+
+impl LocalBoxes{
+    /// Coordinate groups instead of boxes
+    #[cfg(feature="packer")]
+    pub fn grouped(groups: Groups) -> Self{
+        Self{boxes: BTreeMap::new(), groups}
+    }
+
+    /// Add the coordinate groups read from a file
+    pub fn set_groups(&mut self, groups: Groups){
+        self.groups = groups;
+    }
+
+    pub fn groups(&self) -> &Groups{
+        &self.groups
+    }
+
+    /// Read the boxes from a file, `data` must be exactly the right length
+    pub fn from_bytes(data: &[u8]) -> Option<Self>{
+        if !data.len().is_multiple_of(BOX_LEN){
+            return None;
+        }
+        let mut boxes = BTreeMap::new();
+        for b in data.chunks_exact(BOX_LEN){
+            let u = |i: usize| u16::from_le_bytes([b[i], b[i+1]]);
+            let block = u(0) as usize;
+            let local = LocalBox{long: (u(2), u(4)), lat: (u(6), u(8))};
+            if block >= LUT_SIZE || local.long.0 >= local.long.1 || local.lat.0 >= local.lat.1{
+                return None;
+            }
+            boxes.insert(block, local);
+        }
+        Some(Self{boxes, groups: Groups::default()})
+    }
+
+    #[cfg(feature="packer")]
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(self.boxes.len() * BOX_LEN);
+        for (&block, b) in &self.boxes{
+            for x in [block as u16, b.long.0, b.long.1, b.lat.0, b.lat.1]{
+                out.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// Number of blocks with their own box
+    pub fn len(&self) -> usize{
+        self.boxes.len()
+    }
+
+    pub fn get(&self, block: usize) -> Option<&LocalBox>{
+        self.boxes.get(&block)
+    }
+
+    /// The box that the location of a canonical postcode in a block is quantized in: its group's
+    /// box, or the block's box, or the file's bounding box if the block doesn't have its own
+    pub fn bounds(&self, block: usize, postcode: &[u8], file_min: Point, file_max: Point) -> (Point, Point){
+        if let Some(bounds) = self.groups.bounds(postcode){
+            return bounds;
+        }
+        match self.get(block){
+            Some(b) => b.bounds(file_min, file_max),
+            None => (file_min, file_max),
+        }
+    }
+
+    /// Convert a location quantized as `bounds` says to one quantized to 16 bits in the file's
+    /// bounding box, which is what the spatial index uses
+    #[cfg(feature="packer")]
+    pub fn to_file(&self, block: usize, postcode: &[u8], (long, lat): (u32, u32), resolution: u32, file_min: Point, file_max: Point) -> (u16, u16){
+        if let Some((minll, maxll)) = self.groups.bounds(postcode){
+            let (long, lat) = quantize_ll(file_min, file_max, dequantize_ll(minll, maxll, long, lat, resolution), 16);
+            return (long as u16, lat as u16);
+        }
+        let max = max_quantized(resolution) as f64;
+        match self.get(block){
+            Some(b) => b.to_file(long, lat, max),
+            None => ((long as f64 * 65535.0 / max).round() as u16, (lat as f64 * 65535.0 / max).round() as u16),
+        }
+    }
+}
+
+/// The extents of the postcodes in each prefix block, to find the local bounding boxes
+#[cfg(feature="packer")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockExtents(BTreeMap<usize, (Point, Point)>);
+
+#[cfg(feature="packer")]
+impl BlockExtents{
+    /// Outward codes are at the average location of their postcodes, so they're left out
+    pub fn add(&mut self, p: &PostcodeInfo){
+        let Some(block) = lut_index(p.postcode.as_bytes()).filter(|_| !p.is_partial) else{
+            return;
+        };
+        let l = p.location;
+        let (min, max) = self.0.entry(block).or_insert((l, l));
+        *min = Point{x: min.x.min(l.x), y: min.y.min(l.y)};
+        *max = Point{x: max.x.max(l.x), y: max.y.max(l.y)};
+    }
+
+    /// A box for every block that has postcodes
+    pub fn boxes(&self, file_min: Point, file_max: Point) -> LocalBoxes{
+        LocalBoxes{
+            boxes: self.0.iter()
+                .map(|(&block, &(min, max))| (block, LocalBox::around(min, max, file_min, file_max)))
+                .collect(),
+            groups: Groups::default(),
+        }
+    }
+}
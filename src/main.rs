@@ -1,5 +1,4 @@
 #![allow(non_upper_case_globals)]
-#![allow(non_snake_case)]
 /*
 
 Program that converts the postcode database .csv file from the Office for National Statistics (ONS)
@@ -8,599 +7,1927 @@ be read using the javascript library provided.
 
 */
 use std::process::ExitCode;
-use time::{Date, UtcDateTime, Time};
-use std::fs::OpenOptions;
-use std::io::{Write,Seek};
-use std::fmt::Display;
-use std::fmt::Formatter;
-use std::num::ParseFloatError;
-use std::collections::HashMap;
-use clap::{arg, command};
-
-#[derive(Debug)]
-pub enum PostcodeError{
-    IOError(std::io::Error),
-    InputMalformed(),
-    InvalidFormat(),
-    NotFound(),
+use std::io::{BufRead, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use clap::{arg, command, Command, ArgMatches};
+use clap::parser::ValueSource;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn};
+use nearmypostcode_packer::reader::RECORD_KINDS;
+use nearmypostcode_packer::spatial::IndexKind;
+use nearmypostcode_packer::geohash;
+use nearmypostcode_packer::{diff, patch, repack, vectors, geocode, suggest};
+#[cfg(feature="fetch")]
+use nearmypostcode_packer::fetch;
+#[cfg(feature="sign")]
+use nearmypostcode_packer::signature;
+#[cfg(feature="serve")]
+use nearmypostcode_packer::serve;
+#[cfg(feature="boundary")]
+use nearmypostcode_packer::region::Region;
+use nearmypostcode_packer::{Packer, StreamPacker, PostcodeError, PostcodeInfo, Reader, ReadStats, Mismatch, Unpackable, Dropped, Point, ReadOptions, CoordSource, InputFormat, Compression, OutputFormat, Scheme, Validity, Progress, DEFAULT_VERSION, DEFAULT_RESOLUTION, human, release_date_of, hex, json_string, sha256_file, distance_m, Centroid, CentroidLevel, Metadata, RecordOrder, OnDuplicate, Crs, InputSummary, Anomaly, InputError, InputProblem};
+
+/// Writes log messages to stderr, as plain text or as one JSON object per line
+struct Logger{
+    json: bool,
 }
 
-#[derive(Debug,Clone,Copy)]
-struct Point{
-    x: f64,
-    y: f64,
-}
+impl log::Log for Logger{
+    fn enabled(&self, metadata: &log::Metadata) -> bool{
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record){
+        if !self.enabled(record.metadata()){
+            return;
+        }
+        // Hide the progress bar while writing, so that the message doesn't get drawn over
+        match ACTIVE_BAR.lock().ok().and_then(|b| b.clone()){
+            Some(bar) => bar.suspend(|| self.write(record)),
+            None => self.write(record),
+        }
+    }
 
-#[derive(Debug, Clone)]
-struct PostcodeInfo{
-    postcode: String,
-    location: Point,
-    is_partial: bool,
+    fn flush(&self){}
 }
 
-impl Display for PostcodeError{
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        use PostcodeError::*;
-        match self{
-            IOError(e) => write!(f,"Error reading or writing postcode file: {e}"),
-            InputMalformed() => write!(f, "Input file is not well formed"),
-            InvalidFormat() => write!(f, "Postcode format not recognised"),
-            NotFound() => write!(f, "Postcode is well-formed, but not known"),
+impl Logger{
+    fn write(&self, record: &log::Record){
+        if self.json{
+            let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+            let level = record.level().as_str().to_ascii_lowercase();
+            eprintln!("{{\"time\": {time:.3}, \"level\": {}, \"message\": {}}}", json_string(&level), json_string(record.args().to_string().trim()));
+        }
+        else if record.level() == log::Level::Warn{
+            eprintln!("Warning: {}", record.args());
+        }
+        else{
+            eprintln!("{}", record.args());
         }
     }
 }
 
-impl From<std::io::Error> for PostcodeError{
-    fn from(e: std::io::Error) -> Self { PostcodeError::IOError(e) }
-}
+/// Whether to draw progress bars, they're hidden by --quiet and --json-logs
+static SHOW_PROGRESS: AtomicBool = AtomicBool::new(true);
+
+/// The most recent progress bar, which is hidden while log messages are written
+static ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
 
-impl From<ParseFloatError> for PostcodeError{
-    fn from(_: ParseFloatError) -> Self { PostcodeError::InputMalformed() }
+/// A progress bar on stderr, with a spinner instead of a bar if the length isn't known
+fn progress_bar(len: Option<u64>, template: &str) -> ProgressBar{
+    if !SHOW_PROGRESS.load(Ordering::Relaxed){
+        return ProgressBar::hidden();
+    }
+    let bar = match len{
+        Some(len) => ProgressBar::new(len),
+        None => ProgressBar::new_spinner(),
+    };
+    bar.set_style(ProgressStyle::with_template(template).expect("Bad progress bar template"));
+    bar.enable_steady_tick(Duration::from_millis(200));
+    if let Ok(mut active) = ACTIVE_BAR.lock(){
+        *active = Some(bar.clone());
+    }
+    bar
 }
 
-fn encode_AZ(x:u8) -> Result<u32, PostcodeError> {
-    if x >= b'A' && x <= b'Z'{
-        Ok((x-b'A') as u32)
+fn print_read_stats(stats: &ReadStats, (minll, maxll): (Point, Point)){
+    info!("  File contained {} entries.", stats.total);
+    info!("    {} of these were skipped.", stats.skipped);
+    info!("      {} of the skips were for terminated postcodes.", stats.terminated);
+    info!("      {} of the skips were for excluded prefixes.", stats.excluded);
+    if stats.outside_area > 0{
+        info!("      {} of the skips were outside the chosen area.", stats.outside_area);
+    }
+    if stats.low_quality > 0{
+        info!("      {} of the skips were for locations below the minimum quality.", stats.low_quality);
     }
-    else {
-        Err(PostcodeError::InvalidFormat())
+    if stats.unpackable > 0{
+        info!("      {} of the skips were for postcodes that can't be packed (no location, or an unusual layout).", stats.unpackable);
     }
+    if stats.malformed > 0{
+        info!("      {} of the skips were for rows that could not be read.", stats.malformed);
+    }
+    if stats.duplicates > 0{
+        info!("      {} of the skips were for postcodes that are also in another row.", stats.duplicates);
+    }
+    info!("  Will process {} postcodes in the bounding box from {},{} to {},{}", stats.total-stats.skipped, minll.x,minll.y, maxll.x,maxll.y);
 }
 
-fn encode_09(x:u8) -> Result<u32, PostcodeError> {
-    if x >= b'0' && x <= b'9'{
-        Ok((x-b'0') as u32)
+fn report_mismatches(mismatches: &[Mismatch], total: usize) -> Result<(),PostcodeError>{
+    const max_report: usize = 20;
+    for m in mismatches.iter().take(max_report){
+        match m.found{
+            Some(l) => warn!("{}: expected {},{} but found {},{}", m.postcode, m.expected.x, m.expected.y, l.x, l.y),
+            None => warn!("{}: expected {},{} but it was not found", m.postcode, m.expected.x, m.expected.y),
+        }
     }
-    else {
-        Err(PostcodeError::InvalidFormat())
+    if mismatches.len() > max_report{
+        warn!("...and {} more", mismatches.len() - max_report);
     }
+    if !mismatches.is_empty(){
+        return Err(PostcodeError::VerifyFailed(mismatches.len()));
+    }
+    info!("  All {} postcodes verified", total);
+    Ok(())
 }
 
-fn encode_AZ09(x:u8) -> Result<u32, PostcodeError> {
-    encode_AZ(x).or_else(|_|Ok(encode_09(x)?+26))
+/// Whether to look for postcodes outside the usual region of their area, and if so, whether
+/// finding any is an error
+fn anomaly_check(matches: &ArgMatches) -> Option<bool>{
+    let fail = matches.get_flag("fail-on-anomaly");
+    (fail || matches.get_flag("check-anomalies")).then_some(fail)
 }
 
-fn encode_AZ09_space(x:u8) -> Result<u32, PostcodeError> {
-    if x == b' '{ Ok(36) } else{ encode_AZ(x).or_else(|_|Ok(encode_09(x)?+26)) }
+fn report_anomalies(anomalies: &[Anomaly], fail: bool) -> Result<(),PostcodeError>{
+    const max_report: usize = 20;
+    info!("Checking for postcodes outside the usual region of their area...");
+    for a in anomalies.iter().take(max_report){
+        warn!("{}: {},{} is {:.0}m outside the usual region of {} postcodes", a.postcode, a.location.y, a.location.x, a.distance, a.area);
+    }
+    if anomalies.len() > max_report{
+        warn!("...and {} more", anomalies.len() - max_report);
+    }
+    if anomalies.is_empty(){
+        info!("  No postcodes are out of place");
+    }
+    else if fail{
+        return Err(PostcodeError::InputMalformed(InputError{line: None, field: None, reason: InputProblem::Anomalies(anomalies.len())}));
+    }
+    Ok(())
 }
 
+/// With --reproducible, the date for input files that have no dates in them: from
+/// SOURCE_DATE_EPOCH (see reproducible-builds.org) if it's set, otherwise 1970-01-01
+fn reproducible_date(matches: &ArgMatches) -> Result<Option<time::Date>, PostcodeError>{
+    if !matches.get_flag("reproducible"){
+        return Ok(None);
+    }
+    let seconds = match std::env::var("SOURCE_DATE_EPOCH"){
+        Ok(s) => s.trim().parse::<i64>().map_err(|_| PostcodeError::IncompatibleOptions("SOURCE_DATE_EPOCH must be a whole number of seconds"))?,
+        Err(_) => 0,
+    };
+    let date = time::OffsetDateTime::from_unix_timestamp(seconds)
+        .map_err(|_| PostcodeError::IncompatibleOptions("SOURCE_DATE_EPOCH is out of range"))?
+        .date();
+    debug!("Dates of input files without dates of their own are {date}");
+    Ok(Some(date))
+}
 
-pub fn pack_outward_code(code: &str) -> Result<[u8;3], PostcodeError>{
-    if code.len() < 7{
-        return Err(PostcodeError::InvalidFormat());
+/// The options for reading the input file, from the arguments added by `input_args`
+fn read_options(matches: &ArgMatches) -> Result<ReadOptions, PostcodeError>{
+    let many = |name: &str| -> Vec<String> {
+        matches.get_many::<String>(name).map(|e| e.cloned().collect()).unwrap_or_default()
+    };
+    let coords = match matches.get_one::<String>("coords").map(|c|c.as_str()){
+        Some("osgb36") => CoordSource::Osgb36,
+        _ => CoordSource::Wgs84,
+    };
+    let format = match matches.get_one::<String>("input-format").map(|c|c.as_str()){
+        Some("codepoint") => InputFormat::CodePoint,
+        Some("pointer") => InputFormat::Pointer,
+        _ => InputFormat::Onspd,
+    };
+    let scheme = matches.get_one::<String>("scheme")
+        .and_then(|s| Scheme::from_name(s))
+        .unwrap_or_default();
+    let mut read_options = ReadOptions{
+        exclude: many("exclude"),
+        include: many("include").iter().map(|p| p.to_ascii_uppercase()).collect(),
+        exclude_regex: matches.get_many::<regex::Regex>("exclude-regex").map(|e| e.cloned().collect()).unwrap_or_default(),
+        coords,
+        format,
+        scheme,
+        include_terminated: matches.get_flag("include-terminated"),
+        only_country: many("only-country"),
+        only_region: many("only-region"),
+        only_la: many("only-la"),
+        min_quality: matches.get_one::<u8>("min-quality").copied(),
+        progress: None,
+        strict: matches.get_flag("strict"),
+        extra_csv: many("extra-csv"),
+        file_date: reproducible_date(matches)?,
+        attributes: many("with-attributes"),
+        // Not there for `outliers`, which doesn't write a report
+        report_dropped: matches.try_contains_id("report-dropped").unwrap_or(false),
+        on_duplicate: matches.get_one::<String>("on-duplicate")
+            .and_then(|d| OnDuplicate::from_name(d))
+            .unwrap_or_default(),
+        crs: matches.get_one::<String>("crs")
+            .and_then(|c| Crs::from_name(c))
+            .unwrap_or_default(),
+    };
+    for path in many("exclude-file"){
+        read_options.read_exclude_file(&path)?;
     }
+    Ok(read_options)
+}
 
-    let mut chars = code.as_bytes().iter();
+fn do_postcode_repack(infilename: &str, outfilename: &str, matches: &ArgMatches, verify: bool) -> Result<(),PostcodeError>{
+    let read_options = read_options(matches)?;
+    // Not there for `build`, which always writes a pack file
+    let output_format = matches.try_get_one::<String>("output-format").ok().flatten()
+        .and_then(|f| OutputFormat::from_name(f))
+        .unwrap_or_default();
+    if output_format != OutputFormat::Pack{
+        return do_export(infilename, outfilename, matches, read_options, output_format, verify);
+    }
 
-    // Skip the first two chars
-    let _a = encode_AZ(*chars.next().unwrap())?;
-    let _b = encode_AZ09(*chars.next().unwrap())?;
+    let compression = matches.get_one::<String>("compress")
+        .and_then(|c|Compression::from_name(c))
+        .unwrap_or(Compression::None);
+    let index_kind = matches.get_one::<IndexKind>("spatial-index").copied();
+    let spatial_index = index_kind.is_some();
+    let index_kind = index_kind.unwrap_or_default();
+    let countries = matches.get_flag("countries");
+    let centroids = matches.get_flag("centroids");
+    let prefix_extents = matches.get_flag("prefix-extents");
+    let sector_bitmap = matches.get_flag("sector-bitmap");
+    let dataset_date = matches.get_one::<u64>("dataset-date").copied();
+    let comment = matches.get_one::<String>("comment");
+    let metadata = matches.get_flag("metadata") || comment.is_some();
+    let chunk_size = matches.get_one::<u32>("chunk-size").copied();
+    let checksums = matches.get_flag("checksums");
+    let runs = matches.get_flag("runs");
+    let varints = matches.get_flag("varints");
+    let entropy = matches.get_flag("entropy");
+    let local_bounds = matches.get_flag("local-bounds");
+    let clip_bbox = matches.get_one::<usize>("clip-bbox").copied().unwrap_or(0);
+    let groups = matches.get_flag("groups");
+    let order = matches.get_one::<String>("order")
+        .and_then(|o| RecordOrder::from_name(o))
+        .unwrap_or_default();
+    let resolution = matches.get_one::<u32>("resolution").copied().unwrap_or(DEFAULT_RESOLUTION);
+    let report_unpackable = matches.get_one::<String>("report-unpackable");
+    let report_dropped = matches.get_one::<String>("report-dropped");
+    // Not there if the packer was built without the sign feature
+    let sign = matches.try_get_one::<String>("sign").ok().flatten();
+    let format_version = matches.get_one::<u32>("format-version").copied().unwrap_or(
+        if order != RecordOrder::Alphabetical || read_options.crs != Crs::Wgs84 { 26 }
+        else if groups { 25 }
+        else if clip_bbox > 0 { 24 }
+        else if metadata { 23 }
+        else if dataset_date.is_some() { 22 }
+        else if sector_bitmap { 21 }
+        else if prefix_extents { 20 }
+        else if !read_options.attributes.is_empty() { 19 }
+        else if index_kind != IndexKind::Grid { index_kind.min_version() }
+        else if read_options.scheme != Scheme::Uk { 17 }
+        else if resolution != DEFAULT_RESOLUTION { 16 }
+        else if local_bounds { 15 }
+        else if entropy { 14 }
+        else if varints { 13 }
+        else if runs { 12 }
+        else if sign.is_some() { 11 }
+        else if checksums { 10 }
+        else if chunk_size.is_some() { 9 }
+        else if centroids { 8 }
+        else if countries { 7 }
+        else if read_options.include_terminated { 6 }
+        else if spatial_index { 5 }
+        else if compression != Compression::None { 4 }
+        else { DEFAULT_VERSION }
+    );
+    if checksums && format_version < 10{
+        return Err(PostcodeError::IncompatibleOptions("checksums need file format version 10 or newer"));
+    }
+    if runs && format_version < 12{
+        return Err(PostcodeError::IncompatibleOptions("runs of postcodes at the same location need file format version 12 or newer"));
+    }
+    if varints && format_version < 13{
+        return Err(PostcodeError::IncompatibleOptions("varint records need file format version 13 or newer"));
+    }
+    if entropy && format_version < 14{
+        return Err(PostcodeError::IncompatibleOptions("entropy coding needs file format version 14 or newer"));
+    }
+    if local_bounds && format_version < 15{
+        return Err(PostcodeError::IncompatibleOptions("local bounding boxes need file format version 15 or newer"));
+    }
+    if resolution != DEFAULT_RESOLUTION && format_version < 16{
+        return Err(PostcodeError::IncompatibleOptions("resolutions other than 16 bits need file format version 16 or newer"));
+    }
+    if read_options.scheme != Scheme::Uk && format_version < 17{
+        return Err(PostcodeError::IncompatibleOptions("codes other than UK postcodes need file format version 17 or newer"));
+    }
+    // Without --dataset-date, the release date comes from the name of the input file, but it's
+    // only written if the format version was chosen for something else
+    let release_date = dataset_date.or_else(|| (format_version >= 22).then(|| release_date_of(infilename)).flatten());
+    if let Some(date) = release_date.and_then(date_string){
+        debug!("Release date is {date}");
+    }
+    // Version 23 onwards always says how it was made
+    let metadata = if metadata || format_version >= 23 { pack_metadata(infilename, matches, comment) } else { Metadata::new() };
+    // Read the key first, so that a bad key is found before the slow part. Older versions
+    // are still signed, they just don't have the key's fingerprint in the header.
+    #[cfg(feature="sign")]
+    let signing_key = sign.map(|path| signature::read_signing_key(std::path::Path::new(path))).transpose()?;
+    #[cfg(feature="sign")]
+    let key_id = match &signing_key{
+        Some(key) if format_version >= 11 => signature::key_id(&key.verifying_key()),
+        _ => [0;8],
+    };
+    #[cfg(not(feature="sign"))]
+    let key_id = [0;8];
+
+    debug!("Writing format version {format_version}, compression {compression}");
+    // --low-memory reads the input file again to write and verify, so the bar is used again
+    let bar = progress_bar(None, "  {spinner} {human_pos} rows read [{elapsed}]");
+    let rows = bar.clone();
+    let read_options = ReadOptions{
+        progress: Some(Progress(Arc::new(move |n| rows.set_position(n as u64)))),
+        ..read_options
+    };
+    let start = std::time::Instant::now();
+    let read: (ReadStats, (Point, Point));
+    info!("Reading postcodes...");
+    if matches.get_flag("low-memory"){
+        let packer = StreamPacker::from_csv_with_options(infilename, &read_options);
+        bar.finish_and_clear();
+        let mut packer = packer?;
+        debug!("Read postcodes in {:.1?}", start.elapsed());
+        packer.set_format_version(format_version)?;
+        packer.set_compression(compression);
+        packer.set_spatial_index(spatial_index);
+        packer.set_index_kind(index_kind);
+        packer.set_countries(countries);
+        packer.set_centroids(centroids);
+        packer.set_prefix_extents(prefix_extents);
+        packer.set_sector_bitmap(sector_bitmap);
+        packer.set_release_date(release_date);
+        packer.set_metadata(metadata.clone());
+        packer.set_chunk_size(chunk_size.unwrap_or(0));
+        packer.set_key_id(key_id);
+        packer.set_entropy(entropy);
+        packer.set_local_bounds(local_bounds);
+        packer.set_resolution(resolution)?;
+        packer.set_clip_bbox(clip_bbox)?;
+        packer.set_groups(groups);
+        packer.set_order(order);
+        print_read_stats(packer.stats(), packer.bounds());
+        read = (*packer.stats(), packer.bounds());
+        if let Some(fail) = anomaly_check(matches){
+            report_anomalies(&packer.anomalies()?, fail)?;
+        }
+        if let Some(reportfilename) = report_unpackable{
+            write_unpackable_report(reportfilename, packer.unpackable())?;
+        }
+        if let Some(reportfilename) = report_dropped{
+            write_dropped_report(reportfilename, packer.dropped())?;
+        }
+        info!("Writing packed postcodes to file...");
+        bar.reset();
+        let size = packer.write(outfilename);
+        bar.finish_and_clear();
+        info!("  Total file size: {}", human(size?));
+        if verify{
+            info!("Verifying packed postcodes...");
+            let reader = Reader::open(outfilename)?;
+            bar.reset();
+            let mismatches = packer.verify(&reader);
+            bar.finish_and_clear();
+            report_mismatches(&mismatches?, packer.len())?;
+        }
+    }
+    else{
+        let packer = Packer::from_csv_with_options(infilename, &read_options);
+        bar.finish_and_clear();
+        let mut packer = packer?;
+        debug!("Read postcodes in {:.1?}", start.elapsed());
+        packer.set_format_version(format_version)?;
+        packer.set_compression(compression);
+        packer.set_spatial_index(spatial_index);
+        packer.set_index_kind(index_kind);
+        packer.set_countries(countries);
+        packer.set_centroids(centroids);
+        packer.set_prefix_extents(prefix_extents);
+        packer.set_sector_bitmap(sector_bitmap);
+        packer.set_release_date(release_date);
+        packer.set_metadata(metadata.clone());
+        packer.set_chunk_size(chunk_size.unwrap_or(0));
+        packer.set_key_id(key_id);
+        packer.set_entropy(entropy);
+        packer.set_local_bounds(local_bounds);
+        packer.set_resolution(resolution)?;
+        packer.set_clip_bbox(clip_bbox)?;
+        packer.set_groups(groups);
+        packer.set_order(order);
+        print_read_stats(packer.stats(), packer.bounds());
+        read = (*packer.stats(), packer.bounds());
+        if let Some(fail) = anomaly_check(matches){
+            report_anomalies(&packer.anomalies(), fail)?;
+        }
+        if let Some(reportfilename) = report_unpackable{
+            write_unpackable_report(reportfilename, packer.unpackable())?;
+        }
+        if let Some(reportfilename) = report_dropped{
+            write_dropped_report(reportfilename, packer.dropped())?;
+        }
+        info!("Writing packed postcodes to file...");
+        let size = packer.write(outfilename)?;
+        info!("  Total file size: {}", human(size));
+        if verify{
+            info!("Verifying packed postcodes...");
+            let reader = Reader::open(outfilename)?;
+            report_mismatches(&packer.verify(&reader), packer.postcodes().len())?;
+        }
+    }
+    if format_version == 9 || chunk_size.is_some(){
+        let sidecar = std::path::Path::new(outfilename).with_extension("idx.json");
+        info!("Writing block index to {}...", sidecar.display());
+        write_block_index(outfilename, &sidecar)?;
+    }
+    #[cfg(feature="sign")]
+    if let Some(key) = &signing_key{
+        let sigfile = std::path::Path::new(outfilename).with_extension("sig");
+        info!("Writing signature to {}...", sigfile.display());
+        std::fs::write(&sigfile, signature::sign(&std::fs::read(outfilename)?, key))?;
+        info!("  Signed with key {}", hex(&signature::key_id(&key.verifying_key())));
+    }
+    if let Some(statsfilename) = matches.get_one::<String>("stats-json"){
+        info!("Writing stats to {statsfilename}...");
+        write_stats_json(outfilename, statsfilename, &read.0, read.1)?;
+    }
+    if matches.get_flag("layered"){
+        write_layers(outfilename)?;
+    }
+    Ok(())
+}
 
-    // Encode the rest
-    let c = 37*encode_AZ09_space(*chars.next().unwrap())?;
-    let d = encode_AZ09_space(*chars.next().unwrap())?;
-    let encoded = c + d;
-    assert!(encoded < 2_u32.pow(16));
-    let encoded = encoded.to_le_bytes();
-    Ok([
-        encoded[0],
-        encoded[1],
-        encoded[2],
-    ])
+/// Options that only apply to pack files
+const PACK_OPTIONS: [&str;26] = [
+    "format-version", "compress", "spatial-index", "countries", "centroids", "chunk-size", "stats-json",
+    "checksums", "runs", "varints", "entropy", "local-bounds", "resolution", "low-memory", "sign",
+    "with-attributes", "prefix-extents", "sector-bitmap", "dataset-date", "metadata", "comment",
+    "clip-bbox", "groups", "layered", "order", "crs",
+];
+
+/// Options that don't change what is packed, or that are secret, so they aren't recorded in the
+/// metadata
+const UNRECORDED_OPTIONS: [&str;16] = [
+    "input", "output", "metadata", "comment", "sign", "stats-json", "report-unpackable", "report-dropped",
+    "verify", "threads", "quiet", "verbose", "low-memory", "cache-dir", "sha256", "layered",
+];
+
+/// What to record about how a pack file was made: this packer, the name of the input file, the
+/// options that were given, and the comment if there is one
+fn pack_metadata(infilename: &str, matches: &ArgMatches, comment: Option<&String>) -> Metadata{
+    let mut metadata = Metadata::generated();
+    let source = std::path::Path::new(infilename).file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    metadata.set("source", &source);
+    let mut options = Vec::new();
+    for id in matches.ids().map(|id| id.as_str()){
+        if UNRECORDED_OPTIONS.contains(&id) || matches.value_source(id) != Some(ValueSource::CommandLine){
+            continue;
+        }
+        let values: Vec<String> = matches.get_raw(id).into_iter().flatten()
+            .map(|v| v.to_string_lossy().to_string())
+            .collect();
+        options.push(format!("--{id}"));
+        // Flags have the value "true"
+        if values != ["true"]{
+            options.extend(values.iter().map(|v| if v.contains(char::is_whitespace) { format!("'{v}'") } else { v.clone() }));
+        }
+    }
+    metadata.set("options", &options.join(" "));
+    if let Some(comment) = comment{
+        metadata.set("comment", comment);
+    }
+    metadata
 }
 
-pub fn pack_code(code: &str) -> Result<[u8;3], PostcodeError>{
-    if code.len() < 7{
-        return Err(PostcodeError::InvalidFormat());
+/// Read the postcodes, and write them in one of the output formats other than a pack file
+fn do_export(infilename: &str, outfilename: &str, matches: &ArgMatches, read_options: ReadOptions, format: OutputFormat, verify: bool) -> Result<(),PostcodeError>{
+    // Only the options that were given, --sign isn't there at all in builds without signing
+    let pack_options = matches.ids()
+        .any(|id| PACK_OPTIONS.contains(&id.as_str()) && matches.value_source(id.as_str()) == Some(ValueSource::CommandLine));
+    if verify || pack_options{
+        return Err(PostcodeError::IncompatibleOptions("the pack file options and --verify can only be used with --output-format pack"));
+    }
+    let bar = progress_bar(None, "  {spinner} {human_pos} rows read [{elapsed}]");
+    let rows = bar.clone();
+    let read_options = ReadOptions{
+        progress: Some(Progress(Arc::new(move |n| rows.set_position(n as u64)))),
+        ..read_options
+    };
+    info!("Reading postcodes...");
+    let packer = Packer::from_csv_with_options(infilename, &read_options);
+    bar.finish_and_clear();
+    let packer = packer?;
+    print_read_stats(packer.stats(), packer.bounds());
+    if let Some(fail) = anomaly_check(matches){
+        report_anomalies(&packer.anomalies(), fail)?;
     }
+    if let Some(reportfilename) = matches.get_one::<String>("report-unpackable"){
+        write_unpackable_report(reportfilename, packer.unpackable())?;
+    }
+    if let Some(reportfilename) = matches.get_one::<String>("report-dropped"){
+        write_dropped_report(reportfilename, packer.dropped())?;
+    }
+    info!("Writing postcodes to {format} file...");
+    let size = packer.write_as(format, outfilename)?;
+    info!("  Total file size: {}", human(size));
+    Ok(())
+}
 
-    let mut chars = code.as_bytes().iter();
-    
-    // Skip the first two chars
-    let _a = encode_AZ(*chars.next().unwrap())?;
-    let _b = encode_AZ09(*chars.next().unwrap())?;
+/// Write a CSV file of the postcodes that were skipped because they can't be packed
+fn write_unpackable_report(filename: &str, unpackable: &[Unpackable]) -> Result<(),PostcodeError>{
+    info!("Writing {} unpackable postcodes to {filename}...", unpackable.len());
+    let mut csv = csv::Writer::from_path(filename).map_err(|e| PostcodeError::IOError(e.into()))?;
+    let csv_err = |e: csv::Error| PostcodeError::IOError(e.into());
+    csv.write_record(["postcode", "line", "reason"]).map_err(csv_err)?;
+    for u in unpackable{
+        let line = u.line.map(|l| l.to_string()).unwrap_or_default();
+        csv.write_record([u.postcode.as_str(), &line, u.reason.name()]).map_err(csv_err)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
 
-    // Encode the rest
-    let c = 26*26*10*37*encode_AZ09_space(*chars.next().unwrap())?;
-    let d = 26*26*10*encode_AZ09_space(*chars.next().unwrap())?;
+/// Write a CSV file of every row that was skipped
+fn write_dropped_report(filename: &str, dropped: &[Dropped]) -> Result<(),PostcodeError>{
+    info!("Writing {} skipped rows to {filename}...", dropped.len());
+    let mut csv = csv::Writer::from_path(filename).map_err(|e| PostcodeError::IOError(e.into()))?;
+    let csv_err = |e: csv::Error| PostcodeError::IOError(e.into());
+    csv.write_record(["postcode", "line", "reason"]).map_err(csv_err)?;
+    for d in dropped{
+        let line = d.line.map(|l| l.to_string()).unwrap_or_default();
+        csv.write_record([d.postcode.as_str(), &line, d.reason.name()]).map_err(csv_err)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
 
-    let e = 26*26*encode_09(*chars.next().unwrap())?;
-    let f = 26*encode_AZ(*chars.next().unwrap())?;
-    let g = encode_AZ(*chars.next().unwrap())?;
-    let encoded = c + d + e + f + g;
-    assert!(encoded < 2_u32.pow(24));
-    let encoded = encoded.to_le_bytes();
-    Ok([
-        encoded[0],
-        encoded[1],
-        encoded[2]
-    ])
+/// Write a report of what was packed as JSON, for checking in scripts
+fn write_stats_json(packfilename: &str, outfilename: &str, read: &ReadStats, (minll, maxll): (Point, Point)) -> Result<(),PostcodeError>{
+    let reader = Reader::open(packfilename)?;
+    let stats = reader.file_stats();
+    let entries: usize = stats.blocks.iter().map(|(_, n)|n).sum();
+    let date = time::OffsetDateTime::from_unix_timestamp(reader.last_update() as i64)
+        .map(|d|d.date().to_string())
+        .unwrap_or_default();
+    let encodings: Vec<String> = RECORD_KINDS.iter().zip(stats.record_kinds).zip(stats.record_bytes)
+        .map(|((name, n), bytes)| format!("\n    {}: {{\"records\": {n}, \"bytes\": {bytes}}}", json_string(name)))
+        .collect();
+    let json = format!(
+        "{{\n  \"file\": {},\n  \"size\": {},\n  \"sha256\": {},\n  \"format_version\": {},\n  \"compression\": {},\n  \"dataset_date\": {},\n  \"last_update\": {},\n  \"release_date\": {},\n  \"input\": {{\n    \"rows\": {},\n    \"skipped\": {},\n    \"terminated\": {},\n    \"excluded\": {},\n    \"outside_area\": {},\n    \"low_quality\": {},\n    \"malformed\": {},\n    \"unpackable\": {},\n    \"duplicates\": {}\n  }},\n  \"entries\": {},\n  \"postcodes\": {},\n  \"outward_codes\": {},\n  \"bounds\": [{}, {}, {}, {}],\n  \"data_bytes\": {},\n  \"encodings\": {{{}\n  }}\n}}\n",
+        json_string(packfilename), reader.file_len(), json_string(&sha256_file(std::path::Path::new(packfilename))?),
+        reader.version(), json_string(reader.compression().name()), json_string(&date), reader.last_update(),
+        reader.release_date().and_then(date_string).map(|d| json_string(&d)).unwrap_or_else(|| "null".to_string()),
+        read.total, read.skipped, read.terminated, read.excluded, read.outside_area, read.low_quality, read.malformed, read.unpackable, read.duplicates,
+        entries, entries - stats.outward_codes, stats.outward_codes,
+        minll.x, maxll.x, minll.y, maxll.y, stats.data_len, encodings.join(","),
+    );
+    std::fs::write(outfilename, json)?;
+    Ok(())
 }
 
+/// Write the layer 0 file of a layered pack, with one postcode from each sector of a pack file,
+/// and a manifest of both layers, smallest first
+fn write_layers(packfilename: &str) -> Result<(),PostcodeError>{
+    let path = std::path::Path::new(packfilename);
+    let layer0 = path.with_extension("layer0.pack");
+    info!("Writing layer 0 to {}...", layer0.display());
+    let reader = Reader::open(packfilename)?;
+    let packer = repack::sample_sectors(&reader);
+    let size = packer.write(&layer0.to_string_lossy())?;
+    info!("  {} sectors, total file size: {}", packer.postcodes().iter().filter(|p| !p.is_partial).count(), human(size));
+    let manifest = path.with_extension("layers.json");
+    info!("Writing manifest to {}...", manifest.display());
+    let layer = |file: &std::path::Path| -> Result<String,PostcodeError>{
+        let reader = Reader::open(&file.to_string_lossy())?;
+        let stats = reader.file_stats();
+        let entries: usize = stats.blocks.iter().map(|(_, n)|n).sum();
+        let file_name = file.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        Ok(format!(
+            "\n    {{\"file\": {}, \"size\": {}, \"sha256\": {}, \"postcodes\": {}, \"outward_codes\": {}}}",
+            json_string(&file_name), reader.file_len(), json_string(&sha256_file(file)?), entries - stats.outward_codes, stats.outward_codes,
+        ))
+    };
+    let json = format!(
+        "{{\n  \"format_version\": {},\n  \"layers\": [{},{}\n  ]\n}}\n",
+        reader.version(), layer(&layer0)?, layer(path)?,
+    );
+    std::fs::write(manifest, json)?;
+    Ok(())
+}
 
-fn field_id(name: &str, headers: &Vec<&str>) -> Result<usize, PostcodeError>{
-    match headers.iter().position(|n|*n==name) {
-        Some(n) => Ok(n),
-        None => Err(PostcodeError::InputMalformed()),
-    }
+/// Write the byte range of every prefix block in a version 9 file as JSON, so that a client
+/// can fetch the blocks it needs with range requests without reading the lookup table first
+fn write_block_index(infilename: &str, outfilename: &std::path::Path) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let (minll, maxll) = reader.bounds();
+    let blocks: Vec<String> = reader.block_ranges().iter()
+        .map(|(prefix, start, end)| format!("\n    \"{}\": [{start}, {end}]", prefix.trim_end()))
+        .collect();
+    let json = format!(
+        "{{\n  \"version\": {},\n  \"date\": {},\n  \"file_len\": {},\n  \"chunk_size\": {},\n  \"data_start\": {},\n  \"bounds\": [{}, {}, {}, {}],\n  \"blocks\": {{{}\n  }}\n}}\n",
+        reader.version(), reader.last_update(), reader.file_len(), reader.chunk_size().unwrap_or(0), reader.data_start(),
+        minll.x, maxll.x, minll.y, maxll.y, blocks.join(","),
+    );
+    std::fs::write(outfilename, json)?;
+    Ok(())
 }
 
-fn parse_date(d: Option<&str>) -> Option<Date> {
-    let d = d?;
-    if d.len()<6 {
-        None
+fn do_unpack(infilename: &str, outfilename: Option<&str>) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let out: Box<dyn Write> = match outfilename{
+        Some(name) => Box::new(std::fs::File::create(name)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut csv = csv::Writer::from_writer(out);
+    let csv_err = |e: csv::Error| PostcodeError::IOError(e.into());
+    // Only version 6 files can have terminated postcodes
+    let has_terminated = reader.version() >= 6;
+    let has_countries = reader.country_runs().is_some();
+    let mut header = vec!["postcode", "lat", "long"];
+    if has_terminated{
+        header.push("terminated");
     }
-    else{
-        let y = d[0..4].parse().ok()?;
-        let m:time::Month = d[4..6].parse::<u8>().ok()?.try_into().ok()?;
-        let date = Date::from_calendar_date(y,m.into(),1);
-        Some(date.ok()?)
-    }
-}
-
-fn read_postcodes(path: &str, exclude: &Vec<&str>) -> Result<(Vec<PostcodeInfo>,Point,Point,usize,usize,usize,u64), PostcodeError> {
-    let file = OpenOptions::new().read(true).open(path)?;
-    let mut pclist = Vec::new();
-    let mut postcodes = csv::Reader::from_reader(file);
-    let headers = postcodes.headers();
-    if headers.is_err(){
-        return Err(PostcodeError::InputMalformed());
-    }
-    let headers: Vec<&str> = headers.unwrap().iter().collect();
-    let id_postcode = field_id("pcd", &headers).or(field_id("pcd7", &headers))?;
-    let id_lat = field_id("lat", &headers)?;
-    let id_long = field_id("long", &headers)?;
-    let id_date_intr = field_id("dointr", &headers)?;
-    let id_date_term  = field_id("doterm", &headers)?;
-
-    let mut minlat = 9999.0f64;
-    let mut maxlat = -9999.0f64;
-    let mut minlong = 9999.0f64;
-    let mut maxlong = -9999.0f64;
-
-    let mut total = 0;
-    let mut num_terminated = 0;
-    let mut num_excluded = 0;
-
-    let mut last_update = Date::from_ordinal_date(1970,1).unwrap();
-
-    'pcloop: for line in postcodes.records() {
-        if line.is_err(){
-            return Err(PostcodeError::InputMalformed());
-        }
-        total += 1;
-        let line = line.unwrap();
-        let postcode = line.get(id_postcode);
-        if postcode.is_none(){
-            continue;
+    if has_countries{
+        header.push("ctry");
+    }
+    header.extend(reader.attribute_names());
+    csv.write_record(&header).map_err(csv_err)?;
+    for entry in reader.iter(){
+        let postcode = if entry.is_partial { &entry.postcode[0..4] } else { &entry.postcode };
+        let mut record = vec![
+            postcode.to_string(),
+            entry.location.y.to_string(),
+            entry.location.x.to_string(),
+        ];
+        if has_terminated{
+            record.push(entry.terminated.map(|y| y.to_string()).unwrap_or_default());
         }
-        let postcode = postcode.unwrap().to_string();
-        let introduced = parse_date(line.get(id_date_intr));
-        let terminated = parse_date(line.get(id_date_term));
-        let is_current = match (introduced, terminated) {
-            (Some(_), None) => true,
-            _ => false,
+        if has_countries{
+            record.push(entry.country.map(|c| c.code().to_string()).unwrap_or_default());
+        }
+        record.extend(entry.attributes);
+        csv.write_record(&record).map_err(csv_err)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+fn do_query(infilename: &str, postcodes: &[&String]) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let mut missing = 0;
+    for pc in postcodes{
+        let canonical = match reader.scheme().codec().format(pc){
+            Ok(c) => c,
+            Err(e) => { eprintln!("{pc}: {e}"); missing += 1; continue; }
         };
-        if !is_current{
-            num_terminated += 1;
-            continue;
+        match reader.lookup(&canonical){
+            Some(l) => match reader.country(&canonical){
+                Some(c) => println!("{},{},{},{}", canonical, l.y, l.x, c.code()),
+                None => println!("{},{},{}", canonical, l.y, l.x),
+            },
+            None => { eprintln!("{pc}: {}", PostcodeError::NotFound()); missing += 1; },
         }
-        let lat = line.get(id_lat);
-        if lat.is_none(){
+    }
+    if missing > 0{
+        return Err(PostcodeError::NotFound());
+    }
+    Ok(())
+}
+
+fn do_nearest(infilename: &str, lat: f64, long: f64, k: usize) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    for (p, metres) in reader.nearest_n(lat, long, k){
+        println!("{},{},{},{:.0}", p.postcode, p.location.y, p.location.x, metres);
+    }
+    Ok(())
+}
+
+fn do_validate(infilename: &str, listfilename: Option<&str>) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let list: Box<dyn BufRead> = match listfilename{
+        Some(name) => Box::new(std::io::BufReader::new(std::fs::File::open(name)?)),
+        None => Box::new(std::io::stdin().lock()),
+    };
+    let mut counts = [0usize;4];
+    let all = [Validity::Exists, Validity::Terminated, Validity::WellFormed, Validity::Unknown];
+    for line in list.lines(){
+        let line = line?;
+        let code = line.trim();
+        if code.is_empty(){
             continue;
         }
-        let lat: f64 = lat.unwrap().parse().unwrap();
-        if lat > 99.0{
-            continue; // no location known
-        }
-        let long = line.get(id_long);
-        if long.is_none(){
-            continue;
+        let validity = reader.validate(code);
+        counts[all.iter().position(|v| *v == validity).unwrap()] += 1;
+        // The code goes last, so that one with a comma in it is still one column
+        println!("{},{code}", validity.name());
+    }
+    let summary: Vec<String> = all.iter().zip(counts).map(|(v, n)| format!("{n} {}", v.name())).collect();
+    info!("{}", summary.join(", "));
+    Ok(())
+}
+
+fn do_suggest(infilename: &str, postcodes: &[&String], max_edits: usize) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    for postcode in postcodes{
+        for found in reader.suggest(postcode, max_edits){
+            println!("{found}");
         }
-        let long: f64 = long.unwrap().parse().unwrap();
-        let location = Point{x:long, y:lat};
+    }
+    Ok(())
+}
 
-        for prefix in exclude{
-            if postcode.starts_with(prefix){
-                num_excluded += 1;
-                continue 'pcloop;
-            }
+fn do_distances(infilename: &str, postcodes: &[&String]) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let mut found = Vec::new();
+    for pc in postcodes{
+        let canonical = reader.scheme().codec().format(pc).inspect_err(|e| eprintln!("{pc}: {e}"))?;
+        match reader.lookup(&canonical){
+            Some(l) => found.push((canonical, l)),
+            None => { eprintln!("{pc}: {}", PostcodeError::NotFound()); return Err(PostcodeError::NotFound()); }
         }
+    }
+    let names: Vec<&str> = found.iter().map(|(pc, _)| pc.as_str()).collect();
+    println!(",{}", names.join(","));
+    for (pc, from) in &found{
+        let row: Vec<String> = found.iter().map(|(_, to)| format!("{:.0}", distance_m(from, to))).collect();
+        println!("{pc},{}", row.join(","));
+    }
+    Ok(())
+}
 
-        let introduced = introduced.unwrap();
-        if introduced > last_update{
-            last_update = introduced;
-        }
-
-        minlat = minlat.min(lat);
-        maxlat = maxlat.max(lat);
-        minlong = minlong.min(long);
-        maxlong = maxlong.max(long);
-        
-        pclist.push(PostcodeInfo{
-            postcode,
-            location,
-            is_partial: false,
-        });
-    }
-    let skipped = total - pclist.len();
-    let unixtime = UtcDateTime::new(last_update, Time::from_hms(0,0,0).unwrap()).unix_timestamp() as u64;
-    Ok((
-        pclist, // Postcodes
-        Point{x:minlong, y:minlat}, // Lower left corner of bounding box
-        Point{x:maxlong, y:maxlat}, // Upper right corner of bounding box
-        skipped, // Number of postcodes skipped
-        num_terminated, // number of postcodes terminated
-        num_excluded, // number of postcodes terminated
-        unixtime, // date of last update
-    ))
-}
-
-
-fn calc_ll(minll: Point, maxll: Point, ll: Point) -> (u16,u16){
-    let latrange = maxll.y - minll.y;
-    let longrange = maxll.x - minll.x;
-    let lat = (((ll.y-minll.y)/latrange)*65535.0).round() as u16;
-    let long = (((ll.x-minll.x)/longrange)*65535.0).round() as u16;
-    (long,lat)
-}
-
-enum DeltaPacked{
-    Absolute([u8;8]),
-    DeltaP([u8;5]),
-    DeltaLL([u8;6]),
-    DeltaPLL([u8;3]),
-}
-
-impl DeltaPacked{
-    fn write_to_file<W:Write>(&self, mut f:W) -> std::io::Result<usize>{
-        use DeltaPacked::*;
-        match self{
-            Absolute(a) => {f.write(a)},
-            DeltaP(a) => {f.write(a)},
-            DeltaLL(a) => {f.write(a)},
-            DeltaPLL(a) => {f.write(a)},
-        }
-    }
-
-    fn len(&self) -> usize{
-        use DeltaPacked::*;
-        match self{
-            Absolute(_) => 8,
-            DeltaP(_) => 5,
-            DeltaLL(_) => 6,
-            DeltaPLL(_) => 3,
-        }
-    }
-}
-
-fn pack_postcodes(postcodes: &Vec<PostcodeInfo>, minll: Point, maxll:Point) -> Result<Vec<DeltaPacked>, PostcodeError> {
-    let mut packed_codes = Vec::new();
-    let mut last_code:u32 = 0;
-    let mut last_lat:i32 = 0;
-    let mut last_long:i32 = 0;
-    let mut last_prefix = "  ".to_string();
-    for p in postcodes{
-        let this_prefix = &p.postcode[0..2];
-        if this_prefix != last_prefix{
-            // Any time the prefix changes, reset the previous code state.
-            // This is important because the decoder skips to the start of
-            // a prefix block as the first step, so it will still have the
-            // initial state at this point.
-            last_code = 0;
-            last_lat = 0;
-            last_long = 0;
-            last_prefix = this_prefix.to_string();
-        }
-        let partial = p.is_partial;
-        let c = if partial {
-            pack_outward_code(&p.postcode)?
-        } else {
-            pack_code(&p.postcode)?
-        };
-        let code_number = u32::from_le_bytes([c[0],c[1],c[2],0]);
-        let can_delta_encode_pc = (!p.is_partial) && {
-            if last_code > code_number{
-                // List is probably not sorted, inefficient
-                false
-            }
-            else{
-                (code_number - last_code) <= 64
-            }
-        };
-        let (long,lat) = calc_ll(minll, maxll, p.location);
-        let dlong = (long as i32) - last_long;
-        let dlat = (lat as i32) - last_lat;
-        let can_delta_encode_ll: bool = (!p.is_partial) && {
-            let can_long = dlong >= -128 && dlong <= 127;
-            let can_lat = dlat >= -128 && dlat <= 127;
-            can_long && can_lat
-        };
-        let latb = lat.to_le_bytes();
-        let longb = long.to_le_bytes();
-        let ll = [latb[0],latb[1],longb[0],longb[1]];
-
-        match (can_delta_encode_pc, can_delta_encode_ll){
-            (false,false) => {
-                let mut packed: [u8;8] = [0;8];
-                packed[0] = if partial {0x20} else {0x00};
-                packed[1] = c[0];
-                packed[2] = c[1];
-                packed[3] = c[2];
-                packed[4] = ll[0];
-                packed[5] = ll[1];
-                packed[6] = ll[2];
-                packed[7] = ll[3];
-                packed_codes.push(DeltaPacked::Absolute(packed));
-            },
-            (true,false) => {
-                let mut packed: [u8;5] = [0;5];
-                packed[0] = 0x80 + ((code_number - last_code - 1) as u8).to_le_bytes()[0];
-                packed[1] = ll[0];
-                packed[2] = ll[1];
-                packed[3] = ll[2];
-                packed[4] = ll[3];
-                packed_codes.push(DeltaPacked::DeltaP(packed));
-            },
-            (false,true) => {
-                let mut packed: [u8;6] = [0;6];
-                packed[0] = 0x40;
-                packed[1] = c[0];
-                packed[2] = c[1];
-                packed[3] = c[2];
-                packed[4] = dlat.to_le_bytes()[0];
-                packed[5] = dlong.to_le_bytes()[0];
-                packed_codes.push(DeltaPacked::DeltaLL(packed));
-            },
-            (true,true) => {
-                let mut packed: [u8;3] = [0;3];
-                packed[0] = 0xc0 + ((code_number - last_code - 1) as u8).to_le_bytes()[0];
-                packed[1] = dlat.to_le_bytes()[0];
-                packed[2] = dlong.to_le_bytes()[0];
-                packed_codes.push(DeltaPacked::DeltaPLL(packed));
-            },
+fn do_geocode(packfilename: &str, infilename: &str, column: &geocode::Column, outfilename: Option<&str>, has_header: bool) -> Result<(),PostcodeError>{
+    let reader = Reader::open(packfilename)?;
+    let input = std::io::BufReader::new(std::fs::File::open(infilename)?);
+    let out: Box<dyn Write> = match outfilename{
+        Some(name) => Box::new(std::fs::File::create(name)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let stats = geocode::geocode(&reader, input, out, column, has_header)?;
+    info!("Found {} of {} postcodes", stats.found, stats.rows);
+    if stats.found < stats.rows{
+        warn!("{} rows have no location, their postcodes are missing, malformed or not in the pack file", stats.rows - stats.found);
+    }
+    Ok(())
+}
+
+fn do_centroids(infilename: &str, level: CentroidLevel, names: &[&String]) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    if reader.centroid_counts().is_none(){
+        return Err(PostcodeError::IncompatibleOptions("the pack file has no centroid tables, pack it with --centroids"));
+    }
+    let print = |c: &Centroid| println!("{},{},{},{}", c.name, c.location.y, c.location.x, c.count);
+    if names.is_empty(){
+        reader.centroids(level).iter().for_each(print);
+        return Ok(());
+    }
+    let mut missing = 0;
+    for name in names{
+        match reader.centroid(level, name){
+            Some(c) => print(&c),
+            None => { eprintln!("{name}: no such {}", level.name()); missing += 1; },
         }
-        last_code = code_number;
-        last_lat = lat as i32;
-        last_long = long as i32;
     }
-    Ok(packed_codes)
+    if missing > 0{
+        return Err(PostcodeError::NotFound());
+    }
+    Ok(())
 }
 
-fn human(n: u64) -> String{
-    let mut n: f64 = n as f64;
-    const names: [&str;4] = [
-        "Bytes",
-        "KiB",
-        "MiB",
-        "GiB",
-    ];
-    let mut ni = 0;
-    while ni < names.len()-1 && n > 1024.0{
-        ni += 1;
-        n /= 1024.0;
+fn do_outliers(infilename: &str, options: &ReadOptions, count: usize) -> Result<(),PostcodeError>{
+    let packer = Packer::from_csv_with_options(infilename, options)?;
+    let area = |(min, max): (Point, Point)| (max.x - min.x) * (max.y - min.y);
+    let full = area(packer.bounds());
+    for o in packer.outliers(count)?{
+        let (min, max) = o.bounds;
+        println!("{},{},{},{},{},{},{}", o.postcode, o.location.y, o.location.x, min.y, min.x, max.y, max.x);
+        debug!("  {} leaves {:.1}% of the box", o.postcode, 100.0 * area(o.bounds) / full);
     }
-    format!("{:.3} {}",n, names[ni])
+    Ok(())
 }
 
-fn insert_outward_averages(postcodes: &mut Vec<PostcodeInfo>){
-    struct LLTotal{
-        lat: f64,
-        long: f64,
-        n: u32,
+fn do_stats(infilename: &str, options: &ReadOptions, by_district: bool) -> Result<(),PostcodeError>{
+    let summary = InputSummary::from_csv(infilename, options)?;
+    let percent = |n: usize, of: usize| if of == 0 { 0.0 } else { 100.0 * n as f64 / of as f64 };
+    let postcodes = summary.postcodes.live + summary.postcodes.terminated;
+    println!("Rows:           {}", summary.rows);
+    println!("Postcodes:      {} live, {} terminated ({:.1}%)", summary.postcodes.live, summary.postcodes.terminated, percent(summary.postcodes.terminated, postcodes));
+    println!("Areas:          {}", summary.areas.len());
+    println!("Districts:      {}", summary.districts.len());
+    if summary.bad_postcodes > 0{
+        println!("Bad postcodes:  {} rows have no postcode that can be read", summary.bad_postcodes);
+    }
+    if summary.malformed > 0{
+        println!("Malformed:      {} rows could not be read", summary.malformed);
+    }
+
+    println!("\nPositional quality:");
+    for (quality, n) in &summary.quality{
+        println!("  {quality:<8}{n:>9} {:>5.1}%", percent(*n, summary.rows));
+    }
+    if summary.no_quality > 0{
+        println!("  {:<8}{:>9} {:>5.1}%", "none", summary.no_quality, percent(summary.no_quality, summary.rows));
+    }
+
+    println!("\nIntroduced:");
+    let most = summary.introduced.values().copied().max().unwrap_or(0);
+    for (year, n) in &summary.introduced{
+        let bar = "#".repeat((50 * n).div_ceil(most));
+        println!("  {year:<8}{n:>9} {bar}");
+    }
+    if summary.no_date > 0{
+        println!("  {:<8}{:>9}", "no date", summary.no_date);
+    }
+
+    let (name, counts) = if by_district { ("district", &summary.districts) } else { ("area", &summary.areas) };
+    println!("\nPostcodes by {name}:");
+    println!("  {:<8}{:>9}{:>11}", "", "live", "terminated");
+    for (prefix, c) in counts{
+        println!("  {prefix:<8}{:>9}{:>11}", c.live, c.terminated);
     }
+    Ok(())
+}
 
-    impl LLTotal{
-        fn new() -> Self{
-            Self {lat:0.0, long:0.0, n:0}
+fn do_extents(infilename: &str, names: &[&String]) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    if reader.prefix_extent_count().is_none(){
+        return Err(PostcodeError::IncompatibleOptions("the pack file has no prefix extents, pack it with --prefix-extents"));
+    }
+    let print = |name: &str, min: Point, max: Point| println!("{name},{},{},{},{}", min.y, min.x, max.y, max.x);
+    if names.is_empty(){
+        for (name, min, max) in reader.prefix_extents(){
+            print(&name, min, max);
         }
-        fn add(&mut self, p: &Point){
-            self.n += 1;
-            self.lat += p.y;
-            self.long += p.x;
+        return Ok(());
+    }
+    let mut missing = 0;
+    for name in names{
+        match reader.prefix_extent(name){
+            Some((min, max)) => print(name, min, max),
+            None => { eprintln!("{name}: no such outward code"); missing += 1; },
         }
+    }
+    if missing > 0{
+        return Err(PostcodeError::NotFound());
+    }
+    Ok(())
+}
 
-        fn average(&self) -> Point{
-            if self.n == 0 {
-                return Point{x:0.0,y:0.0};
+fn do_complete(infilename: &str, texts: &[&String], postcodes: Option<usize>) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    if let Some(limit) = postcodes{
+        for text in texts{
+            for found in reader.complete_postcodes(text, limit){
+                println!("{found}");
             }
-            let n = self.n as f64;
-            Point{x:self.long/n, y:self.lat/n}
         }
+        return Ok(());
+    }
+    if reader.sector_bitmap_counts().is_none(){
+        return Err(PostcodeError::IncompatibleOptions("the pack file has no sector bitmap, pack it with --sector-bitmap"));
+    }
+    for text in texts{
+        for found in reader.complete(text){
+            println!("{found}");
+        }
+    }
+    Ok(())
+}
+
+fn do_diff(oldfilename: &str, newfilename: &str, min_move: f64, summary_only: bool) -> Result<(),PostcodeError>{
+    let old = Reader::open(oldfilename)?;
+    let new = Reader::open(newfilename)?;
+    let d = diff::diff(&old, &new, min_move);
+    if !summary_only{
+        for p in &d.added{
+            println!("+ {},{},{}", p.postcode, p.location.y, p.location.x);
+        }
+        for p in &d.removed{
+            println!("- {},{},{}", p.postcode, p.location.y, p.location.x);
+        }
+        for m in &d.moved{
+            println!("~ {},{},{} -> {},{} ({:.0}m)", m.postcode, m.from.y, m.from.x, m.to.y, m.to.x, m.distance);
+        }
+    }
+    println!("{} added, {} removed, {} moved more than {}m", d.added.len(), d.removed.len(), d.moved.len(), min_move);
+    Ok(())
+}
+
+/// The current postcodes of a release, from a pack file or an input file
+fn release_postcodes(filename: &str) -> Result<(Vec<PostcodeInfo>, Scheme),PostcodeError>{
+    let mut magic = [0u8;4];
+    let is_pack = std::fs::File::open(filename)?.read(&mut magic)? == 4 && &magic == b"UKPP";
+    if is_pack{
+        let reader = Reader::open(filename)?;
+        let postcodes = reader.iter().filter(|p| !p.is_partial && p.terminated.is_none()).collect();
+        return Ok((postcodes, reader.scheme()));
     }
+    info!("Reading {filename}...");
+    // Only one row of each postcode is needed to compare them
+    let options = ReadOptions{on_duplicate: OnDuplicate::First, ..Default::default()};
+    let packer = Packer::from_csv_with_options(filename, &options)?;
+    Ok((packer.postcodes().to_vec(), packer.scheme()))
+}
 
-    let mut totals: HashMap<String, LLTotal> = HashMap::new();
+fn do_compare_releases(oldfilename: &str, newfilename: &str, reportfilename: Option<&String>, min_move: f64) -> Result<(),PostcodeError>{
+    let (old, _) = release_postcodes(oldfilename)?;
+    let (new, scheme) = release_postcodes(newfilename)?;
+    let d = diff::diff_postcodes(old, new, scheme, min_move);
+    let name = |f: &str| std::path::Path::new(f).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| f.to_string());
+    let notes = diff::release_notes(&d, &name(oldfilename), &name(newfilename), scheme, min_move);
+    match reportfilename{
+        Some(f) => {
+            std::fs::write(f, &notes)?;
+            info!("{} new, {} retired, {} new districts, {} retired districts, written to {f}", d.added.len(), d.removed.len(), d.added_districts.len(), d.removed_districts.len());
+        },
+        None => print!("{notes}"),
+    }
+    Ok(())
+}
 
-    for p in postcodes.iter(){
-        let outward = &p.postcode[0..4];
-        if ! totals.contains_key(outward) {
-            totals.insert(outward.to_string(), LLTotal::new());
+fn do_merge(outfilename: &str, infilenames: &[&String], version: Option<u32>) -> Result<(),PostcodeError>{
+    let readers = infilenames.iter().map(|f| Reader::open(f)).collect::<Result<Vec<_>,_>>()?;
+    let (mut packer, duplicates) = repack::merge(&readers)?;
+    if duplicates > 0{
+        warn!("{duplicates} postcodes were in more than one file, the entry from the first file was kept");
+    }
+    if let Some(version) = version{
+        packer.set_format_version(version)?;
+    }
+    let (minll, maxll) = packer.bounds();
+    info!("Merged {} files, {} entries in the bounding box from {},{} to {},{}", readers.len(), packer.postcodes().len(), minll.x, minll.y, maxll.x, maxll.y);
+    info!("Writing format version {}...", packer.format_version());
+    let size = packer.write(outfilename)?;
+    info!("  Total file size: {}", human(size));
+    Ok(())
+}
+
+fn do_extract(infilename: &str, outfilename: &str, bbox: Option<(Point, Point)>, prefixes: &[&str]) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let packer = repack::extract(&reader, bbox, prefixes);
+    if packer.postcodes().is_empty(){
+        return Err(PostcodeError::IncompatibleOptions("no postcodes match the bounding box and prefixes"));
+    }
+    let (minll, maxll) = packer.bounds();
+    let total = reader.iter().filter(|p| !p.is_partial).count();
+    info!("Extracted {} of {} postcodes, in the bounding box from {},{} to {},{}", packer.postcodes().iter().filter(|p| !p.is_partial).count(), total, minll.x, minll.y, maxll.x, maxll.y);
+    info!("Writing format version {}...", packer.format_version());
+    let size = packer.write(outfilename)?;
+    info!("  Total file size: {}", human(size));
+    Ok(())
+}
+
+fn do_upgrade(infilename: &str, outfilename: &str, matches: &ArgMatches) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let mut packer = repack::upgrade(&reader);
+    if let Some(&version) = matches.get_one::<u32>("format-version"){
+        packer.set_format_version(version)?;
+    }
+    if let Some(&kind) = matches.get_one::<IndexKind>("spatial-index"){
+        packer.set_spatial_index(true);
+        packer.set_index_kind(kind);
+    }
+    packer.set_centroids(packer.centroids() || matches.get_flag("centroids"));
+    packer.set_prefix_extents(packer.prefix_extents() || matches.get_flag("prefix-extents"));
+    packer.set_sector_bitmap(packer.sector_bitmap() || matches.get_flag("sector-bitmap"));
+    packer.set_entropy(packer.entropy() || matches.get_flag("entropy"));
+    if matches.get_flag("groups"){
+        packer.set_groups(true);
+        packer.set_local_bounds(false);
+        packer.set_clip_bbox(0)?;
+    }
+    if let Some(&chunk_size) = matches.get_one::<u32>("chunk-size"){
+        packer.set_chunk_size(chunk_size);
+    }
+    if let Some(order) = matches.get_one::<String>("order").and_then(|o| RecordOrder::from_name(o)){
+        packer.set_order(order);
+    }
+    info!("Upgrading format version {} to version {}...", reader.version(), packer.format_version());
+    let size = packer.write(outfilename)?;
+    info!("  Total file size: {}", human(size));
+    Ok(())
+}
+
+#[cfg(feature="boundary")]
+fn do_filter_region(infilename: &str, outfilename: &str, boundary: &str) -> Result<(),PostcodeError>{
+    let region = Region::from_geojson(&std::fs::read_to_string(boundary)?)?;
+    let reader = Reader::open(infilename)?;
+    let packer = repack::extract_region(&reader, &region);
+    if packer.postcodes().is_empty(){
+        return Err(PostcodeError::IncompatibleOptions("no postcodes are inside the boundary"));
+    }
+    let total = reader.iter().filter(|p| !p.is_partial).count();
+    info!("Extracted {} of {} postcodes, inside {} polygons", packer.postcodes().iter().filter(|p| !p.is_partial).count(), total, region.polygons().len());
+    info!("Writing format version {}...", packer.format_version());
+    let size = packer.write(outfilename)?;
+    info!("  Total file size: {}", human(size));
+    Ok(())
+}
+
+/// Read the kind of spatial index, as grid, kdtree, geohash or geohash:<precision>
+fn parse_index_kind(s: &str) -> Result<IndexKind, String>{
+    IndexKind::from_name(s).ok_or_else(|| format!("expected grid, kdtree, geohash or geohash:<precision> (1 to {})", geohash::MAX_PRECISION))
+}
+
+/// Read a release date given as YYYY-MM (the first of the month) or YYYY-MM-DD, as a unix timestamp
+fn parse_dataset_date(s: &str) -> Result<u64, String>{
+    let parts = s.trim().split('-').map(|n| n.parse::<u16>()).collect::<Result<Vec<_>,_>>()
+        .map_err(|e| format!("{e}"))?;
+    let (year, month, day) = match parts[..]{
+        [year, month] => (year, month, 1),
+        [year, month, day] => (year, month, day),
+        _ => return Err("expected a date such as 2025-02 or 2025-02-01".to_string()),
+    };
+    let month = time::Month::try_from(month as u8).map_err(|e| format!("{e}"))?;
+    let date = time::Date::from_calendar_date(year as i32, month, day as u8).map_err(|e| format!("{e}"))?;
+    Ok(date.midnight().assume_utc().unix_timestamp().max(0) as u64)
+}
+
+/// A unix timestamp as YYYY-MM-DD, `None` if it's out of range
+fn date_string(unixtime: u64) -> Option<String>{
+    time::OffsetDateTime::from_unix_timestamp(unixtime as i64).ok().map(|d| d.date().to_string())
+}
+
+/// Read a bounding box given as minlong,minlat,maxlong,maxlat
+fn parse_bbox(s: &str) -> Result<(Point, Point), String>{
+    let v = s.split(',').map(|n| n.trim().parse::<f64>()).collect::<Result<Vec<_>,_>>()
+        .map_err(|e| format!("{e}"))?;
+    match v[..]{
+        [minx, miny, maxx, maxy] if minx <= maxx && miny <= maxy => Ok((Point{x: minx, y: miny}, Point{x: maxx, y: maxy})),
+        [_, _, _, _] => Err("the minimum longitude and latitude must not be more than the maximum".to_string()),
+        _ => Err("expected four numbers: minlong,minlat,maxlong,maxlat".to_string()),
+    }
+}
+
+fn do_gen_test_vectors(outdir: &str, version: u32) -> Result<(),PostcodeError>{
+    let n = vectors::generate(std::path::Path::new(outdir), version)?;
+    info!("Wrote {n} lookups to {}", std::path::Path::new(outdir).join("vectors.json").display());
+    Ok(())
+}
+
+#[cfg(feature="fetch")]
+fn do_fetch(release: Option<&str>, url: Option<&str>, sha256: Option<&str>, cache_dir: Option<&str>, list: bool) -> Result<(),PostcodeError>{
+    if list{
+        for r in fetch::find_releases()?{
+            println!("{}  {}", r.name(), r.title);
         }
-        let t = totals.get_mut(outward).unwrap();
-        t.add(&p.location);
+        return Ok(());
     }
-    for (k, v) in totals{
-        let p = PostcodeInfo{
-            is_partial: true,
-            postcode: format!("{}   ", k),
-            location: v.average(),
-        };
-        postcodes.push(p);
-    }
-}
-
-fn do_postcode_repack(infilename: &str, outfilename: &str, exclude: &Vec<&str>) -> Result<(),PostcodeError>{
-    println!("Reading postcodes...");
-    let (mut postcodes, minll, maxll, skipped, terminated, excluded, last_update) = read_postcodes(infilename, exclude)?;
-    println!("  File contained {} entries.", postcodes.len()+skipped);
-    println!("    {} of these were skipped.", skipped);
-    println!("      {} of the skips were for terminated postcodes.", terminated);
-    println!("      {} of the skips were for excluded prefixes.", excluded);
-    println!("  Will process {} postcodes in the bounding box from {},{} to {},{}", postcodes.len(), minll.x,minll.y, maxll.x,maxll.y);
-    println!("Sorting postcode lists...");
-    insert_outward_averages(&mut postcodes);
-    postcodes.sort_by(|a,b|a.postcode.cmp(&b.postcode));
-    println!("Packing postcodes...");
-    let packed_codes = pack_postcodes(&postcodes, minll, maxll)?;
-    let mut outfile = OpenOptions::new().write(true).create(true).truncate(true).open(outfilename)?;
-    println!("Writing packed postcodes to file...");
-
-    /*
-    File structure:
-    (all numbers in little endian unless specified otherwise)
-    
-    Header, 16 bytes:
-
-        magic:   4 bytes "UKPP" - magic number for "UK Postcode Pack"
-        version: 4 bytes (u32)  - version number of the file format (this code generates version 1)
-        date:    8 bytes (u64)  - a unix epoch that represents the release date of the ONS dataset that the file was generated from
-
-    Boudning box extents, 4*8 = 32 bytes:
-
-        minlong: 8 bytes (f64)
-        maxlong: 8 bytes (f64)
-        minlat:  8 bytes (f64)
-        maxlat:  8 bytes (f64)
-    
-    Quick lookup table, 26*36*4 = 3744 bytes:
-
-        list of 26*36 index values
-            position: 4 bytes (u32, byte offset into postcode data list)
-        last_pos: 4 bytes (u32, conveniently is just above last entry in the table)
-        
-    Postcode data, variable length (3 to 8 bytes per postcode):
-    
-        list of postcodes:
-            format:   1 bytes (bitfield)
-                postcode_is_delta: 1 bit (flag indicating if postcode is delta-encoded)
-                latlong_is_delta:  1 bit (flag indicating if lat/long is delta-encoded)
-                extra_data: 6 bits
-                    postcode_is_delta == 1 => postcode_delta:    6 bits (u6 number to add to previous postcode to calculate this postcode)
-                    postcode_is_delta == 0 => special mode: 1 bit
-                        0 => 
-                            00000 => No Special mode
-                            (all other values) => reserved
-                        1 => Special mode
-                            00000 => Postcode only contains outward code, match on first 4 chars only
-                            (all other values) => reserved
-            postcode: 0 or 3 bytes (custom encoding, present only if not postcode_is_delta)
-            longlat:  2 or 4 bytes (2 x i8 if latlong_is_delta, or 2 x u16 otherwise)
-
-    */
-
-    // Header...
-    outfile.write(b"UKPP")?; // magic number is 1347439445
-
-    // version 2 introduces outward-only postcodes
-    const version: u32 = 2;
-    outfile.write(&version.to_le_bytes())?;
-
-    // data update date
-    outfile.write(&last_update.to_le_bytes())?;
-
-    // bounding box extents
-    let minlong = minll.x;
-    let maxlong = maxll.x;
-    let minlat = minll.y;
-    let maxlat = maxll.y;
-    outfile.write(&minlong.to_le_bytes())?;
-    outfile.write(&maxlong.to_le_bytes())?;
-    outfile.write(&minlat.to_le_bytes())?;
-    outfile.write(&maxlat.to_le_bytes())?;
-
-    let mut lut: HashMap<String, u32> = HashMap::new();
-
-    // Build and write the table
-    let mut last_prefix = String::new();
-    let mut pos = 0;
-    for (postcode, packed_code) in postcodes.iter().zip(&packed_codes){
-        let this_prefix = postcode.postcode[0..2].to_string();
-        if this_prefix != last_prefix{
-            lut.insert(this_prefix.clone(), pos as u32);
-            last_prefix = this_prefix;
-        }
-        pos += packed_code.len();
-    }
-
-    // Build the table in reverse to be able to calculate the offsets
-    let mut lastpos = pos as u32;
-    for c1 in (0..26).rev(){
-        let s1 = b'A'+c1;
-        for c2 in (0..36).rev(){
-            let s2 = if c2 > 9{ b'A'+c2-10 } else { b'0'+c2};
-            let s_bytes = [s1,s2];
-            let s = std::str::from_utf8(&s_bytes).unwrap().to_string();
-            let pos = lut.get(&s).copied().unwrap_or(lastpos);
-            lastpos = pos;
-            lut.insert(s, pos);
-        }
-    }
-
-    // Write it forwards, since that's the way the lookup will happen
-    for c1 in 0..26{
-        let s1 = b'A'+c1;
-        for c2 in 0..36{
-            let s2 = if c2 > 9{ b'A'+c2-10 } else { b'0'+c2};
-            let s_bytes = [s1,s2];
-            let s = std::str::from_utf8(&s_bytes).unwrap().to_string();
-            let pos = lut.get(&s).unwrap();
-            outfile.write(&pos.to_le_bytes())?;
-        }
-    }
-
-    // One extra element after end, total bytes
-    outfile.write(&lastpos.to_le_bytes())?;
-    for p in packed_codes.iter(){
-        p.write_to_file(&outfile)?;
-    }
-
-    if let Ok(l) = outfile.stream_position() {
-        println!("  Total file size: {}", human(l));
+    let (_, _, fetched) = download(release, url, sha256, cache_dir)?;
+    println!("{} ({})", fetched.path.display(), if fetched.cached { "already downloaded" } else { "downloaded" });
+    println!("SHA-256: {}", fetched.sha256);
+    Ok(())
+}
+
+/// Download a release, or any URL, in to the cache. Returns the release, the URL, and the file.
+#[cfg(feature="fetch")]
+fn download(release: Option<&str>, url: Option<&str>, sha256: Option<&str>, cache_dir: Option<&str>) -> Result<(Option<fetch::Release>, String, fetch::Fetched),PostcodeError>{
+    let (found, url, file_name) = match url{
+        Some(url) => (None, url.to_string(), url.rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("download").to_string()),
+        None => {
+            info!("Finding release...");
+            let r = fetch::find_release(release)?;
+            info!("  {}", r.title);
+            let file_name = format!("ONSPD_{}.zip", r.name());
+            (Some(r.clone()), r.url, file_name)
+        },
+    };
+    let cache_dir = cache_dir.map(std::path::PathBuf::from).unwrap_or_else(fetch::default_cache_dir);
+    let mut bar: Option<ProgressBar> = None;
+    let fetched = fetch::fetch(&url, &cache_dir, &file_name, sha256, |done, total|{
+        bar.get_or_insert_with(|| match total{
+            Some(_) => progress_bar(total, "  {bar:40} {bytes} of {total_bytes} [{elapsed}] ({bytes_per_sec}, {eta} left)"),
+            None => progress_bar(None, "  {spinner} {bytes} [{elapsed}] ({bytes_per_sec})"),
+        }).set_position(done);
+    });
+    if let Some(bar) = bar{
+        bar.finish_and_clear();
+    }
+    Ok((found, url, fetched?))
+}
+
+/// Fetch the ONS postcode database, pack it, verify it, and write a manifest that describes the
+/// pack file and where it came from
+#[cfg(feature="fetch")]
+fn do_build(matches: &ArgMatches) -> Result<(),PostcodeError>{
+    let outfilename = matches.get_one::<String>("output").expect("No output file");
+    let arg = |name: &str| matches.get_one::<String>(name).map(|s| s.as_str());
+    info!("[1/3] Fetching the ONS postcode database...");
+    let (release, url, source) = download(arg("release"), arg("url"), arg("sha256"), arg("cache-dir"))?;
+    info!("  {} ({})", source.path.display(), if source.cached { "already downloaded" } else { "downloaded" });
+    info!("[2/3] Packing postcodes...");
+    do_postcode_repack(&source.path.to_string_lossy(), outfilename, matches, true)?;
+    let manifest = std::path::Path::new(outfilename).with_extension("manifest.json");
+    info!("[3/3] Writing manifest to {}...", manifest.display());
+    write_manifest(outfilename, &manifest, release.as_ref(), &url, &source)?;
+    Ok(())
+}
+
+#[cfg(feature="fetch")]
+fn write_manifest(packfilename: &str, outfilename: &std::path::Path, release: Option<&fetch::Release>, url: &str, source: &fetch::Fetched) -> Result<(),PostcodeError>{
+    let reader = Reader::open(packfilename)?;
+    let stats = reader.file_stats();
+    let entries: usize = stats.blocks.iter().map(|(_, n)|n).sum();
+    let date = time::OffsetDateTime::from_unix_timestamp(reader.last_update() as i64)
+        .map(|d|d.date().to_string())
+        .unwrap_or_default();
+    let file_name = std::path::Path::new(packfilename).file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let source_name = source.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let json = format!(
+        "{{\n  \"file\": {},\n  \"size\": {},\n  \"sha256\": {},\n  \"format_version\": {},\n  \"compression\": {},\n  \"dataset_date\": {},\n  \"release_date\": {},\n  \"postcodes\": {},\n  \"outward_codes\": {},\n  \"source\": {{\n    \"release\": {},\n    \"url\": {},\n    \"file\": {},\n    \"sha256\": {}\n  }},\n  \"packer_version\": {}\n}}\n",
+        json_string(&file_name), reader.file_len(), json_string(&sha256_file(std::path::Path::new(packfilename))?),
+        reader.version(), json_string(reader.compression().name()), json_string(&date),
+        reader.release_date().and_then(date_string).map(|d| json_string(&d)).unwrap_or_else(|| "null".to_string()),
+        entries - stats.outward_codes, stats.outward_codes,
+        release.map(|r| json_string(&r.name())).unwrap_or_else(|| "null".to_string()),
+        json_string(url), json_string(&source_name), json_string(&source.sha256),
+        json_string(env!("CARGO_PKG_VERSION")),
+    );
+    std::fs::write(outfilename, json)?;
+    Ok(())
+}
+
+/// Check the detached signature of a pack file
+#[cfg(feature="sign")]
+fn do_verify_signature(infilename: &str, keyfilename: &str, sigfilename: Option<&str>) -> Result<(),PostcodeError>{
+    let key = signature::read_verifying_key(std::path::Path::new(keyfilename))?;
+    let sigfile = sigfilename.map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::path::Path::new(infilename).with_extension("sig"));
+    signature::verify(&std::fs::read(infilename)?, &std::fs::read(&sigfile)?, &key)?;
+    info!("Signature is valid, signed by key {}", hex(&signature::key_id(&key)));
+    Ok(())
+}
+
+fn do_make_patch(oldfilename: &str, newfilename: &str, patchfilename: &str) -> Result<(),PostcodeError>{
+    let old = std::fs::read(oldfilename)?;
+    let new = std::fs::read(newfilename)?;
+    let p = patch::make_patch(&old, &new)?;
+    std::fs::write(patchfilename, &p)?;
+    info!("Patch size: {} (new pack file is {})", human(p.len() as u64), human(new.len() as u64));
+    Ok(())
+}
+
+fn do_apply_patch(oldfilename: &str, patchfilename: &str, outfilename: &str) -> Result<(),PostcodeError>{
+    let old = std::fs::read(oldfilename)?;
+    let p = std::fs::read(patchfilename)?;
+    let new = patch::apply_patch(&old, &p)?;
+    std::fs::write(outfilename, &new)?;
+    info!("Wrote {}", human(new.len() as u64));
+    Ok(())
+}
+
+fn do_inspect(infilename: &str, all_blocks: bool) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    let stats = reader.file_stats();
+    let (minll, maxll) = reader.bounds();
+    let date = time::OffsetDateTime::from_unix_timestamp(reader.last_update() as i64)
+        .map(|d|d.date().to_string())
+        .unwrap_or_else(|_|"unknown".to_string());
+    println!("Magic:          UKPP");
+    println!("Version:        {}", reader.version());
+    println!("Dataset date:   {} ({})", date, reader.last_update());
+    match reader.release_date(){
+        Some(release) => println!("Release date:   {} ({release})", date_string(release).unwrap_or_else(|| "unknown".to_string())),
+        None => println!("Release date:   unknown"),
+    }
+    if reader.metadata().is_empty(){
+        println!("Metadata:       none");
+    }
+    for (i, (key, value)) in reader.metadata().iter().enumerate(){
+        println!("{:<16}{key}: {value}", if i == 0 { "Metadata:" } else { "" });
+    }
+    println!("Bounding box:   {},{} to {},{}", minll.x, minll.y, maxll.x, maxll.y);
+    match reader.outliers().len(){
+        0 => println!("Outliers:       none"),
+        n => println!("Outliers:       {n} outside the bounding box"),
+    }
+    let groups = reader.coordinate_groups();
+    if groups.is_empty(){
+        println!("Groups:         none");
+    }
+    for (i, ((min, max), members)) in groups.iter().enumerate(){
+        let name = if i == 0 { "main".to_string() } else { members.join(" ") };
+        println!("{:<16}{name}: {},{} to {},{}", if i == 0 { "Groups:" } else { "" }, min.x, min.y, max.x, max.y);
+    }
+    println!("Compression:    {}", reader.compression());
+    match (reader.index_size(), reader.kd_tree_len(), reader.geohash_buckets()){
+        (Some((cols, rows)), _, _) => println!("Spatial index:  {cols}x{rows} cells"),
+        (_, Some(points), _) => println!("Spatial index:  k-d tree of {points} postcodes"),
+        (_, _, Some((buckets, points))) => println!("Spatial index:  {}, {points} postcodes in {buckets} buckets", reader.index_kind().unwrap()),
+        _ => println!("Spatial index:  none"),
+    }
+    match reader.country_runs(){
+        Some(runs) => println!("Countries:      {runs} runs"),
+        None => println!("Countries:      none"),
+    }
+    match reader.centroid_counts(){
+        Some([areas, districts, sectors]) => println!("Centroids:      {areas} areas, {districts} districts, {sectors} sectors"),
+        None => println!("Centroids:      none"),
+    }
+    match reader.prefix_extent_count(){
+        Some(n) => println!("Prefix extents: {n} outward codes"),
+        None => println!("Prefix extents: none"),
+    }
+    match reader.sector_bitmap_counts(){
+        Some((outward, sectors)) => println!("Sector bitmap:  {outward} outward codes, {sectors} sectors"),
+        None => println!("Sector bitmap:  none"),
+    }
+    let attributes: Vec<String> = reader.attribute_names().iter()
+        .map(|name| format!("{name} ({} values)", reader.attribute_values(name).map(|v| v.len()).unwrap_or(0)))
+        .collect();
+    if attributes.is_empty(){
+        println!("Attributes:     none");
+    }
+    else{
+        println!("Attributes:     {}", attributes.join(", "));
+    }
+    if let Some(chunk_size) = reader.chunk_size(){
+        match chunk_size{
+            0 => println!("Chunk size:     not aligned"),
+            _ => println!("Chunk size:     {}", human(chunk_size as u64)),
+        }
+    }
+    if reader.version() >= 10{
+        println!("Checksums:      ok");
+    }
+    if reader.version() >= 11{
+        match reader.key_id(){
+            Some(id) => println!("Signing key:    {}", hex(&id)),
+            None => println!("Signing key:    none"),
+        }
+    }
+    println!("File size:      {}", human(reader.file_len() as u64));
+    println!("Postcode data:  {}", human(stats.data_len as u64));
+    let entries: usize = stats.blocks.iter().map(|(_, n)|n).sum();
+    println!("Entries:        {} ({} postcodes, {} outward codes)", entries, entries - stats.outward_codes, stats.outward_codes);
+    println!("LUT occupancy:  {} of {} prefixes", stats.blocks.len(), 26*36);
+    println!("Record encodings:");
+    for ((name, n), bytes) in RECORD_KINDS.iter().zip(stats.record_kinds).zip(stats.record_bytes){
+        let percent = if entries == 0 { 0.0 } else { 100.0 * n as f64 / entries as f64 };
+        println!("  {:<10} {:>9} ({:.1}%) {}", name, n, percent, human(bytes as u64));
+    }
+    if reader.version() >= 13{
+        println!("Varint saving:  {} compared to fixed size records", human(stats.varint_saving as u64));
+    }
+    if reader.version() >= 14{
+        match reader.entropy_coded(){
+            true => println!("Entropy coding: on, {} of records before coding", human(stats.record_bytes.iter().sum::<usize>() as u64)),
+            false => println!("Entropy coding: off"),
+        }
+    }
+    if reader.version() >= 15{
+        println!("Local boxes:    {} prefixes have their own bounding box", reader.local_boxes());
+    }
+    if reader.version() >= 16{
+        println!("Resolution:     {} bits per axis", reader.resolution());
+    }
+    if reader.version() >= 17{
+        println!("Scheme:         {}", reader.scheme());
+    }
+    println!("CRS:            {} (EPSG:{})", reader.crs(), reader.crs().epsg());
+    if reader.version() >= 26{
+        println!("Record order:   {}", reader.record_order());
+        let lens = reader.order_lens()?;
+        let this = lens.iter().find(|(order, _)| *order == reader.record_order()).map_or(0, |(_, len)| *len);
+        for (order, len) in lens{
+            let change = if this == 0 { 0.0 } else { 100.0 * (len as f64 - this as f64) / this as f64 };
+            let compared = if order == reader.record_order() { "(this file)".to_string() } else { format!("({change:+.1}%)") };
+            println!("  {:<10} {:>9} of records {compared}", order.name(), human(len as u64));
+        }
+        println!("Sections:");
+        for (name, start, len) in reader.sections(){
+            println!("  {name:<20} {start:>9} {}", human(len as u64));
+        }
+    }
+    const max_blocks: usize = 10;
+    if all_blocks{
+        println!("Entries per prefix:");
+        for (prefix, n) in &stats.blocks{
+            println!("  {prefix} {n:>9}");
+        }
     }
     else{
-        println!("  Non-fatal error: unable to determine final file size");
+        let mut blocks = stats.blocks.clone();
+        blocks.sort_by(|a,b|b.1.cmp(&a.1).then_with(||a.0.cmp(&b.0)));
+        println!("Largest prefixes (use --all for every prefix):");
+        for (prefix, n) in blocks.iter().take(max_blocks){
+            println!("  {prefix} {n:>9}");
+        }
     }
     Ok(())
 }
 
-fn main() -> ExitCode {
-    let matches = command!()
-        .arg(arg!(<input> "Input file name (path to ONS Postcode Database CSV file)"))
-        .arg(arg!(<output> "Output file name"))
+#[cfg(feature="serve")]
+fn do_serve(infilename: &str, address: &str, threads: usize) -> Result<(),PostcodeError>{
+    let reader = Reader::open(infilename)?;
+    info!("Serving {infilename} on http://{address}/ with {threads} threads");
+    serve::serve(&reader, address, threads)
+}
+
+/// Options for downloading, used by `fetch` and `build`
+#[cfg(feature="fetch")]
+fn fetch_args(cmd: Command) -> Command{
+    cmd
+        .arg(arg!(--release <release> "Which release to download, e.g. 2024-11 (default: the latest)"))
+        .arg(arg!(--url <url> "Download this URL instead of looking for a release"))
+        .arg(arg!(--sha256 <hex> "Expected SHA-256 of the file, the download fails if it is different"))
+        .arg(arg!(--"cache-dir" <dir> "Where to keep downloads (default: ~/.cache/nearmypostcode)"))
+}
+
+/// Options for reading the input file, used by the main command, `build` and `outliers`
+fn input_args(cmd: Command) -> Command{
+    cmd
         .arg(arg!(--exclude <prefix> ... "Exclude a group of postcodes by its prefix (can be specified multiple times)"))
-        .get_matches();
-
-    let infilename = &matches.get_one::<String>("input").expect("No input file");
-    let outfilename = &matches.get_one::<String>("output").expect("No output file");
-    let exclude = if let Some(e) = matches.get_many::<String>("exclude"){
-        e.map(|a|a.as_str()).collect()
-    } else {
-        Vec::new()
-    };
+        .arg(arg!(--include <prefix> ... "Only keep postcodes with this prefix, where a prefix of only letters is a whole postcode area, e.g. E for E1 to E20 but not EC (can be specified multiple times, and the exclusions still apply)"))
+        .arg(arg!(--"exclude-regex" <regex> ... "Exclude the postcodes that match a regular expression, written with a single space, e.g. '^BT' or '^(GY|JE|IM)' (can be specified multiple times)")
+            .value_parser(|s: &str| regex::Regex::new(s).map_err(|e| e.to_string()))
+        )
+        .arg(arg!(--"exclude-file" <file> ... "Exclude the prefixes listed in a file, one on each line, with comments after a # (can be specified multiple times)"))
+        .arg(arg!(--"only-country" <code> ... "Only keep postcodes in this country, by its ONS code, e.g. E92000001 for England (can be specified multiple times)"))
+        .arg(arg!(--"only-region" <code> ... "Only keep postcodes in this region, by its ONS code, e.g. E12000007 for London (can be specified multiple times)"))
+        .arg(arg!(--"only-la" <code> ... "Only keep postcodes in this local authority district, by its ONS code (can be specified multiple times)"))
+        .arg(arg!(--"min-quality" <level> "Only keep postcodes with a positional quality (osgrdind) of this or better, from 1 (within a building) to 9 (no location)")
+            .value_parser(clap::value_parser!(u8).range(1..=9))
+        )
+        .arg(arg!(--coords <source> "Where to read locations from: lat/long columns (wgs84, the default), or OS grid reference columns (osgb36)")
+            .value_parser(["wgs84", "osgb36"])
+        )
+        .arg(arg!(--"input-format" <format> "Type of input: the ONS postcode database or the NSPL (onspd or nspl, the default), an OS Code-Point Open directory or zip file (codepoint), or the OSNI Pointer address database for Northern Ireland (pointer)")
+            .value_parser(["onspd", "nspl", "codepoint", "pointer"])
+        )
+        .arg(arg!(--scheme <scheme> "Which country's codes the input file has: UK postcodes (uk, the default) or Irish Eircodes (eircode, needs format version 17)")
+            .value_parser(Scheme::ALL.map(|s|s.name()))
+        )
+        .arg(arg!(--"extra-csv" <file> ... "Also read postcodes from this CSV file, which has a postcode column and lat and long columns, e.g. for Jersey, Guernsey and the Isle of Man, which the ONS database has no locations for (can be specified multiple times)"))
+        .arg(arg!(--"include-terminated" "Keep terminated postcodes, marked with the year they were terminated (needs format version 6)"))
+        .arg(arg!(--"with-attributes" <columns> ... "Store these columns of the input file as attributes of each postcode, e.g. laua,lsoa11,ward (needs format version 19)")
+            .value_delimiter(',')
+        )
+        .arg(arg!(--strict "Stop at the first row that can't be read, instead of skipping it with a warning"))
+        .arg(arg!(--"on-duplicate" <policy> "What to do about a postcode that is in more than one row: stop with an error (error, the default), keep the first or last row (first or last, where extra CSV files come after the main input), or keep the row nearest the average location of its outward code (nearest-centroid). Only error can be used with --low-memory")
+            .value_parser(OnDuplicate::ALL.map(|d|d.name()))
+        )
+        .arg(arg!(--crs <crs> "Coordinate reference system to store the locations in: wgs84 (the default), etrs89, or osgb36 (the datum of the National Grid, as a latitude and longitude). The locations are shifted to it as they're read, and the file says which it is (needs format version 26 for anything but wgs84)")
+            .value_parser(Crs::ALL.map(|c|c.name()))
+        )
+        .arg(arg!(--reproducible "Make the same pack file every time the same data is packed: input files without dates of their own (Code-Point Open and Pointer) are dated from SOURCE_DATE_EPOCH, or 1970-01-01, instead of when the files were last modified"))
+}
 
-    match do_postcode_repack(infilename, outfilename, &exclude){
-        Err(e) => { eprintln!("Error repacking postcodes: {e}"); ExitCode::FAILURE }
-        Ok(_) => { println!("Complete"); ExitCode::SUCCESS }
-    }
+/// Options for packing, used by the main command and by `build`
+fn pack_args(cmd: Command) -> Command{
+    let cmd = input_args(cmd)
+        .arg(arg!(--"format-version" <version> "File format version to write (2 to 26, default 2, or the lowest version that supports the other options. Versions 3 and above need NMP 1.2.0 or newer)")
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(arg!(--compress <codec> "Compress the postcode data inside the pack file (needs format version 4)")
+            .value_parser(Compression::ALL.map(|c|c.name()))
+        )
+        .arg(arg!(--"spatial-index" [kind] "Include an index for finding the nearest postcode to a location: a grid of cells (grid, the default, needs format version 5), a k-d tree of every postcode, which is bigger but faster (kdtree, needs format version 18), or a table of every postcode by geohash, with 1 to 6 characters (geohash:<precision>, or geohash for 6, needs format version 18)")
+            .value_parser(parse_index_kind)
+            .num_args(0..=1)
+            .default_missing_value("grid")
+        )
+        .arg(arg!(--countries "Store the country of each postcode, from the ctry column (needs format version 7)"))
+        .arg(arg!(--centroids "Store the location and number of postcodes of each postcode area, district and sector (needs format version 8)"))
+        .arg(arg!(--"prefix-extents" "Store the bounding box of the postcodes in each outward code, so that a map can zoom to it before the whole postcode is typed (needs format version 20)"))
+        .arg(arg!(--"sector-bitmap" "Store which outward codes and sectors exist, so that partly typed postcodes can be checked and completed (needs format version 21)"))
+        .arg(arg!(--"dataset-date" <date> "Release date of the dataset, e.g. 2025-02, stored as well as the date of the newest postcode (needs format version 22). Without it, version 22 files take the month from the input file's name, e.g. ONSPD_FEB_2025_UK.csv").value_parser(parse_dataset_date))
+        .arg(arg!(--metadata "Record how the pack file was made: the packer's version, the name of the input file and the options given, shown by inspect (needs format version 23, which always records them)"))
+        .arg(arg!(--comment <text> "Record a comment about the pack file along with the rest of the metadata, shown by inspect (needs format version 23)"))
+        .arg(arg!(--"chunk-size" <bytes> "Align the prefix blocks to chunks of this many bytes, and write a .idx.json file of their byte ranges, for reading with HTTP range requests (needs format version 9)")
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(arg!(--"stats-json" <file> "Also write a JSON report of what was packed: counts, skipped rows, bounding box, bytes per record encoding, dataset date and the SHA-256 of the pack file"))
+        .arg(arg!(--checksums "Add checksums of each section and of the whole file, so that readers can tell when a file is damaged or truncated (needs format version 10)"))
+        .arg(arg!(--"report-unpackable" <file> "Write a CSV file of the postcodes that can't be packed, because they have no location or an unusual layout (e.g. GIR 0AA or BFPO numbers), and why"))
+        .arg(arg!(--"report-dropped" <file> "Write a CSV file of every row that was skipped, with the line it was on and why: terminated, excluded, outside-area, low-quality, parse-error, no-postcode, no-location, unpackable-code or duplicate"))
+        .arg(arg!(--"check-anomalies" "Warn about postcodes whose locations are far outside the region that the rest of their postcode area is in, such as a swapped latitude and longitude"))
+        .arg(arg!(--"fail-on-anomaly" "As --check-anomalies, but stop with an error before writing anything if any are found, for CI"))
+        .arg(arg!(--runs "Store runs of postcodes at exactly the same location in a more compact record, one byte per postcode (needs format version 12)"))
+        .arg(arg!(--varints "Store postcode and location deltas that are too big for the fixed size records as varints, instead of in full (needs format version 13)"))
+        .arg(arg!(--entropy "Huffman code each block of postcode data, with a code trained on the whole file (needs format version 14)"))
+        .arg(arg!(--"local-bounds" "Give each prefix its own bounding box, so that the locations are more precise in small areas, at the cost of a slightly larger file (needs format version 15)"))
+        .arg(arg!(--"clip-bbox" <count> "Leave the postcodes that stretch the bounding box the most (up to 1000, see the outliers command) out of it, so that the other locations are more precise. Their exact locations are kept in the header (needs format version 24)")
+            .value_parser(clap::value_parser!(usize))
+        )
+        .arg(arg!(--groups "Split the postcodes in to groups far apart from each other, such as Great Britain, Northern Ireland and Shetland, and store each group's locations across its own bounding box, so that they are more precise (needs format version 25, can't be used with --local-bounds or --clip-bbox)"))
+        .arg(arg!(--order <order> "Order of the postcodes in each prefix: alpha (the default), or hilbert to put postcodes near each other next to each other, so that more locations are stored as small deltas. Which is smaller depends on the data, inspect compares them (needs format version 26 for hilbert)")
+            .value_parser(RecordOrder::ALL.map(|o|o.name()))
+        )
+        .arg(arg!(--layered "Also write a small layer 0 file with one postcode from each sector (<output>.layer0.pack), for a web page to load before the full file, and a manifest of both (<output>.layers.json)"))
+        .arg(arg!(--resolution <bits> "Bits per axis for each location: 8, 12, 16 (the default) or 24. More bits are more precise, fewer make a smaller file (needs format version 16 for anything but 16)")
+            .value_parser(clap::value_parser!(u32))
+        )
+        .arg(arg!(--"low-memory" "Read the input file twice instead of keeping every postcode in memory (slower, but uses much less memory)"));
+    #[cfg(feature="sign")]
+    let cmd = cmd
+        .arg(arg!(--sign <key> "Sign the pack file with this Ed25519 private key (PEM), writing the signature to <output>.sig, and the key's fingerprint in the header (needs format version 11 for the fingerprint)"));
+    cmd
 }
 
+fn main() -> ExitCode {
+    let cli = pack_args(command!()
+            .arg(arg!(<input> "Input file name (path to ONS Postcode Database CSV file, or the zip file it came in)"))
+            .arg(arg!(<output> "Output file name"))
+        )
+        .arg(arg!(--"output-format" <format> "Type of file to write: a pack file for NMP (pack, the default), an SQLite database with a postcodes(pcd, lat, long) table (sqlite), GeoJSON points (geojson, or ndjson for one feature on each line), a Parquet file of postcode, lat, long, country and introduced date (parquet), a FlatGeobuf file with a spatial index (flatgeobuf), or a CSV file of postcode, lat, long and a three word name for the location, for demos (words-csv). Only pack files use the pack file options")
+            .value_parser(OutputFormat::ALL.map(|f|f.name()))
+        )
+        .arg(arg!(--verify "Read the output file back after writing it, and check that every postcode is correct"))
+        .arg(arg!(--threads <n> "Number of threads to use (default: one per CPU core)")
+            .value_parser(clap::value_parser!(usize))
+        )
+        .arg(arg!(-q --quiet "Only print errors, and no progress bars").global(true).conflicts_with("verbose"))
+        .arg(arg!(-v --verbose "Print more detail about what is being done").global(true))
+        .arg(arg!(--"json-logs" "Print messages as JSON objects, one per line, with no progress bars").global(true))
+        .subcommand_negates_reqs(true)
+        .subcommand(Command::new("unpack")
+            .about("Convert a pack file back in to a CSV file of postcode,lat,long")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!([output] "Output CSV file name (default: standard output)"))
+        )
+        .subcommand(Command::new("query")
+            .about("Look up postcodes in a pack file, and print them as postcode,lat,long")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(<postcode> ... "Postcodes or outward codes to look up, in any case and with any spacing"))
+        )
+        .subcommand(Command::new("nearest")
+            .about("Find the postcodes nearest to a location, and print them as postcode,lat,long,distance (in metres), nearest first")
+            .allow_negative_numbers(true)
+            .arg(arg!(<input> "Pack file to read, ideally packed with --spatial-index"))
+            .arg(arg!(<lat> "Latitude").value_parser(clap::value_parser!(f64)))
+            .arg(arg!(<long> "Longitude").value_parser(clap::value_parser!(f64)))
+            .arg(arg!(-n --count <k> "Number of postcodes to find (default: 1)").value_parser(clap::value_parser!(usize)))
+        )
+        .subcommand(Command::new("validate")
+            .about("Check a list of postcodes, and print each one as status,postcode, where the status is exists, terminated, absent (well-formed, but not in the pack file) or malformed")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!([list] "File of postcodes or outward codes to check, one per line (default: standard input)"))
+        )
+        .subcommand(Command::new("suggest")
+            .about("List the postcodes that a mistyped postcode could have been meant to be, nearest misses first")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(<postcode> ... "Mistyped postcodes, e.g. \"SW1A IAA\""))
+            .arg(arg!(--"max-edits" <edits> "Most chars changed or swapped, 1 or 2 (default 1)")
+                .value_parser(clap::value_parser!(u8).range(1..=suggest::MAX_EDITS as i64))
+            )
+        )
+        .subcommand(Command::new("distances")
+            .about("Print the distances (in metres) between postcodes, as a CSV matrix with a row and a column for each postcode")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(<postcode> ... "Postcodes or outward codes, in any case and with any spacing").num_args(2..))
+        )
+        .subcommand(Command::new("geocode")
+            .about("Add lat and long columns to a CSV file, by looking up the postcode in one of its columns")
+            .arg(arg!(<pack> "Pack file to read"))
+            .arg(arg!(--input <file> "CSV file to read").required(true))
+            .arg(arg!(--"postcode-column" <column> "Column with the postcodes in it, by number (from 1) or by name").required(true))
+            .arg(arg!(--output <file> "File to write (default: standard output)"))
+            .arg(arg!(--"no-header" "The CSV file has no header row"))
+        )
+        .subcommand(Command::new("centroids")
+            .about("Print the centroids of postcode areas, districts or sectors as name,lat,long,count")
+            .arg(arg!(<input> "Pack file to read, packed with --centroids"))
+            .arg(arg!([name] ... "Areas, districts or sectors to look up (default: all of them)"))
+            .arg(arg!(--level <level> "Which centroids to print (default: district)")
+                .value_parser(CentroidLevel::ALL.map(|l|l.name()))
+            )
+        )
+        .subcommand(input_args(Command::new("outliers"))
+            .about("Print the postcodes that stretch the bounding box the most, in the order --clip-bbox takes them out of it, as postcode,lat,long,minlat,minlong,maxlat,maxlong with the box that is left")
+            .arg(arg!(<input> "CSV file to read, as for packing"))
+            .arg(arg!(-n --count <count> "Number of postcodes to list (default: 10)")
+                .value_parser(clap::value_parser!(usize))
+            )
+        )
+        .subcommand(Command::new("stats")
+            .about("Summarize an input file without packing it: live and terminated postcodes in each area or district, positional quality, and the year each postcode was introduced")
+            .arg(arg!(<input> "CSV file to read, as for packing"))
+            .arg(arg!(--"input-format" <format> "Type of input: onspd or nspl (the default), or codepoint")
+                .value_parser(["onspd", "nspl", "codepoint"])
+            )
+            .arg(arg!(--scheme <scheme> "Which country's codes the input file has (default: uk)")
+                .value_parser(Scheme::ALL.map(|s|s.name()))
+            )
+            .arg(arg!(--by <level> "Count postcodes in each area (the default) or district")
+                .value_parser(["area", "district"])
+            )
+        )
+        .subcommand(Command::new("extents")
+            .about("Print the bounding box of the postcodes in each outward code as outward,minlat,minlong,maxlat,maxlong")
+            .arg(arg!(<input> "Pack file to read, packed with --prefix-extents"))
+            .arg(arg!([outward] ... "Outward codes to look up (default: all of them)"))
+        )
+        .subcommand(Command::new("complete")
+            .about("List the outward codes and sectors, or the full postcodes, that start with partly typed postcodes")
+            .arg(arg!(<input> "Pack file to read, packed with --sector-bitmap unless --postcodes is given"))
+            .arg(arg!(<text> ... "Partly typed postcodes, e.g. SW1 or \"SW1A 2\""))
+            .arg(arg!(--postcodes "List full postcodes instead, from any pack file"))
+            .arg(arg!(-n --count <count> "Most postcodes to list for each text, with --postcodes (default: 20)")
+                .value_parser(clap::value_parser!(usize))
+            )
+        )
+        .subcommand(Command::new("diff")
+            .about("Compare two pack files, and list the postcodes that were added, removed or moved")
+            .arg(arg!(<old> "Older pack file"))
+            .arg(arg!(<new> "Newer pack file"))
+            .arg(arg!(--"min-move" <metres> "Ignore moves shorter than this, to allow for rounding (default 50)")
+                .value_parser(clap::value_parser!(f64))
+            )
+            .arg(arg!(--summary "Only print the number of changes"))
+        )
+        .subcommand(Command::new("compare-releases")
+            .about("Compare two releases, as input files or pack files, and write Markdown release notes: new and retired districts, retired postcodes, and postcodes that moved a long way")
+            .arg(arg!(<old> "Older release, a CSV file as for packing or a pack file"))
+            .arg(arg!(<new> "Newer release, a CSV file as for packing or a pack file"))
+            .arg(arg!(--report <file> "Write the release notes to this file (default: print them)"))
+            .arg(arg!(--"min-move" <metres> "Only list moves longer than this (default 500)")
+                .value_parser(clap::value_parser!(f64))
+            )
+        )
+        .subcommand(Command::new("gen-test-vectors")
+            .about("Write a small pack file and a JSON file of the results that readers should find in it")
+            .arg(arg!(<outdir> "Directory to write vectors.csv, vectors.pack and vectors.json to"))
+            .arg(arg!(--"format-version" <version> "File format version to write (default 2, from 17 the codes are Eircodes)")
+                .value_parser(clap::value_parser!(u32))
+            )
+        )
+        .subcommand(Command::new("make-patch")
+            .about("Create a patch file that turns one pack file in to another")
+            .arg(arg!(<old> "Older pack file"))
+            .arg(arg!(<new> "Newer pack file"))
+            .arg(arg!(<patch> "Patch file to write (.pckpatch)"))
+        )
+        .subcommand(Command::new("apply-patch")
+            .about("Apply a patch file to a pack file")
+            .arg(arg!(<old> "Pack file that the patch was made from"))
+            .arg(arg!(<patch> "Patch file to apply"))
+            .arg(arg!(<output> "New pack file to write"))
+        )
+        .subcommand(Command::new("merge")
+            .about("Combine several pack files in to one, e.g. files built separately for each nation")
+            .arg(arg!(<output> "Pack file to write"))
+            .arg(arg!(<input> ... "Pack files to combine. If a postcode is in more than one of them, the first file's entry is kept"))
+            .arg(arg!(--"format-version" <version> "File format version to write (default: the newest version of the input files)")
+                .value_parser(clap::value_parser!(u32))
+            )
+        )
+        .subcommand(Command::new("extract")
+            .about("Make a smaller pack file with only the postcodes in an area, e.g. just London")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(<output> "Pack file to write"))
+            .arg(arg!(--bbox <box> "Only keep postcodes in this bounding box, given as minlong,minlat,maxlong,maxlat")
+                .value_parser(parse_bbox)
+                .allow_hyphen_values(true)
+            )
+            .arg(arg!(--prefix <prefixes> "Only keep postcodes that start with one of these comma separated prefixes, e.g. SW,SE,E. A prefix of only letters is a whole postcode area")
+                .value_delimiter(',')
+            )
+        )
+        .subcommand(Command::new("upgrade")
+            .about("Rewrite a pack file of any version in the newest format version, without the CSV file it was made from")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(<output> "Pack file to write"))
+            .arg(arg!(--"format-version" <version> "File format version to write (default: the newest)")
+                .value_parser(clap::value_parser!(u32))
+            )
+            .arg(arg!(--"spatial-index" [kind] "Add a spatial index, or change its kind: grid (the default), kdtree, geohash or geohash:<precision>")
+                .value_parser(parse_index_kind)
+                .num_args(0..=1)
+                .default_missing_value("grid")
+            )
+            .arg(arg!(--centroids "Add the location and number of postcodes of each postcode area, district and sector"))
+            .arg(arg!(--"prefix-extents" "Add the bounding box of the postcodes in each outward code"))
+            .arg(arg!(--"sector-bitmap" "Add the list of outward codes and sectors that exist"))
+            .arg(arg!(--entropy "Huffman code each block of postcode data"))
+            .arg(arg!(--groups "Quantize groups of postcodes far apart from each other in their own bounding boxes, instead of local bounding boxes or a clipped bounding box"))
+            .arg(arg!(--"chunk-size" <bytes> "Align the prefix blocks to chunks of this many bytes")
+                .value_parser(clap::value_parser!(u32))
+            )
+            .arg(arg!(--order <order> "Order of the postcodes in each prefix: alpha or hilbert")
+                .value_parser(RecordOrder::ALL.map(|o|o.name()))
+            )
+        )
+        .subcommand(Command::new("inspect")
+            .about("Print the header of a pack file and statistics about its contents")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(--all "List the number of entries for every prefix, not just the largest"))
+        );
+    #[cfg(feature="sign")]
+    let cli = cli
+        .subcommand(Command::new("verify-signature")
+            .about("Check that a pack file was signed with a key, and hasn't changed since")
+            .arg(arg!(<input> "Pack file to check"))
+            .arg(arg!(<key> "Public key of the signer (PEM)"))
+            .arg(arg!(--signature <file> "Signature file (default: the pack file name with .sig)"))
+        );
+    #[cfg(feature="fetch")]
+    let cli = cli
+        .subcommand(fetch_args(Command::new("fetch")
+            .about("Download the ONS postcode database from the ONS Open Geography portal, and cache it")
+            .arg(arg!(--list "List the releases that can be downloaded"))
+        ))
+        .subcommand(pack_args(fetch_args(Command::new("build")
+            .about("Download the ONS postcode database, pack it, verify it, and write a manifest (<output>.manifest.json)")
+            .arg(arg!(<output> "Output file name"))
+        )));
+    #[cfg(feature="serve")]
+    let cli = cli
+        .subcommand(Command::new("serve")
+            .about("Serve postcode lookups from a pack file over HTTP, as JSON (GET /lookup/<postcode> and /nearest?lat=<lat>&long=<long>)")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(--listen <address> "Address and port to listen on (default: 127.0.0.1:8080)"))
+        );
+    #[cfg(feature="boundary")]
+    let cli = cli
+        .subcommand(Command::new("filter-region")
+            .about("Make a smaller pack file with only the postcodes inside a boundary, e.g. a local authority")
+            .arg(arg!(<input> "Pack file to read"))
+            .arg(arg!(<output> "Pack file to write"))
+            .arg(arg!(--geojson <file> "GeoJSON file of the boundary: a Polygon or MultiPolygon, or a Feature or FeatureCollection of them")
+                .required(true)
+            )
+        );
+    let matches = cli.get_matches();
+
+    let json = matches.get_flag("json-logs");
+    let level = if matches.get_flag("quiet") { log::LevelFilter::Error }
+        else if matches.get_flag("verbose") { log::LevelFilter::Debug }
+        else { log::LevelFilter::Info };
+    SHOW_PROGRESS.store(!json && level >= log::LevelFilter::Info, Ordering::Relaxed);
+    if log::set_logger(Box::leak(Box::new(Logger{json}))).is_ok(){
+        log::set_max_level(level);
+    }
+
+    if let Some(n) = matches.get_one::<usize>("threads"){
+        if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(*n).build_global(){
+            error!("Error setting up threads: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(("unpack", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let outfilename = sub.get_one::<String>("output").map(|s|s.as_str());
+        return match do_unpack(infilename, outfilename){
+            Err(e) => { error!("Error unpacking postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("query", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let postcodes: Vec<&String> = sub.get_many::<String>("postcode").expect("No postcode").collect();
+        return match do_query(infilename, &postcodes){
+            Err(e) => { error!("Error looking up postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("validate", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let listfilename = sub.get_one::<String>("list").map(|s| s.as_str());
+        return match do_validate(infilename, listfilename){
+            Err(e) => { error!("Error checking postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("suggest", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let postcodes: Vec<&String> = sub.get_many::<String>("postcode").map(|p| p.collect()).unwrap_or_default();
+        let max_edits = sub.get_one::<u8>("max-edits").map(|&e| e as usize).unwrap_or(1);
+        return match do_suggest(infilename, &postcodes, max_edits){
+            Err(e) => { error!("Error suggesting postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("nearest", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let lat = *sub.get_one::<f64>("lat").expect("No latitude");
+        let long = *sub.get_one::<f64>("long").expect("No longitude");
+        let k = sub.get_one::<usize>("count").copied().unwrap_or(1);
+        return match do_nearest(infilename, lat, long, k){
+            Err(e) => { error!("Error finding postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("distances", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let postcodes: Vec<&String> = sub.get_many::<String>("postcode").expect("No postcode").collect();
+        return match do_distances(infilename, &postcodes){
+            Err(e) => { error!("Error measuring distances: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("geocode", sub)) = matches.subcommand(){
+        let packfilename = sub.get_one::<String>("pack").expect("No pack file");
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let column = geocode::Column::parse(sub.get_one::<String>("postcode-column").expect("No postcode column"));
+        let outfilename = sub.get_one::<String>("output").map(|s|s.as_str());
+        return match do_geocode(packfilename, infilename, &column, outfilename, !sub.get_flag("no-header")){
+            Err(e) => { error!("Error geocoding {infilename}: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("outliers", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let count = sub.get_one::<usize>("count").copied().unwrap_or(10);
+        let options = match read_options(sub){
+            Ok(options) => options,
+            Err(e) => { error!("Error finding outliers: {e}"); return ExitCode::FAILURE }
+        };
+        return match do_outliers(infilename, &options, count){
+            Err(e) => { error!("Error finding outliers: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("stats", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let options = ReadOptions{
+            format: if sub.get_one::<String>("input-format").is_some_and(|f| f == "codepoint") { InputFormat::CodePoint } else { InputFormat::Onspd },
+            scheme: sub.get_one::<String>("scheme").and_then(|s| Scheme::from_name(s)).unwrap_or_default(),
+            ..Default::default()
+        };
+        let by_district = sub.get_one::<String>("by").is_some_and(|b| b == "district");
+        return match do_stats(infilename, &options, by_district){
+            Err(e) => { error!("Error reading input file: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("extents", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let names: Vec<&String> = sub.get_many::<String>("outward").map(|n| n.collect()).unwrap_or_default();
+        return match do_extents(infilename, &names){
+            Err(e) => { error!("Error reading prefix extents: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("complete", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let texts: Vec<&String> = sub.get_many::<String>("text").map(|t| t.collect()).unwrap_or_default();
+        let postcodes = sub.get_flag("postcodes").then(|| sub.get_one::<usize>("count").copied().unwrap_or(20));
+        return match do_complete(infilename, &texts, postcodes){
+            Err(e) => { error!("Error completing postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("centroids", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let names: Vec<&String> = sub.get_many::<String>("name").map(|n| n.collect()).unwrap_or_default();
+        let level = sub.get_one::<String>("level")
+            .and_then(|l| CentroidLevel::from_name(l))
+            .unwrap_or(CentroidLevel::District);
+        return match do_centroids(infilename, level, &names){
+            Err(e) => { error!("Error reading centroids: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("diff", sub)) = matches.subcommand(){
+        let oldfilename = sub.get_one::<String>("old").expect("No old file");
+        let newfilename = sub.get_one::<String>("new").expect("No new file");
+        let min_move = sub.get_one::<f64>("min-move").copied().unwrap_or(diff::DEFAULT_MIN_MOVE);
+        return match do_diff(oldfilename, newfilename, min_move, sub.get_flag("summary")){
+            Err(e) => { error!("Error comparing pack files: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("compare-releases", sub)) = matches.subcommand(){
+        let oldfilename = sub.get_one::<String>("old").expect("No old file");
+        let newfilename = sub.get_one::<String>("new").expect("No new file");
+        let min_move = sub.get_one::<f64>("min-move").copied().unwrap_or(diff::DEFAULT_NOTABLE_MOVE);
+        return match do_compare_releases(oldfilename, newfilename, sub.get_one::<String>("report"), min_move){
+            Err(e) => { error!("Error comparing releases: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("gen-test-vectors", sub)) = matches.subcommand(){
+        let outdir = sub.get_one::<String>("outdir").expect("No output directory");
+        let version = sub.get_one::<u32>("format-version").copied().unwrap_or(DEFAULT_VERSION);
+        return match do_gen_test_vectors(outdir, version){
+            Err(e) => { error!("Error writing test vectors: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    #[cfg(feature="fetch")]
+    if let Some(("build", sub)) = matches.subcommand(){
+        return match do_build(sub){
+            Err(e) => { error!("Error building pack file: {e}"); ExitCode::FAILURE }
+            Ok(_) => { info!("Complete"); ExitCode::SUCCESS }
+        };
+    }
+
+    #[cfg(feature="fetch")]
+    if let Some(("fetch", sub)) = matches.subcommand(){
+        let arg = |name: &str| sub.get_one::<String>(name).map(|s| s.as_str());
+        return match do_fetch(arg("release"), arg("url"), arg("sha256"), arg("cache-dir"), sub.get_flag("list")){
+            Err(e) => { error!("Error fetching postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    #[cfg(feature="serve")]
+    if let Some(("serve", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let address = sub.get_one::<String>("listen").map(|s| s.as_str()).unwrap_or("127.0.0.1:8080");
+        return match do_serve(infilename, address, rayon::current_num_threads()){
+            Err(e) => { error!("Error serving postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    #[cfg(feature="boundary")]
+    if let Some(("filter-region", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let outfilename = sub.get_one::<String>("output").expect("No output file");
+        let boundary = sub.get_one::<String>("geojson").expect("No boundary file");
+        return match do_filter_region(infilename, outfilename, boundary){
+            Err(e) => { error!("Error extracting postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    #[cfg(feature="sign")]
+    if let Some(("verify-signature", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let keyfilename = sub.get_one::<String>("key").expect("No key file");
+        let sigfilename = sub.get_one::<String>("signature").map(|s|s.as_str());
+        return match do_verify_signature(infilename, keyfilename, sigfilename){
+            Err(e) => { error!("Error verifying signature: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("make-patch", sub)) = matches.subcommand(){
+        let oldfilename = sub.get_one::<String>("old").expect("No old file");
+        let newfilename = sub.get_one::<String>("new").expect("No new file");
+        let patchfilename = sub.get_one::<String>("patch").expect("No patch file");
+        return match do_make_patch(oldfilename, newfilename, patchfilename){
+            Err(e) => { error!("Error creating patch: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("apply-patch", sub)) = matches.subcommand(){
+        let oldfilename = sub.get_one::<String>("old").expect("No old file");
+        let patchfilename = sub.get_one::<String>("patch").expect("No patch file");
+        let outfilename = sub.get_one::<String>("output").expect("No output file");
+        return match do_apply_patch(oldfilename, patchfilename, outfilename){
+            Err(e) => { error!("Error applying patch: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("merge", sub)) = matches.subcommand(){
+        let outfilename = sub.get_one::<String>("output").expect("No output file");
+        let infilenames: Vec<&String> = sub.get_many::<String>("input").expect("No input files").collect();
+        let version = sub.get_one::<u32>("format-version").copied();
+        return match do_merge(outfilename, &infilenames, version){
+            Err(e) => { error!("Error merging pack files: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("extract", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let outfilename = sub.get_one::<String>("output").expect("No output file");
+        let bbox = sub.get_one::<(Point, Point)>("bbox").copied();
+        let prefixes: Vec<&str> = sub.get_many::<String>("prefix").unwrap_or_default().map(|p| p.as_str()).collect();
+        return match do_extract(infilename, outfilename, bbox, &prefixes){
+            Err(e) => { error!("Error extracting postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("upgrade", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        let outfilename = sub.get_one::<String>("output").expect("No output file");
+        return match do_upgrade(infilename, outfilename, sub){
+            Err(e) => { error!("Error upgrading pack file: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    if let Some(("inspect", sub)) = matches.subcommand(){
+        let infilename = sub.get_one::<String>("input").expect("No input file");
+        return match do_inspect(infilename, sub.get_flag("all")){
+            Err(e) => { error!("Error inspecting postcodes: {e}"); ExitCode::FAILURE }
+            Ok(_) => ExitCode::SUCCESS,
+        };
+    }
+
+    let infilename = matches.get_one::<String>("input").expect("No input file");
+    let outfilename = matches.get_one::<String>("output").expect("No output file");
+    match do_postcode_repack(infilename, outfilename, &matches, matches.get_flag("verify")){
+        Err(e) => { error!("Error repacking postcodes: {e}"); ExitCode::FAILURE }
+        Ok(_) => { info!("Complete"); ExitCode::SUCCESS }
+    }
+}
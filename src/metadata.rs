@@ -0,0 +1,108 @@
+/*
+
+How a pack file was made, from format version 23 onwards.
+
+A pack file that turns up long after it was made can say what made it, from which dataset, with
+which options, and anything else its maker wanted to note. These are text fields, kept in the
+header (just before the bounding box extents), so a patch copies them as they are. The packer
+writes these fields, but any others can be added:
+
+    generator: name and version of the program that wrote the file, e.g. "nearmypostcode_packer 0.1.0"
+    source:    the dataset it was made from, e.g. "ONSPD_FEB_2025_UK.csv"
+    options:   the options it was packed with, e.g. "--exclude BT --sector-bitmap"
+    comment:   free text
+
+Metadata, variable length:
+
+    metadata_len: 4 bytes (u32, length of the fields, 0 if there are none)
+    fields: one after the other, in the order they were set
+        key_len:   1 byte (u8)
+        key:       key_len bytes (ASCII)
+        value_len: 2 bytes (u16)
+        value:     value_len bytes (UTF-8)
+
+*/
+#[cfg(feature="packer")]
+use crate::PostcodeError;
+
+/// Text fields that say how a pack file was made
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata{
+    fields: Vec<(String, String)>,
+}
+
+impl Metadata{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// Metadata that names this packer as the generator
+    pub fn generated() -> Self{
+        let mut metadata = Self::new();
+        metadata.set("generator", &format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+        metadata
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.fields.is_empty()
+    }
+
+    /// Value of a field, `None` if it isn't set
+    pub fn get(&self, key: &str) -> Option<&str>{
+        self.fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Set a field, replacing its value if it's already set. Fields that aren't set yet go
+    /// after the others.
+    pub fn set(&mut self, key: &str, value: &str){
+        match self.fields.iter_mut().find(|(k, _)| k == key){
+            Some((_, v)) => *v = value.to_string(),
+            None => self.fields.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Every field, in order
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> + '_{
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Check that the fields can be stored
+    #[cfg(feature="packer")]
+    pub(crate) fn check(&self) -> Result<(), PostcodeError>{
+        if self.fields.iter().any(|(k, _)| k.is_empty() || k.len() > 255 || !k.is_ascii()){
+            return Err(PostcodeError::IncompatibleOptions("metadata keys must be 1 to 255 ASCII characters"));
+        }
+        if self.fields.iter().any(|(_, v)| v.len() > u16::MAX as usize){
+            return Err(PostcodeError::IncompatibleOptions("metadata values can be at most 65535 bytes long"));
+        }
+        Ok(())
+    }
+
+    /// The fields, without their length
+    #[cfg(feature="packer")]
+    pub(crate) fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::new();
+        for (key, value) in &self.fields{
+            out.push(key.len() as u8);
+            out.extend_from_slice(key.as_bytes());
+            out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            out.extend_from_slice(value.as_bytes());
+        }
+        out
+    }
+
+    pub(crate) fn from_bytes(data: &[u8]) -> Option<Self>{
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while pos < data.len(){
+            let key_len = data[pos] as usize;
+            let key = std::str::from_utf8(data.get(pos+1..pos+1+key_len)?).ok()?;
+            pos += 1 + key_len;
+            let value_len = u16::from_le_bytes(data.get(pos..pos+2)?.try_into().unwrap()) as usize;
+            let value = std::str::from_utf8(data.get(pos+2..pos+2+value_len)?).ok()?;
+            pos += 2 + value_len;
+            fields.push((key.to_string(), value.to_string()));
+        }
+        Some(Self{fields})
+    }
+}
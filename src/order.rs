@@ -0,0 +1,99 @@
+/*
+
+The order of the records in each prefix block.
+
+Records are normally in order of postcode, so that the postcode deltas are small. The location
+deltas are only small when postcodes next to each other in that order are close together, which
+is common but far from always: a district's sectors can be on opposite sides of a town. In
+Hilbert order, the records of each block are in order along a Hilbert curve through their
+quantized locations instead, so the ±128 location deltas are used much more often, and a
+postcode delta only when the next code along the curve is also higher. Which is smaller depends
+on the data, so `inspect` gives the size of the records in both orders.
+
+Readers search each block from its start whatever the order, and the blocks themselves are always
+in order of prefix. Only version 26 files can say what order their records are in (in the record
+order section, see section.rs), and patches and spatial indexes need to know it, because they
+count the entries of a block in the order they're written.
+
+*/
+use std::fmt::{Display, Formatter};
+use crate::PREFIX_LEN;
+
+/// How the records are ordered within each prefix block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordOrder{
+    /// In order of postcode
+    #[default]
+    Alphabetical,
+    /// Along a Hilbert curve through the quantized locations, then in order of postcode
+    Hilbert,
+}
+
+impl RecordOrder{
+    pub const ALL: [RecordOrder;2] = [RecordOrder::Alphabetical, RecordOrder::Hilbert];
+
+    /// Value stored in the file to identify the order
+    pub fn id(&self) -> u32{
+        match self{
+            RecordOrder::Alphabetical => 0,
+            RecordOrder::Hilbert => 1,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self>{
+        Self::ALL.into_iter().find(|o| o.id() == id)
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            RecordOrder::Alphabetical => "alpha",
+            RecordOrder::Hilbert => "hilbert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|o| o.name() == name)
+    }
+}
+
+impl Display for RecordOrder{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Distance along a Hilbert curve that fills the whole range of u32 coordinates
+pub(crate) fn hilbert(x: u32, y: u32) -> u64{
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut d = 0;
+    let mut s: u64 = 1 << 31;
+    while s > 0{
+        let rx = u64::from(x & s > 0);
+        let ry = u64::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // Turn the quadrant so that the curve through it starts and ends at the right corners
+        if ry == 0{
+            if rx == 1{
+                x = u32::MAX as u64 - x;
+                y = u32::MAX as u64 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    d
+}
+
+/// Put the entries of each prefix block in `order`. The entries must be in order of postcode,
+/// and `entry` gives the canonical postcode and quantized (long, lat) of each one.
+pub(crate) fn arrange<T>(entries: &mut [T], order: RecordOrder, entry: impl Fn(&T) -> ([u8;7], u32, u32)){
+    if order == RecordOrder::Alphabetical{
+        return;
+    }
+    for block in entries.chunk_by_mut(|a, b| entry(a).0[..PREFIX_LEN] == entry(b).0[..PREFIX_LEN]){
+        block.sort_by_cached_key(|e| {
+            let (postcode, long, lat) = entry(e);
+            (hilbert(long, lat), postcode)
+        });
+    }
+}
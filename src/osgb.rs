@@ -0,0 +1,249 @@
+/*
+
+Conversion from Ordnance Survey grid references (eastings and northings) to WGS84 lat/long,
+and from WGS84 lat/long to the other datums that a pack file can be in (see crs.rs).
+
+Postcodes in Great Britain use the OSGB36 National Grid. The ONS postcode database gives
+Northern Ireland postcodes in the Irish Grid instead, which uses a different projection and datum.
+
+The method is the one described in "A guide to coordinate systems in Great Britain" (Ordnance
+Survey): inverse Transverse Mercator projection to lat/long on the grid's ellipsoid, then a
+Helmert transformation to WGS84. The Helmert transformation is accurate to a few metres, which
+is well within the resolution of the packed format.
+
+WGS84 and ETRS89 are the same datum in 1989, but ETRS89 moves with the Eurasian plate, which
+drifts about 2.5cm a year north east relative to WGS84 (which is kept in line with ITRF). The
+shift between them is the Helmert transformation from ITRF2014 to ETRF2000, whose parameters
+change each year, at the epoch `ETRS89_EPOCH`. In 2025 this is about 0.8m.
+
+*/
+use crate::Point;
+
+struct Ellipsoid{
+    a: f64,
+    b: f64,
+}
+
+struct Grid{
+    ellipsoid: Ellipsoid,
+    /// Scale factor on the central meridian
+    f0: f64,
+    /// True origin, in degrees
+    lat0: f64,
+    long0: f64,
+    /// False origin, in metres
+    e0: f64,
+    n0: f64,
+}
+
+/// Helmert transformation from one datum to another
+struct Helmert{
+    /// Translation, in metres
+    tx: f64,
+    ty: f64,
+    tz: f64,
+    /// Scale, in ppm
+    s: f64,
+    /// Rotation, in arc seconds
+    rx: f64,
+    ry: f64,
+    rz: f64,
+}
+
+const AIRY_1830: Ellipsoid = Ellipsoid{a: 6377563.396, b: 6356256.909};
+const AIRY_MODIFIED: Ellipsoid = Ellipsoid{a: 6377340.189, b: 6356034.447};
+const WGS84: Ellipsoid = Ellipsoid{a: 6378137.000, b: 6356752.3142};
+
+const NATIONAL_GRID: Grid = Grid{
+    ellipsoid: AIRY_1830,
+    f0: 0.9996012717,
+    lat0: 49.0,
+    long0: -2.0,
+    e0: 400000.0,
+    n0: -100000.0,
+};
+
+const IRISH_GRID: Grid = Grid{
+    ellipsoid: AIRY_MODIFIED,
+    f0: 1.000035,
+    lat0: 53.5,
+    long0: -8.0,
+    e0: 200000.0,
+    n0: 250000.0,
+};
+
+const OSGB36_TO_WGS84: Helmert = Helmert{
+    tx: 446.448, ty: -125.157, tz: 542.060,
+    s: -20.4894,
+    rx: 0.1502, ry: 0.2470, rz: 0.8421,
+};
+
+/// WGS84 is taken to be ITRF2014, which it is within a few centimetres. The parameters are at
+/// 2010.0, see `itrf2014_to_etrf2000`.
+const ITRF2014_TO_ETRF2000: Helmert = Helmert{
+    tx: 0.0547, ty: 0.0522, tz: -0.0741,
+    s: 0.00212,
+    rx: 0.001701, ry: 0.010290, rz: -0.016632,
+};
+
+/// Yearly change of each of the parameters of `ITRF2014_TO_ETRF2000`
+const ITRF2014_TO_ETRF2000_RATES: Helmert = Helmert{
+    tx: 0.0001, ty: 0.0001, tz: -0.0019,
+    s: 0.00011,
+    rx: 0.000081, ry: 0.000490, rz: -0.000792,
+};
+
+/// Epoch (as a year) that locations are shifted to ETRS89 at. A year either side moves them
+/// about 2.5cm, far less than the step between stored locations.
+pub const ETRS89_EPOCH: f64 = 2025.0;
+
+const IRELAND1965_TO_WGS84: Helmert = Helmert{
+    tx: 482.530, ty: -130.596, tz: 564.557,
+    s: 8.150,
+    rx: -1.042, ry: -0.214, rz: -0.631,
+};
+
+/// Inverse Transverse Mercator projection, returns (lat, long) in radians
+fn grid_to_latlong(grid: &Grid, e: f64, n: f64) -> (f64, f64){
+    let Ellipsoid{a, b} = grid.ellipsoid;
+    let f0 = grid.f0;
+    let lat0 = grid.lat0.to_radians();
+    let long0 = grid.long0.to_radians();
+    let e2 = 1.0 - (b*b)/(a*a);
+    let n_ = (a-b)/(a+b);
+    let (n2, n3) = (n_*n_, n_*n_*n_);
+
+    // Meridional arc
+    let arc = |lat: f64| b * f0 * (
+        (1.0 + n_ + (5.0/4.0)*n2 + (5.0/4.0)*n3) * (lat-lat0)
+        - (3.0*n_ + 3.0*n2 + (21.0/8.0)*n3) * (lat-lat0).sin() * (lat+lat0).cos()
+        + ((15.0/8.0)*n2 + (15.0/8.0)*n3) * (2.0*(lat-lat0)).sin() * (2.0*(lat+lat0)).cos()
+        - (35.0/24.0)*n3 * (3.0*(lat-lat0)).sin() * (3.0*(lat+lat0)).cos()
+    );
+
+    let mut lat = lat0;
+    let mut m = 0.0;
+    loop{
+        lat += (n - grid.n0 - m) / (a*f0);
+        m = arc(lat);
+        if (n - grid.n0 - m).abs() < 0.00001{
+            break;
+        }
+    }
+
+    let sin_lat = lat.sin();
+    let nu = a*f0 / (1.0 - e2*sin_lat*sin_lat).sqrt();
+    let rho = a*f0*(1.0-e2) / (1.0 - e2*sin_lat*sin_lat).powf(1.5);
+    let eta2 = nu/rho - 1.0;
+
+    let tan_lat = lat.tan();
+    let (tan2, tan4, tan6) = (tan_lat*tan_lat, tan_lat.powi(4), tan_lat.powi(6));
+    let sec_lat = 1.0/lat.cos();
+    let vii = tan_lat/(2.0*rho*nu);
+    let viii = tan_lat/(24.0*rho*nu.powi(3)) * (5.0 + 3.0*tan2 + eta2 - 9.0*tan2*eta2);
+    let ix = tan_lat/(720.0*rho*nu.powi(5)) * (61.0 + 90.0*tan2 + 45.0*tan4);
+    let x = sec_lat/nu;
+    let xi = sec_lat/(6.0*nu.powi(3)) * (nu/rho + 2.0*tan2);
+    let xii = sec_lat/(120.0*nu.powi(5)) * (5.0 + 28.0*tan2 + 24.0*tan4);
+    let xiia = sec_lat/(5040.0*nu.powi(7)) * (61.0 + 662.0*tan2 + 1320.0*tan4 + 720.0*tan6);
+
+    let de = e - grid.e0;
+    let lat = lat - vii*de.powi(2) + viii*de.powi(4) - ix*de.powi(6);
+    let long = long0 + x*de - xi*de.powi(3) + xii*de.powi(5) - xiia*de.powi(7);
+    (lat, long)
+}
+
+/// Lat/long (in radians, at zero height) to cartesian coordinates
+fn to_cartesian(ellipsoid: &Ellipsoid, lat: f64, long: f64) -> (f64, f64, f64){
+    let Ellipsoid{a, b} = *ellipsoid;
+    let e2 = 1.0 - (b*b)/(a*a);
+    let nu = a / (1.0 - e2*lat.sin().powi(2)).sqrt();
+    (
+        nu * lat.cos() * long.cos(),
+        nu * lat.cos() * long.sin(),
+        (1.0-e2) * nu * lat.sin(),
+    )
+}
+
+/// Cartesian coordinates to lat/long, in radians
+fn from_cartesian(ellipsoid: &Ellipsoid, x: f64, y: f64, z: f64) -> (f64, f64){
+    let Ellipsoid{a, b} = *ellipsoid;
+    let e2 = 1.0 - (b*b)/(a*a);
+    let p = (x*x + y*y).sqrt();
+    let mut lat = z.atan2(p*(1.0-e2));
+    loop{
+        let nu = a / (1.0 - e2*lat.sin().powi(2)).sqrt();
+        let next = (z + e2*nu*lat.sin()).atan2(p);
+        if (next-lat).abs() < 1e-12{
+            lat = next;
+            break;
+        }
+        lat = next;
+    }
+    (lat, y.atan2(x))
+}
+
+fn helmert(t: &Helmert, x: f64, y: f64, z: f64) -> (f64, f64, f64){
+    let s = 1.0 + t.s/1e6;
+    let rx = (t.rx/3600.0).to_radians();
+    let ry = (t.ry/3600.0).to_radians();
+    let rz = (t.rz/3600.0).to_radians();
+    (
+        t.tx + (x*s) - (y*rz) + (z*ry),
+        t.ty + (x*rz) + (y*s) - (z*rx),
+        t.tz - (x*ry) + (y*rx) + (z*s),
+    )
+}
+
+/// The transformation from ITRF2014 to ETRF2000 at an epoch
+fn itrf2014_to_etrf2000(epoch: f64) -> Helmert{
+    let (t, r, years) = (ITRF2014_TO_ETRF2000, ITRF2014_TO_ETRF2000_RATES, epoch - 2010.0);
+    Helmert{
+        tx: t.tx + r.tx*years, ty: t.ty + r.ty*years, tz: t.tz + r.tz*years,
+        s: t.s + r.s*years,
+        rx: t.rx + r.rx*years, ry: t.ry + r.ry*years, rz: t.rz + r.rz*years,
+    }
+}
+
+/// The reverse of a transformation, to the same accuracy
+fn inverse(t: &Helmert) -> Helmert{
+    Helmert{tx: -t.tx, ty: -t.ty, tz: -t.tz, s: -t.s, rx: -t.rx, ry: -t.ry, rz: -t.rz}
+}
+
+/// Shift a WGS84 lat/long to another datum
+fn shift_wgs84(p: Point, transform: &Helmert, ellipsoid: &Ellipsoid) -> Point{
+    let (x, y, z) = to_cartesian(&WGS84, p.y.to_radians(), p.x.to_radians());
+    let (x, y, z) = helmert(transform, x, y, z);
+    let (lat, long) = from_cartesian(ellipsoid, x, y, z);
+    Point{x: long.to_degrees(), y: lat.to_degrees()}
+}
+
+/// Convert a WGS84 lat/long to ETRS89, at `ETRS89_EPOCH`
+pub fn wgs84_to_etrs89(p: Point) -> Point{
+    // ETRS89 uses the GRS80 ellipsoid, which is WGS84's to within a tenth of a millimetre
+    shift_wgs84(p, &itrf2014_to_etrf2000(ETRS89_EPOCH), &WGS84)
+}
+
+/// Convert a WGS84 lat/long to a lat/long on the OSGB36 datum (Airy 1830), the one that the
+/// National Grid is projected from
+pub fn wgs84_to_osgb36(p: Point) -> Point{
+    shift_wgs84(p, &inverse(&OSGB36_TO_WGS84), &AIRY_1830)
+}
+
+fn grid_to_wgs84(grid: &Grid, transform: &Helmert, e: f64, n: f64) -> Point{
+    let (lat, long) = grid_to_latlong(grid, e, n);
+    let (x, y, z) = to_cartesian(&grid.ellipsoid, lat, long);
+    let (x, y, z) = helmert(transform, x, y, z);
+    let (lat, long) = from_cartesian(&WGS84, x, y, z);
+    Point{x: long.to_degrees(), y: lat.to_degrees()}
+}
+
+/// Convert an OSGB36 National Grid reference to WGS84
+pub fn osgb36_to_wgs84(easting: f64, northing: f64) -> Point{
+    grid_to_wgs84(&NATIONAL_GRID, &OSGB36_TO_WGS84, easting, northing)
+}
+
+/// Convert an Irish Grid reference (as used in Northern Ireland) to WGS84
+pub fn irish_grid_to_wgs84(easting: f64, northing: f64) -> Point{
+    grid_to_wgs84(&IRISH_GRID, &IRELAND1965_TO_WGS84, easting, northing)
+}
@@ -0,0 +1,195 @@
+/*
+
+Entries far from the rest, which stretch the bounding box, from format version 24 onwards.
+
+Locations are quantized across the bounding box of the whole file, so a few remote postcodes (on
+Shetland or the Isles of Scilly) make every location in the file less precise. The packer can find
+the postcodes that stretch the box the most, by taking away one at a time whichever postcode on
+the edge of the box leaves the smallest box (by area). With `--clip-bbox` the file's box is made
+without them, and their exact locations are kept in a list of outliers in the header.
+
+Their records are still written, with the locations clamped to the edge of the box, so lookups, the
+spatial index and patches work as they did. Readers use the location from the list instead. An
+outward code whose average location is outside the box is an outlier too. The centroids and prefix
+extents are still quantized in the box, so those of an area that is all outliers are on its edge.
+
+Outliers, variable length (in the header, after the metadata):
+
+    count: 4 bytes (u32, number of outliers)
+    outliers: count of these, sorted by postcode
+        postcode: 7 bytes (canonical, outward codes padded to 7 chars)
+        long:     4 bytes (u32, fraction of the range -180 to 180, like the bounding box)
+        lat:      4 bytes (u32, fraction of the range -90 to 90)
+
+*/
+#[cfg(feature="packer")]
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{Point, dequantize_extent};
+#[cfg(feature="packer")]
+use crate::{PostcodeInfo, quantize_extent};
+
+const ENTRY_LEN: usize = 15;
+
+/// Most postcodes that can be taken out of the bounding box
+pub const MAX_OUTLIERS: usize = 1000;
+
+/// A postcode that stretches the bounding box, from `Packer::outliers`
+#[derive(Debug, Clone)]
+pub struct Outlier{
+    pub postcode: String,
+    pub location: Point,
+    /// Lower left and upper right corners of the box around the postcodes that are left, once
+    /// this one and the ones before it are taken out
+    pub bounds: (Point, Point),
+}
+
+/// The postcodes furthest out on each side (west, south, east and north), which are the only ones
+/// that can be taken out of the box
+#[cfg(feature="packer")]
+#[derive(Debug, Clone)]
+pub(crate) struct Extremes{
+    keep: usize,
+    /// Furthest out first, ties in postcode order so that the input order doesn't matter
+    sides: [Vec<(f64, String, Point)>; 4],
+}
+
+#[cfg(feature="packer")]
+impl Extremes{
+    /// Keep enough postcodes to take `count` of them out
+    pub fn new(count: usize) -> Self{
+        Self{keep: count + 1, sides: Default::default()}
+    }
+
+    pub fn add(&mut self, p: &PostcodeInfo){
+        if p.is_partial{
+            return;
+        }
+        let distances = [-p.location.x, -p.location.y, p.location.x, p.location.y];
+        for (side, d) in self.sides.iter_mut().zip(distances){
+            let further = |(sd, sp, _): &(f64, String, Point)| *sd > d || (*sd == d && *sp < p.postcode);
+            if side.len() == self.keep && further(&side[self.keep - 1]){
+                continue;
+            }
+            let at = side.partition_point(further);
+            side.insert(at, (d, p.postcode.clone(), p.location));
+            side.truncate(self.keep);
+        }
+    }
+
+    /// The box around what's left on each side, without the postcodes in `taken`, `None` if
+    /// there's nothing left
+    fn bounds(&self, taken: &HashSet<&str>) -> Option<(Point, Point)>{
+        let mut edges = [0.0; 4];
+        for (edge, side) in edges.iter_mut().zip(&self.sides){
+            *edge = side.iter().find(|(_, p, _)| !taken.contains(&p.as_str()))?.0;
+        }
+        Some((Point{x: -edges[0], y: -edges[1]}, Point{x: edges[2], y: edges[3]}))
+    }
+
+    /// Take out up to `count` postcodes, one at a time, each time the one that leaves the smallest
+    /// box. At least one postcode is always left.
+    pub fn outliers(&self, count: usize) -> Vec<Outlier>{
+        let area = |(min, max): (Point, Point)| (max.x - min.x) * (max.y - min.y);
+        let mut taken: HashSet<&str> = HashSet::new();
+        let mut outliers = Vec::new();
+        while outliers.len() < count.min(self.keep - 1){
+            // Only the postcodes on the edge of the box can make it smaller
+            let mut best: Option<(f64, &str, Point, (Point, Point))> = None;
+            for side in &self.sides{
+                let Some((_, postcode, location)) = side.iter().find(|(_, p, _)| !taken.contains(&p.as_str())) else{
+                    return outliers;
+                };
+                taken.insert(postcode);
+                let left = self.bounds(&taken);
+                taken.remove(postcode.as_str());
+                let Some(left) = left else{
+                    return outliers;
+                };
+                let smaller = |(a, p, _, _): &(f64, &str, Point, (Point, Point))| area(left) < *a || (area(left) == *a && postcode.as_str() < *p);
+                if best.as_ref().is_none_or(smaller){
+                    best = Some((area(left), postcode, *location, left));
+                }
+            }
+            let (_, postcode, location, bounds) = best.unwrap();
+            taken.insert(postcode);
+            outliers.push(Outlier{postcode: postcode.to_string(), location, bounds});
+        }
+        outliers
+    }
+}
+
+/// The entries outside the file's bounding box, by canonical postcode padded to 7 chars
+#[cfg(feature="packer")]
+pub(crate) struct OutlierTotals{
+    entries: BTreeMap<String, Point>,
+    minll: Point,
+    maxll: Point,
+}
+
+#[cfg(feature="packer")]
+impl OutlierTotals{
+    /// For the bounding box as it is stored in the file
+    pub fn new(minll: Point, maxll: Point) -> Self{
+        Self{entries: BTreeMap::new(), minll, maxll}
+    }
+
+    pub fn add(&mut self, p: &PostcodeInfo){
+        let l = p.location;
+        if l.x < self.minll.x || l.x > self.maxll.x || l.y < self.minll.y || l.y > self.maxll.y{
+            self.entries.insert(format!("{:<7}", p.postcode), l);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(4 + (self.entries.len() * ENTRY_LEN));
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (postcode, l) in &self.entries{
+            out.extend_from_slice(postcode.as_bytes());
+            out.extend_from_slice(&quantize_extent(l.x, 180.0, false).to_le_bytes());
+            out.extend_from_slice(&quantize_extent(l.y, 90.0, false).to_le_bytes());
+        }
+        out
+    }
+}
+
+/// The outliers read from a pack file
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Outliers{
+    entries: Vec<([u8;7], Point)>,
+}
+
+impl Outliers{
+    /// Read the outliers from the start of `data`, returns them and their length
+    pub fn from_bytes(data: &[u8]) -> Option<(Self, usize)>{
+        let count = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+        let len = count.checked_mul(ENTRY_LEN)?.checked_add(4)?;
+        let entries: Vec<([u8;7], Point)> = data.get(4..len)?.chunks_exact(ENTRY_LEN)
+            .map(|e| {
+                let q = |i: usize| u32::from_le_bytes(e[i..i+4].try_into().unwrap());
+                (e[0..7].try_into().unwrap(), Point{x: dequantize_extent(q(7), 180.0), y: dequantize_extent(q(11), 90.0)})
+            })
+            .collect();
+        if !entries.iter().all(|(p, _)| p.is_ascii()) || !entries.windows(2).all(|w| w[0].0 < w[1].0){
+            return None;
+        }
+        Some((Self{entries}, len))
+    }
+
+    pub fn len(&self) -> usize{
+        self.entries.len()
+    }
+
+    /// Exact location of a canonical postcode or outward code, if it's an outlier
+    pub fn get(&self, postcode: &str) -> Option<Point>{
+        let key = format!("{postcode:<7}");
+        let i = self.entries.binary_search_by(|(p, _)| p.as_slice().cmp(key.as_bytes())).ok()?;
+        Some(self.entries[i].1)
+    }
+
+    /// Canonical postcode (outward codes padded to 7 chars, as `Reader::iter` gives them) and
+    /// location of every outlier, in order
+    pub fn iter(&self) -> impl Iterator<Item=(&str, Point)> + '_{
+        self.entries.iter().map(|(p, l)| (std::str::from_utf8(p).unwrap(), *l))
+    }
+}
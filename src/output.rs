@@ -0,0 +1,306 @@
+/*
+
+Other kinds of output file, for backends that would rather not read the pack file format.
+
+These are written from the postcodes as they were read from the input file, so the locations are
+exact, not quantized as in a pack file. Outward codes by themselves are left out, they are only
+in pack files so that NMP can look them up.
+
+SQLite (`sqlite`):
+
+    CREATE TABLE postcodes(pcd TEXT PRIMARY KEY, lat REAL, long REAL) WITHOUT ROWID
+
+    pcd is the postcode in the same 7 character form as a pack file (see `format_postcode`), e.g.
+    "EC1A1BB" or "AB1 0AA", so a lookup is one query on the primary key.
+
+GeoJSON (`geojson`), and newline delimited GeoJSON (`ndjson`) for tools that stream features:
+
+    A FeatureCollection of Point features, or for ndjson one Feature on each line. The properties
+    are `postcode` (as in SQLite), `country` (the ONS code, if the input has a country column) and
+    `terminated` (the year, only for terminated postcodes).
+
+Parquet (`parquet`), compressed with Snappy:
+
+    message postcodes {
+        REQUIRED BYTE_ARRAY postcode (UTF8);  as in SQLite
+        REQUIRED DOUBLE lat;
+        REQUIRED DOUBLE long;
+        OPTIONAL BYTE_ARRAY country (UTF8);   the ONS code, if the input has a country column
+        OPTIONAL INT32 introduced (DATE);     the 1st of the month the postcode was introduced
+    }
+
+FlatGeobuf (`flatgeobuf`):
+
+    Point features in WGS84 (EPSG:4326), with the same properties as GeoJSON, sorted along a
+    Hilbert curve and with FlatGeobuf's packed R-tree index, so GIS tools can read just the
+    postcodes in an area.
+
+Three word CSV (`words-csv`):
+
+    postcode,lat,long,words
+
+    postcode as in SQLite, and words the three word name of the location (see words.rs), e.g.
+    "apple.river.stone", or empty outside the UK and Ireland.
+
+*/
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::{BufWriter, Write};
+use crate::{PostcodeError, PostcodeInfo, json_string, words};
+
+/// Which kind of file the packer writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat{
+    /// A pack file, for NMP
+    #[default]
+    Pack,
+    /// An SQLite database with one table, `postcodes`
+    Sqlite,
+    /// A GeoJSON FeatureCollection
+    Geojson,
+    /// Newline delimited GeoJSON, one Feature on each line
+    Ndjson,
+    /// A Parquet file, for analytics tools
+    Parquet,
+    /// A FlatGeobuf file with a spatial index, for GIS tools
+    Flatgeobuf,
+    /// A CSV file with a three word name for each location
+    WordsCsv,
+}
+
+impl OutputFormat{
+    pub const ALL: [OutputFormat;7] = [OutputFormat::Pack, OutputFormat::Sqlite, OutputFormat::Geojson, OutputFormat::Ndjson, OutputFormat::Parquet, OutputFormat::Flatgeobuf, OutputFormat::WordsCsv];
+
+    pub fn name(&self) -> &'static str{
+        use OutputFormat::*;
+        match self{
+            Pack => "pack",
+            Sqlite => "sqlite",
+            Geojson => "geojson",
+            Ndjson => "ndjson",
+            Parquet => "parquet",
+            Flatgeobuf => "flatgeobuf",
+            WordsCsv => "words-csv",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+}
+
+impl Display for OutputFormat{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Write an SQLite database of the postcodes, replacing `path` if it exists. Returns the size of the file.
+#[cfg(feature="sqlite")]
+pub fn write_sqlite(postcodes: &[PostcodeInfo], path: &str) -> Result<u64, PostcodeError>{
+    let db_err = |e: rusqlite::Error| PostcodeError::IOError(std::io::Error::other(e));
+    match std::fs::remove_file(path){
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => (),
+    }
+    let mut db = rusqlite::Connection::open(path).map_err(db_err)?;
+    // Nothing else can see the file until it's written, so there's no need for a journal
+    db.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;").map_err(db_err)?;
+    db.execute("CREATE TABLE postcodes(pcd TEXT PRIMARY KEY, lat REAL, long REAL) WITHOUT ROWID", ()).map_err(db_err)?;
+    let tx = db.transaction().map_err(db_err)?;
+    {
+        // If a postcode is in the input more than once, the first one is kept, as in a pack file lookup
+        let mut insert = tx.prepare("INSERT OR IGNORE INTO postcodes VALUES (?1, ?2, ?3)").map_err(db_err)?;
+        for p in postcodes.iter().filter(|p| !p.is_partial){
+            insert.execute((&p.postcode, p.location.y, p.location.x)).map_err(db_err)?;
+        }
+    }
+    tx.commit().map_err(db_err)?;
+    db.close().map_err(|(_, e)| db_err(e))?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// rusqlite builds SQLite from C, so it can be left out with `default-features = false` (e.g. for WebAssembly)
+#[cfg(not(feature="sqlite"))]
+pub fn write_sqlite(_postcodes: &[PostcodeInfo], _path: &str) -> Result<u64, PostcodeError>{
+    Err(PostcodeError::IOError(std::io::Error::new(std::io::ErrorKind::Unsupported, "this build does not support SQLite output")))
+}
+
+/// One postcode as a GeoJSON Feature, on one line
+fn geojson_feature(p: &PostcodeInfo) -> String{
+    let mut properties = format!("\"postcode\": {}", json_string(&p.postcode));
+    if let Some(country) = p.country{
+        properties.push_str(&format!(", \"country\": {}", json_string(country.code())));
+    }
+    if let Some(year) = p.terminated{
+        properties.push_str(&format!(", \"terminated\": {year}"));
+    }
+    format!(
+        "{{\"type\": \"Feature\", \"geometry\": {{\"type\": \"Point\", \"coordinates\": [{}, {}]}}, \"properties\": {{{properties}}}}}",
+        p.location.x, p.location.y,
+    )
+}
+
+/// Write the postcodes as a GeoJSON FeatureCollection, or if `delimited`, as one Feature on each
+/// line. Returns the size of the file.
+pub fn write_geojson(postcodes: &[PostcodeInfo], path: &str, delimited: bool) -> Result<u64, PostcodeError>{
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    let mut features = postcodes.iter().filter(|p| !p.is_partial).map(geojson_feature).peekable();
+    if delimited{
+        for feature in features{
+            writeln!(out, "{feature}")?;
+        }
+    }
+    else{
+        writeln!(out, "{{\"type\": \"FeatureCollection\", \"features\": [")?;
+        while let Some(feature) = features.next(){
+            let comma = if features.peek().is_some() { "," } else { "" };
+            writeln!(out, "{feature}{comma}")?;
+        }
+        writeln!(out, "]}}")?;
+    }
+    out.flush()?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Write a CSV file of the postcodes with the three word name of each location, returns the size
+/// of the file
+pub fn write_words_csv(postcodes: &[PostcodeInfo], path: &str) -> Result<u64, PostcodeError>{
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    writeln!(out, "postcode,lat,long,words")?;
+    for p in postcodes.iter().filter(|p| !p.is_partial){
+        let words = words::words_at(p.location).unwrap_or_default();
+        writeln!(out, "{},{},{},{words}", p.postcode, p.location.y, p.location.x)?;
+    }
+    out.flush()?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Rows in each Parquet row group
+#[cfg(feature="parquet")]
+const ROW_GROUP_LEN: usize = 1 << 20;
+
+/// Write a Parquet file of the postcodes, returns the size of the file
+#[cfg(feature="parquet")]
+pub fn write_parquet(postcodes: &[PostcodeInfo], path: &str) -> Result<u64, PostcodeError>{
+    use std::sync::Arc;
+    use parquet::basic::Compression;
+    use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    let pq_err = |e: parquet::errors::ParquetError| PostcodeError::IOError(std::io::Error::other(e));
+    let schema = parse_message_type("
+        message postcodes {
+            REQUIRED BYTE_ARRAY postcode (UTF8);
+            REQUIRED DOUBLE lat;
+            REQUIRED DOUBLE long;
+            OPTIONAL BYTE_ARRAY country (UTF8);
+            OPTIONAL INT32 introduced (DATE);
+        }
+    ").map_err(pq_err)?;
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let file = std::fs::File::create(path)?;
+    let mut writer = SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties)).map_err(pq_err)?;
+    let postcodes: Vec<&PostcodeInfo> = postcodes.iter().filter(|p| !p.is_partial).collect();
+    for group in postcodes.chunks(ROW_GROUP_LEN){
+        // Optional columns only have the values that are there, and a definition level for each
+        // row, 1 if it has a value or 0 if not
+        let postcode: Vec<ByteArray> = group.iter().map(|p| p.postcode.as_str().into()).collect();
+        let lat: Vec<f64> = group.iter().map(|p| p.location.y).collect();
+        let long: Vec<f64> = group.iter().map(|p| p.location.x).collect();
+        let country: Vec<ByteArray> = group.iter().filter_map(|p| Some(p.country?.code().into())).collect();
+        let has_country: Vec<i16> = group.iter().map(|p| p.country.is_some() as i16).collect();
+        let introduced: Vec<i32> = group.iter()
+            // Parquet dates are days since 1970-01-01
+            .filter_map(|p| Some((p.introduced? / 86400) as i32))
+            .collect();
+        let has_introduced: Vec<i16> = group.iter().map(|p| p.introduced.is_some() as i16).collect();
+
+        let mut row_group = writer.next_row_group().map_err(pq_err)?;
+        write_column::<ByteArrayType>(&mut row_group, &postcode, None).map_err(pq_err)?;
+        write_column::<DoubleType>(&mut row_group, &lat, None).map_err(pq_err)?;
+        write_column::<DoubleType>(&mut row_group, &long, None).map_err(pq_err)?;
+        write_column::<ByteArrayType>(&mut row_group, &country, Some(&has_country)).map_err(pq_err)?;
+        write_column::<Int32Type>(&mut row_group, &introduced, Some(&has_introduced)).map_err(pq_err)?;
+        row_group.close().map_err(pq_err)?;
+    }
+    writer.close().map_err(pq_err)?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Write the next column of a Parquet row group
+#[cfg(feature="parquet")]
+fn write_column<T: parquet::data_type::DataType>(
+    row_group: &mut parquet::file::writer::SerializedRowGroupWriter<std::fs::File>,
+    values: &[T::T],
+    def_levels: Option<&[i16]>,
+) -> parquet::errors::Result<()>{
+    let mut column = row_group.next_column()?.expect("more columns than in the schema");
+    column.typed::<T>().write_batch(values, def_levels, None)?;
+    column.close()
+}
+
+/// The parquet crate is large, so it can be left out with `default-features = false`
+#[cfg(not(feature="parquet"))]
+pub fn write_parquet(_postcodes: &[PostcodeInfo], _path: &str) -> Result<u64, PostcodeError>{
+    Err(PostcodeError::IOError(std::io::Error::new(std::io::ErrorKind::Unsupported, "this build does not support Parquet output")))
+}
+
+/// A location as a geozero point, for the FlatGeobuf writer
+#[cfg(feature="flatgeobuf")]
+struct GeoPoint(crate::Point);
+
+#[cfg(feature="flatgeobuf")]
+impl geozero::GeozeroGeometry for GeoPoint{
+    fn process_geom<P: geozero::GeomProcessor>(&self, processor: &mut P) -> geozero::error::Result<()>{
+        processor.point_begin(0)?;
+        processor.xy(self.0.x, self.0.y, 0)?;
+        processor.point_end(0)
+    }
+}
+
+/// Write a FlatGeobuf file of the postcodes, with a spatial index. Returns the size of the file.
+#[cfg(feature="flatgeobuf")]
+pub fn write_flatgeobuf(postcodes: &[PostcodeInfo], path: &str) -> Result<u64, PostcodeError>{
+    use flatgeobuf::{ColumnType, FgbCrs, FgbWriter, FgbWriterOptions, GeometryType};
+    use geozero::{ColumnValue, PropertyProcessor};
+
+    let fgb_err = |e: &dyn std::error::Error| PostcodeError::IOError(std::io::Error::other(e.to_string()));
+    let options = FgbWriterOptions{
+        crs: FgbCrs{code: 4326, ..Default::default()},
+        ..Default::default()
+    };
+    let mut fgb = FgbWriter::create_with_options("postcodes", GeometryType::Point, options).map_err(|e| fgb_err(&e))?;
+    // Properties that aren't known are left out of the feature, so the last two are nullable
+    fgb.add_column("postcode", ColumnType::String, |_, c| c.nullable = false);
+    fgb.add_column("country", ColumnType::String, |_, _| ());
+    fgb.add_column("terminated", ColumnType::UShort, |_, _| ());
+    for p in postcodes.iter().filter(|p| !p.is_partial){
+        let mut result = Ok(());
+        fgb.add_feature_geom(GeoPoint(p.location), |f|{
+            result = (||{
+                f.property(0, "postcode", &ColumnValue::String(&p.postcode))?;
+                if let Some(country) = p.country{
+                    f.property(1, "country", &ColumnValue::String(country.code()))?;
+                }
+                if let Some(year) = p.terminated{
+                    f.property(2, "terminated", &ColumnValue::UShort(year))?;
+                }
+                Ok(())
+            })();
+        }).map_err(|e| fgb_err(&e))?;
+        result.map_err(|e: geozero::error::GeozeroError| fgb_err(&e))?;
+    }
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    fgb.write(&mut out).map_err(|e| fgb_err(&e))?;
+    out.flush()?;
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// The FlatGeobuf crates are large, so they can be left out with `default-features = false`
+#[cfg(not(feature="flatgeobuf"))]
+pub fn write_flatgeobuf(_postcodes: &[PostcodeInfo], _path: &str) -> Result<u64, PostcodeError>{
+    Err(PostcodeError::IOError(std::io::Error::new(std::io::ErrorKind::Unsupported, "this build does not support FlatGeobuf output")))
+}
@@ -0,0 +1,2272 @@
+/*
+
+Reading the input files and writing pack files: the `Packer`, and the options and statistics of
+reading. This needs the `packer` feature, which brings in the CSV, date, zip and thread pool
+crates, so builds that only read pack files (such as the WebAssembly module) can leave it out.
+
+*/
+use time::{Date, UtcDateTime, Time};
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::collections::{BTreeMap, HashMap};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use crate::*;
+use crate::spatial::{SpatialIndex, IndexKind, IndexPoint};
+use crate::centroid::CentroidTotals;
+use crate::extent::ExtentTotals;
+use crate::sector::SectorTotals;
+use crate::outlier::{Extremes, OutlierTotals, MAX_OUTLIERS};
+use crate::local::{BlockExtents, LocalBoxes};
+use crate::group::OutwardExtents;
+use crate::section::SectionKind;
+
+impl From<csv::Error> for PostcodeError{
+    fn from(e: csv::Error) -> Self {
+        let line = e.position().map(|p| p.line());
+        let why = match e.kind(){
+            csv::ErrorKind::UnequalLengths{expected_len, len, ..} => format!("the row has {len} fields, but the first row has {expected_len}"),
+            csv::ErrorKind::Utf8{..} => "the row is not valid UTF-8".to_string(),
+            _ => e.to_string(),
+        };
+        match e.into_kind(){
+            csv::ErrorKind::Io(e) => PostcodeError::IOError(e),
+            _ => input_error(line, None, InputProblem::BadRow(why)),
+        }
+    }
+}
+
+/// Find the first of a list of column names in the header row, ignoring case.
+///
+/// Column names vary between the ONSPD and NSPL, and between releases of each.
+pub(crate) fn field_id(names: &[&str], headers: &[&str]) -> Result<usize, PostcodeError>{
+    names.iter()
+        .find_map(|name| headers.iter().position(|n| n.trim().eq_ignore_ascii_case(name)))
+        .ok_or_else(|| input_error(None, None, InputProblem::MissingColumn(names.join(" or "))))
+}
+
+/// Parse a date in the form YYYYMM, `None` if the field is empty
+pub(crate) fn parse_date(d: &str) -> Result<Option<Date>, InputProblem> {
+    let d = d.trim();
+    if d.is_empty(){
+        return Ok(None);
+    }
+    let parse = || -> Option<Date>{
+        let y = d.get(0..4)?.parse().ok()?;
+        let m:time::Month = d.get(4..6)?.parse::<u8>().ok()?.try_into().ok()?;
+        Date::from_calendar_date(y,m,1).ok()
+    };
+    parse().map(Some).ok_or_else(|| InputProblem::BadDate(d.to_string()))
+}
+
+/// Counts of the rows in the input file, and what happened to them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadStats{
+    /// Number of rows in the input file
+    pub total: usize,
+    /// Number of rows that were not packed (for any reason)
+    pub skipped: usize,
+    /// Number of rows skipped because the postcode is terminated
+    pub terminated: usize,
+    /// Number of rows skipped because the postcode matched an excluded prefix
+    pub excluded: usize,
+    /// Number of rows skipped because the postcode is outside the chosen countries, regions or
+    /// local authorities
+    pub outside_area: usize,
+    /// Number of rows skipped because the location is not precise enough
+    pub low_quality: usize,
+    /// Number of rows skipped because they could not be read (e.g. a latitude that isn't a number)
+    pub malformed: usize,
+    /// Number of rows skipped because the postcode can't be stored in a pack file,
+    /// see `Packer::unpackable`
+    pub unpackable: usize,
+    /// Number of rows skipped because their postcode is also in another row that was kept,
+    /// see `ReadOptions::on_duplicate`
+    pub duplicates: usize,
+}
+
+/// A postcode that was in the input file, but can't be stored in a pack file
+#[derive(Debug, Clone)]
+pub struct Unpackable{
+    pub postcode: String,
+    /// Line of the input file
+    pub line: Option<u64>,
+    pub reason: UnpackableReason,
+}
+
+/// Why a postcode can't be packed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnpackableReason{
+    /// The postcode has no location, like GIR 0AA and other non-geographic postcodes
+    NoLocation,
+    /// The postcode doesn't have the usual layout of an outward code (2 to 4 chars, starting
+    /// with a letter) and an inward code (a digit and two letters), like BFPO numbers
+    BadCode,
+}
+
+impl UnpackableReason{
+    /// Short name, for reports
+    pub fn name(&self) -> &'static str{
+        match self{
+            UnpackableReason::NoLocation => "no-location",
+            UnpackableReason::BadCode => "unpackable-code",
+        }
+    }
+}
+
+impl Display for UnpackableReason{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self{
+            UnpackableReason::NoLocation => write!(f, "the postcode has no location"),
+            UnpackableReason::BadCode => write!(f, "the postcode doesn't have the usual layout"),
+        }
+    }
+}
+
+/// A row of the input file that was not packed
+#[derive(Debug, Clone)]
+pub struct Dropped{
+    /// Postcode as it is in the input file, empty if the row doesn't have one that can be read
+    pub postcode: String,
+    /// Line of the input file
+    pub line: Option<u64>,
+    pub reason: DropReason,
+}
+
+/// Why a row of the input file was not packed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason{
+    Terminated,
+    Excluded,
+    OutsideArea,
+    LowQuality,
+    /// The row could not be read
+    ParseError,
+    /// The postcode column is empty
+    NoPostcode,
+    Unpackable(UnpackableReason),
+    /// The postcode is also in another row, which was kept instead
+    Duplicate,
+}
+
+impl DropReason{
+    /// Short name, for reports
+    pub fn name(&self) -> &'static str{
+        match self{
+            DropReason::Terminated => "terminated",
+            DropReason::Excluded => "excluded",
+            DropReason::OutsideArea => "outside-area",
+            DropReason::LowQuality => "low-quality",
+            DropReason::ParseError => "parse-error",
+            DropReason::NoPostcode => "no-postcode",
+            DropReason::Unpackable(u) => u.name(),
+            DropReason::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// A postcode that did not survive the round trip through a pack file
+#[derive(Debug, Clone)]
+pub struct Mismatch{
+    pub postcode: String,
+    /// Location in the input file
+    pub expected: Point,
+    /// Location read back from the pack file, `None` if the postcode was missing
+    pub found: Option<Point>,
+}
+
+/// A list of postcodes along with everything needed to write them out as a pack file
+#[derive(Debug, Clone)]
+pub struct Packer{
+    pub(crate) postcodes: Vec<PostcodeInfo>,
+    pub(crate) minll: Point,
+    pub(crate) maxll: Point,
+    pub(crate) last_update: u64,
+    /// Release date of the dataset, as a unix timestamp, 0 if it isn't known
+    pub(crate) release_date: u64,
+    pub(crate) stats: ReadStats,
+    /// Postcodes that were skipped because they can't be packed
+    pub(crate) unpackable: Vec<Unpackable>,
+    /// Every row that was skipped, if `ReadOptions::report_dropped` was set
+    pub(crate) dropped: Vec<Dropped>,
+    pub(crate) version: u32,
+    pub(crate) compression: Compression,
+    pub(crate) spatial_index: bool,
+    pub(crate) index_kind: IndexKind,
+    pub(crate) countries: bool,
+    pub(crate) centroids: bool,
+    pub(crate) prefix_extents: bool,
+    pub(crate) sector_bitmap: bool,
+    pub(crate) chunk_size: u32,
+    pub(crate) key_id: [u8;8],
+    pub(crate) entropy: bool,
+    pub(crate) local_bounds: bool,
+    pub(crate) resolution: u32,
+    pub(crate) scheme: Scheme,
+    /// Names of the attributes of each postcode, in the order of `PostcodeInfo::attributes`
+    pub(crate) attributes: Vec<String>,
+    /// How the file was made, see metadata.rs
+    pub(crate) metadata: Metadata,
+    /// Number of postcodes taken out of the bounding box, see outlier.rs
+    pub(crate) clip_bbox: usize,
+    /// The bounding box before any postcodes were taken out of it
+    pub(crate) full_bounds: Option<(Point, Point)>,
+    /// Whether the locations are quantized in coordinate groups, see group.rs
+    pub(crate) groups: bool,
+    /// Order of the records in each prefix block, see order.rs
+    pub(crate) order: RecordOrder,
+    /// Coordinate reference system of the locations, see crs.rs
+    pub(crate) crs: Crs,
+}
+
+/// Which columns of the input file to take the locations from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoordSource{
+    /// WGS84 lat/long, from the `lat` and `long` columns
+    #[default]
+    Wgs84,
+    /// Grid references from the `oseast1m` and `osnrth1m` columns, converted to WGS84.
+    /// These are OSGB36 National Grid references, except for Northern Ireland (BT)
+    /// postcodes which use the Irish Grid.
+    Osgb36,
+}
+
+/// Which kind of file the postcodes are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat{
+    /// The ONS postcode database (ONSPD) or the National Statistics Postcode Lookup (NSPL),
+    /// a CSV file with a header row. The columns are found by name.
+    #[default]
+    Onspd,
+    /// Ordnance Survey Code-Point Open, a directory (or zip file) of CSV files without
+    /// header rows. These only have grid references, so `coords` is ignored.
+    CodePoint,
+    /// The OSNI Pointer address database for Northern Ireland, a CSV file with one row per
+    /// address. Each postcode is put at the mean of its addresses' Irish Grid references, so
+    /// `coords` is ignored.
+    Pointer,
+}
+
+/// Options that control how the input file is read
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions{
+    /// Postcodes that start with any of these prefixes are skipped
+    pub exclude: Vec<String>,
+    /// If not empty, only postcodes that start with one of these prefixes (in capitals) are kept.
+    /// A prefix of only letters is a whole postcode area, so "E" is E1 to E20 but not EC or EH.
+    /// The exclusions still apply to the postcodes that are kept.
+    pub include: Vec<String>,
+    /// Postcodes that match any of these are skipped. They are matched against the postcode with
+    /// a single space, e.g. "B1 1AA", so `^BT` is every postcode in Northern Ireland.
+    pub exclude_regex: Vec<regex::Regex>,
+    pub coords: CoordSource,
+    pub format: InputFormat,
+    /// Which country's codes the file has, anything but UK postcodes needs format version 17
+    pub scheme: Scheme,
+    /// Keep terminated postcodes, along with the year they were terminated (needs format version 6)
+    pub include_terminated: bool,
+    /// If not empty, only postcodes in one of these countries are kept (ONS codes, e.g. E92000001)
+    pub only_country: Vec<String>,
+    /// If not empty, only postcodes in one of these regions are kept (ONS codes, e.g. E12000007)
+    pub only_region: Vec<String>,
+    /// If not empty, only postcodes in one of these local authorities are kept (ONS codes, e.g. E09000033)
+    pub only_la: Vec<String>,
+    /// If set, only postcodes with a positional quality (`osgrdind`) of this or better are kept.
+    /// 1 is the best (within a building), 9 means there's no location at all.
+    pub min_quality: Option<u8>,
+    /// Called with the number of rows read so far, after each chunk of rows is parsed
+    pub progress: Option<Progress>,
+    /// Stop at the first row that can't be read, instead of skipping it with a warning
+    pub strict: bool,
+    /// More CSV files to read after the main input, for postcodes that it has no locations for,
+    /// such as the Crown Dependencies (JE, GY and IM). These need a postcode column and `lat`
+    /// and `long` columns, the dates and area codes are optional. Their postcodes widen the
+    /// bounding box like any others, and shouldn't also be in the main input.
+    pub extra_csv: Vec<String>,
+    /// Date to use for input files that have no dates in them (Code-Point Open and Pointer),
+    /// instead of the time that the files were last modified, so that packing the same data
+    /// always gives the same file
+    pub file_date: Option<Date>,
+    /// Columns of the input file to store as attributes of each postcode (needs format version
+    /// 19), e.g. laua, ward or lsoa11. See attribute.rs for the other names that some of them go by.
+    pub attributes: Vec<String>,
+    /// Keep a list of every row that is skipped, and why, see `Packer::dropped`. Most of the
+    /// rows of the full ONS database are terminated postcodes, so this list can be long.
+    pub report_dropped: bool,
+    /// What to do about a postcode that is in more than one row, by default an error
+    pub on_duplicate: OnDuplicate,
+    /// Coordinate reference system to shift the locations into (see crs.rs), by default WGS84
+    pub crs: Crs,
+}
+
+/// A callback for showing how much of the input file has been read
+#[derive(Clone)]
+pub struct Progress(pub std::sync::Arc<dyn Fn(usize) + Send + Sync>);
+
+impl std::fmt::Debug for Progress{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "Progress")
+    }
+}
+
+impl ReadOptions{
+    pub fn with_exclude(exclude: &[&str]) -> Self{
+        Self{
+            exclude: exclude.iter().map(|e|e.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Add the prefixes in a file to `exclude`, one on each line. Blank lines are ignored, and so
+    /// is anything after a `#`, so the file can say why each prefix is left out.
+    pub fn read_exclude_file(&mut self, path: &str) -> Result<(), PostcodeError>{
+        let text = std::fs::read_to_string(path)?;
+        for line in text.lines(){
+            let prefix = line.split('#').next().unwrap_or_default().trim();
+            if !prefix.is_empty(){
+                self.exclude.push(prefix.to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Column positions of the fields that the packer uses
+struct Columns{
+    /// Postcodes in any layout, see `normalize_postcode`
+    postcode: usize,
+    y: usize,
+    x: usize,
+    /// Code-Point Open doesn't have dates, all of its postcodes are current
+    date_intr: Option<usize>,
+    date_term: Option<usize>,
+    /// Area codes, the country is also stored in the pack file if it's there
+    country: Option<usize>,
+    region: Option<usize>,
+    la: Option<usize>,
+    /// Positional quality, only needed when filtering by quality
+    quality: Option<usize>,
+    /// Attributes to keep, in the order of `ReadOptions::attributes`. Supplementary files don't
+    /// need to have them.
+    attributes: Vec<Option<usize>>,
+}
+
+/// Column positions in Code-Point Open files
+const CODEPOINT_COLUMNS: Columns = Columns{
+    postcode: 0,
+    y: 3,
+    x: 2,
+    date_intr: None,
+    date_term: None,
+    // Code-Point Open has no region column
+    country: Some(4),
+    region: None,
+    la: Some(8),
+    quality: Some(1),
+    attributes: Vec::new(),
+};
+
+/// What happened to a single row of the input file
+enum Row{
+    Postcode(PostcodeInfo),
+    Terminated,
+    Excluded,
+    OutsideArea,
+    LowQuality,
+    Unpackable(Unpackable),
+    Skipped,
+}
+
+/// Number of rows that are read before being parsed in parallel
+const CHUNK_SIZE: usize = 1<<16;
+
+fn parse_row(line: &csv::StringRecord, headers: Option<&csv::StringRecord>, cols: &Columns, options: &ReadOptions) -> Result<Row, PostcodeError>{
+    // Say where the problem is, so that a bad row can be found in a big file
+    let bad = |i: usize, reason: InputProblem|{
+        let field = headers.and_then(|h| h.get(i)).map(|n| n.trim().to_string()).unwrap_or_else(|| (i+1).to_string());
+        input_error(line.position().map(|p| p.line()), Some(field), reason)
+    };
+    let number = |i: usize, value: &str| -> Result<f64, PostcodeError>{
+        value.trim().parse().map_err(|_| bad(i, InputProblem::BadNumber(value.to_string())))
+    };
+    let date = |i: Option<usize>| -> Result<Option<Date>, PostcodeError>{
+        match i.and_then(|i| Some((i, line.get(i)?))){
+            Some((i, d)) => parse_date(d).map_err(|e| bad(i, e)),
+            None => Ok(None),
+        }
+    };
+    let Some(raw_postcode) = line.get(cols.postcode) else {
+        return Ok(Row::Skipped);
+    };
+    if raw_postcode.trim().is_empty(){
+        return Ok(Row::Skipped);
+    }
+    let unpackable = |reason|{
+        Ok(Row::Unpackable(Unpackable{
+            postcode: raw_postcode.trim().to_string(),
+            line: line.position().map(|p| p.line()),
+            reason,
+        }))
+    };
+    // This isn't reported until the row is known to be wanted
+    let postcode = options.scheme.codec().normalize(raw_postcode);
+    let introduced = date(cols.date_intr)?;
+    let terminated = date(cols.date_term)?;
+    let is_current = match cols.date_intr{
+        Some(_) => matches!((introduced, terminated), (Some(_), None)),
+        None => true,
+    };
+    let keep_terminated = options.include_terminated && introduced.is_some();
+    if !is_current && !keep_terminated{
+        return Ok(Row::Terminated);
+    }
+    let in_area = |col: Option<usize>, codes: &[String]|{
+        codes.is_empty() || col.and_then(|i| line.get(i))
+            .is_some_and(|v| codes.iter().any(|c| c.eq_ignore_ascii_case(v.trim())))
+    };
+    // Files without a country column can still give the Crown Dependencies from the postcode
+    let country = match cols.country{
+        Some(i) => line.get(i).and_then(Country::from_code),
+        None => postcode.as_deref().and_then(Country::from_postcode),
+    };
+    let in_country = match cols.country{
+        Some(_) => in_area(cols.country, &options.only_country),
+        None => options.only_country.is_empty() || country.is_some_and(|c| options.only_country.iter().any(|o| o.eq_ignore_ascii_case(c.code()))),
+    };
+    if !(in_country
+        && in_area(cols.region, &options.only_region)
+        && in_area(cols.la, &options.only_la)){
+        return Ok(Row::OutsideArea);
+    }
+    if let Some(min_quality) = options.min_quality{
+        let quality = cols.quality.and_then(|i| line.get(i))
+            .and_then(|q| q.trim().parse::<u8>().ok())
+            .map(|q| match options.format{
+                InputFormat::Onspd => q,
+                // Code-Point Open uses the same scale, multiplied by 10
+                InputFormat::CodePoint => q / 10,
+                // Pointer has no quality column, see read_pointer_with
+                InputFormat::Pointer => q,
+            });
+        // Rows without a quality are treated as the worst quality
+        if quality.is_none_or(|q| q > min_quality){
+            return Ok(Row::LowQuality);
+        }
+    }
+    let Some(postcode) = postcode else {
+        return unpackable(UnpackableReason::BadCode);
+    };
+    let coords = match options.format{
+        InputFormat::Onspd => options.coords,
+        InputFormat::CodePoint | InputFormat::Pointer => CoordSource::Osgb36,
+    };
+    let location = match coords{
+        CoordSource::Wgs84 => {
+            let Some(lat) = line.get(cols.y) else {
+                return Ok(Row::Skipped);
+            };
+            let lat = number(cols.y, lat)?;
+            if lat > 99.0{
+                return unpackable(UnpackableReason::NoLocation);
+            }
+            let Some(long) = line.get(cols.x) else {
+                return Ok(Row::Skipped);
+            };
+            let long = number(cols.x, long)?;
+            Point{x:long, y:lat}
+        },
+        CoordSource::Osgb36 => {
+            let (Some(e), Some(n)) = (line.get(cols.x), line.get(cols.y)) else {
+                return Ok(Row::Skipped);
+            };
+            if e.is_empty() || n.is_empty(){
+                // no grid reference (e.g. Channel Islands and Isle of Man)
+                return unpackable(UnpackableReason::NoLocation);
+            }
+            let e = number(cols.x, e)?;
+            let n = number(cols.y, n)?;
+            if e == 0.0 && n == 0.0{
+                // no location known (Code-Point Open)
+                return unpackable(UnpackableReason::NoLocation);
+            }
+            if postcode.starts_with("BT"){
+                osgb::irish_grid_to_wgs84(e, n)
+            }
+            else{
+                osgb::osgb36_to_wgs84(e, n)
+            }
+        },
+    };
+    let location = options.crs.from_wgs84(location);
+
+    if !options.include.is_empty() && !options.include.iter().any(|prefix| has_prefix(&postcode, prefix)){
+        return Ok(Row::Excluded);
+    }
+    if options.exclude.iter().any(|prefix| postcode.starts_with(prefix)){
+        return Ok(Row::Excluded);
+    }
+    if !options.exclude_regex.is_empty(){
+        let spaced = spaced_postcode(&postcode, options.scheme);
+        if options.exclude_regex.iter().any(|r| r.is_match(&spaced)){
+            return Ok(Row::Excluded);
+        }
+    }
+
+    Ok(Row::Postcode(
+        PostcodeInfo{
+            postcode,
+            location,
+            is_partial: false,
+            terminated: terminated.map(|t| t.year() as u16),
+            country,
+            introduced: introduced.map(unix_time),
+            attributes: cols.attributes.iter()
+                .map(|i| i.and_then(|i| line.get(i)).unwrap_or_default().trim().to_string())
+                .collect(),
+        },
+    ))
+}
+
+/// Read the ONS postcode database csv file, or the zip or gzip file that contains it.
+/// Code-Point Open is read instead if `options.format` says so.
+///
+/// Terminated postcodes, postcodes without a known location, and postcodes that start with
+/// any of the excluded prefixes are skipped. The result contains only full postcodes,
+/// in file order. Use `Packer::from_csv` to get a list that is ready to write.
+///
+/// Rows are read in chunks, and each chunk is parsed in parallel.
+pub fn read_postcodes(path: &str, options: &ReadOptions) -> Result<Packer, PostcodeError> {
+    let mut pclist = Vec::new();
+    let mut packer = read_postcodes_with(path, options, |p| pclist.push(p))?;
+    packer.postcodes = pclist;
+    Ok(packer)
+}
+
+/// Release date of an ONS postcode database file, as a unix timestamp, from the month in its
+/// name or in the name of the CSV file in it (e.g. `Data/ONSPD_FEB_2025_UK.csv`). `None` if the
+/// names don't say, as for Code-Point Open.
+pub fn release_date_of(path: &str) -> Option<u64>{
+    input::release_date(path).map(unix_time)
+}
+
+/// Midnight at the start of a day, as a unix timestamp
+pub(crate) fn unix_time(date: Date) -> u64{
+    UtcDateTime::new(date, Time::MIDNIGHT).unix_timestamp() as u64
+}
+
+/// As `read_postcodes`, but hands each postcode to `f` instead of keeping it.
+///
+/// The result has the bounding box, date and stats filled in, but no postcodes.
+pub(crate) fn read_postcodes_with<F: FnMut(PostcodeInfo)>(path: &str, options: &ReadOptions, f: F) -> Result<Packer, PostcodeError> {
+    read_input_with(path, options, true, f)
+}
+
+/// As `read_postcodes_with`, for reading the same file again. Rows that can't be read have
+/// already been warned about, so they are skipped quietly.
+pub(crate) fn reread_postcodes_with<F: FnMut(PostcodeInfo)>(path: &str, options: &ReadOptions, f: F) -> Result<Packer, PostcodeError> {
+    read_input_with(path, options, false, f)
+}
+
+fn read_input_with<F: FnMut(PostcodeInfo)>(path: &str, options: &ReadOptions, report: bool, mut f: F) -> Result<Packer, PostcodeError> {
+    if options.format != InputFormat::Onspd && options.scheme != Scheme::Uk{
+        return Err(PostcodeError::IncompatibleOptions("Code-Point Open and Pointer only have UK postcodes"));
+    }
+    if options.format != InputFormat::Onspd && !options.attributes.is_empty(){
+        return Err(PostcodeError::IncompatibleOptions("attributes can only be read from ONSPD style CSV files"));
+    }
+    attribute::check_names(&options.attributes)?;
+    let mut state = ReadState::new(report, options.report_dropped);
+    match options.format{
+        InputFormat::Onspd => input::with_input(path, |file| read_csv_with(file, options, false, &mut state, &mut f)),
+        InputFormat::CodePoint => read_codepoint_with(path, options, &mut state, &mut f),
+        InputFormat::Pointer => read_pointer_with(path, options, &mut state, &mut f),
+    }?;
+    if !options.extra_csv.is_empty(){
+        // Always lat/long, whatever the main input is
+        let extra_options = ReadOptions{
+            coords: CoordSource::Wgs84,
+            format: InputFormat::Onspd,
+            ..options.clone()
+        };
+        for extra in &options.extra_csv{
+            input::with_input(extra, |file| read_csv_with(file, &extra_options, true, &mut state, &mut f))?;
+        }
+    }
+    let mut packer = state.into_packer();
+    packer.scheme = options.scheme;
+    packer.attributes = options.attributes.clone();
+    packer.crs = options.crs;
+    Ok(packer)
+}
+
+/// Totals that are collected while reading the input, which might be spread over several files
+struct ReadState{
+    minll: Point,
+    maxll: Point,
+    total: usize,
+    kept: usize,
+    terminated: usize,
+    excluded: usize,
+    outside_area: usize,
+    low_quality: usize,
+    malformed: usize,
+    unpackable: Vec<Unpackable>,
+    unpackable_count: usize,
+    /// Every row that was skipped, only kept if it was asked for
+    dropped: Option<Vec<Dropped>>,
+    /// Unix timestamp of the newest date in the input
+    last_update: u64,
+    /// Warn about rows that can't be read, and keep the list of unpackable postcodes.
+    /// This is off when the same file is read again.
+    report: bool,
+}
+
+impl ReadState{
+    fn new(report: bool, report_dropped: bool) -> Self{
+        Self{
+            minll: Point{x:9999.0, y:9999.0},
+            maxll: Point{x:-9999.0, y:-9999.0},
+            total: 0,
+            kept: 0,
+            terminated: 0,
+            excluded: 0,
+            outside_area: 0,
+            low_quality: 0,
+            malformed: 0,
+            unpackable: Vec::new(),
+            unpackable_count: 0,
+            dropped: (report && report_dropped).then(Vec::new),
+            last_update: 0,
+            report,
+        }
+    }
+
+    fn into_packer(self) -> Packer{
+        Packer{
+            postcodes: Vec::new(),
+            minll: self.minll, // Lower left corner of bounding box
+            maxll: self.maxll, // Upper right corner of bounding box
+            last_update: self.last_update, // date of last update
+            release_date: 0,
+            stats: ReadStats{
+                total: self.total,
+                skipped: self.total - self.kept,
+                terminated: self.terminated,
+                excluded: self.excluded,
+                outside_area: self.outside_area,
+                low_quality: self.low_quality,
+                malformed: self.malformed,
+                unpackable: self.unpackable_count,
+                duplicates: 0,
+            },
+            unpackable: self.unpackable,
+            dropped: self.dropped.unwrap_or_default(),
+            version: DEFAULT_VERSION,
+            compression: Compression::None,
+            spatial_index: false,
+            index_kind: IndexKind::Grid,
+            countries: false,
+            centroids: false,
+            prefix_extents: false,
+            sector_bitmap: false,
+            chunk_size: 0,
+            key_id: [0;8],
+            entropy: false,
+            local_bounds: false,
+            resolution: DEFAULT_RESOLUTION,
+            scheme: Scheme::Uk,
+            attributes: Vec::new(),
+            metadata: Metadata::new(),
+            clip_bbox: 0,
+            full_bounds: None,
+            groups: false,
+            order: RecordOrder::Alphabetical,
+            crs: Crs::Wgs84,
+        }
+    }
+}
+
+/// Read an ONSPD style CSV file, with a header row. Supplementary files (`extra`) don't need
+/// the date or country columns.
+fn read_csv_with<R: Read, F: FnMut(PostcodeInfo)>(file: R, options: &ReadOptions, extra: bool, state: &mut ReadState, f: &mut F) -> Result<(), PostcodeError> {
+    let mut postcodes = csv::Reader::from_reader(file);
+    let header_row = postcodes.headers()?.clone();
+    let headers: Vec<&str> = header_row.iter().collect();
+    let (y, x) = match options.coords{
+        CoordSource::Wgs84 => (field_id(&["lat"], &headers)?, field_id(&["long"], &headers)?),
+        CoordSource::Osgb36 => (field_id(&["osnrth1m", "north1m"], &headers)?, field_id(&["oseast1m", "east1m"], &headers)?),
+    };
+    // Any of the forms of the postcode will do, they're all normalized
+    let postcode = field_id(&["pcd", "pcd7", "pcd2", "pcd8", "pcds", "postcode", "eircode"], &headers)?;
+    // Other countries' files don't have to have dates, their codes are all taken to be current
+    let date_column = |name: &str| match options.scheme{
+        Scheme::Uk if !extra => field_id(&[name], &headers).map(Some),
+        _ => Ok(field_id(&[name], &headers).ok()),
+    };
+    let cols = Columns{
+        postcode,
+        y,
+        x,
+        date_intr: date_column("dointr")?,
+        date_term: date_column("doterm")?,
+        country: if options.only_country.is_empty() || extra { field_id(&["ctry"], &headers).ok() } else { Some(field_id(&["ctry"], &headers)?) },
+        region: area_column(&options.only_region, &["rgn", "gor"], &headers)?,
+        la: area_column(&options.only_la, &["laua", "lad"], &headers)?,
+        quality: if options.min_quality.is_some() { Some(field_id(&["osgrdind"], &headers)?) } else { None },
+        attributes: options.attributes.iter()
+            .map(|name| {
+                let column = field_id(&attribute::column_names(name), &headers);
+                if extra { Ok(column.ok()) } else { column.map(Some) }
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    read_records(postcodes.into_records(), Some(&header_row), &cols, options, state, f)
+}
+
+/// Find an area code column, if it's needed for filtering
+fn area_column(codes: &[String], names: &[&str], headers: &[&str]) -> Result<Option<usize>, PostcodeError>{
+    if codes.is_empty(){
+        Ok(None)
+    }
+    else{
+        field_id(names, headers).map(Some)
+    }
+}
+
+/// Read Code-Point Open, which is split in to one file per postcode area
+fn read_codepoint_with<F: FnMut(PostcodeInfo)>(path: &str, options: &ReadOptions, state: &mut ReadState, f: &mut F) -> Result<(), PostcodeError> {
+    if !options.only_region.is_empty(){
+        return Err(PostcodeError::IncompatibleOptions("Code-Point Open has no region codes"));
+    }
+    input::with_codepoint_inputs(path, |file, modified| {
+        let postcodes = csv::ReaderBuilder::new().has_headers(false).from_reader(file);
+        read_records(postcodes.into_records(), None, &CODEPOINT_COLUMNS, options, state, f)?;
+        // There are no dates in the data, the best we can do is the date of the files
+        if let Some(m) = options.file_date.or(modified){
+            state.last_update = state.last_update.max(unix_time(m));
+        }
+        Ok(())
+    })
+}
+
+/// Read the OSNI Pointer address database. There's a row for each address, so the addresses
+/// are grouped by postcode first, and each postcode is read as if it was one row with the mean
+/// of their grid references. Archived addresses are left out.
+fn read_pointer_with<F: FnMut(PostcodeInfo)>(path: &str, options: &ReadOptions, state: &mut ReadState, f: &mut F) -> Result<(), PostcodeError> {
+    if !options.only_region.is_empty() || !options.only_la.is_empty(){
+        return Err(PostcodeError::IncompatibleOptions("Pointer has no region or local authority codes"));
+    }
+    if options.min_quality.is_some(){
+        return Err(PostcodeError::IncompatibleOptions("Pointer has no positional quality column"));
+    }
+    // Sum of eastings, sum of northings and number of addresses, by postcode
+    let mut addresses: BTreeMap<String, (f64, f64, u32)> = BTreeMap::new();
+    input::with_input(path, |file| {
+        let mut rows = csv::Reader::from_reader(file);
+        let header_row = rows.headers()?.clone();
+        let headers: Vec<&str> = header_row.iter().collect();
+        let postcode = field_id(&["postcode"], &headers)?;
+        let x = field_id(&["x_cor", "x_coordinate", "easting"], &headers)?;
+        let y = field_id(&["y_cor", "y_coordinate", "northing"], &headers)?;
+        let archived = field_id(&["archived_date"], &headers).ok();
+        for row in rows.into_records(){
+            let row = match row{
+                Ok(row) => row,
+                Err(e) => {
+                    state.total += 1;
+                    skip_bad_row(e.into(), "", options, state)?;
+                    continue;
+                },
+            };
+            let Some(raw_postcode) = row.get(postcode).map(str::trim).filter(|p| !p.is_empty()) else {
+                continue;
+            };
+            if archived.and_then(|i| row.get(i)).is_some_and(|a| !a.trim().is_empty()){
+                continue;
+            }
+            let number = |i: usize| -> Result<f64, PostcodeError>{
+                let value = row.get(i).unwrap_or("").trim();
+                value.parse().map_err(|_| input_error(row.position().map(|p| p.line()), Some(headers[i].trim().to_string()), InputProblem::BadNumber(value.to_string())))
+            };
+            let (e, n) = match (number(x), number(y)){
+                (Ok(e), Ok(n)) => (e, n),
+                (Err(err), _) | (_, Err(err)) => {
+                    state.total += 1;
+                    skip_bad_row(err, raw_postcode, options, state)?;
+                    continue;
+                },
+            };
+            // Group the different layouts of the same postcode, but keep the ones that can't be
+            // normalized as they are, so that they are reported as unpackable
+            let key = normalize_postcode(raw_postcode).unwrap_or_else(|| raw_postcode.to_string());
+            let sum = addresses.entry(key).or_insert((0.0, 0.0, 0));
+            // Addresses without a location have a grid reference of 0,0, which would pull the mean away
+            if e != 0.0 || n != 0.0{
+                *sum = (sum.0 + e, sum.1 + n, sum.2 + 1);
+            }
+        }
+        Ok(())
+    })?;
+    // There are no dates of introduction, the best we can do is the date of the file
+    let modified = std::fs::metadata(path)?.modified().ok().map(|m| time::OffsetDateTime::from(m).date());
+    if let Some(m) = options.file_date.or(modified){
+        state.last_update = state.last_update.max(unix_time(m));
+    }
+    let rows = addresses.into_iter().map(|(postcode, (e, n, count))|{
+        let (e, n) = if count == 0 { (0.0, 0.0) } else { (e / count as f64, n / count as f64) };
+        // Pointer only covers Northern Ireland
+        Ok(csv::StringRecord::from(vec![postcode, e.to_string(), n.to_string(), Country::NorthernIreland.code().to_string()]))
+    });
+    read_records(rows, None, &POINTER_COLUMNS, options, state, f)
+}
+
+/// Column positions of the rows made by `read_pointer_with`
+const POINTER_COLUMNS: Columns = Columns{
+    postcode: 0,
+    y: 2,
+    x: 1,
+    date_intr: None,
+    date_term: None,
+    country: Some(3),
+    region: None,
+    la: None,
+    quality: None,
+    attributes: Vec::new(),
+};
+
+/// Read the rows of a CSV file. `headers` is the header row, if the file has one.
+fn read_records<F: FnMut(PostcodeInfo)>(mut records: impl Iterator<Item=csv::Result<csv::StringRecord>>, headers: Option<&csv::StringRecord>, cols: &Columns, options: &ReadOptions, state: &mut ReadState, f: &mut F) -> Result<(), PostcodeError> {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    loop{
+        chunk.clear();
+        let mut rows_read = 0;
+        for line in records.by_ref().take(CHUNK_SIZE){
+            rows_read += 1;
+            match line{
+                Ok(line) => chunk.push(line),
+                Err(e) => {
+                    state.total += 1;
+                    skip_bad_row(e.into(), "", options, state)?;
+                },
+            }
+        }
+        if rows_read == 0{
+            break;
+        }
+        state.total += chunk.len();
+        if let Some(progress) = &options.progress{
+            (progress.0)(state.total);
+        }
+        // Errors are found in file order, so the first bad row is the one that's reported
+        let rows: Vec<Result<Row, PostcodeError>> = chunk.par_iter()
+            .map(|line| parse_row(line, headers, cols, options))
+            .collect();
+        for (row, line) in rows.into_iter().zip(&chunk){
+            let postcode = || line.get(cols.postcode).unwrap_or("").trim();
+            let row = match row{
+                Ok(row) => row,
+                Err(e) => {
+                    skip_bad_row(e, postcode(), options, state)?;
+                    continue;
+                },
+            };
+            if let Some(dropped) = &mut state.dropped{
+                let reason = match &row{
+                    Row::Postcode(_) => None,
+                    Row::Terminated => Some(DropReason::Terminated),
+                    Row::Excluded => Some(DropReason::Excluded),
+                    Row::OutsideArea => Some(DropReason::OutsideArea),
+                    Row::LowQuality => Some(DropReason::LowQuality),
+                    Row::Unpackable(u) => Some(DropReason::Unpackable(u.reason)),
+                    Row::Skipped => Some(DropReason::NoPostcode),
+                };
+                if let Some(reason) = reason{
+                    dropped.push(Dropped{postcode: postcode().to_string(), line: line.position().map(|p| p.line()), reason});
+                }
+            }
+            match row{
+                Row::Postcode(p) => {
+                    if let Some(introduced) = p.introduced{
+                        state.last_update = state.last_update.max(introduced);
+                    }
+                    state.minll.y = state.minll.y.min(p.location.y);
+                    state.maxll.y = state.maxll.y.max(p.location.y);
+                    state.minll.x = state.minll.x.min(p.location.x);
+                    state.maxll.x = state.maxll.x.max(p.location.x);
+                    state.kept += 1;
+                    f(p);
+                },
+                Row::Terminated => state.terminated += 1,
+                Row::Excluded => state.excluded += 1,
+                Row::OutsideArea => state.outside_area += 1,
+                Row::LowQuality => state.low_quality += 1,
+                Row::Unpackable(u) => {
+                    state.unpackable_count += 1;
+                    if state.report{
+                        state.unpackable.push(u);
+                    }
+                },
+                Row::Skipped => {},
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Skip a row that can't be read, unless `options.strict` is set.
+/// Anything other than a bad row (e.g. an IO error) always stops the read.
+fn skip_bad_row(e: PostcodeError, postcode: &str, options: &ReadOptions, state: &mut ReadState) -> Result<(), PostcodeError>{
+    const max_report: usize = 20;
+    let PostcodeError::InputMalformed(problem) = e else {
+        return Err(e);
+    };
+    if options.strict{
+        return Err(PostcodeError::InputMalformed(problem));
+    }
+    state.malformed += 1;
+    if let Some(dropped) = &mut state.dropped{
+        dropped.push(Dropped{postcode: postcode.to_string(), line: problem.line, reason: DropReason::ParseError});
+    }
+    if state.report{
+        if state.malformed <= max_report{
+            log::warn!("Skipping {problem}");
+        }
+        else if state.malformed == max_report + 1{
+            log::warn!("More rows can't be read, they are skipped and counted in the summary");
+        }
+    }
+    Ok(())
+}
+
+/// Encode a sorted list of postcodes as packed records, one for each postcode.
+///
+/// Locations are quantized relative to the bounding box given by `minll` and `maxll`.
+/// Terminated postcodes can only be packed in format version 6 or newer.
+pub fn pack_postcodes(postcodes: &[PostcodeInfo], minll: Point, maxll:Point, version: u32) -> Result<Vec<DeltaPacked>, PostcodeError> {
+    pack_postcodes_in(postcodes, minll, maxll, &LocalBoxes::default(), version, DEFAULT_RESOLUTION, Scheme::Uk, RecordOrder::Alphabetical)
+}
+
+/// As `pack_postcodes`, with the locations in each block quantized in its own bounding box if it
+/// has one, to `resolution` bits per axis, the codes packed as `scheme` says, and the records
+/// of each block in `order`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pack_postcodes_in(postcodes: &[PostcodeInfo], minll: Point, maxll:Point, boxes: &LocalBoxes, version: u32, resolution: u32, scheme: Scheme, order: RecordOrder) -> Result<Vec<DeltaPacked>, PostcodeError> {
+    // Any time the prefix changes, the previous code state is reset.
+    // This is important because the decoder skips to the start of
+    // a prefix block as the first step, so it will still have the
+    // initial state at this point. It also means that the blocks
+    // can be packed independently.
+    let blocks: Vec<&[PostcodeInfo]> = postcodes.chunk_by(|a,b| a.postcode[..PREFIX_LEN] == b.postcode[..PREFIX_LEN]).collect();
+    let packed_blocks = blocks.par_iter()
+        .map(|block| {
+            let index = lut_index(block[0].postcode.as_bytes());
+            let mut block = block.iter()
+                .map(|p| {
+                    let (minll, maxll) = index
+                        .map(|index| boxes.bounds(index, p.postcode.as_bytes(), minll, maxll))
+                        .unwrap_or((minll, maxll));
+                    QuantizedPostcode::new(p, minll, maxll, resolution, scheme)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            order::arrange(&mut block, order, |p| (p.postcode, p.long, p.lat));
+            pack_block(&block, version, resolution)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(packed_blocks.into_iter().flatten().collect())
+}
+
+/// SHA-256 of a file, as lower case hex
+pub fn sha256_file(path: &std::path::Path) -> Result<String, PostcodeError>{
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Running totals of the postcode locations in each outward code. These are in order, so that
+/// the outward codes come out in the same order every time.
+pub(crate) struct OutwardTotals{
+    totals: BTreeMap<String, LLTotal>,
+    scheme: Scheme,
+}
+
+impl OutwardTotals{
+    pub fn new(scheme: Scheme) -> Self{
+        Self{totals: BTreeMap::new(), scheme}
+    }
+
+    pub fn add(&mut self, p: &PostcodeInfo){
+        // Outward codes are placed among the postcodes that are in use
+        if p.terminated.is_some(){
+            return;
+        }
+        let outward = self.scheme.codec().prefix(&p.postcode);
+        let t = self.totals.entry(outward.to_string()).or_insert_with(LLTotal::new);
+        t.add(&p.location, p.country);
+    }
+
+    /// A partial postcode for each outward code, located at the average of its postcodes
+    pub fn averages(self) -> impl Iterator<Item=PostcodeInfo>{
+        self.totals.into_iter().map(|(k, v)| PostcodeInfo{
+            is_partial: true,
+            postcode: format!("{:<7}", k),
+            location: v.average(),
+            terminated: None,
+            country: v.country,
+            introduced: None,
+            attributes: Vec::new(),
+        })
+    }
+}
+
+fn insert_outward_averages(postcodes: &mut Vec<PostcodeInfo>, scheme: Scheme){
+    let mut totals = OutwardTotals::new(scheme);
+    for p in postcodes.iter(){
+        totals.add(p);
+    }
+    postcodes.extend(totals.averages());
+}
+
+/// Look up a postcode in a pack file, and check that it's within one step of its original location
+pub(crate) fn check_postcode(reader: &Reader, p: &PostcodeInfo, step_x: f64, step_y: f64) -> Option<Mismatch>{
+    let key = if p.is_partial { &p.postcode[0..4] } else { &p.postcode[..] };
+    let found = reader.lookup_canonical(key);
+    let ok = match found{
+        Some(l) => (l.x - p.location.x).abs() <= step_x && (l.y - p.location.y).abs() <= step_y,
+        None => false,
+    };
+    if ok{
+        return None;
+    }
+    Some(Mismatch{
+        postcode: p.postcode.clone(),
+        expected: p.location,
+        found,
+    })
+}
+
+impl Packer{
+    /// Read the ONS postcode database csv file and prepare the postcodes for packing.
+    ///
+    /// This adds the outward code averages and sorts the list, see `read_postcodes` for
+    /// details of which rows are skipped. A postcode that is in more than one row is an error,
+    /// unless `ReadOptions::on_duplicate` says which row to keep.
+    pub fn from_csv(path: &str, exclude: &[&str]) -> Result<Self, PostcodeError>{
+        Self::from_csv_with_options(path, &ReadOptions::with_exclude(exclude))
+    }
+
+    /// As `from_csv`, with more control over how the file is read
+    pub fn from_csv_with_options(path: &str, options: &ReadOptions) -> Result<Self, PostcodeError>{
+        let mut packer = read_postcodes(path, options)?;
+        packer.postcodes.par_sort_by(|a,b|a.postcode.cmp(&b.postcode));
+        let removed = duplicate::resolve(&mut packer.postcodes, options.on_duplicate, packer.scheme)?;
+        if !removed.is_empty(){
+            packer.stats.duplicates = removed.len();
+            packer.stats.skipped += removed.len();
+            if options.report_dropped{
+                packer.dropped.extend(removed.iter().map(|p| Dropped{postcode: spaced_postcode(p, packer.scheme), line: None, reason: DropReason::Duplicate}));
+            }
+            // The bounding box is only around the rows that are kept
+            let (mut minll, mut maxll) = (Point{x: 9999.0, y: 9999.0}, Point{x: -9999.0, y: -9999.0});
+            for p in &packer.postcodes{
+                minll = Point{x: minll.x.min(p.location.x), y: minll.y.min(p.location.y)};
+                maxll = Point{x: maxll.x.max(p.location.x), y: maxll.y.max(p.location.y)};
+            }
+            (packer.minll, packer.maxll) = (minll, maxll);
+        }
+        insert_outward_averages(&mut packer.postcodes, packer.scheme);
+        packer.postcodes.par_sort_by(|a,b|a.postcode.cmp(&b.postcode));
+        Ok(packer)
+    }
+
+    /// Prepare postcodes that didn't come from an input file, e.g. from other pack files (see
+    /// repack.rs). Outward codes are left out and worked out again from the postcodes, and the
+    /// bounding box is the one around the postcodes.
+    pub fn from_postcodes(postcodes: impl IntoIterator<Item=PostcodeInfo>, last_update: u64, scheme: Scheme) -> Self{
+        let mut state = ReadState::new(false, false);
+        let mut postcodes: Vec<PostcodeInfo> = postcodes.into_iter().filter(|p| !p.is_partial).collect();
+        for p in &postcodes{
+            state.minll = Point{x: state.minll.x.min(p.location.x), y: state.minll.y.min(p.location.y)};
+            state.maxll = Point{x: state.maxll.x.max(p.location.x), y: state.maxll.y.max(p.location.y)};
+        }
+        state.total = postcodes.len();
+        state.kept = postcodes.len();
+        let mut packer = state.into_packer();
+        packer.last_update = last_update;
+        packer.scheme = scheme;
+        insert_outward_averages(&mut postcodes, scheme);
+        postcodes.par_sort_by(|a,b|a.postcode.cmp(&b.postcode));
+        packer.postcodes = postcodes;
+        packer
+    }
+
+    /// The postcodes that will be packed, in the order they will be written
+    pub fn postcodes(&self) -> &[PostcodeInfo]{
+        &self.postcodes
+    }
+
+    /// Lower left and upper right corners of the bounding box
+    pub fn bounds(&self) -> (Point, Point){
+        (self.minll, self.maxll)
+    }
+
+    /// Date of the most recently introduced postcode, as a unix timestamp
+    pub fn last_update(&self) -> u64{
+        self.last_update
+    }
+
+    /// Release date of the dataset, as a unix timestamp, `None` if it isn't known
+    pub fn release_date(&self) -> Option<u64>{
+        (self.release_date > 0).then_some(self.release_date)
+    }
+
+    /// Record the release date of the dataset, as a unix timestamp. This is often a month or
+    /// two after the most recently introduced postcode. Needs format version 22 or newer.
+    pub fn set_release_date(&mut self, release_date: Option<u64>){
+        self.release_date = release_date.unwrap_or(0);
+    }
+
+    pub fn stats(&self) -> &ReadStats{
+        &self.stats
+    }
+
+    /// Postcodes that were skipped because they can't be stored in a pack file, in file order
+    pub fn unpackable(&self) -> &[Unpackable]{
+        &self.unpackable
+    }
+
+    /// Every row that was skipped, in file order, if `ReadOptions::report_dropped` was set
+    pub fn dropped(&self) -> &[Dropped]{
+        &self.dropped
+    }
+
+    /// Postcodes whose locations are outside the usual region of their postcode area, which
+    /// are likely to be mistakes in the input, in order of postcode. See anomaly.rs.
+    pub fn anomalies(&self) -> Vec<Anomaly>{
+        anomaly::find_anomalies(&self.postcodes, self.scheme)
+    }
+
+    /// File format version that will be written
+    pub fn format_version(&self) -> u32{
+        self.version
+    }
+
+    /// Choose the file format version to write, must be one of `WRITABLE_VERSIONS`
+    pub fn set_format_version(&mut self, version: u32) -> Result<(), PostcodeError>{
+        if !WRITABLE_VERSIONS.contains(&version){
+            return Err(PostcodeError::UnsupportedVersion(version));
+        }
+        self.version = version;
+        Ok(())
+    }
+
+    /// Compression that will be applied to the postcode data
+    pub fn compression(&self) -> Compression{
+        self.compression
+    }
+
+    /// Choose how to compress the postcode data, anything other than `Compression::None`
+    /// needs format version 4 or newer
+    pub fn set_compression(&mut self, compression: Compression){
+        self.compression = compression;
+    }
+
+    /// Whether a spatial index will be written
+    pub fn spatial_index(&self) -> bool{
+        self.spatial_index
+    }
+
+    /// Choose whether to write a spatial index, needs format version 5 or newer
+    pub fn set_spatial_index(&mut self, spatial_index: bool){
+        self.spatial_index = spatial_index;
+    }
+
+    /// Kind of spatial index that will be written, if there is one
+    pub fn index_kind(&self) -> IndexKind{
+        self.index_kind
+    }
+
+    /// Choose the kind of spatial index to write, a grid by default. A k-d tree or geohash
+    /// buckets need format version 18 or newer.
+    pub fn set_index_kind(&mut self, kind: IndexKind){
+        self.index_kind = kind;
+    }
+
+    /// Whether the country of each postcode will be written
+    pub fn countries(&self) -> bool{
+        self.countries
+    }
+
+    /// Choose whether to write the country of each postcode, needs format version 7 or newer
+    pub fn set_countries(&mut self, countries: bool){
+        self.countries = countries;
+    }
+
+    /// Whether the area, district and sector centroid tables will be written
+    pub fn centroids(&self) -> bool{
+        self.centroids
+    }
+
+    /// Choose whether to write the centroid tables, needs format version 8 or newer
+    pub fn set_centroids(&mut self, centroids: bool){
+        self.centroids = centroids;
+    }
+
+    /// Whether the bounding box of each outward code will be written
+    pub fn prefix_extents(&self) -> bool{
+        self.prefix_extents
+    }
+
+    /// Choose whether to write the bounding box of each outward code, needs format version 20 or
+    /// newer
+    pub fn set_prefix_extents(&mut self, prefix_extents: bool){
+        self.prefix_extents = prefix_extents;
+    }
+
+    /// Whether the list of outward codes and sectors that exist will be written
+    pub fn sector_bitmap(&self) -> bool{
+        self.sector_bitmap
+    }
+
+    /// Choose whether to write the list of outward codes and sectors that exist, needs format
+    /// version 21 or newer
+    pub fn set_sector_bitmap(&mut self, sector_bitmap: bool){
+        self.sector_bitmap = sector_bitmap;
+    }
+
+    /// Chunk size that prefix blocks are aligned to, 0 if they are not aligned
+    pub fn chunk_size(&self) -> u32{
+        self.chunk_size
+    }
+
+    /// Choose a chunk size in bytes, so that each prefix block can be fetched with as few
+    /// chunk-aligned range requests as possible. Needs format version 9 or newer.
+    pub fn set_chunk_size(&mut self, chunk_size: u32){
+        self.chunk_size = chunk_size;
+    }
+
+    /// Fingerprint of the key that the file will be signed with, all zeros if it won't be signed
+    pub fn key_id(&self) -> [u8;8]{
+        self.key_id
+    }
+
+    /// Record the fingerprint of the signing key in the header, see `signature::key_id`.
+    /// Needs format version 11 or newer.
+    pub fn set_key_id(&mut self, key_id: [u8;8]){
+        self.key_id = key_id;
+    }
+
+    /// Whether the postcode data will be entropy coded
+    pub fn entropy(&self) -> bool{
+        self.entropy
+    }
+
+    /// Choose whether to entropy code the postcode data, see entropy.rs. Needs format version 14
+    /// or newer.
+    pub fn set_entropy(&mut self, entropy: bool){
+        self.entropy = entropy;
+    }
+
+    /// Whether each prefix block will have its own bounding box
+    pub fn local_bounds(&self) -> bool{
+        self.local_bounds
+    }
+
+    /// Choose whether to give each prefix block its own bounding box, so that the locations are
+    /// more precise (see local.rs). Needs format version 15 or newer.
+    pub fn set_local_bounds(&mut self, local_bounds: bool){
+        self.local_bounds = local_bounds;
+    }
+
+    /// Whether the locations will be quantized in coordinate groups
+    pub fn groups(&self) -> bool{
+        self.groups
+    }
+
+    /// Choose whether to split the postcodes in to groups that are far apart, such as Great
+    /// Britain and Shetland, and quantize each group's locations in its own bounding box (see
+    /// group.rs). Needs format version 25 or newer.
+    pub fn set_groups(&mut self, groups: bool){
+        self.groups = groups;
+    }
+
+    /// Order of the records in each prefix block
+    pub fn order(&self) -> RecordOrder{
+        self.order
+    }
+
+    /// Choose the order of the records in each prefix block (see order.rs). Anything other than
+    /// alphabetical needs format version 26 or newer.
+    pub fn set_order(&mut self, order: RecordOrder){
+        self.order = order;
+    }
+
+    /// Coordinate reference system of the locations
+    pub fn crs(&self) -> Crs{
+        self.crs
+    }
+
+    /// Say which coordinate reference system the locations are in (see crs.rs). This doesn't
+    /// shift them, see `ReadOptions::crs`. Anything other than WGS84 needs format version 26
+    /// or newer.
+    pub fn set_crs(&mut self, crs: Crs){
+        self.crs = crs;
+    }
+
+    /// Bits per axis of the quantized locations
+    pub fn resolution(&self) -> u32{
+        self.resolution
+    }
+
+    /// Choose the bits per axis of the quantized locations, must be one of `RESOLUTIONS`.
+    /// Anything other than 16 needs format version 16 or newer.
+    pub fn set_resolution(&mut self, resolution: u32) -> Result<(), PostcodeError>{
+        if !RESOLUTIONS.contains(&resolution){
+            return Err(PostcodeError::IncompatibleOptions("the resolution must be 8, 12, 16 or 24 bits"));
+        }
+        self.resolution = resolution;
+        Ok(())
+    }
+
+    /// Which country's codes will be packed, this comes from `ReadOptions::scheme`
+    pub fn scheme(&self) -> Scheme{
+        self.scheme
+    }
+
+    /// Names of the attributes that will be written, empty if there are none
+    pub fn attributes(&self) -> &[String]{
+        &self.attributes
+    }
+
+    /// Name the values in each postcode's `attributes`, so that they are written. This comes from
+    /// `ReadOptions::attributes`, and needs format version 19 or newer.
+    pub fn set_attributes(&mut self, names: Vec<String>){
+        self.attributes = names;
+    }
+
+    /// How the file was made, empty if it won't say
+    pub fn metadata(&self) -> &Metadata{
+        &self.metadata
+    }
+
+    /// Record how the file was made, such as the generator, the source dataset and the options it
+    /// was packed with. Needs format version 23 or newer.
+    pub fn set_metadata(&mut self, metadata: Metadata){
+        self.metadata = metadata;
+    }
+
+    /// Number of postcodes taken out of the bounding box
+    pub fn clip_bbox(&self) -> usize{
+        self.clip_bbox
+    }
+
+    /// Take the `count` postcodes that stretch the bounding box the most out of it, so that the
+    /// other locations are more precise (see outlier.rs), 0 puts them back. Needs format version
+    /// 24 or newer.
+    pub fn set_clip_bbox(&mut self, count: usize) -> Result<(), PostcodeError>{
+        let outliers = self.outliers(count)?;
+        self.clip_to(&outliers, count);
+        Ok(())
+    }
+
+    /// The `count` postcodes that stretch the bounding box the most, in the order they would be
+    /// taken out of it
+    pub fn outliers(&self, count: usize) -> Result<Vec<Outlier>, PostcodeError>{
+        if count > MAX_OUTLIERS{
+            return Err(PostcodeError::IncompatibleOptions("at most 1000 postcodes can be taken out of the bounding box"));
+        }
+        let mut extremes = Extremes::new(count);
+        for p in &self.postcodes{
+            extremes.add(p);
+        }
+        Ok(extremes.outliers(count))
+    }
+
+    /// Shrink the bounding box to leave out some outliers
+    pub(crate) fn clip_to(&mut self, outliers: &[Outlier], count: usize){
+        let full = *self.full_bounds.get_or_insert((self.minll, self.maxll));
+        (self.minll, self.maxll) = outliers.last().map(|o| o.bounds).unwrap_or(full);
+        self.clip_bbox = count;
+    }
+
+    /// The bounding box of each prefix block or the coordinate groups, empty if they don't have
+    /// their own
+    pub(crate) fn local_boxes(&self) -> LocalBoxes{
+        if self.groups{
+            let mut extents = OutwardExtents::new(self.scheme);
+            for p in &self.postcodes{
+                extents.add(p);
+            }
+            return LocalBoxes::grouped(extents.groups());
+        }
+        if !self.local_bounds{
+            return LocalBoxes::default();
+        }
+        let mut extents = BlockExtents::default();
+        for p in &self.postcodes{
+            extents.add(p);
+        }
+        let (minll, maxll) = self.stored_bounds();
+        extents.boxes(minll, maxll)
+    }
+
+    /// The bounding box as it will be stored in the file.
+    ///
+    /// From version 3 the extents are quantized, so this is slightly larger than `bounds()`.
+    /// Locations are always packed relative to this box, since that's what the reader will see.
+    pub fn stored_bounds(&self) -> (Point, Point){
+        if self.version < 3{
+            return (self.minll, self.maxll);
+        }
+        let minll = Point{
+            x: dequantize_extent(quantize_extent(self.minll.x, 180.0, false), 180.0),
+            y: dequantize_extent(quantize_extent(self.minll.y, 90.0, false), 90.0),
+        };
+        let maxll = Point{
+            x: dequantize_extent(quantize_extent(self.maxll.x, 180.0, true), 180.0),
+            y: dequantize_extent(quantize_extent(self.maxll.y, 90.0, true), 90.0),
+        };
+        (minll, maxll)
+    }
+
+    /// Encode the postcodes as packed records
+    pub fn pack(&self) -> Result<Vec<DeltaPacked>, PostcodeError>{
+        let (minll, maxll) = self.stored_bounds();
+        pack_postcodes_in(&self.postcodes, minll, maxll, &self.local_boxes(), self.version, self.resolution, self.scheme, self.order)
+    }
+
+    /// Check that every postcode can be read back from a pack file.
+    ///
+    /// Each postcode must be found, and must be within one quantization step
+    /// of its original location. Returns the postcodes that failed.
+    pub fn verify(&self, reader: &Reader) -> Vec<Mismatch>{
+        let (minll, maxll) = self.stored_bounds();
+        let max = max_quantized(self.resolution) as f64;
+        let step_x = (maxll.x - minll.x) / max;
+        let step_y = (maxll.y - minll.y) / max;
+        self.postcodes.iter()
+            .filter_map(|p| check_postcode(reader, p, step_x, step_y))
+            .collect()
+    }
+
+    /// Check that the optional sections and compression are supported by the format version
+    pub(crate) fn check_options(&self) -> Result<(), PostcodeError>{
+        if self.compression != Compression::None && self.version < 4{
+            return Err(PostcodeError::IncompatibleOptions("compression needs file format version 4 or newer"));
+        }
+        if self.spatial_index && self.version < 5{
+            return Err(PostcodeError::IncompatibleOptions("the spatial index needs file format version 5 or newer"));
+        }
+        if self.spatial_index && self.version < self.index_kind.min_version(){
+            return Err(PostcodeError::IncompatibleOptions("the k-d tree and geohash spatial indexes need file format version 18 or newer"));
+        }
+        if self.countries && self.version < 7{
+            return Err(PostcodeError::IncompatibleOptions("countries need file format version 7 or newer"));
+        }
+        if self.centroids && self.version < 8{
+            return Err(PostcodeError::IncompatibleOptions("centroid tables need file format version 8 or newer"));
+        }
+        if self.chunk_size > 0 && self.version < 9{
+            return Err(PostcodeError::IncompatibleOptions("chunk alignment needs file format version 9 or newer"));
+        }
+        if self.key_id != [0;8] && self.version < 11{
+            return Err(PostcodeError::IncompatibleOptions("the signing key fingerprint needs file format version 11 or newer"));
+        }
+        if self.entropy && self.version < 14{
+            return Err(PostcodeError::IncompatibleOptions("entropy coding needs file format version 14 or newer"));
+        }
+        if self.local_bounds && self.version < 15{
+            return Err(PostcodeError::IncompatibleOptions("local bounding boxes need file format version 15 or newer"));
+        }
+        if self.resolution != DEFAULT_RESOLUTION && self.version < 16{
+            return Err(PostcodeError::IncompatibleOptions("resolutions other than 16 bits need file format version 16 or newer"));
+        }
+        if self.scheme != Scheme::Uk && self.version < 17{
+            return Err(PostcodeError::IncompatibleOptions("codes other than UK postcodes need file format version 17 or newer"));
+        }
+        if !self.attributes.is_empty() && self.version < 19{
+            return Err(PostcodeError::IncompatibleOptions("attributes need file format version 19 or newer"));
+        }
+        if self.prefix_extents && self.version < 20{
+            return Err(PostcodeError::IncompatibleOptions("prefix extents need file format version 20 or newer"));
+        }
+        if self.sector_bitmap && self.version < 21{
+            return Err(PostcodeError::IncompatibleOptions("the sector bitmap needs file format version 21 or newer"));
+        }
+        if self.release_date > 0 && self.version < 22{
+            return Err(PostcodeError::IncompatibleOptions("the release date needs file format version 22 or newer"));
+        }
+        if !self.metadata.is_empty() && self.version < 23{
+            return Err(PostcodeError::IncompatibleOptions("metadata needs file format version 23 or newer"));
+        }
+        self.metadata.check()?;
+        if self.clip_bbox > 0 && self.version < 24{
+            return Err(PostcodeError::IncompatibleOptions("clipping the bounding box needs file format version 24 or newer"));
+        }
+        if self.groups && self.version < 25{
+            return Err(PostcodeError::IncompatibleOptions("coordinate groups need file format version 25 or newer"));
+        }
+        if self.groups && (self.local_bounds || self.clip_bbox > 0){
+            return Err(PostcodeError::IncompatibleOptions("coordinate groups can't be used with local bounding boxes or a clipped bounding box"));
+        }
+        if self.order != RecordOrder::Alphabetical && self.version < 26{
+            return Err(PostcodeError::IncompatibleOptions("record orders other than alphabetical need file format version 26 or newer"));
+        }
+        if self.crs != Crs::Wgs84 && self.version < 26{
+            return Err(PostcodeError::IncompatibleOptions("coordinate reference systems other than WGS84 need file format version 26 or newer"));
+        }
+        if self.scheme != Scheme::Uk && self.centroids{
+            return Err(PostcodeError::IncompatibleOptions("centroid tables are only for UK postcodes"));
+        }
+        if self.version >= 9 && self.compression != Compression::None{
+            return Err(PostcodeError::IncompatibleOptions("file format version 9 onwards is for range requests, so it can't be compressed"));
+        }
+        Ok(())
+    }
+
+    fn build_spatial_index(&self, boxes: &LocalBoxes) -> SpatialIndex{
+        let (file_min, file_max) = self.stored_bounds();
+        let mut points: Vec<([u8;7], (u32, u32), IndexPoint)> = self.postcodes.iter()
+            .filter_map(|p| {
+                let block = lut_index(p.postcode.as_bytes())?;
+                // Quantized as in the file, so that it's the same as `StreamPacker`
+                let (minll, maxll) = boxes.bounds(block, p.postcode.as_bytes(), file_min, file_max);
+                let quantized = quantize_ll(minll, maxll, p.location, self.resolution);
+                let location = dequantize_ll(minll, maxll, quantized.0, quantized.1, self.resolution);
+                let (long, lat) = boxes.to_file(block, p.postcode.as_bytes(), quantized, self.resolution, file_min, file_max);
+                let postcode = p.postcode.as_bytes().get(0..7)?.try_into().ok()?;
+                Some((postcode, quantized, IndexPoint{block, long, lat, is_partial: p.is_partial, terminated: p.terminated.is_some(), location}))
+            })
+            .collect();
+        // The index counts the entries of each block in the order they're written
+        order::arrange(&mut points, self.order, |&(postcode, (long, lat), _)| (postcode, long, lat));
+        SpatialIndex::build(self.index_kind, points.into_iter().map(|(_, _, p)| p))
+    }
+
+    /// Write the pack file to the named path, returns the number of bytes written
+    pub fn write(&self, path: &str) -> Result<u64, PostcodeError>{
+        let outfile = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+        self.write_to(outfile)
+    }
+
+    /// Write the postcodes to the named path in any of the output formats, returns the number of
+    /// bytes written. The other options are only used for pack files.
+    pub fn write_as(&self, format: OutputFormat, path: &str) -> Result<u64, PostcodeError>{
+        match format{
+            OutputFormat::Pack => self.write(path),
+            OutputFormat::Sqlite => output::write_sqlite(&self.postcodes, path),
+            OutputFormat::Geojson => output::write_geojson(&self.postcodes, path, false),
+            OutputFormat::Ndjson => output::write_geojson(&self.postcodes, path, true),
+            OutputFormat::Parquet => output::write_parquet(&self.postcodes, path),
+            OutputFormat::Flatgeobuf => output::write_flatgeobuf(&self.postcodes, path),
+            OutputFormat::WordsCsv => output::write_words_csv(&self.postcodes, path),
+        }
+    }
+
+    /// Write the pack file, returns the number of bytes written
+    pub fn write_to<W: Write>(&self, outfile: W) -> Result<u64, PostcodeError>{
+        self.check_options()?;
+        let packed_codes = self.pack()?;
+        let boxes = self.local_boxes();
+        let mut sections = Sections::default();
+        if self.spatial_index{
+            sections.index = self.build_spatial_index(&boxes).to_bytes();
+        }
+        if self.countries{
+            sections.countries = country::encode_runs(self.postcodes.iter().map(|p| (p.postcode.as_bytes(), p.country)));
+        }
+        if self.centroids{
+            let mut totals = CentroidTotals::default();
+            for p in &self.postcodes{
+                totals.add(p);
+            }
+            let (minll, maxll) = self.stored_bounds();
+            sections.centroids = totals.to_bytes(minll, maxll);
+        }
+        if !self.attributes.is_empty(){
+            sections.attributes = attribute::encode(&self.attributes, self.postcodes.iter().map(|p| (p.postcode.as_bytes(), p.attributes.as_slice())))?;
+        }
+        if self.prefix_extents{
+            let mut totals = ExtentTotals::new(self.scheme);
+            for p in &self.postcodes{
+                totals.add(p);
+            }
+            let (minll, maxll) = self.stored_bounds();
+            sections.prefix_extents = totals.to_bytes(minll, maxll);
+        }
+        if self.sector_bitmap{
+            let mut totals = SectorTotals::new(self.scheme);
+            for p in &self.postcodes{
+                totals.add(p);
+            }
+            sections.sector_bitmap = totals.to_bytes();
+        }
+        if self.version >= 24{
+            let (minll, maxll) = self.stored_bounds();
+            let mut totals = OutlierTotals::new(minll, maxll);
+            for p in &self.postcodes{
+                totals.add(p);
+            }
+            sections.outliers = totals.to_bytes();
+        }
+        let prefixes = self.postcodes.iter().map(|p| &p.postcode[..PREFIX_LEN]);
+        self.write_packed(outfile, prefixes, &packed_codes, &boxes, &sections)
+    }
+
+    /// Write the header and lookup table from this packer, followed by the packed records.
+    ///
+    /// `prefixes` gives the first two characters of the postcode of each record.
+    pub(crate) fn write_packed<'a, W: Write, I: Iterator<Item=&'a str>>(&self, mut outfile: W, prefixes: I, packed_codes: &[DeltaPacked], boxes: &LocalBoxes, sections: &Sections) -> Result<u64, PostcodeError>{
+
+        /*
+        File structure:
+        (all numbers in little endian unless specified otherwise)
+
+        Header, 16 bytes:
+
+            magic:   4 bytes "UKPP" - magic number for "UK Postcode Pack"
+            version: 4 bytes (u32)  - version number of the file format (this code generates versions 2 to 26)
+            date:    8 bytes (u64)  - a unix epoch of the day that the most recently introduced postcode was introduced, which stands in for the release date of the dataset that the file was generated from
+
+        Section table, version 26 onwards:
+
+            Everything after the header is in sections, which a table after the header gives
+            the place of, see section.rs. Each section is laid out as the part of the same name
+            below, the fields that were only there to give the length of another part are gone.
+
+        Compression, version 4 onwards, 4 bytes:
+
+            codec:   4 bytes (u32)  - compression applied to the postcode data (0 = none, 1 = gzip, 2 = zstd, 3 = brotli)
+
+        Spatial index length, version 5 onwards, 4 bytes:
+
+            index_len: 4 bytes (u32) - length of the spatial index section at the end of the file, 0 if there is no index
+
+        Countries length, version 7 onwards, 4 bytes:
+
+            countries_len: 4 bytes (u32) - length of the countries section before the spatial index, 0 if there are no countries
+
+        Centroids length, version 8 onwards, 4 bytes:
+
+            centroids_len: 4 bytes (u32) - length of the centroids section before the countries, 0 if there are no centroids
+
+        Chunk size, version 9 onwards, 4 bytes:
+
+            chunk_size: 4 bytes (u32) - prefix blocks only cross a multiple of this many bytes if they are longer than it, 0 if not aligned
+
+        Signing key, version 11 onwards, 8 bytes:
+
+            key_id: 8 bytes - fingerprint of the key that signed the file (see signature.rs), 0 if it is not signed
+
+        Entropy code, version 14 onwards, 256 bytes:
+
+            code_lengths: 256 bytes (u8 each) - length of the code for each byte value in the postcode data (see entropy.rs), all 0 if it is not coded
+
+        Local bounding boxes count, version 15 onwards, 4 bytes:
+
+            local_boxes: 4 bytes (u32) - number of prefix blocks with their own bounding box, 0 if there are none
+
+        Resolution, version 16 onwards, 4 bytes:
+
+            resolution: 4 bytes (u32) - bits per axis of the quantized locations: 8, 12, 16 or 24 (always 16 before version 16)
+
+        Postcode scheme, version 17 onwards, 4 bytes:
+
+            scheme: 4 bytes (u32) - which country's codes the file has (0 = UK postcodes, 1 = Irish Eircodes), see scheme.rs. Always UK before version 17
+
+        Spatial index kind, version 18 onwards, 4 bytes:
+
+            index_kind: 4 bytes (u32) - layout of the spatial index section (0 = grid, 1 = k-d tree, 2 = geohash), see spatial.rs, kdtree.rs and geohash.rs. Always a grid before version 18
+
+        Attributes length, version 19 onwards, 4 bytes:
+
+            attributes_len: 4 bytes (u32) - length of the attributes section before the centroids, 0 if there are no attributes
+
+        Prefix extents length, version 20 onwards, 4 bytes:
+
+            prefix_extents_len: 4 bytes (u32) - length of the prefix extents section before the attributes, 0 if there are none
+
+        Sector bitmap length, version 21 onwards, 4 bytes:
+
+            sector_bitmap_len: 4 bytes (u32) - length of the sector bitmap section before the prefix extents, 0 if there is none
+
+        Release date, version 22 onwards, 8 bytes:
+
+            release_date: 8 bytes (u64) - a unix epoch of the day the dataset was released (e.g. 2025-02-01 for the February 2025 ONSPD), 0 if it isn't known
+
+        Metadata, version 23 onwards, 4 + metadata_len bytes:
+
+            metadata_len: 4 bytes (u32) - length of the metadata fields, 0 if there are none
+            metadata:     metadata_len bytes - how the file was made, such as the generator and the options it was packed with, see metadata.rs
+
+        Outliers, version 24 onwards, 4 + 15*count bytes:
+
+            count:    4 bytes (u32) - number of entries outside the bounding box, 0 if there are none
+            outliers: count*15 bytes - their exact locations, see outlier.rs. Their records are clamped to the edge of the box.
+
+        Coordinate groups, version 25 onwards, 8 + 16*count + 5*members bytes:
+
+            count:   4 bytes (u32) - number of groups, 0 if there are none
+            groups:  count*16 bytes - the bounding box of each group, see group.rs
+            members: 4 bytes (u32) - number of outward codes that aren't in the main group
+            outward codes: members*5 bytes - each one and its group. Locations are quantized in their group's box.
+
+        Boudning box extents, version 1 and 2, 4*8 = 32 bytes:
+
+            minlong: 8 bytes (f64)
+            maxlong: 8 bytes (f64)
+            minlat:  8 bytes (f64)
+            maxlat:  8 bytes (f64)
+
+        Boudning box extents, version 3 onwards, 4*4 = 16 bytes:
+
+            minlong: 4 bytes (u32, fraction of the range -180 to 180)
+            maxlong: 4 bytes (u32, fraction of the range -180 to 180)
+            minlat:  4 bytes (u32, fraction of the range -90 to 90)
+            maxlat:  4 bytes (u32, fraction of the range -90 to 90)
+
+        Local bounding boxes, version 15 onwards, local_boxes*10 bytes:
+
+            see local.rs. The locations in a block with its own box are quantized in that box,
+            instead of in the file's bounding box.
+
+        Quick lookup table, 26*36*4 = 3744 bytes:
+
+            list of 26*36 index values
+                position: 4 bytes (u32, byte offset into postcode data list)
+            last_pos: 4 bytes (u32, conveniently is just above last entry in the table)
+
+        Quick lookup table, version 9 onwards, 26*36*8 = 7488 bytes:
+
+            list of 26*36 byte ranges
+                start: 4 bytes (u32, absolute position in the file of the first record of the block)
+                end:   4 bytes (u32, absolute position in the file just after the last record of the block)
+
+            Blocks are in the same order as in earlier versions, but there can be padding (zeros)
+            between them, so that a block can be fetched with a range request that covers as few
+            chunks as possible. Version 9 files are never compressed.
+
+        Postcode data, variable length (3 to 8 bytes per postcode, before compression):
+
+            list of postcodes:
+                format:   1 bytes (bitfield)
+                    postcode_is_delta: 1 bit (flag indicating if postcode is delta-encoded)
+                    latlong_is_delta:  1 bit (flag indicating if lat/long is delta-encoded)
+                    extra_data: 6 bits
+                        postcode_is_delta == 1 => postcode_delta:    6 bits (u6 number to add to previous postcode to calculate this postcode)
+                        postcode_is_delta == 0 => special mode: 1 bit
+                            0 =>
+                                00000 => No Special mode
+                                (all other values) => reserved
+                            1 => Special mode
+                                00000 => Postcode only contains outward code, match on first 4 chars only
+                                (all other values) => reserved
+                postcode: 0 or 3 bytes (custom encoding, present only if not postcode_is_delta)
+                longlat:  2 or 4 bytes (2 x i8 if latlong_is_delta, or 2 x u16 otherwise)
+
+        Postcode data, version 6 onwards (3 to 9 bytes per postcode, before compression):
+
+            As above, but with a flag for terminated postcodes in the format byte:
+                format:   1 bytes (bitfield)
+                    postcode_is_delta: 1 bit
+                    latlong_is_delta:  1 bit
+                    terminated:        1 bit (flag indicating that the postcode is no longer in use)
+                    extra_data: 5 bits
+                        postcode_is_delta == 1 => postcode_delta: 5 bits (u5 number to add to previous postcode, minus one)
+                        postcode_is_delta == 0 => special mode: 1 bit, then 0000 (other values reserved)
+                            1 => Postcode only contains outward code
+                postcode: 0 or 3 bytes
+                longlat:  2 or 4 bytes
+                terminated_year: 0 or 1 bytes (u8, years since 1900, present only if terminated)
+
+        Postcode data, version 12 onwards (1 to 9 bytes per postcode, before compression):
+
+            As version 6, with one more kind of record for runs of postcodes at exactly the same
+            location as the record before (which is common in large buildings and PO box ranges):
+                format: 1 byte, 0x41 (latlong_is_delta with special mode 00001, reserved before version 12)
+                count:  1 byte (u8, number of postcodes in the run, minus one)
+                deltas: count+1 bytes (u8 each, number to add to the previous postcode, minus one)
+            The postcodes in a run are never outward codes or terminated.
+
+        Postcode data, version 13 onwards:
+
+            As version 12, with one more kind of record for full postcodes whose deltas are too big
+            for the other records:
+                format:    1 byte, 0x42 (latlong_is_delta with special mode 00010), or 0x62 if terminated
+                postcode:  varint (number to add to the previous postcode, minus one)
+                lat, long: 2 varints (deltas, zigzag encoded: 0, -1, 1, -2, 2, ... are stored as 0, 1, 2, 3, 4, ...)
+                terminated_year: 0 or 1 bytes
+            Varints are unsigned LEB128: 7 bits per byte, least significant first, with the top
+            bit set on every byte except the last.
+
+        Postcode data, version 14 onwards:
+
+            As version 13, but if the header has an entropy code, each prefix block is coded on its
+            own (see entropy.rs). The byte ranges in the quick lookup table are of the coded blocks.
+
+        Postcode data, version 16 onwards:
+
+            As version 14, but the absolute lat and long in each record are as many bytes as the
+            resolution needs (1 byte each for 8 bits, 2 for 12 or 16 bits, 3 for 24 bits), and
+            locations are fractions of 2^resolution - 1 instead of 65535. The deltas are the same.
+
+        Postcode data, version 17 onwards:
+
+            As version 16, but the 3 byte postcodes are packed as the header's scheme says (see
+            scheme.rs). Outward codes are packed the same way for every scheme.
+
+        Sector bitmap, version 21 onwards, optional, variable length:
+
+            see sector.rs
+
+        Prefix extents, version 20 onwards, optional, variable length:
+
+            see extent.rs
+
+        Attributes, version 19 onwards, optional, variable length:
+
+            see attribute.rs
+
+        Centroids, version 8 onwards, optional, variable length:
+
+            see centroid.rs
+
+        Countries, version 7 onwards, optional, variable length:
+
+            see country.rs
+
+        Spatial index, version 5 onwards, optional, variable length:
+
+            see spatial.rs, and kdtree.rs or geohash.rs for the k-d tree or geohash buckets (version 18 onwards)
+
+        Checksums, version 10 onwards, 20 bytes:
+
+            data_crc:      4 bytes (u32, CRC32 of the postcode data, including any padding)
+            centroids_crc: 4 bytes (u32, CRC32 of the centroids section, 0 if there is none)
+            countries_crc: 4 bytes (u32, CRC32 of the countries section, 0 if there is none)
+            index_crc:     4 bytes (u32, CRC32 of the spatial index section, 0 if there is none)
+            file_crc:      4 bytes (u32, CRC32 of the whole file before this field)
+
+        Checksums, version 19 onwards, 24 bytes:
+
+            As above, with the checksum of the attributes section after the postcode data's:
+            data_crc, attributes_crc, centroids_crc, countries_crc, index_crc, file_crc
+
+        Checksums, version 20 onwards, 28 bytes:
+
+            As above, with the checksum of the prefix extents section after the postcode data's:
+            data_crc, prefix_extents_crc, attributes_crc, centroids_crc, countries_crc, index_crc, file_crc
+
+        Checksums, version 21 onwards, 32 bytes:
+
+            As above, with the checksum of the sector bitmap section after the postcode data's:
+            data_crc, sector_bitmap_crc, prefix_extents_crc, attributes_crc, centroids_crc, countries_crc, index_crc, file_crc
+
+            Readers check these when the file is loaded, so that a truncated or damaged download
+            is reported as such. A reader that only fetches some of the file (with range requests)
+            can check the sections it has.
+
+        */
+
+        // version 26 replaces the fixed fields with a table of sections
+        if self.version >= 26{
+            return self.write_sectioned(outfile, prefixes, packed_codes, boxes, sections);
+        }
+
+        // The whole file is put together in memory and written at once, since most of it is
+        // small fields and records
+        let data_len: usize = packed_codes.iter().map(|p| p.bytes().len()).sum();
+        let sections_len: usize = [&sections.outliers, &sections.sector_bitmap, &sections.prefix_extents, &sections.attributes, &sections.centroids, &sections.countries, &sections.index].iter().map(|s| s.len()).sum();
+        let mut out: Vec<u8> = Vec::with_capacity(HEADER_CAPACITY + (boxes.len() * 10) + (LUT_SIZE * 8) + data_len + sections_len);
+
+        // Header...
+        out.extend_from_slice(b"UKPP"); // magic number is 1347439445
+
+        // version 2 introduces outward-only postcodes
+        // version 3 stores the bounding box as u32s
+        out.extend_from_slice(&self.version.to_le_bytes());
+
+        // data update date
+        out.extend_from_slice(&self.last_update.to_le_bytes());
+
+        // version 4 adds compression
+        if self.version >= 4{
+            out.extend_from_slice(&self.compression.id().to_le_bytes());
+        }
+
+        // version 5 adds the spatial index
+        if self.version >= 5{
+            out.extend_from_slice(&(sections.index.len() as u32).to_le_bytes());
+        }
+
+        // version 7 adds the countries
+        if self.version >= 7{
+            out.extend_from_slice(&(sections.countries.len() as u32).to_le_bytes());
+        }
+
+        // version 8 adds the centroid tables
+        if self.version >= 8{
+            out.extend_from_slice(&(sections.centroids.len() as u32).to_le_bytes());
+        }
+
+        // version 9 adds absolute block ranges, aligned to chunks
+        if self.version >= 9{
+            out.extend_from_slice(&self.chunk_size.to_le_bytes());
+        }
+
+        // version 11 adds the fingerprint of the signing key
+        if self.version >= 11{
+            out.extend_from_slice(&self.key_id);
+        }
+
+        // version 14 adds the entropy code, which is trained on all of the records
+        let code_lengths = self.entropy.then(|| {
+            let data: Vec<u8> = packed_codes.iter().flat_map(|p|p.bytes()).copied().collect();
+            entropy::code_lengths(&data)
+        });
+        if self.version >= 14{
+            out.extend_from_slice(&code_lengths.unwrap_or([0;256]));
+        }
+
+        // version 15 adds the bounding box of each prefix block
+        if self.version >= 15{
+            out.extend_from_slice(&(boxes.len() as u32).to_le_bytes());
+        }
+
+        // version 16 adds the resolution
+        if self.version >= 16{
+            out.extend_from_slice(&self.resolution.to_le_bytes());
+        }
+
+        // version 17 adds the postcode scheme
+        if self.version >= 17{
+            out.extend_from_slice(&self.scheme.id().to_le_bytes());
+        }
+
+        // version 18 adds the kind of spatial index
+        if self.version >= 18{
+            out.extend_from_slice(&self.index_kind.id().to_le_bytes());
+        }
+
+        // version 19 adds the attributes
+        if self.version >= 19{
+            out.extend_from_slice(&(sections.attributes.len() as u32).to_le_bytes());
+        }
+
+        // version 20 adds the bounding box of each outward code
+        if self.version >= 20{
+            out.extend_from_slice(&(sections.prefix_extents.len() as u32).to_le_bytes());
+        }
+
+        // version 21 adds the outward codes and sectors that exist
+        if self.version >= 21{
+            out.extend_from_slice(&(sections.sector_bitmap.len() as u32).to_le_bytes());
+        }
+
+        // version 22 adds the release date, as well as the date of the newest postcode
+        if self.version >= 22{
+            out.extend_from_slice(&self.release_date.to_le_bytes());
+        }
+
+        // version 23 adds the metadata
+        if self.version >= 23{
+            let metadata = self.metadata.to_bytes();
+            out.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+            out.extend_from_slice(&metadata);
+        }
+
+        // version 24 adds the entries that are outside the bounding box
+        if self.version >= 24{
+            let outliers = if sections.outliers.is_empty() { &0u32.to_le_bytes()[..] } else { &sections.outliers };
+            out.extend_from_slice(outliers);
+        }
+
+        // version 25 adds the coordinate groups
+        if self.version >= 25{
+            let groups = boxes.groups().to_bytes();
+            out.extend_from_slice(&groups);
+        }
+
+        // bounding box extents
+        let minlong = self.minll.x;
+        let maxlong = self.maxll.x;
+        let minlat = self.minll.y;
+        let maxlat = self.maxll.y;
+        if self.version < 3{
+            out.extend_from_slice(&minlong.to_le_bytes());
+            out.extend_from_slice(&maxlong.to_le_bytes());
+            out.extend_from_slice(&minlat.to_le_bytes());
+            out.extend_from_slice(&maxlat.to_le_bytes());
+        }
+        else{
+            out.extend_from_slice(&quantize_extent(minlong, 180.0, false).to_le_bytes());
+            out.extend_from_slice(&quantize_extent(maxlong, 180.0, true).to_le_bytes());
+            out.extend_from_slice(&quantize_extent(minlat, 90.0, false).to_le_bytes());
+            out.extend_from_slice(&quantize_extent(maxlat, 90.0, true).to_le_bytes());
+        }
+        let boxes = boxes.to_bytes();
+        out.extend_from_slice(&boxes);
+
+        let chunked = (self.version >= 9).then_some(ChunkedLayout{lut_start: out.len() as u64, chunk_size: self.chunk_size, checksums: self.version >= 10, attributes_crc: self.version >= 19, prefix_extents_crc: self.version >= 20, sector_bitmap_crc: self.version >= 21, code_lengths});
+        write_records(&mut out, self.compression, chunked, prefixes, packed_codes, sections)?;
+        // version 10 ends with a checksum of everything before it
+        if self.version >= 10{
+            out.extend_from_slice(&crc32(&out).to_le_bytes());
+        }
+        outfile.write_all(&out)?;
+        outfile.flush()?;
+
+        Ok(out.len() as u64)
+    }
+
+    /// Write a version 26 file, with a table of sections after the header
+    fn write_sectioned<'a, W: Write, I: Iterator<Item=&'a str>>(&self, mut outfile: W, prefixes: I, packed_codes: &[DeltaPacked], boxes: &LocalBoxes, sections: &Sections) -> Result<u64, PostcodeError>{
+        let u32_bytes = |x: u32| x.to_le_bytes().to_vec();
+        let mut bounds = Vec::with_capacity(16);
+        bounds.extend_from_slice(&quantize_extent(self.minll.x, 180.0, false).to_le_bytes());
+        bounds.extend_from_slice(&quantize_extent(self.maxll.x, 180.0, true).to_le_bytes());
+        bounds.extend_from_slice(&quantize_extent(self.minll.y, 90.0, false).to_le_bytes());
+        bounds.extend_from_slice(&quantize_extent(self.maxll.y, 90.0, true).to_le_bytes());
+        let code_lengths = self.entropy.then(|| {
+            let data: Vec<u8> = packed_codes.iter().flat_map(|p|p.bytes()).copied().collect();
+            entropy::code_lengths(&data)
+        });
+
+        // Everything needed to read any block goes before the quick lookup table, and the
+        // sections that would be empty are left out
+        let mut before: Vec<(SectionKind, Vec<u8>)> = vec![
+            (SectionKind::Bounds, bounds),
+            (SectionKind::Resolution, u32_bytes(self.resolution)),
+            (SectionKind::Scheme, u32_bytes(self.scheme.id())),
+        ];
+        before.extend(code_lengths.map(|lengths| (SectionKind::EntropyCode, lengths.to_vec())));
+        before.push((SectionKind::LocalBoxes, boxes.to_bytes()));
+        // An empty list of outliers or groups is just its counts
+        if sections.outliers.len() > 4{
+            before.push((SectionKind::Outliers, sections.outliers.clone()));
+        }
+        if !boxes.groups().is_empty(){
+            before.push((SectionKind::Groups, boxes.groups().to_bytes()));
+        }
+        before.push((SectionKind::ReleaseDate, if self.release_date > 0 { self.release_date.to_le_bytes().to_vec() } else { Vec::new() }));
+        before.push((SectionKind::Metadata, self.metadata.to_bytes()));
+        before.push((SectionKind::KeyId, if self.key_id != [0;8] { self.key_id.to_vec() } else { Vec::new() }));
+        before.push((SectionKind::ChunkSize, if self.chunk_size > 0 { u32_bytes(self.chunk_size) } else { Vec::new() }));
+        before.push((SectionKind::RecordOrder, if self.order != RecordOrder::Alphabetical { u32_bytes(self.order.id()) } else { Vec::new() }));
+        before.push((SectionKind::Crs, if self.crs != Crs::Wgs84 { u32_bytes(self.crs.id()) } else { Vec::new() }));
+        before.retain(|(_, contents)| !contents.is_empty());
+        let after: Vec<(SectionKind, &[u8])> = [
+            (SectionKind::SectorBitmap, &sections.sector_bitmap),
+            (SectionKind::PrefixExtents, &sections.prefix_extents),
+            (SectionKind::Attributes, &sections.attributes),
+            (SectionKind::Centroids, &sections.centroids),
+            (SectionKind::Countries, &sections.countries),
+            (SectionKind::Index(self.index_kind), &sections.index),
+        ].into_iter().filter(|(_, contents)| !contents.is_empty()).map(|(kind, contents)| (kind, contents.as_slice())).collect();
+
+        let count = before.len() + 2 + after.len();
+        let lut_start = 16 + section::table_len(count) + before.iter().map(|(_, contents)| contents.len()).sum::<usize>();
+        let layout = ChunkedLayout{lut_start: lut_start as u64, chunk_size: self.chunk_size, checksums: false, attributes_crc: false, prefix_extents_crc: false, sector_bitmap_crc: false, code_lengths};
+        let (lut, data) = chunked_blocks(&layout, prefixes, packed_codes)?;
+        let mut all: Vec<(SectionKind, &[u8])> = before.iter().map(|(kind, contents)| (*kind, contents.as_slice())).collect();
+        all.push((SectionKind::LookupTable, &lut));
+        all.push((SectionKind::PostcodeData, &data));
+        all.extend(after);
+        if lut_start + all[before.len()..].iter().map(|(_, contents)| contents.len()).sum::<usize>() > u32::MAX as usize{
+            return Err(PostcodeError::IncompatibleOptions("file format version 26 can't be larger than 4GiB"));
+        }
+
+        let mut out = Vec::with_capacity(lut_start + lut.len() + data.len());
+        out.extend_from_slice(b"UKPP");
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.last_update.to_le_bytes());
+        out.extend_from_slice(&section::write_table(&all));
+        for (_, contents) in &all{
+            out.extend_from_slice(contents);
+        }
+        out.extend_from_slice(&crc32(&out).to_le_bytes());
+        outfile.write_all(&out)?;
+        outfile.flush()?;
+        Ok(out.len() as u64)
+    }
+}
+
+/// The optional sections after the postcode data, each is empty if it is not written
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Sections{
+    /// The outliers, which go in the header instead (version 24)
+    pub outliers: Vec<u8>,
+    pub sector_bitmap: Vec<u8>,
+    pub prefix_extents: Vec<u8>,
+    pub attributes: Vec<u8>,
+    pub centroids: Vec<u8>,
+    pub countries: Vec<u8>,
+    pub index: Vec<u8>,
+}
+
+/// The version 9 layout, where the quick lookup table has the absolute byte range of each block
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChunkedLayout{
+    /// Position of the quick lookup table in the file
+    pub lut_start: u64,
+    /// Blocks only cross a multiple of this many bytes if they have to, 0 for no alignment
+    pub chunk_size: u32,
+    /// Write the section checksums after the sections (version 10). The caller writes the
+    /// checksum of the whole file, since that covers the header too.
+    pub checksums: bool,
+    /// Include the checksum of the attributes section (version 19)
+    pub attributes_crc: bool,
+    /// Include the checksum of the prefix extents section (version 20)
+    pub prefix_extents_crc: bool,
+    /// Include the checksum of the sector bitmap section (version 21)
+    pub sector_bitmap_crc: bool,
+    /// Entropy code the blocks with this code (version 14)
+    pub code_lengths: Option<entropy::CodeLengths>,
+}
+
+/// Write the quick lookup table, the postcode data and the optional sections, everything after
+/// the bounding box. Returns the number of bytes written.
+///
+/// `prefixes` gives the first two characters of the postcode of each record.
+pub(crate) fn write_records<'a, W: Write, I: Iterator<Item=&'a str>>(mut outfile: W, compression: Compression, chunked: Option<ChunkedLayout>, prefixes: I, packed_codes: &[DeltaPacked], sections: &Sections) -> Result<u64, PostcodeError>{
+    if let Some(layout) = chunked{
+        return write_chunked_records(outfile, layout, prefixes, packed_codes, sections);
+    }
+    let mut written: u64 = 0;
+    let mut lut: HashMap<String, u32> = HashMap::new();
+
+    // Build and write the table
+    let mut last_prefix = String::new();
+    let mut pos = 0;
+    for (prefix, packed_code) in prefixes.zip(packed_codes){
+        let this_prefix = prefix.to_string();
+        if this_prefix != last_prefix{
+            lut.insert(this_prefix.clone(), pos as u32);
+            last_prefix = this_prefix;
+        }
+        pos += packed_code.bytes().len();
+    }
+
+    // Build the table in reverse to be able to calculate the offsets
+    let mut lastpos = pos as u32;
+    for c1 in (0..26).rev(){
+        let s1 = b'A'+c1;
+        for c2 in (0..36).rev(){
+            let s2 = if c2 > 9{ b'A'+c2-10 } else { b'0'+c2};
+            let s_bytes = [s1,s2];
+            let s = std::str::from_utf8(&s_bytes).unwrap().to_string();
+            let pos = lut.get(&s).copied().unwrap_or(lastpos);
+            lastpos = pos;
+            lut.insert(s, pos);
+        }
+    }
+
+    // Write it forwards, since that's the way the lookup will happen
+    for c1 in 0..26{
+        let s1 = b'A'+c1;
+        for c2 in 0..36{
+            let s2 = if c2 > 9{ b'A'+c2-10 } else { b'0'+c2};
+            let s_bytes = [s1,s2];
+            let s = std::str::from_utf8(&s_bytes).unwrap().to_string();
+            let pos = lut.get(&s).unwrap();
+            outfile.write_all(&pos.to_le_bytes())?;
+            written += 4;
+        }
+    }
+
+    // One extra element after end, total bytes
+    outfile.write_all(&lastpos.to_le_bytes())?;
+    written += 4;
+    if compression == Compression::None{
+        for p in packed_codes.iter(){
+            p.write_to_file(&mut outfile)?;
+        }
+        written += pos as u64;
+    }
+    else{
+        let data: Vec<u8> = packed_codes.iter().flat_map(|p|p.bytes()).copied().collect();
+        let data = compression.compress(&data)?;
+        outfile.write_all(&data)?;
+        written += data.len() as u64;
+    }
+    written += write_sections(&mut outfile, sections)?;
+
+    Ok(written)
+}
+
+/// The version 9 quick lookup table, and the postcode data with padding between the blocks
+fn chunked_blocks<'a, I: Iterator<Item=&'a str>>(layout: &ChunkedLayout, prefixes: I, packed_codes: &[DeltaPacked]) -> Result<(Vec<u8>, Vec<u8>), PostcodeError>{
+    let mut blocks: Vec<Vec<u8>> = vec![Vec::new(); LUT_SIZE];
+    for (prefix, packed_code) in prefixes.zip(packed_codes){
+        let index = lut_index(prefix.as_bytes()).ok_or(PostcodeError::InvalidFormat())?;
+        blocks[index].extend_from_slice(packed_code.bytes());
+    }
+    if let Some(lengths) = &layout.code_lengths{
+        for block in blocks.iter_mut().filter(|b| !b.is_empty()){
+            *block = entropy::encode(block, lengths);
+        }
+    }
+
+    let chunk = layout.chunk_size as u64;
+    let data_start = layout.lut_start + (LUT_SIZE as u64 * 8);
+    let mut ranges = Vec::with_capacity(LUT_SIZE);
+    let mut data = Vec::new();
+    for block in &blocks{
+        let len = block.len() as u64;
+        let pos = data_start + data.len() as u64;
+        // Start a new chunk if the block would cross in to the next one, or if it's longer than
+        // a chunk and not already at the start of one
+        let in_chunk = if chunk > 0 { pos % chunk } else { 0 };
+        let padding = if len > 0 && in_chunk > 0 && (in_chunk + len > chunk){
+            chunk - in_chunk
+        }
+        else{
+            0
+        };
+        data.resize(data.len() + padding as usize, 0);
+        let start = data_start + data.len() as u64;
+        data.extend_from_slice(block);
+        ranges.push((start, start + len));
+    }
+    if data_start + data.len() as u64 > u32::MAX as u64{
+        return Err(PostcodeError::IncompatibleOptions("file format version 9 can't be larger than 4GiB"));
+    }
+    let mut lut = Vec::with_capacity(LUT_SIZE * 8);
+    for (start, end) in ranges{
+        lut.extend_from_slice(&(start as u32).to_le_bytes());
+        lut.extend_from_slice(&(end as u32).to_le_bytes());
+    }
+    Ok((lut, data))
+}
+
+/// Write the version 9 quick lookup table, the postcode data with padding between the blocks, and
+/// the optional sections. Returns the number of bytes written.
+fn write_chunked_records<'a, W: Write, I: Iterator<Item=&'a str>>(mut outfile: W, layout: ChunkedLayout, prefixes: I, packed_codes: &[DeltaPacked], sections: &Sections) -> Result<u64, PostcodeError>{
+    let (lut, data) = chunked_blocks(&layout, prefixes, packed_codes)?;
+    outfile.write_all(&lut)?;
+    outfile.write_all(&data)?;
+    let mut written = (lut.len() + data.len()) as u64;
+    written += write_sections(&mut outfile, sections)?;
+    if layout.checksums{
+        let sector_bitmap = layout.sector_bitmap_crc.then_some(&sections.sector_bitmap);
+        let prefix_extents = layout.prefix_extents_crc.then_some(&sections.prefix_extents);
+        let attributes = layout.attributes_crc.then_some(&sections.attributes);
+        for section in [Some(&data), sector_bitmap, prefix_extents, attributes, Some(&sections.centroids), Some(&sections.countries), Some(&sections.index)].into_iter().flatten(){
+            let crc = if section.is_empty() { 0 } else { crc32(section) };
+            outfile.write_all(&crc.to_le_bytes())?;
+            written += 4;
+        }
+    }
+    Ok(written)
+}
+
+/// Write the optional sections after the postcode data, returns the number of bytes written
+fn write_sections<W: Write>(mut outfile: W, sections: &Sections) -> Result<u64, PostcodeError>{
+    let mut written: u64 = 0;
+    for section in [&sections.sector_bitmap, &sections.prefix_extents, &sections.attributes, &sections.centroids, &sections.countries, &sections.index]{
+        outfile.write_all(section)?;
+        written += section.len() as u64;
+    }
+    Ok(written)
+}
@@ -0,0 +1,419 @@
+/*
+
+Patch files, which turn one pack file in to another (usually the next release).
+
+Most postcodes don't change between releases, so a patch that lists only the changes is much
+smaller than a new pack file. The patch records the new file's header as it is, along with the
+entries that were removed, and the entries that were added or moved. Applying the patch
+rebuilds the new file from the old file's entries, and checks the result against a checksum.
+
+If the bounding box changes, every location is quantized differently, and the patch will
+contain every entry. The same goes for the entries of a prefix block whose own bounding box
+changes (version 15). Patches can only reproduce files that were written by this packer.
+
+The precision of a geohash spatial index isn't in the header, so the index is rebuilt with the
+precision of the old file's geohash index (or the default, if it doesn't have one), and a patch
+can't be made between files that would need it to change.
+
+Patch file structure:
+(all numbers in little endian)
+
+    magic:      4 bytes "UKPD" - "UK Postcode Pack Diff"
+    version:    4 bytes (u32, version of the patch format: 1, 2 for pack files with a resolution over
+                16 bits, 3 for pack files with attributes, or 4 for pack files with prefix extents)
+    old_len:    8 bytes (u64, length of the file the patch applies to)
+    old_crc:    4 bytes (u32, CRC32 of the file the patch applies to)
+    new_len:    8 bytes (u64, length of the file the patch produces)
+    new_crc:    4 bytes (u32, CRC32 of the file the patch produces)
+    header_len: 4 bytes (u32)
+    header:     header_len bytes (the new file, up to the quick lookup table)
+    changes:    gzip compressed, to the end of the file
+        removed_count: 4 bytes (u32)
+        removed:       removed_count * 7 bytes (canonical postcodes, outward codes are padded to 7 chars)
+        changed_count: 4 bytes (u32)
+        changed:       changed_count * 13 bytes (17 bytes from version 2)
+            postcode:   7 bytes
+            lat:        2 bytes (u16, quantized as in the pack file, u32 from version 2)
+            long:       2 bytes (u16, quantized as in the pack file, u32 from version 2)
+            terminated: 1 byte (year as stored in a version 6 pack file, 0xff if not terminated)
+            country:    1 byte (as stored in a version 7 pack file, 0 if not known)
+        centroids_len: 4 bytes (u32)
+        centroids:     centroids_len bytes (the new file's centroids section, see centroid.rs)
+        attributes_len: 4 bytes (u32, version 3 onwards)
+        attributes:     attributes_len bytes (the new file's attributes section, see attribute.rs)
+        prefix_extents_len: 4 bytes (u32, version 4 onwards)
+        prefix_extents:     prefix_extents_len bytes (the new file's prefix extents section, see extent.rs)
+
+The centroids and prefix extents are computed from the exact locations of the postcodes, which a
+pack file doesn't have, so they can't be rebuilt from the entries and are copied as they are. The attributes are
+copied as they are too, so that the entries don't need to carry them. The spatial index, the
+countries and the sector bitmap are rebuilt from the entries, and the records of each block are
+put in the order that the new file's header gives (see order.rs).
+
+*/
+use std::collections::HashMap;
+use rayon::prelude::*;
+
+use crate::{PostcodeError, Reader, Compression, Scheme, DEFAULT_RESOLUTION, RESOLUTIONS, QuantizedPostcode, Sections, ChunkedLayout, pack_outward_code, pack_block, write_records, lut_index, PREFIX_LEN, crc32, Point, dequantize_extent, dequantize_ll};
+use crate::spatial::{SpatialIndex, IndexKind, IndexPoint};
+use crate::country::{self, Country};
+use crate::local::LocalBoxes;
+use crate::order::{self, RecordOrder};
+use crate::group::Groups;
+use crate::sector::SectorTotals;
+use crate::section::{SectionKind, SectionTable};
+
+/// Newest patch format, older ones are still written when they can be
+const PATCH_VERSION: u32 = 4;
+const FIXED_LEN: usize = 40;
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32>{
+    Some(u32::from_le_bytes(data.get(pos..pos.checked_add(4)?)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], pos: usize) -> Option<u64>{
+    Some(u64::from_le_bytes(data.get(pos..pos+8)?.try_into().ok()?))
+}
+
+/// Kind of spatial index that applying a patch builds, given the kind in the new file's header
+fn rebuilt_kind(header_kind: IndexKind, old: &Reader) -> IndexKind{
+    match (header_kind, old.index_kind()){
+        (IndexKind::Geohash(_), Some(IndexKind::Geohash(precision))) => IndexKind::Geohash(precision),
+        (kind, _) => kind,
+    }
+}
+
+/// What the new file's header says about how to write the rest of it
+struct Layout{
+    compression: Compression,
+    chunked: Option<ChunkedLayout>,
+    has_index: bool,
+    has_countries: bool,
+    has_sector_bitmap: bool,
+    resolution: u32,
+    scheme: Scheme,
+    index_kind: IndexKind,
+    boxes: LocalBoxes,
+    /// The file's bounding box, version 3 onwards
+    extents: Option<(Point, Point)>,
+    order: RecordOrder,
+}
+
+/// Dequantize a version 3 bounding box
+fn extents(bounds: &[u8]) -> Option<(Point, Point)>{
+    let extent = |i: usize, range: f64| Some(dequantize_extent(read_u32(bounds, i * 4)?, range));
+    Some((
+        Point{x: extent(0, 180.0)?, y: extent(2, 90.0)?},
+        Point{x: extent(1, 180.0)?, y: extent(3, 90.0)?},
+    ))
+}
+
+/// Read the layout from the fixed fields of a header before version 26
+fn fixed_layout(header: &[u8], new_version: u32) -> Option<Layout>{
+    let compression = if new_version >= 4{
+        Compression::from_id(read_u32(header, 16)?)?
+    }
+    else{
+        Compression::None
+    };
+    let has_index = new_version >= 5 && read_u32(header, 20)? > 0;
+    let has_countries = new_version >= 7 && read_u32(header, 24)? > 0;
+    let has_sector_bitmap = new_version >= 21 && read_u32(header, 324)? > 0;
+    let chunked = if new_version >= 9{
+        // The entropy code is after the signing key, all zeros if the data isn't coded
+        let code_lengths = match header.get(44..300){
+            Some(lengths) if new_version >= 14 && lengths.iter().any(|&l| l > 0) => Some(lengths.try_into().unwrap()),
+            _ => None,
+        };
+        Some(ChunkedLayout{lut_start: header.len() as u64, chunk_size: read_u32(header, 32)?, checksums: new_version >= 10, attributes_crc: new_version >= 19, prefix_extents_crc: new_version >= 20, sector_bitmap_crc: new_version >= 21, code_lengths})
+    }
+    else{
+        None
+    };
+    let resolution = if new_version >= 16 { read_u32(header, 304)? } else { DEFAULT_RESOLUTION };
+    if !RESOLUTIONS.contains(&resolution){
+        return None;
+    }
+    let scheme = if new_version >= 17 { Scheme::from_id(read_u32(header, 308)?)? } else { Scheme::Uk };
+    let index_kind = if new_version >= 18 { IndexKind::from_id(read_u32(header, 312)?)? } else { IndexKind::Grid };
+    // The local bounding boxes are at the end of the header, after the extents
+    let boxes_len = if new_version >= 15 { (read_u32(header, 300)? as usize).checked_mul(10)? } else { 0 };
+    let boxes_start = header.len().checked_sub(boxes_len)?;
+    let mut boxes = if new_version >= 15{
+        LocalBoxes::from_bytes(&header[boxes_start..])?
+    }
+    else{
+        LocalBoxes::default()
+    };
+    // The coordinate groups are after the metadata and the outliers
+    if new_version >= 25{
+        let outliers_start = (read_u32(header, 336)? as usize).checked_add(340)?;
+        let groups_start = (read_u32(header, outliers_start)? as usize).checked_mul(15)?.checked_add(outliers_start + 4)?;
+        let (groups, _) = Groups::from_bytes(header.get(groups_start..)?, scheme)?;
+        boxes.set_groups(groups);
+    }
+    // Version 3 onwards has u32 extents, just before the local bounding boxes
+    let extents = if new_version >= 3 { extents(header.get(boxes_start.checked_sub(16)?..boxes_start)?) } else { None };
+    Some(Layout{compression, chunked, has_index, has_countries, has_sector_bitmap, resolution, scheme, index_kind, boxes, extents, order: RecordOrder::Alphabetical})
+}
+
+/// Read the layout from the section table of a version 26 header, which is everything before
+/// the quick lookup table
+fn section_layout(header: &[u8], new_len: usize) -> Result<Layout, PostcodeError>{
+    let bad = PostcodeError::NotAPatchFile;
+    let sections = SectionTable::from_bytes(header, new_len)?;
+    // The sections in the header must be whole, and the table must say where it ends
+    let bytes = |kind: SectionKind| sections.get(kind).map(|s| header.get(s.start..s.end()).ok_or_else(bad)).transpose();
+    if sections.get(SectionKind::LookupTable).is_none_or(|s| s.start != header.len()){
+        return Err(bad());
+    }
+    let u32_of = |kind: SectionKind| bytes(kind)?.map(|b| read_u32(b, 0).filter(|_| b.len() == 4).ok_or_else(bad)).transpose();
+    let resolution = u32_of(SectionKind::Resolution)?.unwrap_or(DEFAULT_RESOLUTION);
+    if !RESOLUTIONS.contains(&resolution){
+        return Err(bad());
+    }
+    let scheme = match u32_of(SectionKind::Scheme)?{
+        Some(id) => Scheme::from_id(id).ok_or_else(bad)?,
+        None => Scheme::Uk,
+    };
+    let code_lengths = bytes(SectionKind::EntropyCode)?.map(|b| b.try_into().map_err(|_| bad())).transpose()?;
+    let mut boxes = LocalBoxes::from_bytes(bytes(SectionKind::LocalBoxes)?.unwrap_or_default()).ok_or_else(bad)?;
+    if let Some(b) = bytes(SectionKind::Groups)?{
+        boxes.set_groups(Groups::from_bytes(b, scheme).ok_or_else(bad)?.0);
+    }
+    Ok(Layout{
+        compression: Compression::None,
+        chunked: Some(ChunkedLayout{lut_start: header.len() as u64, chunk_size: u32_of(SectionKind::ChunkSize)?.unwrap_or(0), checksums: false, attributes_crc: false, prefix_extents_crc: false, sector_bitmap_crc: false, code_lengths}),
+        has_index: sections.index().is_some(),
+        has_countries: sections.get(SectionKind::Countries).is_some(),
+        has_sector_bitmap: sections.get(SectionKind::SectorBitmap).is_some(),
+        resolution,
+        scheme,
+        index_kind: sections.index().map(|(kind, _)| kind).unwrap_or(IndexKind::Grid),
+        boxes,
+        extents: Some(extents(bytes(SectionKind::Bounds)?.ok_or_else(bad)?).ok_or_else(bad)?),
+        order: match u32_of(SectionKind::RecordOrder)?{
+            Some(id) => RecordOrder::from_id(id).ok_or_else(bad)?,
+            None => RecordOrder::Alphabetical,
+        },
+    })
+}
+
+/// Create a patch that turns the pack file `old` in to the pack file `new`
+pub fn make_patch(old: &[u8], new: &[u8]) -> Result<Vec<u8>, PostcodeError>{
+    let old_reader = Reader::from_bytes(old.to_vec())?;
+    let new_reader = Reader::from_bytes(new.to_vec())?;
+    if let Some(kind @ IndexKind::Geohash(_)) = new_reader.index_kind(){
+        if rebuilt_kind(kind, &old_reader) != kind{
+            return Err(PostcodeError::IncompatibleOptions("a patch can't change the precision of a geohash spatial index"));
+        }
+    }
+
+    let mut old_entries: HashMap<[u8;7], QuantizedPostcode> = old_reader.quantized().into_iter()
+        .map(|p| (p.postcode, p))
+        .collect();
+    let mut changed = Vec::new();
+    // Locations in a block with its own bounding box are quantized in that box, and in a group's
+    // box if there are groups
+    let groups_changed = old_reader.groups() != new_reader.groups();
+    let box_changed = |p: &QuantizedPostcode| groups_changed || lut_index(&p.postcode)
+        .is_some_and(|block| old_reader.local_box(block) != new_reader.local_box(block));
+    for p in new_reader.quantized(){
+        if old_entries.remove(&p.postcode).as_ref() != Some(&p) || box_changed(&p){
+            changed.push(p);
+        }
+    }
+    let mut removed: Vec<[u8;7]> = old_entries.into_keys().collect();
+    removed.sort();
+
+    let mut changes = Vec::with_capacity(8 + (removed.len()*7) + (changed.len()*17));
+    changes.extend_from_slice(&(removed.len() as u32).to_le_bytes());
+    for r in &removed{
+        changes.extend_from_slice(r);
+    }
+    // Locations only need version 2 if they don't fit in 16 bits, attributes need version 3 and
+    // prefix extents need version 4
+    let attributes = new_reader.attribute_bytes();
+    let prefix_extents = new_reader.prefix_extent_bytes();
+    let version: u32 = if !prefix_extents.is_empty() { 4 }
+        else if !attributes.is_empty() { 3 }
+        else if new_reader.resolution() > DEFAULT_RESOLUTION { 2 }
+        else { 1 };
+    let coord_len = if version >= 2 { 4 } else { 2 };
+    changes.extend_from_slice(&(changed.len() as u32).to_le_bytes());
+    for p in &changed{
+        changes.extend_from_slice(&p.postcode);
+        changes.extend_from_slice(&p.lat.to_le_bytes()[..coord_len]);
+        changes.extend_from_slice(&p.long.to_le_bytes()[..coord_len]);
+        changes.push(p.terminated.unwrap_or(0xff));
+        changes.push(p.country.map(|c| c.byte()).unwrap_or(0));
+    }
+    let centroids = new_reader.centroid_bytes();
+    changes.extend_from_slice(&(centroids.len() as u32).to_le_bytes());
+    changes.extend_from_slice(centroids);
+    if version >= 3{
+        changes.extend_from_slice(&(attributes.len() as u32).to_le_bytes());
+        changes.extend_from_slice(attributes);
+    }
+    if version >= 4{
+        changes.extend_from_slice(&(prefix_extents.len() as u32).to_le_bytes());
+        changes.extend_from_slice(prefix_extents);
+    }
+
+    let header = new_reader.header_bytes();
+    let mut out = Vec::new();
+    out.extend_from_slice(b"UKPD");
+    out.extend_from_slice(&version.to_le_bytes());
+    out.extend_from_slice(&(old.len() as u64).to_le_bytes());
+    out.extend_from_slice(&crc32(old).to_le_bytes());
+    out.extend_from_slice(&(new.len() as u64).to_le_bytes());
+    out.extend_from_slice(&crc32(new).to_le_bytes());
+    out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    out.extend_from_slice(header);
+    out.extend_from_slice(&Compression::Gzip.compress(&changes)?);
+    Ok(out)
+}
+
+/// Apply a patch to the pack file `old`, returns the new pack file
+pub fn apply_patch(old: &[u8], patch: &[u8]) -> Result<Vec<u8>, PostcodeError>{
+    if patch.len() < FIXED_LEN || &patch[0..4] != b"UKPD"{
+        return Err(PostcodeError::NotAPatchFile());
+    }
+    let bad = PostcodeError::NotAPatchFile;
+    let version = read_u32(patch, 4).ok_or_else(bad)?;
+    if version == 0 || version > PATCH_VERSION{
+        return Err(PostcodeError::UnsupportedVersion(version));
+    }
+    let coord_len = if version >= 2 { 4 } else { 2 };
+    let old_len = read_u64(patch, 8).ok_or_else(bad)?;
+    let old_crc = read_u32(patch, 16).ok_or_else(bad)?;
+    let new_len = read_u64(patch, 20).ok_or_else(bad)?;
+    let new_crc = read_u32(patch, 28).ok_or_else(bad)?;
+    if old.len() as u64 != old_len || crc32(old) != old_crc{
+        return Err(PostcodeError::PatchDoesNotApply());
+    }
+    let header_len = read_u32(patch, 32).ok_or_else(bad)? as usize;
+    let header = patch.get(36..36+header_len).ok_or_else(bad)?;
+    let changes = Compression::Gzip.decompress(&patch[36+header_len..]).map_err(|_|bad())?;
+
+    // The header of the new file tells us how to write the rest of it
+    let new_version = read_u32(header, 4).ok_or_else(bad)?;
+    let layout = if new_version >= 26 { section_layout(header, new_len as usize)? } else { fixed_layout(header, new_version).ok_or_else(bad)? };
+    let Layout{compression, chunked, has_index, has_countries, has_sector_bitmap, resolution, scheme, index_kind, boxes, extents, order} = layout;
+
+    let old_reader = Reader::from_bytes(old.to_vec())?;
+    let index_kind = rebuilt_kind(index_kind, &old_reader);
+    let mut entries: HashMap<[u8;7], QuantizedPostcode> = old_reader.quantized().into_iter()
+        .map(|p| (p.postcode, p))
+        .collect();
+    let removed_count = read_u32(&changes, 0).ok_or_else(bad)? as usize;
+    let mut pos = 4;
+    for _ in 0..removed_count{
+        let postcode: [u8;7] = changes.get(pos..pos+7).ok_or_else(bad)?.try_into().unwrap();
+        entries.remove(&postcode);
+        pos += 7;
+    }
+    let changed_count = read_u32(&changes, pos).ok_or_else(bad)? as usize;
+    pos += 4;
+    let entry_len = 9 + (coord_len * 2);
+    let coord = |b: &[u8]| b.iter().rev().fold(0u32, |n, &x| (n << 8) | x as u32);
+    for _ in 0..changed_count{
+        let c = changes.get(pos..pos+entry_len).ok_or_else(bad)?;
+        let (lat, rest) = c[7..].split_at(coord_len);
+        let (long, rest) = rest.split_at(coord_len);
+        let postcode: [u8;7] = c[0..7].try_into().unwrap();
+        let s = std::str::from_utf8(&postcode).map_err(|_|bad())?;
+        let is_partial = &postcode[4..7] == b"   ";
+        let code = if is_partial { pack_outward_code(s)? } else { scheme.codec().encode(s)? };
+        entries.insert(postcode, QuantizedPostcode{
+            postcode,
+            code,
+            is_partial,
+            lat: coord(lat),
+            long: coord(long),
+            terminated: if rest[0] == 0xff { None } else { Some(rest[0]) },
+            country: if rest[1] == 0 { None } else { Some(Country::from_byte(rest[1]).ok_or_else(bad)?) },
+        });
+        pos += entry_len;
+    }
+    let centroids_len = read_u32(&changes, pos).ok_or_else(bad)? as usize;
+    pos += 4;
+    let centroids = changes.get(pos..pos+centroids_len).ok_or_else(bad)?.to_vec();
+    pos += centroids_len;
+    let attributes = if version >= 3{
+        let attributes_len = read_u32(&changes, pos).ok_or_else(bad)? as usize;
+        pos += 4;
+        let attributes = changes.get(pos..pos+attributes_len).ok_or_else(bad)?.to_vec();
+        pos += attributes_len;
+        attributes
+    }
+    else{
+        Vec::new()
+    };
+    let prefix_extents = if version >= 4{
+        let prefix_extents_len = read_u32(&changes, pos).ok_or_else(bad)? as usize;
+        pos += 4;
+        changes.get(pos..pos+prefix_extents_len).ok_or_else(bad)?.to_vec()
+    }
+    else{
+        Vec::new()
+    };
+
+    let mut postcodes: Vec<QuantizedPostcode> = entries.into_values().collect();
+    postcodes.par_sort_by(|a,b|a.postcode.cmp(&b.postcode));
+    // The countries are runs of postcodes in order, so they're found before the records are put
+    // in the order they're written
+    let countries = if has_countries{
+        country::encode_runs(postcodes.iter().map(|p| (p.postcode.as_slice(), p.country)))
+    }
+    else{
+        Vec::new()
+    };
+    order::arrange(&mut postcodes, order, |p| (p.postcode, p.long, p.lat));
+    let blocks: Vec<&[QuantizedPostcode]> = postcodes.chunk_by(|a,b| a.postcode[..PREFIX_LEN] == b.postcode[..PREFIX_LEN]).collect();
+    let packed_codes: Vec<_> = blocks.par_iter()
+        .map(|block| pack_block(block, new_version, resolution))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut sections = Sections{
+        prefix_extents,
+        attributes,
+        centroids,
+        countries,
+        ..Default::default()
+    };
+    if has_index{
+        let (file_min, file_max) = extents.ok_or_else(bad)?;
+        sections.index = SpatialIndex::build(
+            index_kind,
+            postcodes.iter()
+                .filter_map(|p| {
+                    let block = lut_index(&p.postcode)?;
+                    let (minll, maxll) = boxes.bounds(block, &p.postcode, file_min, file_max);
+                    let location = dequantize_ll(minll, maxll, p.long, p.lat, resolution);
+                    let (long, lat) = boxes.to_file(block, &p.postcode, (p.long, p.lat), resolution, file_min, file_max);
+                    Some(IndexPoint{block, long, lat, is_partial: p.is_partial, terminated: p.terminated.is_some(), location})
+                })
+        ).to_bytes();
+    }
+    if has_sector_bitmap{
+        let mut totals = SectorTotals::new(scheme);
+        for p in postcodes.iter().filter(|p| !p.is_partial && p.terminated.is_none()){
+            totals.add_code(std::str::from_utf8(&p.postcode).map_err(|_|bad())?);
+        }
+        sections.sector_bitmap = totals.to_bytes();
+    }
+
+    let mut out = header.to_vec();
+    write_records(&mut out, compression, chunked, postcodes.iter().map(|p| p.prefix()), &packed_codes, &sections)?;
+    if new_version >= 10{
+        let file_crc = crc32(&out);
+        out.extend_from_slice(&file_crc.to_le_bytes());
+    }
+    if out.len() as u64 != new_len || crc32(&out) != new_crc{
+        return Err(PostcodeError::PatchDoesNotApply());
+    }
+    Ok(out)
+}
@@ -0,0 +1,1619 @@
+/*
+
+Reader for the packed postcode format, the Rust equivalent of the javascript library.
+
+See `Packer::write_to` for a description of the file structure.
+
+The file can be read in to memory, or memory mapped with `Reader::map`. Either way, the quick
+lookup table and the records are read where they are in the file, and nothing is decoded until
+a query needs it. Entropy coded blocks are decoded the first time they are read, and kept. Only
+compressed files (versions 4 to 8) have to be decompressed in to memory up front.
+
+The records of each block are decoded by the `nearmypostcode_core` crate (in the core directory),
+which builds without the standard library for embedded devices.
+
+Every format version from 1 onwards can be read, and none is ever dropped, since pack files are
+kept in caches long after they were made. tests/compat.rs reads an archived file of some of the
+oldest versions.
+
+*/
+use std::fs;
+use std::ops::Deref;
+use std::sync::OnceLock;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use crate::spatial::{SpatialIndex, IndexKind, Grid};
+use crate::kdtree::{KdTree, Node};
+use crate::geohash::{self, GeohashIndex, Cell};
+use crate::region::{self, in_polygon};
+use crate::country::{Country, CountryRuns};
+use crate::centroid::{Centroid, CentroidLevel, CentroidTables};
+use crate::attribute::Attributes;
+use crate::extent::PrefixExtents;
+use crate::sector::SectorBitmap;
+use crate::metadata::Metadata;
+use crate::outlier::Outliers;
+use crate::group::Groups;
+use crate::local::LocalBoxes;
+#[cfg(feature="packer")]
+use crate::local::LocalBox;
+use crate::section::{SectionKind, SectionTable};
+use crate::suggest::{Cost, near_misses};
+use crate::order::{self, RecordOrder};
+use crate::crs::Crs;
+use nearmypostcode_core::{Records, Record, DecodeState, Decoder};
+use crate::{PostcodeError, pack_block, Compression, Point, PostcodeInfo, QuantizedPostcode, TERMINATED_YEAR_BASE, fixed_record_len, coord_len, DEFAULT_RESOLUTION, RESOLUTIONS, LUT_SIZE, lut_index, lut_prefix, crc32, pack_outward_code, unpack_outward_code, dequantize_extent, dequantize_ll, Scheme, distance_m, EARTH_RADIUS_M};
+
+const HEADER_LEN: usize = 16;
+const LUT_LEN: usize = (LUT_SIZE+1)*4;
+
+/// The newest file format version that this reader understands
+pub const MAX_VERSION: u32 = 26;
+
+/// Length of the checksums at the end of a version 10 file, versions 19, 20 and 21 each add one
+fn checksums_len(version: u32) -> usize{
+    if version < 19 { 5*4 } else if version < 20 { 6*4 } else if version < 21 { 7*4 } else { 8*4 }
+}
+
+/// The bytes of a pack file
+#[derive(Debug, Clone)]
+enum Bytes{
+    Owned(Vec<u8>),
+    /// Shared, so that the reader can be cloned without copying the file
+    #[cfg(feature="mmap")]
+    Mapped(std::sync::Arc<memmap2::Mmap>),
+}
+
+// Servers share one reader between their threads, so this mustn't stop compiling
+const _: fn() = || {
+    fn shared<T: Send + Sync>(){}
+    shared::<Reader>();
+};
+
+impl Deref for Bytes{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8]{
+        match self{
+            Bytes::Owned(data) => data,
+            #[cfg(feature="mmap")]
+            Bytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// A postcode pack file, in memory or memory mapped
+///
+/// A reader is `Send` and `Sync`. It is never changed after it is opened, except for the parts
+/// that are decoded the first time they are needed, which are filled in once through `OnceLock`s,
+/// so one reader can be shared between threads in an `Arc` and queried from all of them at once.
+#[derive(Debug, Clone)]
+pub struct Reader{
+    data: Bytes,
+    version: u32,
+    lut_start: usize,
+    data_start: usize,
+    /// End of the postcode data, the optional sections and the checksums are after it
+    data_end: usize,
+    last_update: u64,
+    /// Version 22 onwards, 0 if it isn't known
+    release_date: u64,
+    /// Version 23 onwards, empty before
+    metadata: Metadata,
+    /// Version 24 onwards, the entries outside the bounding box
+    outliers: Outliers,
+    /// The full postcodes in `outliers`, filled in the first time a search needs them
+    outlier_entries: OnceLock<Vec<PostcodeInfo>>,
+    minll: Point,
+    maxll: Point,
+    compression: Compression,
+    file_len: usize,
+    index: Option<SpatialIndex>,
+    countries: Option<CountryRuns>,
+    centroids: Option<CentroidTables>,
+    /// Version 19 onwards
+    attributes: Option<Attributes>,
+    /// Position in the header of the length of the attributes section, version 19 onwards. The
+    /// length of the prefix extents section is after it, version 20 onwards, and then the length of
+    /// the sector bitmap, version 21 onwards, and then the release date, version 22 onwards.
+    attributes_len_start: Option<usize>,
+    /// Version 20 onwards
+    prefix_extents: Option<PrefixExtents>,
+    /// Version 21 onwards
+    sector_bitmap: Option<SectorBitmap>,
+    /// Version 9 onwards, blocks have absolute byte ranges aligned to this many bytes
+    chunk_size: Option<u32>,
+    /// Version 11 onwards, fingerprint of the signing key
+    key_id: Option<[u8;8]>,
+    /// Version 14 onwards, for decoding the prefix blocks if the data is entropy coded
+    decoder: Option<Decoder>,
+    /// Each prefix block after entropy decoding, filled in as they are read. Empty if the data
+    /// isn't coded.
+    decoded: Vec<OnceLock<Vec<u8>>>,
+    /// Version 15 onwards, the blocks that have their own bounding box
+    boxes: LocalBoxes,
+    /// Bits per axis of the quantized locations, version 16 onwards can be other than 16
+    resolution: u32,
+    /// Which country's codes the file has, version 17 onwards can be other than UK postcodes
+    scheme: Scheme,
+    /// Version 26 onwards, where each part of the file is
+    sections: Option<SectionTable>,
+    /// Version 26 onwards can be other than alphabetical
+    order: RecordOrder,
+    /// Version 26 onwards can be other than WGS84
+    crs: Crs,
+}
+
+/// What a pack file says about a code, from `Reader::validate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validity{
+    /// A well-formed postcode or outward code that isn't in the file
+    WellFormed,
+    /// In the file
+    Exists,
+    /// In the file, but terminated (only files packed with terminated postcodes have these)
+    Terminated,
+    /// Not a well-formed postcode or outward code of the file's scheme, so it can't be in any
+    /// file
+    Unknown,
+}
+
+impl Validity{
+    /// How the validate subcommand reports it
+    pub fn name(&self) -> &'static str{
+        match self{
+            Validity::WellFormed => "absent",
+            Validity::Exists => "exists",
+            Validity::Terminated => "terminated",
+            Validity::Unknown => "malformed",
+        }
+    }
+}
+
+/// Summary of the contents of a pack file, see `Reader::file_stats`
+#[derive(Debug, Clone, Default)]
+pub struct FileStats{
+    /// Prefix and number of entries of each block that is not empty, in file order
+    pub blocks: Vec<(String, usize)>,
+    /// Number of entries that are outward codes only
+    pub outward_codes: usize,
+    /// Number of records in each encoding, indexed like `RECORD_KINDS`
+    pub record_kinds: [usize;6],
+    /// Number of bytes used by the records of each encoding, indexed like `RECORD_KINDS`
+    pub record_bytes: [usize;6],
+    /// Number of bytes that the varint records save, compared to the fixed size records they replace
+    pub varint_saving: usize,
+    /// Length of the postcode data, after decompression
+    pub data_len: usize,
+}
+
+/// Names of the record encodings, see `DeltaPacked`. Each postcode in a run counts as one record.
+pub const RECORD_KINDS: [&str;6] = ["Absolute", "DeltaP", "DeltaLL", "DeltaPLL", "Run", "Varint"];
+
+fn read_u32(data: &[u8], pos: usize) -> u32{
+    u32::from_le_bytes(data[pos..pos+4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], pos: usize) -> u64{
+    u64::from_le_bytes(data[pos..pos+8].try_into().unwrap())
+}
+
+fn read_f64(data: &[u8], pos: usize) -> f64{
+    f64::from_le_bytes(data[pos..pos+8].try_into().unwrap())
+}
+
+fn in_box(p: &Point, min: Point, max: Point) -> bool{
+    p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+}
+
+/// Shortest great circle distance from a point to anywhere in a box, in metres, or a little less
+fn min_distance_to_box(centre: &Point, min: Point, max: Point) -> f64{
+    let dlat = (min.y - centre.y).max(centre.y - max.y).max(0.0).to_radians();
+    let dlong = (min.x - centre.x).max(centre.x - max.x).max(0.0).to_radians().min(std::f64::consts::PI);
+    // Lines of longitude are closest together at the latitude furthest from the equator
+    let max_lat = centre.y.abs().max(min.y.abs()).max(max.y.abs()).min(90.0).to_radians();
+    let across = 2.0 * EARTH_RADIUS_M * (max_lat.cos() * (dlong / 2.0).sin()).asin();
+    (EARTH_RADIUS_M * dlat).max(across)
+}
+
+/// Something to look at in a k-d tree or geohash search
+#[derive(Debug)]
+enum Candidate{
+    Node(Node),
+    Cell(Cell),
+    /// An entry of a prefix block, whose postcode hasn't been decoded yet
+    Entry{block: usize, entry: usize},
+    Found(PostcodeInfo),
+}
+
+/// A candidate in the queue of a k-d tree or geohash search, the nearest comes out first. Candidates that
+/// are no nearer than a postcode that has been found still come out before it, in case they
+/// turn out to be the same distance away and sort before it.
+#[derive(Debug)]
+struct Queued{
+    /// As near as the candidate can be, in metres
+    distance: f64,
+    item: Candidate,
+}
+
+impl Queued{
+    fn rank(&self) -> u8{
+        match self.item{
+            Candidate::Found(_) => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl Ord for Queued{
+    fn cmp(&self, other: &Self) -> Ordering{
+        other.distance.total_cmp(&self.distance)
+            .then_with(|| other.rank().cmp(&self.rank()))
+            .then_with(|| match (&self.item, &other.item){
+                (Candidate::Found(a), Candidate::Found(b)) => b.postcode.cmp(&a.postcode),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for Queued{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>{
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Queued{
+    fn eq(&self, other: &Self) -> bool{
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued{}
+
+/// Check the checksums at the end of a version 10 file. If the file is damaged, this finds
+/// which part it is in if the section lengths in the header can be trusted.
+///
+/// `attributes_len_start` is the position of the attributes section's length in the header,
+/// for version 19 onwards.
+fn check_checksums(data: &[u8], version: u32, data_start: usize, attributes_len_start: Option<usize>) -> Result<(), PostcodeError>{
+    let checksums_len = checksums_len(version);
+    let footer_start = data.len().checked_sub(checksums_len)
+        .filter(|&f| f >= data_start)
+        .ok_or(PostcodeError::Corrupt("file"))?;
+    if crc32(&data[..data.len()-4]) == read_u32(data, data.len()-4){
+        return Ok(());
+    }
+    // The section lengths are in the header, in reverse order of the sections
+    let mut sections = vec![("spatial index", 20), ("countries", 24), ("centroids", 28)];
+    // Only version 19 onwards has the attributes, version 20 the prefix extents and version 21
+    // the sector bitmap
+    sections.extend(attributes_len_start.map(|pos| ("attributes", pos)));
+    sections.extend(attributes_len_start.filter(|_| version >= 20).map(|pos| ("prefix extents", pos + 4)));
+    sections.extend(attributes_len_start.filter(|_| version >= 21).map(|pos| ("sector bitmap", pos + 8)));
+    let mut end = footer_start;
+    let mut ranges = Vec::new();
+    for (name, pos) in sections{
+        let start = end.checked_sub(read_u32(data, pos) as usize)
+            .filter(|&s| s >= data_start)
+            .ok_or(PostcodeError::Corrupt("file"))?;
+        ranges.push((name, start, end));
+        end = start;
+    }
+    ranges.push(("postcode data", data_start, end));
+    for (i, (name, start, end)) in ranges.into_iter().rev().enumerate(){
+        let expected = read_u32(data, footer_start + i*4);
+        let found = if start == end { 0 } else { crc32(&data[start..end]) };
+        if found != expected{
+            return Err(PostcodeError::Corrupt(name));
+        }
+    }
+    Err(PostcodeError::Corrupt("header"))
+}
+
+impl Reader{
+    /// Read a pack file from disk
+    pub fn open(path: &str) -> Result<Self, PostcodeError>{
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    /// Memory map a pack file, so that only the parts of it that are used are read from disk.
+    /// This is much quicker to open than `open` for a few lookups.
+    ///
+    /// The checksums of version 10 files are not checked, since that would read the whole file.
+    /// Use `check_checksums` to check them.
+    ///
+    /// The file must not be changed while the reader (or any clone of it) is in use. Replace it
+    /// with a new file instead, e.g. by renaming one over it.
+    #[cfg(feature="mmap")]
+    pub fn map(path: &str) -> Result<Self, PostcodeError>{
+        let file = fs::File::open(path)?;
+        // Safety: see above, changing the file while it is mapped is not allowed
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Self::from_storage(Bytes::Mapped(std::sync::Arc::new(map)), false)
+    }
+
+    /// Use the contents of a pack file that is already in memory
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, PostcodeError>{
+        Self::from_storage(Bytes::Owned(data), true)
+    }
+
+    /// Use a pack file that is already in memory without checking its checksums, for one that
+    /// was put together from parts of another (see remote.rs)
+    pub(crate) fn from_unchecked(data: Vec<u8>) -> Result<Self, PostcodeError>{
+        Self::from_storage(Bytes::Owned(data), false)
+    }
+
+    /// Read a pack file from an async source, such as an object storage download, without
+    /// blocking the runtime. The file is read in to memory, then checked and opened on tokio's
+    /// blocking thread pool, since checking the checksums of a whole file takes a few
+    /// milliseconds. It must be called from within a tokio runtime.
+    #[cfg(feature="tokio")]
+    pub async fn from_async_read<R: tokio::io::AsyncRead + Unpin>(mut source: R) -> Result<Self, PostcodeError>{
+        let mut data = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut source, &mut data).await?;
+        match tokio::task::spawn_blocking(move || Self::from_bytes(data)).await{
+            Ok(reader) => reader,
+            // A task that was cancelled, because the runtime is shutting down, didn't panic
+            Err(e) => match e.try_into_panic(){
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(e) => Err(PostcodeError::IOError(std::io::Error::other(e))),
+            },
+        }
+    }
+
+    /// Check the checksums of a version 10 file, which `map` doesn't do. Files from before
+    /// version 10 have no checksums, so they always pass.
+    pub fn check_checksums(&self) -> Result<(), PostcodeError>{
+        if self.version < 10{
+            return Ok(());
+        }
+        if let Some(sections) = &self.sections{
+            return sections.check(&self.data);
+        }
+        check_checksums(&self.data, self.version, self.data_start, self.attributes_len_start)
+    }
+
+    fn from_storage(mut data: Bytes, checksums: bool) -> Result<Self, PostcodeError>{
+        let file_len = data.len();
+        let mut end = data.len();
+        if data.len() < HEADER_LEN || &data[0..4] != b"UKPP"{
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let version = read_u32(&data, 4);
+        if version == 0 || version > MAX_VERSION{
+            return Err(PostcodeError::UnsupportedVersion(version));
+        }
+        if version >= 26{
+            return Self::from_sections(data, version, checksums);
+        }
+        let compression_len = if version < 4 { 0 } else { 4 };
+        let index_len_len = if version < 5 { 0 } else { 4 };
+        let countries_len_len = if version < 7 { 0 } else { 4 };
+        let centroids_len_len = if version < 8 { 0 } else { 4 };
+        let chunk_size_len = if version < 9 { 0 } else { 4 };
+        let key_id_len = if version < 11 { 0 } else { 8 };
+        let entropy_len = if version < 14 { 0 } else { 256 };
+        let boxes_len_len = if version < 15 { 0 } else { 4 };
+        let resolution_len = if version < 16 { 0 } else { 4 };
+        let scheme_len = if version < 17 { 0 } else { 4 };
+        let index_kind_len = if version < 18 { 0 } else { 4 };
+        let attributes_len_len = if version < 19 { 0 } else { 4 };
+        let prefix_extents_len_len = if version < 20 { 0 } else { 4 };
+        let sector_bitmap_len_len = if version < 21 { 0 } else { 4 };
+        let release_date_len = if version < 22 { 0 } else { 8 };
+        let chunk_size_start = HEADER_LEN + compression_len + index_len_len + countries_len_len + centroids_len_len;
+        let entropy_start = chunk_size_start + chunk_size_len + key_id_len;
+        let boxes_len_start = entropy_start + entropy_len;
+        let resolution_start = boxes_len_start + boxes_len_len;
+        let scheme_start = resolution_start + resolution_len;
+        let index_kind_start = scheme_start + scheme_len;
+        let attributes_len_start = index_kind_start + index_kind_len;
+        let release_date_start = attributes_len_start + attributes_len_len + prefix_extents_len_len + sector_bitmap_len_len;
+        let metadata_start = release_date_start + release_date_len;
+        let metadata_len = if version < 23 { 0 } else {
+            let len = data.get(metadata_start..metadata_start+4).ok_or(PostcodeError::NotAPackFile())?;
+            (u32::from_le_bytes(len.try_into().unwrap()) as usize).checked_add(4).ok_or(PostcodeError::NotAPackFile())?
+        };
+        // The lengths from the file can overflow a 32-bit usize, e.g. in the wasm build
+        let outliers_start = metadata_start.checked_add(metadata_len).ok_or(PostcodeError::NotAPackFile())?;
+        let (outliers, outliers_len) = if version < 24 { (Outliers::default(), 0) } else {
+            data.get(outliers_start..).and_then(Outliers::from_bytes).ok_or(PostcodeError::NotAPackFile())?
+        };
+        let groups_start = outliers_start + outliers_len;
+        let (groups, groups_len) = if version < 25 { (Groups::default(), 0) } else {
+            let scheme = data.get(scheme_start..scheme_start+4).and_then(|s| Scheme::from_id(u32::from_le_bytes(s.try_into().unwrap())));
+            scheme.and_then(|scheme| Groups::from_bytes(data.get(groups_start..)?, scheme)).ok_or(PostcodeError::NotAPackFile())?
+        };
+        let extents_start = groups_start + groups_len;
+        let extents_len = if version < 3 { 4*8 } else { 4*4 };
+        // The local bounding boxes are between the extents and the lookup table
+        let boxes_start = extents_start + extents_len;
+        let boxes_len = if version < 15 { 0 } else {
+            let count = data.get(boxes_len_start..boxes_len_start+4).ok_or(PostcodeError::NotAPackFile())?;
+            (u32::from_le_bytes(count.try_into().unwrap()) as usize).checked_mul(10).ok_or(PostcodeError::NotAPackFile())?
+        };
+        let lut_len = if version < 9 { LUT_LEN } else { LUT_SIZE*8 };
+        let lut_start = boxes_start.checked_add(boxes_len).ok_or(PostcodeError::NotAPackFile())?;
+        let data_start = lut_start.checked_add(lut_len).ok_or(PostcodeError::NotAPackFile())?;
+        let attributes_len_start = (version >= 19).then_some(attributes_len_start);
+        // Check the checksums first, so that a damaged file isn't mistaken for something else
+        if version >= 10{
+            if checksums{
+                check_checksums(&data, version, data_start, attributes_len_start)?;
+            }
+            end = end.checked_sub(checksums_len(version)).ok_or(PostcodeError::Corrupt("file"))?;
+        }
+        if end < data_start{
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let last_update = read_u64(&data, 8);
+        let release_date = if version < 22 { 0 } else { read_u64(&data, release_date_start) };
+        let metadata = if version < 23 { Metadata::new() } else {
+            data.get(metadata_start+4..metadata_start+metadata_len).and_then(Metadata::from_bytes).ok_or(PostcodeError::NotAPackFile())?
+        };
+        let chunk_size = (version >= 9).then(|| read_u32(&data, chunk_size_start));
+        let key_id = (version >= 11).then(|| data[chunk_size_start+4..chunk_size_start+12].try_into().unwrap());
+        let mut index = None;
+        if version >= 5{
+            let index_len = read_u32(&data, HEADER_LEN + compression_len) as usize;
+            if index_len > end - data_start{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if index_len > 0{
+                let index_start = end - index_len;
+                let kind = if version < 18 { IndexKind::Grid } else { IndexKind::from_id(read_u32(&data, index_kind_start)).ok_or(PostcodeError::NotAPackFile())? };
+                index = Some(SpatialIndex::from_bytes(kind, &data[index_start..end]).ok_or(PostcodeError::NotAPackFile())?);
+                end = index_start;
+            }
+        }
+        // The countries are just before the spatial index
+        let mut countries = None;
+        if version >= 7{
+            let countries_len = read_u32(&data, HEADER_LEN + compression_len + index_len_len) as usize;
+            if countries_len > end - data_start{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if countries_len > 0{
+                let countries_start = end - countries_len;
+                countries = Some(CountryRuns::from_bytes(&data[countries_start..end]).ok_or(PostcodeError::NotAPackFile())?);
+                end = countries_start;
+            }
+        }
+        // And the centroids are before the countries
+        let mut centroids = None;
+        if version >= 8{
+            let centroids_len = read_u32(&data, HEADER_LEN + compression_len + index_len_len + countries_len_len) as usize;
+            if centroids_len > end - data_start{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if centroids_len > 0{
+                let centroids_start = end - centroids_len;
+                centroids = Some(CentroidTables::from_bytes(&data[centroids_start..end]).ok_or(PostcodeError::NotAPackFile())?);
+                end = centroids_start;
+            }
+        }
+        // And the attributes are before the centroids
+        let mut attributes = None;
+        if let Some(pos) = attributes_len_start{
+            let attributes_len = read_u32(&data, pos) as usize;
+            if attributes_len > end - data_start{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if attributes_len > 0{
+                let attributes_start = end - attributes_len;
+                attributes = Some(Attributes::from_bytes(&data[attributes_start..end]).ok_or(PostcodeError::NotAPackFile())?);
+                end = attributes_start;
+            }
+        }
+        // And the prefix extents are before the attributes
+        let mut prefix_extents = None;
+        if let Some(pos) = attributes_len_start.filter(|_| version >= 20){
+            let prefix_extents_len = read_u32(&data, pos + 4) as usize;
+            if prefix_extents_len > end - data_start{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if prefix_extents_len > 0{
+                let prefix_extents_start = end - prefix_extents_len;
+                prefix_extents = Some(PrefixExtents::from_bytes(&data[prefix_extents_start..end]).ok_or(PostcodeError::NotAPackFile())?);
+                end = prefix_extents_start;
+            }
+        }
+        // And the sector bitmap is before the prefix extents
+        let mut sector_bitmap = None;
+        if let Some(pos) = attributes_len_start.filter(|_| version >= 21){
+            let sector_bitmap_len = read_u32(&data, pos + 8) as usize;
+            if sector_bitmap_len > end - data_start{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if sector_bitmap_len > 0{
+                let sector_bitmap_start = end - sector_bitmap_len;
+                sector_bitmap = Some(SectorBitmap::from_bytes(&data[sector_bitmap_start..end]).ok_or(PostcodeError::NotAPackFile())?);
+                end = sector_bitmap_start;
+            }
+        }
+        let mut compression = Compression::None;
+        if version >= 4{
+            compression = Compression::from_id(read_u32(&data, HEADER_LEN)).ok_or(PostcodeError::NotAPackFile())?;
+            // Version 9 onwards is for range requests, and never compressed, so the checksums
+            // are always of the data as it is
+            if compression != Compression::None && version >= 9{
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if compression != Compression::None{
+                // The only case where the records can't be read where they are
+                let records = compression.decompress(&data[data_start..end]).map_err(|_|PostcodeError::NotAPackFile())?;
+                let mut decompressed = data[..data_start].to_vec();
+                decompressed.extend_from_slice(&records);
+                end = decompressed.len();
+                data = Bytes::Owned(decompressed);
+            }
+        }
+        let (minlong, maxlong, minlat, maxlat) = if version < 3{
+            (
+                read_f64(&data, extents_start),
+                read_f64(&data, extents_start+8),
+                read_f64(&data, extents_start+16),
+                read_f64(&data, extents_start+24),
+            )
+        }
+        else{
+            (
+                dequantize_extent(read_u32(&data, extents_start), 180.0),
+                dequantize_extent(read_u32(&data, extents_start+4), 180.0),
+                dequantize_extent(read_u32(&data, extents_start+8), 90.0),
+                dequantize_extent(read_u32(&data, extents_start+12), 90.0),
+            )
+        };
+        let mut boxes = LocalBoxes::from_bytes(&data[boxes_start..lut_start]).ok_or(PostcodeError::NotAPackFile())?;
+        boxes.set_groups(groups);
+        let resolution = if version < 16 { DEFAULT_RESOLUTION } else { read_u32(&data, resolution_start) };
+        if !RESOLUTIONS.contains(&resolution){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let scheme = if version < 17 { Scheme::Uk } else { Scheme::from_id(read_u32(&data, scheme_start)).ok_or(PostcodeError::NotAPackFile())? };
+        let reader = Self{
+            data,
+            version,
+            lut_start,
+            data_start,
+            data_end: end,
+            last_update,
+            release_date,
+            metadata,
+            outliers,
+            outlier_entries: OnceLock::new(),
+            minll: Point{x:minlong, y:minlat},
+            maxll: Point{x:maxlong, y:maxlat},
+            compression,
+            file_len,
+            index,
+            countries,
+            centroids,
+            attributes,
+            attributes_len_start,
+            prefix_extents,
+            sector_bitmap,
+            chunk_size,
+            key_id,
+            decoder: None,
+            decoded: Vec::new(),
+            boxes,
+            resolution,
+            scheme,
+            sections: None,
+            order: RecordOrder::Alphabetical,
+            crs: Crs::Wgs84,
+        };
+        reader.check_blocks(entropy_start..entropy_start+entropy_len)
+    }
+
+    /// Read a version 26 file, which has a table of where each section is
+    fn from_sections(data: Bytes, version: u32, checksums: bool) -> Result<Self, PostcodeError>{
+        let sections = SectionTable::from_bytes(&data, data.len())?;
+        if checksums{
+            sections.check(&data)?;
+        }
+        let bytes = |kind: SectionKind| sections.bytes(&data, kind);
+        let u32_of = |kind: SectionKind| bytes(kind).map(|b| b.try_into().map(u32::from_le_bytes).map_err(|_| PostcodeError::NotAPackFile())).transpose();
+        let u64_of = |kind: SectionKind| bytes(kind).map(|b| b.try_into().map(u64::from_le_bytes).map_err(|_| PostcodeError::NotAPackFile())).transpose();
+        let resolution = u32_of(SectionKind::Resolution)?.unwrap_or(DEFAULT_RESOLUTION);
+        if !RESOLUTIONS.contains(&resolution){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let scheme = match u32_of(SectionKind::Scheme)?{
+            Some(id) => Scheme::from_id(id).ok_or(PostcodeError::NotAPackFile())?,
+            None => Scheme::Uk,
+        };
+        let bounds = bytes(SectionKind::Bounds).filter(|b| b.len() == 16).ok_or(PostcodeError::NotAPackFile())?;
+        let extent = |i: usize, range: f64| dequantize_extent(read_u32(bounds, i*4), range);
+        let lut = sections.get(SectionKind::LookupTable).filter(|s| s.len == LUT_SIZE*8).ok_or(PostcodeError::NotAPackFile())?;
+        let postcode_data = sections.get(SectionKind::PostcodeData).ok_or(PostcodeError::NotAPackFile())?;
+        // The ranges in the lookup table are relative to the end of the table, like version 9
+        if postcode_data.start != lut.end(){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let outliers = match bytes(SectionKind::Outliers){
+            Some(b) => Outliers::from_bytes(b).filter(|(_, len)| *len == b.len()).ok_or(PostcodeError::NotAPackFile())?.0,
+            None => Outliers::default(),
+        };
+        let mut boxes = LocalBoxes::from_bytes(bytes(SectionKind::LocalBoxes).unwrap_or_default()).ok_or(PostcodeError::NotAPackFile())?;
+        if let Some(b) = bytes(SectionKind::Groups){
+            let (groups, len) = Groups::from_bytes(b, scheme).ok_or(PostcodeError::NotAPackFile())?;
+            if len != b.len(){
+                return Err(PostcodeError::NotAPackFile());
+            }
+            boxes.set_groups(groups);
+        }
+        let metadata = Metadata::from_bytes(bytes(SectionKind::Metadata).unwrap_or_default()).ok_or(PostcodeError::NotAPackFile())?;
+        let key_id = match bytes(SectionKind::KeyId){
+            Some(b) => b.try_into().map_err(|_| PostcodeError::NotAPackFile())?,
+            None => [0;8],
+        };
+        let index = match sections.index(){
+            Some((kind, s)) => Some(SpatialIndex::from_bytes(kind, &data[s.start..s.end()]).ok_or(PostcodeError::NotAPackFile())?),
+            None => None,
+        };
+        let countries = bytes(SectionKind::Countries).map(|b| CountryRuns::from_bytes(b).ok_or(PostcodeError::NotAPackFile())).transpose()?;
+        let centroids = bytes(SectionKind::Centroids).map(|b| CentroidTables::from_bytes(b).ok_or(PostcodeError::NotAPackFile())).transpose()?;
+        let attributes = bytes(SectionKind::Attributes).map(|b| Attributes::from_bytes(b).ok_or(PostcodeError::NotAPackFile())).transpose()?;
+        let prefix_extents = bytes(SectionKind::PrefixExtents).map(|b| PrefixExtents::from_bytes(b).ok_or(PostcodeError::NotAPackFile())).transpose()?;
+        let sector_bitmap = bytes(SectionKind::SectorBitmap).map(|b| SectorBitmap::from_bytes(b).ok_or(PostcodeError::NotAPackFile())).transpose()?;
+        let order = match u32_of(SectionKind::RecordOrder)?{
+            Some(id) => RecordOrder::from_id(id).ok_or(PostcodeError::NotAPackFile())?,
+            None => RecordOrder::Alphabetical,
+        };
+        let crs = match u32_of(SectionKind::Crs)?{
+            Some(id) => Crs::from_id(id).ok_or(PostcodeError::NotAPackFile())?,
+            None => Crs::Wgs84,
+        };
+        let entropy = sections.get(SectionKind::EntropyCode).filter(|s| s.len == 256).map(|s| s.start..s.end());
+        if entropy.is_none() && sections.get(SectionKind::EntropyCode).is_some(){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let reader = Self{
+            version,
+            lut_start: lut.start,
+            data_start: postcode_data.start,
+            data_end: postcode_data.end(),
+            last_update: read_u64(&data, 8),
+            release_date: u64_of(SectionKind::ReleaseDate)?.unwrap_or(0),
+            metadata,
+            outliers,
+            outlier_entries: OnceLock::new(),
+            minll: Point{x: extent(0, 180.0), y: extent(2, 90.0)},
+            maxll: Point{x: extent(1, 180.0), y: extent(3, 90.0)},
+            compression: Compression::None,
+            file_len: data.len(),
+            index,
+            countries,
+            centroids,
+            attributes,
+            attributes_len_start: None,
+            prefix_extents,
+            sector_bitmap,
+            chunk_size: Some(u32_of(SectionKind::ChunkSize)?.unwrap_or(0)),
+            key_id: Some(key_id),
+            decoder: None,
+            decoded: Vec::new(),
+            boxes,
+            resolution,
+            scheme,
+            sections: Some(sections),
+            order,
+            crs,
+            data,
+        };
+        reader.check_blocks(entropy.unwrap_or(0..0))
+    }
+
+    /// Check that every block is within the postcode data, and set up the entropy decoder if
+    /// the code at `entropy` in the file isn't all zeros
+    fn check_blocks(mut self, entropy: std::ops::Range<usize>) -> Result<Self, PostcodeError>{
+        // Every block must be within the file
+        for i in 0..LUT_SIZE{
+            let (start, end) = self.block_range(i);
+            if start > end || self.data_start.checked_add(end).is_none_or(|e| e > self.data_end){
+                return Err(PostcodeError::NotAPackFile());
+            }
+        }
+        // The blocks are decoded when they are first read, see `Reader::records`
+        let code_lengths = &self.data[entropy];
+        if code_lengths.iter().any(|&l| l > 0){
+            self.decoder = Some(Decoder::new(code_lengths).ok_or(PostcodeError::NotAPackFile())?);
+            self.decoded = (0..LUT_SIZE).map(|_| OnceLock::new()).collect();
+        }
+        if self.index.as_ref().and_then(|index| index.max_block()).is_some_and(|b| b >= LUT_SIZE){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        Ok(self)
+    }
+
+    /// File format version
+    pub fn version(&self) -> u32{
+        self.version
+    }
+
+    /// Date of the most recently introduced postcode, as a unix timestamp
+    pub fn last_update(&self) -> u64{
+        self.last_update
+    }
+
+    /// Release date of the dataset, as a unix timestamp. Version 22 onwards, `None` if the file
+    /// doesn't say.
+    pub fn release_date(&self) -> Option<u64>{
+        (self.release_date > 0).then_some(self.release_date)
+    }
+
+    /// How the file was made, see metadata.rs. Version 23 onwards, empty if the file doesn't say.
+    pub fn metadata(&self) -> &Metadata{
+        &self.metadata
+    }
+
+    /// Lower left and upper right corners of the bounding box
+    pub fn bounds(&self) -> (Point, Point){
+        (self.minll, self.maxll)
+    }
+
+    /// Compression applied to the postcode data
+    pub fn compression(&self) -> Compression{
+        self.compression
+    }
+
+    /// Size of the file as it was read, in bytes
+    pub fn file_len(&self) -> usize{
+        self.file_len
+    }
+
+    /// Columns and rows of the spatial index grid, if there is one
+    pub fn index_size(&self) -> Option<(usize, usize)>{
+        match &self.index{
+            Some(SpatialIndex::Grid(grid)) => Some((grid.cols(), grid.rows())),
+            _ => None,
+        }
+    }
+
+    /// Kind of spatial index the file has, if it has one
+    pub fn index_kind(&self) -> Option<IndexKind>{
+        self.index.as_ref().map(|i| i.kind())
+    }
+
+    /// Number of postcodes in the k-d tree spatial index, if the file has one
+    pub fn kd_tree_len(&self) -> Option<usize>{
+        match &self.index{
+            Some(SpatialIndex::KdTree(tree)) => Some(tree.len()),
+            _ => None,
+        }
+    }
+
+    /// Number of buckets and postcodes in the geohash spatial index, if the file has one
+    pub fn geohash_buckets(&self) -> Option<(usize, usize)>{
+        match &self.index{
+            Some(SpatialIndex::Geohash(index)) => Some((index.buckets(), index.len())),
+            _ => None,
+        }
+    }
+
+    /// Order of the records in each prefix block, always alphabetical before version 26
+    pub fn record_order(&self) -> RecordOrder{
+        self.order
+    }
+
+    /// Coordinate reference system of the locations, always WGS84 before version 26
+    pub fn crs(&self) -> Crs{
+        self.crs
+    }
+
+    /// Chunk size that the prefix blocks are aligned to, for version 9 files (0 if not aligned)
+    pub fn chunk_size(&self) -> Option<u32>{
+        self.chunk_size
+    }
+
+    /// Fingerprint of the key that signed the file, for version 11 files that were signed
+    pub fn key_id(&self) -> Option<[u8;8]>{
+        self.key_id.filter(|k| *k != [0;8])
+    }
+
+    /// Number of prefix blocks with their own bounding box, for version 15 files
+    pub fn local_boxes(&self) -> usize{
+        self.boxes.len()
+    }
+
+    /// Bounding box of each coordinate group and the outward codes in it, for version 25 files
+    /// packed with groups. The first is the main group, which has every outward code that isn't
+    /// listed in another, so its list is empty.
+    pub fn coordinate_groups(&self) -> Vec<((Point, Point), Vec<String>)>{
+        self.boxes.groups().list()
+    }
+
+    #[cfg(feature="packer")]
+    pub(crate) fn groups(&self) -> &Groups{
+        self.boxes.groups()
+    }
+
+    /// The bounding box of a prefix block, if it has its own
+    #[cfg(feature="packer")]
+    pub(crate) fn local_box(&self, block: usize) -> Option<&LocalBox>{
+        self.boxes.get(block)
+    }
+
+    /// Bits per axis of the quantized locations
+    pub fn resolution(&self) -> u32{
+        self.resolution
+    }
+
+    /// Which country's codes the file has
+    pub fn scheme(&self) -> Scheme{
+        self.scheme
+    }
+
+    /// Whether the postcode data is entropy coded, for version 14 files
+    pub fn entropy_coded(&self) -> bool{
+        self.decoder.is_some()
+    }
+
+    /// Absolute byte range in the file of each prefix block that is not empty, in file order.
+    /// These can be fetched on their own with range requests.
+    pub fn block_ranges(&self) -> Vec<(String, usize, usize)>{
+        (0..LUT_SIZE)
+            .map(|i| (i, self.block_range(i)))
+            .filter(|(_, (start, end))| end > start)
+            .map(|(i, (start, end))| (lut_prefix(i), self.data_start + start, self.data_start + end))
+            .collect()
+    }
+
+    /// Name, position and length of each section of a version 26 file, in the order of the
+    /// table. Kinds that this version doesn't know are named "unknown". Empty before version 26.
+    pub fn sections(&self) -> Vec<(&'static str, usize, usize)>{
+        self.sections.iter()
+            .flat_map(|t| t.iter())
+            .map(|s| (s.kind().map(|k| k.name()).unwrap_or("unknown"), s.start, s.len))
+            .collect()
+    }
+
+    /// Position in the file of the first record, everything before it is needed to read any block
+    pub fn data_start(&self) -> usize{
+        self.data_start
+    }
+
+    /// Number of runs in the countries section, if there is one
+    pub fn country_runs(&self) -> Option<usize>{
+        self.countries.as_ref().map(|c| c.len())
+    }
+
+    /// Number of areas, districts and sectors in the centroid tables, if there are any
+    pub fn centroid_counts(&self) -> Option<[usize; 3]>{
+        self.centroids.as_ref().map(|c| c.counts())
+    }
+
+    /// The centroids section as it is in the file, empty if there is none
+    #[cfg(feature="packer")]
+    pub(crate) fn centroid_bytes(&self) -> &[u8]{
+        self.centroids.as_ref().map(|c| c.as_bytes()).unwrap_or_default()
+    }
+
+    /// Names of the attributes of each postcode, empty if the file has none
+    pub fn attribute_names(&self) -> Vec<&str>{
+        self.attributes.as_ref().map(|a| a.names()).unwrap_or_default()
+    }
+
+    /// Every value of an attribute, sorted. `None` if the file doesn't have the attribute.
+    pub fn attribute_values(&self, name: &str) -> Option<&[String]>{
+        self.attributes.as_ref()?.values(name)
+    }
+
+    /// The attributes section as it is in the file, empty if there is none
+    #[cfg(feature="packer")]
+    pub(crate) fn attribute_bytes(&self) -> &[u8]{
+        self.attributes.as_ref().map(|a| a.as_bytes()).unwrap_or_default()
+    }
+
+    /// Number of outward codes in the prefix extents section, if there is one
+    pub fn prefix_extent_count(&self) -> Option<usize>{
+        self.prefix_extents.as_ref().map(|e| e.len())
+    }
+
+    /// The prefix extents section as it is in the file, empty if there is none
+    #[cfg(feature="packer")]
+    pub(crate) fn prefix_extent_bytes(&self) -> &[u8]{
+        self.prefix_extents.as_ref().map(|e| e.as_bytes()).unwrap_or_default()
+    }
+
+    /// Canonical postcode and exact location of every entry outside the bounding box (outward
+    /// codes padded to 7 chars, as `iter` gives them), in order. Empty before version 24.
+    pub fn outliers(&self) -> Vec<(&str, Point)>{
+        self.outliers.iter().collect()
+    }
+
+    /// Number of outward codes and of sectors in the sector bitmap, if there is one
+    pub fn sector_bitmap_counts(&self) -> Option<(usize, usize)>{
+        self.sector_bitmap.as_ref().map(|b| (b.len(), b.sector_count()))
+    }
+
+    /// Length of the records of every prefix block, before entropy coding, if they were written
+    /// in each order. The order of a file's records is chosen when it is packed, so this is for
+    /// seeing which would be smaller.
+    pub fn order_lens(&self) -> Result<Vec<(RecordOrder, usize)>, PostcodeError>{
+        let mut postcodes = self.quantized();
+        postcodes.sort_by_key(|p| p.postcode);
+        RecordOrder::ALL.into_iter()
+            .map(|order| {
+                let mut ordered = postcodes.clone();
+                order::arrange(&mut ordered, order, |p| (p.postcode, p.long, p.lat));
+                let len = ordered.chunk_by(|a, b| a.prefix() == b.prefix())
+                    .map(|block| Ok(pack_block(block, self.version, self.resolution)?.iter().map(|r| r.bytes().len()).sum::<usize>()))
+                    .sum::<Result<usize, PostcodeError>>()?;
+                Ok((order, len))
+            })
+            .collect()
+    }
+
+    /// Count the entries in each prefix block, and the records of each encoding
+    pub fn file_stats(&self) -> FileStats{
+        let mut stats = FileStats{
+            data_len: self.data_end - self.data_start,
+            ..Default::default()
+        };
+        for i in 0..LUT_SIZE{
+            let mut n = 0;
+            let mut last = DecodeState::default();
+            for r in self.records(i){
+                n += 1;
+                stats.record_kinds[r.kind] += 1;
+                stats.record_bytes[r.kind] += r.len;
+                if r.kind == 5{
+                    // The record that would have been used instead, see `pack_block`
+                    let pc_is_delta = r.code.checked_sub(last.code).is_some_and(|d| d <= 32);
+                    let ll_is_delta = [r.lat as i64 - last.lat as i64, r.long as i64 - last.long as i64]
+                        .iter().all(|d| (-128..=127).contains(d));
+                    let fixed = fixed_record_len(pc_is_delta, ll_is_delta, coord_len(self.resolution)) + r.terminated.is_some() as usize;
+                    stats.varint_saving += fixed.saturating_sub(r.len);
+                }
+                last = DecodeState{code: r.code, lat: r.lat, long: r.long};
+                if r.is_partial{
+                    stats.outward_codes += 1;
+                }
+            }
+            if n > 0{
+                stats.blocks.push((lut_prefix(i), n));
+            }
+        }
+        stats
+    }
+
+    /// The start of the file, up to the quick lookup table
+    #[cfg(feature="packer")]
+    pub(crate) fn header_bytes(&self) -> &[u8]{
+        &self.data[0..self.lut_start]
+    }
+
+    /// Every entry in the file with its quantized location, in file order
+    pub(crate) fn quantized(&self) -> Vec<QuantizedPostcode>{
+        let mut out = Vec::new();
+        for i in 0..LUT_SIZE{
+            for r in self.records(i){
+                let c = r.code.to_le_bytes();
+                let postcode = self.entry(i, &r).postcode;
+                out.push(QuantizedPostcode{
+                    postcode: postcode.as_bytes()[0..7].try_into().unwrap(),
+                    code: [c[0], c[1], c[2]],
+                    is_partial: r.is_partial,
+                    long: r.long,
+                    lat: r.lat,
+                    terminated: r.terminated,
+                    country: self.countries.as_ref().and_then(|c| c.get(postcode.as_bytes())),
+                });
+            }
+        }
+        out
+    }
+
+    /// Byte range of a prefix block, relative to the start of the postcode data
+    fn block_range(&self, index: usize) -> (usize, usize){
+        let lut_start = self.lut_start;
+        if self.chunk_size.is_some(){
+            // Version 9 has absolute ranges, invalid ones wrap around and are caught by from_bytes
+            let start = read_u32(&self.data, lut_start + (index*8)) as usize;
+            let end = read_u32(&self.data, lut_start + (index*8) + 4) as usize;
+            return (start.wrapping_sub(self.data_start), end.wrapping_sub(self.data_start));
+        }
+        let start = read_u32(&self.data, lut_start + (index*4)) as usize;
+        // The last entry in the table is not reliable in older files, but the
+        // last block always runs to the end of the file anyway.
+        let end = if index + 1 == LUT_SIZE{
+            self.data_end - self.data_start
+        }
+        else{
+            read_u32(&self.data, lut_start + ((index+1)*4)) as usize
+        };
+        (start, end)
+    }
+
+    /// The records of a prefix block
+    fn records(&self, index: usize) -> Records<'_>{
+        let (start, end) = self.block_range(index);
+        let block = &self.data[self.data_start+start..self.data_start+end];
+        // A damaged block reads as empty. Versions with entropy coding always have checksums, so
+        // this only happens in a mapped file that wasn't checked.
+        let data = match &self.decoder{
+            Some(decoder) => self.decoded[index].get_or_init(|| decoder.decode(block).unwrap_or_default()).as_slice(),
+            None => block,
+        };
+        Records::new(data, self.version, self.resolution)
+    }
+
+    /// Country of a postcode in any case and spacing (see `lookup`), if the file has countries.
+    ///
+    /// Returns `None` if the postcode is not in the file.
+    pub fn country(&self, postcode: &str) -> Option<Country>{
+        let postcode = self.scheme.codec().format(postcode).ok()?;
+        self.lookup_canonical(&postcode)?;
+        let countries = self.countries.as_ref()?;
+        countries.get(format!("{postcode:7}").as_bytes())
+    }
+
+    /// Value of an attribute of a postcode in any case and spacing (see `lookup`), e.g. the local
+    /// authority for "laua". Names are not case sensitive.
+    ///
+    /// Returns `None` if the postcode is not in the file, the file doesn't have the attribute, or
+    /// the postcode has no value for it.
+    pub fn attribute(&self, postcode: &str, name: &str) -> Option<&str>{
+        let postcode = self.scheme.codec().format(postcode).ok()?;
+        self.lookup_canonical(&postcode)?;
+        self.attributes.as_ref()?.get(name, format!("{postcode:7}").as_bytes())
+    }
+
+    /// Lower left and upper right corners of the bounding box of the postcodes in an outward
+    /// code, e.g. "YO1", in any case and spacing. A full postcode gives the box of its outward
+    /// code. The box is rounded outwards, so it covers every postcode in it.
+    ///
+    /// Returns `None` if there isn't one, or the file has no prefix extents.
+    pub fn prefix_extent(&self, outward: &str) -> Option<(Point, Point)>{
+        let codec = self.scheme.codec();
+        let code = codec.format(outward).ok()?;
+        let outward = if code.len() == 7 { codec.prefix(&code) } else { code.trim_end() };
+        let [minlong, minlat, maxlong, maxlat] = self.prefix_extents.as_ref()?.get(&format!("{outward:<4}"))?;
+        Some((self.location(minlat, minlong), self.location(maxlat, maxlong)))
+    }
+
+    /// Every outward code in the prefix extents section, sorted, with its lower left and upper
+    /// right corners
+    pub fn prefix_extents(&self) -> Vec<(String, Point, Point)>{
+        let Some(extents) = &self.prefix_extents else{
+            return Vec::new();
+        };
+        extents.iter()
+            .map(|(outward, [minlong, minlat, maxlong, maxlat])| (outward.to_string(), self.location(minlat, minlong), self.location(maxlat, maxlong)))
+            .collect()
+    }
+
+    /// Whether an outward code, e.g. "SW1A", or a sector, e.g. "SW1A 2", has any postcodes, in any
+    /// case. A sector needs a space between the outward code and the first char of the inward
+    /// code (or of the unique identifier, for Eircodes).
+    ///
+    /// Returns `None` if the file has no sector bitmap.
+    pub fn prefix_exists(&self, text: &str) -> Option<bool>{
+        Some(self.sector_bitmap.as_ref()?.exists(text))
+    }
+
+    /// Outward codes and sectors that start with some partly typed text, sorted, e.g. "SW1A"
+    /// gives "SW1A", "SW1A 0", "SW1A 1" and "SW1A 2", and "SW1" gives those and the other outward
+    /// codes that start with SW1. Empty if the file has no sector bitmap.
+    pub fn complete(&self, text: &str) -> Vec<String>{
+        self.sector_bitmap.as_ref().map(|b| b.complete(text)).unwrap_or_default()
+    }
+
+    /// Up to `limit` full postcodes that start with some partly typed text, in any case, in
+    /// canonical form and sorted, e.g. "sw1a 1a" gives "SW1A1AA". Without a space, the text can
+    /// run on from the outward code in to the inward code, so "E16" gives E16 postcodes and E1 6
+    /// ones. With a space, only the postcodes of the outward code before it are listed.
+    /// Terminated postcodes are left out.
+    ///
+    /// Only the prefix blocks that can have them are decoded, and the search stops once there
+    /// are enough, so it doesn't need a sector bitmap.
+    pub fn complete_postcodes(&self, text: &str, limit: usize) -> Vec<String>{
+        let text = text.trim_start().to_ascii_uppercase();
+        if !text.is_ascii(){
+            return Vec::new();
+        }
+        // A space at the end still means that the outward code has been typed
+        let outward = text.split_once(char::is_whitespace).map(|(outward, inward)| (outward, inward.trim()));
+        let typed: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let codec = self.scheme.codec();
+        let found = self.iter_prefix(&typed[..typed.len().min(2)])
+            .filter(|p| !p.is_partial && p.terminated.is_none())
+            .filter(|p| match outward{
+                Some((outward, inward)) => {
+                    let code_outward = codec.prefix(&p.postcode);
+                    code_outward == outward && p.postcode[code_outward.len()..].trim_start().starts_with(inward)
+                }
+                None => p.postcode.replace(' ', "").starts_with(&typed),
+            })
+            .map(|p| p.postcode);
+        if self.order == RecordOrder::Alphabetical{
+            return found.take(limit).collect();
+        }
+        // In any other order, the first ones in the file aren't the first ones in order
+        let mut found: Vec<String> = found.collect();
+        found.sort();
+        found.truncate(limit);
+        found
+    }
+
+    /// Find the centroid of an area, district or sector, e.g. "YO", "YO1" or "YO1 7". The name can be
+    /// in any case and spacing.
+    ///
+    /// Returns `None` if there isn't one, or the file has no centroid tables.
+    pub fn centroid(&self, level: CentroidLevel, name: &str) -> Option<Centroid>{
+        let (name, lat, long, count) = self.centroids.as_ref()?.get(level, name)?;
+        Some(Centroid{name, location: self.location(lat, long), count})
+    }
+
+    /// All of the centroids at one level, sorted by name
+    pub fn centroids(&self, level: CentroidLevel) -> Vec<Centroid>{
+        let Some(tables) = &self.centroids else{
+            return Vec::new();
+        };
+        tables.iter(level)
+            .map(|(name, lat, long, count)| Centroid{name, location: self.location(lat, long), count})
+            .collect()
+    }
+
+    /// Whether the file has a spatial index, without one `nearest` has to scan the whole file
+    pub fn has_spatial_index(&self) -> bool{
+        self.index.is_some()
+    }
+
+    /// Convert a location from the centroid tables, which are always 16 bits, in to a real one
+    fn location(&self, lat: u16, long: u16) -> Point{
+        let lat = self.minll.y + ((self.maxll.y - self.minll.y) * (lat as f64 / 65535.0));
+        let long = self.minll.x + ((self.maxll.x - self.minll.x) * (long as f64 / 65535.0));
+        Point{x:long, y:lat}
+    }
+
+    /// Convert a quantized location of a canonical postcode in a prefix block in to a real one,
+    /// the block or the postcode's group may have its own bounding box
+    fn block_location(&self, block: usize, postcode: &str, lat: u32, long: u32) -> Point{
+        let (minll, maxll) = self.boxes.bounds(block, postcode.as_bytes(), self.minll, self.maxll);
+        dequantize_ll(minll, maxll, long, lat, self.resolution)
+    }
+
+    /// Look up a postcode in any case and spacing, e.g. "sw1a1aa", " SW1A 1AA ", or the canonical
+    /// or 8 char padded forms. See `CodeCodec::format` for the file's scheme. Outward codes may be
+    /// looked up by themselves in files from version 2 onwards.
+    pub fn lookup(&self, postcode: &str) -> Option<Point>{
+        self.lookup_canonical(&self.scheme.codec().format(postcode).ok()?)
+    }
+
+    /// Look up a postcode that is already in canonical form: full postcodes 7 chars long, with
+    /// the inward code aligned right, and outward codes 4 chars long
+    pub(crate) fn lookup_canonical(&self, postcode: &str) -> Option<Point>{
+        self.record(postcode).map(|(index, r)| self.outliers.get(postcode).unwrap_or_else(|| self.block_location(index, postcode, r.lat, r.long)))
+    }
+
+    /// Check a postcode or outward code in any case and spacing, telling a code that isn't
+    /// well-formed apart from one that is, but isn't in the file
+    pub fn validate(&self, code: &str) -> Validity{
+        let codec = self.scheme.codec();
+        let Ok(canonical) = codec.format(code) else{
+            return Validity::Unknown;
+        };
+        // Formatting only checks the length and the chars, not where the letters and digits are
+        let well_formed = if canonical.len() == 4 { pack_outward_code(&format!("{canonical}   ")).is_ok() } else { codec.encode(&canonical).is_ok() };
+        if !well_formed{
+            return Validity::Unknown;
+        }
+        match self.record(&canonical){
+            Some((_, r)) if r.terminated.is_some() => Validity::Terminated,
+            Some(_) => Validity::Exists,
+            None => Validity::WellFormed,
+        }
+    }
+
+    /// Postcodes in the file that a typo of `code` could have come from, up to `max_edits` edits
+    /// away (at most `suggest::MAX_EDITS`), see suggest.rs. The nearest misses come first, and
+    /// of those, the ones that only need lookalike chars (O and 0, I and 1) changed. Terminated
+    /// postcodes, outward codes and the code itself are left out.
+    pub fn suggest(&self, code: &str, max_edits: usize) -> Vec<String>{
+        let codec = self.scheme.codec();
+        let typed: String = code.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect();
+        if !typed.is_ascii(){
+            return Vec::new();
+        }
+        // The packed codes to look for in each block
+        let mut blocks: HashMap<usize, HashMap<u32, (Cost, String)>> = HashMap::new();
+        for (candidate, cost) in near_misses(&typed, max_edits){
+            // Only full codes, not outward codes by themselves
+            let Some(canonical) = codec.format(&candidate).ok().filter(|c| c.len() == 7) else{
+                continue;
+            };
+            let (Ok(packed), Some(index)) = (codec.encode(&canonical), lut_index(canonical.as_bytes())) else{
+                continue;
+            };
+            let packed = u32::from_le_bytes([packed[0], packed[1], packed[2], 0]);
+            blocks.entry(index).or_default().insert(packed, (cost, canonical));
+        }
+        let mut found: Vec<(Cost, String)> = Vec::new();
+        for (index, candidates) in blocks{
+            for r in self.records(index).filter(|r| !r.is_partial && r.terminated.is_none()){
+                if let Some(c) = candidates.get(&r.code){
+                    found.push(c.clone());
+                }
+            }
+        }
+        found.sort();
+        found.into_iter().map(|(_, postcode)| postcode).collect()
+    }
+
+    /// Find the record of a postcode in canonical form, and the number of its prefix block
+    fn record(&self, postcode: &str) -> Option<(usize, Record)>{
+        let outward_only = postcode.len() == 4;
+        let code = if outward_only{
+            if self.version < 2{
+                return None;
+            }
+            pack_outward_code(&format!("{postcode}   ")).ok()?
+        }
+        else{
+            self.scheme.codec().encode(postcode).ok()?
+        };
+        let code = u32::from_le_bytes([code[0], code[1], code[2], 0]);
+        let index = lut_index(postcode.as_bytes())?;
+        self.records(index)
+            .find(|r| r.is_partial == outward_only && r.code == code)
+            .map(|r| (index, r))
+    }
+
+    /// Find the postcode closest to a location, by great circle distance.
+    ///
+    /// Outward-only entries and terminated postcodes are never returned. Returns `None` only if
+    /// the file has no postcodes.
+    pub fn nearest(&self, lat: f64, long: f64) -> Option<PostcodeInfo>{
+        self.nearest_n(lat, long, 1).pop().map(|(p, _)| p)
+    }
+
+    /// Find the `k` postcodes closest to a location, nearest first, with their distances in
+    /// metres.
+    ///
+    /// Outward-only entries and terminated postcodes are never returned. Fewer than `k` are
+    /// returned only if the file doesn't have that many postcodes.
+    pub fn nearest_n(&self, lat: f64, long: f64, k: usize) -> Vec<(PostcodeInfo, f64)>{
+        let mut found = self.indexed_nearest_n(lat, long, k);
+        let centre = Point{x:long, y:lat};
+        if self.add_outliers(&centre, &mut found, |_| true){
+            found.truncate(k);
+        }
+        found
+    }
+
+    /// As `nearest_n`, using the spatial index if there is one
+    fn indexed_nearest_n(&self, lat: f64, long: f64, k: usize) -> Vec<(PostcodeInfo, f64)>{
+        if k == 0{
+            return Vec::new();
+        }
+        let centre = Point{x:long, y:lat};
+        // The k nearest so far, nearest first
+        let mut best: Vec<(f64, PostcodeInfo)> = Vec::with_capacity(k + 1);
+        let consider = |best: &mut Vec<(f64, PostcodeInfo)>, p: PostcodeInfo|{
+            if p.is_partial || p.terminated.is_some(){
+                return;
+            }
+            // Sorted by distance, then by postcode so that ties always go the same way
+            let d = distance_m(&centre, &p.location);
+            let before = |(bd, bp): &(f64, PostcodeInfo)| *bd < d || (*bd == d && bp.postcode < p.postcode);
+            if best.len() == k && before(&best[k - 1]){
+                return;
+            }
+            let at = best.partition_point(before);
+            best.insert(at, (d, p));
+            best.truncate(k);
+        };
+        let with_metres = |best: Vec<(f64, PostcodeInfo)>| best.into_iter().map(|(d, p)| (p, d)).collect();
+
+        let index = match &self.index{
+            Some(SpatialIndex::Grid(grid)) => grid,
+            Some(SpatialIndex::KdTree(tree)) => return with_metres(self.tree_nearest(tree, &centre, |found, _| found >= k)),
+            Some(SpatialIndex::Geohash(buckets)) => return with_metres(self.geohash_nearest(buckets, &centre, |found, _| found >= k)),
+            None => {
+                self.iter().for_each(|p| consider(&mut best, p));
+                return with_metres(best);
+            }
+        };
+
+        // Search outwards from the cell containing the location, one ring of cells at a time
+        let (col, row) = self.cell_at(index, lat, long);
+        let cell_w = ((self.maxll.x - self.minll.x) / index.cols() as f64).to_radians();
+        let cell_h = ((self.maxll.y - self.minll.y) / index.rows() as f64).to_radians();
+        // Lines of longitude are closest together at the latitude furthest from the equator
+        let max_lat = lat.abs().max(self.minll.y.abs()).max(self.maxll.y.abs()).min(90.0).to_radians();
+        // Shortest distance to a point that is `cells` whole cells away in either direction
+        let min_distance = |cells: f64|{
+            let across = 2.0 * EARTH_RADIUS_M * (max_lat.cos() * ((cells * cell_w).min(std::f64::consts::PI) / 2.0).sin()).asin();
+            across.min(EARTH_RADIUS_M * cells * cell_h)
+        };
+        let mut visited = vec![false; LUT_SIZE];
+        let max_ring = index.cols().max(index.rows());
+        for ring in 0..=max_ring{
+            // Anything not yet seen is in this ring or further out, and might be as near as the
+            // furthest found so far, which it would displace if its postcode sorts first
+            if ring > 0 && best.len() == k && best[k - 1].0 < min_distance((ring - 1) as f64){
+                break;
+            }
+            let (c0, c1) = (col as isize - ring as isize, col as isize + ring as isize);
+            let (r0, r1) = (row as isize - ring as isize, row as isize + ring as isize);
+            for r in r0..=r1{
+                for c in c0..=c1{
+                    let on_ring = r == r0 || r == r1 || c == c0 || c == c1;
+                    if !on_ring || r < 0 || c < 0 || r as usize >= index.rows() || c as usize >= index.cols(){
+                        continue;
+                    }
+                    for &block in index.blocks(c as usize, r as usize){
+                        let block = block as usize;
+                        if visited[block]{
+                            continue;
+                        }
+                        visited[block] = true;
+                        self.records(block).for_each(|rec| consider(&mut best, self.entry(block, &rec)));
+                    }
+                }
+            }
+        }
+        with_metres(best)
+    }
+
+    /// Find all of the postcodes within a distance (in metres) of a location, nearest first.
+    ///
+    /// Outward-only entries and terminated postcodes are never returned.
+    pub fn within_radius(&self, lat: f64, long: f64, metres: f64) -> Vec<PostcodeInfo>{
+        let found = self.indexed_within_radius(lat, long, metres);
+        let centre = Point{x:long, y:lat};
+        let mut found: Vec<(PostcodeInfo, f64)> = found.into_iter().map(|p| { let d = distance_m(&centre, &p.location); (p, d) }).collect();
+        self.add_outliers(&centre, &mut found, |d| d <= metres);
+        found.into_iter().map(|(p, _)| p).collect()
+    }
+
+    /// The spatial index only has the outliers on the edge of the bounding box, so a search that
+    /// uses it might miss them. Add the ones that `keep` their distance to what was found, in
+    /// order. Returns whether any were added.
+    fn add_outliers(&self, centre: &Point, found: &mut Vec<(PostcodeInfo, f64)>, keep: impl Fn(f64) -> bool) -> bool{
+        if self.index.is_none() || self.outliers.len() == 0{
+            return false;
+        }
+        let entries = self.outlier_entries.get_or_init(|| self.outliers.iter()
+            .filter(|(postcode, _)| !postcode.ends_with("   "))
+            .filter_map(|(postcode, _)| self.record(postcode))
+            .map(|(block, r)| self.entry(block, &r))
+            .filter(|p| !p.is_partial && p.terminated.is_none())
+            .collect());
+        let mut added = false;
+        for p in entries{
+            let d = distance_m(centre, &p.location);
+            if keep(d) && !found.iter().any(|(f, _)| f.postcode == p.postcode){
+                found.push((p.clone(), d));
+                added = true;
+            }
+        }
+        found.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.postcode.cmp(&b.0.postcode)));
+        added
+    }
+
+    /// As `within_radius`, using the spatial index if there is one
+    fn indexed_within_radius(&self, lat: f64, long: f64, metres: f64) -> Vec<PostcodeInfo>{
+        let centre = Point{x:long, y:lat};
+        let mut found: Vec<(f64, PostcodeInfo)> = Vec::new();
+        let mut consider = |p: PostcodeInfo|{
+            if p.is_partial || p.terminated.is_some(){
+                return;
+            }
+            let d = distance_m(&centre, &p.location);
+            if d <= metres{
+                found.push((d, p));
+            }
+        };
+        match &self.index{
+            None => self.iter().for_each(consider),
+            Some(SpatialIndex::KdTree(tree)) => {
+                return self.tree_nearest(tree, &centre, |_, d| d > metres).into_iter().map(|(_, p)| p).collect();
+            }
+            Some(SpatialIndex::Geohash(buckets)) => {
+                return self.geohash_nearest(buckets, &centre, |_, d| d > metres).into_iter().map(|(_, p)| p).collect();
+            }
+            Some(SpatialIndex::Grid(index)) => {
+                // Only look in the cells that overlap the bounding box of the circle
+                let dlat = (metres / EARTH_RADIUS_M).to_degrees();
+                let max_lat = (lat.abs() + dlat).min(89.0);
+                let dlong = dlat / max_lat.to_radians().cos();
+                let (c0, r0) = self.cell_at(index, lat - dlat, long - dlong);
+                let (c1, r1) = self.cell_at(index, lat + dlat, long + dlong);
+                let mut visited = vec![false; LUT_SIZE];
+                for r in r0..=r1{
+                    for c in c0..=c1{
+                        for &block in index.blocks(c, r){
+                            let block = block as usize;
+                            if visited[block]{
+                                continue;
+                            }
+                            visited[block] = true;
+                            self.records(block).for_each(|rec| consider(self.entry(block, &rec)));
+                        }
+                    }
+                }
+            }
+        }
+        found.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.postcode.cmp(&b.1.postcode)));
+        found.into_iter().map(|(_, p)| p).collect()
+    }
+
+    /// Search a k-d tree index for the postcodes nearest to a location, nearest first, until
+    /// `done(found, distance)` says there are enough, where `distance` is as near as any of the
+    /// postcodes not yet found can be
+    fn tree_nearest(&self, tree: &KdTree, centre: &Point, done: impl Fn(usize, f64) -> bool) -> Vec<(f64, PostcodeInfo)>{
+        // The tree's locations are only within a step of the real ones, so the boxes are made
+        // a step bigger each way
+        let to_point = |long: f64, lat: f64| Point{
+            x: self.minll.x + ((self.maxll.x - self.minll.x) * (long / 65535.0)),
+            y: self.minll.y + ((self.maxll.y - self.minll.y) * (lat / 65535.0)),
+        };
+        let distance = |min: (u16, u16), max: (u16, u16)| min_distance_to_box(
+            centre,
+            to_point(min.0 as f64 - 1.0, min.1 as f64 - 1.0),
+            to_point(max.0 as f64 + 1.0, max.1 as f64 + 1.0),
+        );
+        let root = tree.root().map(|root| Queued{distance: distance(root.min, root.max), item: Candidate::Node(root)});
+        self.best_first(centre, root, done, |item, queue|{
+            if let Candidate::Node(node) = item{
+                let p = tree.points()[node.mid()];
+                let entry = Candidate::Entry{block: p.block as usize, entry: p.entry as usize};
+                queue.push(Queued{distance: distance((p.long, p.lat), (p.long, p.lat)), item: entry});
+                let (left, right) = tree.children(&node);
+                for child in [left, right].into_iter().flatten(){
+                    queue.push(Queued{distance: distance(child.min, child.max), item: Candidate::Node(child)});
+                }
+            }
+        })
+    }
+
+    /// Search a geohash index in the same way as `tree_nearest`, halving the area at each step
+    fn geohash_nearest(&self, buckets: &GeohashIndex, centre: &Point, done: impl Fn(usize, f64) -> bool) -> Vec<(f64, PostcodeInfo)>{
+        let distance = |cell: &Cell|{
+            let (min, max) = geohash::cell_bounds(cell.prefix, cell.bits);
+            min_distance_to_box(centre, min, max)
+        };
+        let root = buckets.root().map(|root| Queued{distance: distance(&root), item: Candidate::Cell(root)});
+        self.best_first(centre, root, done, |item, queue|{
+            let Candidate::Cell(cell) = item else{
+                return;
+            };
+            if buckets.is_bucket(&cell){
+                let d = distance(&cell);
+                for &(block, entry) in buckets.records(&cell){
+                    queue.push(Queued{distance: d, item: Candidate::Entry{block: block as usize, entry: entry as usize}});
+                }
+                return;
+            }
+            let (left, right) = buckets.children(&cell);
+            for child in [left, right].into_iter().flatten(){
+                queue.push(Queued{distance: distance(&child), item: Candidate::Cell(child)});
+            }
+        })
+    }
+
+    /// Take the nearest candidate from the queue until `done`. Candidates that are part of the
+    /// index are passed to `expand`, to put the parts of them in the queue.
+    fn best_first(&self, centre: &Point, root: Option<Queued>, done: impl Fn(usize, f64) -> bool, expand: impl Fn(Candidate, &mut BinaryHeap<Queued>)) -> Vec<(f64, PostcodeInfo)>{
+        let mut queue: BinaryHeap<Queued> = root.into_iter().collect();
+        // Every entry of each block that has been decoded
+        let mut blocks: HashMap<usize, Vec<PostcodeInfo>> = HashMap::new();
+        let mut found = Vec::new();
+        while let Some(Queued{distance: d, item}) = queue.pop(){
+            if done(found.len(), d){
+                break;
+            }
+            match item{
+                Candidate::Entry{block, entry} => {
+                    let entries = blocks.entry(block)
+                        .or_insert_with(|| self.records(block).map(|r| self.entry(block, &r)).collect());
+                    // Only a damaged index can point at anything else
+                    if let Some(e) = entries.get(entry).filter(|e| !e.is_partial && e.terminated.is_none()){
+                        queue.push(Queued{distance: distance_m(centre, &e.location), item: Candidate::Found(e.clone())});
+                    }
+                }
+                Candidate::Found(p) => found.push((d, p)),
+                other => expand(other, &mut queue),
+            }
+        }
+        found
+    }
+
+    /// Column and row of the index cell containing a location, locations outside of the
+    /// bounding box are moved to the nearest cell on the edge
+    fn cell_at(&self, index: &Grid, lat: f64, long: f64) -> (usize, usize){
+        let quantize = |x: f64, min: f64, max: f64| (((x - min) / (max - min)) * 65535.0).round().clamp(0.0, 65535.0) as u16;
+        index.cell(
+            quantize(long, self.minll.x, self.maxll.x),
+            quantize(lat, self.minll.y, self.maxll.y),
+        )
+    }
+
+    /// Convert a decoded record from a prefix block in to a postcode entry
+    fn entry(&self, block: usize, r: &Record) -> PostcodeInfo{
+        let prefix = lut_prefix(block);
+        let postcode = if r.is_partial{
+            unpack_outward_code(&prefix, r.code)
+        }
+        else{
+            self.scheme.codec().decode(&prefix, r.code)
+        };
+        PostcodeInfo{
+            location: self.outliers.get(&postcode).unwrap_or_else(|| self.block_location(block, &postcode, r.lat, r.long)),
+            is_partial: r.is_partial,
+            terminated: r.terminated.map(|y| y as u16 + TERMINATED_YEAR_BASE),
+            country: self.countries.as_ref().and_then(|c| c.get(postcode.as_bytes())),
+            introduced: None,
+            attributes: self.attributes.as_ref().map(|a| a.get_all(postcode.as_bytes())).unwrap_or_default(),
+            postcode,
+        }
+    }
+
+    /// Every entry inside a bounding box (lower left and upper right corners, edges included),
+    /// in file order. Like `iter`, this includes outward-only entries and terminated postcodes.
+    pub fn within_bbox(&self, min: Point, max: Point) -> impl Iterator<Item=PostcodeInfo> + '_{
+        self.iter().filter(move |p| in_box(&p.location, min, max))
+    }
+
+    /// Every entry inside a polygon, by the even-odd rule (see region.rs), in file order. Like
+    /// `iter`, this includes outward-only entries and terminated postcodes.
+    pub fn within_polygon<'a>(&'a self, polygon: &'a [Point]) -> impl Iterator<Item=PostcodeInfo> + 'a{
+        let bounds = region::bounds(polygon);
+        self.iter().filter(move |p| bounds.is_some_and(|(min, max)| in_box(&p.location, min, max)) && in_polygon(&p.location, polygon))
+    }
+
+    /// Iterate over the entries whose canonical form starts with `prefix`, e.g. "SW1A" or
+    /// "E1  6", in file order, outward codes included. Only the prefix blocks that can have them
+    /// are decoded, one at a time as the iterator reaches them, so "SW" or a block name from
+    /// `block_ranges` is one block.
+    pub fn iter_prefix(&self, prefix: &str) -> impl Iterator<Item=PostcodeInfo> + '_{
+        let prefix = prefix.to_string();
+        let blocks: Vec<usize> = (0..LUT_SIZE)
+            .filter(|&i| {
+                let name = lut_prefix(i);
+                name.starts_with(&prefix) || prefix.starts_with(&name)
+            })
+            .collect();
+        blocks.into_iter()
+            .flat_map(move |index| self.records(index).map(move |r| self.entry(index, &r)))
+            .filter(move |p| p.postcode.starts_with(&prefix))
+    }
+
+    /// Iterate over every entry in the file, in file order
+    pub fn iter(&self) -> Entries<'_>{
+        Entries{
+            reader: self,
+            index: 0,
+            block: self.records(0),
+        }
+    }
+}
+
+/// Iterator over all of the entries in a pack file
+pub struct Entries<'a>{
+    reader: &'a Reader,
+    index: usize,
+    block: Records<'a>,
+}
+
+impl Iterator for Entries<'_>{
+    type Item = PostcodeInfo;
+
+    fn next(&mut self) -> Option<PostcodeInfo>{
+        loop{
+            if let Some(r) = self.block.next(){
+                return Some(self.reader.entry(self.index, &r));
+            }
+            self.index += 1;
+            if self.index >= LUT_SIZE{
+                return None;
+            }
+            self.block = self.reader.records(self.index);
+        }
+    }
+}
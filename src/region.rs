@@ -0,0 +1,133 @@
+/*
+
+Areas bounded by polygons, for finding the postcodes in e.g. a local authority.
+
+A polygon is a list of corners, as longitude and latitude, and doesn't need to repeat the first
+corner at the end. A point is inside it if a line from the point crosses its edges an odd number
+of times (the even-odd rule). A region is any number of polygons, and a point is inside the
+region if it is inside an odd number of them, so a hole in a polygon is just another polygon
+inside it, as in GeoJSON.
+
+Regions can be read from GeoJSON files with the `boundary` feature, which is on by default. The
+file can be a Polygon or MultiPolygon, or a Feature, FeatureCollection or GeometryCollection of
+them. Any other geometries in the file are ignored.
+
+*/
+use crate::Point;
+#[cfg(feature="boundary")]
+use crate::PostcodeError;
+
+/// Whether a point is inside a polygon, by the even-odd rule. Points exactly on an edge may be
+/// inside or outside.
+pub fn in_polygon(point: &Point, polygon: &[Point]) -> bool{
+    let mut inside = false;
+    let mut previous = match polygon.last(){
+        Some(p) => p,
+        None => return false,
+    };
+    for corner in polygon{
+        // Edges that cross the horizontal line through the point, to the right of it
+        if (corner.y > point.y) != (previous.y > point.y){
+            let x = corner.x + ((point.y - corner.y) * (previous.x - corner.x) / (previous.y - corner.y));
+            if point.x < x{
+                inside = !inside;
+            }
+        }
+        previous = corner;
+    }
+    inside
+}
+
+/// Lower left and upper right corners of the box around some points, `None` if there are none
+pub(crate) fn bounds<'a, I: IntoIterator<Item=&'a Point>>(points: I) -> Option<(Point, Point)>{
+    points.into_iter().fold(None, |b, p| match b{
+        None => Some((*p, *p)),
+        Some((min, max)) => Some((Point{x: min.x.min(p.x), y: min.y.min(p.y)}, Point{x: max.x.max(p.x), y: max.y.max(p.y)})),
+    })
+}
+
+/// An area made of polygons
+#[derive(Debug, Clone)]
+pub struct Region{
+    polygons: Vec<Vec<Point>>,
+}
+
+impl Region{
+    pub fn new(polygons: Vec<Vec<Point>>) -> Region{
+        Region{polygons}
+    }
+
+    pub fn polygons(&self) -> &[Vec<Point>]{
+        &self.polygons
+    }
+
+    /// Whether a point is inside an odd number of the polygons
+    pub fn contains(&self, point: &Point) -> bool{
+        self.polygons.iter().filter(|p| in_polygon(point, p)).count() % 2 == 1
+    }
+
+    /// Lower left and upper right corners of the box around the region, `None` if it has no
+    /// corners
+    pub fn bounds(&self) -> Option<(Point, Point)>{
+        bounds(self.polygons.iter().flatten())
+    }
+
+    /// Read the polygons from a GeoJSON file
+    #[cfg(feature="boundary")]
+    pub fn from_geojson(json: &str) -> Result<Region, PostcodeError>{
+        let json: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| PostcodeError::BadBoundary(format!("not valid JSON: {e}")))?;
+        let mut polygons = Vec::new();
+        add_geojson(&json, &mut polygons)?;
+        if polygons.is_empty(){
+            return Err(PostcodeError::BadBoundary("there are no polygons in it".to_string()));
+        }
+        Ok(Region{polygons})
+    }
+}
+
+#[cfg(feature="boundary")]
+fn add_geojson(json: &serde_json::Value, polygons: &mut Vec<Vec<Point>>) -> Result<(), PostcodeError>{
+    let bad = |why: &str| PostcodeError::BadBoundary(why.to_string());
+    let ring = |ring: &serde_json::Value| -> Result<Vec<Point>, PostcodeError>{
+        ring.as_array().ok_or_else(|| bad("a polygon ring is not a list of positions"))?
+            .iter()
+            .map(|position|{
+                let number = |i: usize| position.get(i).and_then(|n| n.as_f64());
+                match (number(0), number(1)){
+                    (Some(x), Some(y)) => Ok(Point{x, y}),
+                    _ => Err(bad("a position is not a list of numbers")),
+                }
+            })
+            .collect()
+    };
+    let rings = |polygon: &serde_json::Value| -> Result<Vec<Vec<Point>>, PostcodeError>{
+        polygon.as_array().ok_or_else(|| bad("a polygon is not a list of rings"))?.iter().map(ring).collect()
+    };
+    let list = |name: &str| json[name].as_array().ok_or_else(|| PostcodeError::BadBoundary(format!("{name} is missing, or is not a list")));
+    match json["type"].as_str(){
+        Some("Polygon") => polygons.extend(rings(&json["coordinates"])?),
+        Some("MultiPolygon") => {
+            for polygon in list("coordinates")?{
+                polygons.extend(rings(polygon)?);
+            }
+        }
+        Some("Feature") => add_geojson(&json["geometry"], polygons)?,
+        Some("FeatureCollection") => {
+            for feature in list("features")?{
+                add_geojson(feature, polygons)?;
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in list("geometries")?{
+                add_geojson(geometry, polygons)?;
+            }
+        }
+        // Points and lines have no inside
+        Some(_) => {}
+        // A Feature's geometry can be null
+        None if json.is_null() => {}
+        None => return Err(bad("a GeoJSON object has no type")),
+    }
+    Ok(())
+}
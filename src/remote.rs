@@ -0,0 +1,242 @@
+/*
+
+Looking up postcodes in a pack file that hasn't been downloaded, by reading only the parts of it
+that a lookup needs.
+
+A version 26 file has everything needed to read any prefix block at its start (see section.rs),
+and the quick lookup table holds the absolute byte range of each block, so a `RemoteReader`
+opens a file with three small reads: the header, the section table, and the rest of the start
+of the file up to the postcode data. Each lookup then reads the one block that the postcode is
+in, which is usually a few kilobytes, and keeps it for the next lookup in the same block. With
+`--chunk-size`, blocks are also aligned to the chunks that a CDN caches.
+
+Where the bytes come from is up to a `RangeRead`: a local file, an HTTP server that supports
+range requests (`HttpRange`, with the `fetch` feature), or anything else that can read part of
+a file. Older versions have sections at the end of the file that are needed to read the blocks,
+so they have to be upgraded first (see repack.rs).
+
+For each block, the start of the file and the block are put together in to a small pack file of
+their own, with every other block empty and the sections after the postcode data left out of
+the table, so the usual `Reader` decodes it.
+
+*/
+use std::sync::OnceLock;
+
+use crate::{PostcodeError, Point, Reader, Scheme, LUT_SIZE, lut_index, crc32};
+use crate::section::{SectionKind, SectionTable, TABLE_START, table_len};
+
+/// First format version whose files can be read a part at a time
+pub const MIN_VERSION: u32 = 26;
+
+fn read_u32(data: &[u8], pos: usize) -> u32{
+    u32::from_le_bytes(data[pos..pos+4].try_into().unwrap())
+}
+
+/// Somewhere a pack file can be read from, a part at a time
+pub trait RangeRead{
+    /// Length of the whole file in bytes
+    fn file_len(&self) -> Result<u64, PostcodeError>;
+
+    /// Bytes `start` to `end` (not included) of the file
+    fn read_range(&self, start: u64, end: u64) -> Result<Vec<u8>, PostcodeError>;
+}
+
+impl RangeRead for Vec<u8>{
+    fn file_len(&self) -> Result<u64, PostcodeError>{
+        Ok(self.len() as u64)
+    }
+
+    fn read_range(&self, start: u64, end: u64) -> Result<Vec<u8>, PostcodeError>{
+        self.get(start as usize..end as usize).map(|b| b.to_vec()).ok_or(PostcodeError::Corrupt("file"))
+    }
+}
+
+#[cfg(unix)]
+impl RangeRead for std::fs::File{
+    fn file_len(&self) -> Result<u64, PostcodeError>{
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_range(&self, start: u64, end: u64) -> Result<Vec<u8>, PostcodeError>{
+        use std::os::unix::fs::FileExt;
+        let mut out = vec![0; end.saturating_sub(start) as usize];
+        self.read_exact_at(&mut out, start)?;
+        Ok(out)
+    }
+}
+
+/// A pack file on an HTTP server that supports range requests
+#[cfg(feature="fetch")]
+#[derive(Debug, Clone)]
+pub struct HttpRange{
+    pub url: String,
+}
+
+#[cfg(feature="fetch")]
+impl RangeRead for HttpRange{
+    fn file_len(&self) -> Result<u64, PostcodeError>{
+        let response = ureq::head(&self.url).call().map_err(|e| PostcodeError::DownloadFailed(e.to_string()))?;
+        response.headers().get("content-length")
+            .and_then(|l| l.to_str().ok()?.parse().ok())
+            .ok_or_else(|| PostcodeError::DownloadFailed("the server didn't say how long the file is".to_string()))
+    }
+
+    fn read_range(&self, start: u64, end: u64) -> Result<Vec<u8>, PostcodeError>{
+        if end <= start{
+            return Ok(Vec::new());
+        }
+        let mut response = ureq::get(&self.url)
+            .header("Range", format!("bytes={start}-{}", end - 1))
+            .call().map_err(|e| PostcodeError::DownloadFailed(e.to_string()))?;
+        // A server that ignores the range sends the whole file
+        if response.status() != 206{
+            return Err(PostcodeError::DownloadFailed("the server doesn't support range requests".to_string()));
+        }
+        let body = response.body_mut().read_to_vec().map_err(|e| PostcodeError::DownloadFailed(e.to_string()))?;
+        if body.len() as u64 != end - start{
+            return Err(PostcodeError::Corrupt("file"));
+        }
+        Ok(body)
+    }
+}
+
+/// A pack file that is read a part at a time, only looking up postcodes
+pub struct RemoteReader<R: RangeRead>{
+    source: R,
+    /// The start of the file, up to the postcode data, with a section table of just the sections
+    /// in it and the postcode data
+    head: Vec<u8>,
+    /// Where the quick lookup table is in `head`
+    lut_start: usize,
+    /// Absolute byte range of each prefix block
+    ranges: Vec<(u64, u64)>,
+    /// The file with no postcode data, for its header
+    header: Reader,
+    /// Each prefix block that has been read
+    blocks: Vec<OnceLock<Vec<u8>>>,
+}
+
+impl<R: RangeRead> RemoteReader<R>{
+    /// Read the start of a pack file, which must be version 26 or newer
+    pub fn open(source: R) -> Result<Self, PostcodeError>{
+        let file_len = source.file_len()?;
+        let start = source.read_range(0, file_len.min(TABLE_START as u64 + 4))?;
+        if start.len() < TABLE_START + 4 || &start[0..4] != b"UKPP"{
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let version = read_u32(&start, 4);
+        if version < MIN_VERSION{
+            return Err(PostcodeError::IncompatibleOptions("only files of format version 26 onwards can be read a part at a time, rewrite it with the upgrade subcommand"));
+        }
+        let count = read_u32(&start, TABLE_START) as usize;
+        let table_end = (TABLE_START + table_len(count)) as u64;
+        if table_end > file_len{
+            return Err(PostcodeError::NotAPackFile());
+        }
+        let mut head = start;
+        head.extend(source.read_range(head.len() as u64, table_end)?);
+        let table = SectionTable::from_bytes(&head, file_len as usize)?;
+        let lut = *table.get(SectionKind::LookupTable).ok_or(PostcodeError::NotAPackFile())?;
+        let data = *table.get(SectionKind::PostcodeData).ok_or(PostcodeError::NotAPackFile())?;
+        if data.start != lut.end(){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        head.extend(source.read_range(table_end, data.start as u64)?);
+        // Every section that a block needs is in the start of the file
+        if let Some(s) = table.iter().find(|s| s.required && s.id != data.id && s.end() > data.start){
+            return Err(PostcodeError::UnsupportedSection(s.id));
+        }
+        for s in table.iter().filter(|s| s.end() <= data.start){
+            if crc32(&head[s.start..s.end()]) != s.crc{
+                return Err(PostcodeError::Corrupt(s.kind().map(|k| k.name()).unwrap_or("header")));
+            }
+        }
+        let ranges: Vec<(u64, u64)> = (0..LUT_SIZE)
+            .map(|i| (read_u32(&head, lut.start + (i*8)) as u64, read_u32(&head, lut.start + (i*8) + 4) as u64))
+            .collect();
+        if ranges.iter().any(|&(s, e)| s > e || s < data.start as u64 || e > data.end() as u64){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        // The section table is put back with fewer sections, the rest of its space isn't used
+        let new_table = table.truncated(data.start, 0).to_bytes();
+        head[TABLE_START..TABLE_START + new_table.len()].copy_from_slice(&new_table);
+        head[TABLE_START + new_table.len()..table_end as usize].fill(0);
+        let header = part_file(&head, lut.start, None)?;
+        Ok(Self{
+            source,
+            head,
+            lut_start: lut.start,
+            ranges,
+            header,
+            blocks: (0..LUT_SIZE).map(|_| OnceLock::new()).collect(),
+        })
+    }
+
+    /// File format version
+    pub fn version(&self) -> u32{
+        self.header.version()
+    }
+
+    /// Date of the most recently introduced postcode, as a unix timestamp
+    pub fn last_update(&self) -> u64{
+        self.header.last_update()
+    }
+
+    /// Which country's codes the file has
+    pub fn scheme(&self) -> Scheme{
+        self.header.scheme()
+    }
+
+    /// Number of prefix blocks that have been read so far
+    pub fn blocks_read(&self) -> usize{
+        self.blocks.iter().filter(|b| b.get().is_some()).count()
+    }
+
+    /// Look up a postcode, or an outward code by itself, in any case and spacing, reading its
+    /// prefix block if it hasn't been read yet. `Ok(None)` if it isn't in the file, or isn't a
+    /// postcode at all.
+    pub fn lookup(&self, postcode: &str) -> Result<Option<Point>, PostcodeError>{
+        let Ok(canonical) = self.scheme().codec().format(postcode) else{
+            return Ok(None);
+        };
+        let Some(index) = lut_index(canonical.as_bytes()) else{
+            return Ok(None);
+        };
+        let block = match self.blocks[index].get(){
+            Some(block) => block,
+            None => {
+                let (start, end) = self.ranges[index];
+                let block = self.source.read_range(start, end)?;
+                if block.len() as u64 != end - start{
+                    return Err(PostcodeError::Corrupt("postcode data"));
+                }
+                // Another thread may have read it first, which is the same
+                let _ = self.blocks[index].set(block);
+                self.blocks[index].get().unwrap()
+            }
+        };
+        Ok(part_file(&self.head, self.lut_start, Some((index, block)))?.lookup_canonical(&canonical))
+    }
+}
+
+/// A small pack file of the start of a file and one of its blocks, or none
+fn part_file(head: &[u8], lut_start: usize, block: Option<(usize, &[u8])>) -> Result<Reader, PostcodeError>{
+    let data_start = head.len();
+    let data_len = block.map_or(0, |(_, b)| b.len());
+    let mut file = Vec::with_capacity(data_start + data_len + 4);
+    file.extend_from_slice(head);
+    for i in 0..LUT_SIZE{
+        let end = if block.is_some_and(|(index, _)| index == i) { data_start + data_len } else { data_start };
+        let entry = lut_start + (i*8);
+        file[entry..entry+4].copy_from_slice(&(data_start as u32).to_le_bytes());
+        file[entry+4..entry+8].copy_from_slice(&(end as u32).to_le_bytes());
+    }
+    // The postcode data's length in the table, and the file's checksum, which isn't checked
+    let table = SectionTable::from_bytes(&file, usize::MAX)?.truncated(data_start, data_len).to_bytes();
+    file[TABLE_START..TABLE_START + table.len()].copy_from_slice(&table);
+    if let Some((_, b)) = block{
+        file.extend_from_slice(b);
+    }
+    file.extend_from_slice(&[0; 4]);
+    Reader::from_unchecked(file)
+}
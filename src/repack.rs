@@ -0,0 +1,194 @@
+/*
+
+Making new pack files from existing ones, without the input files they were made from.
+
+The postcodes are read back from each pack file and packed again, so their locations are
+quantized twice: once in the old file's bounding box and again in the new one's. Each location
+can move by up to a step of each, which is a few metres at the default resolution.
+
+The new file has each optional section that any of the old ones had, in the newest format
+version of any of them. It has every attribute that any of them had, and postcodes from a file
+without one of them have no value for it. Signatures can't be carried over, so the new file is not signed.
+From version 23, the new file's metadata names this packer as its generator, and the sources of
+the old files as its source.
+
+Any pack file can be upgraded to the newest format version the same way, so that files made
+with an older packer can have the newer sections (a k-d tree index, say) without the CSV file.
+
+A layered pack is a full pack file and a small "layer 0" file with one postcode from each sector,
+for a web page to load first. Any postcode can be looked up in layer 0 approximately, at the
+location of the one postcode from its sector, while the full file downloads.
+
+*/
+use std::collections::BTreeMap;
+
+use crate::{Reader, Packer, Compression, PostcodeError, PostcodeInfo, Point, Metadata, Scheme, DEFAULT_VERSION, NEWEST_VERSION, DEFAULT_RESOLUTION, has_prefix, distance_m, RecordOrder, Crs};
+use crate::outlier::MAX_OUTLIERS;
+use crate::spatial::IndexKind;
+use crate::region::Region;
+
+/// Combine several pack files in to one, e.g. files that were built separately for each nation.
+///
+/// Where a postcode is in more than one file, the entry from the first of them is kept. Returns
+/// the packer, ready to write, and the number of postcodes that were left out because they were
+/// in an earlier file. The files must all have the same postcode scheme and coordinate reference
+/// system.
+pub fn merge(readers: &[Reader]) -> Result<(Packer, usize), PostcodeError>{
+    let scheme = readers.first().map(|r| r.scheme()).unwrap_or_default();
+    if readers.iter().any(|r| r.scheme() != scheme){
+        return Err(PostcodeError::IncompatibleOptions("pack files with different postcode schemes can't be merged"));
+    }
+    let crs = readers.first().map(|r| r.crs()).unwrap_or_default();
+    if readers.iter().any(|r| r.crs() != crs){
+        return Err(PostcodeError::IncompatibleOptions("pack files in different coordinate reference systems can't be merged"));
+    }
+    let names = attribute_names(readers);
+    let mut postcodes: Vec<PostcodeInfo> = readers.iter()
+        .flat_map(|r| {
+            // Where each of the new file's attributes is in this file's
+            let columns: Vec<Option<usize>> = names.iter()
+                .map(|n| r.attribute_names().iter().position(|m| m == n))
+                .collect();
+            r.iter().map(move |mut p| {
+                p.attributes = columns.iter().map(|c| c.and_then(|c| p.attributes.get(c).cloned()).unwrap_or_default()).collect();
+                p
+            })
+        })
+        .filter(|p| !p.is_partial)
+        .collect();
+    let total = postcodes.len();
+    // The sort is stable, so the first file's entry comes first
+    postcodes.sort_by(|a,b| a.postcode.cmp(&b.postcode));
+    postcodes.dedup_by(|a,b| a.postcode == b.postcode);
+    let duplicates = total - postcodes.len();
+    let last_update = readers.iter().map(|r| r.last_update()).max().unwrap_or(0);
+    let mut packer = Packer::from_postcodes(postcodes, last_update, scheme);
+    carry_options(&mut packer, readers);
+    Ok((packer, duplicates))
+}
+
+/// Make a smaller pack file with only some of the postcodes of another one, e.g. just London.
+///
+/// Postcodes are kept if they are inside `bbox` (lower left and upper right corners, as longitude
+/// and latitude) and start with one of `prefixes`. Either can be left out to keep everything. A
+/// prefix of only letters is a whole postcode area, so "E" is E1 to E20 but not EC or EH.
+pub fn extract(reader: &Reader, bbox: Option<(Point, Point)>, prefixes: &[&str]) -> Packer{
+    let prefixes: Vec<String> = prefixes.iter().map(|p| p.to_ascii_uppercase()).collect();
+    let postcodes = reader.iter()
+        .filter(|p| bbox.is_none_or(|(minll, maxll)| in_bbox(p.location, minll, maxll)))
+        .filter(|p| prefixes.is_empty() || prefixes.iter().any(|prefix| has_prefix(&p.postcode, prefix)));
+    let mut packer = Packer::from_postcodes(postcodes, reader.last_update(), reader.scheme());
+    carry_options(&mut packer, std::slice::from_ref(reader));
+    packer
+}
+
+/// Make a smaller pack file with only the postcodes inside a region, e.g. a local authority
+pub fn extract_region(reader: &Reader, region: &Region) -> Packer{
+    let mut packer = Packer::from_postcodes(reader.iter().filter(|p| region.contains(&p.location)), reader.last_update(), reader.scheme());
+    carry_options(&mut packer, std::slice::from_ref(reader));
+    packer
+}
+
+/// Rewrite a pack file of any version in the newest format version, with every optional section
+/// that it had. More sections can be turned on in the packer before it's written.
+pub fn upgrade(reader: &Reader) -> Packer{
+    let mut packer = Packer::from_postcodes(reader.iter(), reader.last_update(), reader.scheme());
+    carry_options_in(&mut packer, std::slice::from_ref(reader), NEWEST_VERSION);
+    packer
+}
+
+/// Make the layer 0 file of a layered pack, with one postcode from each sector (e.g. "SW1A 2"):
+/// the one nearest the average location of the sector's postcodes. Terminated postcodes are
+/// left out, and the outward codes are at the average location of the postcodes that are kept.
+pub fn sample_sectors(reader: &Reader) -> Packer{
+    // The sector is the outward code (padded) and the first char of the inward code, or the
+    // routing key and the first char of the unique identifier of an Eircode
+    let sector_len = match reader.scheme(){
+        Scheme::Uk => 5,
+        _ => 4,
+    };
+    let mut sectors: BTreeMap<String, Vec<PostcodeInfo>> = BTreeMap::new();
+    for p in reader.iter().filter(|p| !p.is_partial && p.terminated.is_none()){
+        sectors.entry(p.postcode[0..sector_len].to_string()).or_default().push(p);
+    }
+    let postcodes = sectors.into_values().map(|mut postcodes| {
+        let n = postcodes.len() as f64;
+        let average = Point{
+            x: postcodes.iter().map(|p| p.location.x).sum::<f64>() / n,
+            y: postcodes.iter().map(|p| p.location.y).sum::<f64>() / n,
+        };
+        // Ties go to the first, in postcode order
+        let nearest = (0..postcodes.len())
+            .min_by(|&a, &b| distance_m(&postcodes[a].location, &average).total_cmp(&distance_m(&postcodes[b].location, &average)))
+            .unwrap();
+        postcodes.swap_remove(nearest)
+    });
+    let mut packer = Packer::from_postcodes(postcodes, reader.last_update(), reader.scheme());
+    carry_options(&mut packer, std::slice::from_ref(reader));
+    packer
+}
+
+fn in_bbox(p: Point, minll: Point, maxll: Point) -> bool{
+    p.x >= minll.x && p.x <= maxll.x && p.y >= minll.y && p.y <= maxll.y
+}
+
+/// Every attribute that any of the files has, in the order they first appear
+fn attribute_names(readers: &[Reader]) -> Vec<String>{
+    let mut names: Vec<String> = Vec::new();
+    for name in readers.iter().flat_map(|r| r.attribute_names()){
+        if !names.iter().any(|n| n == name){
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Set up the packer to write everything that any of the old files had, in the newest version of
+/// any of them
+fn carry_options(packer: &mut Packer, readers: &[Reader]){
+    let version = readers.iter().map(|r| r.version()).max().unwrap_or(DEFAULT_VERSION).max(DEFAULT_VERSION);
+    carry_options_in(packer, readers, version);
+}
+
+/// Set up the packer to write everything that any of the old files had, in a version at least as
+/// new as any of them
+fn carry_options_in(packer: &mut Packer, readers: &[Reader], version: u32){
+    // Only versions 1 to 26 can be read, so this is always writable
+    packer.set_format_version(version).expect("readable version that can't be written");
+    // Compressed files are version 4 to 8, the chunked layout from version 9 can't be compressed
+    if version < 9{
+        let compression = readers.iter().map(|r| r.compression()).find(|&c| c != Compression::None);
+        packer.set_compression(compression.unwrap_or_default());
+    }
+    packer.set_spatial_index(readers.iter().any(|r| r.has_spatial_index()));
+    if let Some(kind) = readers.iter().filter_map(|r| r.index_kind()).find(|&k| k != IndexKind::Grid){
+        packer.set_index_kind(kind);
+    }
+    packer.set_countries(readers.iter().any(|r| r.country_runs().is_some()));
+    packer.set_centroids(readers.iter().any(|r| r.centroid_counts().is_some()));
+    packer.set_attributes(attribute_names(readers));
+    packer.set_prefix_extents(readers.iter().any(|r| r.prefix_extent_count().is_some()));
+    packer.set_sector_bitmap(readers.iter().any(|r| r.sector_bitmap_counts().is_some()));
+    packer.set_release_date(readers.iter().filter_map(|r| r.release_date()).max());
+    if version >= 23{
+        let mut metadata = Metadata::generated();
+        let sources: Vec<&str> = readers.iter().filter_map(|r| r.metadata().get("source")).collect();
+        if !sources.is_empty(){
+            metadata.set("source", &sources.join(", "));
+        }
+        packer.set_metadata(metadata);
+    }
+    packer.set_chunk_size(readers.iter().filter_map(|r| r.chunk_size()).max().unwrap_or(0));
+    packer.set_entropy(readers.iter().any(|r| r.entropy_coded()));
+    packer.set_order(readers.iter().map(|r| r.record_order()).find(|&o| o != RecordOrder::Alphabetical).unwrap_or_default());
+    packer.set_crs(readers.iter().map(|r| r.crs()).find(|&c| c != Crs::Wgs84).unwrap_or_default());
+    // Coordinate groups do the same job as local bounding boxes and outliers, so they win
+    let groups = readers.iter().any(|r| !r.coordinate_groups().is_empty());
+    packer.set_groups(groups);
+    packer.set_local_bounds(!groups && readers.iter().any(|r| r.local_boxes() > 0));
+    // The outward codes outside the box go with their postcodes
+    let clip = readers.iter().map(|r| r.outliers().iter().filter(|(p, _)| !p.ends_with("   ")).count()).max().unwrap_or(0);
+    packer.set_clip_bbox(if groups { 0 } else { clip.min(MAX_OUTLIERS) }).expect("too many outliers to clip");
+    let resolution = readers.iter().map(|r| r.resolution()).max().unwrap_or(DEFAULT_RESOLUTION);
+    packer.set_resolution(resolution).expect("resolution of a pack file that can't be written");
+}
@@ -0,0 +1,197 @@
+/*
+
+Postcode schemes, from format version 17 onwards.
+
+The pack format was made for UK postcodes, but all it needs from a code is a two char prefix for
+the quick lookup table, the rest of the code packed in to 24 bits, and an outward part (the
+district, or whatever the country calls it) to place the partial entries at. A `CodeCodec` does
+that for one country's codes, and the header says which one the file uses. Files before version
+17 are always UK postcodes.
+
+Canonical codes are always 7 chars, and outward codes are padded to 4 chars, so that the rest of
+the packer doesn't need to know which scheme it's dealing with. The outward codes are packed the
+same way for every scheme (see `pack_outward_code`).
+
+Eircodes (Ireland) are a three char routing key, a letter and two digits (or D6W), followed by a
+four char unique identifier. Canonical Eircodes have no space, e.g. A65F4E2. The unique
+identifier only uses digits and 15 of the letters, so the last five chars fit in 24 bits as
+11 * 25^4 = 4296875 values.
+
+*/
+use std::fmt::Display;
+use std::fmt::Formatter;
+use crate::{PostcodeError, pack_code, unpack_code, format_postcode, normalize_postcode};
+
+/// How the codes of one country are checked and packed
+pub trait CodeCodec: Send + Sync{
+    /// Canonical form of a full code or an outward code by itself, as typed by a user
+    fn format(&self, code: &str) -> Result<String, PostcodeError>;
+
+    /// Canonical form of a full code from an input file, `None` if it isn't one that can be packed
+    fn normalize(&self, code: &str) -> Option<String>;
+
+    /// Pack a canonical full code, without its first two chars
+    fn encode(&self, code: &str) -> Result<[u8;3], PostcodeError>;
+
+    /// Reverse of `encode`, needs the first two chars of the code since they are not packed
+    fn decode(&self, prefix: &str, code: u32) -> String;
+
+    /// The outward part of a canonical full code, without padding
+    fn prefix<'a>(&self, code: &'a str) -> &'a str;
+}
+
+/// UK postcodes, e.g. SW1A 1AA
+pub struct UkPostcode;
+
+impl CodeCodec for UkPostcode{
+    fn format(&self, code: &str) -> Result<String, PostcodeError>{
+        format_postcode(code)
+    }
+
+    fn normalize(&self, code: &str) -> Option<String>{
+        normalize_postcode(code).filter(|p| pack_code(p).is_ok())
+    }
+
+    fn encode(&self, code: &str) -> Result<[u8;3], PostcodeError>{
+        pack_code(code)
+    }
+
+    fn decode(&self, prefix: &str, code: u32) -> String{
+        unpack_code(prefix, code)
+    }
+
+    fn prefix<'a>(&self, code: &'a str) -> &'a str{
+        code[0..4].trim_end()
+    }
+}
+
+/// Irish Eircodes, e.g. A65 F4E2
+pub struct Eircode;
+
+/// Letters that start a routing key
+const EIRCODE_ROUTING_LETTERS: &[u8] = b"ACDEFHKNPRTVWXY";
+
+/// Third char of a routing key, only D6W has a letter
+const EIRCODE_ROUTING_LAST: &[u8] = b"0123456789W";
+
+/// Chars of the unique identifier, in ASCII order so that packed codes sort like the codes
+const EIRCODE_UNIQUE: &[u8] = b"0123456789ACDEFHKNPRTVWXY";
+
+fn position(chars: &[u8], c: u8) -> Result<u32, PostcodeError>{
+    chars.iter().position(|&x| x == c).map(|i| i as u32).ok_or(PostcodeError::InvalidFormat())
+}
+
+impl Eircode{
+    /// Check a routing key, which must already be upper case
+    fn check_routing_key(key: &[u8]) -> Result<(), PostcodeError>{
+        match key{
+            [b'D', b'6', b'W'] => Ok(()),
+            [a, b, c] if EIRCODE_ROUTING_LETTERS.contains(a) && b.is_ascii_digit() && c.is_ascii_digit() => Ok(()),
+            _ => Err(PostcodeError::InvalidFormat()),
+        }
+    }
+}
+
+impl CodeCodec for Eircode{
+    fn format(&self, code: &str) -> Result<String, PostcodeError>{
+        if !code.chars().all(|c| c == ' ' || c.is_ascii_alphanumeric()){
+            return Err(PostcodeError::InvalidFormat());
+        }
+        let code: String = code.chars().filter(|c| *c != ' ').map(|c| c.to_ascii_uppercase()).collect();
+        Self::check_routing_key(code.as_bytes().get(0..3).ok_or(PostcodeError::InvalidFormat())?)?;
+        match code.len(){
+            // Just the routing key
+            3 => Ok(format!("{code} ")),
+            7 => {
+                for &c in &code.as_bytes()[3..]{
+                    position(EIRCODE_UNIQUE, c)?;
+                }
+                Ok(code)
+            },
+            _ => Err(PostcodeError::InvalidFormat()),
+        }
+    }
+
+    fn normalize(&self, code: &str) -> Option<String>{
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != 7{
+            return None;
+        }
+        self.format(&code).ok()
+    }
+
+    fn encode(&self, code: &str) -> Result<[u8;3], PostcodeError>{
+        let code = code.as_bytes();
+        if code.len() < 7{
+            return Err(PostcodeError::InvalidFormat());
+        }
+        let mut encoded = position(EIRCODE_ROUTING_LAST, code[2])?;
+        for &c in &code[3..7]{
+            encoded = (encoded * 25) + position(EIRCODE_UNIQUE, c)?;
+        }
+        let encoded = encoded.to_le_bytes();
+        Ok([encoded[0], encoded[1], encoded[2]])
+    }
+
+    fn decode(&self, prefix: &str, mut code: u32) -> String{
+        let mut unique = [0u8;4];
+        for c in unique.iter_mut().rev(){
+            *c = EIRCODE_UNIQUE[(code % 25) as usize];
+            code /= 25;
+        }
+        let last = EIRCODE_ROUTING_LAST.get(code as usize).copied().unwrap_or(b'?');
+        format!("{}{}{}", prefix, last as char, String::from_utf8_lossy(&unique))
+    }
+
+    fn prefix<'a>(&self, code: &'a str) -> &'a str{
+        &code[0..3]
+    }
+}
+
+/// Which kind of codes a pack file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheme{
+    #[default]
+    Uk,
+    Eircode,
+}
+
+impl Scheme{
+    pub const ALL: [Scheme;2] = [Scheme::Uk, Scheme::Eircode];
+
+    /// Value stored in the file to identify the scheme
+    pub fn id(&self) -> u32{
+        match self{
+            Scheme::Uk => 0,
+            Scheme::Eircode => 1,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self>{
+        Self::ALL.into_iter().find(|s| s.id() == id)
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            Scheme::Uk => "uk",
+            Scheme::Eircode => "eircode",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self>{
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    pub fn codec(&self) -> &'static dyn CodeCodec{
+        match self{
+            Scheme::Uk => &UkPostcode,
+            Scheme::Eircode => &Eircode,
+        }
+    }
+}
+
+impl Display for Scheme{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
@@ -0,0 +1,334 @@
+/*
+
+The section table, from format version 26 onwards.
+
+Up to version 25, each new feature added a field at a fixed place in the header, or a length
+there and a section at the end of the file, so every version moved everything after it and a
+reader had to know every version before its own. From version 26 the header is followed by a
+table of sections, and each part of the file is a section that the table gives the place of.
+A new optional section can be added without a new format version, and a reader skips the kinds
+that it doesn't know. A section that changes how the rest of the file is read is marked as
+required, so that a reader that doesn't know it refuses the file instead of misreading it.
+
+Sections that would be empty are left out, and a reader uses the default for them (16 bits per
+axis, UK postcodes, no entropy code, and so on). The bounding box, quick lookup table and
+postcode data are always there. The packer writes the small sections first, then the quick
+lookup table and the postcode data, then the sections from the sector bitmap on, so that
+everything needed to read any block is at the start of the file. Readers only go by the table.
+
+Section table, 4 + count*16 bytes (after the 16 byte header):
+
+    count: 4 bytes (u32, number of sections)
+    sections: count of these
+        kind:   2 bytes (u16, see `SectionKind`)
+        flags:  2 bytes (u16, bit 0 is set if the section is required, the others are 0)
+        offset: 4 bytes (u32, absolute position in the file)
+        length: 4 bytes (u32)
+        crc:    4 bytes (u32, CRC32 of the section)
+
+The sections follow the table, and the file ends with the CRC32 of everything before it.
+
+Kinds of section, required:
+
+    1  bounds:         16 bytes, the bounding box as in version 3 onwards
+    2  lookup table:   26*36*8 bytes, absolute byte ranges as in version 9 onwards
+    3  postcode data:  as in version 17 onwards
+    4  resolution:     4 bytes (u32)
+    5  scheme:         4 bytes (u32)
+    6  entropy code:   256 bytes, see entropy.rs
+    7  local boxes:    10 bytes each, see local.rs
+    8  outliers:       see outlier.rs
+    9  groups:         see group.rs
+    10 crs:            4 bytes (u32, see crs.rs, left out for WGS84)
+
+Optional:
+
+    16 release date:   8 bytes (u64)
+    17 metadata:       the fields, without their length, see metadata.rs
+    18 key id:         8 bytes, see signature.rs
+    19 chunk size:     4 bytes (u32)
+    20 sector bitmap:  see sector.rs
+    21 prefix extents: see extent.rs
+    22 attributes:     see attribute.rs
+    23 centroids:      see centroid.rs
+    24 countries:      see country.rs
+    25 grid index:     see spatial.rs
+    26 k-d tree index: see kdtree.rs
+    27 geohash index:  see geohash.rs
+    28 record order:   4 bytes (u32, see order.rs, left out for alphabetical order)
+
+*/
+use crate::{PostcodeError, crc32};
+use crate::spatial::IndexKind;
+
+pub(crate) const TABLE_START: usize = 16;
+const ENTRY_LEN: usize = 16;
+const REQUIRED: u16 = 1;
+
+/// The kinds of section that this version knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SectionKind{
+    Bounds,
+    LookupTable,
+    PostcodeData,
+    Resolution,
+    Scheme,
+    EntropyCode,
+    LocalBoxes,
+    Outliers,
+    Groups,
+    Crs,
+    ReleaseDate,
+    Metadata,
+    KeyId,
+    ChunkSize,
+    SectorBitmap,
+    PrefixExtents,
+    Attributes,
+    Centroids,
+    Countries,
+    Index(IndexKind),
+    RecordOrder,
+}
+
+impl SectionKind{
+    pub fn id(&self) -> u16{
+        use SectionKind::*;
+        match self{
+            Bounds => 1,
+            LookupTable => 2,
+            PostcodeData => 3,
+            Resolution => 4,
+            Scheme => 5,
+            EntropyCode => 6,
+            LocalBoxes => 7,
+            Outliers => 8,
+            Groups => 9,
+            Crs => 10,
+            ReleaseDate => 16,
+            Metadata => 17,
+            KeyId => 18,
+            ChunkSize => 19,
+            SectorBitmap => 20,
+            PrefixExtents => 21,
+            Attributes => 22,
+            Centroids => 23,
+            Countries => 24,
+            Index(kind) => 25 + kind.id() as u16,
+            RecordOrder => 28,
+        }
+    }
+
+    /// The precision of a geohash index is in the index section, so this has the default
+    pub fn from_id(id: u16) -> Option<Self>{
+        use SectionKind::*;
+        Some(match id{
+            1 => Bounds,
+            2 => LookupTable,
+            3 => PostcodeData,
+            4 => Resolution,
+            5 => Scheme,
+            6 => EntropyCode,
+            7 => LocalBoxes,
+            8 => Outliers,
+            9 => Groups,
+            10 => Crs,
+            16 => ReleaseDate,
+            17 => Metadata,
+            18 => KeyId,
+            19 => ChunkSize,
+            20 => SectorBitmap,
+            21 => PrefixExtents,
+            22 => Attributes,
+            23 => Centroids,
+            24 => Countries,
+            28 => RecordOrder,
+            25.. => Index(IndexKind::from_id((id - 25) as u32)?),
+            _ => return None,
+        })
+    }
+
+    /// Whether a reader that doesn't know this kind would read the file wrongly without it
+    #[cfg(feature="packer")]
+    pub fn required(&self) -> bool{
+        self.id() < 16
+    }
+
+    /// How `Reader::check_checksums` and inspect name it
+    pub fn name(&self) -> &'static str{
+        use SectionKind::*;
+        match self{
+            Bounds => "bounding box",
+            LookupTable => "quick lookup table",
+            PostcodeData => "postcode data",
+            Resolution => "resolution",
+            Scheme => "scheme",
+            EntropyCode => "entropy code",
+            LocalBoxes => "local bounding boxes",
+            Outliers => "outliers",
+            Groups => "coordinate groups",
+            ReleaseDate => "release date",
+            Metadata => "metadata",
+            KeyId => "signing key",
+            ChunkSize => "chunk size",
+            SectorBitmap => "sector bitmap",
+            PrefixExtents => "prefix extents",
+            Attributes => "attributes",
+            Centroids => "centroids",
+            Countries => "countries",
+            Index(_) => "spatial index",
+            RecordOrder => "record order",
+            Crs => "crs",
+        }
+    }
+}
+
+/// One entry in the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Section{
+    pub id: u16,
+    pub required: bool,
+    pub start: usize,
+    pub len: usize,
+    pub crc: u32,
+}
+
+impl Section{
+    pub fn kind(&self) -> Option<SectionKind>{
+        SectionKind::from_id(self.id)
+    }
+
+    pub fn end(&self) -> usize{
+        self.start + self.len
+    }
+}
+
+/// The section table of a version 26 file
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SectionTable{
+    sections: Vec<Section>,
+}
+
+impl SectionTable{
+    /// Read the table from a whole file, or from the start of one up to the end of the table.
+    /// Every section must be within `file_len` bytes, less the file's checksum.
+    pub fn from_bytes(data: &[u8], file_len: usize) -> Result<Self, PostcodeError>{
+        let u32_at = |i: usize| data.get(i..i+4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let count = u32_at(TABLE_START).ok_or(PostcodeError::NotAPackFile())? as usize;
+        let table_end = count.checked_mul(ENTRY_LEN).and_then(|l| l.checked_add(TABLE_START + 4)).ok_or(PostcodeError::NotAPackFile())?;
+        let entries = data.get(TABLE_START+4..table_end).ok_or(PostcodeError::NotAPackFile())?;
+        let body_end = file_len.checked_sub(4).ok_or(PostcodeError::NotAPackFile())?;
+        let mut sections: Vec<Section> = Vec::with_capacity(count);
+        for e in entries.chunks_exact(ENTRY_LEN){
+            let u = |i: usize| u32::from_le_bytes(e[i..i+4].try_into().unwrap());
+            let flags = u16::from_le_bytes([e[2], e[3]]);
+            let section = Section{
+                id: u16::from_le_bytes([e[0], e[1]]),
+                required: flags & REQUIRED != 0,
+                start: u(4) as usize,
+                len: u(8) as usize,
+                crc: u(12),
+            };
+            if section.start < table_end || section.end() > body_end || sections.iter().any(|s| s.id == section.id){
+                return Err(PostcodeError::NotAPackFile());
+            }
+            if section.kind().is_none() && section.required{
+                return Err(PostcodeError::UnsupportedSection(section.id));
+            }
+            sections.push(section);
+        }
+        let table = Self{sections};
+        if [SectionKind::Bounds, SectionKind::LookupTable, SectionKind::PostcodeData].iter().any(|&k| table.get(k).is_none()){
+            return Err(PostcodeError::NotAPackFile());
+        }
+        Ok(table)
+    }
+
+    /// The entry of a kind of section, `None` if the file doesn't have it
+    pub fn get(&self, kind: SectionKind) -> Option<&Section>{
+        self.sections.iter().find(|s| s.id == kind.id())
+    }
+
+    /// The contents of a kind of section, `None` if the file doesn't have it
+    pub fn bytes<'a>(&self, data: &'a [u8], kind: SectionKind) -> Option<&'a [u8]>{
+        self.get(kind).and_then(|s| data.get(s.start..s.end()))
+    }
+
+    /// The spatial index entry, whichever kind it is
+    pub fn index(&self) -> Option<(IndexKind, &Section)>{
+        self.sections.iter().find_map(|s| match s.kind(){
+            Some(SectionKind::Index(kind)) => Some((kind, s)),
+            _ => None,
+        })
+    }
+
+    /// Every entry, in the order of the table
+    pub fn iter(&self) -> impl Iterator<Item=&Section> + '_{
+        self.sections.iter()
+    }
+
+    /// The sections that end by `end`, and the postcode data with a new length, for a file made
+    /// of the start of another and some of its postcode data (see remote.rs)
+    pub fn truncated(&self, end: usize, data_len: usize) -> Self{
+        let data_id = SectionKind::PostcodeData.id();
+        let sections = self.sections.iter()
+            .filter(|s| s.id == data_id || s.end() <= end)
+            .map(|s| if s.id == data_id { Section{len: data_len, ..*s} } else { *s })
+            .collect();
+        Self{sections}
+    }
+
+    /// The table as it is in the file, with its count
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(table_len(self.sections.len()));
+        out.extend_from_slice(&(self.sections.len() as u32).to_le_bytes());
+        for s in &self.sections{
+            let flags = if s.required { REQUIRED } else { 0 };
+            out.extend_from_slice(&s.id.to_le_bytes());
+            out.extend_from_slice(&flags.to_le_bytes());
+            out.extend_from_slice(&(s.start as u32).to_le_bytes());
+            out.extend_from_slice(&(s.len as u32).to_le_bytes());
+            out.extend_from_slice(&s.crc.to_le_bytes());
+        }
+        out
+    }
+
+    /// Check the checksum of the whole file, and if it doesn't match, find which section is
+    /// damaged
+    pub fn check(&self, data: &[u8]) -> Result<(), PostcodeError>{
+        let body_end = data.len().checked_sub(4).ok_or(PostcodeError::Corrupt("file"))?;
+        if crc32(&data[..body_end]) == u32::from_le_bytes(data[body_end..].try_into().unwrap()){
+            return Ok(());
+        }
+        for s in &self.sections{
+            let section = data.get(s.start..s.end()).ok_or(PostcodeError::Corrupt("file"))?;
+            if crc32(section) != s.crc{
+                return Err(PostcodeError::Corrupt(s.kind().map(|k| k.name()).unwrap_or("file")));
+            }
+        }
+        Err(PostcodeError::Corrupt("header"))
+    }
+}
+
+/// Length of the table of `count` sections, including the count
+pub(crate) fn table_len(count: usize) -> usize{
+    4 + (count * ENTRY_LEN)
+}
+
+/// The section table for these sections, which are written in this order straight after it
+#[cfg(feature="packer")]
+pub(crate) fn write_table(sections: &[(SectionKind, &[u8])]) -> Vec<u8>{
+    let mut out = Vec::with_capacity(table_len(sections.len()));
+    out.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+    let mut pos = TABLE_START + table_len(sections.len());
+    for (kind, contents) in sections{
+        let flags = if kind.required() { REQUIRED } else { 0 };
+        out.extend_from_slice(&kind.id().to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&(pos as u32).to_le_bytes());
+        out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        out.extend_from_slice(&crc32(contents).to_le_bytes());
+        pos += contents.len();
+    }
+    out
+}
@@ -0,0 +1,171 @@
+/*
+
+Which outward codes and sectors exist, from format version 21 onwards.
+
+So that a form can check and complete a postcode as it is typed, the packer can store a list of
+the outward codes that have postcodes, and for each one a bitmap of its sectors. A sector is the
+outward code and the first char of the inward code, e.g. "SW1A 2". Eircodes don't have sectors,
+so the first char of the unique identifier is used instead, e.g. "A65 F". Terminated postcodes
+are not counted.
+
+Sector bitmap section, variable length (before the prefix extents section):
+
+    count: 4 bytes (u32, number of outward codes)
+    outward codes: count of these, sorted
+        outward: 4 bytes (as in a canonical postcode, e.g. "B1  ", or "D6W " for an Eircode)
+        sectors: 4 bytes (u32, bit n is set if the sector whose char is at position n of
+                 "0123456789ACDEFHKNPRTVWXY" exists, so UK sectors are bits 0 to 9)
+
+*/
+#[cfg(feature="packer")]
+use std::collections::BTreeMap;
+
+#[cfg(feature="packer")]
+use crate::{PostcodeInfo, Scheme};
+
+const ENTRY_LEN: usize = 8;
+
+/// Chars that a sector can end with, in the order of the bits
+const SECTOR_CHARS: &[u8] = b"0123456789ACDEFHKNPRTVWXY";
+
+/// Bitmap of the sectors of each outward code, in order
+#[cfg(feature="packer")]
+pub(crate) struct SectorTotals{
+    outward_codes: BTreeMap<String, u32>,
+    scheme: Scheme,
+}
+
+#[cfg(feature="packer")]
+impl SectorTotals{
+    pub fn new(scheme: Scheme) -> Self{
+        Self{outward_codes: BTreeMap::new(), scheme}
+    }
+
+    pub fn add(&mut self, p: &PostcodeInfo){
+        if !p.is_partial && p.terminated.is_none(){
+            self.add_code(&p.postcode);
+        }
+    }
+
+    /// Add a canonical full code, that is not terminated
+    pub fn add_code(&mut self, postcode: &str){
+        let outward = self.scheme.codec().prefix(postcode);
+        // The inward code is after the padding of a UK outward code, and straight after an
+        // Eircode's routing key
+        let inward = match self.scheme{
+            Scheme::Uk => &postcode[4..],
+            _ => &postcode[outward.len()..],
+        };
+        let bit = inward.bytes().next().and_then(sector_bit);
+        let sectors = self.outward_codes.entry(format!("{outward:<4}")).or_insert(0);
+        if let Some(bit) = bit{
+            *sectors |= 1 << bit;
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(4 + (self.outward_codes.len() * ENTRY_LEN));
+        out.extend_from_slice(&(self.outward_codes.len() as u32).to_le_bytes());
+        for (outward, sectors) in &self.outward_codes{
+            out.extend_from_slice(outward.as_bytes());
+            out.extend_from_slice(&sectors.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// A sector bitmap section read from a pack file
+#[derive(Debug, Clone)]
+pub(crate) struct SectorBitmap{
+    data: Vec<u8>,
+}
+
+impl SectorBitmap{
+    pub fn from_bytes(data: &[u8]) -> Option<Self>{
+        let count = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+        if count.checked_mul(ENTRY_LEN)?.checked_add(4)? != data.len(){
+            return None;
+        }
+        let entries: Vec<&[u8]> = data[4..].chunks_exact(ENTRY_LEN).collect();
+        if !entries.iter().all(|e| e[0..4].is_ascii()) || !entries.windows(2).all(|w| w[0][0..4] < w[1][0..4]){
+            return None;
+        }
+        Some(Self{data: data.to_vec()})
+    }
+
+    /// Number of outward codes
+    pub fn len(&self) -> usize{
+        (self.data.len() - 4) / ENTRY_LEN
+    }
+
+    /// Number of sectors, in every outward code
+    pub fn sector_count(&self) -> usize{
+        self.iter().map(|(_, sectors)| sectors.count_ones() as usize).sum()
+    }
+
+    /// Outward code (without padding) and sector bitmap of every entry, in order
+    pub fn iter(&self) -> impl Iterator<Item=(&str, u32)> + '_{
+        self.data[4..].chunks_exact(ENTRY_LEN).map(|e| (
+            std::str::from_utf8(&e[0..4]).unwrap().trim_end(),
+            u32::from_le_bytes(e[4..8].try_into().unwrap()),
+        ))
+    }
+
+    /// Sector bitmap of an outward code, in any case, `None` if it doesn't exist
+    pub fn get(&self, outward: &str) -> Option<u32>{
+        let key = format!("{:<4}", outward.to_ascii_uppercase());
+        let entries: Vec<&[u8]> = self.data[4..].chunks_exact(ENTRY_LEN).collect();
+        let i = entries.binary_search_by(|e| e[0..4].cmp(key.as_bytes())).ok()?;
+        Some(u32::from_le_bytes(entries[i][4..8].try_into().unwrap()))
+    }
+
+    /// Whether an outward code, e.g. "SW1A", or a sector, e.g. "SW1A 2", exists. The outward
+    /// code and the sector's char must be separated by a space.
+    pub fn exists(&self, text: &str) -> bool{
+        let text = text.trim();
+        let (outward, sector) = match text.split_once(char::is_whitespace){
+            Some((outward, sector)) => (outward, Some(sector.trim_start())),
+            None => (text, None),
+        };
+        let Some(sectors) = self.get(outward) else{
+            return false;
+        };
+        match sector{
+            None => true,
+            Some(sector) => sector.len() == 1 && sector_bit(sector.as_bytes()[0]).is_some_and(|bit| sectors & (1 << bit) != 0),
+        }
+    }
+
+    /// Outward codes and sectors that start with what has been typed so far, in order, e.g.
+    /// "SW1" gives "SW1A", "SW1A 0", "SW1A 1", "SW1A 2", "SW1E" and so on. Spaces are ignored,
+    /// except that if there is one, only the sectors of the outward code before it are listed.
+    pub fn complete(&self, text: &str) -> Vec<String>{
+        // A space at the end still means that the outward code has been typed
+        let text = text.trim_start().to_ascii_uppercase();
+        let outward_only = text.split_once(char::is_whitespace).map(|(outward, _)| outward);
+        let typed: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut found = Vec::new();
+        for (outward, sectors) in self.iter(){
+            if outward_only.is_some_and(|o| o != outward){
+                continue;
+            }
+            if outward_only.is_none() && outward.starts_with(&typed){
+                found.push(outward.to_string());
+            }
+            // Sectors are only listed once the whole outward code has been typed
+            let Some(rest) = typed.strip_prefix(outward) else{
+                continue;
+            };
+            for (bit, &c) in SECTOR_CHARS.iter().enumerate(){
+                if sectors & (1 << bit) != 0 && (rest.is_empty() || rest.as_bytes() == [c]){
+                    found.push(format!("{outward} {}", c as char));
+                }
+            }
+        }
+        found
+    }
+}
+
+fn sector_bit(c: u8) -> Option<usize>{
+    SECTOR_CHARS.iter().position(|&s| s == c.to_ascii_uppercase())
+}
@@ -0,0 +1,132 @@
+/*
+
+A small HTTP server for looking up postcodes in a pack file, so that the data can be used from
+any language without the JS library or this crate.
+
+    GET /lookup/<postcode>              {"postcode": "SW1A1AA", "lat": 51.50101, "long": -0.14159, "country": "E92000001"}
+    GET /nearest?lat=<lat>&long=<long>  {"postcode": "SW1A1AA", "lat": 51.50101, "long": -0.14159, "country": "E92000001"}
+
+Postcodes can be in any case, with or without the space (as %20 or +). "country" is only there
+if the pack file has country codes. Errors are JSON too, e.g. {"error": "Postcode is well-formed,
+but not known"}, with status 400 for bad requests and 404 for unknown postcodes.
+
+The pack file is read once, and requests are handled by a fixed number of threads that share it.
+
+Only built with the `serve` feature, which is on by default.
+
+*/
+use crate::{PostcodeError, Point, Reader, Country, json_string};
+
+/// A response to one request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response{
+    pub status: u16,
+    /// JSON
+    pub body: String,
+}
+
+impl Response{
+    fn found(postcode: &str, location: &Point, country: Option<Country>) -> Response{
+        let country = country.map(|c| format!(", \"country\": {}", json_string(c.code()))).unwrap_or_default();
+        Response{
+            status: 200,
+            body: format!("{{\"postcode\": {}, \"lat\": {}, \"long\": {}{country}}}", json_string(postcode), location.y, location.x),
+        }
+    }
+
+    fn error(status: u16, why: &str) -> Response{
+        Response{status, body: format!("{{\"error\": {}}}", json_string(why))}
+    }
+}
+
+/// Decode %xx escapes, and + as a space
+fn percent_decode(s: &str) -> Option<String>{
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len(){
+        match bytes[i]{
+            b'%' => {
+                let hex = s.get(i+1..i+3)?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => { out.push(b' '); i += 1; }
+            b => { out.push(b); i += 1; }
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn lookup(reader: &Reader, postcode: &str) -> Response{
+    let Some(postcode) = percent_decode(postcode) else{
+        return Response::error(400, "Bad escape in postcode");
+    };
+    let canonical = match reader.scheme().codec().format(&postcode){
+        Ok(c) => c,
+        Err(e) => return Response::error(400, &e.to_string()),
+    };
+    match reader.lookup(&canonical){
+        Some(l) => Response::found(&canonical, &l, reader.country(&canonical)),
+        None => Response::error(404, &PostcodeError::NotFound().to_string()),
+    }
+}
+
+fn nearest(reader: &Reader, query: &str) -> Response{
+    let mut lat = None;
+    let mut long = None;
+    for pair in query.split('&'){
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value).and_then(|v| v.parse::<f64>().ok()).filter(|v| v.is_finite());
+        match name{
+            "lat" => lat = value,
+            "long" => long = value,
+            _ => {}
+        }
+    }
+    let (Some(lat), Some(long)) = (lat, long) else{
+        return Response::error(400, "lat and long must both be given, as numbers");
+    };
+    match reader.nearest(lat, long){
+        Some(p) => Response::found(&p.postcode, &p.location, p.country),
+        None => Response::error(404, "The pack file has no postcodes"),
+    }
+}
+
+/// Answer one request, given its method and URL (the path and query string)
+pub fn handle(reader: &Reader, method: &str, url: &str) -> Response{
+    if method != "GET"{
+        return Response::error(405, "Only GET requests are supported");
+    }
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    if let Some(postcode) = path.strip_prefix("/lookup/"){
+        return lookup(reader, postcode);
+    }
+    if path == "/nearest"{
+        return nearest(reader, query);
+    }
+    Response::error(404, "Unknown path, try /lookup/<postcode> or /nearest?lat=<lat>&long=<long>")
+}
+
+/// Serve requests on an address (e.g. "127.0.0.1:8080") until the process is stopped
+pub fn serve(reader: &Reader, address: &str, threads: usize) -> Result<(),PostcodeError>{
+    let server = tiny_http::Server::http(address).map_err(std::io::Error::other)?;
+    let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json").expect("Header is valid");
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1){
+            s.spawn(|| {
+                while let Ok(request) = server.recv(){
+                    let response = handle(reader, request.method().as_str(), request.url());
+                    log::debug!("{} {} {}", request.method(), request.url(), response.status);
+                    let http = tiny_http::Response::from_string(response.body)
+                        .with_status_code(response.status)
+                        .with_header(content_type.clone());
+                    if let Err(e) = request.respond(http){
+                        log::warn!("Could not send response: {e}");
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,82 @@
+/*
+
+Detached signatures for pack files.
+
+Pack files are often served from mirrors and CDNs, so a consumer may want to check that a file
+really came from whoever published it. The publisher signs the pack file with an Ed25519 key, and
+publishes the signature next to it. Consumers check it with the publisher's public key.
+
+Keys are read from PEM files, as written by `openssl genpkey -algorithm ed25519 -out key.pem`
+(the private key) and `openssl pkey -in key.pem -pubout -out key.pub.pem` (the public key).
+
+From version 11, the pack file header has the fingerprint of the key that signed it, so a
+consumer can tell which key to use, and that a file is meant to be signed at all. The signature
+covers the whole file, including the fingerprint.
+
+Signature file structure:
+(all numbers in little endian)
+
+    magic:     4 bytes "UKPS" - "UK Postcode Pack Signature"
+    key_id:    8 bytes (fingerprint of the key, see `key_id`)
+    signature: 64 bytes (Ed25519 signature of the whole pack file)
+
+Only built with the `sign` feature, which is on by default.
+
+*/
+use std::fs;
+use std::path::Path;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use sha2::{Digest, Sha256};
+
+use crate::{PostcodeError, Reader};
+
+const SIGNATURE_LEN: usize = 4 + 8 + 64;
+
+/// Read an Ed25519 private key from a PEM file
+pub fn read_signing_key(path: &Path) -> Result<SigningKey, PostcodeError>{
+    let pem = fs::read_to_string(path)?;
+    SigningKey::from_pkcs8_pem(&pem).map_err(|e| PostcodeError::BadKey(e.to_string()))
+}
+
+/// Read an Ed25519 public key from a PEM file. A private key can be used too.
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey, PostcodeError>{
+    let pem = fs::read_to_string(path)?;
+    if let Ok(key) = VerifyingKey::from_public_key_pem(&pem){
+        return Ok(key);
+    }
+    SigningKey::from_pkcs8_pem(&pem)
+        .map(|k| k.verifying_key())
+        .map_err(|e| PostcodeError::BadKey(e.to_string()))
+}
+
+/// Fingerprint of a public key: the first 8 bytes of the SHA-256 of the key
+pub fn key_id(key: &VerifyingKey) -> [u8;8]{
+    Sha256::digest(key.as_bytes())[0..8].try_into().unwrap()
+}
+
+/// Sign a pack file, returns the contents of the signature file
+pub fn sign(data: &[u8], key: &SigningKey) -> Vec<u8>{
+    let mut out = Vec::with_capacity(SIGNATURE_LEN);
+    out.extend_from_slice(b"UKPS");
+    out.extend_from_slice(&key_id(&key.verifying_key()));
+    out.extend_from_slice(&key.sign(data).to_bytes());
+    out
+}
+
+/// Check the signature of a pack file
+pub fn verify(data: &[u8], signature: &[u8], key: &VerifyingKey) -> Result<(), PostcodeError>{
+    if signature.len() != SIGNATURE_LEN || &signature[0..4] != b"UKPS"{
+        return Err(PostcodeError::BadSignature("not a signature file"));
+    }
+    let id = key_id(key);
+    if signature[4..12] != id{
+        return Err(PostcodeError::BadSignature("it was made with a different key"));
+    }
+    if Reader::from_bytes(data.to_vec())?.key_id().is_some_and(|k| k != id){
+        return Err(PostcodeError::BadSignature("the pack file says that it was signed with a different key"));
+    }
+    let signature = Signature::from_bytes(signature[12..].try_into().unwrap());
+    key.verify_strict(data, &signature)
+        .map_err(|_| PostcodeError::BadSignature("the pack file has changed since it was signed"))
+}
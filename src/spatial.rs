@@ -0,0 +1,266 @@
+/*
+
+Spatial index, from format version 5 onwards.
+
+Up to version 17 the index is always a grid. The bounding box is divided in to a grid of cells.
+For each cell, the index lists the prefix blocks that contain at least one postcode in that
+cell. Postcode areas are compact, so a cell only touches a few blocks. To find the postcodes
+near a point, a reader only has to decode the blocks listed for the cells around it.
+
+Version 18 onwards, the header says which kind of index the file has (see lib.rs), which is
+a grid, a k-d tree (see kdtree.rs), or geohash buckets (see geohash.rs).
+
+Grid spatial index section, variable length (at the end of the file):
+
+    cols:       2 bytes (u16, number of columns in the grid)
+    rows:       2 bytes (u16, number of rows in the grid)
+    cell_start: (cols*rows+1)*4 bytes
+        list of cols*rows offsets, row by row, starting from minlat, minlong
+            offset: 4 bytes (u32, index in to the block list of the first block for this cell)
+        last_offset: 4 bytes (u32, length of the block list)
+    blocks:     variable length
+        list of block numbers
+            block: 2 bytes (u16, position of the block's prefix in the quick lookup table)
+
+Cells are square in the quantized coordinate space, a location (long, lat) is in the cell
+at column (long * cols / 65536) and row (lat * rows / 65536).
+
+*/
+use std::fmt::{Display, Formatter};
+#[cfg(feature="packer")]
+use crate::Point;
+use crate::kdtree::KdTree;
+use crate::geohash::{self, GeohashIndex};
+
+/// Number of rows and columns in the grid written by the packer
+#[cfg(feature="packer")]
+pub(crate) const GRID_SIZE: u16 = 64;
+
+/// Which kind of spatial index a file has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexKind{
+    /// Prefix blocks listed by grid cell, version 5 onwards
+    #[default]
+    Grid,
+    /// Every postcode in a k-d tree, version 18 onwards
+    KdTree,
+    /// Every postcode in a bucket for its geohash, with this many characters, version 18 onwards
+    Geohash(u8),
+}
+
+impl IndexKind{
+    pub const ALL: [IndexKind;3] = [IndexKind::Grid, IndexKind::KdTree, IndexKind::Geohash(geohash::DEFAULT_PRECISION)];
+
+    /// Value stored in the header to identify the kind of index
+    pub fn id(&self) -> u32{
+        match self{
+            IndexKind::Grid => 0,
+            IndexKind::KdTree => 1,
+            IndexKind::Geohash(_) => 2,
+        }
+    }
+
+    /// The precision of a geohash index is in the index section, so this has the default
+    pub fn from_id(id: u32) -> Option<Self>{
+        Self::ALL.into_iter().find(|k| k.id() == id)
+    }
+
+    pub fn name(&self) -> &'static str{
+        match self{
+            IndexKind::Grid => "grid",
+            IndexKind::KdTree => "kdtree",
+            IndexKind::Geohash(_) => "geohash",
+        }
+    }
+
+    /// A name, or geohash:<precision> for a geohash index with a precision other than the default
+    pub fn from_name(name: &str) -> Option<Self>{
+        if let Some(precision) = name.strip_prefix("geohash:"){
+            return precision.parse().ok()
+                .filter(|p| (1..=geohash::MAX_PRECISION).contains(p))
+                .map(IndexKind::Geohash);
+        }
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    /// Oldest file format version that can have this kind of index
+    pub fn min_version(&self) -> u32{
+        match self{
+            IndexKind::Grid => 5,
+            IndexKind::KdTree | IndexKind::Geohash(_) => 18,
+        }
+    }
+}
+
+impl Display for IndexKind{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self{
+            IndexKind::Geohash(precision) => write!(f, "{}:{precision}", self.name()),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// An entry of a pack file, for building an index. Indexes are built from every entry of the
+/// file, in file order.
+#[cfg(feature="packer")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IndexPoint{
+    /// Position of the entry's prefix in the quick lookup table
+    pub block: usize,
+    /// Quantized to 16 bits in the file's bounding box
+    pub long: u16,
+    pub lat: u16,
+    pub is_partial: bool,
+    pub terminated: bool,
+    /// Location as it is read back from the file
+    pub location: Point,
+}
+
+#[derive(Debug, Clone)]
+pub enum SpatialIndex{
+    Grid(Grid),
+    KdTree(KdTree),
+    Geohash(GeohashIndex),
+}
+
+impl SpatialIndex{
+    #[cfg(feature="packer")]
+    pub(crate) fn build<I: Iterator<Item=IndexPoint>>(kind: IndexKind, points: I) -> Self{
+        match kind{
+            IndexKind::Grid => SpatialIndex::Grid(Grid::build(points.filter(|p| !p.is_partial).map(|p| (p.block, p.long, p.lat)))),
+            IndexKind::KdTree => SpatialIndex::KdTree(KdTree::build(points)),
+            IndexKind::Geohash(precision) => SpatialIndex::Geohash(GeohashIndex::build(precision, points)),
+        }
+    }
+
+    pub fn kind(&self) -> IndexKind{
+        match self{
+            SpatialIndex::Grid(_) => IndexKind::Grid,
+            SpatialIndex::KdTree(_) => IndexKind::KdTree,
+            SpatialIndex::Geohash(index) => IndexKind::Geohash(index.precision()),
+        }
+    }
+
+    #[cfg(feature="packer")]
+    pub(crate) fn to_bytes(&self) -> Vec<u8>{
+        match self{
+            SpatialIndex::Grid(grid) => grid.to_bytes(),
+            SpatialIndex::KdTree(tree) => tree.to_bytes(),
+            SpatialIndex::Geohash(index) => index.to_bytes(),
+        }
+    }
+
+    /// Parse the index section, returns `None` if it is damaged
+    pub(crate) fn from_bytes(kind: IndexKind, data: &[u8]) -> Option<Self>{
+        match kind{
+            IndexKind::Grid => Grid::from_bytes(data).map(SpatialIndex::Grid),
+            IndexKind::KdTree => KdTree::from_bytes(data).map(SpatialIndex::KdTree),
+            IndexKind::Geohash(_) => GeohashIndex::from_bytes(data).map(SpatialIndex::Geohash),
+        }
+    }
+
+    /// Highest block number in the index, to check that it is in the lookup table
+    pub(crate) fn max_block(&self) -> Option<usize>{
+        match self{
+            SpatialIndex::Grid(grid) => grid.blocks.iter().max().map(|&b| b as usize),
+            SpatialIndex::KdTree(tree) => tree.points().iter().map(|p| p.block as usize).max(),
+            SpatialIndex::Geohash(index) => index.max_block(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Grid{
+    cols: u16,
+    rows: u16,
+    cell_start: Vec<u32>,
+    blocks: Vec<u16>,
+}
+
+impl Grid{
+    /// Build an index from the quantized (long, lat) location of each postcode, and the
+    /// position of its prefix in the quick lookup table
+    #[cfg(feature="packer")]
+    pub(crate) fn build<I: Iterator<Item=(usize, u16, u16)>>(points: I) -> Self{
+        let cols = GRID_SIZE;
+        let rows = GRID_SIZE;
+        let mut cells: Vec<Vec<u16>> = vec![Vec::new(); cols as usize * rows as usize];
+        for (block, long, lat) in points{
+            let (col, row) = cell_of(cols, rows, long, lat);
+            cells[(row * cols as usize) + col].push(block as u16);
+        }
+        let mut cell_start = Vec::with_capacity(cells.len() + 1);
+        let mut blocks = Vec::new();
+        for mut cell in cells{
+            cell.sort();
+            cell.dedup();
+            cell_start.push(blocks.len() as u32);
+            blocks.extend(cell);
+        }
+        cell_start.push(blocks.len() as u32);
+        Self{cols, rows, cell_start, blocks}
+    }
+
+    #[cfg(feature="packer")]
+    pub(crate) fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(4 + (self.cell_start.len()*4) + (self.blocks.len()*2));
+        out.extend_from_slice(&self.cols.to_le_bytes());
+        out.extend_from_slice(&self.rows.to_le_bytes());
+        for s in &self.cell_start{
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        for b in &self.blocks{
+            out.extend_from_slice(&b.to_le_bytes());
+        }
+        out
+    }
+
+    /// Parse the index section, returns `None` if it is damaged
+    pub(crate) fn from_bytes(data: &[u8]) -> Option<Self>{
+        let cols = u16::from_le_bytes(data.get(0..2)?.try_into().ok()?);
+        let rows = u16::from_le_bytes(data.get(2..4)?.try_into().ok()?);
+        let n_cells = cols as usize * rows as usize;
+        let starts_end = n_cells.checked_add(1)?.checked_mul(4)?.checked_add(4)?;
+        let cell_start: Vec<u32> = data.get(4..starts_end)?
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        let blocks: Vec<u16> = data.get(starts_end..)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        if cols == 0 || rows == 0 || *cell_start.last()? as usize != blocks.len(){
+            return None;
+        }
+        if cell_start.windows(2).any(|w| w[0] > w[1]){
+            return None;
+        }
+        Some(Self{cols, rows, cell_start, blocks})
+    }
+
+    pub fn cols(&self) -> usize{
+        self.cols as usize
+    }
+
+    pub fn rows(&self) -> usize{
+        self.rows as usize
+    }
+
+    /// Column and row of the cell containing a quantized location
+    pub(crate) fn cell(&self, long: u16, lat: u16) -> (usize, usize){
+        cell_of(self.cols, self.rows, long, lat)
+    }
+
+    /// Prefix blocks that have postcodes in a cell
+    pub(crate) fn blocks(&self, col: usize, row: usize) -> &[u16]{
+        let i = (row * self.cols as usize) + col;
+        &self.blocks[self.cell_start[i] as usize..self.cell_start[i+1] as usize]
+    }
+}
+
+fn cell_of(cols: u16, rows: u16, long: u16, lat: u16) -> (usize, usize){
+    let col = (long as usize * cols as usize) / 65536;
+    let row = (lat as usize * rows as usize) / 65536;
+    (col, row)
+}
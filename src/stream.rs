@@ -0,0 +1,467 @@
+/*
+
+Low-memory packing.
+
+`Packer` keeps every postcode from the input file in memory, which is a lot of small strings for
+the full ONS database. `StreamPacker` reads the input file twice instead. The first pass finds the
+bounding box, the date, the outward code averages and the extents of each prefix block. The second pass quantizes each postcode
+as it is read, so only a few bytes per postcode are kept while the records are sorted and packed.
+
+The output is identical to the file written by `Packer`.
+
+*/
+use std::fs::OpenOptions;
+use std::io::Write;
+use rayon::prelude::*;
+
+use crate::{
+    Packer, PostcodeError, PostcodeInfo, Point, ReadOptions, ReadStats, Reader, Mismatch, Unpackable, Dropped, Scheme,
+    Compression, QuantizedPostcode, OutwardTotals, Sections, pack_block, read_postcodes_with, reread_postcodes_with,
+    check_postcode, lut_index, PREFIX_LEN, max_quantized, dequantize_ll, Metadata, Outlier, RecordOrder, OnDuplicate, Anomaly, Crs,
+};
+use crate::centroid::CentroidTotals;
+use crate::extent::ExtentTotals;
+use crate::sector::SectorTotals;
+use crate::outlier::{Extremes, OutlierTotals, MAX_OUTLIERS};
+use crate::spatial::{SpatialIndex, IndexKind, IndexPoint};
+use crate::{country, order, duplicate};
+use crate::local::{BlockExtents, LocalBoxes};
+use crate::group::OutwardExtents;
+use crate::anomaly::AreaLocations;
+
+/// Packs a postcode file without keeping all of the postcodes in memory
+#[derive(Debug, Clone)]
+pub struct StreamPacker{
+    path: String,
+    options: ReadOptions,
+    /// Everything except the full postcodes, the postcode list only has the outward codes
+    packer: Packer,
+    /// For the local bounding boxes, if they're used
+    extents: BlockExtents,
+    /// The postcodes furthest out, in case some are taken out of the bounding box
+    extremes: Extremes,
+    /// For the coordinate groups, if they're used
+    outward_extents: OutwardExtents,
+}
+
+impl StreamPacker{
+    /// Read the ONS postcode database csv file, see `read_postcodes` for details of which
+    /// rows are skipped. The file is read again when the pack file is written.
+    pub fn from_csv(path: &str, exclude: &[&str]) -> Result<Self, PostcodeError>{
+        Self::from_csv_with_options(path, &ReadOptions::with_exclude(exclude))
+    }
+
+    /// As `from_csv`, with more control over how the file is read.
+    ///
+    /// Attributes are not kept while the postcodes are sorted, so they can't be written this way.
+    pub fn from_csv_with_options(path: &str, options: &ReadOptions) -> Result<Self, PostcodeError>{
+        if !options.attributes.is_empty(){
+            return Err(PostcodeError::IncompatibleOptions("attributes can't be written by the low-memory packer"));
+        }
+        if options.on_duplicate != OnDuplicate::Error{
+            return Err(PostcodeError::IncompatibleOptions("the low-memory packer can't choose which row of a duplicated postcode to keep"));
+        }
+        let mut totals = OutwardTotals::new(options.scheme);
+        let mut extents = BlockExtents::default();
+        let mut extremes = Extremes::new(MAX_OUTLIERS);
+        let mut outward_extents = OutwardExtents::new(options.scheme);
+        let mut packer = read_postcodes_with(path, options, |p| {
+            totals.add(&p);
+            extents.add(&p);
+            extremes.add(&p);
+            outward_extents.add(&p);
+        })?;
+        packer.postcodes = totals.averages().collect();
+        for p in &packer.postcodes{
+            outward_extents.add(p);
+        }
+        Ok(Self{
+            path: path.to_string(),
+            options: options.clone(),
+            packer,
+            extents,
+            extremes,
+            outward_extents,
+        })
+    }
+
+    /// Number of postcodes that will be packed, including the outward codes
+    pub fn len(&self) -> usize{
+        self.packer.stats.total - self.packer.stats.skipped + self.packer.postcodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.len() == 0
+    }
+
+    /// Lower left and upper right corners of the bounding box
+    pub fn bounds(&self) -> (Point, Point){
+        self.packer.bounds()
+    }
+
+    /// Date of the most recently introduced postcode, as a unix timestamp
+    pub fn last_update(&self) -> u64{
+        self.packer.last_update()
+    }
+
+    pub fn stats(&self) -> &ReadStats{
+        self.packer.stats()
+    }
+
+    /// Postcodes that were skipped because they can't be stored in a pack file, in file order
+    pub fn unpackable(&self) -> &[Unpackable]{
+        self.packer.unpackable()
+    }
+
+    /// Every row that was skipped, in file order, if `ReadOptions::report_dropped` was set
+    pub fn dropped(&self) -> &[Dropped]{
+        self.packer.dropped()
+    }
+
+    /// As `Packer::anomalies`. The input file is read twice more, once to find the region of
+    /// each postcode area and once to check each postcode against it, and only the locations
+    /// are kept in memory.
+    pub fn anomalies(&self) -> Result<Vec<Anomaly>, PostcodeError>{
+        let mut locations = AreaLocations::new(self.packer.scheme);
+        reread_postcodes_with(&self.path, &self.options, |p| locations.add(&p))?;
+        let regions = locations.regions();
+        let mut anomalies = Vec::new();
+        reread_postcodes_with(&self.path, &self.options, |p| anomalies.extend(regions.check(&p)))?;
+        anomalies.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+        Ok(anomalies)
+    }
+
+    /// File format version that will be written
+    pub fn format_version(&self) -> u32{
+        self.packer.format_version()
+    }
+
+    /// Choose the file format version to write, must be one of `WRITABLE_VERSIONS`
+    pub fn set_format_version(&mut self, version: u32) -> Result<(), PostcodeError>{
+        self.packer.set_format_version(version)
+    }
+
+    /// Compression that will be applied to the postcode data
+    pub fn compression(&self) -> Compression{
+        self.packer.compression()
+    }
+
+    /// Choose how to compress the postcode data, see `Packer::set_compression`
+    pub fn set_compression(&mut self, compression: Compression){
+        self.packer.set_compression(compression);
+    }
+
+    /// Whether a spatial index will be written
+    pub fn spatial_index(&self) -> bool{
+        self.packer.spatial_index()
+    }
+
+    /// Choose whether to write a spatial index, needs format version 5 or newer
+    pub fn set_spatial_index(&mut self, spatial_index: bool){
+        self.packer.set_spatial_index(spatial_index);
+    }
+
+    /// Kind of spatial index that will be written, if there is one
+    pub fn index_kind(&self) -> IndexKind{
+        self.packer.index_kind()
+    }
+
+    /// Choose the kind of spatial index to write, a k-d tree or geohash buckets need format
+    /// version 18 or newer
+    pub fn set_index_kind(&mut self, kind: IndexKind){
+        self.packer.set_index_kind(kind);
+    }
+
+    /// Whether the country of each postcode will be written
+    pub fn countries(&self) -> bool{
+        self.packer.countries()
+    }
+
+    /// Choose whether to write the country of each postcode, needs format version 7 or newer
+    pub fn set_countries(&mut self, countries: bool){
+        self.packer.set_countries(countries);
+    }
+
+    /// Whether the area, district and sector centroid tables will be written
+    pub fn centroids(&self) -> bool{
+        self.packer.centroids()
+    }
+
+    /// Choose whether to write the centroid tables, needs format version 8 or newer
+    pub fn set_centroids(&mut self, centroids: bool){
+        self.packer.set_centroids(centroids);
+    }
+
+    /// Whether the bounding box of each outward code will be written
+    pub fn prefix_extents(&self) -> bool{
+        self.packer.prefix_extents()
+    }
+
+    /// Choose whether to write the bounding box of each outward code, needs format version 20 or
+    /// newer
+    pub fn set_prefix_extents(&mut self, prefix_extents: bool){
+        self.packer.set_prefix_extents(prefix_extents);
+    }
+
+    /// Whether the list of outward codes and sectors that exist will be written
+    pub fn sector_bitmap(&self) -> bool{
+        self.packer.sector_bitmap()
+    }
+
+    /// Choose whether to write the list of outward codes and sectors that exist, needs format
+    /// version 21 or newer
+    pub fn set_sector_bitmap(&mut self, sector_bitmap: bool){
+        self.packer.set_sector_bitmap(sector_bitmap);
+    }
+
+    /// Release date of the dataset, as a unix timestamp, `None` if it isn't known
+    pub fn release_date(&self) -> Option<u64>{
+        self.packer.release_date()
+    }
+
+    /// Record the release date of the dataset, as a unix timestamp, needs format version 22 or
+    /// newer
+    pub fn set_release_date(&mut self, release_date: Option<u64>){
+        self.packer.set_release_date(release_date);
+    }
+
+    /// How the file was made, empty if it won't say
+    pub fn metadata(&self) -> &Metadata{
+        self.packer.metadata()
+    }
+
+    /// Record how the file was made, needs format version 23 or newer
+    pub fn set_metadata(&mut self, metadata: Metadata){
+        self.packer.set_metadata(metadata);
+    }
+
+    /// Number of postcodes taken out of the bounding box
+    pub fn clip_bbox(&self) -> usize{
+        self.packer.clip_bbox()
+    }
+
+    /// Take the postcodes that stretch the bounding box the most out of it, see
+    /// `Packer::set_clip_bbox`
+    pub fn set_clip_bbox(&mut self, count: usize) -> Result<(), PostcodeError>{
+        let outliers = self.outliers(count)?;
+        self.packer.clip_to(&outliers, count);
+        Ok(())
+    }
+
+    /// The postcodes that stretch the bounding box the most, see `Packer::outliers`
+    pub fn outliers(&self, count: usize) -> Result<Vec<Outlier>, PostcodeError>{
+        if count > MAX_OUTLIERS{
+            return Err(PostcodeError::IncompatibleOptions("at most 1000 postcodes can be taken out of the bounding box"));
+        }
+        Ok(self.extremes.outliers(count))
+    }
+
+    /// Chunk size that the prefix blocks will be aligned to
+    pub fn chunk_size(&self) -> u32{
+        self.packer.chunk_size()
+    }
+
+    /// Choose a chunk size in bytes, see `Packer::set_chunk_size`
+    pub fn set_chunk_size(&mut self, chunk_size: u32){
+        self.packer.set_chunk_size(chunk_size);
+    }
+
+    /// Fingerprint of the key that the file will be signed with
+    pub fn key_id(&self) -> [u8;8]{
+        self.packer.key_id()
+    }
+
+    /// Record the fingerprint of the signing key, see `Packer::set_key_id`
+    pub fn set_key_id(&mut self, key_id: [u8;8]){
+        self.packer.set_key_id(key_id);
+    }
+
+    /// Whether the postcode data will be entropy coded
+    pub fn entropy(&self) -> bool{
+        self.packer.entropy()
+    }
+
+    /// Choose whether to entropy code the postcode data, see `Packer::set_entropy`
+    pub fn set_entropy(&mut self, entropy: bool){
+        self.packer.set_entropy(entropy);
+    }
+
+    /// Whether each prefix block will have its own bounding box
+    pub fn local_bounds(&self) -> bool{
+        self.packer.local_bounds()
+    }
+
+    /// Choose whether to give each prefix block its own bounding box, see `Packer::set_local_bounds`
+    pub fn set_local_bounds(&mut self, local_bounds: bool){
+        self.packer.set_local_bounds(local_bounds);
+    }
+
+    /// Choose whether to quantize the locations in coordinate groups, see `Packer::set_groups`
+    pub fn set_groups(&mut self, groups: bool){
+        self.packer.set_groups(groups);
+    }
+
+    /// Choose the order of the records in each prefix block, see `Packer::set_order`
+    pub fn set_order(&mut self, order: RecordOrder){
+        self.packer.set_order(order);
+    }
+
+    /// Bits per axis of the quantized locations
+    pub fn resolution(&self) -> u32{
+        self.packer.resolution()
+    }
+
+    /// Choose the bits per axis of the quantized locations, see `Packer::set_resolution`
+    pub fn set_resolution(&mut self, resolution: u32) -> Result<(), PostcodeError>{
+        self.packer.set_resolution(resolution)
+    }
+
+    /// Which country's codes will be packed, this comes from `ReadOptions::scheme`
+    pub fn scheme(&self) -> Scheme{
+        self.packer.scheme()
+    }
+
+    /// Coordinate reference system of the locations, this comes from `ReadOptions::crs`
+    pub fn crs(&self) -> Crs{
+        self.packer.crs()
+    }
+
+    /// The bounding box of each prefix block or the coordinate groups, empty if they don't have
+    /// their own
+    fn local_boxes(&self) -> LocalBoxes{
+        if self.packer.groups{
+            return LocalBoxes::grouped(self.outward_extents.groups());
+        }
+        if !self.packer.local_bounds{
+            return LocalBoxes::default();
+        }
+        let (minll, maxll) = self.packer.stored_bounds();
+        self.extents.boxes(minll, maxll)
+    }
+
+    /// Read the input file again, and quantize every postcode. The result is sorted.
+    ///
+    /// The centroid totals, prefix extents and outliers are added up on the way, if they're needed.
+    fn quantized(&self, totals: &mut CentroidTotals, extents: &mut ExtentTotals, outliers: &mut OutlierTotals, boxes: &LocalBoxes) -> Result<Vec<QuantizedPostcode>, PostcodeError>{
+        let (file_min, file_max) = self.packer.stored_bounds();
+        // Each postcode is quantized in the bounding box of its group or block
+        let bounds = |p: &PostcodeInfo| lut_index(p.postcode.as_bytes())
+            .map(|index| boxes.bounds(index, p.postcode.as_bytes(), file_min, file_max))
+            .unwrap_or((file_min, file_max));
+        let mut postcodes = Vec::with_capacity(self.len());
+        let mut error = None;
+        reread_postcodes_with(&self.path, &self.options, |p| {
+            if self.packer.centroids{
+                totals.add(&p);
+            }
+            if self.packer.prefix_extents{
+                extents.add(&p);
+            }
+            outliers.add(&p);
+            let (minll, maxll) = bounds(&p);
+            match QuantizedPostcode::new(&p, minll, maxll, self.packer.resolution, self.packer.scheme){
+                Ok(q) => postcodes.push(q),
+                Err(e) => { error.get_or_insert(e); },
+            }
+        })?;
+        if let Some(e) = error{
+            return Err(e);
+        }
+        for p in &self.packer.postcodes{
+            outliers.add(p);
+            let (minll, maxll) = bounds(p);
+            postcodes.push(QuantizedPostcode::new(p, minll, maxll, self.packer.resolution, self.packer.scheme)?);
+        }
+        postcodes.par_sort_by(|a,b|a.postcode.cmp(&b.postcode));
+        if let Some(w) = postcodes.windows(2).find(|w| w[0].postcode == w[1].postcode){
+            return Err(duplicate::duplicate_error(&String::from_utf8_lossy(&w[0].postcode), self.packer.scheme));
+        }
+        Ok(postcodes)
+    }
+
+    /// Write the pack file to the named path, returns the number of bytes written
+    pub fn write(&self, path: &str) -> Result<u64, PostcodeError>{
+        let outfile = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+        self.write_to(outfile)
+    }
+
+    /// Write the pack file, returns the number of bytes written
+    pub fn write_to<W: Write>(&self, outfile: W) -> Result<u64, PostcodeError>{
+        self.packer.check_options()?;
+        let mut totals = CentroidTotals::default();
+        let mut extents = ExtentTotals::new(self.packer.scheme);
+        let (minll, maxll) = self.packer.stored_bounds();
+        let mut outliers = OutlierTotals::new(minll, maxll);
+        let boxes = self.local_boxes();
+        let mut postcodes = self.quantized(&mut totals, &mut extents, &mut outliers, &boxes)?;
+        let mut sections = Sections::default();
+        // The countries are runs of postcodes in order, so they're found before the records are
+        // put in the order they're written
+        if self.packer.countries{
+            sections.countries = country::encode_runs(postcodes.iter().map(|p| (p.postcode.as_slice(), p.country)));
+        }
+        order::arrange(&mut postcodes, self.packer.order, |p| (p.postcode, p.long, p.lat));
+        let blocks: Vec<&[QuantizedPostcode]> = postcodes.chunk_by(|a,b| a.postcode[..PREFIX_LEN] == b.postcode[..PREFIX_LEN]).collect();
+        let packed_codes: Vec<_> = blocks.par_iter()
+            .map(|block| pack_block(block, self.packer.version, self.packer.resolution))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        if self.packer.spatial_index{
+            let (file_min, file_max) = self.packer.stored_bounds();
+            sections.index = SpatialIndex::build(
+                self.packer.index_kind,
+                postcodes.iter()
+                    .filter_map(|p| {
+                        let block = lut_index(&p.postcode)?;
+                        let (minll, maxll) = boxes.bounds(block, &p.postcode, file_min, file_max);
+                        let location = dequantize_ll(minll, maxll, p.long, p.lat, self.packer.resolution);
+                        let (long, lat) = boxes.to_file(block, &p.postcode, (p.long, p.lat), self.packer.resolution, file_min, file_max);
+                        Some(IndexPoint{block, long, lat, is_partial: p.is_partial, terminated: p.terminated.is_some(), location})
+                    })
+            ).to_bytes();
+        }
+        if self.packer.centroids{
+            let (minll, maxll) = self.packer.stored_bounds();
+            sections.centroids = totals.to_bytes(minll, maxll);
+        }
+        if self.packer.prefix_extents{
+            let (minll, maxll) = self.packer.stored_bounds();
+            sections.prefix_extents = extents.to_bytes(minll, maxll);
+        }
+        if self.packer.sector_bitmap{
+            let mut totals = SectorTotals::new(self.packer.scheme);
+            for p in postcodes.iter().filter(|p| !p.is_partial && p.terminated.is_none()){
+                totals.add_code(std::str::from_utf8(&p.postcode).unwrap());
+            }
+            sections.sector_bitmap = totals.to_bytes();
+        }
+        if self.packer.version >= 24{
+            sections.outliers = outliers.to_bytes();
+        }
+        let prefixes = postcodes.iter().map(|p| p.prefix());
+        self.packer.write_packed(outfile, prefixes, &packed_codes, &boxes, &sections)
+    }
+
+    /// Check that every postcode can be read back from a pack file, see `Packer::verify`.
+    ///
+    /// This reads the input file again.
+    pub fn verify(&self, reader: &Reader) -> Result<Vec<Mismatch>, PostcodeError>{
+        let (minll, maxll) = self.packer.stored_bounds();
+        let max = max_quantized(self.packer.resolution) as f64;
+        let step_x = (maxll.x - minll.x) / max;
+        let step_y = (maxll.y - minll.y) / max;
+        let mut mismatches = Vec::new();
+        let mut check = |p: &PostcodeInfo| {
+            if let Some(m) = check_postcode(reader, p, step_x, step_y){
+                mismatches.push(m);
+            }
+        };
+        reread_postcodes_with(&self.path, &self.options, |p| check(&p))?;
+        for p in &self.packer.postcodes{
+            check(p);
+        }
+        Ok(mismatches)
+    }
+}
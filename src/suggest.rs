@@ -0,0 +1,73 @@
+/*
+
+Near misses for a postcode that isn't in the file, for "did you mean" suggestions.
+
+The codes that a typo could have come from are made by changing the typed code, one edit at a
+time: replacing one char with any other letter or digit, or swapping two chars next to each
+other. Letters and digits that look alike (O and 0, I and 1) are the most likely mistakes, so
+a near miss that only needs those comes before one that needs other edits. The ones that are in
+the file are found by reading the prefix blocks that they would be in, once each.
+
+The number of codes grows quickly with each edit (about 250 for one edit of a UK postcode, and
+about 30,000 for two), so at most `MAX_EDITS` edits are tried.
+
+*/
+use std::collections::HashMap;
+
+/// Most edits that a near miss can be from the typed code
+pub const MAX_EDITS: usize = 2;
+
+/// Chars that a code can have
+const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// Pairs of chars that look alike
+const LOOKALIKES: &[(u8, u8)] = &[(b'O', b'0'), (b'I', b'1')];
+
+/// How far a near miss is from the typed code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Cost{
+    pub edits: usize,
+    /// Edits that aren't between lookalike chars
+    pub other: usize,
+}
+
+fn lookalike(a: u8, b: u8) -> bool{
+    LOOKALIKES.iter().any(|&(x, y)| (a, b) == (x, y) || (a, b) == (y, x))
+}
+
+/// Every code up to `max_edits` edits from `code` (upper case, without spaces), each with its
+/// lowest cost, not counting the code itself
+pub(crate) fn near_misses(code: &str, max_edits: usize) -> HashMap<String, Cost>{
+    let mut found: HashMap<Vec<u8>, Cost> = HashMap::new();
+    let mut last = vec![(code.as_bytes().to_vec(), Cost{edits: 0, other: 0})];
+    for _ in 0..max_edits.min(MAX_EDITS){
+        let mut next = Vec::new();
+        for (code, cost) in &last{
+            let mut add = |candidate: Vec<u8>, is_lookalike: bool| {
+                let cost = Cost{edits: cost.edits + 1, other: cost.other + usize::from(!is_lookalike)};
+                match found.get(&candidate){
+                    Some(c) if *c <= cost => {}
+                    _ => {
+                        found.insert(candidate.clone(), cost);
+                        next.push((candidate, cost));
+                    }
+                }
+            };
+            for i in 0..code.len(){
+                for &c in CHARS.iter().filter(|&&c| c != code[i]){
+                    let mut candidate = code.clone();
+                    candidate[i] = c;
+                    add(candidate, lookalike(code[i], c));
+                }
+                if i + 1 < code.len() && code[i] != code[i + 1]{
+                    let mut candidate = code.clone();
+                    candidate.swap(i, i + 1);
+                    add(candidate, false);
+                }
+            }
+        }
+        last = next;
+    }
+    found.remove(code.as_bytes());
+    found.into_iter().map(|(c, cost)| (String::from_utf8(c).unwrap(), cost)).collect()
+}
@@ -0,0 +1,138 @@
+/*
+
+A summary of an input file, for checking a new release before packing it.
+
+Every row is counted, whatever the options for packing would do with it: how many postcodes are
+live and how many terminated, in each postcode area and district, how precise their locations
+are (the positional quality, `osgrdind`, from 1 for within a building to 9 for no location at
+all), and how many were introduced in each year. A release with a district missing, a sudden
+jump in the number of terminated postcodes, or a lot of postcodes with poor locations shows up
+here before it's in a pack file.
+
+Only the postcode, date and quality columns are read, so rows with bad locations are counted
+like any others. Code-Point Open has no dates and only live postcodes, and its quality is on
+the same scale multiplied by 10. The Pointer address database has neither, so it can't be
+summarized.
+
+*/
+use std::collections::BTreeMap;
+use std::io::Read;
+use crate::{PostcodeError, ReadOptions, InputFormat, Scheme, field_id, parse_date, input};
+
+/// Numbers of live and terminated postcodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts{
+    pub live: usize,
+    pub terminated: usize,
+}
+
+impl Counts{
+    fn add(&mut self, terminated: bool){
+        if terminated{
+            self.terminated += 1;
+        }
+        else{
+            self.live += 1;
+        }
+    }
+}
+
+/// What is in an input file
+#[derive(Debug, Clone, Default)]
+pub struct InputSummary{
+    /// Number of rows in the input file
+    pub rows: usize,
+    /// Postcodes of every row that has one that can be read
+    pub postcodes: Counts,
+    /// Postcodes in each postcode area (the letters at the start, e.g. SW)
+    pub areas: BTreeMap<String, Counts>,
+    /// Postcodes in each district (the outward code, e.g. SW1A)
+    pub districts: BTreeMap<String, Counts>,
+    /// Number of rows with each positional quality, from 1 to 9
+    pub quality: BTreeMap<u8, usize>,
+    /// Rows without a positional quality
+    pub no_quality: usize,
+    /// Number of postcodes introduced in each year
+    pub introduced: BTreeMap<i32, usize>,
+    /// Rows without a date of introduction
+    pub no_date: usize,
+    /// Rows with a postcode that can't be read, or no postcode at all
+    pub bad_postcodes: usize,
+    /// Rows that can't be read as CSV, or that have a date that can't be read
+    pub malformed: usize,
+}
+
+/// Column positions of the fields that are summarized
+struct Columns{
+    postcode: usize,
+    date_intr: Option<usize>,
+    date_term: Option<usize>,
+    quality: Option<usize>,
+    /// Divide the quality by this to get the ONS scale
+    quality_scale: u8,
+}
+
+impl InputSummary{
+    /// Summarize an input file. Only the format and scheme of the options are used.
+    pub fn from_csv(path: &str, options: &ReadOptions) -> Result<Self, PostcodeError>{
+        let mut summary = Self::default();
+        match options.format{
+            InputFormat::Onspd => input::with_input(path, |file| {
+                let mut rows = csv::Reader::from_reader(file);
+                let header_row = rows.headers()?.clone();
+                let headers: Vec<&str> = header_row.iter().collect();
+                let cols = Columns{
+                    postcode: field_id(&["pcd", "pcd7", "pcd2", "pcd8", "pcds", "postcode", "eircode"], &headers)?,
+                    date_intr: field_id(&["dointr"], &headers).ok(),
+                    date_term: field_id(&["doterm"], &headers).ok(),
+                    quality: field_id(&["osgrdind"], &headers).ok(),
+                    quality_scale: 1,
+                };
+                summary.add_rows(rows, &cols, options.scheme);
+                Ok(())
+            })?,
+            InputFormat::CodePoint => {
+                let cols = Columns{postcode: 0, date_intr: None, date_term: None, quality: Some(1), quality_scale: 10};
+                input::with_codepoint_inputs(path, |file, _| {
+                    summary.add_rows(csv::ReaderBuilder::new().has_headers(false).from_reader(file), &cols, options.scheme);
+                    Ok(())
+                })?
+            },
+            InputFormat::Pointer => return Err(PostcodeError::IncompatibleOptions("the Pointer address database has no dates or positional quality to summarize")),
+        }
+        Ok(summary)
+    }
+
+    fn add_rows<R: Read>(&mut self, rows: csv::Reader<R>, cols: &Columns, scheme: Scheme){
+        for row in rows.into_records(){
+            self.rows += 1;
+            let Ok(row) = row else {
+                self.malformed += 1;
+                continue;
+            };
+            let date = |col: Option<usize>| col.and_then(|i| row.get(i)).map(parse_date).unwrap_or(Ok(None));
+            let (Ok(introduced), Ok(terminated)) = (date(cols.date_intr), date(cols.date_term)) else {
+                self.malformed += 1;
+                continue;
+            };
+            let Some(postcode) = row.get(cols.postcode).and_then(|p| scheme.codec().normalize(p)) else {
+                self.bad_postcodes += 1;
+                continue;
+            };
+            let terminated = terminated.is_some();
+            self.postcodes.add(terminated);
+            let district = scheme.codec().prefix(&postcode);
+            let area: String = district.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+            self.areas.entry(area).or_default().add(terminated);
+            self.districts.entry(district.to_string()).or_default().add(terminated);
+            match cols.quality.and_then(|i| row.get(i)).and_then(|q| q.trim().parse::<u8>().ok()){
+                Some(q) => *self.quality.entry(q / cols.quality_scale).or_default() += 1,
+                None => self.no_quality += 1,
+            }
+            match introduced{
+                Some(d) => *self.introduced.entry(d.year()).or_default() += 1,
+                None => self.no_date += 1,
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+/*
+
+Test vectors for pack file readers.
+
+`generate` writes a small, made up postcode file, packs it, and writes a JSON file of what a
+reader should find in the pack file. The javascript library's tests check every lookup in it,
+so any difference between the Rust writer and the javascript reader is caught.
+
+The same postcodes and locations are generated every time, so the output only changes if the
+file format does. From version 17 the codes are Eircodes instead of UK postcodes, so that the
+second scheme is covered too. From version 19 the Eircodes also have a made up county, which is
+stored as an attribute, from version 20 the file has the bounding box of each routing key, from
+version 21 it has the sector bitmap, from version 22 a release date, from version 23 some
+metadata, and in version 24 the three codes furthest out are left out of the bounding box. From
+version 25 the locations are quantized in coordinate groups instead of local bounding boxes, and
+version 26 has the same options, in sections.
+
+Files written:
+
+    vectors.csv:  the made up input file, in the ONS postcode database format (or just the
+                  code and location for Eircodes)
+    vectors.pack: the pack file
+    vectors.json: the expected results
+        format_version: version of vectors.pack
+        date:           last_update of vectors.pack (unix timestamp)
+        release_date:   version 22 onwards, release date of vectors.pack (unix timestamp)
+        metadata:       version 23 onwards, {key: value} of the metadata of vectors.pack
+        lookups:        list of {query, postcode, long, lat}. Every postcode and outward code,
+                        queried in a mix of cases and spacings. Locations are as they are stored
+                        in the pack file, not as they are in vectors.csv. From version 19, also
+                        attributes: {name: value, or null if the postcode has none}
+        extents:        version 20 onwards, list of {query, min, max}. The bounding box of each
+                        routing key, queried by the key or by one of its Eircodes, with the lower
+                        left and upper right corners as [long, lat]. Queries for keys that are
+                        not in the file have null corners.
+        completions:    version 21 onwards, list of {query, results}. The routing keys and sectors
+                        (a routing key and the first char of the unique identifier, e.g. "A65 F")
+                        that start with some partly typed Eircodes, in order.
+        prefixes:       version 21 onwards, list of {query, exists}. Whether each of the
+                        completions, and some routing keys and sectors that don't exist, have
+                        any Eircodes.
+        not_found:      well-formed postcodes that are not in the pack file
+        invalid:        strings that are not postcodes
+
+*/
+use std::fs;
+use std::path::Path;
+
+use crate::{Packer, PostcodeError, Point, Reader, ReadOptions, Scheme, Metadata, json_string};
+use crate::spatial::IndexKind;
+
+/// Outward codes to generate postcodes in, chosen to cover every shape of outward code
+const DISTRICTS: [&str; 12] = ["B1", "B33", "E1W", "G2", "AB10", "CB2", "SW1A", "W1A", "YO1", "ZE3", "BT9", "EC1A"];
+
+/// Postcodes that are terminated, so they are not packed
+const TERMINATED: [&str; 2] = ["CB2 9ZZ", "YO1 9ZZ"];
+
+const NOT_FOUND: [&str; 4] = ["ZZ9 9ZZ", "A0 0AA", "SW1A 9AA", "XX1"];
+
+const INVALID: [&str; 5] = ["A", "ABCD1234", "ab12_345", "SW1A 2AA!", ""];
+
+/// Postcodes in each district
+const PER_DISTRICT: u32 = 12;
+
+/// Inward codes of the postcodes in each district that share a location
+const BUILDING: [&str; 3] = ["9XA", "9XB", "9XD"];
+
+/// Routing keys to generate Eircodes in, including D6W which is the only one with a letter
+const ROUTING_KEYS: [&str; 6] = ["A65", "D02", "D6W", "T12", "V94", "Y35"];
+
+const EIRCODE_NOT_FOUND: [&str; 3] = ["A65 YYYY", "C15 A0C0", "X91"];
+
+const EIRCODE_INVALID: [&str; 5] = ["A", "B12 3456", "A65 F4B2", "D02 X28", ""];
+
+/// Partly typed Eircodes to complete, from version 21
+const EIRCODE_PARTIAL: [&str; 12] = ["", "a", "D", "d0", "D6W", "a65 ", "A65 4", "t12x", "Y35 X", "V9", "X", "D02 Q"];
+
+/// Routing keys and sectors that don't exist, or aren't written in a way that can be checked
+const EIRCODE_NO_PREFIX: [&str; 6] = ["A65 B", "a65 0x", "Z99", "A6", "C15 0", "A65F"];
+
+/// Made up county of the Eircodes in each routing key
+const COUNTIES: [&str; 6] = ["Westmeath", "Dublin", "Dublin", "Cork", "Limerick", "Wexford"];
+
+/// Made up release date of the vectors from version 22, 2025-02-01
+const RELEASE_DATE: u64 = 1738368000;
+
+/// Chars of the unique identifier of an Eircode
+const EIRCODE_UNIQUE: &[u8] = b"0123456789ACDEFHKNPRTVWXY";
+
+/// A small linear congruential generator, so the locations are the same on every platform
+struct Lcg(u64);
+
+impl Lcg{
+    /// A number from 0 to 1
+    fn next(&mut self) -> f64{
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The made up input file
+fn input_csv() -> String{
+    let mut rng = Lcg(0x4e4d50);
+    let mut csv = String::from("pcds,dointr,doterm,ctry,lat,long\n");
+    for (d, district) in DISTRICTS.iter().enumerate(){
+        let country = if district.starts_with("BT") { "N92000002" } else if district.starts_with("ZE") || district.starts_with('G') { "S92000003" } else { "E92000001" };
+        // Each district is somewhere in the UK, with its postcodes spread over a few km
+        let lat = 50.0 + (d as f64 * 0.7);
+        let long = -6.0 + (rng.next() * 7.0);
+        for i in 0..PER_DISTRICT{
+            let inward = format!("{}{}{}", i % 10, (b'A' + (i % 3) as u8) as char, (b'A' + (i / 3) as u8) as char);
+            // Every fourth postcode jumps further away, so both kinds of location record are used
+            let spread = if i % 4 == 3 { 0.5 } else { 0.02 };
+            let plat = lat + (rng.next() - 0.5) * spread;
+            let plong = long + (rng.next() - 0.5) * spread;
+            csv.push_str(&format!("{district} {inward},202401,,{country},{plat:.6},{plong:.6}\n"));
+        }
+        // A few postcodes at exactly the same place (a large building), so version 12 has runs
+        for inward in BUILDING{
+            csv.push_str(&format!("{district} {inward},202401,,{country},{lat:.6},{long:.6}\n"));
+        }
+    }
+    for t in TERMINATED{
+        csv.push_str(&format!("{t},199001,200512,E92000001,52.0,-1.0\n"));
+    }
+    csv
+}
+
+/// The made up input file for Eircodes, which has no dates or countries. With `counties`, it has
+/// a county column too, which is empty for some of them.
+fn eircode_csv(counties: bool) -> String{
+    let mut rng = Lcg(0x4e4d50);
+    let mut csv = String::from(if counties { "eircode,lat,long,county\n" } else { "eircode,lat,long\n" });
+    for (k, key) in ROUTING_KEYS.iter().enumerate(){
+        let lat = 51.5 + (k as f64 * 0.6);
+        let long = -10.0 + (rng.next() * 4.0);
+        for i in 0..PER_DISTRICT as usize{
+            let unique: String = [i % 25, (i*3 + 1) % 25, (i*7 + 2) % 25, i % 10].iter().map(|&c| EIRCODE_UNIQUE[c] as char).collect();
+            let spread = if i % 4 == 3 { 0.5 } else { 0.02 };
+            let plat = lat + (rng.next() - 0.5) * spread;
+            let plong = long + (rng.next() - 0.5) * spread;
+            csv.push_str(&format!("{key} {unique},{plat:.6},{plong:.6}"));
+            if counties{
+                csv.push_str(&format!(",{}", if i % 5 == 4 { "" } else { COUNTIES[k] }));
+            }
+            csv.push('\n');
+        }
+        for unique in ["XA00", "XA01", "XA02"]{
+            csv.push_str(&format!("{key}{unique},{lat:.6},{long:.6}"));
+            if counties{
+                csv.push_str(&format!(",{}", COUNTIES[k]));
+            }
+            csv.push('\n');
+        }
+    }
+    csv
+}
+
+/// A query for a postcode, in a different form each time
+fn query(postcode: &str, i: usize, scheme: Scheme) -> String{
+    let spaced = match (postcode.len(), scheme){
+        (7, Scheme::Eircode) => format!("{} {}", &postcode[0..3], &postcode[3..7]),
+        (7, _) => format!("{} {}", postcode[0..4].trim_end(), &postcode[4..7]),
+        _ => postcode.trim_end().to_string(),
+    };
+    match i % 3{
+        0 => spaced,
+        1 => spaced.to_ascii_lowercase(),
+        _ => spaced.replace(' ', ""),
+    }
+}
+
+/// Write `vectors.csv`, `vectors.pack` and `vectors.json` in to a directory, returns the number
+/// of lookups in `vectors.json`
+pub fn generate(dir: &Path, version: u32) -> Result<usize, PostcodeError>{
+    fs::create_dir_all(dir)?;
+    let csv_path = dir.join("vectors.csv");
+    let pack_path = dir.join("vectors.pack");
+    let scheme = if version >= 17 { Scheme::Eircode } else { Scheme::Uk };
+    fs::write(&csv_path, if scheme == Scheme::Uk { input_csv() } else { eircode_csv(version >= 19) })?;
+    let attributes = if version >= 19 { vec!["county".to_string()] } else { Vec::new() };
+    let options = ReadOptions{scheme, attributes, ..Default::default()};
+    let mut packer = Packer::from_csv_with_options(&csv_path.to_string_lossy(), &options)?;
+    packer.set_format_version(version)?;
+    // Entropy coding, local bounding boxes and other resolutions are optional, but the vectors
+    // should cover them
+    packer.set_entropy(version >= 14);
+    packer.set_local_bounds((15..25).contains(&version));
+    if version >= 16{
+        packer.set_resolution(24)?;
+    }
+    packer.set_prefix_extents(version >= 20);
+    packer.set_sector_bitmap(version >= 21);
+    packer.set_release_date((version >= 22).then_some(RELEASE_DATE));
+    if version >= 23{
+        // Not the packer's version, so that the vectors don't change with it
+        let mut metadata = Metadata::new();
+        metadata.set("generator", "gen-test-vectors");
+        metadata.set("source", "vectors.csv");
+        metadata.set("comment", "Made up Eircodes, \u{e9}\u{e1}");
+        packer.set_metadata(metadata);
+    }
+    if version == 24{
+        packer.set_clip_bbox(3)?;
+    }
+    packer.set_groups(version >= 25);
+    // The javascript library doesn't use the k-d tree, but has to skip over it
+    if version >= 18{
+        packer.set_spatial_index(true);
+        packer.set_index_kind(IndexKind::KdTree);
+    }
+    packer.write(&pack_path.to_string_lossy())?;
+
+    let reader = Reader::open(&pack_path.to_string_lossy())?;
+    let mut lookups = Vec::new();
+    for (i, entry) in reader.iter().enumerate(){
+        let postcode = if entry.is_partial { &entry.postcode[0..4] } else { &entry.postcode };
+        let l = reader.lookup(postcode).ok_or(PostcodeError::NotFound())?;
+        let values: Vec<String> = reader.attribute_names().iter()
+            .map(|name| format!("{}: {}", json_string(name), reader.attribute(postcode, name).map(json_string).unwrap_or("null".to_string())))
+            .collect();
+        let attributes = if values.is_empty() { String::new() } else { format!(", \"attributes\": {{{}}}", values.join(", ")) };
+        lookups.push(format!(
+            "    {{\"query\": {}, \"postcode\": {}, \"long\": {}, \"lat\": {}{}}}",
+            json_string(&query(postcode, i, scheme)), json_string(postcode), l.x, l.y, attributes,
+        ));
+    }
+    let mut extents = Vec::new();
+    if version >= 20{
+        let corner = |p: Option<Point>| p.map(|p| format!("[{}, {}]", p.x, p.y)).unwrap_or("null".to_string());
+        let queries = ROUTING_KEYS.iter().map(|k| k.to_ascii_lowercase())
+            .chain(reader.iter().filter(|p| !p.is_partial).step_by(10).map(|p| p.postcode))
+            .chain(EIRCODE_NOT_FOUND.iter().map(|k| k.to_string()));
+        for query in queries{
+            let extent = reader.prefix_extent(&query);
+            extents.push(format!(
+                "    {{\"query\": {}, \"min\": {}, \"max\": {}}}",
+                json_string(&query), corner(extent.map(|e| e.0)), corner(extent.map(|e| e.1)),
+            ));
+        }
+    }
+    let extents = if extents.is_empty() { String::new() } else { format!("  \"extents\": [\n{}\n  ],\n", extents.join(",\n")) };
+    let list = |items: &[&str]| items.iter().map(|s| json_string(s)).collect::<Vec<_>>().join(", ");
+    let mut sectors = String::new();
+    if version >= 21{
+        let mut completions = Vec::new();
+        let mut prefixes: Vec<String> = EIRCODE_NO_PREFIX.iter().map(|p| p.to_string()).collect();
+        for query in EIRCODE_PARTIAL{
+            let results = reader.complete(query);
+            completions.push(format!("    {{\"query\": {}, \"results\": [{}]}}", json_string(query), list(&results.iter().map(|r| r.as_str()).collect::<Vec<_>>())));
+            prefixes.extend(results.into_iter().map(|r| r.to_ascii_lowercase()));
+        }
+        prefixes.sort();
+        prefixes.dedup();
+        let prefixes: Vec<String> = prefixes.iter()
+            .map(|p| format!("    {{\"query\": {}, \"exists\": {}}}", json_string(p), reader.prefix_exists(p) == Some(true)))
+            .collect();
+        sectors = format!("  \"completions\": [\n{}\n  ],\n  \"prefixes\": [\n{}\n  ],\n", completions.join(",\n"), prefixes.join(",\n"));
+    }
+    let (not_found, invalid): (Vec<&str>, &[&str]) = match scheme{
+        Scheme::Uk => (TERMINATED.iter().chain(NOT_FOUND.iter()).copied().collect(), &INVALID),
+        _ => (EIRCODE_NOT_FOUND.to_vec(), &EIRCODE_INVALID),
+    };
+    let release_date = reader.release_date().map(|d| format!("  \"release_date\": {d},\n")).unwrap_or_default();
+    let metadata: Vec<String> = reader.metadata().iter().map(|(k, v)| format!("{}: {}", json_string(k), json_string(v))).collect();
+    let metadata = if version >= 23 { format!("  \"metadata\": {{{}}},\n", metadata.join(", ")) } else { String::new() };
+    let json = format!(
+        "{{\n  \"format_version\": {},\n  \"date\": {},\n{}{}  \"lookups\": [\n{}\n  ],\n{}{}  \"not_found\": [{}],\n  \"invalid\": [{}]\n}}\n",
+        reader.version(), reader.last_update(), release_date, metadata, lookups.join(",\n"), extents, sectors, list(&not_found), list(invalid),
+    );
+    fs::write(dir.join("vectors.json"), json)?;
+    Ok(lookups.len())
+}
@@ -0,0 +1,177 @@
+/*
+
+Three word names for locations, for demos and for reading a location out to someone.
+
+The UK and Ireland (`MIN` to `MAX`) are split in to a grid of 2^15 by 2^15 cells, about 40m
+north to south and 26m east to west, and each cell is named by three words from a list of 1024.
+The list is of short, common English words, written for this packer and under the same licence.
+The cell's number is scrambled (multiplied by an odd constant, which can be undone) before it's
+split in to words, so that neighbouring cells have unrelated names instead of sharing their first
+two words. Names are the same for the same location in every release, but a postcode's name
+changes if its location does, and postcodes in the same cell have the same name.
+
+*/
+use crate::Point;
+
+/// Lower left corner of the grid of cells
+pub const MIN: Point = Point{x: -11.0, y: 49.0};
+/// Upper right corner of the grid of cells
+pub const MAX: Point = Point{x: 2.0, y: 61.0};
+
+/// Bits per axis of the cell numbers
+const CELL_BITS: u32 = 15;
+/// Bits of the scrambled number that each word stands for
+const WORD_BITS: u32 = 10;
+const MASK: u32 = (1 << (2 * CELL_BITS)) - 1;
+/// Odd, so multiplying by it (mod 2^30) is a bijection
+const SCRAMBLE: u32 = 0x2545_f491;
+const UNSCRAMBLE: u32 = inverse(SCRAMBLE);
+
+/// The inverse of an odd number mod 2^32, by Newton's method (each step doubles the correct bits)
+const fn inverse(n: u32) -> u32{
+    let mut inv = n;
+    let mut i = 0;
+    while i < 5{
+        inv = inv.wrapping_mul(2u32.wrapping_sub(n.wrapping_mul(inv)));
+        i += 1;
+    }
+    inv
+}
+
+/// The three words of the cell that a location is in, separated by dots, or `None` outside the grid
+pub fn words_at(location: Point) -> Option<String>{
+    let cells = (1u32 << CELL_BITS) as f64;
+    let x = ((location.x - MIN.x) / (MAX.x - MIN.x) * cells).floor();
+    let y = ((location.y - MIN.y) / (MAX.y - MIN.y) * cells).floor();
+    if !(0.0..cells).contains(&x) || !(0.0..cells).contains(&y){
+        return None;
+    }
+    let n = (((y as u32) << CELL_BITS) | x as u32).wrapping_mul(SCRAMBLE) & MASK;
+    let word = |i: u32| WORDS[((n >> (WORD_BITS * i)) & ((1 << WORD_BITS) - 1)) as usize];
+    Some(format!("{}.{}.{}", word(2), word(1), word(0)))
+}
+
+/// The centre of the cell named by three words, separated by dots, spaces or hyphens. Case
+/// doesn't matter.
+pub fn location_of(words: &str) -> Option<Point>{
+    let words: Vec<String> = words.split(['.', ' ', '-']).filter(|w| !w.is_empty()).map(|w| w.to_ascii_lowercase()).collect();
+    let [a, b, c] = words.as_slice() else {
+        return None;
+    };
+    let mut n = 0;
+    for word in [a, b, c]{
+        n = (n << WORD_BITS) | WORDS.binary_search(&word.as_str()).ok()? as u32;
+    }
+    let n = n.wrapping_mul(UNSCRAMBLE) & MASK;
+    let cells = (1u32 << CELL_BITS) as f64;
+    let (x, y) = ((n & ((1 << CELL_BITS) - 1)) as f64, (n >> CELL_BITS) as f64);
+    Some(Point{
+        x: MIN.x + (x + 0.5) / cells * (MAX.x - MIN.x),
+        y: MIN.y + (y + 0.5) / cells * (MAX.y - MIN.y),
+    })
+}
+
+/// The words, in order so that they can be found by binary search
+const WORDS: [&str; 1 << WORD_BITS] = [
+    "abbey", "able", "acorn", "agate", "agile", "alder", "alley", "almond", "alpha", "amber",
+    "ample", "anchor", "ant", "anvil", "apple", "apricot", "apron", "arch", "archer", "arrow",
+    "artist", "ash", "aspen", "atlas", "attic", "autumn", "avenue", "axe", "axle", "azure",
+    "bacon", "badge", "badger", "bagel", "bagpipe", "bake", "baker", "bakery", "balcony", "ball",
+    "balloon", "bamboo", "banana", "banjo", "banner", "barge", "barley", "barn", "baron", "barrel",
+    "basil", "basket", "bat", "bay", "beach", "beacon", "bead", "bean", "bear", "beaver", "bed",
+    "bedroom", "bee", "beech", "beetle", "bell", "belt", "bench", "berry", "beta", "bicycle",
+    "birch", "biscuit", "bishop", "bison", "black", "blanket", "blender", "bloom", "blossom",
+    "blouse", "blue", "boat", "bog", "boil", "bold", "bolt", "bonnet", "book", "boot", "bottle",
+    "boulder", "bounce", "bow", "bowl", "box", "bramble", "branch", "brass", "brave", "bread",
+    "breeze", "brick", "bridge", "bright", "bronze", "brooch", "brook", "broom", "brown", "brush",
+    "bubble", "bucket", "buckle", "bud", "bugle", "build", "bull", "bundle", "bus", "busy",
+    "butte", "butter", "button", "buzzard", "cabbage", "cabin", "cabinet", "cable", "cactus",
+    "cake", "calf", "call", "calm", "camel", "camera", "canal", "canary", "candle", "candy",
+    "canoe", "canvas", "canyon", "cap", "cape", "car", "card", "carpet", "carrot", "carry", "cart",
+    "carve", "castle", "cat", "catch", "cave", "cavern", "cedar", "celery", "cellar", "cello",
+    "cereal", "chain", "chair", "chalk", "channel", "chapel", "cheer", "cheese", "cheetah",
+    "cherry", "chess", "chest", "chimney", "chisel", "chive", "chop", "church", "cinema", "circle",
+    "clam", "clap", "clay", "clever", "cliff", "climb", "cloak", "clock", "cloud", "clove",
+    "clover", "clown", "club", "coach", "coal", "coast", "coat", "cobalt", "cobble", "cobra",
+    "cocoa", "coconut", "cod", "collar", "college", "comb", "comet", "compass", "cook", "cookie",
+    "copper", "copse", "coral", "cosy", "cottage", "cotton", "couch", "cove", "cow", "crab",
+    "cradle", "craft", "crane", "crate", "crater", "crayon", "cream", "creek", "cricket",
+    "crimson", "crisp", "crow", "crown", "crumb", "crystal", "cup", "curtain", "custard", "cymbal",
+    "cypress", "daisy", "dale", "dance", "dancer", "daring", "dart", "date", "dawn", "deer",
+    "delta", "denim", "desert", "desk", "dew", "diamond", "dice", "dig", "dill", "dish", "dive",
+    "dizzy", "dock", "doctor", "dog", "dolphin", "dome", "donkey", "door", "dove", "dragon",
+    "draw", "drawer", "dress", "drill", "drive", "drum", "duck", "duke", "dune", "dusk", "dust",
+    "dwarf", "eager", "eagle", "earl", "early", "easel", "eclipse", "eel", "eight", "eleven",
+    "elf", "elk", "elm", "emerald", "emu", "engine", "estuary", "evening", "fair", "falcon",
+    "fancy", "farmer", "fen", "fence", "fennel", "fern", "ferry", "fetch", "fiddle", "field",
+    "fifty", "fig", "finch", "fir", "firefly", "fisher", "five", "flag", "flame", "flint", "float",
+    "flute", "fly", "fog", "fond", "forest", "forge", "fork", "fort", "forty", "four", "fox",
+    "freezer", "fresh", "fridge", "frog", "frost", "frosty", "fry", "fudge", "fuzzy", "galaxy",
+    "gale", "gallery", "game", "gamma", "gannet", "garden", "garlic", "garnet", "gate", "gecko",
+    "gentle", "giant", "ginger", "giraffe", "glacier", "glen", "glide", "glider", "globe", "glove",
+    "glue", "goal", "goat", "goblin", "golden", "goose", "gorge", "gorilla", "gown", "grand",
+    "granite", "grape", "grass", "gravy", "green", "grey", "griffin", "grill", "grotto", "grouse",
+    "grove", "grow", "guard", "guava", "guitar", "gull", "haddock", "hail", "hall", "hammer",
+    "happy", "harbour", "hardy", "hare", "harp", "hasty", "hat", "hawk", "hazel", "hearty",
+    "heath", "heather", "hedge", "helmet", "hen", "heron", "herring", "hill", "hippo", "hoe",
+    "holly", "home", "honey", "hood", "hook", "hoop", "hop", "horn", "hornet", "horse", "house",
+    "hum", "humble", "hunter", "hut", "iceberg", "icy", "igloo", "iguana", "indigo", "ink",
+    "inlet", "iris", "iron", "island", "islet", "ivory", "ivy", "jackdaw", "jacket", "jade",
+    "jaguar", "jam", "jasmine", "jelly", "jet", "jewel", "jigsaw", "jolly", "judge", "jug", "jump",
+    "jumper", "jumpy", "jungle", "juniper", "kale", "kayak", "keel", "keen", "kernel", "kestrel",
+    "kettle", "kick", "kind", "king", "kitchen", "kite", "kiwi", "knife", "knight", "knit",
+    "koala", "lace", "ladder", "ladle", "lady", "lagoon", "lake", "lamb", "lamp", "lane",
+    "lantern", "larch", "laugh", "laurel", "lazy", "lead", "leaf", "leather", "leek", "lemon",
+    "lemur", "leopard", "letter", "lettuce", "library", "lichen", "lift", "lilac", "lily", "lime",
+    "linen", "lion", "lively", "lizard", "llama", "lobster", "lock", "locket", "locust", "lodge",
+    "lofty", "lord", "lotus", "loyal", "lucky", "lute", "lynx", "lyre", "magenta", "magnet",
+    "magpie", "maiden", "mango", "manor", "map", "maple", "marble", "march", "market", "maroon",
+    "marrow", "marsh", "mast", "meadow", "medal", "mellow", "melon", "mend", "merry", "mesa",
+    "meteor", "mighty", "mill", "miner", "mint", "mirror", "mist", "misty", "mitten", "mole",
+    "monk", "monkey", "moon", "moor", "moose", "mop", "moss", "moth", "mound", "mouse", "mud",
+    "muffin", "mug", "mule", "museum", "mussel", "mustard", "myrtle", "nail", "navy", "nebula",
+    "nectar", "needle", "net", "nettle", "newt", "nickel", "night", "nimble", "nine", "noble",
+    "noodle", "noon", "novel", "nun", "nurse", "nutmeg", "oak", "oar", "oasis", "oboe", "ocean",
+    "ochre", "octopus", "olive", "omega", "one", "onion", "onyx", "opal", "orange", "orbit",
+    "orchard", "orchid", "organ", "ostrich", "otter", "oven", "owl", "oyster", "paddle", "paint",
+    "palace", "palm", "pan", "pancake", "panda", "panther", "pantry", "papaya", "paper", "parcel",
+    "parlour", "parrot", "parsley", "parsnip", "pass", "pasta", "path", "patio", "pea", "peach",
+    "peacock", "peak", "peanut", "pear", "pearl", "pebble", "pecan", "peel", "pelican", "pen",
+    "pencil", "penguin", "pepper", "petal", "phoenix", "piano", "pickle", "pie", "pier", "pig",
+    "pigeon", "pillar", "pillow", "pilot", "pine", "pink", "pirate", "plain", "plane", "planet",
+    "plant", "plate", "plateau", "plucky", "plum", "pocket", "poem", "poet", "polite", "pollen",
+    "pond", "pony", "pool", "poplar", "poppy", "porch", "pot", "potato", "potter", "prairie",
+    "prawn", "pretzel", "prince", "prize", "proud", "puck", "puffin", "pull", "puma", "pumpkin",
+    "purple", "push", "puzzle", "python", "quail", "quartz", "queen", "quick", "quiet", "quill",
+    "quilt", "quince", "quiver", "rabbit", "racket", "radish", "raft", "rafter", "railway", "rain",
+    "rainbow", "raisin", "rake", "ranger", "rapid", "rapids", "rat", "raven", "ravine", "read",
+    "ready", "reap", "red", "reed", "reef", "rhino", "ribbon", "rice", "riddle", "ride", "ridge",
+    "ring", "river", "road", "roast", "robe", "robin", "rock", "rocket", "roll", "roof", "rook",
+    "root", "rope", "rose", "rosy", "row", "rowan", "ruby", "rudder", "rug", "ruler", "run",
+    "rush", "russet", "rustic", "saddle", "sage", "sail", "sailor", "salad", "salmon", "salt",
+    "sand", "sandal", "sandy", "sauce", "savanna", "saw", "scarf", "scarlet", "school", "scone",
+    "scooter", "scout", "screw", "sea", "seal", "season", "sedge", "seed", "seven", "sew", "shark",
+    "sharp", "shears", "shed", "sheep", "shelf", "shell", "shiny", "ship", "shirt", "shoe", "shop",
+    "shore", "shout", "shovel", "shrew", "shrimp", "shy", "sieve", "sigma", "signal", "silk",
+    "silky", "silly", "silver", "sing", "singer", "six", "sketch", "skip", "skirt", "sky", "slate",
+    "sleepy", "sleet", "sleeve", "slice", "slide", "slipper", "slope", "sloth", "slug", "smart",
+    "smile", "smith", "smooth", "snail", "snake", "snow", "snug", "soar", "sock", "sofa", "soft",
+    "soup", "sow", "spade", "sparrow", "spider", "spin", "spinach", "spoon", "spring", "sprout",
+    "spruce", "squash", "squid", "squire", "stable", "stamp", "star", "station", "statue",
+    "steady", "steppe", "stew", "stir", "stone", "stool", "store", "stork", "storm", "story",
+    "stove", "strait", "stream", "street", "stroll", "study", "sturdy", "sugar", "summer",
+    "summit", "sun", "sunny", "sunrise", "sunset", "swamp", "swan", "sweater", "sweet", "swift",
+    "swim", "swing", "syrup", "table", "tailor", "tall", "tape", "target", "tart", "tassel",
+    "teal", "teapot", "temple", "ten", "tern", "terrace", "theatre", "thirty", "thistle", "thorn",
+    "thread", "three", "throw", "thrush", "thunder", "thyme", "ticket", "tide", "tidy", "tiger",
+    "timber", "tin", "tinsel", "tiny", "toad", "toast", "toaster", "toffee", "token", "tomato",
+    "topaz", "tower", "track", "trail", "train", "tram", "tree", "troll", "trophy", "trout",
+    "trowel", "truck", "trumpet", "tuba", "tulip", "tuna", "tundra", "tunnel", "turkey", "turnip",
+    "turtle", "twelve", "twenty", "twig", "twirl", "two", "umber", "unicorn", "vale", "valley",
+    "van", "vanilla", "velvet", "vest", "villa", "violet", "violin", "viper", "vivid", "volcano",
+    "vole", "waffle", "wagon", "walk", "wall", "walnut", "walrus", "warm", "wasp", "water", "wave",
+    "weave", "weaver", "well", "whale", "wharf", "whisk", "whistle", "white", "wicker", "wild",
+    "willow", "wind", "window", "windy", "winter", "wise", "witch", "witty", "wizard", "wok",
+    "wolf", "wood", "wool", "woolly", "worm", "wren", "write", "yacht", "yarn", "yellow", "yew",
+    "yogurt", "young", "yoyo", "zany", "zebra", "zephyr", "zero", "zesty", "zinc", "zipper",
+];
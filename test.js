@@ -1,7 +1,7 @@
 #!/usr/bin/env node
 
 import http from 'node:http';
-import { NearMyPostcode } from './nearmypostcode.mjs';
+import { NearMyPostcode, NearMyPostcodeLayered } from './nearmypostcode.mjs';
 import fs from 'node:fs';
 const data = await fs.openAsBlob('postcodes.pack');
 const databuf = await data.arrayBuffer();
@@ -91,6 +91,74 @@ describe('NearMyPostcode()', () => {
         await assert.throws(() => nmp.lookup_postcode('A1'), new Error(nmp.E_DATA_VERSION));
     });
 
+    it('should be able to load files created for version 3 of the algorithm', async () => {
+        const v3data = await fs.openAsBlob('testdata/version=3/A0AA0AA=>(0,0).pack');
+        const v3databuf = await v3data.arrayBuffer();
+        const nmp = await NearMyPostcode(v3databuf, true);
+        const [cpc, [lon,lat]] = nmp.lookup_postcode('a0aa0aa');
+        assert.equal('A0AA0AA', cpc);
+        // The bounding box is quantized in version 3, so the location is not exact
+        assert(Math.abs(lon) < 0.000001, `lon value incorrect: expected 0 but got ${lon}`);
+        assert(Math.abs(lat) < 0.000001, `lat value incorrect: expected 0 but got ${lat}`);
+        assert.equal('A0AA', nmp.lookup_postcode('a0aa')[0]);
+    });
+
+    it('should check the checksums in files created for version 10 of the algorithm', async () => {
+        const v10data = await fs.openAsBlob('testdata/version=10/A0AA0AA=>(0,0).pack');
+        const v10bytes = await v10data.bytes();
+        const nmp = await NearMyPostcode(v10bytes.slice().buffer, true);
+        assert.equal('A0AA0AA', nmp.lookup_postcode('a0aa0aa')[0]);
+        assert.equal('A0AA', nmp.lookup_postcode('a0aa')[0]);
+
+        // A truncated or damaged file should be rejected, rather than giving wrong answers
+        await assert.rejects(async () => NearMyPostcode(v10bytes.slice(0, v10bytes.length - 100).buffer, true), /corrupt or truncated/);
+        const damaged = v10bytes.slice();
+        damaged[damaged.length - 100] ^= 1;
+        await assert.rejects(async () => NearMyPostcode(damaged.buffer, true), /corrupt or truncated/);
+    });
+
+    it('should find the same results as the Rust reader (testdata/vectors, vectors-v12, vectors-v13, vectors-v14, vectors-v15, vectors-v16, vectors-v17, vectors-v18, vectors-v19, vectors-v20, vectors-v21, vectors-v22, vectors-v23, vectors-v24, vectors-v25 and vectors-v26, from gen-test-vectors)', async () => {
+        for (const dir of ['testdata/vectors', 'testdata/vectors-v12', 'testdata/vectors-v13', 'testdata/vectors-v14', 'testdata/vectors-v15', 'testdata/vectors-v16', 'testdata/vectors-v17', 'testdata/vectors-v18', 'testdata/vectors-v19', 'testdata/vectors-v20', 'testdata/vectors-v21', 'testdata/vectors-v22', 'testdata/vectors-v23', 'testdata/vectors-v24', 'testdata/vectors-v25', 'testdata/vectors-v26']){
+            const vectors = JSON.parse(fs.readFileSync(`${dir}/vectors.json`, 'utf8'));
+            const vdata = await fs.openAsBlob(`${dir}/vectors.pack`);
+            const nmp = await NearMyPostcode(await vdata.arrayBuffer(), true);
+            assert.equal(vectors.date, nmp.date_last_updated.getTime() / 1000);
+            assert.equal(vectors.release_date ?? null, nmp.date_released && nmp.date_released.getTime() / 1000);
+            assert.deepEqual(vectors.metadata ?? {}, nmp.metadata);
+            for (const v of vectors.lookups){
+                const [cpc, [lon,lat]] = nmp.lookup_postcode(v.query);
+                assert.equal(v.postcode, cpc);
+                assert(Math.abs(lon - v.long) < 1e-9, `lon value incorrect for postcode ${cpc} in ${dir}: expected ${v.long} but got ${lon}`);
+                assert(Math.abs(lat - v.lat) < 1e-9, `lat value incorrect for postcode ${cpc} in ${dir}: expected ${v.lat} but got ${lat}`);
+                for (const [name, value] of Object.entries(v.attributes ?? {})){
+                    assert.equal(value, nmp.lookup_attribute(v.query, name), `${name} of ${cpc} in ${dir}`);
+                }
+            }
+            for (const e of vectors.extents ?? []){
+                const extent = nmp.lookup_extent(e.query);
+                if (e.min === null){
+                    assert.equal(extent, null, e.query);
+                    continue;
+                }
+                for (const [found, expected] of [[extent[0], e.min], [extent[1], e.max]]){
+                    assert(Math.abs(found[0] - expected[0]) < 1e-9 && Math.abs(found[1] - expected[1]) < 1e-9, `extent of ${e.query} in ${dir}: expected ${expected} but got ${found}`);
+                }
+            }
+            for (const c of vectors.completions ?? []){
+                assert.deepEqual(nmp.complete(c.query), c.results, `completions of ${c.query} in ${dir}`);
+            }
+            for (const p of vectors.prefixes ?? []){
+                assert.equal(nmp.prefix_exists(p.query), p.exists, `${p.query} in ${dir}`);
+            }
+            for (const pc of vectors.not_found){
+                assert.throws(() => nmp.lookup_postcode(pc), new Error(nmp.E_NOTFOUND), pc);
+            }
+            for (const pc of vectors.invalid){
+                assert.throws(() => nmp.lookup_postcode(pc), new Error(nmp.E_FORMAT), pc);
+            }
+        }
+    });
+
     it('should not load files without the right header ID', async () => {
         const brokendata= await fs.openAsBlob('testdata/invalid.pack');
         const brokenbuf = await brokendata.arrayBuffer();
@@ -133,5 +201,56 @@ describe('NearMyPostcode()', () => {
             });
         });
     });
+
+    it('should load layer 0 of a layered pack first, and look up postcodes in it approximately (testdata/layered, from --layered)', async () => {
+        const server = http.createServer((req, res) => {
+            const file = `testdata/layered${req.url}`;
+            if (!req.url.includes('..') && fs.existsSync(file)){
+                res.writeHead(200);
+                res.end(fs.readFileSync(file));
+            }
+            else{
+                res.writeHead(404);
+                res.end();
+            }
+        });
+        await new Promise((resolve) => server.listen(9877, 'localhost', resolve));
+        try{
+            let layer0;
+            const full = await new Promise((resolve, reject) => {
+                NearMyPostcodeLayered('http://localhost:9877/postcodes.layers.json', resolve, reject, true).then((nmp) => layer0 = nmp, reject);
+            });
+            const vectors = JSON.parse(fs.readFileSync('testdata/vectors/vectors.json', 'utf8'));
+            // Layer 0 has one postcode from each sector
+            const sectors = new Map();
+            const missing = [];
+            for (const v of vectors.lookups){
+                const [cpc] = full.lookup_postcode(v.query);
+                try{
+                    const [, location] = layer0.lookup_postcode(v.query);
+                    if (cpc.length == 7){
+                        assert(!sectors.has(cpc.slice(0,5)), `two postcodes in sector ${cpc.slice(0,5)}`);
+                        sectors.set(cpc.slice(0,5), location);
+                    }
+                }
+                catch{
+                    missing.push(v.query);
+                }
+            }
+            assert(missing.length > 0);
+            // Every other postcode is found approximately, at the one in its sector
+            for (const query of missing){
+                const [cpc, [lon,lat]] = layer0.lookup_postcode(query, true);
+                const [long0, lat0] = sectors.get(cpc.slice(0,5));
+                assert(Math.abs(lon - long0) < 1e-9 && Math.abs(lat - lat0) < 1e-9, `${cpc} is not at the postcode in its sector`);
+            }
+            // Outward codes that don't exist are still not found
+            assert.throws(() => layer0.lookup_postcode('ZZ9 9ZZ', true), new Error(layer0.E_NOTFOUND));
+            await assert.rejects(async () => NearMyPostcodeLayered('http://localhost:9877/missing.json', () => {}));
+        }
+        finally{
+            server.close();
+        }
+    });
 });
 
@@ -0,0 +1,69 @@
+/*
+
+Tests of finding postcodes whose locations are far outside the region of their postcode area.
+
+*/
+use nearmypostcode_packer::{Packer, StreamPacker, ReadOptions, PostcodeInfo, Point, Scheme, format_postcode};
+
+/// Postcodes around Aberdeen and York, with one AB postcode in Cornwall, one YO postcode with
+/// its latitude and longitude swapped, and a small area of postcodes far apart from each other
+fn postcodes() -> Vec<PostcodeInfo>{
+    let mut rows = Vec::new();
+    for i in 0..30{
+        let step = i as f64 * 0.003;
+        let unit = ["A", "B", "D"][i / 10];
+        rows.push((format!("AB10 {}{unit}A", i % 10), 57.14 + step, -2.10 - step));
+        rows.push((format!("YO1 {}{unit}B", i % 10), 53.96 - step, -1.08 + step));
+    }
+    rows.push(("AB10 9ZZ".to_string(), 50.26, -5.05));
+    rows.push(("YO1 9ZZ".to_string(), -1.08, 53.96));
+    rows.push(("ZE1 0AA".to_string(), 60.15, -1.15));
+    rows.push(("ZE1 0AB".to_string(), 51.5, -0.1));
+    rows.into_iter()
+        .map(|(code, y, x)| PostcodeInfo{
+            postcode: format_postcode(&code).unwrap(),
+            location: Point{x, y},
+            is_partial: false,
+            terminated: None,
+            country: None,
+            introduced: None,
+            attributes: Vec::new(),
+        })
+        .collect()
+}
+
+#[test]
+fn stray_postcodes_are_found(){
+    let all = postcodes();
+    let packer = Packer::from_postcodes(all.clone(), 0, Scheme::Uk);
+    let anomalies = packer.anomalies();
+    let found: Vec<(&str, &str)> = anomalies.iter().map(|a| (a.postcode.as_str(), a.area.as_str())).collect();
+    // ZE only has two postcodes, too few to tell which of them is out of place
+    assert_eq!(found, [(format_postcode("AB10 9ZZ").unwrap().as_str(), "AB"), (format_postcode("YO1 9ZZ").unwrap().as_str(), "YO")]);
+    // Cornwall is hundreds of kilometres from the region of the AB postcodes
+    assert!(anomalies[0].distance > 300_000.0, "{}", anomalies[0].distance);
+    let (min, max) = anomalies[0].region;
+    assert!(min.y < 57.14 && max.y > 57.14 + 0.087 && min.x < -2.187 && max.x > -2.10);
+
+    // Without the strays, nothing is out of place
+    let good: Vec<PostcodeInfo> = all.into_iter().filter(|p| !p.postcode.ends_with("9ZZ")).collect();
+    assert!(Packer::from_postcodes(good, 0, Scheme::Uk).anomalies().is_empty());
+}
+
+#[test]
+fn the_low_memory_packer_finds_the_same(){
+    let path = std::env::temp_dir().join(format!("nmp-anomaly-{}.csv", std::process::id()));
+    let mut csv = "pcds,dointr,doterm,lat,long\n".to_string();
+    for p in postcodes(){
+        csv += &format!("{},201001,,{},{}\n", p.postcode, p.location.y, p.location.x);
+    }
+    std::fs::write(&path, csv).unwrap();
+    let path = path.to_string_lossy();
+    let packer = Packer::from_csv_with_options(&path, &ReadOptions::default()).unwrap();
+    let stream = StreamPacker::from_csv_with_options(&path, &ReadOptions::default()).unwrap();
+    let codes = |anomalies: Vec<nearmypostcode_packer::Anomaly>| anomalies.into_iter().map(|a| a.postcode).collect::<Vec<_>>();
+    let found = codes(packer.anomalies());
+    assert_eq!(found.len(), 2);
+    assert_eq!(codes(stream.anomalies().unwrap()), found);
+    std::fs::remove_file(&*path).unwrap();
+}
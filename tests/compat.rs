@@ -0,0 +1,118 @@
+/*
+
+Tests that files of every format version can still be read. Pack files are cached by consumers,
+in production and in browsers, long after they were made, so the reader never drops a version.
+
+The archived files in testdata/version=N were written by the packer as it was for that version,
+and each has one postcode, A0AA0AA, at (0,0). The golden files cover every version that the
+packer can still write. Files of any version can be upgraded to the newest one.
+
+*/
+use std::path::{Path, PathBuf};
+use nearmypostcode_packer::{Reader, PostcodeError, Validity, WRITABLE_VERSIONS, NEWEST_VERSION};
+use nearmypostcode_packer::repack;
+
+fn testdata() -> PathBuf{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata")
+}
+
+fn archived(version: u32) -> Result<Reader, PostcodeError>{
+    Reader::open(&testdata().join(format!("version={version}")).join("A0AA0AA=>(0,0).pack").to_string_lossy())
+}
+
+#[test]
+fn archived_files_are_read(){
+    for version in [1, 3, 10]{
+        let reader = archived(version).unwrap();
+        assert_eq!(reader.version(), version);
+        let location = reader.lookup("A0AA0AA").unwrap();
+        assert!(location.x.abs() < 1e-6 && location.y.abs() < 1e-6, "version {version} reads A0AA0AA at {location:?}");
+        let entries: Vec<String> = reader.iter().filter(|p| !p.is_partial).map(|p| p.postcode).collect();
+        assert_eq!(entries, ["A0AA0AA"], "version {version}");
+        assert_eq!(reader.nearest(0.0, 0.0).unwrap().postcode, "A0AA0AA");
+        assert_eq!(reader.validate("A0AA 0AA"), Validity::Exists);
+        assert!(reader.lookup("A0AA0AB").is_none());
+    }
+}
+
+#[test]
+fn version_1_has_no_outward_codes(){
+    let reader = archived(1).unwrap();
+    assert!(reader.lookup("A0AA").is_none());
+
+    // Version 1 records are the same as version 2's, but a version 1 reader doesn't know about
+    // outward codes, so a version 2 file labelled as version 1 reads the same without them
+    let mut data = std::fs::read(testdata().join("golden").join("v2.pack")).unwrap();
+    let v2 = Reader::from_bytes(data.clone()).unwrap();
+    data[4..8].copy_from_slice(&1u32.to_le_bytes());
+    let v1 = Reader::from_bytes(data).unwrap();
+    assert_eq!(v1.version(), 1);
+    let full = |r: &Reader| r.iter().filter(|p| !p.is_partial).map(|p| (p.postcode, p.location.x, p.location.y)).collect::<Vec<_>>();
+    assert!(!full(&v1).is_empty());
+    assert_eq!(full(&v1), full(&v2));
+    assert!(v2.lookup("AB10").is_some());
+    assert!(v1.lookup("AB10").is_none());
+}
+
+#[test]
+fn unknown_versions_are_refused(){
+    assert!(matches!(archived(999999), Err(PostcodeError::UnsupportedVersion(999999))));
+    assert!(matches!(Reader::open(&testdata().join("invalid.pack").to_string_lossy()), Err(PostcodeError::NotAPackFile())));
+}
+
+#[test]
+fn header_lengths_that_dont_fit_are_refused(){
+    // The count of local bounding boxes, and the length of the metadata, which overflow a usize
+    // on 32-bit targets (e.g. with --target i686-unknown-linux-gnu)
+    for pos in [300, 336]{
+        let mut data = std::fs::read(testdata().join("golden").join("v25.pack")).unwrap();
+        data[pos..pos+4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let len = data.len();
+        let crc = crc32fast::hash(&data[..len-4]);
+        data[len-4..].copy_from_slice(&crc.to_le_bytes());
+        assert!(matches!(Reader::from_bytes(data), Err(PostcodeError::NotAPackFile()) | Err(PostcodeError::Corrupt(_))), "length at {pos}");
+    }
+}
+
+#[test]
+fn every_writable_version_is_read(){
+    let golden = Reader::open(&testdata().join("golden").join("v2.pack").to_string_lossy()).unwrap();
+    for version in WRITABLE_VERSIONS{
+        let reader = Reader::open(&testdata().join("golden").join(format!("v{version}.pack")).to_string_lossy()).unwrap();
+        assert_eq!(reader.version(), version);
+        reader.check_checksums().unwrap();
+        for p in golden.iter().filter(|p| !p.is_partial){
+            let location = reader.lookup(&p.postcode).unwrap_or_else(|| panic!("version {version} is missing {}", p.postcode));
+            assert!((location.x - p.location.x).abs() < 1e-3 && (location.y - p.location.y).abs() < 1e-3, "version {version} reads {} at {location:?}", p.postcode);
+        }
+    }
+}
+
+#[test]
+fn old_files_are_upgraded_to_the_newest_version(){
+    let upgraded = |reader: &Reader| {
+        let mut data = Vec::new();
+        repack::upgrade(reader).write_to(&mut data).unwrap();
+        Reader::from_bytes(data).unwrap()
+    };
+    let new = upgraded(&archived(1).unwrap());
+    assert_eq!(new.version(), NEWEST_VERSION);
+    assert!(new.lookup("A0AA0AA").is_some());
+    // Outward codes are worked out again, so the upgraded file has them
+    assert!(new.lookup("A0AA").is_some());
+
+    for version in [2, 9, 19, 24]{
+        let old = Reader::open(&testdata().join("golden").join(format!("v{version}.pack")).to_string_lossy()).unwrap();
+        let new = upgraded(&old);
+        assert_eq!(new.version(), NEWEST_VERSION);
+        assert_eq!(new.last_update(), old.last_update());
+        assert_eq!(new.has_spatial_index(), old.has_spatial_index());
+        assert_eq!(new.country_runs().is_some(), old.country_runs().is_some());
+        assert_eq!(new.attribute_names(), old.attribute_names());
+        for p in old.iter().filter(|p| !p.is_partial){
+            let location = new.lookup(&p.postcode).unwrap();
+            assert!((location.x - p.location.x).abs() < 1e-3 && (location.y - p.location.y).abs() < 1e-3, "{} moved upgrading version {version}", p.postcode);
+            assert_eq!(new.iter().find(|q| q.postcode == p.postcode).unwrap().terminated, p.terminated);
+        }
+    }
+}
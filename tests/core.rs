@@ -0,0 +1,136 @@
+/*
+
+Tests of the decoding core, which decodes the blocks of a file without the rest of the reader.
+
+*/
+use std::path::Path;
+use nearmypostcode_packer::{Packer, Reader, NEWEST_VERSION};
+use nearmypostcode_packer::repack;
+use nearmypostcode_core::Records;
+
+#[test]
+fn blocks_decode_without_the_reader(){
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv");
+    let packer = Packer::from_csv(&golden.to_string_lossy(), &[]).unwrap();
+    for version in [3, 6, 12, 13, NEWEST_VERSION]{
+        let mut packer = packer.clone();
+        packer.set_format_version(version).unwrap();
+        let mut data = Vec::new();
+        packer.write_to(&mut data).unwrap();
+        let reader = Reader::from_bytes(data.clone()).unwrap();
+        let entries: Vec<_> = reader.iter().collect();
+
+        let mut n = 0;
+        let mut terminated = 0;
+        for (_, start, end) in reader.block_ranges(){
+            for record in Records::new(&data[start..end], version, reader.resolution()){
+                n += 1;
+                terminated += record.terminated.is_some() as usize;
+            }
+        }
+        assert_eq!(n, entries.len(), "version {version}");
+        assert_eq!(terminated, entries.iter().filter(|p| p.terminated.is_some()).count(), "version {version}");
+    }
+}
+
+#[test]
+fn postcode_deltas_that_overflow_end_the_block(){
+    // A varint record with the largest postcode, then a run that would go past it
+    let block = [0x42, 0xfe, 0xff, 0xff, 0xff, 0x0f, 0, 0, 0x41, 1, 5, 5];
+    let records: Vec<_> = Records::new(&block, 13, 0).collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].code, u32::MAX);
+    // And a fixed size record with a postcode delta
+    let block = [0x42, 0xfe, 0xff, 0xff, 0xff, 0x0f, 0, 0, 0xc0, 0, 0];
+    assert_eq!(Records::new(&block, 13, 0).count(), 1);
+}
+
+#[test]
+fn truncated_blocks_end_at_the_last_whole_record(){
+    // An absolute record cut short, a varint with no last byte, and a run with no length
+    assert_eq!(Records::new(&[0x00, 1, 2, 3, 4, 5], 6, 24).count(), 0);
+    assert_eq!(Records::new(&[0x42, 0x80], 13, 24).count(), 0);
+    assert_eq!(Records::new(&[0x41], 13, 24).count(), 0);
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv");
+    let mut data = Vec::new();
+    Packer::from_csv(&golden.to_string_lossy(), &[]).unwrap().write_to(&mut data).unwrap();
+    let reader = Reader::from_bytes(data.clone()).unwrap();
+    for (prefix, start, end) in reader.block_ranges(){
+        let whole: Vec<_> = Records::new(&data[start..end], reader.version(), reader.resolution()).collect();
+        for cut in start..end{
+            let part: Vec<_> = Records::new(&data[start..cut], reader.version(), reader.resolution()).collect();
+            // Each record that is read is one of the whole block's, and the rest are whole records
+            assert!(part.len() < whole.len(), "{prefix} cut at {cut}");
+            assert!(part.iter().zip(&whole).all(|(a, b)| a.code == b.code && a.len == b.len), "{prefix} cut at {cut}");
+            assert!(part.iter().map(|r| r.len).sum::<usize>() <= cut - start);
+        }
+    }
+}
+
+/// A testdata pack file with the start of its largest block replaced by `records`, and the rest
+/// of the block by a varint record that is cut short, with the checksum of the whole file fixed
+/// up so that it still opens
+fn hostile(name: &str, records: &[u8]) -> Vec<u8>{
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(name).join("vectors.pack");
+    let mut data = std::fs::read(path).unwrap();
+    let reader = Reader::from_bytes(data.clone()).unwrap();
+    let (_, start, end) = reader.block_ranges().into_iter().max_by_key(|(_, start, end)| end - start).unwrap();
+    assert!(end - start > records.len());
+    data[start..start+records.len()].copy_from_slice(records);
+    data[start+records.len()] = 0x42;
+    data[start+records.len()+1..end].fill(0x80);
+    let len = data.len();
+    let crc = crc32fast::hash(&data[..len-4]);
+    data[len-4..].copy_from_slice(&crc.to_le_bytes());
+    data
+}
+
+#[test]
+fn location_deltas_that_overflow_are_counted(){
+    // A latitude of i32::MAX, then one more
+    let data = hostile("vectors-v13", &[0x42, 0, 0xfe, 0xff, 0xff, 0xff, 0x0f, 0, 0x42, 0, 2, 0]);
+    let reader = Reader::from_bytes(data).unwrap();
+    let stats = reader.file_stats();
+    assert!(stats.record_kinds.iter().sum::<usize>() < 192);
+}
+
+#[test]
+fn locations_outside_the_resolution_end_the_block(){
+    // An absolute record with a latitude of 0xffff, which needs 16 bits
+    let block = [0x00, 1, 0, 0, 0xff, 0xff, 0, 0];
+    assert_eq!(Records::new(&block, 16, 16).count(), 1);
+    assert_eq!(Records::new(&block, 16, 12).count(), 0);
+    // A varint record that takes the longitude below zero, which wraps around
+    let block = [0x00, 1, 0, 0, 0, 0, 0, 0, 0x42, 0, 0, 1];
+    assert_eq!(Records::new(&block, 16, 16).count(), 1);
+}
+
+#[test]
+fn damaged_files_are_not_packed_again(){
+    // A varint record with a postcode delta of 2^22 and both locations at 2^30, which is 15 bytes
+    // and far outside the resolution
+    let data = hostile("vectors-v16", &[0x42, 0x80, 0x80, 0x80, 0x02, 0x80, 0x80, 0x80, 0x80, 0x08, 0x80, 0x80, 0x80, 0x80, 0x08]);
+    let reader = Reader::from_bytes(data).unwrap();
+    assert!(reader.iter().count() < 192);
+    // inspect, repack and merge re-encode what was read
+    reader.order_lens().unwrap();
+    repack::upgrade(&reader).write_to(Vec::new()).unwrap();
+    let (merged, _) = repack::merge(&[reader.clone(), reader]).unwrap();
+    merged.write_to(Vec::new()).unwrap();
+}
+
+#[test]
+fn fuzz_seeds_do_not_panic(){
+    // The damaged files that the reader fuzz target starts from, read as it reads them
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz").join("seeds").join("reader");
+    for file in std::fs::read_dir(dir).unwrap(){
+        let Ok(reader) = Reader::from_bytes(std::fs::read(file.unwrap().path()).unwrap()) else {
+            continue;
+        };
+        let _ = reader.file_stats();
+        let _ = reader.order_lens();
+        let _ = reader.suggest("AB1 0AA", 2);
+        let _ = reader.complete_postcodes("AB1", 20);
+    }
+}
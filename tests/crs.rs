@@ -0,0 +1,82 @@
+/*
+
+Tests of the coordinate reference system of a pack file: the locations are shifted as they're
+read, and the file says which system they're in.
+
+*/
+use std::path::Path;
+use nearmypostcode_packer::{Packer, Reader, ReadOptions, PostcodeError, Crs, Point, NEWEST_VERSION, distance_m, format_postcode};
+use nearmypostcode_packer::repack;
+
+fn golden() -> String{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv").to_string_lossy().to_string()
+}
+
+fn pack_bytes(crs: Crs) -> Vec<u8>{
+    let mut packer = Packer::from_csv_with_options(&golden(), &ReadOptions{crs, ..Default::default()}).unwrap();
+    packer.set_format_version(NEWEST_VERSION).unwrap();
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    data
+}
+
+fn pack(crs: Crs) -> Reader{
+    Reader::from_bytes(pack_bytes(crs)).unwrap()
+}
+
+#[test]
+fn locations_are_shifted_and_recorded(){
+    let wgs84 = pack(Crs::Wgs84);
+    let osgb36 = pack(Crs::Osgb36);
+    let etrs89 = pack(Crs::Etrs89);
+    assert_eq!((wgs84.crs(), osgb36.crs(), etrs89.crs()), (Crs::Wgs84, Crs::Osgb36, Crs::Etrs89));
+    let postcode = format_postcode("SW1A 1AA").unwrap();
+    let location = |r: &Reader| r.lookup(&postcode).unwrap();
+    // OSGB36 is about 100m from WGS84 in London, mostly to the east, and ETRS89 under a metre
+    let (a, b) = (location(&wgs84), location(&osgb36));
+    assert!((80.0..150.0).contains(&distance_m(&a, &b)), "{a:?} {b:?}");
+    assert!(b.x > a.x);
+    assert!(distance_m(&a, &location(&etrs89)) < 2.0);
+
+    // The system is kept by upgrades
+    let mut upgraded = Vec::new();
+    repack::upgrade(&osgb36).write_to(&mut upgraded).unwrap();
+    assert_eq!(Reader::from_bytes(upgraded).unwrap().crs(), Crs::Osgb36);
+}
+
+#[test]
+fn the_shift_to_osgb36_undoes_the_grid_conversion(){
+    // Trafalgar Square, from its National Grid reference. The expected location is the grid
+    // reference projected back to OSGB36 without going through WGS84.
+    let wgs84 = nearmypostcode_packer::osgb::osgb36_to_wgs84(530000.0, 180400.0);
+    let osgb36 = Crs::Osgb36.from_wgs84(wgs84);
+    assert_eq!(distance_m(&Crs::Wgs84.from_wgs84(wgs84), &wgs84), 0.0);
+    let expected = Point{x: -0.126600, y: 51.507075};
+    assert!(distance_m(&osgb36, &expected) < 1.0, "{osgb36:?}");
+}
+
+#[test]
+fn other_systems_need_version_26_and_cant_be_merged(){
+    let mut packer = Packer::from_csv_with_options(&golden(), &ReadOptions{crs: Crs::Osgb36, ..Default::default()}).unwrap();
+    packer.set_format_version(25).unwrap();
+    assert!(matches!(packer.write_to(Vec::new()), Err(PostcodeError::IncompatibleOptions(_))));
+
+    let merged = repack::merge(&[pack(Crs::Wgs84), pack(Crs::Osgb36)]);
+    assert!(matches!(merged, Err(PostcodeError::IncompatibleOptions(_))));
+    let (packer, _) = repack::merge(&[pack(Crs::Etrs89), pack(Crs::Etrs89)]).unwrap();
+    assert_eq!(packer.crs(), Crs::Etrs89);
+}
+
+#[test]
+fn readers_that_dont_know_the_system_refuse_the_file(){
+    // Give the CRS section a kind that this version doesn't know, as if it were an older reader,
+    // and fix the file's checksum
+    let mut data = pack_bytes(Crs::Osgb36);
+    let sections = Reader::from_bytes(data.clone()).unwrap().sections();
+    let entry = 20 + (16 * sections.iter().position(|(name, _, _)| *name == "crs").unwrap());
+    data[entry..entry+2].copy_from_slice(&15u16.to_le_bytes());
+    let body = data.len() - 4;
+    let crc = crc32fast::hash(&data[..body]);
+    data[body..].copy_from_slice(&crc.to_le_bytes());
+    assert!(matches!(Reader::from_bytes(data), Err(PostcodeError::UnsupportedSection(15))));
+}
@@ -0,0 +1,76 @@
+/*
+
+Tests of comparing two releases, as pack files or as lists of postcodes, and of the release
+notes written from the differences.
+
+*/
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme, format_postcode};
+use nearmypostcode_packer::diff::{diff, diff_postcodes, release_notes, DEFAULT_MIN_MOVE};
+
+fn postcode(code: &str, y: f64, x: f64) -> PostcodeInfo{
+    PostcodeInfo{
+        postcode: format_postcode(code).unwrap(),
+        location: Point{x, y},
+        is_partial: false,
+        terminated: None,
+        country: None,
+        introduced: None,
+        attributes: Vec::new(),
+    }
+}
+
+fn releases() -> (Vec<PostcodeInfo>, Vec<PostcodeInfo>){
+    let old = vec![
+        postcode("AB10 1AA", 57.1434, -2.0983),
+        postcode("AB10 1AB", 57.1435, -2.0981),
+        postcode("CB2 1TN", 52.2026, 0.1179),
+        postcode("SW1A 1AA", 51.501009, -0.141588),
+        postcode("ZE1 0AA", 60.1530, -1.1490),
+    ];
+    let new = vec![
+        postcode("AB10 1AA", 57.1434, -2.0983),
+        // About 100m, not a notable move
+        postcode("CB2 1TN", 52.2035, 0.1179),
+        // About 1.1km
+        postcode("SW1A 1AA", 51.511009, -0.141588),
+        postcode("YO1 7HH", 53.96, -1.08),
+        postcode("YO1 7HJ", 53.96, -1.08),
+    ];
+    (old, new)
+}
+
+#[test]
+fn districts_and_moves_are_found(){
+    let (old, new) = releases();
+    let d = diff_postcodes(old.clone(), new.clone(), Scheme::Uk, DEFAULT_MIN_MOVE);
+    assert_eq!((d.old_len, d.new_len), (5, 5));
+    assert_eq!(d.added_districts, ["YO1"]);
+    assert_eq!(d.removed_districts, ["ZE1"]);
+    assert_eq!(d.removed.iter().map(|p| p.postcode.as_str()).collect::<Vec<_>>(), [format_postcode("AB10 1AB").unwrap(), format_postcode("ZE1 0AA").unwrap()]);
+    assert_eq!(d.moved.len(), 2);
+
+    // Pack files give the same differences, give or take the rounding of their locations
+    let pack = |postcodes: Vec<PostcodeInfo>| {
+        let mut data = Vec::new();
+        Packer::from_postcodes(postcodes, 0, Scheme::Uk).write_to(&mut data).unwrap();
+        Reader::from_bytes(data).unwrap()
+    };
+    let packed = diff(&pack(old), &pack(new), DEFAULT_MIN_MOVE);
+    assert_eq!((packed.added_districts, packed.removed_districts), (d.added_districts, d.removed_districts));
+    assert_eq!((packed.added.len(), packed.removed.len(), packed.moved.len()), (2, 2, 2));
+}
+
+#[test]
+fn release_notes_list_notable_changes(){
+    let (old, new) = releases();
+    let d = diff_postcodes(old, new, Scheme::Uk, DEFAULT_MIN_MOVE);
+    let notes = release_notes(&d, "old.pack", "new.csv", Scheme::Uk, 500.0);
+    assert!(notes.starts_with("# Changes from old.pack to new.csv\n"), "{notes}");
+    assert!(notes.contains("| Moved more than 500 m | 1 |"), "{notes}");
+    assert!(notes.contains("## New districts\n\n- YO1 (2 postcodes)\n"), "{notes}");
+    assert!(notes.contains("## Retired districts\n\n- ZE1\n"), "{notes}");
+    assert!(notes.contains("- AB10: AB10 1AB\n- ZE1: ZE1 0AA\n"), "{notes}");
+    // Only the long move is listed, with the postcode written with a space
+    assert!(notes.contains("| SW1A 1AA | 51.50101, -0.14159 | 51.51101, -0.14159 |"), "{notes}");
+    assert!(!notes.contains("CB2 1TN"), "{notes}");
+}
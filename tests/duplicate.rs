@@ -0,0 +1,92 @@
+/*
+
+Tests of postcodes that are in more than one row of the input: an error by default, or one row
+kept by the `on_duplicate` policy.
+
+*/
+use std::path::{Path, PathBuf};
+use nearmypostcode_packer::{Packer, StreamPacker, ReadOptions, PostcodeError, DropReason, OnDuplicate, Point, format_postcode};
+
+/// The golden input with SW1A 1AA moved to the end, and a stray row for it where it was, and an
+/// extra CSV file with a stray row for CB2 1TN, far to the east of everything else
+fn inputs(name: &str) -> (PathBuf, PathBuf){
+    let golden = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv")).unwrap();
+    let sw1a = golden.lines().find(|l| l.starts_with("SW1A 1AA")).unwrap();
+    let main = golden.replace(sw1a, "SW1A 1AA,198001,,E92000001,51.600000,-0.200000") + sw1a + "\n";
+    let dir = std::env::temp_dir();
+    let main_path = dir.join(format!("nmp-duplicate-{name}-{}.csv", std::process::id()));
+    let extra_path = dir.join(format!("nmp-duplicate-{name}-{}-extra.csv", std::process::id()));
+    std::fs::write(&main_path, main).unwrap();
+    std::fs::write(&extra_path, "pcds,lat,long\nCB2 1TN,52.300000,1.500000\n").unwrap();
+    (main_path, extra_path)
+}
+
+fn options(extra: &Path, on_duplicate: OnDuplicate) -> ReadOptions{
+    ReadOptions{
+        extra_csv: vec![extra.to_string_lossy().to_string()],
+        report_dropped: true,
+        on_duplicate,
+        ..Default::default()
+    }
+}
+
+fn location(packer: &Packer, postcode: &str) -> (f64, f64){
+    let canonical = format_postcode(postcode).unwrap();
+    let p = packer.postcodes().iter().find(|p| p.postcode == canonical).unwrap();
+    (p.location.y, p.location.x)
+}
+
+#[test]
+fn duplicates_are_an_error_by_default(){
+    let (main, extra) = inputs("error");
+    let err = Packer::from_csv_with_options(&main.to_string_lossy(), &options(&extra, OnDuplicate::Error)).unwrap_err();
+    assert!(matches!(err, PostcodeError::InputMalformed(_)));
+    assert!(err.to_string().contains("CB2 1TN is in more than one row"), "{err}");
+
+    // The low-memory packer finds them when it sorts the postcodes, and can't keep one of them
+    let stream = StreamPacker::from_csv_with_options(&main.to_string_lossy(), &options(&extra, OnDuplicate::Error)).unwrap();
+    assert!(matches!(stream.write_to(Vec::new()), Err(PostcodeError::InputMalformed(_))));
+    let stream = StreamPacker::from_csv_with_options(&main.to_string_lossy(), &options(&extra, OnDuplicate::First));
+    assert!(matches!(stream, Err(PostcodeError::IncompatibleOptions(_))));
+
+    // Without the extra file, only the SW1A 1AA rows are duplicates
+    let err = Packer::from_csv_with_options(&main.to_string_lossy(), &ReadOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("SW1A 1AA"), "{err}");
+    std::fs::remove_file(main).unwrap();
+    std::fs::remove_file(extra).unwrap();
+}
+
+#[test]
+fn one_row_of_each_duplicate_is_kept(){
+    let (main, extra) = inputs("kept");
+    let pack = |on_duplicate| Packer::from_csv_with_options(&main.to_string_lossy(), &options(&extra, on_duplicate)).unwrap();
+    let stray_sw1a = (51.6, -0.2);
+    let stray_cb2 = (52.3, 1.5);
+    let sw1a = (51.501009, -0.141588);
+    let cb2 = (52.2026, 0.1179);
+
+    let first = pack(OnDuplicate::First);
+    assert_eq!((location(&first, "SW1A 1AA"), location(&first, "CB2 1TN")), (stray_sw1a, cb2));
+    let last = pack(OnDuplicate::Last);
+    assert_eq!((location(&last, "SW1A 1AA"), location(&last, "CB2 1TN")), (sw1a, stray_cb2));
+    // The stray rows are further from the average of their outward codes
+    let nearest = pack(OnDuplicate::NearestCentroid);
+    assert_eq!((location(&nearest, "SW1A 1AA"), location(&nearest, "CB2 1TN")), (sw1a, cb2));
+
+    for packer in [&first, &last, &nearest]{
+        assert_eq!(packer.stats().duplicates, 2);
+        assert_eq!(packer.postcodes().iter().filter(|p| p.postcode == format_postcode("CB2 1TN").unwrap()).count(), 1);
+        let dropped: Vec<(&str, DropReason)> = packer.dropped().iter()
+            .filter(|d| d.reason == DropReason::Duplicate)
+            .map(|d| (d.postcode.as_str(), d.reason))
+            .collect();
+        assert_eq!(dropped, [("CB2 1TN", DropReason::Duplicate), ("SW1A 1AA", DropReason::Duplicate)]);
+        assert_eq!(packer.dropped().len(), packer.stats().skipped);
+    }
+    // The bounding box is around the rows that are kept
+    let east = |p: &Packer| { let (_, Point{x, ..}) = p.bounds(); x };
+    assert_eq!(east(&last), 1.5);
+    assert_eq!(east(&first), 0.1179);
+    std::fs::remove_file(main).unwrap();
+    std::fs::remove_file(extra).unwrap();
+}
@@ -0,0 +1,101 @@
+/*
+
+Tests of leaving postcodes out of a pack file by prefix, by regular expression, and by a list of
+prefixes in a file, of keeping only the postcodes with some prefixes, and of the list of every row
+that was left out.
+
+*/
+use std::path::Path;
+use nearmypostcode_packer::{Packer, StreamPacker, ReadOptions, DropReason, UnpackableReason, format_postcode};
+
+/// Postcodes that are packed from the golden input file with these options
+fn packed(options: &ReadOptions) -> (Vec<String>, usize){
+    let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv");
+    let packer = Packer::from_csv_with_options(&input.to_string_lossy(), options).unwrap();
+    let postcodes = packer.postcodes().iter().filter(|p| !p.is_partial).map(|p| p.postcode.clone()).collect();
+    (postcodes, packer.stats().excluded)
+}
+
+#[test]
+fn regexes_match_postcodes_with_one_space(){
+    let regexes = |r: &[&str]| ReadOptions{
+        exclude_regex: r.iter().map(|r| regex::Regex::new(r).unwrap()).collect(),
+        ..Default::default()
+    };
+    let (all, none) = packed(&ReadOptions::default());
+    assert_eq!(none, 0);
+
+    // The same as --exclude BT
+    let (kept, excluded) = packed(&regexes(&["^BT"]));
+    assert_eq!(excluded, 1);
+    assert_eq!(kept, packed(&ReadOptions::with_exclude(&["BT"])).0);
+
+    // "AB10 1A" only matches with a single space, not in the canonical form, which has none
+    let (kept, excluded) = packed(&regexes(&["^AB10 1A", "^SW1A 2"]));
+    assert_eq!(excluded, 4);
+    assert_eq!(kept.len(), all.len() - 4);
+    assert!(kept.contains(&format_postcode("AB10 6RN").unwrap()) && !kept.contains(&format_postcode("SW1A 2AA").unwrap()));
+}
+
+#[test]
+fn prefixes_can_be_listed_in_a_file(){
+    let path = std::env::temp_dir().join(format!("nmp-exclude-{}.txt", std::process::id()));
+    std::fs::write(&path, "# Licensed separately\nBT  # Northern Ireland\n\n   CB2\n").unwrap();
+    let mut options = ReadOptions::with_exclude(&["SW1A"]);
+    options.read_exclude_file(&path.to_string_lossy()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(options.exclude, ["SW1A", "BT", "CB2"]);
+    let (kept, excluded) = packed(&options);
+    assert_eq!(excluded, 7);
+    assert!(kept.iter().all(|p| p.starts_with("AB10")));
+
+    assert!(ReadOptions::default().read_exclude_file("no such file").is_err());
+}
+
+#[test]
+fn include_keeps_only_some_prefixes(){
+    let include = |prefixes: &[&str], exclude: &[&str]| ReadOptions{
+        include: prefixes.iter().map(|p| p.to_string()).collect(),
+        ..ReadOptions::with_exclude(exclude)
+    };
+    // Prefixes are matched against canonical postcodes, like exclusions
+    let (kept, excluded) = packed(&include(&["CB2", "SW1A1"], &[]));
+    assert_eq!(kept.len(), 5);
+    // Every other row but the terminated postcode and GIR 0AA, which are skipped for other reasons
+    assert_eq!(excluded, 6);
+    assert!(kept.iter().all(|p| p.starts_with("CB2") || p.starts_with("SW1A1")));
+
+    // A prefix of only letters is a whole area, so "S" isn't SW
+    assert!(packed(&include(&["S"], &[])).0.is_empty());
+    assert_eq!(packed(&include(&["SW", "B"], &[])).0.len(), 2);
+
+    // Exclusions still apply
+    let (kept, _) = packed(&include(&["AB"], &["AB101"]));
+    assert_eq!(kept, [format_postcode("AB10 6RN").unwrap()]);
+}
+
+#[test]
+fn every_dropped_row_is_listed(){
+    let golden = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv")).unwrap();
+    let path = std::env::temp_dir().join(format!("nmp-dropped-{}.csv", std::process::id()));
+    std::fs::write(&path, format!("{golden}E1 6AN,201001,,E92000001,north,-0.07\n,201001,,E92000001,51.5,-0.07\n")).unwrap();
+    let path = path.to_string_lossy();
+    let options = ReadOptions{report_dropped: true, ..ReadOptions::with_exclude(&["BT"])};
+    let packer = Packer::from_csv_with_options(&path, &options).unwrap();
+    let dropped: Vec<(&str, Option<u64>, DropReason)> = packer.dropped().iter().map(|d| (d.postcode.as_str(), d.line, d.reason)).collect();
+    assert_eq!(dropped, [
+        ("CB2 9ZZ", Some(10), DropReason::Terminated),
+        ("BT9 5AB", Some(13), DropReason::Excluded),
+        ("GIR 0AA", Some(14), DropReason::Unpackable(UnpackableReason::NoLocation)),
+        ("E1 6AN", Some(15), DropReason::ParseError),
+        ("", Some(16), DropReason::NoPostcode),
+    ]);
+    assert_eq!(dropped.len(), packer.stats().skipped);
+    assert_eq!(packer.dropped()[3].reason.name(), "parse-error");
+
+    // The low-memory packer gives the same list, and it's only kept when it's asked for
+    let stream = StreamPacker::from_csv_with_options(&path, &options).unwrap();
+    assert_eq!(stream.dropped().len(), dropped.len());
+    assert!(Packer::from_csv_with_options(&path, &ReadOptions::default()).unwrap().dropped().is_empty());
+    std::fs::remove_file(&*path).unwrap();
+}
@@ -0,0 +1,43 @@
+/*
+
+Tests of the FlatGeobuf output, read back with the flatgeobuf crate.
+
+*/
+#![cfg(feature="flatgeobuf")]
+use std::path::Path;
+use flatgeobuf::{FallibleStreamingIterator, FeatureProperties, FgbReader};
+use nearmypostcode_packer::{Packer, OutputFormat};
+
+#[test]
+fn flatgeobuf_has_each_postcode_and_an_index(){
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv");
+    let packer = Packer::from_csv(&golden.to_string_lossy(), &[]).unwrap();
+    let path = std::env::temp_dir().join(format!("nmp-fgb-{}.fgb", std::process::id()));
+    packer.write_as(OutputFormat::Flatgeobuf, &path.to_string_lossy()).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let full: Vec<_> = packer.postcodes().iter().filter(|p| !p.is_partial).collect();
+    let reader = FgbReader::open(std::io::Cursor::new(&data)).unwrap();
+    assert_eq!(reader.header().crs().map(|c| c.code()), Some(4326));
+    let mut features = reader.select_all().unwrap();
+    let mut read = Vec::new();
+    while let Some(feature) = features.next().unwrap(){
+        let xy = feature.geometry().unwrap().xy().unwrap();
+        read.push((feature.property::<String>("postcode").unwrap(), xy.get(0), xy.get(1)));
+    }
+    assert_eq!(read.len(), full.len());
+    for p in &full{
+        assert!(read.iter().any(|(postcode, x, y)| *postcode == p.postcode && *x == p.location.x && *y == p.location.y), "{}", p.postcode);
+    }
+
+    // The spatial index finds a postcode from a small box around it
+    let p = full[0];
+    let mut found = FgbReader::open(std::io::Cursor::new(&data)).unwrap()
+        .select_bbox(p.location.x - 1e-6, p.location.y - 1e-6, p.location.x + 1e-6, p.location.y + 1e-6).unwrap();
+    let mut postcodes = Vec::new();
+    while let Some(feature) = found.next().unwrap(){
+        postcodes.push(feature.property::<String>("postcode").unwrap());
+    }
+    assert!(postcodes.contains(&p.postcode));
+}
@@ -0,0 +1,67 @@
+/*
+
+Tests of adding locations to CSV files, and of checking postcodes, using the golden version 17
+pack file.
+
+*/
+use nearmypostcode_packer::{PostcodeError, Reader, Validity};
+use nearmypostcode_packer::geocode::{geocode, Column, GeocodeStats};
+
+fn reader() -> Reader{
+    Reader::open(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden/v17.pack")).unwrap()
+}
+
+fn run(input: &str, column: Column, has_header: bool) -> Result<(String, GeocodeStats), PostcodeError>{
+    let mut out = Vec::new();
+    let stats = geocode(&reader(), input.as_bytes(), &mut out, &column, has_header)?;
+    Ok((String::from_utf8(out).unwrap(), stats))
+}
+
+#[test]
+fn columns_are_added_to_every_row(){
+    let input = "name,street,postcode\nPalace,The Mall,sw1a 1aa\nNowhere,,ZZ99 9ZZ\nNumber 10,Downing St,SW1A2AA\n";
+    let (out, stats) = run(input, Column::parse("postcode"), true).unwrap();
+    assert_eq!(stats, GeocodeStats{rows: 3, found: 2});
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "name,street,postcode,lat,long");
+    assert!(lines[1].starts_with("Palace,The Mall,sw1a 1aa,51.50"), "{}", lines[1]);
+    assert_eq!(lines[2], "Nowhere,,ZZ99 9ZZ,,");
+    assert!(lines[3].starts_with("Number 10,Downing St,SW1A2AA,51.50"), "{}", lines[3]);
+
+    let (numbered, _) = run(input, Column::parse("3"), true).unwrap();
+    assert_eq!(numbered, out);
+}
+
+#[test]
+fn rows_stay_in_order_across_batches(){
+    let postcodes = ["AB10 1AA", "CB2 1TN", "BT9 5AB", "nope"];
+    let input: String = (0..25000).map(|i| format!("{i},{}\n", postcodes[i % 4])).collect();
+    let (out, stats) = run(&input, Column::Number(2), false).unwrap();
+    assert_eq!(stats, GeocodeStats{rows: 25000, found: 18750});
+    for (i, line) in out.lines().enumerate(){
+        let fields: Vec<&str> = line.split(',').collect();
+        assert_eq!(fields[0], i.to_string());
+        let lat = match i % 4{ 0 => "57.14", 1 => "52.20", 2 => "54.58", _ => "" };
+        assert!(fields[2].starts_with(lat) && (lat.is_empty() == fields[2].is_empty()), "{line}");
+    }
+}
+
+#[test]
+fn missing_columns_are_errors(){
+    assert!(matches!(run("a,b\n1,2\n", Column::parse("postcode"), true), Err(PostcodeError::InputMalformed(_))));
+    assert!(matches!(run("a,b\n", Column::parse("postcode"), false), Err(PostcodeError::IncompatibleOptions(_))));
+    assert!(matches!(run("a,b\n", Column::Number(0), false), Err(PostcodeError::IncompatibleOptions(_))));
+}
+
+#[test]
+fn malformed_postcodes_are_told_apart_from_missing_ones(){
+    let reader = reader();
+    assert_eq!(reader.validate("ab10 1aa"), Validity::Exists);
+    assert_eq!(reader.validate("AB10"), Validity::Exists);
+    assert_eq!(reader.validate("CB2 9ZZ"), Validity::Terminated);
+    assert_eq!(reader.validate("ZZ9 9ZZ"), Validity::WellFormed);
+    assert_eq!(reader.validate("ZZ9"), Validity::WellFormed);
+    for bad in ["", "not a postcode", "AB10 1A", "1AB 1AA"]{
+        assert_eq!(reader.validate(bad), Validity::Unknown, "{bad}");
+    }
+}
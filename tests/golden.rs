@@ -0,0 +1,347 @@
+/*
+
+Golden files for the pack format: a tiny input file, and the exact bytes that each format version
+of it should be packed to. Readers (the javascript library especially) depend on the layout of
+each version staying the same, so if one of these tests fails, the writer's output for an
+existing version has changed. Either fix the writer, or add a new format version for the change.
+
+Each version is written with every option that it supports turned on, except compression, which
+would tie the files to the exact version of each compression library.
+
+A version without a golden file fails too. Run with `NMP_WRITE_GOLDEN=1` to write the missing
+ones, and check them in. Existing golden files are never overwritten.
+
+Packing the same data must also give the same bytes every time, whatever the number of threads
+and however the input files were copied around, so that rebuilds can be compared with the files
+that were published before.
+
+*/
+use std::path::{Path, PathBuf};
+use nearmypostcode_packer::{Packer, Reader, ReadOptions, InputFormat, Metadata, Point, PostcodeError, WRITABLE_VERSIONS, release_date_of};
+use nearmypostcode_packer::spatial::IndexKind;
+
+/// 2025-02-01, which is after the newest postcode in the golden input file
+const RELEASE_DATE: u64 = 1738368000;
+
+fn golden_dir() -> PathBuf{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden")
+}
+
+/// Metadata of the golden files, without the packer's version so that they don't change with it
+fn metadata() -> Metadata{
+    let mut metadata = Metadata::new();
+    metadata.set("generator", "golden");
+    metadata.set("source", "golden.csv");
+    metadata.set("options", "--include-terminated --with-attributes ctry");
+    metadata
+}
+
+/// Packer for the golden input file as a version, with the options that the version supports
+fn packer(version: u32) -> Packer{
+    let options = ReadOptions{
+        include_terminated: version >= 6,
+        attributes: if version >= 19 { vec!["ctry".to_string()] } else { Vec::new() },
+        ..Default::default()
+    };
+    let input = golden_dir().join("golden.csv");
+    let mut packer = Packer::from_csv_with_options(&input.to_string_lossy(), &options).unwrap();
+    packer.set_format_version(version).unwrap();
+    packer.set_spatial_index(version >= 5);
+    packer.set_countries(version >= 7);
+    packer.set_centroids(version >= 8);
+    if version >= 9{
+        packer.set_chunk_size(64);
+    }
+    if version >= 11{
+        packer.set_key_id(*b"golden\0\0");
+    }
+    packer.set_entropy(version >= 14);
+    // Coordinate groups take the place of local bounding boxes and outliers
+    packer.set_local_bounds((15..25).contains(&version));
+    if version >= 16{
+        packer.set_resolution(24).unwrap();
+    }
+    if version >= 18{
+        packer.set_index_kind(IndexKind::KdTree);
+    }
+    packer.set_prefix_extents(version >= 20);
+    packer.set_sector_bitmap(version >= 21);
+    packer.set_release_date((version >= 22).then_some(RELEASE_DATE));
+    if version >= 23{
+        packer.set_metadata(metadata());
+    }
+    if version == 24{
+        packer.set_clip_bbox(2).unwrap();
+    }
+    packer.set_groups(version >= 25);
+    packer
+}
+
+fn pack(version: u32) -> Vec<u8>{
+    let mut data = Vec::new();
+    packer(version).write_to(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn pack_output_matches_golden_files(){
+    let write_missing = std::env::var_os("NMP_WRITE_GOLDEN").is_some();
+    let mut missing = Vec::new();
+    let mut changed = Vec::new();
+    for version in WRITABLE_VERSIONS{
+        let path = golden_dir().join(format!("v{version}.pack"));
+        let data = pack(version);
+        match std::fs::read(&path){
+            Ok(expected) => {
+                if data != expected{
+                    changed.push(version);
+                }
+            },
+            Err(_) if write_missing => std::fs::write(&path, &data).unwrap(),
+            Err(_) => missing.push(version),
+        }
+    }
+    assert!(changed.is_empty(), "the packed output of format versions {changed:?} has changed, readers of those versions may break. Change the format version instead.");
+    assert!(missing.is_empty(), "format versions {missing:?} have no golden files, run with NMP_WRITE_GOLDEN=1 to write them");
+}
+
+#[test]
+fn packing_is_repeatable(){
+    let one_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    let many_threads = rayon::ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+    for version in WRITABLE_VERSIONS{
+        let first = one_thread.install(|| pack(version));
+        let second = many_threads.install(|| pack(version));
+        assert!(first == second, "packing the same input twice as version {version} gave different files");
+    }
+}
+
+/// A writer that takes a few bytes at a time, and keeps the length of each buffer it was given
+struct ShortWrites{
+    data: Vec<u8>,
+    offered: Vec<usize>,
+}
+
+impl std::io::Write for ShortWrites{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>{
+        self.offered.push(buf.len());
+        let n = buf.len().min(7);
+        self.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()>{
+        Ok(())
+    }
+}
+
+#[test]
+fn files_are_written_in_one_go(){
+    for version in WRITABLE_VERSIONS{
+        let mut out = ShortWrites{data: Vec::new(), offered: Vec::new()};
+        let written = packer(version).write_to(&mut out).unwrap();
+        let expected = pack(version);
+        assert!(out.data == expected, "version {version} is different when the writer takes a few bytes at a time");
+        assert_eq!(written, expected.len() as u64);
+        // The whole file is offered at once, and then what's left each time
+        assert_eq!(out.offered[0], expected.len(), "version {version}");
+        assert_eq!(out.offered.len(), expected.len().div_ceil(7), "version {version}");
+    }
+}
+
+#[test]
+fn file_date_replaces_modification_time(){
+    let dir = std::env::temp_dir().join(format!("nmp-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("pointer.csv");
+    std::fs::write(&path, "POSTCODE,X_COR,Y_COR,ARCHIVED_DATE\nBT9 5AB,333000,372000,\nBT9 5AD,333100,372100,\n").unwrap();
+    let options = ReadOptions{
+        format: InputFormat::Pointer,
+        file_date: Some(time::Date::from_calendar_date(2025, time::Month::May, 1).unwrap()),
+        ..Default::default()
+    };
+    let pack_at = |modified: std::time::SystemTime|{
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        let mut data = Vec::new();
+        Packer::from_csv_with_options(&path.to_string_lossy(), &options).unwrap().write_to(&mut data).unwrap();
+        data
+    };
+    let first = pack_at(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000));
+    let second = pack_at(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(first == second, "the pack file depends on when the input file was modified");
+}
+
+#[test]
+fn release_date_is_stored_apart_from_the_newest_postcode(){
+    let reader = Reader::from_bytes(pack(22)).unwrap();
+    assert_eq!(reader.release_date(), Some(RELEASE_DATE));
+    assert!(reader.last_update() < RELEASE_DATE);
+    assert_eq!(Reader::from_bytes(pack(21)).unwrap().release_date(), None);
+
+    let mut packer = Packer::from_postcodes(reader.iter(), reader.last_update(), reader.scheme());
+    packer.set_release_date(Some(RELEASE_DATE));
+    packer.set_format_version(21).unwrap();
+    assert!(packer.write_to(&mut Vec::new()).is_err());
+
+    // The month is taken from the names that the ONS gives its files
+    assert_eq!(release_date_of("ONSPD_FEB_2025_UK.csv"), Some(RELEASE_DATE));
+    assert_eq!(release_date_of("/downloads/NSPL21_FEBRUARY_2025_UK.csv"), Some(RELEASE_DATE));
+    assert_eq!(release_date_of("ONSPD_2025-02.zip"), Some(RELEASE_DATE));
+    assert_eq!(release_date_of(&golden_dir().join("golden.csv").to_string_lossy()), None);
+}
+
+#[test]
+fn metadata_is_kept_in_the_header(){
+    let reader = Reader::from_bytes(pack(23)).unwrap();
+    assert_eq!(reader.metadata(), &metadata());
+    assert_eq!(reader.metadata().get("source"), Some("golden.csv"));
+    assert!(Reader::from_bytes(pack(22)).unwrap().metadata().is_empty());
+
+    // A patch copies the header, so the metadata comes with it
+    let mut packer = Packer::from_postcodes(reader.iter().skip(3), reader.last_update(), reader.scheme());
+    packer.set_format_version(23).unwrap();
+    let mut changed = metadata();
+    changed.set("comment", "three fewer postcodes");
+    packer.set_metadata(changed.clone());
+    let mut new = Vec::new();
+    packer.write_to(&mut new).unwrap();
+    let old = pack(23);
+    let patched = nearmypostcode_packer::patch::apply_patch(&old, &nearmypostcode_packer::patch::make_patch(&old, &new).unwrap()).unwrap();
+    assert_eq!(Reader::from_bytes(patched).unwrap().metadata(), &changed);
+
+    // Older versions have nowhere to put it, and keys must fit in a byte
+    packer.set_format_version(22).unwrap();
+    assert!(packer.write_to(&mut Vec::new()).is_err());
+    packer.set_format_version(23).unwrap();
+    changed.set("", "no key");
+    packer.set_metadata(changed);
+    assert!(packer.write_to(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn clipped_bounding_box_keeps_the_outliers_exact(){
+    let reader = Reader::from_bytes(pack(24)).unwrap();
+    let full = Reader::from_bytes(pack(23)).unwrap();
+    let area = |(min, max): (Point, Point)| (max.x - min.x) * (max.y - min.y);
+    assert!(area(reader.bounds()) < area(full.bounds()) / 2.0);
+    assert!(full.outliers().is_empty());
+
+    // Two postcodes are taken out, and the outward codes that are only them go with them
+    let outliers = reader.outliers();
+    assert_eq!(outliers.iter().filter(|(p, _)| !p.ends_with("   ")).count(), 2);
+    let (min, max) = reader.bounds();
+    for p in reader.iter(){
+        let expected = full.lookup(&p.postcode[0..if p.is_partial { 4 } else { 7 }]).unwrap();
+        match outliers.iter().find(|(o, _)| *o == p.postcode){
+            Some((_, location)) => {
+                assert!(p.location.x == location.x && p.location.y == location.y);
+                assert!((p.location.x - expected.x).abs() < 0.001 && (p.location.y - expected.y).abs() < 0.001, "{} moved", p.postcode);
+            },
+            None => assert!(p.location.x >= min.x && p.location.x <= max.x && p.location.y >= min.y && p.location.y <= max.y, "{} is outside the box", p.postcode),
+        }
+    }
+
+    // Merging keeps them out of the box, older versions can't leave them out
+    let (mut packer, _) = nearmypostcode_packer::repack::merge(std::slice::from_ref(&reader)).unwrap();
+    assert_eq!(packer.clip_bbox(), 2);
+    let mut merged = Vec::new();
+    packer.write_to(&mut merged).unwrap();
+    let names = |o: Vec<(&str, Point)>| o.into_iter().map(|(p, _)| p.to_string()).collect::<Vec<_>>();
+    assert_eq!(names(Reader::from_bytes(merged).unwrap().outliers()), names(outliers));
+    packer.set_format_version(23).unwrap();
+    assert!(packer.write_to(&mut Vec::new()).is_err());
+    assert!(packer.set_clip_bbox(1001).is_err());
+}
+
+#[test]
+fn coordinate_groups_are_quantized_apart(){
+    let reader = Reader::from_bytes(pack(25)).unwrap();
+    let groups = reader.coordinate_groups();
+    assert_eq!(groups.iter().map(|(_, members)| members.join(" ")).collect::<Vec<_>>(), ["", "AB10", "BT9", "SW1A"]);
+    assert!(Reader::from_bytes(pack(24)).unwrap().coordinate_groups().is_empty());
+
+    // Every location is in its group's box, and nearer where it was than without groups
+    let input = golden_dir().join("golden.csv");
+    let options = ReadOptions{include_terminated: true, ..Default::default()};
+    let mut packer = Packer::from_csv_with_options(&input.to_string_lossy(), &options).unwrap();
+    let error = |packer: &Packer| {
+        let mut data = Vec::new();
+        packer.write_to(&mut data).unwrap();
+        let reader = Reader::from_bytes(data).unwrap();
+        packer.postcodes().iter().filter(|p| p.terminated.is_none()).map(|p| {
+            let l = reader.lookup(&p.postcode[0..if p.is_partial { 4 } else { 7 }]).unwrap();
+            (l.x - p.location.x).abs().max((l.y - p.location.y).abs())
+        }).fold(0.0, f64::max)
+    };
+    packer.set_format_version(25).unwrap();
+    let ungrouped = error(&packer);
+    packer.set_groups(true);
+    assert!(error(&packer) < ungrouped / 10.0);
+    for ((min, max), members) in &groups[1..]{
+        for p in reader.iter().filter(|p| members.iter().any(|m| p.postcode.starts_with(m.as_str()))){
+            assert!(p.location.x >= min.x && p.location.x <= max.x && p.location.y >= min.y && p.location.y <= max.y, "{} is outside its group", p.postcode);
+        }
+    }
+
+    // Merging keeps them, they need version 25 and can't be used with local bounding boxes
+    let (merged, _) = nearmypostcode_packer::repack::merge(std::slice::from_ref(&reader)).unwrap();
+    assert!(merged.groups());
+    let mut data = Vec::new();
+    merged.write_to(&mut data).unwrap();
+    assert_eq!(Reader::from_bytes(data).unwrap().coordinate_groups().len(), groups.len());
+    packer.set_local_bounds(true);
+    assert!(packer.write_to(&mut Vec::new()).is_err());
+    packer.set_local_bounds(false);
+    packer.set_format_version(24).unwrap();
+    assert!(packer.write_to(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn unknown_sections_are_skipped_unless_required(){
+    let data = std::fs::read(golden_dir().join("v26.pack")).unwrap();
+    let entries = |r: &Reader| r.iter().map(|p| (p.postcode, p.location.x, p.location.y, p.terminated)).collect::<Vec<_>>();
+    let v25 = entries(&Reader::open(&golden_dir().join("v25.pack").to_string_lossy()).unwrap());
+    let reader = Reader::from_bytes(data.clone()).unwrap();
+    assert_eq!(entries(&reader), v25);
+    assert_eq!(reader.release_date(), Some(RELEASE_DATE));
+
+    // Give the release date a kind from some later version, and fix the file's checksum
+    let sections = reader.sections();
+    let entry = 20 + (16 * sections.iter().position(|(name, _, _)| *name == "release date").unwrap());
+    let with_kind = |kind: u16, flags: u16| {
+        let mut data = data.clone();
+        data[entry..entry+2].copy_from_slice(&kind.to_le_bytes());
+        data[entry+2..entry+4].copy_from_slice(&flags.to_le_bytes());
+        let body = data.len() - 4;
+        let mut crc = flate2::Crc::new();
+        crc.update(&data[..body]);
+        data[body..].copy_from_slice(&crc.sum().to_le_bytes());
+        Reader::from_bytes(data)
+    };
+    let skipped = with_kind(1000, 0).unwrap();
+    assert_eq!(skipped.release_date(), None);
+    assert_eq!(entries(&skipped), v25);
+    assert!(skipped.sections().iter().any(|(name, _, _)| *name == "unknown"));
+    assert!(matches!(with_kind(1000, 1), Err(PostcodeError::UnsupportedSection(1000))));
+
+    // Each section has its own checksum, so the damaged one can be named
+    let (_, start, _) = sections.iter().find(|(name, _, _)| *name == "postcode data").unwrap();
+    let mut damaged = data.clone();
+    damaged[*start] ^= 1;
+    assert!(matches!(Reader::from_bytes(damaged), Err(PostcodeError::Corrupt("postcode data"))));
+}
+
+#[cfg(feature="mmap")]
+#[test]
+fn mapped_files_read_the_same(){
+    for version in WRITABLE_VERSIONS{
+        let path = golden_dir().join(format!("v{version}.pack"));
+        let path = path.to_string_lossy();
+        let read = Reader::open(&path).unwrap();
+        let mapped = Reader::map(&path).unwrap();
+        mapped.check_checksums().unwrap();
+        let entries = |r: &Reader| r.iter().map(|p| (p.postcode, p.location.x, p.location.y, p.terminated, p.country)).collect::<Vec<_>>();
+        assert_eq!(entries(&read), entries(&mapped), "version {version} reads differently when mapped");
+    }
+}
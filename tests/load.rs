@@ -0,0 +1,48 @@
+/*
+
+Tests of loading pack files from async sources, as a server would from object storage at
+startup.
+
+*/
+#![cfg(feature="tokio")]
+use tokio::io::AsyncWriteExt;
+use nearmypostcode_packer::{Reader, PostcodeError};
+
+fn golden() -> Vec<u8>{
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden/v26.pack")).unwrap()
+}
+
+#[tokio::test]
+async fn pack_files_are_loaded_from_async_readers(){
+    let data = golden();
+    let expected = Reader::from_bytes(data.clone()).unwrap();
+
+    // Arriving in small pieces, like a download
+    let (mut tx, rx) = tokio::io::duplex(64);
+    let sender = tokio::spawn(async move {
+        for chunk in data.chunks(100){
+            tx.write_all(chunk).await.unwrap();
+        }
+    });
+    let reader = Reader::from_async_read(rx).await.unwrap();
+    sender.await.unwrap();
+    assert_eq!(reader.version(), expected.version());
+    for p in expected.iter(){
+        assert_eq!(reader.lookup(&p.postcode).map(|l| (l.x, l.y)), Some((p.location.x, p.location.y)));
+    }
+}
+
+#[tokio::test]
+async fn bad_sources_are_errors(){
+    let data = golden();
+    assert!(matches!(Reader::from_async_read(&data[..100]).await, Err(PostcodeError::Corrupt(_)) | Err(PostcodeError::NotAPackFile())));
+    assert!(matches!(Reader::from_async_read(&b"not a pack file"[..]).await, Err(PostcodeError::NotAPackFile())));
+
+    struct Failing;
+    impl tokio::io::AsyncRead for Failing{
+        fn poll_read(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, _: &mut tokio::io::ReadBuf<'_>) -> std::task::Poll<std::io::Result<()>>{
+            std::task::Poll::Ready(Err(std::io::Error::other("connection reset")))
+        }
+    }
+    assert!(matches!(Reader::from_async_read(Failing).await, Err(PostcodeError::IOError(_))));
+}
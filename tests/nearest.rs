@@ -0,0 +1,165 @@
+/*
+
+Tests of finding the nearest postcodes to a location, comparing the spatial index search with a
+search of every postcode.
+
+*/
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme, format_postcode, distance_m};
+use nearmypostcode_packer::spatial::IndexKind;
+use nearmypostcode_packer::geohash::geohash;
+
+/// Postcodes in a grid over part of the UK, with some at the same location
+fn postcodes() -> Vec<PostcodeInfo>{
+    let mut postcodes = Vec::new();
+    for district in 1..=40u32{
+        for i in 0..60u32{
+            let step = (district * 61 + i * 7) % 97;
+            postcodes.push(PostcodeInfo{
+                postcode: format_postcode(&format!("E{district} {}A{}", i % 10, (b'A' + (i / 10) as u8) as char)).unwrap(),
+                location: Point{x: -3.0 + step as f64 * 0.03, y: 51.0 + ((i / 2) as f64 * 0.05) + district as f64 * 0.01},
+                is_partial: false,
+                terminated: None,
+                country: None,
+                introduced: None,
+                attributes: Vec::new(),
+            });
+        }
+    }
+    postcodes.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+    postcodes
+}
+
+fn pack(spatial_index: Option<IndexKind>) -> Reader{
+    let mut packer = Packer::from_postcodes(postcodes(), 0, Scheme::Uk);
+    packer.set_format_version(spatial_index.map(|k| k.min_version()).unwrap_or(5)).unwrap();
+    packer.set_spatial_index(spatial_index.is_some());
+    packer.set_index_kind(spatial_index.unwrap_or_default());
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    Reader::from_bytes(data).unwrap()
+}
+
+#[test]
+fn index_finds_the_same_postcodes(){
+    let plain = pack(None);
+    for kind in IndexKind::ALL{
+        same_as_plain(&plain, &pack(Some(kind)));
+    }
+    // Buckets bigger than the whole area, and smaller than the gaps between postcodes
+    for precision in [1, 3, 5]{
+        same_as_plain(&plain, &pack(Some(IndexKind::Geohash(precision))));
+    }
+}
+
+fn same_as_plain(plain: &Reader, indexed: &Reader){
+    for (lat, long) in [(51.5, -1.5), (52.7, 0.0), (50.0, -4.0), (53.3, 1.0), (51.1, -2.99)]{
+        for k in [1, 5, 50]{
+            let a = plain.nearest_n(lat, long, k);
+            let b = indexed.nearest_n(lat, long, k);
+            assert_eq!(a.len(), k);
+            let names = |v: &[(PostcodeInfo, f64)]| v.iter().map(|(p, _)| p.postcode.clone()).collect::<Vec<_>>();
+            assert_eq!(names(&a), names(&b), "{k} nearest to {lat},{long} with {:?}", indexed.index_kind());
+            assert!(b.windows(2).all(|w| w[0].1 <= w[1].1), "not nearest first");
+            let centre = Point{x: long, y: lat};
+            assert!(b.iter().all(|(p, d)| *d == distance_m(&centre, &p.location)));
+        }
+        assert_eq!(indexed.nearest(lat, long).map(|p| p.postcode), Some(indexed.nearest_n(lat, long, 1)[0].0.postcode.clone()));
+        for metres in [0.0, 2_000.0, 30_000.0]{
+            let names = |v: Vec<PostcodeInfo>| v.into_iter().map(|p| p.postcode).collect::<Vec<_>>();
+            assert_eq!(names(plain.within_radius(lat, long, metres)), names(indexed.within_radius(lat, long, metres)));
+        }
+    }
+}
+
+#[test]
+fn outliers_are_found_outside_a_clipped_box(){
+    // Two postcodes far from the rest, which are left out of the bounding box
+    let mut postcodes = postcodes();
+    for (postcode, x, y) in [("TR21 0AA", -6.3, 49.9), ("ZE1 0AA", -1.15, 60.15)]{
+        postcodes.push(PostcodeInfo{
+            postcode: format_postcode(postcode).unwrap(), location: Point{x, y}, is_partial: false, terminated: None, country: None, introduced: None, attributes: Vec::new(),
+        });
+    }
+    postcodes.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+    let write = |spatial_index: Option<IndexKind>, clip: usize| {
+        let mut packer = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+        assert_eq!(packer.outliers(2).unwrap().iter().map(|o| o.postcode.as_str()).collect::<Vec<_>>(), ["ZE1 0AA", "TR210AA"]);
+        packer.set_format_version(24).unwrap();
+        packer.set_spatial_index(spatial_index.is_some());
+        packer.set_index_kind(spatial_index.unwrap_or_default());
+        packer.set_clip_bbox(clip).unwrap();
+        let mut data = Vec::new();
+        packer.write_to(&mut data).unwrap();
+        Reader::from_bytes(data).unwrap()
+    };
+    let plain = write(None, 0);
+    for kind in IndexKind::ALL{
+        let reader = write(Some(kind), 2);
+        assert!(reader.bounds().1.y < 53.0);
+        // The outward codes of just one postcode are outliers too
+        let names: Vec<&str> = reader.outliers().iter().map(|(p, _)| *p).collect();
+        assert_eq!(names, ["TR21   ", "TR210AA", "ZE1    ", "ZE1 0AA"]);
+        let shetland = reader.lookup("ZE1 0AA").unwrap();
+        assert!((shetland.x - -1.15).abs() < 1e-6 && (shetland.y - 60.15).abs() < 1e-6);
+        assert_eq!(reader.nearest(60.0, -1.0).unwrap().postcode, "ZE1 0AA");
+        assert_eq!(reader.nearest_n(49.0, -7.0, 2)[0].0.postcode, "TR210AA");
+        assert_eq!(reader.within_radius(60.15, -1.15, 1_000.0).len(), 1);
+        same_as_plain(&plain, &reader);
+    }
+}
+
+#[test]
+fn asking_for_too_many_returns_them_all(){
+    for kind in IndexKind::ALL{
+        let reader = pack(Some(kind));
+        assert_eq!(reader.nearest_n(51.5, -1.5, 10000).len(), 2400);
+        assert!(reader.nearest_n(51.5, -1.5, 0).is_empty());
+    }
+}
+
+#[test]
+fn distances_are_great_circle(){
+    // One degree of longitude is much shorter than one of latitude this far north
+    let york = Point{x: -1.0803, y: 53.9600};
+    let east = Point{x: -0.0803, y: 53.9600};
+    let north = Point{x: -1.0803, y: 54.9600};
+    assert!((york.haversine_to(&east) - 65_500.0).abs() < 500.0);
+    assert!((york.haversine_to(&north) - 111_200.0).abs() < 500.0);
+    assert_eq!(distance_m(&york, &east), east.haversine_to(&york));
+
+    // So the nearest postcode is the one a degree to the east, not the one 0.7 degrees north
+    let postcodes = [("YO1 7AA", north.y - 0.3, north.x), ("YO1 7AB", east.y, east.x)].map(|(pc, y, x)| PostcodeInfo{
+        postcode: format_postcode(pc).unwrap(), location: Point{x, y}, is_partial: false, terminated: None, country: None, introduced: None, attributes: Vec::new(),
+    });
+    let mut data = Vec::new();
+    Packer::from_postcodes(postcodes, 0, Scheme::Uk).write_to(&mut data).unwrap();
+    let reader = Reader::from_bytes(data).unwrap();
+    assert_eq!(reader.nearest(york.y, york.x).unwrap().postcode, "YO1 7AB");
+}
+
+#[test]
+fn kd_tree_holds_every_live_postcode(){
+    let reader = pack(Some(IndexKind::KdTree));
+    assert_eq!(reader.index_kind(), Some(IndexKind::KdTree));
+    assert_eq!(reader.kd_tree_len(), Some(2400));
+    assert_eq!(pack(Some(IndexKind::Grid)).kd_tree_len(), None);
+}
+
+#[test]
+fn geohashes_match_the_usual_strings(){
+    let base32 = |hash: u32, precision: u8| (0..precision).rev()
+        .map(|i| b"0123456789bcdefghjkmnpqrstuvwxyz"[((hash >> (i * 5)) & 31) as usize] as char)
+        .collect::<String>();
+    assert_eq!(base32(geohash(&Point{x: 10.40744, y: 57.64911}, 6), 6), "u4pruy");
+    assert_eq!(base32(geohash(&Point{x: -0.14159, y: 51.50101}, 6), 6), "gcpuuz");
+    assert_eq!(base32(geohash(&Point{x: -0.14159, y: 51.50101}, 2), 2), "gc");
+
+    assert_eq!(IndexKind::from_name("geohash"), Some(IndexKind::Geohash(6)));
+    assert_eq!(IndexKind::from_name("geohash:4"), Some(IndexKind::Geohash(4)));
+    assert_eq!(IndexKind::from_name("geohash:7"), None);
+    assert_eq!(IndexKind::from_name("geohash:0"), None);
+    assert_eq!(IndexKind::Geohash(4).to_string(), "geohash:4");
+    let reader = pack(Some(IndexKind::Geohash(4)));
+    assert_eq!(reader.index_kind(), Some(IndexKind::Geohash(4)));
+    assert_eq!(reader.geohash_buckets().map(|(_, postcodes)| postcodes), Some(2400));
+}
@@ -0,0 +1,131 @@
+/*
+
+Tests of the order of the records in each prefix block. A file in Hilbert order must read the
+same as one in alphabetical order: lookups, the spatial indexes (which count the entries of a
+block in the order they're written), completions, patches and upgrades.
+
+*/
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, PostcodeError, Point, Scheme, RecordOrder, NEWEST_VERSION, format_postcode};
+use nearmypostcode_packer::spatial::IndexKind;
+use nearmypostcode_packer::patch::{make_patch, apply_patch};
+use nearmypostcode_packer::repack;
+
+/// Postcodes in sectors spread around a town, each on two streets at opposite ends of its sector,
+/// so that postcodes next to each other in alphabetical order are far apart, but each one is
+/// close to the one after next
+fn postcodes() -> Vec<PostcodeInfo>{
+    let mut postcodes = Vec::new();
+    for area in ["LS", "YO"]{
+        for district in 1..=4u32{
+            for sector in 0..10u32{
+                let angle = sector as f64 * 2.4;
+                for (i, unit) in "ABDEFGHJLNPQRSTUWXYZ".chars().enumerate(){
+                    postcodes.push(PostcodeInfo{
+                        postcode: format_postcode(&format!("{area}{district} {sector}A{unit}")).unwrap(),
+                        location: Point{
+                            x: -1.5 + (district as f64 * 0.05) + (angle.cos() * 0.04) + ((i % 2) as f64 * 0.01) + (i as f64 * 0.00002),
+                            y: 53.8 + (angle.sin() * 0.03) + (i as f64 * 0.00001),
+                        },
+                        is_partial: false,
+                        terminated: None,
+                        country: None,
+                        introduced: None,
+                        attributes: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+    postcodes.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+    postcodes
+}
+
+fn pack(postcodes: &[PostcodeInfo], order: RecordOrder, kind: IndexKind) -> Vec<u8>{
+    let mut packer = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+    packer.set_format_version(NEWEST_VERSION).unwrap();
+    packer.set_spatial_index(true);
+    packer.set_index_kind(kind);
+    packer.set_sector_bitmap(true);
+    packer.set_order(order);
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn hilbert_files_read_the_same(){
+    let postcodes = postcodes();
+    for kind in IndexKind::ALL{
+        let alpha = Reader::from_bytes(pack(&postcodes, RecordOrder::Alphabetical, kind)).unwrap();
+        let hilbert = Reader::from_bytes(pack(&postcodes, RecordOrder::Hilbert, kind)).unwrap();
+        hilbert.check_checksums().unwrap();
+        assert_eq!(alpha.record_order(), RecordOrder::Alphabetical);
+        assert_eq!(hilbert.record_order(), RecordOrder::Hilbert);
+        let file_order: Vec<String> = hilbert.iter().map(|p| p.postcode).collect();
+        let mut sorted = file_order.clone();
+        sorted.sort();
+        assert_ne!(file_order, sorted);
+        assert_eq!(sorted, alpha.iter().map(|p| p.postcode).collect::<Vec<_>>());
+
+        for (i, p) in postcodes.iter().enumerate(){
+            let location = |r: &Reader| r.lookup(&p.postcode).map(|l| (l.x, l.y));
+            assert_eq!(location(&hilbert), location(&alpha), "{}", p.postcode);
+            // Nearest searches are slower, so only some postcodes are searched near
+            if i % 7 != 0{
+                continue;
+            }
+            let (lat, long) = (p.location.y + 0.0002, p.location.x - 0.0001);
+            assert_eq!(hilbert.nearest(lat, long).unwrap().postcode, alpha.nearest(lat, long).unwrap().postcode, "near {} with {kind:?}", p.postcode);
+        }
+        let within = |r: &Reader| r.within_radius(53.8, -1.3, 2000.0).into_iter().map(|p| p.postcode).collect::<Vec<_>>();
+        assert_eq!(within(&hilbert), within(&alpha));
+    }
+}
+
+#[test]
+fn completions_are_sorted_in_any_order(){
+    let postcodes = postcodes();
+    let alpha = Reader::from_bytes(pack(&postcodes, RecordOrder::Alphabetical, IndexKind::Grid)).unwrap();
+    let hilbert = Reader::from_bytes(pack(&postcodes, RecordOrder::Hilbert, IndexKind::Grid)).unwrap();
+    for text in ["LS1", "ls2 3", "YO4 9AB", "Y"]{
+        let found = hilbert.complete_postcodes(text, 12);
+        assert_eq!(found, alpha.complete_postcodes(text, 12), "{text}");
+        assert!(!found.is_empty() && found.is_sorted(), "{text}");
+    }
+}
+
+#[test]
+fn hilbert_order_is_smaller_for_scattered_sectors(){
+    let reader = Reader::from_bytes(pack(&postcodes(), RecordOrder::Hilbert, IndexKind::Grid)).unwrap();
+    let lens = reader.order_lens().unwrap();
+    let len = |order: RecordOrder| lens.iter().find(|(o, _)| *o == order).unwrap().1;
+    assert!(len(RecordOrder::Hilbert) < len(RecordOrder::Alphabetical), "{lens:?}");
+    // The length for the file's own order is the length of its records
+    assert_eq!(len(RecordOrder::Hilbert), reader.file_stats().record_bytes.iter().sum::<usize>());
+}
+
+#[test]
+fn patches_and_upgrades_keep_the_order(){
+    let old = pack(&postcodes(), RecordOrder::Hilbert, IndexKind::KdTree);
+    let mut moved = postcodes();
+    // Move some postcodes within the bounding box, and remove some
+    for p in moved.iter_mut().filter(|p| p.postcode.starts_with("LS2")){
+        p.location.x += 0.01;
+    }
+    moved.retain(|p| !p.postcode.starts_with("YO3 4"));
+    let new = pack(&moved, RecordOrder::Hilbert, IndexKind::KdTree);
+    let patch = make_patch(&old, &new).unwrap();
+    assert_eq!(apply_patch(&old, &patch).unwrap(), new);
+
+    let mut upgraded = Vec::new();
+    repack::upgrade(&Reader::from_bytes(new.clone()).unwrap()).write_to(&mut upgraded).unwrap();
+    assert_eq!(Reader::from_bytes(upgraded).unwrap().record_order(), RecordOrder::Hilbert);
+}
+
+#[test]
+fn hilbert_order_needs_version_26(){
+    let mut packer = Packer::from_postcodes(postcodes(), 0, Scheme::Uk);
+    packer.set_format_version(25).unwrap();
+    packer.set_order(RecordOrder::Hilbert);
+    assert!(matches!(packer.write_to(Vec::new()), Err(PostcodeError::IncompatibleOptions(_))));
+}
@@ -0,0 +1,140 @@
+/*
+
+Tests of how postcodes are grouped in to prefix blocks by their first two chars, which is the
+whole area for two letter areas, but the area and a digit for one letter areas, and a letter and
+a digit for Eircodes, and of the layer 0 file of a layered pack, which has a postcode from each
+sector.
+
+*/
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme, Validity, format_postcode};
+
+fn pack(codes: &[&str], scheme: Scheme) -> Reader{
+    let postcodes = codes.iter().enumerate().map(|(i, code)| PostcodeInfo{
+        postcode: scheme.codec().normalize(code).unwrap(),
+        location: Point{x: -2.0 + (i as f64 * 0.01), y: 52.0 + (i as f64 * 0.01)},
+        is_partial: false,
+        terminated: None,
+        country: None,
+        introduced: None,
+        attributes: Vec::new(),
+    });
+    let mut packer = Packer::from_postcodes(postcodes, 0, scheme);
+    packer.set_format_version(if scheme == Scheme::Uk { 16 } else { 17 }).unwrap();
+    packer.set_local_bounds(true);
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    Reader::from_bytes(data).unwrap()
+}
+
+/// Prefix and number of entries (postcodes and outward codes) of each block
+fn blocks(reader: &Reader) -> Vec<(String, usize)>{
+    reader.file_stats().blocks
+}
+
+#[test]
+fn one_and_two_letter_areas_have_their_own_blocks(){
+    let codes = [
+        "B1 1AA", "B10 0AA", "B19 9ZZ", "B2 4QA", "BA1 1AA", "BA10 0AA",
+        "E1 6AN", "E1W 1AA", "EC1A 1BB", "N1C 4AA", "NW1 2DB", "W1A 1AA", "W10 4AA", "WA1 1AA",
+    ];
+    let reader = pack(&codes, Scheme::Uk);
+    let expected = [("B1", 6), ("B2", 2), ("BA", 4), ("E1", 4), ("EC", 2), ("N1", 2), ("NW", 2), ("W1", 4), ("WA", 2)];
+    assert_eq!(blocks(&reader), expected.map(|(p, n)| (p.to_string(), n)));
+    for code in codes{
+        assert!(reader.lookup(&format_postcode(code).unwrap()).is_some(), "{code}");
+    }
+    // Outward codes of both shapes are in the same blocks as their postcodes
+    for outward in ["B1  ", "B10 ", "BA10", "E1W ", "EC1A", "W10 "]{
+        assert!(reader.lookup(outward).is_some(), "{outward}");
+    }
+    // One letter areas never share a block with the two letter areas after them
+    assert!(reader.lookup("B3 1AA").is_none() && reader.lookup("BA2 1AA").is_none());
+    let found: Vec<String> = reader.iter().filter(|p| !p.is_partial).map(|p| p.postcode).collect();
+    let mut expected: Vec<String> = codes.iter().map(|c| format_postcode(c).unwrap()).collect();
+    expected.sort();
+    assert_eq!(found, expected);
+
+    // The blocks one at a time have every entry
+    assert_eq!(reader.iter_prefix("B1").count(), 6);
+    assert_eq!(reader.iter_prefix("ZZ").count(), 0);
+    let by_block: Vec<String> = reader.block_ranges().iter().flat_map(|(prefix, _, _)| reader.iter_prefix(prefix)).map(|p| p.postcode).collect();
+    assert_eq!(by_block, reader.iter().map(|p| p.postcode).collect::<Vec<_>>());
+}
+
+#[test]
+fn entries_can_be_listed_by_prefix(){
+    let codes = ["B1 1AA", "B10 0AA", "B19 9ZZ", "B2 4QA", "BA1 1AA", "E1 6AN", "E1W 1AA", "E10 5AA"];
+    let reader = pack(&codes, Scheme::Uk);
+    let listed = |prefix: &str| reader.iter_prefix(prefix).map(|p| p.postcode).collect::<Vec<_>>();
+    // Longer than a block name, the outward code and its padding narrow it down
+    assert_eq!(listed("B10"), ["B10    ", "B10 0AA"]);
+    assert_eq!(listed("E1  "), ["E1     ", "E1  6AN"]);
+    assert_eq!(listed("E1W 1"), ["E1W 1AA"]);
+    // Shorter than a block name, every block that starts with it
+    assert_eq!(listed("B").len(), reader.iter().filter(|p| p.postcode.starts_with('B')).count());
+    assert!(listed("SW1A").is_empty());
+    assert_eq!(listed("").len(), reader.iter().count());
+}
+
+#[test]
+fn lookups_ignore_case_and_spacing(){
+    let reader = pack(&["E1 6AN", "SW1A 1AA"], Scheme::Uk);
+    let location = reader.lookup("SW1A1AA").unwrap();
+    for code in ["sw1a1aa", "SW1A 1AA", " sw1a 1aa ", "Sw1A  1aA"]{
+        let found = reader.lookup(code).unwrap_or_else(|| panic!("{code}"));
+        assert_eq!((found.x, found.y), (location.x, location.y), "{code}");
+    }
+    // The canonical form, the 8 char padded form (pcd2) and the outward code by itself
+    for code in ["E1  6AN", "E1   6AN", "e16an", "e1", "E1  "]{
+        assert!(reader.lookup(code).is_some(), "{code}");
+    }
+    assert!(reader.lookup("E1 6AM").is_none() && reader.lookup("not a postcode").is_none());
+
+    let reader = pack(&["A65 F4E2"], Scheme::Eircode);
+    assert!(reader.lookup("a65f4e2").is_some() && reader.lookup(" A65 F4E2").is_some() && reader.lookup("a65").is_some());
+}
+
+#[test]
+fn full_postcodes_are_completed(){
+    let codes = ["E1 6AN", "E1 6AW", "E1 7AA", "E16 1AA", "E1W 1AA", "EC1A 1BB", "SW1A 1AA", "SW1A 2AA"];
+    let reader = pack(&codes, Scheme::Uk);
+    assert_eq!(reader.complete_postcodes("sw1a 1a", 10), ["SW1A1AA"]);
+    assert_eq!(reader.complete_postcodes("SW1A", 10), ["SW1A1AA", "SW1A2AA"]);
+    // Without a space the text can run on in to the inward code
+    assert_eq!(reader.complete_postcodes("E16", 10), ["E1  6AN", "E1  6AW", "E16 1AA"]);
+    assert_eq!(reader.complete_postcodes("E1 ", 10), ["E1  6AN", "E1  6AW", "E1  7AA"]);
+    assert_eq!(reader.complete_postcodes("  e1   6a", 10), ["E1  6AN", "E1  6AW"]);
+    assert_eq!(reader.complete_postcodes("E", 4), ["E1  6AN", "E1  6AW", "E1  7AA", "E16 1AA"]);
+    assert_eq!(reader.complete_postcodes("", 100).len(), codes.len());
+    assert!(reader.complete_postcodes("E1 6AN", 0).is_empty());
+    assert!(reader.complete_postcodes("E2", 10).is_empty());
+    assert!(reader.complete_postcodes("É1", 10).is_empty());
+
+    let reader = pack(&["A65 F4E2", "A65 F4E3", "A65 R2AF", "D6W A1C2"], Scheme::Eircode);
+    assert_eq!(reader.complete_postcodes("a65 f4", 10), ["A65F4E2", "A65F4E3"]);
+    assert_eq!(reader.complete_postcodes("D6WA", 10), ["D6WA1C2"]);
+}
+
+#[test]
+fn eircode_routing_keys_are_grouped_by_letter_and_digit(){
+    let codes = ["A65 F4E2", "D02 X285", "D06 A1C2", "D6W 1234", "T12 AC2E"];
+    let reader = pack(&codes, Scheme::Eircode);
+    assert_eq!(blocks(&reader), [("A6", 2), ("D0", 4), ("D6", 2), ("T1", 2)].map(|(p, n)| (p.to_string(), n)));
+    for code in codes{
+        assert_eq!(reader.validate(code), Validity::Exists, "{code}");
+    }
+}
+
+#[test]
+fn layer_zero_has_one_postcode_from_each_sector(){
+    let layer0 = |reader: &Reader| {
+        let mut data = Vec::new();
+        nearmypostcode_packer::repack::sample_sectors(reader).write_to(&mut data).unwrap();
+        Reader::from_bytes(data).unwrap().iter().filter(|p| !p.is_partial).map(|p| p.postcode).collect::<Vec<_>>()
+    };
+    // The one nearest the middle of the sector
+    let uk = pack(&["SW1A 1AA", "SW1A 1AB", "SW1A 1AD", "SW1A 2AA", "SW1A 2AB", "SW1A 2AD", "B1 1AA", "B10 1AA"], Scheme::Uk);
+    assert_eq!(layer0(&uk), ["B1  1AA", "B10 1AA", "SW1A1AB", "SW1A2AB"]);
+    let eircode = pack(&["A65 F4E2", "A65 F4E3", "A65 F4E4", "A65 K4E2", "D6W XA00"], Scheme::Eircode);
+    assert_eq!(layer0(&eircode), ["A65F4E3", "A65K4E2", "D6WXA00"]);
+}
@@ -0,0 +1,131 @@
+/*
+
+Tests of finding the postcodes inside bounding boxes, polygons and GeoJSON boundaries, and of the
+bounding box of each outward code.
+
+*/
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme, format_postcode};
+use nearmypostcode_packer::region::{Region, in_polygon};
+
+/// Postcodes in a 10 by 10 grid, from 0,50 to 0.9,50.9
+fn pack() -> Reader{
+    let postcodes = (0..100u32).map(|i| PostcodeInfo{
+        postcode: format_postcode(&format!("N{} {}AA", 1 + (i / 10), i % 10)).unwrap(),
+        location: Point{x: (i % 10) as f64 * 0.1, y: 50.0 + ((i / 10) as f64 * 0.1)},
+        is_partial: false,
+        terminated: None,
+        country: None,
+        introduced: None,
+        attributes: Vec::new(),
+    });
+    let mut data = Vec::new();
+    Packer::from_postcodes(postcodes, 0, Scheme::Uk).write_to(&mut data).unwrap();
+    Reader::from_bytes(data).unwrap()
+}
+
+fn p(x: f64, y: f64) -> Point{
+    Point{x, y}
+}
+
+#[test]
+fn points_in_polygons(){
+    let triangle = [p(0.0, 0.0), p(4.0, 0.0), p(0.0, 4.0)];
+    assert!(in_polygon(&p(1.0, 1.0), &triangle));
+    assert!(!in_polygon(&p(3.0, 3.0), &triangle));
+    assert!(!in_polygon(&p(-1.0, 1.0), &triangle));
+    // Closing the ring makes no difference
+    let closed = [p(0.0, 0.0), p(4.0, 0.0), p(0.0, 4.0), p(0.0, 0.0)];
+    assert!(in_polygon(&p(1.0, 1.0), &closed));
+    assert!(!in_polygon(&p(1.0, 1.0), &[]));
+
+    // A hole is a polygon inside another one
+    let square = vec![p(0.0, 0.0), p(10.0, 0.0), p(10.0, 10.0), p(0.0, 10.0)];
+    let hole = vec![p(4.0, 4.0), p(6.0, 4.0), p(6.0, 6.0), p(4.0, 6.0)];
+    let region = Region::new(vec![square, hole]);
+    assert!(region.contains(&p(2.0, 2.0)));
+    assert!(!region.contains(&p(5.0, 5.0)));
+    assert!(!region.contains(&p(11.0, 5.0)));
+}
+
+#[test]
+fn postcodes_in_boxes_and_polygons(){
+    let reader = pack();
+    let names = |i: &mut dyn Iterator<Item=PostcodeInfo>| i.filter(|p| !p.is_partial).map(|p| p.postcode).collect::<Vec<_>>();
+
+    let boxed = names(&mut reader.within_bbox(p(0.15, 50.15), p(0.35, 50.25)));
+    assert_eq!(boxed, ["N3  2AA", "N3  3AA"]);
+    // Edges are included
+    assert_eq!(names(&mut reader.within_bbox(p(0.0, 50.0), p(0.0, 50.0))), ["N1  0AA"]);
+    assert_eq!(names(&mut reader.within_bbox(p(0.0, 50.0), p(0.9, 50.9))).len(), 100);
+
+    // A diamond around 0.45,50.45, which has the 4 postcodes nearest the middle in it
+    let diamond = [p(0.45, 50.3), p(0.6, 50.45), p(0.45, 50.6), p(0.3, 50.45)];
+    let mut inside = names(&mut reader.within_polygon(&diamond));
+    inside.sort();
+    assert_eq!(inside, ["N5  4AA", "N5  5AA", "N6  4AA", "N6  5AA"]);
+    assert_eq!(reader.within_polygon(&[]).count(), 0);
+}
+
+#[test]
+fn prefix_extents_cover_their_postcodes(){
+    let postcodes: Vec<PostcodeInfo> = pack().iter().filter(|p| !p.is_partial).collect();
+    let write = |extents: bool| {
+        let mut packer = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+        packer.set_format_version(20).unwrap();
+        packer.set_prefix_extents(extents);
+        let mut data = Vec::new();
+        packer.write_to(&mut data).unwrap();
+        data
+    };
+    let data = write(true);
+    let reader = Reader::from_bytes(data.clone()).unwrap();
+    assert_eq!(reader.prefix_extent_count(), Some(10));
+
+    // N3 is the third row of the grid, 0 to 0.9 by 50.2
+    let (min, max) = reader.prefix_extent("n3").unwrap();
+    assert!(min.x <= 0.0 && min.y <= 50.2 && max.x >= 0.9 && max.y >= 50.2);
+    assert!(max.x - min.x < 0.91 && max.y - min.y < 0.01);
+    // A full postcode gives the box of its outward code
+    assert_eq!(reader.prefix_extent("N3 4AA").map(|(min, _)| min.x), Some(min.x));
+    assert!(reader.prefix_extent("N11").is_none());
+    assert!(reader.prefix_extent("not a postcode").is_none());
+    for p in reader.iter().filter(|p| !p.is_partial){
+        let (min, max) = reader.prefix_extent(&p.postcode).unwrap();
+        assert!(p.location.x >= min.x && p.location.x <= max.x && p.location.y >= min.y && p.location.y <= max.y, "{} is outside its box", p.postcode);
+    }
+    assert_eq!(reader.prefix_extents().len(), 10);
+
+    // They are copied by a patch
+    let old = write(false);
+    assert!(Reader::from_bytes(old.clone()).unwrap().prefix_extent_count().is_none());
+    let patch = nearmypostcode_packer::patch::make_patch(&old, &data).unwrap();
+    assert_eq!(nearmypostcode_packer::patch::apply_patch(&old, &patch).unwrap(), data);
+}
+
+#[cfg(feature="boundary")]
+#[test]
+fn geojson_boundaries(){
+    let reader = pack();
+    let geojson = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"name": "west"}, "geometry": {"type": "Polygon", "coordinates": [
+            [[-0.05, 49.95], [0.25, 49.95], [0.25, 50.95], [-0.05, 50.95], [-0.05, 49.95]],
+            [[0.05, 50.35], [0.15, 50.35], [0.15, 50.55], [0.05, 50.55], [0.05, 50.35]]
+        ]}},
+        {"type": "Feature", "properties": null, "geometry": {"type": "MultiPolygon", "coordinates": [
+            [[[0.85, 49.95], [0.95, 49.95], [0.95, 50.05], [0.85, 50.05]]]
+        ]}},
+        {"type": "Feature", "properties": {}, "geometry": {"type": "Point", "coordinates": [0.5, 50.5]}},
+        {"type": "Feature", "properties": {}, "geometry": null}
+    ]}"#;
+    let region = Region::from_geojson(geojson).unwrap();
+    assert_eq!(region.polygons().len(), 3);
+    let packer = nearmypostcode_packer::repack::extract_region(&reader, &region);
+    let names: Vec<&str> = packer.postcodes().iter().filter(|p| !p.is_partial).map(|p| p.postcode.as_str()).collect();
+    // Three columns, less the two postcodes in the hole, and one in the bottom right corner
+    assert_eq!(names.len(), 30 - 2 + 1);
+    assert!(names.contains(&"N1  9AA") && !names.contains(&"N5  1AA") && !names.contains(&"N1  3AA"));
+
+    assert!(Region::from_geojson("not json").is_err());
+    assert!(Region::from_geojson(r#"{"type": "Point", "coordinates": [0, 0]}"#).is_err());
+    assert!(Region::from_geojson(r#"{"type": "Polygon", "coordinates": [[[0, 0], ["a", 1]]]}"#).is_err());
+}
@@ -0,0 +1,116 @@
+/*
+
+Tests of looking up postcodes by reading only parts of a pack file, compared with reading all of
+it.
+
+*/
+use std::sync::atomic::{AtomicU64, Ordering};
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, PostcodeError, Point, Scheme, NEWEST_VERSION, format_postcode};
+use nearmypostcode_packer::remote::{RangeRead, RemoteReader};
+
+/// A file in memory that counts what is read from it
+struct Counted{
+    data: Vec<u8>,
+    reads: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl RangeRead for &Counted{
+    fn file_len(&self) -> Result<u64, PostcodeError>{
+        Ok(self.data.len() as u64)
+    }
+
+    fn read_range(&self, start: u64, end: u64) -> Result<Vec<u8>, PostcodeError>{
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(end - start, Ordering::Relaxed);
+        self.data.read_range(start, end)
+    }
+}
+
+fn packed(entropy: bool) -> Vec<u8>{
+    let mut postcodes = Vec::new();
+    for (a, area) in ["AB", "B", "E", "SW", "YO"].iter().enumerate(){
+        for district in 1..=10u32{
+            for i in 0..50u32{
+                postcodes.push(PostcodeInfo{
+                    postcode: format_postcode(&format!("{area}{district} {}A{}", i % 10, (b'A' + (i / 10) as u8) as char)).unwrap(),
+                    location: Point{x: -4.0 + (district as f64 * 0.2) + (i as f64 * 0.001), y: 51.0 + (a as f64 * 0.5) + (i as f64 * 0.002)},
+                    is_partial: false,
+                    terminated: None,
+                    country: None,
+                    introduced: None,
+                    attributes: Vec::new(),
+                });
+            }
+        }
+    }
+    postcodes.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+    let mut packer = Packer::from_postcodes(postcodes, 1700000000, Scheme::Uk);
+    packer.set_format_version(NEWEST_VERSION).unwrap();
+    packer.set_entropy(entropy);
+    packer.set_spatial_index(true);
+    packer.set_sector_bitmap(true);
+    packer.set_chunk_size(1024);
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn lookups_only_read_their_blocks(){
+    for entropy in [false, true]{
+        let data = packed(entropy);
+        let reader = Reader::from_bytes(data.clone()).unwrap();
+        let source = Counted{data, reads: AtomicU64::new(0), bytes: AtomicU64::new(0)};
+        let remote = RemoteReader::open(&source).unwrap();
+        assert_eq!(source.reads.load(Ordering::Relaxed), 3);
+        assert_eq!((remote.version(), remote.last_update(), remote.scheme()), (reader.version(), reader.last_update(), reader.scheme()));
+        assert_eq!(remote.blocks_read(), 0);
+
+        let found = remote.lookup("sw5 3ab").unwrap().unwrap();
+        assert_eq!(source.reads.load(Ordering::Relaxed), 4);
+        assert_eq!(remote.blocks_read(), 1);
+        let expected = reader.lookup("SW5 3AB").unwrap();
+        assert_eq!((found.x, found.y), (expected.x, expected.y));
+        // The same block again is already read, and so are outward codes in it
+        assert!(remote.lookup("SW1 0AA").unwrap().is_some());
+        assert!(remote.lookup("SW1").unwrap().is_some());
+        assert!(remote.lookup("SW1 0AZ").unwrap().is_none());
+        assert!(remote.lookup("not a postcode").unwrap().is_none());
+        assert_eq!(source.reads.load(Ordering::Relaxed), 4);
+        assert!(source.bytes.load(Ordering::Relaxed) * 2 < source.data.len() as u64);
+
+        // Each lookup decodes its block again, so only some of them are checked
+        for p in reader.iter().step_by(5){
+            let found = remote.lookup(&p.postcode).unwrap().unwrap_or_else(|| panic!("{} is missing", p.postcode));
+            assert_eq!((found.x, found.y), (p.location.x, p.location.y));
+        }
+        // E and B have a block for each digit
+        assert_eq!(remote.blocks_read(), 3 + 9 + 9);
+        assert!(remote.lookup("ZZ1 1ZZ").unwrap().is_none());
+    }
+}
+
+#[test]
+fn files_are_read_from_disk(){
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden/v26.pack");
+    let reader = Reader::open(path).unwrap();
+    let remote = RemoteReader::open(std::fs::File::open(path).unwrap()).unwrap();
+    for p in reader.iter(){
+        let found = remote.lookup(&p.postcode).unwrap().unwrap();
+        assert_eq!((found.x, found.y), (p.location.x, p.location.y));
+    }
+}
+
+#[test]
+fn old_and_damaged_files_are_refused(){
+    let old = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden/v25.pack")).unwrap();
+    assert!(matches!(RemoteReader::open(old), Err(PostcodeError::IncompatibleOptions(_))));
+    assert!(matches!(RemoteReader::open(b"UKPP".to_vec()), Err(PostcodeError::NotAPackFile())));
+
+    // A byte of the first section after the section table
+    let mut data = packed(false);
+    let table_end = 20 + (u32::from_le_bytes(data[16..20].try_into().unwrap()) as usize * 16);
+    data[table_end + 40] ^= 0xff;
+    assert!(matches!(RemoteReader::open(data), Err(PostcodeError::Corrupt(_))));
+}
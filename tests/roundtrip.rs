@@ -0,0 +1,205 @@
+/*
+
+Property tests for the record encoder: random sorted lists of postcodes are packed, read back,
+and checked against the originals, which must all be there within one quantization step of
+where they were.
+
+The postcodes are generated close together, with locations that are often the same as or near
+to the one before, so that every record encoding gets used: absolute records, postcode and
+location deltas, runs, varints and terminated postcodes.
+
+Attributes are checked the same way, with values that are often the same as the one before, and
+through a patch from a file without them. So is the sector bitmap, which must list the outward
+codes and sectors of every postcode that isn't terminated, and nothing else.
+
+*/
+use std::collections::{BTreeMap, BTreeSet};
+use proptest::prelude::*;
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme, format_postcode};
+use nearmypostcode_packer::patch::{make_patch, apply_patch};
+
+/// Options for one file: format version, resolution, local bounding boxes and entropy coding
+#[derive(Debug, Clone, Copy)]
+struct FileOptions{
+    version: u32,
+    resolution: u32,
+    local_bounds: bool,
+    entropy: bool,
+}
+
+fn file_options() -> impl Strategy<Value=FileOptions>{
+    prop_oneof![
+        // Every version with a different record layout, at the default resolution
+        prop::sample::select(vec![2u32, 3, 6, 12, 13]).prop_map(|version| FileOptions{version, resolution: 16, local_bounds: false, entropy: false}),
+        (prop::sample::select(vec![8u32, 12, 16, 24]), any::<bool>(), any::<bool>())
+            .prop_map(|(resolution, local_bounds, entropy)| FileOptions{version: 16, resolution, local_bounds, entropy}),
+    ]
+}
+
+/// Where a postcode is, relative to the one before it in sorted order
+#[derive(Debug, Clone, Copy)]
+enum Place{
+    Same,
+    Near(f64, f64),
+    Anywhere(f64, f64),
+}
+
+fn place() -> impl Strategy<Value=Place>{
+    prop_oneof![
+        3 => Just(Place::Same),
+        3 => (-0.002..0.002, -0.002..0.002).prop_map(|(x, y)| Place::Near(x, y)),
+        1 => (-8.0..2.0, 49.0..61.0).prop_map(|(x, y)| Place::Anywhere(x, y)),
+    ]
+}
+
+/// A postcode in one of a few areas, with the inward codes bunched together so that many of
+/// them are small deltas apart
+fn postcode() -> impl Strategy<Value=String>{
+    (
+        prop::sample::select(vec!["B", "E", "AB", "SW", "YO"]),
+        prop_oneof![1u32..3, 1u32..100],
+        0u8..10,
+        prop_oneof![b'A'..b'C', b'A'..=b'Z'],
+        b'A'..=b'Z',
+    ).prop_map(|(area, district, digit, l1, l2)| {
+        format_postcode(&format!("{area}{district} {digit}{}{}", l1 as char, l2 as char)).unwrap()
+    })
+}
+
+fn entries() -> impl Strategy<Value=Vec<(String, Place, Option<u16>)>>{
+    prop::collection::vec((postcode(), place(), prop::option::weighted(0.1, 1980u16..2025)), 1..400)
+}
+
+/// The postcodes in sorted order, each placed relative to the one before
+fn postcodes(mut entries: Vec<(String, Place, Option<u16>)>, terminated: bool) -> Vec<PostcodeInfo>{
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries.dedup_by(|a, b| a.0 == b.0);
+    let mut location = Point{x: -2.0, y: 54.0};
+    entries.into_iter().map(|(postcode, place, year)| {
+        location = match place{
+            Place::Same => location,
+            Place::Near(dx, dy) => Point{x: location.x + dx, y: location.y + dy},
+            Place::Anywhere(x, y) => Point{x, y},
+        };
+        PostcodeInfo{
+            postcode,
+            location,
+            is_partial: false,
+            terminated: year.filter(|_| terminated),
+            country: None,
+            introduced: None,
+            attributes: Vec::new(),
+        }
+    }).collect()
+}
+
+fn pack(postcodes: &[PostcodeInfo], options: FileOptions) -> (Packer, Reader){
+    let mut packer = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+    packer.set_format_version(options.version).unwrap();
+    packer.set_resolution(options.resolution).unwrap();
+    packer.set_local_bounds(options.local_bounds);
+    packer.set_entropy(options.entropy);
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    let reader = Reader::from_bytes(data).unwrap();
+    (packer, reader)
+}
+
+proptest!{
+    #[test]
+    fn records_round_trip(entries in entries(), options in file_options()){
+        // Terminated postcodes need the flag bit from version 6
+        let postcodes = postcodes(entries, options.version >= 6);
+        let (packer, reader) = pack(&postcodes, options);
+
+        let mismatches = packer.verify(&reader);
+        prop_assert!(mismatches.is_empty(), "{} postcodes did not round trip, e.g. {:?}", mismatches.len(), mismatches[0]);
+
+        // Nothing is lost or added, and the termination years survive
+        let expected: BTreeMap<&str, Option<u16>> = postcodes.iter().map(|p| (p.postcode.as_str(), p.terminated)).collect();
+        let found: BTreeMap<String, Option<u16>> = reader.iter().filter(|p| !p.is_partial).map(|p| (p.postcode, p.terminated)).collect();
+        prop_assert_eq!(found.len(), expected.len());
+        for (postcode, terminated) in &found{
+            prop_assert_eq!(expected.get(postcode.as_str()), Some(terminated));
+        }
+    }
+
+    #[test]
+    fn attributes_round_trip(entries in entries(), values in prop::collection::vec(prop::option::of(0..300u32), 400)){
+        let mut postcodes = postcodes(entries, false);
+        // Two attributes, one in runs of random values, and one that is different for every
+        // postcode, so that big files have more values than fit in a byte
+        let mut value = None;
+        for (i, (p, v)) in postcodes.iter_mut().zip(&values).enumerate(){
+            if v.is_none_or(|v| v % 4 == 0){
+                value = *v;
+            }
+            let unique = if i % 7 == 3 { String::new() } else { format!("E{i:08}") };
+            p.attributes = vec![value.map(|v| v.to_string()).unwrap_or_default(), unique];
+        }
+        let names = vec!["laua".to_string(), "lsoa11".to_string()];
+        let mut packer = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+        packer.set_attributes(names.clone());
+        packer.set_format_version(19).unwrap();
+        let mut data = Vec::new();
+        packer.write_to(&mut data).unwrap();
+        let reader = Reader::from_bytes(data.clone()).unwrap();
+
+        prop_assert_eq!(reader.attribute_names(), ["laua", "lsoa11"]);
+        for p in &postcodes{
+            for (name, value) in names.iter().zip(&p.attributes){
+                let expected = Some(value.as_str()).filter(|v| !v.is_empty());
+                prop_assert_eq!(reader.attribute(&p.postcode, name), expected);
+            }
+        }
+        let found: BTreeMap<String, Vec<String>> = reader.iter().filter(|p| !p.is_partial).map(|p| (p.postcode, p.attributes)).collect();
+        let expected: BTreeMap<String, Vec<String>> = postcodes.iter().map(|p| (p.postcode.clone(), p.attributes.clone())).collect();
+        prop_assert_eq!(found, expected);
+        prop_assert_eq!(reader.attribute(&postcodes[0].postcode[0..4], "laua"), None);
+
+        // A patch adds them to a file that didn't have them
+        let mut old = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+        old.set_format_version(19).unwrap();
+        let mut old_data = Vec::new();
+        old.write_to(&mut old_data).unwrap();
+        let patch = make_patch(&old_data, &data).unwrap();
+        prop_assert_eq!(apply_patch(&old_data, &patch).unwrap(), data);
+    }
+
+    #[test]
+    fn sector_bitmap_round_trip(entries in entries()){
+        let postcodes = postcodes(entries, true);
+        let write = |sector_bitmap: bool| {
+            let mut packer = Packer::from_postcodes(postcodes.iter().cloned(), 0, Scheme::Uk);
+            packer.set_format_version(21).unwrap();
+            packer.set_sector_bitmap(sector_bitmap);
+            let mut data = Vec::new();
+            packer.write_to(&mut data).unwrap();
+            data
+        };
+        let data = write(true);
+        let reader = Reader::from_bytes(data.clone()).unwrap();
+
+        let live: Vec<&PostcodeInfo> = postcodes.iter().filter(|p| p.terminated.is_none()).collect();
+        let outward_codes: BTreeSet<String> = live.iter().map(|p| p.postcode[0..4].trim_end().to_string()).collect();
+        let sectors: BTreeSet<String> = live.iter().map(|p| format!("{} {}", p.postcode[0..4].trim_end(), &p.postcode[4..5])).collect();
+        prop_assert_eq!(reader.sector_bitmap_counts(), Some((outward_codes.len(), sectors.len())));
+        prop_assert_eq!(reader.complete("").into_iter().collect::<BTreeSet<String>>(), outward_codes.clone());
+        for outward in &outward_codes{
+            prop_assert_eq!(reader.prefix_exists(&outward.to_ascii_lowercase()), Some(true));
+            let found: BTreeSet<String> = reader.complete(&format!("{outward} ")).into_iter().filter(|c| c.contains(' ')).collect();
+            let expected: BTreeSet<String> = sectors.iter().filter(|s| s.split(' ').next() == Some(outward.as_str())).cloned().collect();
+            prop_assert_eq!(found, expected);
+        }
+        for p in &postcodes{
+            let sector = format!("{} {}", p.postcode[0..4].trim_end(), &p.postcode[4..5]);
+            prop_assert_eq!(reader.prefix_exists(&sector), Some(sectors.contains(&sector)));
+        }
+
+        // A patch rebuilds it in a file that didn't have it
+        let old = write(false);
+        prop_assert_eq!(Reader::from_bytes(old.clone()).unwrap().prefix_exists("AB1"), None);
+        let patch = make_patch(&old, &data).unwrap();
+        prop_assert_eq!(apply_patch(&old, &patch).unwrap(), data);
+    }
+}
@@ -0,0 +1,45 @@
+/*
+
+Tests of the HTTP server's routes, using the golden version 17 pack file. The requests are
+answered directly, without a socket.
+
+*/
+#![cfg(feature="serve")]
+use nearmypostcode_packer::Reader;
+use nearmypostcode_packer::serve::handle;
+
+fn reader() -> Reader{
+    Reader::open(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden/v17.pack")).unwrap()
+}
+
+#[test]
+fn lookup_finds_postcodes_in_any_form(){
+    let reader = reader();
+    for url in ["/lookup/SW1A%201AA", "/lookup/sw1a1aa", "/lookup/SW1A+1AA"]{
+        let response = handle(&reader, "GET", url);
+        assert_eq!(response.status, 200, "{url}");
+        assert!(response.body.starts_with("{\"postcode\": \"SW1A1AA\", \"lat\": 51.50"), "{}", response.body);
+        assert!(response.body.ends_with(", \"country\": \"E92000001\"}"), "{}", response.body);
+    }
+}
+
+#[test]
+fn nearest_finds_the_closest_postcode(){
+    let reader = reader();
+    let response = handle(&reader, "GET", "/nearest?lat=57.1257&long=-2.1173");
+    assert_eq!(response.status, 200);
+    assert!(response.body.starts_with("{\"postcode\": \"AB106RN\""), "{}", response.body);
+}
+
+#[test]
+fn errors_are_json(){
+    let reader = reader();
+    let status = |method, url| handle(&reader, method, url).status;
+    assert_eq!(status("GET", "/lookup/SW1A2ZZ"), 404);
+    assert_eq!(status("GET", "/lookup/NOT A POSTCODE"), 400);
+    assert_eq!(status("GET", "/nearest?lat=51.5"), 400);
+    assert_eq!(status("GET", "/nearest?lat=51.5&long=east"), 400);
+    assert_eq!(status("GET", "/"), 404);
+    assert_eq!(status("POST", "/lookup/SW1A1AA"), 405);
+    assert!(handle(&reader, "GET", "/lookup/SW1A2ZZ").body.starts_with("{\"error\": "));
+}
@@ -0,0 +1,51 @@
+/*
+
+Tests of "did you mean" suggestions for postcodes that aren't in the file.
+
+*/
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme};
+
+fn pack(codes: &[(&str, Option<u16>)], scheme: Scheme) -> Reader{
+    let postcodes = codes.iter().enumerate().map(|(i, (code, terminated))| PostcodeInfo{
+        postcode: scheme.codec().normalize(code).unwrap(),
+        location: Point{x: -2.0 + (i as f64 * 0.01), y: 52.0 + (i as f64 * 0.01)},
+        is_partial: false,
+        terminated: *terminated,
+        country: None,
+        introduced: None,
+        attributes: Vec::new(),
+    });
+    let mut packer = Packer::from_postcodes(postcodes, 0, scheme);
+    packer.set_format_version(if scheme == Scheme::Uk { 6 } else { 17 }).unwrap();
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    Reader::from_bytes(data).unwrap()
+}
+
+#[test]
+fn near_misses_are_suggested(){
+    let reader = pack(&[("SW1A 1AA", None), ("SW1A 2AA", None), ("SW1A 0AA", Some(2010)), ("AB10 1AA", None), ("BA10 1AA", None)], Scheme::Uk);
+    // I for 1 is more likely than 2 for I, and the terminated postcode isn't suggested
+    assert_eq!(reader.suggest("sw1a iaa", 1), ["SW1A1AA", "SW1A2AA"]);
+    assert_eq!(reader.suggest("SW1A 0AO", 1), Vec::<String>::new());
+    assert_eq!(reader.suggest("SW1A 1AO", 1), ["SW1A1AA"]);
+    // Swapped chars, in any block
+    assert_eq!(reader.suggest("AB10 A1A", 1), ["AB101AA"]);
+    assert_eq!(reader.suggest("BA10 1AA", 1), ["AB101AA"]);
+    // The code itself isn't a suggestion, but the ones near it are
+    assert_eq!(reader.suggest("SW1A 1AA", 1), ["SW1A2AA"]);
+
+    assert!(reader.suggest("SW1I 1AO", 1).is_empty());
+    assert_eq!(reader.suggest("SW1I 1AO", 2), ["SW1A1AA"]);
+    // No more than two edits are tried
+    assert_eq!(reader.suggest("SW1I IAO", 3), Vec::<String>::new());
+    assert!(reader.suggest("", 1).is_empty() && reader.suggest("SW1A 1Aé", 1).is_empty());
+}
+
+#[test]
+fn eircodes_are_suggested(){
+    let reader = pack(&[("A65 F4E2", None), ("D02 X285", None)], Scheme::Eircode);
+    assert_eq!(reader.suggest("A65 F4E", 1), Vec::<String>::new());
+    assert_eq!(reader.suggest("A65 F4EZ", 1), ["A65F4E2"]);
+    assert_eq!(reader.suggest("DO2 X285", 1), ["D02X285"]);
+}
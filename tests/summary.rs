@@ -0,0 +1,48 @@
+/*
+
+Tests of the summary of an input file, which counts every row whatever the packing options are.
+
+*/
+use std::path::Path;
+use nearmypostcode_packer::{InputSummary, ReadOptions, InputFormat, PostcodeError};
+use nearmypostcode_packer::summary::Counts;
+
+#[test]
+fn every_row_is_counted(){
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv");
+    let summary = InputSummary::from_csv(&golden.to_string_lossy(), &ReadOptions::with_exclude(&["BT"])).unwrap();
+    assert_eq!(summary.rows, 13);
+    // The exclusion doesn't apply, and GIR 0AA has no location but is still a postcode
+    assert_eq!(summary.postcodes, Counts{live: 12, terminated: 1});
+    assert_eq!(summary.areas.keys().collect::<Vec<_>>(), ["AB", "BT", "CB", "GIR", "SW"]);
+    assert_eq!(summary.areas["CB"], Counts{live: 4, terminated: 1});
+    assert_eq!(summary.districts["AB10"], Counts{live: 4, terminated: 0});
+    assert_eq!(summary.introduced.iter().map(|(y, n)| (*y, *n)).collect::<Vec<_>>(), [(1980, 8), (1992, 1), (1998, 1), (2010, 2), (2015, 1)]);
+    // The golden file has no quality column
+    assert!(summary.quality.is_empty());
+    assert_eq!(summary.no_quality, 13);
+}
+
+#[test]
+fn quality_and_bad_rows(){
+    let path = std::env::temp_dir().join(format!("nmp-summary-{}.csv", std::process::id()));
+    std::fs::write(&path, "pcds,dointr,doterm,osgrdind,lat,long\n\
+        YO1 7HH,198001,,1,53.96,-1.08\n\
+        YO1 7HJ,198001,,1,53.96,-1.08\n\
+        YO10 5DD,200506,201001,6,53.95,-1.05\n\
+        YO1 7HL,1980,,9,,\n\
+        NOT A POSTCODE,198001,,9,,\n\
+        YO1 7HN,,,,53.96,-1.08\n").unwrap();
+    let summary = InputSummary::from_csv(&path.to_string_lossy(), &ReadOptions::default()).unwrap();
+    assert_eq!(summary.rows, 6);
+    assert_eq!((summary.malformed, summary.bad_postcodes), (1, 1));
+    assert_eq!(summary.quality.iter().map(|(q, n)| (*q, *n)).collect::<Vec<_>>(), [(1, 2), (6, 1)]);
+    assert_eq!((summary.no_quality, summary.no_date), (1, 1));
+    assert_eq!(summary.districts["YO1"], Counts{live: 3, terminated: 0});
+    assert_eq!(summary.districts["YO10"], Counts{live: 0, terminated: 1});
+    assert_eq!(summary.areas["YO"], Counts{live: 3, terminated: 1});
+
+    let pointer = ReadOptions{format: InputFormat::Pointer, ..Default::default()};
+    assert!(matches!(InputSummary::from_csv(&path.to_string_lossy(), &pointer), Err(PostcodeError::IncompatibleOptions(_))));
+    std::fs::remove_file(&path).unwrap();
+}
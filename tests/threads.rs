@@ -0,0 +1,86 @@
+/*
+
+Tests of sharing one reader between threads. The entropy coded blocks and the outliers are
+decoded the first time they are needed, so the threads race to fill them in, and every thread
+must still get the same answers as a reader used from one thread.
+
+*/
+use std::sync::Arc;
+use nearmypostcode_packer::{Packer, Reader, PostcodeInfo, Point, Scheme, NEWEST_VERSION, format_postcode};
+
+const THREADS: usize = 8;
+
+/// Postcodes in a few areas, so that there are several prefix blocks to decode
+fn reader() -> Reader{
+    let mut postcodes = Vec::new();
+    for (a, area) in ["AB", "B", "E", "LS", "SW", "YO"].iter().enumerate(){
+        for district in 1..=8u32{
+            for i in 0..40u32{
+                postcodes.push(PostcodeInfo{
+                    postcode: format_postcode(&format!("{area}{district} {}A{}", i % 10, (b'A' + (i / 10) as u8) as char)).unwrap(),
+                    location: Point{x: -4.0 + (district as f64 * 0.2) + (i as f64 * 0.001), y: 51.0 + (a as f64 * 0.5) + (i as f64 * 0.002)},
+                    is_partial: false,
+                    terminated: None,
+                    country: None,
+                    introduced: None,
+                    attributes: Vec::new(),
+                });
+            }
+        }
+    }
+    postcodes.sort_by(|a, b| a.postcode.cmp(&b.postcode));
+    let mut packer = Packer::from_postcodes(postcodes, 0, Scheme::Uk);
+    packer.set_format_version(NEWEST_VERSION).unwrap();
+    packer.set_entropy(true);
+    packer.set_spatial_index(true);
+    let mut data = Vec::new();
+    packer.write_to(&mut data).unwrap();
+    Reader::from_bytes(data).unwrap()
+}
+
+#[test]
+fn readers_are_shared_between_threads(){
+    // The expected answers come from a reader of their own, so the shared one starts cold
+    let single = reader();
+    let entries: Vec<PostcodeInfo> = single.iter().filter(|p| !p.is_partial).collect();
+    // Nearest searches are slower, so only some postcodes are searched near
+    let expected: Vec<Option<String>> = entries.iter().enumerate()
+        .map(|(i, p)| (i % 5 == 0).then(|| single.nearest(p.location.y + 0.001, p.location.x).unwrap().postcode))
+        .collect();
+
+    let shared = Arc::new(reader());
+    let handles: Vec<_> = (0..THREADS).map(|t| {
+        let reader = Arc::clone(&shared);
+        let entries = entries.clone();
+        let expected = expected.clone();
+        std::thread::spawn(move || {
+            // Each thread starts somewhere else, so they need different blocks first
+            let start = t * entries.len() / THREADS;
+            for i in (start..entries.len()).chain(0..start){
+                let p = &entries[i];
+                let location = reader.lookup(&p.postcode).unwrap();
+                assert!((location.x - p.location.x).abs() < 1e-3 && (location.y - p.location.y).abs() < 1e-3);
+                if let Some(nearest) = &expected[i]{
+                    assert_eq!(&reader.nearest(p.location.y + 0.001, p.location.x).unwrap().postcode, nearest);
+                }
+            }
+            reader.iter().filter(|p| !p.is_partial).count()
+        })
+    }).collect();
+    for handle in handles{
+        assert_eq!(handle.join().unwrap(), entries.len());
+    }
+}
+
+#[test]
+fn cloned_readers_are_used_from_other_threads(){
+    let reader = reader();
+    let postcode = reader.iter().find(|p| !p.is_partial).unwrap().postcode;
+    std::thread::scope(|s| {
+        for _ in 0..THREADS{
+            let reader = reader.clone();
+            let postcode = &postcode;
+            s.spawn(move || assert!(reader.lookup(postcode).is_some()));
+        }
+    });
+}
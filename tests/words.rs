@@ -0,0 +1,47 @@
+/*
+
+Tests of the three word names of locations, and the words-csv output that uses them.
+
+*/
+use std::path::Path;
+use nearmypostcode_packer::{Packer, OutputFormat, Point, distance_m};
+use nearmypostcode_packer::words::{words_at, location_of};
+
+#[test]
+fn names_lead_back_to_their_cells(){
+    let places = [Point{x: -0.141588, y: 51.501009}, Point{x: -3.1883, y: 55.9533}, Point{x: -6.2603, y: 53.3498}, Point{x: -1.2, y: 60.8}];
+    for p in places{
+        let words = words_at(p).unwrap();
+        assert_eq!(words.split('.').count(), 3, "{words}");
+        let centre = location_of(&words).unwrap();
+        assert!(distance_m(&p, &centre) < 30.0, "{words} {p:?} {centre:?}");
+        // Any case and separator
+        assert_eq!(words_at(location_of(&words.to_uppercase().replace('.', " ")).unwrap()).unwrap(), words);
+    }
+    // Neighbouring cells don't share words
+    let (a, b) = (words_at(places[0]).unwrap(), words_at(Point{x: places[0].x + 0.0004, ..places[0]}).unwrap());
+    assert_ne!(a, b);
+    assert!(a.split('.').zip(b.split('.')).all(|(x, y)| x != y), "{a} {b}");
+
+    assert!(words_at(Point{x: 10.0, y: 51.0}).is_none());
+    assert!(location_of("apple.river").is_none());
+    assert!(location_of("apple.river.notaword").is_none());
+}
+
+#[test]
+fn words_csv_has_a_name_for_each_postcode(){
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden").join("golden.csv");
+    let packer = Packer::from_csv(&golden.to_string_lossy(), &[]).unwrap();
+    let path = std::env::temp_dir().join(format!("nmp-words-{}.csv", std::process::id()));
+    packer.write_as(OutputFormat::WordsCsv, &path.to_string_lossy()).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("postcode,lat,long,words"));
+    let rows: Vec<Vec<&str>> = lines.map(|l| l.split(',').collect()).collect();
+    assert_eq!(rows.len(), packer.postcodes().iter().filter(|p| !p.is_partial).count());
+    for row in rows{
+        let location = Point{x: row[2].parse().unwrap(), y: row[1].parse().unwrap()};
+        assert_eq!(row[3], words_at(location).unwrap(), "{}", row[0]);
+    }
+}
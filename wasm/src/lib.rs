@@ -0,0 +1,111 @@
+/*
+
+WebAssembly build of the Rust pack file reader.
+
+This exposes the same `Reader` that the packer uses to verify its output, so the file format is
+only decoded in one place. Build it with `./build_wasm`, which writes an ES module and its
+TypeScript types to `pkg/`.
+
+    import init, { NearMyPostcode } from './pkg/nearmypostcode_wasm.js';
+    await init();
+    const nmp = new NearMyPostcode(new Uint8Array(await (await fetch('postcodes.pack')).arrayBuffer()));
+    const {postcode, lat, long} = nmp.lookup_postcode('sw1a 2aa');
+
+*/
+use wasm_bindgen::prelude::*;
+use nearmypostcode_packer::{Reader, PostcodeInfo, PostcodeError, format_postcode as format};
+
+fn js_error(e: PostcodeError) -> JsError{
+    JsError::new(&e.to_string())
+}
+
+/// Put a postcode in to canonical form: upper case, 7 chars with the inward code aligned right,
+/// or 4 chars for an outward code by itself
+#[wasm_bindgen]
+pub fn format_postcode(postcode: &str) -> Result<String, JsError>{
+    format(postcode).map_err(js_error)
+}
+
+/// A postcode found by `lookup_postcode`, `nearest` or `within_radius`
+#[wasm_bindgen]
+pub struct Postcode{
+    info: PostcodeInfo,
+}
+
+#[wasm_bindgen]
+impl Postcode{
+    /// The postcode in canonical form, or the 4 char outward code
+    #[wasm_bindgen(getter)]
+    pub fn postcode(&self) -> String{
+        let postcode = &self.info.postcode;
+        if self.info.is_partial { postcode[0..4].to_string() } else { postcode.clone() }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn lat(&self) -> f64{
+        self.info.location.y
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn long(&self) -> f64{
+        self.info.location.x
+    }
+
+    /// ONS code of the country the postcode is in, if the pack file has countries
+    #[wasm_bindgen(getter)]
+    pub fn country(&self) -> Option<String>{
+        self.info.country.map(|c| c.code().to_string())
+    }
+}
+
+/// A loaded pack file
+#[wasm_bindgen]
+pub struct NearMyPostcode{
+    reader: Reader,
+}
+
+#[wasm_bindgen]
+impl NearMyPostcode{
+    /// Load a pack file from its contents
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>) -> Result<NearMyPostcode, JsError>{
+        Ok(Self{reader: Reader::from_bytes(data).map_err(js_error)?})
+    }
+
+    /// File format version of the pack file
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> u32{
+        self.reader.version()
+    }
+
+    /// Date of the most recently introduced postcode, as a unix timestamp in seconds
+    #[wasm_bindgen(getter)]
+    pub fn last_update(&self) -> f64{
+        self.reader.last_update() as f64
+    }
+
+    /// Look up a postcode, or an outward code by itself, in any case and spacing
+    pub fn lookup_postcode(&self, postcode: &str) -> Result<Postcode, JsError>{
+        let canonical = self.reader.scheme().codec().format(postcode).map_err(js_error)?;
+        let location = self.reader.lookup(&canonical).ok_or_else(|| js_error(PostcodeError::NotFound()))?;
+        Ok(Postcode{info: PostcodeInfo{
+            is_partial: canonical.len() == 4,
+            country: self.reader.country(&canonical),
+            postcode: canonical,
+            location,
+            terminated: None,
+            introduced: None,
+            attributes: Vec::new(),
+        }})
+    }
+
+    /// The postcode closest to a location, faster if the pack file has a spatial index
+    pub fn nearest(&self, lat: f64, long: f64) -> Option<Postcode>{
+        self.reader.nearest(lat, long).map(|info| Postcode{info})
+    }
+
+    /// All of the postcodes within a distance (in metres) of a location, nearest first
+    pub fn within_radius(&self, lat: f64, long: f64, metres: f64) -> Vec<Postcode>{
+        self.reader.within_radius(lat, long, metres).into_iter().map(|info| Postcode{info}).collect()
+    }
+}